@@ -0,0 +1,86 @@
+//! Resource serialization and event fan-out
+//!
+//! Measures serializing the full resource list (as fetched by apps on every
+//! start), both cached and from scratch, and delivering a light update
+//! event to a number of event stream subscribers.
+//!
+//! Run with `cargo bench --bench resources`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use hue::api::{LightUpdate, RType, Update};
+use hue::event::EventBlock;
+use hue::version::SwVersion;
+
+use bifrost::backend::virt::VirtualBackend;
+use bifrost::config::{VirtualLightConfig, VirtualLightKind};
+use bifrost::model::entertainment::EntertainmentSettings;
+use bifrost::model::state::State;
+use bifrost::resource::Resources;
+use bifrost::server::hueevents::HueEventStream;
+
+const LIGHTS: u32 = 50;
+const SUBSCRIBERS: usize = 8;
+
+fn resources() -> Resources {
+    let settings = EntertainmentSettings {
+        max_fps: 25,
+        smoothing: 0x400,
+    };
+    let mut res = Resources::new(SwVersion::default(), State::new(), settings);
+
+    let conf = VirtualLightConfig {
+        name: "Bench".to_string(),
+        model_id: Some("LCT015".to_string()),
+        kind: VirtualLightKind::Color,
+        gradient: false,
+        count: None,
+        room: None,
+    };
+
+    for index in 0..LIGHTS {
+        let name = format!("Bench light {index}");
+        for (link, obj) in VirtualBackend::light_resources(&conf, ("bench", &name), &name) {
+            res.add(&link, obj).unwrap();
+        }
+    }
+
+    res
+}
+
+fn serialize(c: &mut Criterion) {
+    let res = resources();
+
+    c.bench_function("resources/serialize (uncached)", |b| {
+        b.iter(|| {
+            serde_json::to_vec(&serde_json::json!({
+                "data": res.get_resources(),
+                "errors": [],
+            }))
+            .unwrap()
+        });
+    });
+
+    c.bench_function("resources/resource_list_json (cached)", |b| {
+        b.iter(|| res.resource_list_json().unwrap());
+    });
+}
+
+fn fanout(c: &mut Criterion) {
+    let mut events = HueEventStream::new(128);
+    let mut receivers: Vec<_> = (0..SUBSCRIBERS).map(|_| events.subscribe()).collect();
+    let light = RType::Light.deterministic(("bench", "Bench light 0"));
+    let upd = Update::Light(LightUpdate::new().with_brightness(Some(50.0)));
+
+    c.bench_function(&format!("events/fanout ({SUBSCRIBERS} subscribers)"), |b| {
+        b.iter(|| {
+            events.hue_event(EventBlock::update(&light.rid, None, upd.clone()).unwrap());
+            for rx in &mut receivers {
+                rx.try_recv().unwrap();
+            }
+        });
+    });
+}
+
+criterion_group!(benches, serialize, fanout);
+criterion_main!(benches);
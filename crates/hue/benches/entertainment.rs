@@ -0,0 +1,39 @@
+//! Entertainment frame encoding
+//!
+//! Compares packing a freshly allocated frame with encoding into the
+//! reusable buffer of an entertainment stream, for a full 10-channel frame.
+//!
+//! Run with `cargo bench -p hue --bench entertainment`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use hue::zigbee::{
+    EntertainmentZigbeeStream, HueEntFrame, HueEntFrameLightRecord, LightRecordMode,
+};
+
+fn entertainment(c: &mut Criterion) {
+    let blks: Vec<HueEntFrameLightRecord> = (0..10)
+        .map(|addr| HueEntFrameLightRecord::new(addr, 0x7FF, LightRecordMode::Segment, [1, 2, 3]))
+        .collect();
+
+    c.bench_function("entertainment/pack (allocating)", |b| {
+        b.iter(|| {
+            let frame = HueEntFrame {
+                counter: 1,
+                smoothing: EntertainmentZigbeeStream::DEFAULT_SMOOTHING,
+                blks: blks.clone(),
+            };
+            frame.pack().unwrap()
+        });
+    });
+
+    let mut stream = EntertainmentZigbeeStream::new(0);
+    c.bench_function("entertainment/encode_frame (reused)", |b| {
+        b.iter(|| stream.encode_frame(black_box(&blks)).unwrap().len());
+    });
+}
+
+criterion_group!(benches, entertainment);
+criterion_main!(benches);
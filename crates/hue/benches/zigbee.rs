@@ -0,0 +1,56 @@
+//! Hue zigbee update encoding and decoding
+//!
+//! Covers a simple on/brightness update, a color update with transition and
+//! effect, and a full gradient update.
+//!
+//! Run with `cargo bench -p hue --bench zigbee`
+
+use std::hint::black_box;
+use std::io::Cursor;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use hue::xy::XY;
+use hue::zigbee::{EffectType, GradientStyle, HueZigbeeUpdate};
+
+fn updates() -> Vec<(&'static str, HueZigbeeUpdate)> {
+    let simple = HueZigbeeUpdate::new()
+        .with_on_off(true)
+        .with_brightness(0x80);
+
+    let color = HueZigbeeUpdate::new()
+        .with_on_off(true)
+        .with_brightness(0xFE)
+        .with_color_xy(XY::new(0.3, 0.4))
+        .with_transition(Duration::from_millis(400))
+        .with_effect_type(EffectType::Candle)
+        .with_effect_speed(0x40);
+
+    let points = (0..5)
+        .map(|i| XY::new(f64::from(i).mul_add(0.1, 0.1), 0.3))
+        .collect();
+    let gradient = HueZigbeeUpdate::new()
+        .with_on_off(true)
+        .with_brightness(0xFE)
+        .with_gradient_colors(GradientStyle::Linear, points)
+        .unwrap();
+
+    vec![("simple", simple), ("color", color), ("gradient", gradient)]
+}
+
+fn zigbee(c: &mut Criterion) {
+    for (name, upd) in updates() {
+        c.bench_function(&format!("zigbee/encode/{name}"), |b| {
+            b.iter(|| black_box(&upd).to_vec().unwrap());
+        });
+
+        let data = upd.to_vec().unwrap();
+        c.bench_function(&format!("zigbee/decode/{name}"), |b| {
+            b.iter(|| HueZigbeeUpdate::from_reader(&mut Cursor::new(black_box(&data))).unwrap());
+        });
+    }
+}
+
+criterion_group!(benches, zigbee);
+criterion_main!(benches);
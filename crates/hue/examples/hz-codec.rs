@@ -0,0 +1,245 @@
+//! Decode and encode payloads in the Hue manufacturer-specific zigbee formats
+//!
+//! Usage:
+//!
+//!   hz-codec decode <hex>..       Decode cluster 0xFC03 (composite update) payloads
+//!   hz-codec decode-ent <hex>..   Decode cluster 0xFC01 entertainment frames
+//!   hz-codec encode <json>..      Encode cluster 0xFC03 payloads from json
+//!
+//! If no arguments are given, input is read from stdin, one item per line.
+//! Output is likewise one item per line.
+//!
+//! The json format used by "encode" is the same as the output of "decode", so
+//! payloads can be decoded, edited, and encoded again.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{stdin, BufRead, Cursor};
+
+use packed_struct::{PrimitiveEnum, PrimitiveEnumDynamicStr};
+use serde::{Deserialize, Serialize};
+
+use hue::xy::XY;
+use hue::zigbee::{
+    EffectType, GradientColors, GradientParams, GradientStyle, GradientUpdateHeader, HueEntFrame,
+    HueZigbeeUpdate,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Update {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    onoff: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mirek: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xy: Option<XY>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fade_speed: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effect: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effect_speed: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gradient: Option<Gradient>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gradient_params: Option<Params>,
+    /// Values of unknown flags, as hex strings keyed by flag bits (e.g. "0200")
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    unknown: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Gradient {
+    style: String,
+    points: Vec<XY>,
+    /// Reserved header fields, kept to make unknown values visible
+    #[serde(default, skip_serializing_if = "is_zero")]
+    resv0: u8,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    resv2: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Params {
+    scale: u8,
+    offset: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct EntFrame {
+    counter: u32,
+    smoothing: u16,
+    lights: Vec<EntLight>,
+}
+
+#[derive(Debug, Serialize)]
+struct EntLight {
+    addr: String,
+    mode: String,
+    brightness: u16,
+    xy: XY,
+}
+
+fn is_zero<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn parse_enum<T: PrimitiveEnum>(kind: &str, name: &str) -> Result<T> {
+    T::from_str(name)
+        .or_else(|| T::from_str_lower(&name.to_lowercase()))
+        .ok_or_else(|| format!("Unknown {kind}: {name:?}").into())
+}
+
+impl From<HueZigbeeUpdate> for Update {
+    fn from(hz: HueZigbeeUpdate) -> Self {
+        Self {
+            onoff: hz.onoff,
+            brightness: hz.brightness,
+            mirek: hz.color_mirek,
+            xy: hz.color_xy,
+            fade_speed: hz.fade_speed,
+            effect: hz.effect_type.map(|fx| fx.to_display_str().to_string()),
+            effect_speed: hz.effect_speed,
+            gradient: hz.gradient_colors.map(|grad| Gradient {
+                style: grad.header.style.to_display_str().to_string(),
+                points: grad.points,
+                resv0: grad.header.resv0,
+                resv2: grad.header.resv2,
+            }),
+            gradient_params: hz.gradient_params.map(|gp| Params {
+                scale: gp.scale,
+                offset: gp.offset,
+            }),
+            unknown: hz
+                .unknown_fields
+                .iter()
+                .map(|(flags, data)| (format!("{flags:04x}"), hex::encode(data)))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<Update> for HueZigbeeUpdate {
+    type Error = Box<dyn Error>;
+
+    fn try_from(upd: Update) -> Result<Self> {
+        let gradient_colors = match upd.gradient {
+            Some(grad) => Some(GradientColors {
+                header: GradientUpdateHeader {
+                    nlights: u8::try_from(grad.points.len())?,
+                    resv0: grad.resv0,
+                    style: parse_enum::<GradientStyle>("gradient style", &grad.style)?,
+                    resv2: grad.resv2,
+                },
+                points: grad.points,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            onoff: upd.onoff,
+            brightness: upd.brightness,
+            color_mirek: upd.mirek,
+            color_xy: upd.xy,
+            fade_speed: upd.fade_speed,
+            gradient_colors,
+            gradient_params: upd.gradient_params.map(|gp| GradientParams {
+                scale: gp.scale,
+                offset: gp.offset,
+            }),
+            effect_type: upd
+                .effect
+                .map(|name| parse_enum::<EffectType>("effect", &name))
+                .transpose()?,
+            effect_speed: upd.effect_speed,
+            unknown_fields: upd
+                .unknown
+                .iter()
+                .map(|(flags, data)| Ok((u16::from_str_radix(flags, 16)?, hex::decode(data)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+fn decode(line: &str) -> Result<()> {
+    let data = hex::decode(line)?;
+    let mut cur = Cursor::new(data.as_slice());
+    let upd = Update::from(HueZigbeeUpdate::from_reader_lenient(&mut cur)?);
+
+    println!("{}", serde_json::to_string(&upd)?);
+
+    let rest = cur.fill_buf()?;
+    if !rest.is_empty() {
+        eprintln!("Trailing data: {}", hex::encode(rest));
+    }
+
+    Ok(())
+}
+
+fn decode_ent(line: &str) -> Result<()> {
+    let frame = HueEntFrame::parse(&hex::decode(line)?)?;
+
+    let desc = EntFrame {
+        counter: frame.counter,
+        smoothing: frame.smoothing,
+        lights: frame
+            .blks
+            .iter()
+            .map(|blk| EntLight {
+                addr: format!("{:04x}", blk.addr()),
+                mode: blk
+                    .mode()
+                    .map_or_else(|| "unknown".to_string(), |mode| format!("{mode:?}")),
+                brightness: blk.brightness(),
+                xy: XY::from_quant(blk.raw()),
+            })
+            .collect(),
+    };
+
+    println!("{}", serde_json::to_string(&desc)?);
+
+    Ok(())
+}
+
+fn encode(line: &str) -> Result<()> {
+    let upd: Update = serde_json::from_str(line)?;
+    let hz = HueZigbeeUpdate::try_from(upd)?;
+
+    println!("{}", hex::encode(hz.to_vec()?));
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    let func = match args.next().as_deref() {
+        Some("decode") => decode,
+        Some("decode-ent") => decode_ent,
+        Some("encode") => encode,
+        _ => {
+            eprintln!("usage: hz-codec <decode | decode-ent | encode> [input..]");
+            std::process::exit(1);
+        }
+    };
+
+    let mut inputs: Vec<String> = args.collect();
+    if inputs.is_empty() {
+        inputs = stdin().lines().collect::<std::io::Result<_>>()?;
+    }
+
+    for input in inputs.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if let Err(err) = func(input) {
+            eprintln!("Failed to process {input:?}: {err}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::api::{ButtonEvent, ResourceLink};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DollarRef {
+    #[serde(rename = "$ref", skip_serializing_if = "Option::is_none")]
+    pub dref: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BehaviorScript {
+    pub configuration_schema: DollarRef,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_number_instances: Option<u32>,
+    pub metadata: Value,
+    pub state_schema: DollarRef,
+    pub supported_features: Vec<String>,
+    pub trigger_schema: DollarRef,
+    pub version: String,
+}
+
+impl BehaviorScript {
+    /// Script used by the hue app to configure accessories (dimmer switches,
+    /// tap switches, etc)
+    pub const BUTTON_SCRIPT_ID: Uuid = Uuid::from_u128(0x67d9395b_4403_42cc_b5f0_740b699d67c6);
+
+    #[must_use]
+    pub fn button() -> Self {
+        Self {
+            configuration_schema: DollarRef {
+                dref: Some("basic_button_config.json#".to_string()),
+            },
+            description: "Button actions for accessories".to_string(),
+            max_number_instances: None,
+            metadata: serde_json::json!({
+                "name": "Basic button",
+                "category": "accessory",
+            }),
+            state_schema: DollarRef { dref: None },
+            supported_features: vec![],
+            trigger_schema: DollarRef { dref: None },
+            version: "0.0.1".to_string(),
+        }
+    }
+}
+
+fn deserialize_optional_field<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Some(Value::deserialize(deserializer)?))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BehaviorInstance {
+    pub configuration: Value,
+    #[serde(default)]
+    pub dependees: Vec<Value>,
+    pub enabled: bool,
+    pub last_error: Option<String>,
+    pub metadata: BehaviorInstanceMetadata,
+    pub script_id: Uuid,
+    pub status: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_field",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub state: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub migrated_from: Option<Value>,
+}
+
+impl BehaviorInstance {
+    /// Parse the configuration of an accessory (button) behavior.
+    ///
+    /// Returns `None` for instances of other behavior scripts.
+    #[must_use]
+    pub fn button_configuration(&self) -> Option<serde_json::Result<ButtonConfiguration>> {
+        (self.script_id == BehaviorScript::BUTTON_SCRIPT_ID)
+            .then(|| serde_json::from_value(self.configuration.clone()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BehaviorInstanceMetadata {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BehaviorInstanceUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BehaviorInstanceMetadata>,
+}
+
+/// Configuration of the accessory behavior script: what happens when the
+/// buttons of a device are pressed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ButtonConfiguration {
+    pub device: ResourceLink,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(default)]
+    pub buttons: BTreeMap<Uuid, ButtonActions>,
+    /// Rooms or zones controlled by buttons without their own targets
+    #[serde(rename = "where", default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<ButtonTarget>,
+}
+
+impl ButtonConfiguration {
+    /// Rooms or zones controlled by the given button
+    #[must_use]
+    pub fn targets(&self, button: &Uuid) -> Vec<ResourceLink> {
+        self.buttons
+            .get(button)
+            .map(|btn| &btn.targets)
+            .filter(|targets| !targets.is_empty())
+            .unwrap_or(&self.targets)
+            .iter()
+            .map(|target| target.group)
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ButtonTarget {
+    pub group: ResourceLink,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ButtonActions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_short_release: Option<ButtonAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_long_press: Option<ButtonAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_repeat: Option<ButtonAction>,
+    #[serde(rename = "where", default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<ButtonTarget>,
+}
+
+impl ButtonActions {
+    #[must_use]
+    pub const fn for_event(&self, event: ButtonEvent) -> Option<&ButtonAction> {
+        match event {
+            ButtonEvent::ShortRelease => self.on_short_release.as_ref(),
+            ButtonEvent::LongPress => self.on_long_press.as_ref(),
+            ButtonEvent::Repeat => self.on_repeat.as_ref(),
+            ButtonEvent::InitialPress
+            | ButtonEvent::LongRelease
+            | ButtonEvent::DoubleShortRelease => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonAction {
+    /// Recall a single scene
+    RecallSingle(Vec<SceneRecallStep>),
+
+    /// Recall the next scene in a list, each time the button is pressed
+    SceneCycle(Vec<Vec<SceneRecallStep>>),
+
+    /// Same as [`ButtonAction::SceneCycle`], in the format used by newer apps
+    SceneCycleExtended(SceneCycleExtended),
+
+    Action(SimpleAction),
+
+    /// Actions not supported by bifrost (e.g. time based scenes) are kept
+    /// as-is, but not executed
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SceneCycleExtended {
+    pub slots: Vec<Vec<SceneRecallStep>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with_off: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SceneRecallStep {
+    pub action: SceneRecallTarget,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SceneRecallTarget {
+    pub recall: ResourceLink,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimpleAction {
+    AllOff,
+    DimUp,
+    DimDown,
+    DoNothing,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::api::{
+        BehaviorInstance, BehaviorScript, ButtonAction, ButtonEvent, RType, SimpleAction,
+    };
+
+    #[test]
+    fn button_configuration() {
+        let button = Uuid::new_v4();
+        let scene = Uuid::new_v4();
+        let room = Uuid::new_v4();
+
+        let inst: BehaviorInstance = serde_json::from_value(json!({
+            "type": "behavior_instance",
+            "enabled": true,
+            "script_id": BehaviorScript::BUTTON_SCRIPT_ID,
+            "metadata": {"name": "Dimmer switch"},
+            "last_error": null,
+            "status": null,
+            "configuration": {
+                "device": {"rid": Uuid::new_v4(), "rtype": "device"},
+                "buttons": {
+                    button.to_string(): {
+                        "on_short_release": {
+                            "recall_single": [{"action": {"recall": {"rid": scene, "rtype": "scene"}}}]
+                        },
+                        "on_long_press": {"action": "all_off"},
+                        "on_repeat": {"time_based": {"slots": []}},
+                        "where": [{"group": {"rid": room, "rtype": "room"}}],
+                    }
+                },
+            },
+        }))
+        .unwrap();
+
+        let config = inst.button_configuration().unwrap().unwrap();
+        let actions = &config.buttons[&button];
+
+        let Some(ButtonAction::RecallSingle(steps)) = actions.for_event(ButtonEvent::ShortRelease)
+        else {
+            panic!("expected scene recall");
+        };
+        assert_eq!(steps[0].action.recall, RType::Scene.link_to(scene));
+
+        assert!(matches!(
+            actions.for_event(ButtonEvent::LongPress),
+            Some(ButtonAction::Action(SimpleAction::AllOff))
+        ));
+        assert!(matches!(
+            actions.for_event(ButtonEvent::Repeat),
+            Some(ButtonAction::Other(_))
+        ));
+        assert!(actions.for_event(ButtonEvent::InitialPress).is_none());
+
+        assert_eq!(config.targets(&button), vec![RType::Room.link_to(room)]);
+        assert!(config.targets(&Uuid::new_v4()).is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ResourceLink;
+use crate::date_format;
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonEvent {
+    InitialPress,
+    Repeat,
+    ShortRelease,
+    LongRelease,
+    DoubleShortRelease,
+    LongPress,
+}
+
+impl ButtonEvent {
+    /// Events reported by regular (non-rotary) buttons
+    pub const ALL: [Self; 5] = [
+        Self::InitialPress,
+        Self::Repeat,
+        Self::ShortRelease,
+        Self::LongRelease,
+        Self::LongPress,
+    ];
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Button {
+    pub owner: ResourceLink,
+    pub metadata: ButtonMetadata,
+    pub button: ButtonData,
+}
+
+impl Button {
+    #[must_use]
+    pub fn new(owner: ResourceLink, control_id: u32) -> Self {
+        Self {
+            owner,
+            metadata: ButtonMetadata { control_id },
+            button: ButtonData {
+                button_report: None,
+                last_event: None,
+                repeat_interval: Some(800),
+                event_values: Some(ButtonEvent::ALL.to_vec()),
+            },
+        }
+    }
+
+    pub fn report(&mut self, event: ButtonEvent) {
+        self.button.last_event = Some(event);
+        self.button.button_report = Some(ButtonReport {
+            updated: Utc::now(),
+            event,
+        });
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ButtonMetadata {
+    pub control_id: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ButtonData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub button_report: Option<ButtonReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_event: Option<ButtonEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_interval: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_values: Option<Vec<ButtonEvent>>,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone)]
+pub struct ButtonReport {
+    #[serde(with = "date_format::utc_ms")]
+    pub updated: DateTime<Utc>,
+    pub event: ButtonEvent,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ButtonUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub button: Option<ButtonData>,
+}
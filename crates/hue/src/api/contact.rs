@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ResourceLink;
+use crate::date_format;
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactState {
+    Contact,
+    NoContact,
+}
+
+impl From<bool> for ContactState {
+    fn from(contact: bool) -> Self {
+        if contact {
+            Self::Contact
+        } else {
+            Self::NoContact
+        }
+    }
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ContactReport {
+    #[serde(with = "date_format::utc_ms")]
+    pub changed: DateTime<Utc>,
+    pub state: ContactState,
+}
+
+/// Door/window contact sensor
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Contact {
+    pub owner: ResourceLink,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact_report: Option<ContactReport>,
+}
+
+impl Contact {
+    #[must_use]
+    pub const fn new(owner: ResourceLink) -> Self {
+        Self {
+            owner,
+            enabled: true,
+            contact_report: None,
+        }
+    }
+
+    /// Record a new contact state. Reports are only made while the sensor is
+    /// enabled, and only when the state actually changes.
+    pub fn report(&mut self, state: ContactState) {
+        if !self.enabled || self.contact_report.is_some_and(|rep| rep.state == state) {
+            return;
+        }
+
+        self.contact_report = Some(ContactReport {
+            changed: Utc::now(),
+            state,
+        });
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContactUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact_report: Option<ContactReport>,
+}
+
+impl ContactUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn with_enabled(self, enabled: Option<bool>) -> Self {
+        Self { enabled, ..self }
+    }
+
+    #[must_use]
+    pub const fn with_contact_report(self, contact_report: Option<ContactReport>) -> Self {
+        Self {
+            contact_report,
+            ..self
+        }
+    }
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TamperState {
+    Tampered,
+    NotTampered,
+}
+
+impl From<bool> for TamperState {
+    fn from(tampered: bool) -> Self {
+        if tampered {
+            Self::Tampered
+        } else {
+            Self::NotTampered
+        }
+    }
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TamperSource {
+    BatteryDoor,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TamperReport {
+    #[serde(with = "date_format::utc_ms")]
+    pub changed: DateTime<Utc>,
+    pub source: TamperSource,
+    pub state: TamperState,
+}
+
+/// Tamper detection (e.g. opened battery cover) of a sensor
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tamper {
+    pub owner: ResourceLink,
+    pub tamper_reports: Vec<TamperReport>,
+}
+
+impl Tamper {
+    #[must_use]
+    pub const fn new(owner: ResourceLink) -> Self {
+        Self {
+            owner,
+            tamper_reports: vec![],
+        }
+    }
+
+    /// Record a new tamper state, if it changed
+    pub fn report(&mut self, state: TamperState) {
+        if self
+            .tamper_reports
+            .last()
+            .is_some_and(|rep| rep.state == state)
+        {
+            return;
+        }
+
+        self.tamper_reports = vec![TamperReport {
+            changed: Utc::now(),
+            source: TamperSource::BatteryDoor,
+            state,
+        }];
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TamperUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tamper_reports: Option<Vec<TamperReport>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{Contact, ContactState, RType};
+
+    #[test]
+    fn contact_reports() {
+        let mut contact = Contact::new(RType::Device.deterministic(1));
+
+        contact.report(ContactState::Contact);
+        let first = contact.contact_report.unwrap();
+
+        // repeated states do not count as changes
+        contact.report(ContactState::Contact);
+        assert_eq!(contact.contact_report, Some(first));
+
+        // disabled sensors do not report
+        contact.enabled = false;
+        contact.report(ContactState::NoContact);
+        assert_eq!(contact.contact_report, Some(first));
+
+        contact.enabled = true;
+        contact.report(ContactState::NoContact);
+        assert_eq!(
+            contact.contact_report.map(|rep| rep.state),
+            Some(ContactState::NoContact)
+        );
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::BTreeSet;
 use std::ops::{AddAssign, Sub};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::api::{Metadata, MetadataUpdate, RType, ResourceLink, Stub};
 use crate::version::SwVersion;
@@ -22,6 +23,8 @@ pub struct Device {
 pub struct DeviceUpdate {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<MetadataUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identify: Option<IdentifyUpdate>,
 }
 
 impl Device {
@@ -34,6 +37,17 @@ impl Device {
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Identify {}
 
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentifyAction {
+    Identify,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct IdentifyUpdate {
+    pub action: IdentifyAction,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct UserTest {
     status: String,
@@ -82,6 +96,7 @@ impl DeviceUpdate {
                 archetype: Some(metadata.archetype),
                 name: Some(metadata.name),
             }),
+            ..self
         }
     }
 }
@@ -183,3 +198,103 @@ pub enum DeviceArchetype {
     #[serde(untagged)]
     Other(String),
 }
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatteryState {
+    Normal,
+    Low,
+    Critical,
+}
+
+impl BatteryState {
+    #[must_use]
+    pub const fn from_level(level: u8) -> Self {
+        match level {
+            0..=4 => Self::Critical,
+            5..=19 => Self::Low,
+            _ => Self::Normal,
+        }
+    }
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct DevicePowerState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_state: Option<BatteryState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_level: Option<u8>,
+}
+
+/// Battery status of a (battery powered) device
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DevicePower {
+    pub owner: ResourceLink,
+    pub power_state: DevicePowerState,
+}
+
+impl DevicePower {
+    #[must_use]
+    pub fn new(owner: ResourceLink) -> Self {
+        Self {
+            owner,
+            power_state: DevicePowerState::default(),
+        }
+    }
+
+    /// Update battery status. A low battery warning from the device takes
+    /// precedence over the state guessed from the battery level.
+    pub fn report(&mut self, level: Option<u8>, low: Option<bool>) {
+        let level = level.or(self.power_state.battery_level);
+        let mut state = level.map(BatteryState::from_level);
+        if low == Some(true) && matches!(state, None | Some(BatteryState::Normal)) {
+            state = Some(BatteryState::Low);
+        }
+
+        self.power_state = DevicePowerState {
+            battery_state: state.or(self.power_state.battery_state),
+            battery_level: level,
+        };
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DevicePowerUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_state: Option<DevicePowerState>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SoftwareUpdateState {
+    #[default]
+    NoUpdate,
+    UpdatePending,
+    ReadyToInstall,
+    Installing,
+}
+
+/// Firmware update status of a device
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceSoftwareUpdate {
+    pub owner: ResourceLink,
+    pub state: SoftwareUpdateState,
+    pub problems: Vec<Value>,
+}
+
+impl DeviceSoftwareUpdate {
+    #[must_use]
+    pub const fn new(owner: ResourceLink) -> Self {
+        Self {
+            owner,
+            state: SoftwareUpdateState::NoUpdate,
+            problems: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeviceSoftwareUpdateUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<SoftwareUpdateState>,
+}
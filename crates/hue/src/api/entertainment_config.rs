@@ -1,6 +1,9 @@
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::api::ResourceLink;
+use crate::api::{Entertainment, RType, ResourceLink};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EntertainmentConfiguration {
@@ -74,7 +77,7 @@ pub struct EntertainmentConfigurationStreamMembers {
     pub index: u16,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum EntertainmentConfigurationAction {
     Start,
@@ -102,7 +105,6 @@ pub struct EntertainmentConfigurationUpdate {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(deny_unknown_fields)]
 pub struct EntertainmentConfigurationNew {
     pub configuration_type: EntertainmentConfigurationType,
     pub metadata: EntertainmentConfigurationMetadata,
@@ -196,3 +198,202 @@ pub struct EntertainmentConfigurationServiceLocationsNew {
     pub positions: Vec<Position>,
     pub service: ResourceLink,
 }
+
+/// Maximum number of channels in an entertainment configuration (the hue
+/// entertainment stream cannot address more)
+pub const MAX_ENTERTAINMENT_CHANNELS: usize = 20;
+
+/// A reason an entertainment configuration cannot be streamed to
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EntertainmentLocationError {
+    #[error("Service {0:?} is not a light entertainment service")]
+    NotEntertainment(ResourceLink),
+
+    #[error("Service {0:?} is listed more than once")]
+    Duplicate(ResourceLink),
+
+    #[error("Service {0:?} has no positions")]
+    NoPositions(ResourceLink),
+
+    #[error("Service {service:?} has {positions} positions, but supports at most {max}")]
+    TooManyPositions {
+        service: ResourceLink,
+        positions: usize,
+        max: usize,
+    },
+
+    #[error("Configuration needs {channels} channels, but at most {max} are supported")]
+    TooManyChannels { channels: usize, max: usize },
+}
+
+impl Entertainment {
+    /// Number of channels this service takes up in an entertainment
+    /// configuration: one per segment, or one for lights without segments
+    #[must_use]
+    pub fn channel_count(&self) -> usize {
+        self.segments
+            .as_ref()
+            .map_or(1, |segs| segs.segments.len().max(1))
+    }
+
+    /// Number of positions that can be given for this service
+    #[must_use]
+    pub fn max_positions(&self) -> usize {
+        self.segments.as_ref().map_or(1, |segs| {
+            usize::try_from(segs.max_segments)
+                .unwrap_or(usize::MAX)
+                .max(segs.segments.len())
+                .max(1)
+        })
+    }
+}
+
+/// Check that service locations can be streamed to.
+///
+/// Every service must be the entertainment service of a light, with a valid
+/// number of positions, and the channels needed must fit in one
+/// entertainment stream.
+///
+/// All problems found are returned, so they can be reported together.
+pub fn check_service_locations<'a>(
+    locations: &[EntertainmentConfigurationServiceLocations],
+    lookup: impl Fn(&ResourceLink) -> Option<&'a Entertainment>,
+) -> Vec<EntertainmentLocationError> {
+    let mut errors = vec![];
+    let mut seen = HashSet::new();
+    let mut channels = 0;
+
+    for location in locations {
+        let service = location.service;
+
+        if !seen.insert(service) {
+            errors.push(EntertainmentLocationError::Duplicate(service));
+            continue;
+        }
+
+        let ent = Some(service)
+            .filter(|svc| svc.rtype == RType::Entertainment)
+            .and_then(|svc| lookup(&svc))
+            .filter(|ent| ent.renderer);
+        let Some(ent) = ent else {
+            errors.push(EntertainmentLocationError::NotEntertainment(service));
+            continue;
+        };
+
+        let positions = location.positions.len();
+        if positions == 0 {
+            errors.push(EntertainmentLocationError::NoPositions(service));
+        } else if positions > ent.max_positions() {
+            errors.push(EntertainmentLocationError::TooManyPositions {
+                service,
+                positions,
+                max: ent.max_positions(),
+            });
+        }
+
+        channels += ent.channel_count();
+    }
+
+    if channels > MAX_ENTERTAINMENT_CHANNELS {
+        errors.push(EntertainmentLocationError::TooManyChannels {
+            channels,
+            max: MAX_ENTERTAINMENT_CHANNELS,
+        });
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::api::{
+        check_service_locations, Entertainment, EntertainmentConfigurationServiceLocations,
+        EntertainmentLocationError, EntertainmentSegment, EntertainmentSegments, Position, RType,
+        ResourceLink,
+    };
+
+    fn entertainment(segments: usize) -> Entertainment {
+        Entertainment {
+            equalizer: true,
+            owner: RType::Device.deterministic(segments),
+            proxy: true,
+            renderer: true,
+            max_streams: None,
+            renderer_reference: None,
+            segments: Some(EntertainmentSegments {
+                configurable: false,
+                max_segments: u32::try_from(segments).unwrap(),
+                segments: (0..segments)
+                    .map(|start| EntertainmentSegment {
+                        length: 1,
+                        start: u32::try_from(start).unwrap(),
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    fn location(
+        service: ResourceLink,
+        positions: usize,
+    ) -> EntertainmentConfigurationServiceLocations {
+        EntertainmentConfigurationServiceLocations {
+            equalization_factor: 1.0,
+            position: Position::default(),
+            positions: vec![Position::default(); positions],
+            service,
+        }
+    }
+
+    #[test]
+    fn valid_locations() {
+        let svc = RType::Entertainment.deterministic(1);
+        let ents = HashMap::from([(svc, entertainment(7))]);
+
+        let errors = check_service_locations(&[location(svc, 3)], |link| ents.get(link));
+        assert_eq!(errors, vec![]);
+    }
+
+    #[test]
+    fn invalid_locations() {
+        let light = RType::Light.deterministic(1);
+        let small = RType::Entertainment.deterministic(2);
+        let ents = HashMap::from([(small, entertainment(1))]);
+
+        let errors = check_service_locations(
+            &[location(light, 1), location(small, 2), location(small, 1)],
+            |link| ents.get(link),
+        );
+        assert_eq!(
+            errors,
+            vec![
+                EntertainmentLocationError::NotEntertainment(light),
+                EntertainmentLocationError::TooManyPositions {
+                    service: small,
+                    positions: 2,
+                    max: 1
+                },
+                EntertainmentLocationError::Duplicate(small),
+            ]
+        );
+    }
+
+    #[test]
+    fn too_many_channels() {
+        let ents: HashMap<ResourceLink, Entertainment> = (0..3)
+            .map(|idx| (RType::Entertainment.deterministic(idx), entertainment(7)))
+            .collect();
+        let locations: Vec<_> = ents.keys().map(|svc| location(*svc, 1)).collect();
+
+        let errors = check_service_locations(&locations, |link| ents.get(link));
+        assert_eq!(
+            errors,
+            vec![EntertainmentLocationError::TooManyChannels {
+                channels: 21,
+                max: 20
+            }]
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::api::{ColorTemperatureUpdate, ColorUpdate, DimmingUpdate, On, ResourceLink, Stub};
+use crate::api::{
+    ColorTemperatureDeltaUpdate, ColorTemperatureUpdate, ColorUpdate, DimmingDeltaUpdate,
+    DimmingUpdate, Light, LightDynamicsUpdate, LightUpdate, On, ResourceLink, Stub,
+};
 use crate::xy::XY;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -56,6 +59,12 @@ pub struct GroupedLightUpdate {
     pub color: Option<ColorUpdate>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub color_temperature: Option<ColorTemperatureUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamics: Option<LightDynamicsUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimming_delta: Option<DimmingDeltaUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_temperature_delta: Option<ColorTemperatureDeltaUpdate>,
 }
 
 impl GroupedLightUpdate {
@@ -100,4 +109,81 @@ impl GroupedLightUpdate {
             ..self
         }
     }
+
+    #[must_use]
+    pub fn with_duration(self, duration: Option<u32>) -> Self {
+        Self {
+            dynamics: duration.map(LightDynamicsUpdate::new),
+            ..self
+        }
+    }
+
+    /// Requested transition time, in milliseconds
+    #[must_use]
+    pub fn duration(&self) -> Option<u32> {
+        self.dynamics.and_then(|dynamics| dynamics.duration)
+    }
+
+    /// True if the given light supports every color mode used in this update
+    #[must_use]
+    pub const fn is_supported_by(&self, light: &Light) -> bool {
+        (self.color.is_none() || light.color.is_some())
+            && (self.color_temperature.is_none() || light.color_temperature.is_some())
+    }
+
+    /// Convert to an update for a single member light.
+    ///
+    /// The requested color is adapted to what the light supports, so a
+    /// color-temperature-only light gets the color temperature nearest to the
+    /// requested color, and a color-only light gets the color matching the
+    /// requested color temperature.
+    #[must_use]
+    pub fn for_light(&self, light: &Light) -> LightUpdate {
+        let mut upd = LightUpdate::from(self.clone());
+        let mut color = upd.color;
+        let mut color_temperature = upd.color_temperature;
+
+        if light.color.is_none() {
+            if let (Some(col), None) = (color, color_temperature) {
+                color_temperature = Some(ColorTemperatureUpdate::new(col.xy.to_mirek()));
+            }
+            color = None;
+        }
+
+        if let Some(ct) = &light.color_temperature {
+            if let Some(upd) = &mut color_temperature {
+                let schema = &ct.mirek_schema;
+                upd.mirek = u16::try_from(
+                    u32::from(upd.mirek).clamp(schema.mirek_minimum, schema.mirek_maximum),
+                )
+                .unwrap_or(upd.mirek);
+            }
+        } else {
+            if let (Some(ct), None, Some(_)) = (color_temperature, color, &light.color) {
+                color = Some(ColorUpdate {
+                    xy: XY::from_mirek(ct.mirek),
+                });
+            }
+            color_temperature = None;
+        }
+
+        upd.color = color;
+        upd.color_temperature = color_temperature;
+        upd
+    }
+}
+
+impl From<GroupedLightUpdate> for LightUpdate {
+    fn from(upd: GroupedLightUpdate) -> Self {
+        Self {
+            on: upd.on,
+            dimming: upd.dimming,
+            color: upd.color,
+            color_temperature: upd.color_temperature,
+            dynamics: upd.dynamics,
+            dimming_delta: upd.dimming_delta,
+            color_temperature_delta: upd.color_temperature_delta,
+            ..Self::default()
+        }
+    }
 }
@@ -4,7 +4,8 @@ use std::ops::{AddAssign, Sub};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::api::{DeviceArchetype, Identify, Metadata, MetadataUpdate, ResourceLink, Stub};
+use crate::api::{DeviceArchetype, Identify, Metadata, MetadataUpdate, RType, ResourceLink, Stub};
+use crate::error::{HueError, HueResult};
 use crate::hs::HS;
 use crate::xy::XY;
 
@@ -147,6 +148,60 @@ impl Light {
         }
     }
 
+    /// Create an on/off-only light (e.g. a smart plug or relay), without
+    /// dimming, color or effects
+    #[must_use]
+    pub fn new_on_off(owner: ResourceLink, metadata: LightMetadata) -> Self {
+        Self {
+            color_temperature_delta: None,
+            dimming_delta: None,
+            timed_effects: None,
+            product_data: Some(LightProductData {
+                function: Some(LightFunction::Functional),
+            }),
+            powerup: Some(LightPowerup {
+                preset: LightPowerupPreset::Safety,
+                configured: true,
+                on: LightPowerupOn::On {
+                    on: On { on: true },
+                },
+                dimming: LightPowerupDimming::None,
+                color: LightPowerupColor::None,
+            }),
+            signaling: Some(LightSignaling {
+                signal_values: vec![LightSignal::NoSignal, LightSignal::OnOff],
+                status: Value::Null,
+            }),
+            ..Self::new(owner, metadata)
+        }
+    }
+
+    /// Check that the light has every feature the update wants to change
+    pub fn check_update(&self, upd: &LightUpdate) -> HueResult<()> {
+        let features = [
+            (
+                "dimming",
+                self.dimming.is_some(),
+                upd.dimming.is_some() || upd.dimming_delta.is_some(),
+            ),
+            (
+                "color_temperature",
+                self.color_temperature.is_some(),
+                upd.color_temperature.is_some() || upd.color_temperature_delta.is_some(),
+            ),
+            ("color", self.color.is_some(), upd.color.is_some()),
+            ("gradient", self.gradient.is_some(), upd.gradient.is_some()),
+        ];
+
+        for (name, supported, requested) in features {
+            if requested && !supported {
+                return Err(HueError::FeatureUnsupported(RType::Light, name));
+            }
+        }
+
+        Ok(())
+    }
+
     #[must_use]
     pub fn as_dimming_opt(&self) -> Option<DimmingUpdate> {
         self.dimming.as_ref().map(|dim| DimmingUpdate {
@@ -526,7 +581,6 @@ pub struct LightEffectsV2Update {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(deny_unknown_fields)]
 pub struct LightEffectActionUpdate {
     #[serde(default)]
     pub effect: Option<LightEffect>,
@@ -534,7 +588,6 @@ pub struct LightEffectActionUpdate {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(deny_unknown_fields)]
 pub struct LightEffectParameters {
     #[serde(default)]
     pub color: Option<ColorUpdate>,
@@ -579,6 +632,12 @@ pub struct LightUpdate {
     pub gradient: Option<LightGradientUpdate>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub effects_v2: Option<LightEffectsV2Update>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamics: Option<LightDynamicsUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimming_delta: Option<DimmingDeltaUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_temperature_delta: Option<ColorTemperatureDeltaUpdate>,
 }
 
 impl LightUpdate {
@@ -637,6 +696,71 @@ impl LightUpdate {
             ..self
         }
     }
+
+    #[must_use]
+    pub fn with_duration(self, duration: Option<u32>) -> Self {
+        Self {
+            dynamics: duration.map(LightDynamicsUpdate::new),
+            ..self
+        }
+    }
+
+    /// Requested transition time, in milliseconds
+    #[must_use]
+    pub fn duration(&self) -> Option<u32> {
+        self.dynamics.and_then(|dynamics| dynamics.duration)
+    }
+
+    /// True if the update changes brightness or color temperature relative
+    /// to the current state
+    #[must_use]
+    pub const fn is_relative(&self) -> bool {
+        self.dimming_delta.is_some() || self.color_temperature_delta.is_some()
+    }
+
+    /// Combine with a later update, into one with the same effect as
+    /// applying both in order. Only valid if neither is relative.
+    #[must_use]
+    pub fn merge(self, newer: Self) -> Self {
+        // a color replaces an earlier color temperature, and vice versa
+        let (color, color_temperature) =
+            if newer.color.is_some() || newer.color_temperature.is_some() {
+                (newer.color, newer.color_temperature)
+            } else {
+                (self.color, self.color_temperature)
+            };
+
+        Self {
+            metadata: newer.metadata.or(self.metadata),
+            on: newer.on.or(self.on),
+            dimming: newer.dimming.or(self.dimming),
+            color,
+            color_temperature,
+            gradient: newer.gradient.or(self.gradient),
+            effects_v2: newer.effects_v2.or(self.effects_v2),
+            dynamics: newer.dynamics.or(self.dynamics),
+            dimming_delta: newer.dimming_delta.or(self.dimming_delta),
+            color_temperature_delta: newer
+                .color_temperature_delta
+                .or(self.color_temperature_delta),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct LightDynamicsUpdate {
+    /// Transition time, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+}
+
+impl LightDynamicsUpdate {
+    #[must_use]
+    pub const fn new(duration: u32) -> Self {
+        Self {
+            duration: Some(duration),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -659,8 +783,58 @@ impl From<Dimming> for DimmingUpdate {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Delta {}
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeltaAction {
+    Up,
+    Down,
+    Stop,
+}
+
+impl DeltaAction {
+    /// Apply direction to `value`, or `None` for [`DeltaAction::Stop`]
+    #[must_use]
+    pub fn signed<T: std::ops::Neg<Output = T>>(self, value: T) -> Option<T> {
+        match self {
+            Self::Up => Some(value),
+            Self::Down => Some(-value),
+            Self::Stop => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct DimmingDeltaUpdate {
+    pub action: DeltaAction,
+    /// Brightness change, in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness_delta: Option<f64>,
+}
+
+impl DimmingDeltaUpdate {
+    /// Signed brightness change, or `None` when stopping
+    #[must_use]
+    pub fn delta(&self) -> Option<f64> {
+        self.action
+            .signed(self.brightness_delta.unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ColorTemperatureDeltaUpdate {
+    pub action: DeltaAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirek_delta: Option<u16>,
+}
+
+impl ColorTemperatureDeltaUpdate {
+    /// Signed mirek change, or `None` when stopping
+    #[must_use]
+    pub fn delta(&self) -> Option<i32> {
+        self.action
+            .signed(i32::from(self.mirek_delta.unwrap_or_default()))
+    }
+}
 
 #[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct On {
@@ -706,6 +880,36 @@ pub struct ColorGamut {
 }
 
 impl ColorGamut {
+    pub const GAMUT_A: Self = Self {
+        red: XY {
+            x: 0.7040,
+            y: 0.2960,
+        },
+        green: XY {
+            x: 0.2151,
+            y: 0.7106,
+        },
+        blue: XY {
+            x: 0.1380,
+            y: 0.0800,
+        },
+    };
+
+    pub const GAMUT_B: Self = Self {
+        red: XY {
+            x: 0.6750,
+            y: 0.3220,
+        },
+        green: XY {
+            x: 0.4090,
+            y: 0.5180,
+        },
+        blue: XY {
+            x: 0.1670,
+            y: 0.0400,
+        },
+    };
+
     pub const GAMUT_C: Self = Self {
         red: XY {
             x: 0.6915,
@@ -735,6 +939,17 @@ impl ColorGamut {
             y: 0.027_116,
         },
     };
+
+    /// The color gamut for a known gamut type
+    #[must_use]
+    pub const fn for_type(gamut_type: &GamutType) -> Option<Self> {
+        match gamut_type {
+            GamutType::A => Some(Self::GAMUT_A),
+            GamutType::B => Some(Self::GAMUT_B),
+            GamutType::C => Some(Self::GAMUT_C),
+            GamutType::Other => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -803,3 +1018,61 @@ impl From<Dimming> for f64 {
         value.brightness
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::api::{DeviceArchetype, Dimming, Light, LightMetadata, LightUpdate, On, RType};
+    use crate::error::HueError;
+    use crate::xy::XY;
+
+    fn plug() -> Light {
+        let owner = RType::Device.link_to(Uuid::nil());
+        Light::new_on_off(owner, LightMetadata::new(DeviceArchetype::Plug, "plug"))
+    }
+
+    #[test]
+    fn on_off_light_accepts_on() {
+        let upd = LightUpdate::new().with_on(On::new(false));
+        assert!(plug().check_update(&upd).is_ok());
+    }
+
+    #[test]
+    fn on_off_light_rejects_brightness() {
+        let upd = LightUpdate::new().with_brightness(Some(50.0));
+        let err = plug().check_update(&upd).unwrap_err();
+        assert!(matches!(
+            err,
+            HueError::FeatureUnsupported(RType::Light, "dimming")
+        ));
+    }
+
+    #[test]
+    fn merge_keeps_latest_values() {
+        let first = LightUpdate::new()
+            .with_on(On::new(true))
+            .with_brightness(Some(20.0))
+            .with_color_temperature(300);
+        let second = LightUpdate::new()
+            .with_brightness(Some(80.0))
+            .with_color_xy(XY::new(0.3, 0.4));
+
+        let upd = first.merge(second);
+        assert_eq!(upd.on, Some(On::new(true)));
+        assert_eq!(upd.dimming.map(|dim| dim.brightness), Some(80.0));
+        assert_eq!(upd.color.map(|col| col.xy), Some(XY::new(0.3, 0.4)));
+        assert!(upd.color_temperature.is_none());
+    }
+
+    #[test]
+    fn dimmable_light_accepts_brightness() {
+        let mut light = plug();
+        light.dimming = Some(Dimming {
+            brightness: 100.0,
+            min_dim_level: None,
+        });
+        let upd = LightUpdate::new().with_brightness(Some(50.0));
+        assert!(light.check_update(&upd).is_ok());
+    }
+}
@@ -1,19 +1,38 @@
+mod behavior;
+mod button;
+mod contact;
 mod device;
 mod entertainment;
 mod entertainment_config;
 mod grouped_light;
 mod light;
+mod power;
 mod resource;
 mod room;
+mod rotary;
 mod scene;
 mod stream;
 mod stubs;
 mod update;
 
-pub use device::{Device, DeviceArchetype, DeviceProductData, DeviceUpdate, Identify};
+pub use behavior::{
+    BehaviorInstance, BehaviorInstanceMetadata, BehaviorInstanceUpdate, BehaviorScript,
+    ButtonAction, ButtonActions, ButtonConfiguration, ButtonTarget, DollarRef, SceneCycleExtended,
+    SceneRecallStep, SceneRecallTarget, SimpleAction,
+};
+pub use button::{Button, ButtonData, ButtonEvent, ButtonMetadata, ButtonReport, ButtonUpdate};
+pub use contact::{
+    Contact, ContactReport, ContactState, ContactUpdate, Tamper, TamperReport, TamperSource,
+    TamperState, TamperUpdate,
+};
+pub use device::{
+    BatteryState, Device, DeviceArchetype, DevicePower, DevicePowerState, DevicePowerUpdate,
+    DeviceProductData, DeviceSoftwareUpdate, DeviceSoftwareUpdateUpdate, DeviceUpdate, Identify,
+    IdentifyAction, IdentifyUpdate, SoftwareUpdateState,
+};
 pub use entertainment::{Entertainment, EntertainmentSegment, EntertainmentSegments};
 pub use entertainment_config::{
-    EntertainmentConfiguration, EntertainmentConfigurationAction,
+    check_service_locations, EntertainmentConfiguration, EntertainmentConfigurationAction,
     EntertainmentConfigurationChannels, EntertainmentConfigurationLocations,
     EntertainmentConfigurationLocationsNew, EntertainmentConfigurationLocationsUpdate,
     EntertainmentConfigurationMetadata, EntertainmentConfigurationNew,
@@ -21,34 +40,38 @@ pub use entertainment_config::{
     EntertainmentConfigurationServiceLocationsUpdate, EntertainmentConfigurationStatus,
     EntertainmentConfigurationStreamMembers, EntertainmentConfigurationStreamProxy,
     EntertainmentConfigurationStreamProxyMode, EntertainmentConfigurationStreamProxyUpdate,
-    EntertainmentConfigurationType, EntertainmentConfigurationUpdate, Position,
+    EntertainmentConfigurationType, EntertainmentConfigurationUpdate, EntertainmentLocationError,
+    Position, MAX_ENTERTAINMENT_CHANNELS,
 };
 pub use grouped_light::{GroupedLight, GroupedLightUpdate};
 pub use light::{
-    ColorGamut, ColorTemperature, ColorTemperatureUpdate, ColorUpdate, Delta, Dimming,
-    DimmingUpdate, GamutType, Light, LightAlert, LightColor, LightDynamics, LightDynamicsStatus,
-    LightEffect, LightEffectActionUpdate, LightEffectParameters, LightEffectStatus,
-    LightEffectValues, LightEffects, LightEffectsV2, LightEffectsV2Update, LightFunction,
-    LightGradient, LightGradientMode, LightGradientPoint, LightGradientUpdate, LightMetadata,
-    LightMode, LightPowerup, LightPowerupColor, LightPowerupDimming, LightPowerupOn,
-    LightPowerupPreset, LightProductData, LightSignal, LightSignaling, LightTimedEffects,
-    LightUpdate, MirekSchema, On,
+    ColorGamut, ColorTemperature, ColorTemperatureDeltaUpdate, ColorTemperatureUpdate, ColorUpdate,
+    DeltaAction, Dimming, DimmingDeltaUpdate, DimmingUpdate, GamutType, Light, LightAlert,
+    LightColor, LightDynamics, LightDynamicsStatus, LightDynamicsUpdate, LightEffect,
+    LightEffectActionUpdate, LightEffectParameters, LightEffectStatus, LightEffectValues,
+    LightEffects, LightEffectsV2, LightEffectsV2Update, LightFunction, LightGradient,
+    LightGradientMode, LightGradientPoint, LightGradientUpdate, LightMetadata, LightMode,
+    LightPowerup, LightPowerupColor, LightPowerupDimming, LightPowerupOn, LightPowerupPreset,
+    LightProductData, LightSignal, LightSignaling, LightTimedEffects, LightUpdate, MirekSchema, On,
 };
+pub use power::{PowerData, PowerMeasurement, PowerMeasurementUpdate, PowerReading, PowerReport};
 pub use resource::{RType, ResourceLink, ResourceRecord};
 pub use room::{Room, RoomArchetype, RoomMetadata, RoomMetadataUpdate, RoomUpdate};
+pub use rotary::{
+    RelativeRotary, RelativeRotaryState, RelativeRotaryUpdate, RotaryAction, RotaryDirection,
+    RotaryEvent, RotaryReport, Rotation,
+};
 pub use scene::{
-    Scene, SceneAction, SceneActionElement, SceneActive, SceneMetadata, SceneRecall, SceneStatus,
-    SceneStatusUpdate, SceneUpdate,
+    remap_actions, Scene, SceneAction, SceneActionElement, SceneActive, SceneMetadata, SceneRecall,
+    SceneStatus, SceneStatusUpdate, SceneUpdate,
 };
 use serde::ser::SerializeMap;
 pub use stream::HueStreamKey;
 pub use stubs::{
-    BehaviorInstance, BehaviorInstanceMetadata, BehaviorScript, Bridge, BridgeHome, Button,
-    ButtonData, ButtonMetadata, ButtonReport, DevicePower, DeviceSoftwareUpdate, DollarRef,
-    GeofenceClient, Geolocation, GroupedLightLevel, GroupedMotion, Homekit, LightLevel, Matter,
-    Metadata, MetadataUpdate, Motion, PrivateGroup, PublicImage, RelativeRotary, SmartScene,
-    Taurus, Temperature, TimeZone, ZigbeeConnectivity, ZigbeeConnectivityStatus,
-    ZigbeeDeviceDiscovery, Zone,
+    Bridge, BridgeHome, BridgeHomeUpdate, BridgeUpdate, GeofenceClient, GeofenceClientUpdate,
+    Geolocation, GroupedLightLevel, GroupedMotion, Homekit, LightLevel, Matter, Metadata,
+    MetadataUpdate, Motion, PrivateGroup, PublicImage, SmartScene, Taurus, Temperature, TimeZone,
+    ZigbeeConnectivity, ZigbeeConnectivityStatus, ZigbeeDeviceDiscovery, Zone,
 };
 pub use update::{Update, UpdateRecord};
 
@@ -82,6 +105,7 @@ pub enum Resource {
     Bridge(Bridge),
     BridgeHome(BridgeHome),
     Button(Button),
+    Contact(Contact),
     Device(Device),
     DevicePower(DevicePower),
     DeviceSoftwareUpdate(DeviceSoftwareUpdate),
@@ -97,12 +121,14 @@ pub enum Resource {
     LightLevel(LightLevel),
     Matter(Matter),
     Motion(Motion),
+    PowerMeasurement(PowerMeasurement),
     PrivateGroup(PrivateGroup),
     PublicImage(PublicImage),
     RelativeRotary(RelativeRotary),
     Room(Room),
     Scene(Scene),
     SmartScene(SmartScene),
+    Tamper(Tamper),
     #[serde(rename = "taurus_7455")]
     Taurus(Taurus),
     Temperature(Temperature),
@@ -121,6 +147,7 @@ impl Resource {
             Self::Bridge(_) => RType::Bridge,
             Self::BridgeHome(_) => RType::BridgeHome,
             Self::Button(_) => RType::Button,
+            Self::Contact(_) => RType::Contact,
             Self::Device(_) => RType::Device,
             Self::DevicePower(_) => RType::DevicePower,
             Self::DeviceSoftwareUpdate(_) => RType::DeviceSoftwareUpdate,
@@ -136,12 +163,14 @@ impl Resource {
             Self::LightLevel(_) => RType::LightLevel,
             Self::Matter(_) => RType::Matter,
             Self::Motion(_) => RType::Motion,
+            Self::PowerMeasurement(_) => RType::PowerMeasurement,
             Self::PrivateGroup(_) => RType::PrivateGroup,
             Self::PublicImage(_) => RType::PublicImage,
             Self::RelativeRotary(_) => RType::RelativeRotary,
             Self::Room(_) => RType::Room,
             Self::Scene(_) => RType::Scene,
             Self::SmartScene(_) => RType::SmartScene,
+            Self::Tamper(_) => RType::Tamper,
             Self::Taurus(_) => RType::Taurus,
             Self::Temperature(_) => RType::Temperature,
             Self::ZigbeeConnectivity(_) => RType::ZigbeeConnectivity,
@@ -160,6 +189,7 @@ impl Resource {
             Self::Bridge(obj) => Some(obj.owner),
             Self::BridgeHome(_) => None,
             Self::Button(obj) => Some(obj.owner),
+            Self::Contact(obj) => Some(obj.owner),
             Self::Device(_) => None,
             Self::DevicePower(obj) => Some(obj.owner),
             Self::DeviceSoftwareUpdate(obj) => Some(obj.owner),
@@ -175,12 +205,14 @@ impl Resource {
             Self::LightLevel(obj) => Some(obj.owner),
             Self::Matter(_) => None,
             Self::Motion(obj) => Some(obj.owner),
+            Self::PowerMeasurement(obj) => Some(obj.owner),
             Self::PrivateGroup(_) => None,
             Self::PublicImage(_) => None,
             Self::RelativeRotary(obj) => Some(obj.owner),
             Self::Room(_) => None,
             Self::Scene(_) => None,
             Self::SmartScene(_) => None,
+            Self::Tamper(obj) => Some(obj.owner),
             Self::Taurus(obj) => Some(obj.owner),
             Self::Temperature(obj) => Some(obj.owner),
             Self::ZigbeeConnectivity(obj) => Some(obj.owner),
@@ -197,6 +229,7 @@ impl Resource {
             RType::Bridge => Self::Bridge(from_value(obj)?),
             RType::BridgeHome => Self::BridgeHome(from_value(obj)?),
             RType::Button => Self::Button(from_value(obj)?),
+            RType::Contact => Self::Contact(from_value(obj)?),
             RType::Device => Self::Device(from_value(obj)?),
             RType::DevicePower => Self::DevicePower(from_value(obj)?),
             RType::DeviceSoftwareUpdate => Self::DeviceSoftwareUpdate(from_value(obj)?),
@@ -212,12 +245,14 @@ impl Resource {
             RType::LightLevel => Self::LightLevel(from_value(obj)?),
             RType::Matter => Self::Matter(from_value(obj)?),
             RType::Motion => Self::Motion(from_value(obj)?),
+            RType::PowerMeasurement => Self::PowerMeasurement(from_value(obj)?),
             RType::PrivateGroup => Self::PrivateGroup(from_value(obj)?),
             RType::PublicImage => Self::PublicImage(from_value(obj)?),
             RType::RelativeRotary => Self::RelativeRotary(from_value(obj)?),
             RType::Room => Self::Room(from_value(obj)?),
             RType::Scene => Self::Scene(from_value(obj)?),
             RType::SmartScene => Self::SmartScene(from_value(obj)?),
+            RType::Tamper => Self::Tamper(from_value(obj)?),
             RType::Taurus => Self::Taurus(from_value(obj)?),
             RType::Temperature => Self::Temperature(from_value(obj)?),
             RType::ZigbeeConnectivity => Self::ZigbeeConnectivity(from_value(obj)?),
@@ -280,7 +315,10 @@ resource_conversion_impl!(BehaviorScript);
 resource_conversion_impl!(Bridge);
 resource_conversion_impl!(BridgeHome);
 resource_conversion_impl!(Button);
+resource_conversion_impl!(Contact);
 resource_conversion_impl!(Device);
+resource_conversion_impl!(DevicePower);
+resource_conversion_impl!(DeviceSoftwareUpdate);
 resource_conversion_impl!(Entertainment);
 resource_conversion_impl!(EntertainmentConfiguration);
 resource_conversion_impl!(GeofenceClient);
@@ -291,12 +329,14 @@ resource_conversion_impl!(Light);
 resource_conversion_impl!(LightLevel);
 resource_conversion_impl!(Matter);
 resource_conversion_impl!(Motion);
+resource_conversion_impl!(PowerMeasurement);
 resource_conversion_impl!(PrivateGroup);
 resource_conversion_impl!(PublicImage);
 resource_conversion_impl!(RelativeRotary);
 resource_conversion_impl!(Room);
 resource_conversion_impl!(Scene);
 resource_conversion_impl!(SmartScene);
+resource_conversion_impl!(Tamper);
 resource_conversion_impl!(Taurus);
 resource_conversion_impl!(Temperature);
 resource_conversion_impl!(ZigbeeConnectivity);
@@ -337,7 +377,9 @@ impl<'a> V1Reply<'a> {
         self.add_option("on", upd.on)?
             .add_option("bri", upd.bri)?
             .add_option("xy", upd.xy)?
-            .add_option("ct", upd.ct)
+            .add_option("ct", upd.ct)?
+            .add_option("transitiontime", upd.transitiontime)?
+            .add_option("alert", upd.alert)
     }
 
     pub fn add<T: Serialize>(mut self, name: &'a str, value: T) -> HueResult<Self> {
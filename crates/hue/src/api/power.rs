@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ResourceLink;
+use crate::date_format;
+
+/// Power and energy readings from a device with a power meter (e.g. a smart
+/// plug)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PowerMeasurement {
+    pub owner: ResourceLink,
+    pub enabled: bool,
+    pub power: PowerData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PowerData {
+    pub power_valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_report: Option<PowerReport>,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PowerReport {
+    #[serde(with = "date_format::utc_ms")]
+    pub changed: DateTime<Utc>,
+    /// Active power, in watts
+    pub power: f64,
+    /// Supply voltage, in volts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage: Option<f64>,
+    /// Current, in amperes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<f64>,
+    /// Total energy consumed, in kilowatt-hours
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy: Option<f64>,
+}
+
+/// A (partial) set of readings from a power meter
+#[derive(Copy, Debug, Clone, Default)]
+pub struct PowerReading {
+    pub power: Option<f64>,
+    pub voltage: Option<f64>,
+    pub current: Option<f64>,
+    pub energy: Option<f64>,
+}
+
+impl PowerReading {
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.power.is_none()
+            && self.voltage.is_none()
+            && self.current.is_none()
+            && self.energy.is_none()
+    }
+}
+
+impl PowerMeasurement {
+    #[must_use]
+    pub fn new(owner: ResourceLink) -> Self {
+        Self {
+            owner,
+            enabled: true,
+            power: PowerData::default(),
+        }
+    }
+
+    /// Update the power report with new readings. Values missing from the
+    /// reading keep their previous value.
+    pub fn report(&mut self, reading: &PowerReading) {
+        let old = self.power.power_report;
+        let power = reading.power.or_else(|| Some(old?.power));
+
+        self.power = PowerData {
+            power_valid: power.is_some(),
+            power_report: Some(PowerReport {
+                changed: Utc::now(),
+                power: power.unwrap_or_default(),
+                voltage: reading.voltage.or_else(|| old?.voltage),
+                current: reading.current.or_else(|| old?.current),
+                energy: reading.energy.or_else(|| old?.energy),
+            }),
+        };
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PowerMeasurementUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{PowerMeasurement, PowerReading, RType};
+
+    #[test]
+    fn partial_reports() {
+        let mut pm = PowerMeasurement::new(RType::Device.deterministic(1));
+        assert!(!pm.power.power_valid);
+
+        pm.report(&PowerReading {
+            power: Some(12.5),
+            energy: Some(3.25),
+            ..PowerReading::default()
+        });
+        pm.report(&PowerReading {
+            voltage: Some(231.0),
+            ..PowerReading::default()
+        });
+
+        let rep = pm.power.power_report.unwrap();
+        assert!(pm.power.power_valid);
+        assert!((rep.power - 12.5).abs() < f64::EPSILON);
+        assert_eq!(rep.voltage, Some(231.0));
+        assert_eq!(rep.energy, Some(3.25));
+        assert_eq!(rep.current, None);
+    }
+}
@@ -15,6 +15,7 @@ pub enum RType {
     Bridge,
     BridgeHome,
     Button,
+    Contact,
     Device,
     DevicePower,
     DeviceSoftwareUpdate,
@@ -30,12 +31,14 @@ pub enum RType {
     LightLevel,
     Matter,
     Motion,
+    PowerMeasurement,
     PrivateGroup,
     PublicImage,
     RelativeRotary,
     Room,
     Scene,
     SmartScene,
+    Tamper,
     #[serde(rename = "taurus_7455")]
     Taurus,
     Temperature,
@@ -103,6 +103,96 @@ pub enum RoomArchetype {
     Other,
 }
 
+impl RoomArchetype {
+    /// Keywords used to guess the archetype of a room from its name.
+    ///
+    /// Checked in order, so more specific keywords (e.g. "kids bedroom") come
+    /// before more general ones (e.g. "bedroom").
+    const KEYWORDS: &'static [(&'static str, Self)] = &[
+        ("living", Self::LivingRoom),
+        ("kitchen", Self::Kitchen),
+        ("dining", Self::Dining),
+        ("kid", Self::KidsBedroom),
+        ("child", Self::KidsBedroom),
+        ("nursery", Self::Nursery),
+        ("baby", Self::Nursery),
+        ("guest", Self::GuestRoom),
+        ("bed", Self::Bedroom),
+        ("bath", Self::Bathroom),
+        ("shower", Self::Bathroom),
+        ("toilet", Self::Toilet),
+        ("restroom", Self::Toilet),
+        ("wc", Self::Toilet),
+        ("office", Self::Office),
+        ("study", Self::Office),
+        ("gym", Self::Gym),
+        ("fitness", Self::Gym),
+        ("hall", Self::Hallway),
+        ("corridor", Self::Hallway),
+        ("front door", Self::FrontDoor),
+        ("entrance", Self::FrontDoor),
+        ("entry", Self::FrontDoor),
+        ("garage", Self::Garage),
+        ("terrace", Self::Terrace),
+        ("patio", Self::Terrace),
+        ("garden", Self::Garden),
+        ("yard", Self::Garden),
+        ("driveway", Self::Driveway),
+        ("carport", Self::Carport),
+        ("downstairs", Self::Downstairs),
+        ("basement", Self::Downstairs),
+        ("upstairs", Self::Upstairs),
+        ("top floor", Self::TopFloor),
+        ("attic", Self::Attic),
+        ("loft", Self::Attic),
+        ("stair", Self::Staircase),
+        ("lounge", Self::Lounge),
+        ("man cave", Self::ManCave),
+        ("computer", Self::Computer),
+        ("studio", Self::Studio),
+        ("music", Self::Music),
+        ("tv", Self::Tv),
+        ("media", Self::Tv),
+        ("cinema", Self::Tv),
+        ("reading", Self::Reading),
+        ("library", Self::Reading),
+        ("closet", Self::Closet),
+        ("wardrobe", Self::Closet),
+        ("storage", Self::Storage),
+        ("pantry", Self::Storage),
+        ("laundry", Self::LaundryRoom),
+        ("utility", Self::LaundryRoom),
+        ("balcony", Self::Balcony),
+        ("porch", Self::Porch),
+        ("barbecue", Self::Barbecue),
+        ("bbq", Self::Barbecue),
+        ("pool", Self::Pool),
+        ("recreation", Self::Recreation),
+        ("game", Self::Recreation),
+        ("play", Self::Recreation),
+        ("home", Self::Home),
+        ("house", Self::Home),
+    ];
+
+    /// Guess the archetype of a room from its name (e.g. "Kitchen" or
+    /// "`kids_bedroom_2`"), if any of the known keywords match.
+    ///
+    /// Keywords are matched at the start of words, so "bedroom" matches
+    /// "bed", but "embedded" does not.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name
+            .to_lowercase()
+            .replace(|c: char| !c.is_alphanumeric(), " ");
+        let name = format!(" {}", name.split_whitespace().collect::<Vec<_>>().join(" "));
+
+        Self::KEYWORDS
+            .iter()
+            .find(|(keyword, _)| name.contains(&format!(" {keyword}")))
+            .map(|(_, archetype)| *archetype)
+    }
+}
+
 impl RoomMetadata {
     #[must_use]
     pub fn new(archetype: RoomArchetype, name: &str) -> Self {
@@ -163,3 +253,30 @@ impl Sub<&Room> for &Room {
         upd
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::api::RoomArchetype;
+
+    #[test]
+    fn archetype_from_name() {
+        let guess = RoomArchetype::from_name;
+
+        assert_eq!(guess("Kitchen"), Some(RoomArchetype::Kitchen));
+        assert_eq!(guess("living_room"), Some(RoomArchetype::LivingRoom));
+        assert_eq!(guess("Kids Bedroom"), Some(RoomArchetype::KidsBedroom));
+        assert_eq!(guess("master-bedroom"), Some(RoomArchetype::Bedroom));
+        assert_eq!(guess("Front door"), Some(RoomArchetype::FrontDoor));
+        assert_eq!(guess("TV corner"), Some(RoomArchetype::Tv));
+        assert_eq!(guess("Hallway 2"), Some(RoomArchetype::Hallway));
+    }
+
+    #[test]
+    fn archetype_from_name_word_start() {
+        let guess = RoomArchetype::from_name;
+
+        assert_eq!(guess("Embedded"), None);
+        assert_eq!(guess("Cellar"), None);
+        assert_eq!(guess(""), None);
+    }
+}
@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ResourceLink;
+use crate::date_format;
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RotaryAction {
+    /// First event of a rotation
+    Start,
+    /// Continued rotation
+    Repeat,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RotaryDirection {
+    ClockWise,
+    CounterClockWise,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    pub direction: RotaryDirection,
+    /// Amount of rotation, in steps
+    pub steps: u32,
+    /// Duration of the rotation, in milliseconds
+    pub duration: u32,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RotaryEvent {
+    pub action: RotaryAction,
+    pub rotation: Rotation,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RotaryReport {
+    #[serde(with = "date_format::utc_ms")]
+    pub updated: DateTime<Utc>,
+    pub action: RotaryAction,
+    pub rotation: Rotation,
+}
+
+#[derive(Copy, Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct RelativeRotaryState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_event: Option<RotaryEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotary_report: Option<RotaryReport>,
+}
+
+/// Rotary dial (e.g. Hue Tap Dial, Ikea Symfonisk)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RelativeRotary {
+    pub owner: ResourceLink,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_rotary: Option<RelativeRotaryState>,
+}
+
+impl RelativeRotary {
+    #[must_use]
+    pub const fn new(owner: ResourceLink) -> Self {
+        Self {
+            owner,
+            relative_rotary: None,
+        }
+    }
+
+    pub fn report(&mut self, event: RotaryEvent) {
+        self.relative_rotary = Some(RelativeRotaryState {
+            last_event: Some(event),
+            rotary_report: Some(RotaryReport {
+                updated: Utc::now(),
+                action: event.action,
+                rotation: event.rotation,
+            }),
+        });
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RelativeRotaryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_rotary: Option<RelativeRotaryState>,
+}
@@ -4,7 +4,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::api::{ColorTemperatureUpdate, ColorUpdate, DimmingUpdate, On, ResourceLink};
+use crate::api::{ColorTemperatureUpdate, ColorUpdate, DimmingUpdate, Light, On, ResourceLink};
 use crate::date_format;
 
 #[derive(Copy, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -80,12 +80,94 @@ pub struct SceneAction {
     pub effects: Value,
 }
 
+impl SceneAction {
+    /// This action, without the parts the given light cannot do
+    #[must_use]
+    pub fn adapted_to(&self, light: &Light) -> Self {
+        let mut res = self.clone();
+        if light.dimming.is_none() {
+            res.dimming = None;
+        }
+        if light.color_temperature.is_none() {
+            res.color_temperature = None;
+        }
+        if light.color.is_none() {
+            res.color = None;
+        }
+        if light.gradient.is_none() {
+            res.gradient = None;
+        }
+        if light.effects.is_none() && light.effects_v2.is_none() {
+            res.effects = Value::Null;
+        }
+        res
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SceneActionElement {
     pub action: SceneAction,
     pub target: ResourceLink,
 }
 
+/// How capable a light is, from on/off only (0) to gradient (4)
+fn capability(light: &Light) -> u8 {
+    if light.gradient.is_some() {
+        4
+    } else if light.color.is_some() {
+        3
+    } else if light.color_temperature.is_some() {
+        2
+    } else {
+        u8::from(light.dimming.is_some())
+    }
+}
+
+/// Map scene actions onto another set of lights (e.g. to copy a scene to
+/// another room).
+///
+/// Every target light gets the action of a source light with the closest
+/// capabilities (preferring more capable lights, since their actions can
+/// be reduced). Lights with the same capabilities are paired up in order,
+/// and source actions are reused if there are more target lights than
+/// source lights. Parts of an action the target light cannot do are
+/// dropped.
+#[must_use]
+pub fn remap_actions(
+    source: &[(&SceneActionElement, &Light)],
+    targets: &[(ResourceLink, &Light)],
+) -> Vec<SceneActionElement> {
+    if source.is_empty() {
+        return vec![];
+    }
+
+    let mut used = [0usize; 5];
+
+    targets
+        .iter()
+        .map(|(target, light)| {
+            let cap = capability(light);
+            let best = source
+                .iter()
+                .map(|(_, src)| capability(src))
+                .min_by_key(|src| (src.abs_diff(cap), std::cmp::Reverse(*src)))
+                .unwrap_or_default();
+
+            let candidates: Vec<_> = source
+                .iter()
+                .filter(|(_, src)| capability(src) == best)
+                .collect();
+            let (elem, _) = candidates[used[usize::from(cap)] % candidates.len()];
+            used[usize::from(cap)] += 1;
+
+            SceneActionElement {
+                action: elem.action.adapted_to(light),
+                target: *target,
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct SceneMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -186,3 +268,88 @@ pub struct SceneRecall {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dimming: Option<DimmingUpdate>,
 }
+
+impl SceneRecall {
+    /// Scene status after this recall, if it recalls the scene at all
+    #[must_use]
+    pub const fn active(&self) -> Option<SceneActive> {
+        match self.action {
+            Some(SceneStatusUpdate::Active | SceneStatusUpdate::Static) => {
+                Some(SceneActive::Static)
+            }
+            Some(SceneStatusUpdate::DynamicPalette) => Some(SceneActive::DynamicPalette),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    use crate::api::{
+        remap_actions, DeviceArchetype, Dimming, DimmingUpdate, Light, LightMetadata, On, RType,
+        SceneAction, SceneActionElement,
+    };
+
+    fn light(dimmable: bool) -> Light {
+        let owner = RType::Device.link_to(Uuid::new_v4());
+        let metadata = LightMetadata::new(DeviceArchetype::SultanBulb, "light");
+        if dimmable {
+            let mut light = Light::new(owner, metadata);
+            light.dimming = Some(Dimming {
+                brightness: 100.0,
+                min_dim_level: None,
+            });
+            light
+        } else {
+            Light::new_on_off(owner, metadata)
+        }
+    }
+
+    fn element(brightness: f64) -> SceneActionElement {
+        SceneActionElement {
+            action: SceneAction {
+                color: None,
+                color_temperature: None,
+                dimming: Some(DimmingUpdate { brightness }),
+                on: Some(On::new(true)),
+                gradient: None,
+                effects: Value::Null,
+            },
+            target: RType::Light.link_to(Uuid::new_v4()),
+        }
+    }
+
+    #[test]
+    fn remap_by_capability() {
+        let (dim, plug) = (light(true), light(false));
+        let (low, high) = (element(10.0), element(90.0));
+        let source = [(&low, &dim), (&high, &dim)];
+
+        let targets = [
+            (RType::Light.link_to(Uuid::new_v4()), &plug),
+            (RType::Light.link_to(Uuid::new_v4()), &dim),
+            (RType::Light.link_to(Uuid::new_v4()), &dim),
+            (RType::Light.link_to(Uuid::new_v4()), &dim),
+        ];
+
+        let res = remap_actions(&source, &targets);
+        let brightness: Vec<_> = res
+            .iter()
+            .map(|elem| elem.action.dimming.as_ref().map(|dim| dim.brightness))
+            .collect();
+
+        assert_eq!(brightness, [None, Some(10.0), Some(90.0), Some(10.0)]);
+        assert!(res.iter().all(|elem| elem.action.on == Some(On::new(true))));
+        assert_eq!(res[0].target, targets[0].0);
+    }
+
+    #[test]
+    fn remap_empty_source() {
+        let plug = light(false);
+        let targets = [(RType::Light.link_to(Uuid::new_v4()), &plug)];
+        assert!(remap_actions(&[], &targets).is_empty());
+    }
+}
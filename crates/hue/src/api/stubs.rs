@@ -1,12 +1,10 @@
 use std::collections::BTreeSet;
 
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use uuid::Uuid;
 
 use crate::api::{DeviceArchetype, ResourceLink, SceneMetadata};
-use crate::{best_guess_timezone, date_format};
+use crate::best_guess_timezone;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Bridge {
@@ -15,110 +13,71 @@ pub struct Bridge {
     pub time_zone: TimeZone,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BridgeHome {
-    pub children: BTreeSet<ResourceLink>,
-    pub services: BTreeSet<ResourceLink>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Button {
-    pub owner: ResourceLink,
-    pub metadata: ButtonMetadata,
-    pub button: ButtonData,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ButtonMetadata {
-    pub control_id: u32,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ButtonData {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub button_report: Option<ButtonReport>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub last_event: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub repeat_interval: Option<u32>,
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BridgeUpdate {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub event_values: Option<Value>,
+    pub time_zone: Option<TimeZone>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ButtonReport {
-    #[serde(with = "date_format::utc_ms")]
-    pub updated: DateTime<Utc>,
-    pub event: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct DollarRef {
-    #[serde(rename = "$ref", skip_serializing_if = "Option::is_none")]
-    pub dref: Option<String>,
-}
+impl BridgeUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct DevicePower {
-    pub owner: ResourceLink,
-    pub power_state: Value,
+    #[must_use]
+    pub fn with_time_zone(mut self, time_zone: Option<TimeZone>) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct DeviceSoftwareUpdate {
-    pub owner: ResourceLink,
-    pub state: Value,
-    pub problems: Vec<Value>,
+pub struct BridgeHome {
+    pub children: BTreeSet<ResourceLink>,
+    pub services: BTreeSet<ResourceLink>,
+    /// True if any geofence client is at home (`None` if there are no
+    /// geofence clients)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_at_home: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BehaviorScript {
-    pub configuration_schema: DollarRef,
-    pub description: String,
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BridgeHomeUpdate {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_number_instances: Option<u32>,
-    pub metadata: Value,
-    pub state_schema: DollarRef,
-    pub supported_features: Vec<String>,
-    pub trigger_schema: DollarRef,
-    pub version: String,
-}
-
-fn deserialize_optional_field<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Ok(Some(Value::deserialize(deserializer)?))
+    pub is_at_home: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BehaviorInstance {
-    pub configuration: Value,
+pub struct GeofenceClient {
+    pub name: String,
     #[serde(default)]
-    pub dependees: Vec<Value>,
-    pub enabled: bool,
-    pub last_error: Option<String>,
-    pub metadata: BehaviorInstanceMetadata,
-    pub script_id: Uuid,
-    pub status: Option<String>,
-    #[serde(
-        default,
-        deserialize_with = "deserialize_optional_field",
-        skip_serializing_if = "Option::is_none"
-    )]
-    pub state: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub migrated_from: Option<Value>,
+    pub is_at_home: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BehaviorInstanceMetadata {
-    pub name: String,
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GeofenceClientUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_at_home: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct GeofenceClient {
-    pub name: String,
+impl GeofenceClientUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(self, name: Option<String>) -> Self {
+        Self { name, ..self }
+    }
+
+    #[must_use]
+    pub fn with_is_at_home(self, is_at_home: Option<bool>) -> Self {
+        Self { is_at_home, ..self }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -190,15 +149,6 @@ pub struct PrivateGroup {}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PublicImage {}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct RelativeRotary {
-    pub owner: ResourceLink,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub relative_rotary: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub rotary_report: Option<Value>,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SmartScene {
     /* active_timeslot: { */
@@ -219,7 +169,7 @@ pub struct Taurus {
     pub owner: ResourceLink,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ZigbeeConnectivityStatus {
     Connected,
@@ -261,18 +211,38 @@ pub struct Temperature {
     pub temperature: Value,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct TimeZone {
     pub time_zone: String,
 }
 
 impl TimeZone {
     #[must_use]
-    pub fn best_guess() -> Self {
+    pub fn new(time_zone: &str) -> Self {
         Self {
-            time_zone: best_guess_timezone(),
+            time_zone: time_zone.to_string(),
         }
     }
+
+    #[must_use]
+    pub fn best_guess() -> Self {
+        Self::new(&best_guess_timezone())
+    }
+
+    /// Check that `name` looks like an IANA time zone name (e.g.
+    /// `Europe/Copenhagen` or `UTC`). The name is not looked up in the time
+    /// zone database, since it might not be installed.
+    #[must_use]
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.split('/').all(|part| {
+                !part.is_empty()
+                    && part != ".."
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c))
+            })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -296,3 +266,42 @@ pub struct MetadataUpdate {
     pub name: Option<String>,
     pub archetype: Option<DeviceArchetype>,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::api::{BridgeHome, GeofenceClient, TimeZone};
+
+    #[test]
+    fn time_zone_names() {
+        assert!(TimeZone::is_valid_name("Europe/Copenhagen"));
+        assert!(TimeZone::is_valid_name("America/Argentina/Buenos_Aires"));
+        assert!(TimeZone::is_valid_name("Etc/GMT+5"));
+        assert!(TimeZone::is_valid_name("UTC"));
+
+        assert!(!TimeZone::is_valid_name(""));
+        assert!(!TimeZone::is_valid_name("/etc/passwd"));
+        assert!(!TimeZone::is_valid_name("Europe/../etc"));
+        assert!(!TimeZone::is_valid_name("Europe/Copenhagen "));
+    }
+
+    #[test]
+    fn geofence_client_from_app() {
+        let client: GeofenceClient =
+            serde_json::from_value(json!({"type": "geofence_client", "name": "phone"})).unwrap();
+        assert_eq!(client.name, "phone");
+        assert!(!client.is_at_home);
+    }
+
+    #[test]
+    fn bridge_home_without_geofence() {
+        let bh: BridgeHome =
+            serde_json::from_value(json!({"children": [], "services": []})).unwrap();
+        assert_eq!(bh.is_at_home, None);
+        assert!(serde_json::to_value(&bh)
+            .unwrap()
+            .get("is_at_home")
+            .is_none());
+    }
+}
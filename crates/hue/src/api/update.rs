@@ -2,30 +2,39 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::api::{
-    DeviceUpdate, EntertainmentConfigurationUpdate, GroupedLightUpdate, LightUpdate, RType,
-    RoomUpdate, SceneUpdate,
+    BehaviorInstanceUpdate, BridgeHomeUpdate, BridgeUpdate, ButtonUpdate, ContactUpdate,
+    DevicePowerUpdate, DeviceSoftwareUpdateUpdate, DeviceUpdate, EntertainmentConfigurationUpdate,
+    GeofenceClientUpdate, GroupedLightUpdate, LightUpdate, PowerMeasurementUpdate, RType,
+    RelativeRotaryUpdate, RoomUpdate, SceneUpdate, TamperUpdate,
 };
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Update {
     /* BehaviorScript(BehaviorScriptUpdate), */
-    /* BehaviorInstance(BehaviorInstanceUpdate), */
-    /* Bridge(BridgeUpdate), */
-    /* BridgeHome(BridgeHomeUpdate), */
+    BehaviorInstance(BehaviorInstanceUpdate),
+    Bridge(BridgeUpdate),
+    BridgeHome(BridgeHomeUpdate),
+    Button(ButtonUpdate),
+    Contact(ContactUpdate),
     Device(DeviceUpdate),
+    DevicePower(DevicePowerUpdate),
+    DeviceSoftwareUpdate(DeviceSoftwareUpdateUpdate),
     /* Entertainment(EntertainmentUpdate), */
     EntertainmentConfiguration(EntertainmentConfigurationUpdate),
-    /* GeofenceClient(GeofenceClientUpdate), */
+    GeofenceClient(GeofenceClientUpdate),
     /* Geolocation(GeolocationUpdate), */
     GroupedLight(GroupedLightUpdate),
     /* Homekit(HomekitUpdate), */
     Light(LightUpdate),
     /* Matter(MatterUpdate), */
+    PowerMeasurement(PowerMeasurementUpdate),
     /* PublicImage(PublicImageUpdate), */
+    RelativeRotary(RelativeRotaryUpdate),
     Room(RoomUpdate),
     Scene(SceneUpdate),
     /* SmartScene(SmartSceneUpdate), */
+    Tamper(TamperUpdate),
     /* ZigbeeConnectivity(ZigbeeConnectivityUpdate), */
     /* ZigbeeDeviceDiscovery(ZigbeeDeviceDiscoveryUpdate), */
     /* Zone(ZoneUpdate), */
@@ -35,10 +44,21 @@ impl Update {
     #[must_use]
     pub const fn rtype(&self) -> RType {
         match self {
+            Self::BehaviorInstance(_) => RType::BehaviorInstance,
+            Self::Bridge(_) => RType::Bridge,
+            Self::BridgeHome(_) => RType::BridgeHome,
+            Self::GeofenceClient(_) => RType::GeofenceClient,
+            Self::Button(_) => RType::Button,
             Self::GroupedLight(_) => RType::GroupedLight,
+            Self::Contact(_) => RType::Contact,
             Self::Device(_) => RType::Device,
+            Self::DevicePower(_) => RType::DevicePower,
+            Self::DeviceSoftwareUpdate(_) => RType::DeviceSoftwareUpdate,
+            Self::Tamper(_) => RType::Tamper,
+            Self::RelativeRotary(_) => RType::RelativeRotary,
             Self::EntertainmentConfiguration(_) => RType::EntertainmentConfiguration,
             Self::Light(_) => RType::Light,
+            Self::PowerMeasurement(_) => RType::PowerMeasurement,
             Self::Room(_) => RType::Room,
             Self::Scene(_) => RType::Scene,
         }
@@ -52,7 +72,18 @@ impl Update {
             }
             Self::Device(_) => Some(format!("/device/{id}")),
             Self::Light(_) => Some(format!("/lights/{id}")),
+            Self::PowerMeasurement(_) => Some(format!("/sensors/{id}")),
             Self::Scene(_) => Some(format!("/scenes/{uuid}")),
+            Self::BehaviorInstance(_)
+            | Self::Bridge(_)
+            | Self::BridgeHome(_)
+            | Self::GeofenceClient(_)
+            | Self::Button(_)
+            | Self::Contact(_)
+            | Self::DevicePower(_)
+            | Self::DeviceSoftwareUpdate(_)
+            | Self::RelativeRotary(_)
+            | Self::Tamper(_) => None,
         }
     }
 }
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+/// Mapping from perceived brightness (as used in the hue api) to the
+/// brightness level sent to a light.
+///
+/// Both sides are in the range `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", try_from = "CurveRepr")]
+pub enum BrightnessCurve {
+    /// No conversion
+    #[default]
+    Linear,
+
+    /// Power curve, `level = value ^ gamma`. The gamma must be positive
+    /// (and finite), which is checked when deserializing.
+    Gamma(f64),
+
+    /// CIE 1931 lightness (L*), which closely matches how the human eye
+    /// perceives brightness
+    Cie1931,
+}
+
+/// Unchecked [`BrightnessCurve`], as deserialized
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CurveRepr {
+    Linear,
+    Gamma(f64),
+    Cie1931,
+}
+
+impl TryFrom<CurveRepr> for BrightnessCurve {
+    type Error = String;
+
+    fn try_from(repr: CurveRepr) -> Result<Self, Self::Error> {
+        match repr {
+            CurveRepr::Linear => Ok(Self::Linear),
+            CurveRepr::Gamma(gamma) if gamma.is_finite() && gamma > 0.0 => Ok(Self::Gamma(gamma)),
+            CurveRepr::Gamma(gamma) => Err(format!("gamma must be a positive number, not {gamma}")),
+            CurveRepr::Cie1931 => Ok(Self::Cie1931),
+        }
+    }
+}
+
+impl BrightnessCurve {
+    const CIE_KAPPA: f64 = 903.3;
+    const CIE_EPSILON: f64 = 0.008_856;
+
+    /// Convert perceived brightness to light level
+    #[must_use]
+    pub fn apply(self, value: f64) -> f64 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => value,
+            Self::Gamma(gamma) => value.powf(gamma),
+            Self::Cie1931 => {
+                let lightness = value * 100.0;
+                if lightness <= 8.0 {
+                    lightness / Self::CIE_KAPPA
+                } else {
+                    ((lightness + 16.0) / 116.0).powi(3)
+                }
+            }
+        }
+    }
+
+    /// Convert light level to perceived brightness
+    #[must_use]
+    pub fn inverse(self, value: f64) -> f64 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => value,
+            Self::Gamma(gamma) => value.powf(1.0 / gamma),
+            Self::Cie1931 => {
+                let lightness = if value <= Self::CIE_EPSILON {
+                    value * Self::CIE_KAPPA
+                } else {
+                    116.0f64.mul_add(value.cbrt(), -16.0)
+                };
+                (lightness / 100.0).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::curve::BrightnessCurve;
+
+    macro_rules! compare {
+        ($expr:expr, $value:expr) => {
+            assert!(($expr - $value).abs() < 1e-3);
+        };
+    }
+
+    #[test]
+    fn curve_endpoints() {
+        for curve in [
+            BrightnessCurve::Linear,
+            BrightnessCurve::Gamma(2.2),
+            BrightnessCurve::Cie1931,
+        ] {
+            compare!(curve.apply(0.0), 0.0);
+            compare!(curve.apply(1.0), 1.0);
+            compare!(curve.inverse(0.0), 0.0);
+            compare!(curve.inverse(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn curve_values() {
+        compare!(BrightnessCurve::Linear.apply(0.5), 0.5);
+        compare!(BrightnessCurve::Gamma(2.0).apply(0.5), 0.25);
+        compare!(BrightnessCurve::Cie1931.apply(0.5), 0.1842);
+    }
+
+    #[test]
+    fn curve_roundtrip() {
+        for curve in [
+            BrightnessCurve::Linear,
+            BrightnessCurve::Gamma(2.2),
+            BrightnessCurve::Cie1931,
+        ] {
+            for value in [0.01, 0.05, 0.1, 0.5, 0.9] {
+                compare!(curve.inverse(curve.apply(value)), value);
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_curves() {
+        let parse = |value| serde_json::from_value::<BrightnessCurve>(value);
+
+        assert_eq!(parse(json!("linear")).unwrap(), BrightnessCurve::Linear);
+        assert_eq!(parse(json!("cie1931")).unwrap(), BrightnessCurve::Cie1931);
+        assert_eq!(
+            parse(json!({"gamma": 2.2})).unwrap(),
+            BrightnessCurve::Gamma(2.2)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_gamma() {
+        #[derive(Debug, Deserialize)]
+        struct Conf {
+            #[allow(dead_code)]
+            curve: BrightnessCurve,
+        }
+
+        for value in [json!({"gamma": 0.0}), json!({"gamma": -2.2})] {
+            let err = serde_json::from_value::<BrightnessCurve>(value).unwrap_err();
+            assert!(err.to_string().contains("positive"), "{err}");
+        }
+
+        // json has no infinity or nan, but toml (and yaml) do
+        for value in ["inf", "-inf", "nan"] {
+            let text = format!("curve = {{ gamma = {value} }}");
+            assert!(toml::from_str::<Conf>(&text).is_err(), "{value}");
+        }
+    }
+}
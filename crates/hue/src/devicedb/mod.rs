@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ColorGamut, DeviceArchetype, DeviceProductData, GamutType, MirekSchema};
+use crate::error::HueResult;
+
+// The device database (quirks.toml) contains discovered product data from
+// multiple sources, including data samples from the community, and various
+// open source or public domain examples.
+//
+// It is a best-effort attempt to gather a database of product data, to
+// provide more realistic API data, even when certain information is not
+// available from the backend (zigbee2mqtt).
+//
+// The bundled database can be extended (or corrected) by users, by loading
+// an additional database file on top of it.
+
+const BUNDLED: &str = include_str!("quirks.toml");
+
+static QUIRKS: RwLock<Option<Arc<QuirkDb>>> = RwLock::new(None);
+
+/// Known data and quirks for a single device model. All fields are optional,
+/// so partial entries can be used to override only certain fields.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quirk {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archetype: Option<DeviceArchetype>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardware_platform_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gamut_type: Option<GamutType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirek_min: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirek_max: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gradient_points: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effects: Option<bool>,
+}
+
+impl Quirk {
+    /// Apply fields from `other` on top of this quirk
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            manufacturer_name: other.manufacturer_name.or(self.manufacturer_name),
+            product_name: other.product_name.or(self.product_name),
+            archetype: other.archetype.or(self.archetype),
+            hardware_platform_type: other.hardware_platform_type.or(self.hardware_platform_type),
+            gamut_type: other.gamut_type.or(self.gamut_type),
+            mirek_min: other.mirek_min.or(self.mirek_min),
+            mirek_max: other.mirek_max.or(self.mirek_max),
+            gradient_points: other.gradient_points.or(self.gradient_points),
+            effects: other.effects.or(self.effects),
+        }
+    }
+
+    #[must_use]
+    pub fn gamut(&self) -> Option<ColorGamut> {
+        self.gamut_type.as_ref().and_then(ColorGamut::for_type)
+    }
+
+    #[must_use]
+    pub const fn mirek_schema(&self) -> Option<MirekSchema> {
+        match (self.mirek_min, self.mirek_max) {
+            (Some(mirek_minimum), Some(mirek_maximum)) => Some(MirekSchema {
+                mirek_minimum,
+                mirek_maximum,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Database of device quirks, by model id
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct QuirkDb {
+    models: BTreeMap<String, Quirk>,
+}
+
+impl QuirkDb {
+    /// The device database bundled with bifrost
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bundled database is invalid (which is checked by tests)
+    #[must_use]
+    pub fn bundled() -> Self {
+        Self::from_toml(BUNDLED).expect("bundled device database is valid")
+    }
+
+    pub fn from_toml(text: &str) -> HueResult<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Load the bundled database, with the entries from the given file on top
+    pub fn load(path: &Path) -> HueResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Self::bundled().with_overrides(Self::from_toml(&text)?))
+    }
+
+    /// Merge entries from `other` into this database, field by field
+    #[must_use]
+    pub fn with_overrides(mut self, other: Self) -> Self {
+        for (model_id, quirk) in other.models {
+            let old = self.models.remove(&model_id).unwrap_or_default();
+            self.models.insert(model_id, old.merge(quirk));
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, model_id: &str) -> Option<&Quirk> {
+        self.models.get(model_id)
+    }
+
+    #[must_use]
+    pub const fn models(&self) -> &BTreeMap<String, Quirk> {
+        &self.models
+    }
+}
+
+/// The active device database (the bundled one, unless replaced by
+/// [`set_quirks`])
+#[must_use]
+pub fn quirks() -> Arc<QuirkDb> {
+    if let Some(db) = &*QUIRKS.read().unwrap_or_else(PoisonError::into_inner) {
+        return db.clone();
+    }
+
+    let mut lock = QUIRKS.write().unwrap_or_else(PoisonError::into_inner);
+    lock.get_or_insert_with(|| Arc::new(QuirkDb::bundled()))
+        .clone()
+}
+
+/// Replace the active device database
+pub fn set_quirks(db: QuirkDb) {
+    *QUIRKS.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(db));
+}
+
+/// Look up the quirks for a model id in the active device database
+#[must_use]
+pub fn quirk(model_id: &str) -> Option<Quirk> {
+    quirks().get(model_id).cloned()
+}
+
+#[derive(Debug)]
+pub struct SimpleProductData {
+    pub manufacturer_name: String,
+    pub product_name: String,
+    pub product_archetype: DeviceArchetype,
+    pub hardware_platform_type: Option<String>,
+}
+
+impl From<Quirk> for SimpleProductData {
+    fn from(quirk: Quirk) -> Self {
+        Self {
+            manufacturer_name: quirk
+                .manufacturer_name
+                .unwrap_or_else(|| DeviceProductData::SIGNIFY_MANUFACTURER_NAME.to_string()),
+            product_name: quirk.product_name.unwrap_or_default(),
+            product_archetype: quirk.archetype.unwrap_or_default(),
+            hardware_platform_type: quirk.hardware_platform_type,
+        }
+    }
+}
+
+#[must_use]
+pub fn product_data(model_id: &str) -> Option<SimpleProductData> {
+    let quirk = quirk(model_id)?;
+    // entries with only hardware quirks are not enough to describe a product
+    quirk.product_name.is_some().then(|| quirk.into())
+}
+
+#[must_use]
+pub fn product_archetype(model_id: &str) -> Option<DeviceArchetype> {
+    product_data(model_id).map(|pd| pd.product_archetype)
+}
+
+#[must_use]
+pub fn hardware_platform_type(model_id: &str) -> Option<String> {
+    product_data(model_id).and_then(|pd| pd.hardware_platform_type)
+}
+
+// use shorter alias for better formatting
+#[allow(clippy::enum_glob_use)]
+use DeviceArchetype::*;
+
+/// Keywords used to guess the archetype of a light from its description.
+///
+/// Checked in order, so more specific keywords come first.
+const ARCHETYPE_KEYWORDS: &[(&str, DeviceArchetype)] = &[
+    ("gradient lightstrip", HueLightstripTv),
+    ("lightstrip", HueLightstrip),
+    ("light strip", HueLightstrip),
+    ("led strip", HueLightstrip),
+    ("signe", HueSigne),
+    ("bloom", HueBloom),
+    ("iris", HueIris),
+    ("play", HuePlay),
+    ("centris", HueCentris),
+    ("tube", HueTube),
+    ("filament", VintageBulb),
+    ("vintage", VintageBulb),
+    ("edison", EdisonBulb),
+    ("globe", LargeGlobeBulb),
+    ("candle", CandleBulb),
+    ("e14", CandleBulb),
+    ("e12", CandleBulb),
+    ("luster", LusterBulb),
+    ("gu10", SpotBulb),
+    ("mr16", SpotBulb),
+    ("spot", SpotBulb),
+    ("downlight", RecessedCeiling),
+    ("recessed", RecessedCeiling),
+    ("br30", FloodBulb),
+    ("par38", FloodBulb),
+    ("flood", FloodBulb),
+    ("pendant", PendantRound),
+    ("ceiling", CeilingRound),
+    ("floor", FloorShade),
+    ("table", TableShade),
+    ("desk", TableShade),
+    ("wall", WallShade),
+    ("bollard", Bollard),
+    ("pedestal", Bollard),
+    ("string", StringLight),
+    ("christmas", ChristmasTree),
+    ("plug", Plug),
+    ("socket", Plug),
+    ("outlet", Plug),
+    ("bulb", ClassicBulb),
+    ("e26", ClassicBulb),
+    ("e27", ClassicBulb),
+    ("a19", ClassicBulb),
+    ("a60", ClassicBulb),
+];
+
+/// Guess device archetype from a product description (e.g. "Hue white
+/// ambiance GU10 spot"), for devices not in the database
+#[must_use]
+pub fn guess_archetype(description: &str) -> Option<DeviceArchetype> {
+    let description = description.to_lowercase();
+
+    ARCHETYPE_KEYWORDS
+        .iter()
+        .find(|(keyword, _)| description.contains(keyword))
+        .map(|(_, archetype)| archetype.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{DeviceArchetype, GamutType};
+    use crate::devicedb::{guess_archetype, product_data, QuirkDb};
+
+    #[test]
+    fn bundled_database() {
+        let db = QuirkDb::bundled();
+        assert!(db.get("LCT015").is_some());
+        assert!(db.get("LCX001").unwrap().gradient_points.is_some());
+    }
+
+    #[test]
+    fn known_product() {
+        let pd = product_data("LCT003").unwrap();
+        assert_eq!(pd.product_archetype, DeviceArchetype::SpotBulb);
+        assert_eq!(pd.hardware_platform_type, None);
+
+        let pd = product_data("Z3-1BRL").unwrap();
+        assert_eq!(pd.manufacturer_name, "Lutron");
+    }
+
+    #[test]
+    fn overrides() {
+        let user = QuirkDb::from_toml(
+            r#"
+[LCT003]
+gamut_type = "C"
+
+[ACME01]
+mirek_min = 200
+mirek_max = 370
+"#,
+        )
+        .unwrap();
+
+        let db = QuirkDb::bundled().with_overrides(user);
+
+        let spot = db.get("LCT003").unwrap();
+        assert_eq!(spot.product_name.as_deref(), Some("Hue color spot"));
+        assert!(matches!(spot.gamut_type, Some(GamutType::C)));
+
+        let acme = db.get("ACME01").unwrap();
+        assert_eq!(acme.mirek_schema().unwrap().mirek_maximum, 370);
+    }
+
+    #[test]
+    fn unknown_field() {
+        assert!(QuirkDb::from_toml("LCT003 = { colour = \"red\" }").is_err());
+    }
+
+    #[test]
+    fn guess_from_description() {
+        assert_eq!(
+            guess_archetype("Hue white ambiance GU10 spot"),
+            Some(DeviceArchetype::SpotBulb)
+        );
+        assert_eq!(
+            guess_archetype("Hue Play gradient lightstrip for 55-60\" TVs"),
+            Some(DeviceArchetype::HueLightstripTv)
+        );
+        assert_eq!(
+            guess_archetype("LED bulb E27 806 lumen, dimmable"),
+            Some(DeviceArchetype::ClassicBulb)
+        );
+        assert_eq!(guess_archetype("Wireless switch"), None);
+    }
+}
@@ -14,6 +14,9 @@ pub enum HueError {
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
 
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+
     #[error(transparent)]
     TryFromIntError(#[from] std::num::TryFromIntError),
 
@@ -38,6 +41,9 @@ pub enum HueError {
     #[error("State changes not supported for: {0:?}")]
     UpdateUnsupported(RType),
 
+    #[error("Resource {0:?} does not support {1}")]
+    FeatureUnsupported(RType, &'static str),
+
     #[error("Resource {0} not found")]
     NotFound(uuid::Uuid),
 
@@ -52,6 +58,12 @@ pub enum HueError {
 
     #[error("Resource type wrong: expected {0:?} but found {1:?}")]
     WrongType(RType, RType),
+
+    #[error("parameter, {0}, not available")]
+    V1ParameterNotAvailable(String),
+
+    #[error("invalid value, {1}, for parameter, {0}")]
+    V1InvalidValue(String, serde_json::Value),
 }
 
 pub type HueResult<T> = Result<T, HueError>;
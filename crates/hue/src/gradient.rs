@@ -0,0 +1,159 @@
+//! Resample gradients to the physical segments of a light strip
+//!
+//! A gradient is defined by a (small) number of color points, but a light
+//! strip has a fixed number of segments. This module maps N points onto M
+//! segments, for each of the gradient styles supported by hue lights.
+
+use crate::xy::XY;
+use crate::zigbee::GradientStyle;
+
+/// Values that can be blended together, to interpolate between gradient
+/// points
+pub trait Blend: Copy {
+    /// Blend `self` with `other`, where `t` (0..=1) is the weight of `other`
+    #[must_use]
+    fn blend(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Blend for f64 {
+    fn blend(&self, other: &Self, t: f64) -> Self {
+        (other - self).mul_add(t, *self)
+    }
+}
+
+impl Blend for XY {
+    fn blend(&self, other: &Self, t: f64) -> Self {
+        Self::new(self.x.blend(&other.x, t), self.y.blend(&other.y, t))
+    }
+}
+
+impl<A: Blend, B: Blend> Blend for (A, B) {
+    fn blend(&self, other: &Self, t: f64) -> Self {
+        (self.0.blend(&other.0, t), self.1.blend(&other.1, t))
+    }
+}
+
+/// Value at position `pos` (0..=1) along the points, linearly interpolated
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn sample<T: Blend>(points: &[T], pos: f64) -> T {
+    let scaled = pos.clamp(0.0, 1.0) * (points.len() - 1) as f64;
+    let idx = (scaled.floor() as usize).min(points.len() - 1);
+    let next = (idx + 1).min(points.len() - 1);
+    points[idx].blend(&points[next], scaled - idx as f64)
+}
+
+/// Interpolate `points` evenly over `count` segments, from first to last
+#[allow(clippy::cast_precision_loss)]
+fn linear<T: Blend>(points: &[T], count: usize) -> Vec<T> {
+    if count == 1 {
+        return vec![points[0]];
+    }
+
+    (0..count)
+        .map(|idx| sample(points, idx as f64 / (count - 1) as f64))
+        .collect()
+}
+
+/// Value at `phase` (0..1) around a closed loop through `points`, where the
+/// last point blends back into the first. Used to rotate colors through a
+/// palette.
+#[must_use]
+pub fn cycle<T: Blend>(points: &[T], phase: f64) -> Option<T> {
+    let first = *points.first()?;
+    let looped: Vec<T> = points.iter().copied().chain([first]).collect();
+    Some(sample(&looped, phase.rem_euclid(1.0)))
+}
+
+/// Resample gradient `points` to `count` segments.
+///
+///  - [`GradientStyle::Linear`]: points are spread evenly over the strip,
+///    with smooth transitions between them.
+///  - [`GradientStyle::Mirrored`]: like linear, but from both ends of the
+///    strip towards the middle (the first point is at both ends, the last
+///    point is in the middle).
+///  - [`GradientStyle::Scattered`]: points are repeated over the strip, in
+///    order, without transitions.
+#[must_use]
+pub fn resample<T: Blend>(points: &[T], count: usize, style: GradientStyle) -> Vec<T> {
+    if points.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    match style {
+        GradientStyle::Linear => linear(points, count),
+        GradientStyle::Mirrored => {
+            let half = linear(points, count.div_ceil(2));
+            half.iter()
+                .chain(half.iter().rev().skip(count % 2))
+                .copied()
+                .collect()
+        }
+        GradientStyle::Scattered => points.iter().cycle().take(count).copied().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gradient::resample;
+    use crate::xy::XY;
+    use crate::zigbee::GradientStyle;
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len(), "{a:?} != {b:?}");
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-9, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn linear() {
+        let res = resample(&[0.0, 1.0], 5, GradientStyle::Linear);
+        assert_close(&res, &[0.0, 0.25, 0.5, 0.75, 1.0]);
+
+        let res = resample(&[0.0, 1.0, 0.0], 4, GradientStyle::Linear);
+        assert_close(&res, &[0.0, 2.0 / 3.0, 2.0 / 3.0, 0.0]);
+
+        // downsampling keeps both ends
+        let res = resample(&[0.0, 0.1, 0.2, 0.3, 0.4], 2, GradientStyle::Linear);
+        assert_close(&res, &[0.0, 0.4]);
+    }
+
+    #[test]
+    fn mirrored() {
+        let res = resample(&[0.0, 1.0], 5, GradientStyle::Mirrored);
+        assert_close(&res, &[0.0, 0.5, 1.0, 0.5, 0.0]);
+
+        let res = resample(&[0.0, 1.0], 4, GradientStyle::Mirrored);
+        assert_close(&res, &[0.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn scattered() {
+        let res = resample(&[0.0, 1.0, 2.0], 7, GradientStyle::Scattered);
+        assert_close(&res, &[0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn cycle() {
+        let points = [0.0, 1.0];
+        assert!((super::cycle(&points, 0.0).unwrap()).abs() < 1e-9);
+        assert!((super::cycle(&points, 0.25).unwrap() - 0.5).abs() < 1e-9);
+        assert!((super::cycle(&points, 0.5).unwrap() - 1.0).abs() < 1e-9);
+        assert!((super::cycle(&points, 0.75).unwrap() - 0.5).abs() < 1e-9);
+        assert!((super::cycle(&points, 1.25).unwrap() - 0.5).abs() < 1e-9);
+        assert!(super::cycle::<f64>(&[], 0.5).is_none());
+    }
+
+    #[test]
+    fn edge_cases() {
+        let xy = XY::new(0.3, 0.4);
+        assert_eq!(resample(&[xy], 3, GradientStyle::Linear), [xy, xy, xy]);
+        assert!(resample::<f64>(&[], 3, GradientStyle::Linear).is_empty());
+        assert!(resample(&[1.0], 0, GradientStyle::Mirrored).is_empty());
+        assert_close(&resample(&[0.0, 1.0], 1, GradientStyle::Mirrored), &[0.0]);
+    }
+}
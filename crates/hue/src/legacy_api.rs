@@ -1,17 +1,38 @@
 use std::{collections::HashMap, net::Ipv4Addr};
 
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, Duration, Local, Utc};
 use mac_address::MacAddress;
 use serde::{Deserialize, Serialize, Serializer};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use uuid::Uuid;
 
 use crate::api::{ColorGamut, DeviceProductData};
 use crate::date_format;
+use crate::error::{self, HueResult};
 use crate::hs::RawHS;
 use crate::version::SwVersion;
 use crate::{api, best_guess_timezone};
 
+/// Error codes used by the v1 api
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorType {
+    UnauthorizedUser = 1,
+    InvalidJson = 2,
+    ResourceNotAvailable = 3,
+    MethodNotAvailable = 4,
+    MissingParameters = 5,
+    ParameterNotAvailable = 6,
+    InvalidValue = 7,
+    ParameterNotModifiable = 8,
+    TooManyItems = 11,
+    PortalRequired = 12,
+    /// Not sent by hue bridges, which have no rate limit for the v1 api
+    TooManyRequests = 429,
+    LinkButtonNotPressed = 101,
+    DeviceOff = 201,
+    InternalError = 901,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HueError {
     #[serde(rename = "type")]
@@ -20,6 +41,17 @@ pub struct HueError {
     description: String,
 }
 
+impl HueError {
+    #[must_use]
+    pub fn new(typ: ApiErrorType, address: &str, description: String) -> Self {
+        Self {
+            typ: typ as u32,
+            address: address.to_string(),
+            description,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum HueApiResult<T> {
@@ -137,7 +169,7 @@ impl Default for ApiInternetServices {
     fn default() -> Self {
         Self {
             internet: ConnectionState::Connected,
-            remoteaccess: ConnectionState::Connected,
+            remoteaccess: ConnectionState::Disconnected,
             swupdate: ConnectionState::Connected,
             time: ConnectionState::Connected,
         }
@@ -146,10 +178,10 @@ impl Default for ApiInternetServices {
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct PortalState {
-    communication: ConnectionState,
-    incoming: bool,
-    outgoing: bool,
-    signedon: bool,
+    pub communication: ConnectionState,
+    pub incoming: bool,
+    pub outgoing: bool,
+    pub signedon: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -167,7 +199,7 @@ impl Default for ApiBackup {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwUpdate {
     #[serde(with = "date_format::legacy_utc")]
     lastinstall: DateTime<Utc>,
@@ -183,18 +215,34 @@ impl Default for SwUpdate {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SwUpdateState {
     NoUpdates,
     Transferring,
     ReadyToInstall,
     AnyReadyToInstall,
+    Installing,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwUpdateAutoInstall {
+    pub on: bool,
+    pub updatetime: String,
+}
+
+impl Default for SwUpdateAutoInstall {
+    fn default() -> Self {
+        Self {
+            on: true,
+            updatetime: "T14:00:00".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SoftwareUpdate2 {
-    autoinstall: Value,
+    autoinstall: SwUpdateAutoInstall,
     bridge: SwUpdate,
     checkforupdate: bool,
     #[serde(with = "date_format::legacy_utc")]
@@ -203,11 +251,17 @@ pub struct SoftwareUpdate2 {
 }
 
 impl SoftwareUpdate2 {
+    /// Time spent in the `transferring` state, when emulating an update
+    pub const TRANSFER_TIME: Duration = Duration::seconds(10);
+
+    /// Time spent in the `installing` state, when emulating an update
+    pub const INSTALL_TIME: Duration = Duration::seconds(20);
+
     #[allow(clippy::new_without_default)]
     #[must_use]
     pub fn new() -> Self {
         Self {
-            autoinstall: json!({ "on": true, "updatetime": "T14:00:00" }),
+            autoinstall: SwUpdateAutoInstall::default(),
             bridge: SwUpdate {
                 lastinstall: Utc::now(),
                 state: SwUpdateState::NoUpdates,
@@ -217,6 +271,102 @@ impl SoftwareUpdate2 {
             state: SwUpdateState::NoUpdates,
         }
     }
+
+    #[must_use]
+    pub const fn state(&self) -> SwUpdateState {
+        self.state
+    }
+
+    #[must_use]
+    pub const fn checkforupdate(&self) -> bool {
+        self.checkforupdate
+    }
+
+    fn set_state(&mut self, state: SwUpdateState, now: DateTime<Utc>) {
+        self.state = state;
+        self.bridge.state = state;
+        self.lastchange = now;
+    }
+
+    /// Start an (emulated) update check, which moves through the
+    /// `transferring` and `installing` states, before settling back in
+    /// `noupdates`. Does nothing if an update is already in progress.
+    pub fn check_for_update(&mut self, now: DateTime<Utc>) {
+        if self.state == SwUpdateState::NoUpdates {
+            self.checkforupdate = true;
+            self.set_state(SwUpdateState::Transferring, now);
+        }
+    }
+
+    /// Install a pending update right away, skipping the rest of the
+    /// transfer. Does nothing if there is no update in progress.
+    pub fn install(&mut self, now: DateTime<Utc>) {
+        if self.state != SwUpdateState::NoUpdates && self.state != SwUpdateState::Installing {
+            self.checkforupdate = false;
+            self.set_state(SwUpdateState::Installing, now);
+        }
+    }
+
+    /// Move the update to its next state, if enough time has been spent in
+    /// the current one. Returns true if the state changed (call again to
+    /// catch up on more than one step).
+    pub fn advance(&mut self, now: DateTime<Utc>) -> bool {
+        let elapsed = now - self.lastchange;
+
+        match self.state {
+            SwUpdateState::Transferring
+            | SwUpdateState::ReadyToInstall
+            | SwUpdateState::AnyReadyToInstall
+                if elapsed >= Self::TRANSFER_TIME =>
+            {
+                self.checkforupdate = false;
+                self.set_state(
+                    SwUpdateState::Installing,
+                    self.lastchange + Self::TRANSFER_TIME,
+                );
+                true
+            }
+            SwUpdateState::Installing if elapsed >= Self::INSTALL_TIME => {
+                let done = self.lastchange + Self::INSTALL_TIME;
+                self.bridge.lastinstall = done;
+                self.set_state(SwUpdateState::NoUpdates, done);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SwUpdateDeviceTypes {
+    pub bridge: bool,
+    pub lights: Vec<String>,
+    pub sensors: Vec<String>,
+}
+
+/// Software update status from before `swupdate2` was introduced. Not used
+/// by the hue app anymore, but some third-party apps still expect it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SoftwareUpdate {
+    pub updatestate: u8,
+    pub checkforupdate: bool,
+    pub devicetypes: SwUpdateDeviceTypes,
+    pub url: String,
+    pub text: String,
+    pub notify: bool,
+}
+
+impl Default for SoftwareUpdate {
+    fn default() -> Self {
+        Self {
+            updatestate: 0,
+            checkforupdate: false,
+            devicetypes: SwUpdateDeviceTypes::default(),
+            url: String::new(),
+            text: String::new(),
+            notify: true,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -243,6 +393,7 @@ pub struct ApiConfig {
     pub portalstate: PortalState,
     pub proxyaddress: String,
     pub proxyport: u16,
+    pub swupdate: SoftwareUpdate,
     pub swupdate2: SoftwareUpdate2,
     pub zigbeechannel: u8,
     pub ipaddress: Ipv4Addr,
@@ -263,12 +414,15 @@ pub enum ApiEffect {
     None,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Copy, Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum ApiAlert {
     #[default]
     None,
+    /// Single "breathe" cycle
     Select,
+    /// Breathe cycles for 15 seconds
+    LSelect,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -352,6 +506,55 @@ pub enum ApiGroupClass {
     Free,
 }
 
+impl From<api::RoomArchetype> for ApiGroupClass {
+    fn from(value: api::RoomArchetype) -> Self {
+        use api::RoomArchetype as RA;
+
+        match value {
+            RA::LivingRoom => Self::LivingRoom,
+            RA::Kitchen => Self::Kitchen,
+            RA::Dining => Self::Dining,
+            RA::Bedroom => Self::Bedroom,
+            RA::KidsBedroom => Self::KidsBedroom,
+            RA::Bathroom => Self::Bathroom,
+            RA::Nursery => Self::Nursery,
+            RA::Recreation => Self::Recreation,
+            RA::Office => Self::Office,
+            RA::Gym => Self::Gym,
+            RA::Hallway => Self::Hallway,
+            RA::Toilet => Self::Toilet,
+            RA::FrontDoor => Self::FrontDoor,
+            RA::Garage => Self::Garage,
+            RA::Terrace => Self::Terrace,
+            RA::Garden => Self::Garden,
+            RA::Driveway => Self::Driveway,
+            RA::Carport => Self::Carport,
+            RA::Home => Self::Home,
+            RA::Downstairs => Self::Downstairs,
+            RA::Upstairs => Self::Upstairs,
+            RA::TopFloor => Self::TopFloor,
+            RA::Attic => Self::Attic,
+            RA::GuestRoom => Self::GuestRoom,
+            RA::Staircase => Self::Staircase,
+            RA::Lounge => Self::Lounge,
+            RA::ManCave => Self::ManCave,
+            RA::Computer => Self::Computer,
+            RA::Studio => Self::Studio,
+            RA::Music => Self::Music,
+            RA::Tv => Self::TV,
+            RA::Reading => Self::Reading,
+            RA::Closet => Self::Closet,
+            RA::Storage => Self::Storage,
+            RA::LaundryRoom => Self::LaundryRoom,
+            RA::Balcony => Self::Balcony,
+            RA::Porch => Self::Porch,
+            RA::Barbecue => Self::Barbecue,
+            RA::Pool => Self::Pool,
+            RA::Other => Self::Other,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiGroup {
     pub name: String,
@@ -408,7 +611,7 @@ impl ApiGroup {
                 alert: ApiAlert::None,
                 colormode: None,
             },
-            class: ApiGroupClass::Other,
+            class: room.metadata.archetype.into(),
             group_type: ApiGroupType::Room,
             recycle: false,
             sensors: vec![],
@@ -480,7 +683,7 @@ pub struct ApiLightState {
     reachable: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ApiLightStateUpdate {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub on: Option<bool>,
@@ -492,6 +695,19 @@ pub struct ApiLightStateUpdate {
     pub ct: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none", flatten)]
     pub hs: Option<RawHS>,
+    /// Transition time, in multiples of 100ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transitiontime: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert: Option<ApiAlert>,
+}
+
+impl ApiLightStateUpdate {
+    /// Transition time, in milliseconds
+    #[must_use]
+    pub fn duration(&self) -> Option<u32> {
+        self.transitiontime.map(|tt| u32::from(tt) * 100)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -504,6 +720,26 @@ pub struct Active {
     pub active: bool,
 }
 
+/// Writable fields of the `swupdate2` section of the bridge configuration
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ApiSwUpdate2Update {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checkforupdate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install: Option<bool>,
+}
+
+/// Writable fields of the bridge configuration
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ApiConfigUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swupdate2: Option<ApiSwUpdate2Update>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub touchlink: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiGroupUpdate2 {
     pub stream: Active,
@@ -525,6 +761,23 @@ impl From<api::SceneAction> for ApiLightStateUpdate {
             xy: action.color.map(|col| col.xy.into()),
             ct: action.color_temperature.map(|ct| ct.mirek),
             hs: None,
+            transitiontime: None,
+            alert: None,
+        }
+    }
+}
+
+impl From<ApiLightStateUpdate> for api::SceneAction {
+    fn from(upd: ApiLightStateUpdate) -> Self {
+        Self {
+            on: upd.on.map(api::On::new),
+            dimming: upd
+                .bri
+                .map(|bri| api::DimmingUpdate::new(f64::from(bri) / 2.54)),
+            color: upd.xy.map(|xy| api::ColorUpdate::new(xy.into())),
+            color_temperature: upd.ct.map(api::ColorTemperatureUpdate::new),
+            gradient: None,
+            effects: Value::Null,
         }
     }
 }
@@ -627,18 +880,80 @@ impl ApiLight {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiResourceLink {
     #[serde(rename = "type")]
     pub link_type: String,
     pub name: String,
     pub description: String,
     pub classid: u32,
-    pub owner: Uuid,
+    pub owner: String,
     pub recycle: bool,
     pub links: Vec<String>,
 }
 
+impl ApiResourceLink {
+    #[must_use]
+    pub fn new(owner: String, new: ApiResourceLinkNew) -> Self {
+        Self {
+            link_type: new.link_type,
+            name: new.name,
+            description: new.description,
+            classid: new.classid,
+            owner,
+            recycle: new.recycle,
+            links: new.links,
+        }
+    }
+
+    pub fn update(&mut self, upd: ApiResourceLinkUpdate) {
+        if let Some(name) = upd.name {
+            self.name = name;
+        }
+        if let Some(description) = upd.description {
+            self.description = description;
+        }
+        if let Some(classid) = upd.classid {
+            self.classid = classid;
+        }
+        if let Some(links) = upd.links {
+            self.links = links;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiResourceLinkNew {
+    #[serde(rename = "type", default = "ApiResourceLinkNew::default_type")]
+    pub link_type: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub classid: u32,
+    #[serde(default)]
+    pub recycle: bool,
+    pub links: Vec<String>,
+}
+
+impl ApiResourceLinkNew {
+    fn default_type() -> String {
+        "Link".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiResourceLinkUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<Vec<String>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiRule {
     pub name: String,
@@ -664,7 +979,7 @@ pub enum ApiSceneVersion {
     V2 = 2,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct ApiSceneAppData {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<String>,
@@ -672,6 +987,19 @@ pub struct ApiSceneAppData {
     pub version: Option<u8>,
 }
 
+impl ApiSceneAppData {
+    /// Appdata for a scene, from the appdata stored with the scene (as
+    /// written by the hue app), or a default that places the scene in its
+    /// room (some clients, e.g. Hue Essentials, require appdata)
+    #[must_use]
+    pub fn new(appdata: Option<&str>, room_id: u32) -> Self {
+        Self {
+            data: Some(appdata.map_or_else(|| format!("xxxxx_r{room_id}"), ToString::to_string)),
+            version: Some(1),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiScene {
     pub name: String,
@@ -715,7 +1043,7 @@ pub struct ApiSchedule {
     pub status: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiSensor {
     #[serde(rename = "type")]
     pub sensor_type: String,
@@ -766,6 +1094,165 @@ impl ApiSensor {
             capabilities: Value::Null,
         }
     }
+
+    /// Virtual sensor, as created by `POST /sensors`
+    pub fn clip(new: ApiSensorNew, now: DateTime<Utc>) -> HueResult<Self> {
+        let kind: ClipSensorType =
+            serde_json::from_value(json!(new.sensor_type)).map_err(|_| {
+                error::HueError::V1InvalidValue("type".to_string(), json!(new.sensor_type))
+            })?;
+
+        let mut sensor = Self {
+            config: json!({
+                "on": true,
+                "reachable": true,
+            }),
+            manufacturername: new.manufacturername,
+            modelid: new.modelid,
+            name: new.name,
+            state: kind.initial_state(),
+            swversion: new.swversion,
+            sensor_type: new.sensor_type,
+            swupdate: None,
+            uniqueid: Some(new.uniqueid),
+            diversityid: None,
+            productname: None,
+            recycle: new.recycle,
+            capabilities: Value::Null,
+        };
+
+        update_fields(&mut sensor.config, &new.config)?;
+        if !new.state.is_empty() {
+            sensor.update_state(&new.state, now)?;
+        }
+
+        Ok(sensor)
+    }
+
+    /// True for virtual sensors (see [`ClipSensorType`])
+    #[must_use]
+    pub fn is_clip(&self) -> bool {
+        serde_json::from_value::<ClipSensorType>(json!(self.sensor_type)).is_ok()
+    }
+
+    /// Update the state of a virtual sensor
+    pub fn update_state(&mut self, upd: &Map<String, Value>, now: DateTime<Utc>) -> HueResult<()> {
+        update_fields(&mut self.state, upd)?;
+        self.state["lastupdated"] = json!(now.format("%Y-%m-%dT%H:%M:%S").to_string());
+        Ok(())
+    }
+
+    /// Update the config of a virtual sensor
+    pub fn update_config(&mut self, upd: &Map<String, Value>) -> HueResult<()> {
+        update_fields(&mut self.config, upd)
+    }
+
+    /// Power meter, presented as a consumption sensor
+    #[must_use]
+    pub fn from_power_measurement(dev: &api::Device, power: &api::PowerMeasurement) -> Self {
+        let report = power.power.power_report;
+        let product_data = dev.product_data.clone();
+
+        Self {
+            config: json!({
+                "on": power.enabled,
+                "reachable": true,
+            }),
+            manufacturername: product_data.manufacturer_name,
+            modelid: product_data.model_id,
+            name: dev.metadata.name.clone(),
+            state: json!({
+                "power": report.map(|rep| rep.power),
+                "voltage": report.and_then(|rep| rep.voltage),
+                "current": report.and_then(|rep| rep.current.map(|amps| amps * 1000.0)),
+                "consumption": report.and_then(|rep| rep.energy.map(|kwh| kwh * 1000.0)),
+                "lastupdated": report.map_or_else(
+                    || "none".to_string(),
+                    |rep| rep.changed.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                ),
+            }),
+            swversion: product_data.software_version,
+            sensor_type: "ZLLConsumption".to_string(),
+            swupdate: None,
+            uniqueid: None,
+            diversityid: None,
+            productname: Some(product_data.product_name),
+            recycle: None,
+            capabilities: Value::Null,
+        }
+    }
+}
+
+/// Virtual sensors, which apps create through the api to keep their own
+/// state on the bridge (e.g. for switch configurations and formulas), or to
+/// share variables between rules and other systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClipSensorType {
+    #[serde(rename = "CLIPGenericFlag")]
+    GenericFlag,
+    #[serde(rename = "CLIPGenericStatus")]
+    GenericStatus,
+    #[serde(rename = "CLIPPresence")]
+    Presence,
+}
+
+impl ClipSensorType {
+    #[must_use]
+    pub fn initial_state(self) -> Value {
+        match self {
+            Self::GenericFlag => json!({"flag": false, "lastupdated": "none"}),
+            Self::GenericStatus => json!({"status": 0, "lastupdated": "none"}),
+            Self::Presence => json!({"presence": false, "lastupdated": "none"}),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiSensorNew {
+    #[serde(rename = "type")]
+    pub sensor_type: String,
+    pub name: String,
+    pub modelid: String,
+    pub swversion: String,
+    pub uniqueid: String,
+    pub manufacturername: String,
+    #[serde(default)]
+    pub state: Map<String, Value>,
+    #[serde(default)]
+    pub config: Map<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recycle: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiSensorUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Set existing fields of `target`. New fields cannot be added, and values
+/// must keep their type, so apps cannot break the layout of the sensor.
+fn update_fields(target: &mut Value, upd: &Map<String, Value>) -> HueResult<()> {
+    for (key, value) in upd {
+        let Some(old) = target.get(key).filter(|_| key != "lastupdated") else {
+            return Err(error::HueError::V1ParameterNotAvailable(key.clone()));
+        };
+
+        let same_type = match (old, value) {
+            (Value::Number(a), Value::Number(b)) => a.is_i64() == b.is_i64(),
+            (Value::Null, _) => true,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        };
+        if !same_type {
+            return Err(error::HueError::V1InvalidValue(key.clone(), value.clone()));
+        }
+    }
+
+    for (key, value) in upd {
+        target[key] = value.clone();
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -794,6 +1281,7 @@ impl Default for ApiConfig {
             portalstate: PortalState::default(),
             proxyaddress: "none".to_string(),
             proxyport: Default::default(),
+            swupdate: SoftwareUpdate::default(),
             swupdate2: SoftwareUpdate2::new(),
             zigbeechannel: 25,
             ipaddress: Ipv4Addr::UNSPECIFIED,
@@ -912,3 +1400,137 @@ impl Capabilities {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::error::HueError;
+    use crate::legacy_api::{
+        ApiConfig, ApiResourceLinkNew, ApiSensor, ApiSensorNew, SoftwareUpdate2, SwUpdateState,
+    };
+
+    #[test]
+    fn config_has_all_sections() {
+        let config = serde_json::to_value(ApiConfig::default()).unwrap();
+
+        for key in [
+            "swupdate",
+            "swupdate2",
+            "internetservices",
+            "portalstate",
+            "backup",
+            "proxyaddress",
+            "proxyport",
+            "UTC",
+            "localtime",
+            "whitelist",
+            "bridgeid",
+        ] {
+            assert!(config.get(key).is_some(), "missing {key}");
+        }
+
+        assert_eq!(config["backup"], json!({"errorcode": 0, "status": "idle"}));
+        assert_eq!(config["swupdate2"]["autoinstall"]["on"], json!(true));
+        assert_eq!(config["swupdate2"]["bridge"]["state"], json!("noupdates"));
+        assert_eq!(
+            config["portalstate"]["communication"],
+            json!("disconnected")
+        );
+    }
+
+    #[test]
+    fn swupdate2_lifecycle() {
+        let start = Utc::now();
+        let mut upd = SoftwareUpdate2::new();
+        assert!(!upd.advance(start));
+
+        upd.check_for_update(start);
+        assert_eq!(upd.state(), SwUpdateState::Transferring);
+        assert!(upd.checkforupdate());
+
+        assert!(!upd.advance(start + SoftwareUpdate2::TRANSFER_TIME / 2));
+        assert!(upd.advance(start + SoftwareUpdate2::TRANSFER_TIME));
+        assert_eq!(upd.state(), SwUpdateState::Installing);
+        assert!(!upd.checkforupdate());
+
+        let done = start + SoftwareUpdate2::TRANSFER_TIME + SoftwareUpdate2::INSTALL_TIME;
+        assert!(upd.advance(done));
+        assert_eq!(upd.state(), SwUpdateState::NoUpdates);
+        assert!(!upd.advance(done));
+
+        let json = serde_json::to_value(&upd).unwrap();
+        assert_eq!(json["bridge"]["state"], json!("noupdates"));
+    }
+
+    #[test]
+    fn swupdate2_install_skips_transfer() {
+        let now = Utc::now();
+        let mut upd = SoftwareUpdate2::new();
+
+        upd.install(now);
+        assert_eq!(upd.state(), SwUpdateState::NoUpdates);
+
+        upd.check_for_update(now);
+        upd.install(now);
+        assert_eq!(upd.state(), SwUpdateState::Installing);
+    }
+
+    fn clip_sensor(sensor_type: &str) -> ApiSensorNew {
+        serde_json::from_value(json!({
+            "type": sensor_type,
+            "name": "Switch config",
+            "modelid": "Essentials",
+            "swversion": "1.0",
+            "uniqueid": "essentials-1",
+            "manufacturername": "Essentials",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn clip_sensor_state() {
+        let now = Utc::now();
+        let mut sensor = ApiSensor::clip(clip_sensor("CLIPGenericStatus"), now).unwrap();
+        assert!(sensor.is_clip());
+        assert_eq!(sensor.state["status"], json!(0));
+        assert_eq!(sensor.state["lastupdated"], json!("none"));
+
+        let upd = json!({"status": 3});
+        sensor.update_state(upd.as_object().unwrap(), now).unwrap();
+        assert_eq!(sensor.state["status"], json!(3));
+        assert_ne!(sensor.state["lastupdated"], json!("none"));
+
+        let upd = json!({"status": true});
+        let err = sensor.update_state(upd.as_object().unwrap(), now);
+        assert!(matches!(err, Err(HueError::V1InvalidValue(key, _)) if key == "status"));
+
+        let upd = json!({"flag": true});
+        let err = sensor.update_state(upd.as_object().unwrap(), now);
+        assert!(matches!(err, Err(HueError::V1ParameterNotAvailable(key)) if key == "flag"));
+        assert_eq!(sensor.state["status"], json!(3));
+
+        let upd = json!({"on": false});
+        sensor.update_config(upd.as_object().unwrap()).unwrap();
+        assert_eq!(sensor.config["on"], json!(false));
+    }
+
+    #[test]
+    fn clip_sensor_unsupported_type() {
+        let err = ApiSensor::clip(clip_sensor("ZLLPresence"), Utc::now());
+        assert!(matches!(err, Err(HueError::V1InvalidValue(key, _)) if key == "type"));
+
+        let sensor = ApiSensor::clip(clip_sensor("CLIPPresence"), Utc::now()).unwrap();
+        assert_eq!(sensor.state["presence"], json!(false));
+    }
+
+    #[test]
+    fn resourcelink_defaults() {
+        let new: ApiResourceLinkNew =
+            serde_json::from_value(json!({"classid": 10010, "links": ["/sensors/5"]})).unwrap();
+        assert_eq!(new.link_type, "Link");
+        assert_eq!(new.name, "");
+        assert!(!new.recycle);
+    }
+}
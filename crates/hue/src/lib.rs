@@ -3,12 +3,14 @@
 pub mod api;
 pub mod clamp;
 pub mod colorspace;
+pub mod curve;
 pub mod date_format;
 pub mod devicedb;
 pub mod error;
 pub mod event;
 pub mod flags;
 pub mod gamma;
+pub mod gradient;
 pub mod hs;
 pub mod legacy_api;
 pub mod scene_icons;
@@ -3,6 +3,8 @@
 
 use uuid::{uuid, Uuid};
 
+use crate::api::{RType, ResourceLink};
+
 pub const RELAX:       Uuid = uuid!("a1f7da49-d181-4328-abea-68c9dc4b5416");
 pub const NIGHT_LIGHT: Uuid = uuid!("28bbfeff-1a0c-444e-bb4b-0b74b88e0c95");
 pub const DIMMED:      Uuid = uuid!("8c74b9ba-6e89-4083-a2a7-b10a1e566fed");
@@ -12,3 +14,47 @@ pub const COOL_BRIGHT: Uuid = uuid!("dbccef2b-096e-49df-93c2-726665e80b26");
 pub const BRIGHT:      Uuid = uuid!("732ff1d9-76a7-4630-aad0-c8acc499bb0b");
 pub const REST:        Uuid = uuid!("11a09ad5-8d65-4e90-959b-f05981a9ab1b");
 pub const CONCENTRATE: Uuid = uuid!("b90c8900-a6b7-422c-a5d3-e170187dbf8c");
+
+/// All standard scene icons, with the name of the scene they belong to
+pub const ALL: [(Uuid, &str); 9] = [
+    (RELAX,       "Relax"),
+    (NIGHT_LIGHT, "Night Light"),
+    (DIMMED,      "Dimmed"),
+    (ENERGIZE,    "Energize"),
+    (READ,        "Read"),
+    (COOL_BRIGHT, "Cool Bright"),
+    (BRIGHT,      "Bright"),
+    (REST,        "Rest"),
+    (CONCENTRATE, "Concentrate"),
+];
+
+/// Guess the scene image for a scene name, for the names of built-in
+/// scenes (and a few aliases)
+#[allow(clippy::match_same_arms)]
+#[must_use]
+pub fn guess(name: &str) -> Option<ResourceLink> {
+    let icon = match name {
+        /* Built-in names */
+        "Bright" => BRIGHT,
+        "Relax" => RELAX,
+        "Night Light" => NIGHT_LIGHT,
+        "Rest" => REST,
+        "Concentrate" => CONCENTRATE,
+        "Dimmed" => DIMMED,
+        "Energize" => ENERGIZE,
+        "Read" => READ,
+        "Cool Bright" => COOL_BRIGHT,
+
+        /* Aliases */
+        "Night" => NIGHT_LIGHT,
+        "Cool" => COOL_BRIGHT,
+        "Dim" => DIMMED,
+
+        _ => return None,
+    };
+
+    Some(ResourceLink {
+        rid: icon,
+        rtype: RType::PublicImage,
+    })
+}
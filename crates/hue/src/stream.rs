@@ -76,6 +76,38 @@ impl HueStreamPacket {
             lights,
         })
     }
+
+    /// Encode as a (version 2.0) stream packet, as sent to a bridge
+    pub fn encode(&self, seqnr: u8) -> HueResult<Vec<u8>> {
+        let mut dest = [0u8; 36];
+        self.area.as_hyphenated().encode_lower(&mut dest);
+
+        let hdr = HueStreamHeader {
+            magic: *b"HueStream",
+            version: 0x0200,
+            seqnr,
+            x0: 0,
+            color_mode: self.lights.color_mode(),
+            x1: 0,
+            dest,
+        };
+
+        let mut res = hdr.pack()?.to_vec();
+        match &self.lights {
+            HueStreamLights::Rgb(lights) => {
+                for light in lights {
+                    res.extend(light.pack()?);
+                }
+            }
+            HueStreamLights::Xy(lights) => {
+                for light in lights {
+                    res.extend(light.pack()?);
+                }
+            }
+        }
+
+        Ok(res)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -101,6 +133,33 @@ impl HueStreamLights {
 
         Ok(res)
     }
+
+    #[must_use]
+    pub const fn color_mode(&self) -> HueStreamColorMode {
+        match self {
+            Self::Rgb(_) => HueStreamColorMode::Rgb,
+            Self::Xy(_) => HueStreamColorMode::Xy,
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Rgb(lights) => lights.is_empty(),
+            Self::Xy(lights) => lights.is_empty(),
+        }
+    }
+
+    /// Returns the (channel, rgb) color of every light in the frame, converted
+    /// to 8-bit rgb regardless of the color mode used by the stream.
+    #[must_use]
+    pub fn to_rgb8(&self) -> Vec<(u8, [u8; 3])> {
+        let rgb = |(xy, b): (XY, f64)| xy.to_rgb(b);
+        match self {
+            Self::Rgb(lights) => lights.iter().map(|l| (l.channel, rgb(l.to_xy()))).collect(),
+            Self::Xy(lights) => lights.iter().map(|l| (l.channel, rgb(l.to_xy()))).collect(),
+        }
+    }
 }
 
 #[derive(PackedStruct, Clone, Debug, Copy)]
@@ -132,4 +191,52 @@ pub struct Xy16 {
     pub b: u16,
 }
 
-impl Xy16 {}
+impl Xy16 {
+    #[must_use]
+    pub fn to_xy(&self) -> (XY, f64) {
+        let x = f64::from(self.x) / f64::from(u16::MAX);
+        let y = f64::from(self.y) / f64::from(u16::MAX);
+        let b = f64::from(self.b) / f64::from(u16::MAX) * 255.0;
+        (XY::new(x, y), b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::stream::{HueStreamColorMode, HueStreamLights, HueStreamPacket, Rgb16};
+
+    #[test]
+    fn encode_roundtrip() {
+        let pkt = HueStreamPacket {
+            color_mode: HueStreamColorMode::Rgb,
+            area: Uuid::new_v4(),
+            lights: HueStreamLights::Rgb(vec![
+                Rgb16 {
+                    channel: 0,
+                    r: 0xFFFF,
+                    g: 0x8000,
+                    b: 0,
+                },
+                Rgb16 {
+                    channel: 3,
+                    r: 1,
+                    g: 2,
+                    b: 3,
+                },
+            ]),
+        };
+
+        let data = pkt.encode(7).unwrap();
+        assert_eq!(data.len(), HueStreamPacket::size_with_lights(2));
+
+        let parsed = HueStreamPacket::parse(&data).unwrap();
+        assert_eq!(parsed.area, pkt.area);
+        assert_eq!(parsed.color_mode, HueStreamColorMode::Rgb);
+        assert_eq!(
+            parsed.lights.to_rgb8(),
+            vec![(0, [0xFF, 0x7F, 0]), (3, [0, 0, 0])]
+        );
+    }
+}
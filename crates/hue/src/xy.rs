@@ -80,6 +80,61 @@ impl XY {
             .xy_to_rgb_color(self.x, self.y, brightness)
             .map(Clamp::unit_to_u8_clamped)
     }
+
+    /// Color of a black body radiator at the given color temperature
+    ///
+    /// Uses the cubic spline approximation of the planckian locus by Kim et
+    /// al., which is valid from 1667K to 25000K.
+    #[must_use]
+    pub fn from_mirek(mirek: u16) -> Self {
+        let t = 1_000_000.0 / f64::from(mirek.max(1)).clamp(40.0, 600.0);
+        let u = 1000.0 / t;
+
+        let x = if t <= 4000.0 {
+            (-0.266_123_9f64)
+                .mul_add(u, -0.234_358_9)
+                .mul_add(u, 0.877_695_6)
+                .mul_add(u, 0.179_910)
+        } else {
+            (-3.025_846_9f64)
+                .mul_add(u, 2.107_037_9)
+                .mul_add(u, 0.222_634_7)
+                .mul_add(u, 0.240_390)
+        };
+
+        let y = if t <= 2222.0 {
+            (-1.106_381_4f64)
+                .mul_add(x, -1.348_110_20)
+                .mul_add(x, 2.185_558_32)
+                .mul_add(x, -0.202_196_83)
+        } else if t <= 4000.0 {
+            (-0.954_947_6f64)
+                .mul_add(x, -1.374_185_93)
+                .mul_add(x, 2.091_370_15)
+                .mul_add(x, -0.167_488_67)
+        } else {
+            3.081_758_0f64
+                .mul_add(x, -5.873_386_70)
+                .mul_add(x, 3.751_129_97)
+                .mul_add(x, -0.370_014_83)
+        };
+
+        Self { x, y }
+    }
+
+    /// Nearest (correlated) color temperature of this color
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    #[must_use]
+    pub fn to_mirek(&self) -> u16 {
+        let n = (self.x - 0.3320) / (0.1858 - self.y);
+        let cct = 449.0f64
+            .mul_add(n, 3525.0)
+            .mul_add(n, 6823.3)
+            .mul_add(n, 5520.33)
+            .clamp(1000.0, 25000.0);
+
+        (1_000_000.0 / cct).round() as u16
+    }
 }
 
 impl XY {
@@ -157,6 +212,26 @@ mod tests {
         }};
     }
 
+    #[test]
+    fn mirek_roundtrip() {
+        for mirek in [153, 250, 366, 454, 500] {
+            let xy = XY::from_mirek(mirek);
+            let back = xy.to_mirek();
+            assert!(
+                back.abs_diff(mirek) <= mirek / 50,
+                "{mirek} -> {xy:?} -> {back}"
+            );
+        }
+    }
+
+    #[test]
+    fn mirek_white_point() {
+        // 6500K is close to (but not on) the D65 white point
+        let xy = XY::from_mirek(154);
+        assert!((xy.x - XY::D65_WHITE_POINT.x).abs() < 0.01);
+        assert!((xy.y - XY::D65_WHITE_POINT.y).abs() < 0.01);
+    }
+
     #[test]
     fn rgb_from_hsl() {
         const ONE: f64 = 1.0;
@@ -1,4 +1,6 @@
+use std::collections::BTreeMap;
 use std::io::{Cursor, Read, Write};
+use std::time::Duration;
 
 use bitflags::bitflags;
 use byteorder::{LittleEndian as LE, ReadBytesExt, WriteBytesExt};
@@ -7,6 +9,7 @@ use packed_struct::{PackedStruct, PackedStructSlice, PrimitiveEnum};
 
 use crate::error::{HueError, HueResult};
 use crate::flags::TakeFlag;
+use crate::gradient;
 use crate::xy::XY;
 
 #[derive(PrimitiveEnum_u8, Debug, Copy, Clone)]
@@ -39,6 +42,7 @@ bitflags! {
         const BRIGHTNESS      = 1 <<  1;
         const COLOR_MIREK     = 1 <<  2;
         const COLOR_XY        = 1 <<  3;
+        /// Transition time, in units of 100ms
         const FADE_SPEED      = 1 <<  4;
         const EFFECT_TYPE     = 1 <<  5;
         const GRADIENT_PARAMS = 1 <<  6;
@@ -54,7 +58,7 @@ bitflags! {
     }
 }
 
-#[derive(PackedStruct)]
+#[derive(PackedStruct, Debug)]
 #[packed_struct(endian = "lsb", bit_numbering = "msb0")]
 pub struct GradientUpdateHeader {
     /// First 4 bits of first byte: number of gradient light points
@@ -74,11 +78,17 @@ pub struct GradientUpdateHeader {
     pub resv2: u16,
 }
 
+#[derive(Debug)]
 pub struct GradientColors {
     pub header: GradientUpdateHeader,
     pub points: Vec<XY>,
 }
 
+impl GradientColors {
+    /// Largest number of points that fit in [`GradientUpdateHeader::nlights`]
+    pub const MAX_POINTS: usize = 0x0F;
+}
+
 #[derive(Debug, PackedStruct)]
 #[packed_struct(endian = "lsb")]
 pub struct GradientParams {
@@ -102,20 +112,29 @@ impl GradientParams {
     }
 }
 
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct HueZigbeeUpdate {
     pub onoff: Option<u8>,
     pub brightness: Option<u8>,
     pub color_mirek: Option<u16>,
     pub color_xy: Option<XY>,
+    /// Transition time, in units of 100ms
     pub fade_speed: Option<u16>,
     pub gradient_colors: Option<GradientColors>,
     pub gradient_params: Option<GradientParams>,
     pub effect_type: Option<EffectType>,
     pub effect_speed: Option<u8>,
+    /// Values of flags unknown to bifrost, keyed by their flag bits.
+    ///
+    /// Only filled by [`HueZigbeeUpdate::from_reader_lenient`]. Since the size
+    /// of unknown values cannot be known, all data following the known fields
+    /// is recorded under the combined bits of all unknown flags.
+    pub unknown_fields: BTreeMap<u16, Vec<u8>>,
 }
 
 impl HueZigbeeUpdate {
+    const TRANSITION_UNIT_MS: u128 = 100;
+
     #[must_use]
     pub fn new() -> Self {
         Self::default()
@@ -151,11 +170,34 @@ impl HueZigbeeUpdate {
         self
     }
 
+    /// Set the transition time for all changes in this update.
+    ///
+    /// The transition time is sent in units of 100ms, so durations are
+    /// rounded down (but never below 100ms), and capped at ~109 minutes.
+    #[must_use]
+    pub fn with_transition(self, transition: Duration) -> Self {
+        let units = transition.as_millis() / Self::TRANSITION_UNIT_MS;
+        self.with_fade_speed(u16::try_from(units).unwrap_or(u16::MAX).max(1))
+    }
+
+    /// Transition time of this update, if any
+    #[must_use]
+    pub fn transition(&self) -> Option<Duration> {
+        self.fade_speed
+            .map(|units| Duration::from_millis(u64::from(units) * 100))
+    }
+
+    /// Set gradient colors. Gradients with more points than the update format
+    /// supports are resampled to [`GradientColors::MAX_POINTS`] points.
     pub fn with_gradient_colors(
         mut self,
         style: GradientStyle,
-        points: Vec<XY>,
+        mut points: Vec<XY>,
     ) -> HueResult<Self> {
+        if points.len() > GradientColors::MAX_POINTS {
+            points = gradient::resample(&points, GradientColors::MAX_POINTS, GradientStyle::Linear);
+        }
+
         self.gradient_colors = Some(GradientColors {
             header: GradientUpdateHeader {
                 nlights: u8::try_from(points.len())?,
@@ -189,7 +231,20 @@ impl HueZigbeeUpdate {
 
 #[allow(clippy::cast_possible_truncation)]
 impl HueZigbeeUpdate {
+    /// Decode update, failing on any flags unknown to bifrost
     pub fn from_reader(rdr: &mut impl Read) -> HueResult<Self> {
+        Self::decode(rdr, false)
+    }
+
+    /// Decode update, keeping the values of unknown flags in
+    /// [`HueZigbeeUpdate::unknown_fields`] instead of failing.
+    ///
+    /// Unknown values are logged, to help reverse engineer new fields.
+    pub fn from_reader_lenient(rdr: &mut impl Read) -> HueResult<Self> {
+        Self::decode(rdr, true)
+    }
+
+    fn decode(rdr: &mut impl Read, lenient: bool) -> HueResult<Self> {
         let mut hz = Self::default();
 
         let mut flags = Flags::from_bits(rdr.read_u16::<LE>()?).unwrap();
@@ -228,7 +283,9 @@ impl HueZigbeeUpdate {
             let mut data = vec![0; 4];
             rdr.read_exact(&mut data)?;
             let header = GradientUpdateHeader::unpack_from_slice(&data)?;
-            debug_assert!(len == header.nlights * 3 + 4);
+            if len != header.nlights * 3 + 4 {
+                return Err(HueError::HueZigbeeDecodeError);
+            }
 
             let mut points = vec![];
             for _ in 0..header.nlights {
@@ -251,10 +308,26 @@ impl HueZigbeeUpdate {
         }
 
         if flags.is_empty() {
-            Ok(hz)
-        } else {
-            Err(HueError::HueZigbeeUnknownFlags(flags.bits()))
+            return Ok(hz);
+        }
+
+        if !lenient {
+            return Err(HueError::HueZigbeeUnknownFlags(flags.bits()));
         }
+
+        let mut data = vec![];
+        rdr.read_to_end(&mut data)?;
+
+        log::warn!(
+            "Unknown hue zigbee flags: flags={:#06x} data={} known={:#06x}",
+            flags.bits(),
+            hex::encode(&data),
+            hz.known_flags().bits(),
+        );
+
+        hz.unknown_fields.insert(flags.bits(), data);
+
+        Ok(hz)
     }
 }
 
@@ -266,7 +339,9 @@ impl HueZigbeeUpdate {
         Ok(cur.into_inner())
     }
 
-    pub fn serialize(&self, wtr: &mut impl Write) -> HueResult<()> {
+    /// Flags for the known fields present in this update
+    #[must_use]
+    pub fn known_flags(&self) -> Flags {
         #[allow(clippy::ref_option)]
         fn opt_to_flag<T>(flags: &mut Flags, opt: &Option<T>, flag: Flags) {
             if opt.is_some() {
@@ -284,6 +359,14 @@ impl HueZigbeeUpdate {
         opt_to_flag(&mut flags, &self.effect_speed, Flags::EFFECT_SPEED);
         opt_to_flag(&mut flags, &self.gradient_colors, Flags::GRADIENT_COLORS);
         opt_to_flag(&mut flags, &self.gradient_params, Flags::GRADIENT_PARAMS);
+        flags
+    }
+
+    pub fn serialize(&self, wtr: &mut impl Write) -> HueResult<()> {
+        let mut flags = self.known_flags();
+        for bits in self.unknown_fields.keys() {
+            flags |= Flags::from_bits_retain(*bits);
+        }
 
         wtr.write_u16::<LE>(flags.bits())?;
 
@@ -330,6 +413,202 @@ impl HueZigbeeUpdate {
             wtr.write_u8(params.offset)?;
         }
 
+        for data in self.unknown_fields.values() {
+            wtr.write_all(data)?;
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use crate::xy::XY;
+    use crate::zigbee::{GradientColors, GradientStyle, HueZigbeeUpdate};
+
+    fn roundtrip(hz: &HueZigbeeUpdate) -> HueZigbeeUpdate {
+        let data = hz.to_vec().unwrap();
+        HueZigbeeUpdate::from_reader(&mut Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn transition() {
+        let hz = HueZigbeeUpdate::new()
+            .with_brightness(0x80)
+            .with_transition(Duration::from_millis(2500));
+
+        assert_eq!(hz.to_vec().unwrap(), [0x12, 0x00, 0x80, 0x19, 0x00]);
+
+        let res = roundtrip(&hz);
+        assert_eq!(res.brightness, Some(0x80));
+        assert_eq!(res.transition(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn transition_limits() {
+        let short = HueZigbeeUpdate::new().with_transition(Duration::ZERO);
+        assert_eq!(short.fade_speed, Some(1));
+
+        let long = HueZigbeeUpdate::new().with_transition(Duration::from_secs(24 * 3600));
+        assert_eq!(long.fade_speed, Some(u16::MAX));
+
+        assert_eq!(HueZigbeeUpdate::new().transition(), None);
+    }
+
+    #[test]
+    fn unknown_flags() {
+        // brightness 0x80, plus unknown flag 0x0200 with two bytes of data
+        let data = [0x02, 0x02, 0x80, 0xab, 0xcd];
+
+        assert!(HueZigbeeUpdate::from_reader(&mut Cursor::new(data)).is_err());
+
+        let hz = HueZigbeeUpdate::from_reader_lenient(&mut Cursor::new(data)).unwrap();
+        assert_eq!(hz.brightness, Some(0x80));
+        assert_eq!(hz.unknown_fields[&0x0200], [0xab, 0xcd]);
+
+        assert_eq!(hz.to_vec().unwrap(), data);
+    }
+
+    #[test]
+    fn gradient_resampled() {
+        let points = (0..20)
+            .map(|idx| XY::new(f64::from(idx) / 40.0, 0.3))
+            .collect();
+        let hz = HueZigbeeUpdate::new()
+            .with_gradient_colors(GradientStyle::Linear, points)
+            .unwrap();
+
+        let colors = roundtrip(&hz).gradient_colors.unwrap();
+        assert_eq!(colors.header.nlights, 15);
+        assert_eq!(colors.points.len(), GradientColors::MAX_POINTS);
+    }
+
+    #[test]
+    fn gradient_length_mismatch() {
+        // gradient flag, length byte claims 1 point but header has 2
+        let data = [0x00, 0x01, 0x07, 0x20, 0x00, 0x00, 0x00, 1, 2, 3, 4, 5, 6];
+        assert!(HueZigbeeUpdate::from_reader(&mut Cursor::new(data)).is_err());
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use std::io::Cursor;
+
+    use proptest::collection::vec;
+    use proptest::option::of;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    use crate::xy::XY;
+    use crate::zigbee::{EffectType, GradientParams, GradientStyle, HueZigbeeUpdate};
+
+    const EFFECTS: [EffectType; 12] = [
+        EffectType::NoEffect,
+        EffectType::Candle,
+        EffectType::Fireplace,
+        EffectType::Prism,
+        EffectType::Sunrise,
+        EffectType::Sparkle,
+        EffectType::Opal,
+        EffectType::Glisten,
+        EffectType::Underwater,
+        EffectType::Cosmos,
+        EffectType::Sunbeam,
+        EffectType::Enchant,
+    ];
+
+    const STYLES: [GradientStyle; 3] = [
+        GradientStyle::Linear,
+        GradientStyle::Scattered,
+        GradientStyle::Mirrored,
+    ];
+
+    /// Colors as they arrive over the air, so they survive quantization
+    fn wire_xy() -> impl Strategy<Value = XY> {
+        (any::<u16>(), any::<u16>())
+            .prop_map(|(x, y)| XY::new(f64::from(x) / 65535.0, f64::from(y) / 65535.0))
+    }
+
+    fn gradient_points() -> impl Strategy<Value = Vec<XY>> {
+        vec(any::<[u8; 3]>(), 0..=15).prop_map(|raw| raw.into_iter().map(XY::from_quant).collect())
+    }
+
+    prop_compose! {
+        fn update()(
+            (onoff, brightness, color_mirek, color_xy, fade_speed) in (
+                of(any::<u8>()),
+                of(any::<u8>()),
+                of(any::<u16>()),
+                of(wire_xy()),
+                of(any::<u16>()),
+            ),
+            (effect_type, effect_speed, params, gradient) in (
+                of(select(EFFECTS.to_vec())),
+                of(any::<u8>()),
+                of(any::<(u8, u8)>()),
+                of((select(STYLES.to_vec()), gradient_points())),
+            ),
+        ) -> HueZigbeeUpdate {
+            let mut hz = HueZigbeeUpdate {
+                onoff,
+                brightness,
+                color_mirek,
+                color_xy,
+                fade_speed,
+                effect_type,
+                effect_speed,
+                gradient_params: params.map(|(scale, offset)| GradientParams { scale, offset }),
+                ..HueZigbeeUpdate::default()
+            };
+            if let Some((style, points)) = gradient {
+                hz = hz.with_gradient_colors(style, points).unwrap();
+            }
+            hz
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn update_roundtrip(hz in update()) {
+            let data = hz.to_vec().unwrap();
+            let res = HueZigbeeUpdate::from_reader(&mut Cursor::new(&data)).unwrap();
+
+            prop_assert_eq!(res.onoff, hz.onoff);
+            prop_assert_eq!(res.brightness, hz.brightness);
+            prop_assert_eq!(res.color_mirek, hz.color_mirek);
+            prop_assert_eq!(res.fade_speed, hz.fade_speed);
+            prop_assert_eq!(res.effect_speed, hz.effect_speed);
+            prop_assert_eq!(res.known_flags(), hz.known_flags());
+            prop_assert_eq!(res.to_vec().unwrap(), data);
+        }
+
+        #[test]
+        fn gradient_roundtrip(raw in vec(any::<[u8; 3]>(), 0..=15)) {
+            let points = raw.iter().copied().map(XY::from_quant).collect();
+            let hz = HueZigbeeUpdate::new()
+                .with_gradient_colors(GradientStyle::Mirrored, points)
+                .unwrap();
+
+            let data = hz.to_vec().unwrap();
+            let res = HueZigbeeUpdate::from_reader(&mut Cursor::new(&data)).unwrap();
+            let colors = res.gradient_colors.unwrap();
+
+            prop_assert_eq!(usize::from(colors.header.nlights), raw.len());
+            let quant: Vec<[u8; 3]> = colors.points.iter().map(XY::to_quant).collect();
+            prop_assert_eq!(quant, raw);
+        }
+
+        #[test]
+        fn decode_arbitrary(data in vec(any::<u8>(), 0..64)) {
+            let _ = HueZigbeeUpdate::from_reader(&mut Cursor::new(&data));
+            if let Ok(hz) = HueZigbeeUpdate::from_reader_lenient(&mut Cursor::new(&data)) {
+                // whatever was decoded must encode again
+                hz.to_vec().unwrap();
+            }
+        }
+    }
+}
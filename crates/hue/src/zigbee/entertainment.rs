@@ -78,11 +78,25 @@ impl HueEntFrameLightRecord {
         }
     }
 
+    #[must_use]
+    pub const fn addr(&self) -> u16 {
+        self.addr
+    }
+
     #[must_use]
     pub const fn brightness(&self) -> u16 {
         self.brightness >> 5
     }
 
+    #[must_use]
+    pub const fn mode(&self) -> Option<LightRecordMode> {
+        match self.brightness & 0b11111 {
+            0b00000 => Some(LightRecordMode::Segment),
+            0b01011 => Some(LightRecordMode::Device),
+            _ => None,
+        }
+    }
+
     #[must_use]
     pub const fn raw(&self) -> [u8; 3] {
         self.raw
@@ -189,16 +203,19 @@ impl HueEntSegmentLayout {
 }
 
 impl HueEntFrame {
+    pub const HEADER_SIZE: usize = 6;
+    pub const RECORD_SIZE: usize = 7;
+
     pub fn parse(data: &[u8]) -> HueResult<Self> {
-        if data.len() < 6 {
+        if data.len() < Self::HEADER_SIZE {
             return Err(HueError::HueZigbeeDecodeError);
         }
 
-        let (hdr, data) = data.split_at(6);
+        let (hdr, data) = data.split_at(Self::HEADER_SIZE);
         let hdr = HueEntFrameHeader::unpack_from_slice(hdr)?;
 
         let blks = data
-            .chunks_exact(7)
+            .chunks_exact(Self::RECORD_SIZE)
             .map(HueEntFrameLightRecord::unpack_from_slice)
             .collect::<Result<_, _>>()?;
 
@@ -209,18 +226,30 @@ impl HueEntFrame {
         })
     }
 
-    pub fn pack(&self) -> HueResult<Vec<u8>> {
-        let hdr = HueEntFrameHeader {
-            counter: self.counter,
-            smoothing: self.smoothing,
-        };
-
-        let mut res = hdr.pack_to_vec()?;
-
-        for blk in &self.blks {
-            res.extend(&blk.pack()?);
+    /// Pack a frame into `buf`, replacing its contents. Once `buf` has grown
+    /// large enough, this does not allocate, so the same buffer can be used
+    /// for every frame of a stream.
+    pub fn pack_into(
+        counter: u32,
+        smoothing: u16,
+        blks: &[HueEntFrameLightRecord],
+        buf: &mut Vec<u8>,
+    ) -> HueResult<()> {
+        let hdr = HueEntFrameHeader { counter, smoothing };
+
+        buf.clear();
+        buf.reserve(Self::HEADER_SIZE + blks.len() * Self::RECORD_SIZE);
+        buf.extend_from_slice(&hdr.pack()?);
+        for blk in blks {
+            buf.extend_from_slice(&blk.pack()?);
         }
 
+        Ok(())
+    }
+
+    pub fn pack(&self) -> HueResult<Vec<u8>> {
+        let mut res = vec![];
+        Self::pack_into(self.counter, self.smoothing, &self.blks, &mut res)?;
         Ok(res)
     }
 }
@@ -229,7 +258,7 @@ impl HueEntFrame {
 mod tests {
     use packed_struct::prelude::*;
 
-    use crate::zigbee::{HueEntFrameLightRecord, LightRecordMode};
+    use crate::zigbee::{HueEntFrame, HueEntFrameLightRecord, LightRecordMode};
 
     #[test]
     fn light_record() {
@@ -267,4 +296,98 @@ mod tests {
 
         assert_eq!("2211ebffaabbcc", hex::encode(data));
     }
+
+    #[test]
+    fn light_record_getters() {
+        let foo =
+            HueEntFrameLightRecord::new(0x1122, 0x123, LightRecordMode::Device, [0xAA, 0xBB, 0xCC]);
+
+        assert_eq!(foo.addr(), 0x1122);
+        assert_eq!(foo.brightness(), 0x123);
+        assert_eq!(foo.mode(), Some(LightRecordMode::Device));
+        assert_eq!(foo.raw(), [0xAA, 0xBB, 0xCC]);
+    }
+
+    #[test]
+    fn frame_pack_into_reuses_buffer() {
+        let blks = [
+            HueEntFrameLightRecord::new(0x1122, 0x7FF, LightRecordMode::Device, [0xAA, 0xBB, 0xCC]),
+            HueEntFrameLightRecord::new(0x3344, 0x7FF, LightRecordMode::Segment, [1, 2, 3]),
+        ];
+
+        let mut buf = vec![];
+        HueEntFrame::pack_into(0x0102_0304, 0x0400, &blks, &mut buf).unwrap();
+        assert_eq!(
+            "040302010004_2211ebffaabbcc_4433e0ff010203".replace('_', ""),
+            hex::encode(&buf)
+        );
+
+        let ptr = buf.as_ptr();
+        HueEntFrame::pack_into(0x0102_0305, 0x0400, &blks, &mut buf).unwrap();
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(
+            buf.len(),
+            HueEntFrame::HEADER_SIZE + 2 * HueEntFrame::RECORD_SIZE
+        );
+
+        let frame = HueEntFrame::parse(&buf).unwrap();
+        assert_eq!(frame.counter, 0x0102_0305);
+        assert_eq!(frame.pack().unwrap(), buf);
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use crate::zigbee::{HueEntFrame, HueEntFrameLightRecord, LightRecordMode};
+
+    prop_compose! {
+        fn record()(
+            addr in any::<u16>(),
+            brightness in 0..0x800u16,
+            device in any::<bool>(),
+            raw in any::<[u8; 3]>(),
+        ) -> HueEntFrameLightRecord {
+            let mode = if device { LightRecordMode::Device } else { LightRecordMode::Segment };
+            HueEntFrameLightRecord::new(addr, brightness, mode, raw)
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn frame_roundtrip(
+            counter in any::<u32>(),
+            smoothing in any::<u16>(),
+            blks in vec(record(), 0..20),
+        ) {
+            let mut data = vec![];
+            HueEntFrame::pack_into(counter, smoothing, &blks, &mut data).unwrap();
+            prop_assert_eq!(data.len(), HueEntFrame::HEADER_SIZE + blks.len() * HueEntFrame::RECORD_SIZE);
+
+            let frame = HueEntFrame::parse(&data).unwrap();
+            prop_assert_eq!(frame.counter, counter);
+            prop_assert_eq!(frame.smoothing, smoothing);
+            prop_assert_eq!(frame.blks.len(), blks.len());
+            for (res, blk) in frame.blks.iter().zip(&blks) {
+                prop_assert_eq!(res.addr(), blk.addr());
+                prop_assert_eq!(res.brightness(), blk.brightness());
+                prop_assert_eq!(res.mode(), blk.mode());
+                prop_assert_eq!(res.raw(), blk.raw());
+            }
+            prop_assert_eq!(frame.pack().unwrap(), data);
+        }
+
+        #[test]
+        fn frame_parse_arbitrary(data in vec(any::<u8>(), 0..128)) {
+            if let Ok(frame) = HueEntFrame::parse(&data) {
+                // trailing partial records are ignored
+                let size = data.len() - (data.len() - HueEntFrame::HEADER_SIZE) % HueEntFrame::RECORD_SIZE;
+                prop_assert_eq!(frame.pack().unwrap(), &data[..size]);
+            } else {
+                prop_assert!(data.len() < HueEntFrame::HEADER_SIZE);
+            }
+        }
+    }
 }
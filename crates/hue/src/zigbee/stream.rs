@@ -6,6 +6,8 @@ use crate::zigbee::{HueEntFrame, HueEntFrameLightRecord, HueEntSegmentConfig, Hu
 pub struct EntertainmentZigbeeStream {
     smoothing: u16,
     counter: u32,
+    /// Encoded frame, reused between frames
+    buf: Vec<u8>,
 }
 
 pub const PHILIPS_HUE_ZIGBEE_VENDOR_ID: u16 = 0x100B;
@@ -73,6 +75,7 @@ impl EntertainmentZigbeeStream {
         Self {
             smoothing: Self::DEFAULT_SMOOTHING,
             counter,
+            buf: Vec::new(),
         }
     }
 
@@ -114,19 +117,19 @@ impl EntertainmentZigbeeStream {
         ))
     }
 
-    pub fn frame(&mut self, blks: Vec<HueEntFrameLightRecord>) -> HueResult<ZigbeeMessage> {
-        let ent = HueEntFrame {
-            counter: self.counter,
-            smoothing: self.smoothing,
-            blks,
-        };
+    /// Encode the next frame, into a buffer that is reused for every frame
+    /// of the stream (so this does not allocate, after the first frame)
+    pub fn encode_frame(&mut self, blks: &[HueEntFrameLightRecord]) -> HueResult<&[u8]> {
+        HueEntFrame::pack_into(self.counter, self.smoothing, blks, &mut self.buf)?;
 
         self.counter += 1;
 
-        Ok(ZigbeeMessage::new(
-            Self::CLUSTER,
-            Self::CMD_FRAME,
-            ent.pack()?,
-        ))
+        Ok(&self.buf)
+    }
+
+    pub fn frame(&mut self, blks: &[HueEntFrameLightRecord]) -> HueResult<ZigbeeMessage> {
+        let data = self.encode_frame(blks)?.to_vec();
+
+        Ok(ZigbeeMessage::new(Self::CLUSTER, Self::CMD_FRAME, data))
     }
 }
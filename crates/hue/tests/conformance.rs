@@ -0,0 +1,279 @@
+//! Conformance tests against hue bridge api responses
+//!
+//! The corpus holds resource lists (`corpus/v2`, as returned by
+//! `GET /clip/v2/resource/<type>`) and event stream messages
+//! (`corpus/events`). Every object is parsed with our types and serialized
+//! again, and every field that does not survive the round-trip is reported.
+//!
+//! The golden tests compare the shape (i.e. the set of fields) of resources
+//! created by bifrost with the shape of the same resources in the corpus.
+//!
+//! The corpus is hand-written in the bridge format, not captured from a real
+//! bridge (see `corpus/README.md`), so these tests only cover the fields it
+//! contains.
+//!
+//! Known differences are listed in `corpus/known-gaps.txt`. The tests fail
+//! on differences not listed there, and on entries that no longer apply, so
+//! the list always reflects the current state.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use hue::api::{
+    ColorTemperature, DeviceArchetype, Dimming, GroupedLight, Light, LightColor, LightMetadata,
+    MirekSchema, RType, Resource, ResourceLink, ResourceRecord, UpdateRecord,
+};
+use hue::event::{Event, EventBlock};
+use hue::xy::XY;
+
+fn corpus_dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/corpus")
+        .join(name)
+}
+
+fn corpus_files(name: &str) -> Vec<(String, Value)> {
+    let mut files: Vec<PathBuf> = fs::read_dir(corpus_dir(name))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let data = fs::read_to_string(&path).unwrap();
+            let value = serde_json::from_str(&data).unwrap_or_else(|err| panic!("{name}: {err}"));
+            (name, value)
+        })
+        .collect()
+}
+
+fn known_gaps() -> BTreeSet<String> {
+    fs::read_to_string(corpus_dir("known-gaps.txt"))
+        .unwrap()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn rtype(value: &Value) -> &str {
+    value["type"].as_str().unwrap_or("unknown")
+}
+
+fn same_number(a: &Value, b: &Value) -> bool {
+    a.is_number() && b.is_number() && a.as_f64() == b.as_f64()
+}
+
+fn same_items(a: &[Value], b: &[Value]) -> bool {
+    let mut a: Vec<String> = a.iter().map(Value::to_string).collect();
+    let mut b: Vec<String> = b.iter().map(Value::to_string).collect();
+    a.sort();
+    b.sort();
+    a == b
+}
+
+/// Record all differences between `before` and `after`, as
+/// `<kind> <path>`. Array indices are written as `*`, so the same field in
+/// different array items is only reported once.
+fn diff(path: &str, before: &Value, after: &Value, out: &mut BTreeSet<String>) {
+    match (before, after) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                let path = format!("{path}/{key}");
+                match b.get(key) {
+                    Some(other) => diff(&path, value, other, out),
+                    None => {
+                        out.insert(format!("lost {path}"));
+                    }
+                }
+            }
+            for key in b.keys().filter(|key| !a.contains_key(*key)) {
+                out.insert(format!("added {path}/{key}"));
+            }
+        }
+        // resource links are sets, so their order does not matter
+        (Value::Array(a), Value::Array(b)) if same_items(a, b) => {}
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (x, y) in a.iter().zip(b) {
+                diff(&format!("{path}/*"), x, y, out);
+            }
+        }
+        (a, b) if a == b || same_number(a, b) => {}
+        _ => {
+            out.insert(format!("changed {path}"));
+        }
+    }
+}
+
+/// Round-trip `value` through `T`, returning all differences as
+/// `<rtype> <prefix><kind> <path>`
+fn roundtrip<T>(value: &Value, prefix: &str) -> Result<BTreeSet<String>, serde_json::Error>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let parsed: T = serde_json::from_value(value.clone())?;
+    let after = serde_json::to_value(parsed)?;
+
+    let mut out = BTreeSet::new();
+    diff("", value, &after, &mut out);
+
+    Ok(out
+        .into_iter()
+        .map(|line| format!("{} {prefix}{line}", rtype(value)))
+        .collect())
+}
+
+/// Set of paths to all fields in `value`
+fn shape(path: &str, value: &Value, out: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(obj) => {
+            for (key, value) in obj {
+                let path = format!("{path}/{key}");
+                out.insert(path.clone());
+                shape(&path, value, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                shape(&format!("{path}/*"), item, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Fields present in every bridge resource of type `rtype`
+fn bridge_shape(rtype: &str) -> BTreeSet<String> {
+    corpus_files("v2")
+        .into_iter()
+        .flat_map(|(_, file)| file["data"].as_array().unwrap().clone())
+        .filter(|item| self::rtype(item) == rtype)
+        .map(|item| {
+            let mut fields = BTreeSet::new();
+            shape("", &item, &mut fields);
+            fields
+        })
+        .reduce(|a, b| a.intersection(&b).cloned().collect())
+        .unwrap_or_default()
+}
+
+fn record(id_v1: &str, obj: Resource) -> Value {
+    let rec = ResourceRecord::new(Uuid::nil(), Some(id_v1.to_string()), obj);
+    serde_json::to_value(rec).unwrap()
+}
+
+/// Fields of the bridge resource missing from the bifrost resource
+fn shape_gaps(rtype: &str, ours: &Value) -> BTreeSet<String> {
+    let mut fields = BTreeSet::new();
+    shape("", ours, &mut fields);
+
+    bridge_shape(rtype)
+        .difference(&fields)
+        .map(|path| format!("{rtype} missing {path}"))
+        .collect()
+}
+
+/// Check found differences against the known gaps with the same prefix
+fn check(kinds: &[&str], found: &BTreeSet<String>) {
+    let known: BTreeSet<String> = known_gaps()
+        .into_iter()
+        .filter(|gap| kinds.iter().any(|kind| gap.split(' ').nth(1) == Some(kind)))
+        .collect();
+
+    let new: Vec<_> = found.difference(&known).collect();
+    let fixed: Vec<_> = known.difference(found).collect();
+
+    assert!(
+        new.is_empty() && fixed.is_empty(),
+        "\nNew differences (add to known-gaps.txt, or fix):\n{new:#?}\n\
+         Differences no longer found (remove from known-gaps.txt):\n{fixed:#?}\n"
+    );
+}
+
+#[test]
+fn resources_roundtrip() {
+    let mut found = BTreeSet::new();
+
+    for (name, file) in corpus_files("v2") {
+        assert_eq!(file["errors"], json!([]), "{name}: reply has errors");
+
+        for item in file["data"].as_array().unwrap() {
+            let gaps = roundtrip::<ResourceRecord>(item, "")
+                .unwrap_or_else(|err| panic!("{name}: cannot parse {}: {err}", item["id"]));
+            found.extend(gaps);
+        }
+    }
+
+    check(&["lost", "added", "changed"], &found);
+}
+
+#[test]
+fn events_roundtrip() {
+    let mut found = BTreeSet::new();
+
+    for (name, file) in corpus_files("events") {
+        for block in file.as_array().unwrap() {
+            let evt: EventBlock = serde_json::from_value(block.clone())
+                .unwrap_or_else(|err| panic!("{name}: cannot parse event: {err}"));
+
+            let data = match evt.event {
+                Event::Add(add) => add.data,
+                Event::Update(upd) => upd.data,
+                Event::Delete(_) | Event::Error(_) => continue,
+            };
+
+            for item in &data {
+                let gaps = match block["type"].as_str() {
+                    Some("add") => roundtrip::<ResourceRecord>(item, "event-"),
+                    _ => roundtrip::<UpdateRecord>(item, "event-"),
+                };
+                found.extend(
+                    gaps.unwrap_or_else(|err| panic!("{name}: cannot parse {item}: {err}")),
+                );
+            }
+        }
+    }
+
+    check(&["event-lost", "event-added", "event-changed"], &found);
+}
+
+#[test]
+fn golden_shapes() {
+    let owner = ResourceLink::new(Uuid::nil(), RType::Device);
+    let room = ResourceLink::new(Uuid::nil(), RType::Room);
+
+    // a color light, with the same capabilities as the lights in the corpus
+    let mut light = Light::new(
+        owner,
+        LightMetadata::new(DeviceArchetype::SultanBulb, "Light"),
+    );
+    light.dimming = Some(Dimming {
+        brightness: 100.0,
+        min_dim_level: Some(0.2),
+    });
+    light.color_temperature = Some(ColorTemperature {
+        mirek: Some(366),
+        mirek_schema: MirekSchema::DEFAULT,
+        mirek_valid: true,
+    });
+    light.color = Some(LightColor::new(XY::new(0.3, 0.3)));
+
+    let grouped_light = GroupedLight::new(room);
+
+    let mut found = shape_gaps("light", &record("/lights/1", Resource::Light(light)));
+    found.extend(shape_gaps(
+        "grouped_light",
+        &record("/groups/1", Resource::GroupedLight(grouped_light)),
+    ));
+
+    check(&["missing"], &found);
+}
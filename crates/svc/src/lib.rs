@@ -1,5 +1,6 @@
 pub mod error;
 pub mod manager;
+pub mod notify;
 pub mod policy;
 pub mod rpc;
 pub mod runservice;
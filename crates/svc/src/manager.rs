@@ -10,9 +10,11 @@ use futures::future::BoxFuture;
 use tokio::select;
 use tokio::sync::{mpsc, watch};
 use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+use tokio::time::Interval;
 use uuid::Uuid;
 
 use crate::error::{RunSvcError, SvcError, SvcResult};
+use crate::notify::SystemdNotify;
 use crate::rpc::RpcRequest;
 use crate::runservice::StandardService;
 use crate::serviceid::{IntoServiceId, ServiceId};
@@ -215,6 +217,9 @@ pub struct ServiceManager {
     names: BTreeMap<String, Uuid>,
     tasks: JoinSet<Result<(), RunSvcError>>,
     shutdown: bool,
+    notify: SystemdNotify,
+    watchdog: Option<Interval>,
+    ready: bool,
 }
 
 impl Default for ServiceManager {
@@ -228,6 +233,8 @@ impl ServiceManager {
     pub fn new() -> Self {
         let (control_tx, control_rx) = mpsc::channel(32);
         let (service_tx, service_rx) = mpsc::channel(32);
+        let notify = SystemdNotify::from_env();
+        let watchdog = notify.watchdog_interval().map(tokio::time::interval);
         Self {
             control_tx,
             control_rx,
@@ -238,6 +245,9 @@ impl ServiceManager {
             names: BTreeMap::new(),
             tasks: JoinSet::new(),
             shutdown: false,
+            notify,
+            watchdog,
+            ready: false,
         }
     }
 
@@ -366,10 +376,29 @@ impl ServiceManager {
         }
     }
 
+    fn systemd_notify(&self, msg: &str) {
+        if let Err(err) = self.notify.notify(msg) {
+            log::warn!("Failed to notify systemd ({msg}): {err}");
+        }
+    }
+
+    async fn watchdog_tick(watchdog: &mut Option<Interval>) {
+        match watchdog {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
     async fn next_event(&mut self) -> SvcResult<()> {
         tokio::select! {
             event = self.control_rx.recv() => self.handle_svm_request(event.ok_or(SvcError::Shutdown)?).await,
             event = self.service_rx.recv() => self.handle_service_event(event.ok_or(SvcError::Shutdown)?).await,
+            () = Self::watchdog_tick(&mut self.watchdog) => {
+                self.systemd_notify("WATCHDOG=1");
+                Ok(())
+            }
         }
     }
 
@@ -379,6 +408,21 @@ impl ServiceManager {
         log::trace!("[{name}] [{}] Service is now {:?}", event.id, event.state);
         self.svcs.get_mut(&event.id).unwrap().state = event.state;
 
+        /* tell systemd we are ready, the first time all services are running */
+        if !self.ready
+            && !self.shutdown
+            && self
+                .svcs
+                .values()
+                .all(|svc| svc.state == ServiceState::Running)
+        {
+            self.ready = true;
+            if self.notify.is_enabled() {
+                log::debug!("All services running, notifying systemd");
+            }
+            self.systemd_notify("READY=1");
+        }
+
         Ok(())
     }
 
@@ -418,6 +462,7 @@ impl ServiceManager {
 
             SvmRequest::Shutdown(rpc) => {
                 log::info!("Service managed shutting down..");
+                self.systemd_notify("STOPPING=1");
                 let ids: Vec<Uuid> = self.list().copied().collect();
 
                 self.stop_multiple(&ids)?;
@@ -0,0 +1,89 @@
+//! Service notifications for systemd (the `sd_notify` protocol)
+//!
+//! When started by systemd with `Type=notify`, the `NOTIFY_SOCKET`
+//! environment variable names a datagram socket, which accepts status
+//! messages like `READY=1`. If the unit has `WatchdogSec=` set,
+//! `WATCHDOG_USEC` holds the watchdog timeout, and the process must send
+//! `WATCHDOG=1` more often than that.
+//!
+//! Outside of systemd, [`SystemdNotify`] does nothing.
+
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct SystemdNotify {
+    socket: Option<String>,
+    watchdog: Option<Duration>,
+}
+
+impl SystemdNotify {
+    /// Read the notification socket and watchdog timeout from the
+    /// environment (as set up by systemd)
+    #[must_use]
+    pub fn from_env() -> Self {
+        let socket = std::env::var("NOTIFY_SOCKET")
+            .ok()
+            .filter(|path| !path.is_empty());
+
+        /* the watchdog applies to us, unless it names another process */
+        let for_us =
+            std::env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|usec| *usec > 0 && for_us)
+            .map(Duration::from_micros);
+
+        Self { socket, watchdog }
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Interval for sending `WATCHDOG=1` (half the watchdog timeout, as
+    /// recommended by systemd), if the watchdog is enabled
+    #[must_use]
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.socket.as_ref()?;
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    /// Send a notification message (e.g. `READY=1`)
+    pub fn notify(&self, msg: &str) -> std::io::Result<()> {
+        let Some(path) = &self.socket else {
+            return Ok(());
+        };
+
+        log::trace!("systemd notify: {msg}");
+        send(path, msg)
+    }
+}
+
+#[cfg(unix)]
+fn send(path: &str, msg: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+
+    /* a leading '@' means a socket in the linux abstract namespace */
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+
+        let addr = SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(msg.as_bytes(), &addr)?;
+        return Ok(());
+    }
+
+    socket.send_to(msg.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_path: &str, _msg: &str) -> std::io::Result<()> {
+    Ok(())
+}
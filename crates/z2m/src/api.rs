@@ -6,6 +6,8 @@ use std::{collections::HashMap, fmt::Display};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
+use crate::convert::button_action;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct RawMessage {
@@ -44,14 +46,32 @@ pub enum Message {
     BridgeConverters(Value),
 }
 
-#[derive(Serialize, Deserialize, Clone, Hash, Debug, Copy)]
+#[derive(Serialize, Deserialize, Clone, Hash, Debug, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Availability {
     Online,
     Offline,
 }
 
-#[derive(Serialize, Deserialize, Clone, Hash)]
+/// Payload of `<device>/availability` messages. zigbee2mqtt 2.x sends an
+/// object, older versions a plain string.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum AvailabilityPayload {
+    State { state: Availability },
+    Legacy(Availability),
+}
+
+impl AvailabilityPayload {
+    #[must_use]
+    pub const fn availability(&self) -> Availability {
+        match self {
+            Self::State { state } | Self::Legacy(state) => *state,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
 #[serde(transparent)]
 pub struct IeeeAddress(#[serde(deserialize_with = "ieee_address")] u64);
 
@@ -72,7 +92,7 @@ where
     D: Deserializer<'de>,
 {
     use serde::de::Error;
-    let s: &str = Deserialize::deserialize(deserializer)?;
+    let s: String = Deserialize::deserialize(deserializer)?;
     let num = u64::from_str_radix(s.trim_start_matches("0x"), 16).map_err(Error::custom)?;
     Ok(num)
 }
@@ -104,6 +124,65 @@ pub struct BridgeLogging {
     pub topic: Option<String>,
 }
 
+/// Incoming zigbee message, as logged by zigbee2mqtt (at debug level)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ZigbeeLogMessage {
+    pub device: String,
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub cluster: String,
+    pub data: Value,
+    pub endpoint: Option<u8>,
+}
+
+/// Command that zigbee2mqtt failed to deliver to a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishError {
+    pub device: String,
+    pub error: String,
+}
+
+impl BridgeLogging {
+    /// Parse a "Publish 'set' 'state' to 'device' failed" log line, if this
+    /// is one
+    #[must_use]
+    pub fn publish_error(&self) -> Option<PublishError> {
+        let rest = self.message.strip_prefix("Publish '")?;
+        let (_, rest) = rest.split_once("' to '")?;
+        let (device, error) = rest.split_once("' failed: '")?;
+
+        Some(PublishError {
+            device: device.to_string(),
+            error: error.strip_suffix('\'').unwrap_or(error).to_string(),
+        })
+    }
+
+    /// Parse a "Received Zigbee message" log line, if this is one
+    #[must_use]
+    pub fn zigbee_message(&self) -> Option<ZigbeeLogMessage> {
+        let rest = self
+            .message
+            .strip_prefix("Received Zigbee message from '")?;
+        let (device, rest) = rest.split_once("', type '")?;
+        let (msg_type, rest) = rest.split_once("', cluster '")?;
+        let (cluster, rest) = rest.split_once("', data '")?;
+        let (data, rest) = rest.rsplit_once("' from endpoint ")?;
+
+        let endpoint = rest
+            .split(|c: char| !c.is_ascii_digit())
+            .next()
+            .and_then(|ep| ep.parse().ok());
+
+        Some(ZigbeeLogMessage {
+            device: device.to_string(),
+            msg_type: msg_type.to_string(),
+            cluster: cluster.to_string(),
+            data: serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.to_string())),
+            endpoint,
+        })
+    }
+}
+
 type BridgeGroups = Vec<Group>;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -340,6 +419,17 @@ impl Device {
         self.definition.as_ref().map_or(&[], |def| &def.exposes)
     }
 
+    /// Tags of the device, written as `#tag` words in the device
+    /// description (which can be edited in the zigbee2mqtt frontend)
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.description
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('#'))
+            .filter(|tag| !tag.is_empty())
+    }
+
     #[must_use]
     pub fn expose_light(&self) -> Option<&ExposeLight> {
         self.exposes().iter().find_map(|exp| {
@@ -351,6 +441,24 @@ impl Device {
         })
     }
 
+    /// Device exposes a single on/off switch, without any light features
+    /// (e.g. a smart plug or relay). Switches with one state per endpoint
+    /// (`state_l1`, `state_l2`, ..) are not counted.
+    #[must_use]
+    pub fn expose_on_off(&self) -> bool {
+        self.exposes().iter().any(|exp| {
+            if let Expose::Switch(switch) = exp {
+                switch
+                    .base
+                    .features
+                    .iter()
+                    .any(|feat| feat.base().property.as_deref() == Some("state"))
+            } else {
+                false
+            }
+        })
+    }
+
     #[must_use]
     pub fn expose_gradient(&self) -> Option<&ExposeList> {
         self.exposes().iter().find_map(|exp| {
@@ -371,6 +479,82 @@ impl Device {
         })
     }
 
+    /// Device exposes a binary property with the given name (e.g. "contact")
+    #[must_use]
+    pub fn expose_binary(&self, name: &str) -> bool {
+        self.exposes().iter().any(|exp| {
+            if let Expose::Binary(ExposeBinary { base, .. }) = exp {
+                base.name.as_deref() == Some(name)
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Device reports its battery level
+    #[must_use]
+    pub fn expose_battery(&self) -> bool {
+        self.exposes().iter().any(|exp| {
+            if let Expose::Numeric(ExposeNumeric { base, .. }) = exp {
+                base.name.as_deref() == Some("battery")
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Device is a rotary dial (e.g. Hue Tap Dial, Ikea Symfonisk)
+    #[must_use]
+    pub fn expose_rotary(&self) -> bool {
+        self.exposes().iter().any(|exp| {
+            let Expose::Enum(ExposeEnum { base, values }) = exp else {
+                return false;
+            };
+            base.name.as_deref() == Some("action")
+                && values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .any(|value| value.starts_with("dial_rotate_") || value.starts_with("rotate_"))
+        })
+    }
+
+    /// Names of the buttons on the device, in the order zigbee2mqtt lists
+    /// their actions (e.g. `on`, `up`, `down`, `off` for a dimmer switch)
+    #[must_use]
+    pub fn expose_buttons(&self) -> Vec<&str> {
+        let mut res: Vec<&str> = vec![];
+        for exp in self.exposes() {
+            let Expose::Enum(ExposeEnum { base, values }) = exp else {
+                continue;
+            };
+            if base.name.as_deref() != Some("action") {
+                continue;
+            }
+            for (name, _) in values
+                .iter()
+                .filter_map(Value::as_str)
+                .filter_map(button_action)
+            {
+                if !res.contains(&name) {
+                    res.push(name);
+                }
+            }
+        }
+        res
+    }
+
+    /// Device has a power meter (e.g. a smart plug with energy monitoring)
+    #[must_use]
+    pub fn expose_power(&self) -> bool {
+        self.exposes().iter().any(|exp| {
+            if let Expose::Numeric(ExposeNumeric { base, .. }) = exp {
+                matches!(base.name.as_deref(), Some("power" | "energy"))
+            } else {
+                false
+            }
+        })
+    }
+
     #[must_use]
     pub fn expose_action(&self) -> bool {
         self.exposes().iter().any(|exp| {
@@ -589,3 +773,85 @@ pub struct DeviceEndpointClusters {
     pub input: Vec<String>,
     pub output: Vec<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::api::{Availability, AvailabilityPayload, BridgeLogging, Device};
+
+    fn logging(message: &str) -> BridgeLogging {
+        BridgeLogging {
+            level: "debug".to_string(),
+            message: message.to_string(),
+            topic: None,
+        }
+    }
+
+    #[test]
+    fn zigbee_log_message() {
+        let msg = logging(
+            "Received Zigbee message from 'Hue dimmer', type 'commandHueNotification', \
+             cluster 'manuSpecificPhilips', data '{\"button\":1,\"type\":0}' from endpoint 2 \
+             with groupID 0",
+        );
+
+        let zm = msg.zigbee_message().unwrap();
+        assert_eq!(zm.device, "Hue dimmer");
+        assert_eq!(zm.msg_type, "commandHueNotification");
+        assert_eq!(zm.cluster, "manuSpecificPhilips");
+        assert_eq!(zm.data, json!({"button": 1, "type": 0}));
+        assert_eq!(zm.endpoint, Some(2));
+
+        assert!(logging("MQTT publish: topic 'zigbee2mqtt/lamp'")
+            .zigbee_message()
+            .is_none());
+    }
+
+    #[test]
+    fn publish_error() {
+        let msg = logging(
+            "Publish 'set' 'brightness' to 'Kitchen light' failed: 'Error: ZCL command \
+             0x0017880106a1b2c3/11 genLevelCtrl.moveToLevelWithOnOff failed (Delivery failed)'",
+        );
+
+        let err = msg.publish_error().unwrap();
+        assert_eq!(err.device, "Kitchen light");
+        assert_eq!(
+            err.error,
+            "Error: ZCL command 0x0017880106a1b2c3/11 genLevelCtrl.moveToLevelWithOnOff \
+             failed (Delivery failed)"
+        );
+
+        assert!(logging("Received Zigbee message from 'lamp'")
+            .publish_error()
+            .is_none());
+    }
+
+    #[test]
+    fn availability_payload() {
+        let new: AvailabilityPayload = serde_json::from_value(json!({"state": "offline"})).unwrap();
+        let old: AvailabilityPayload = serde_json::from_value(json!("online")).unwrap();
+
+        assert_eq!(new.availability(), Availability::Offline);
+        assert_eq!(old.availability(), Availability::Online);
+    }
+
+    #[test]
+    fn device_tags() {
+        let dev: Device = serde_json::from_value(json!({
+            "description": "Hallway sensor #ha-only #sensor # not#tag",
+            "disabled": false,
+            "endpoints": {},
+            "friendly_name": "hallway",
+            "ieee_address": "0x0017880106a1b2c3",
+            "interview_completed": true,
+            "interviewing": false,
+            "network_address": 1234,
+            "type": "EndDevice",
+        }))
+        .unwrap();
+
+        assert_eq!(dev.tags().collect::<Vec<_>>(), ["ha-only", "sensor"]);
+    }
+}
@@ -1,13 +1,15 @@
 use std::collections::BTreeSet;
 
 use hue::api::{
-    ColorGamut, ColorTemperature, DeviceProductData, Dimming, GamutType, LightColor, LightGradient,
-    LightGradientMode, MirekSchema,
+    ButtonEvent, ColorGamut, ColorTemperature, DeviceProductData, Dimming, GamutType, LightColor,
+    LightGradient, LightGradientMode, MirekSchema, RotaryAction, RotaryDirection, RotaryEvent,
+    Rotation, SoftwareUpdateState,
 };
-use hue::devicedb::{hardware_platform_type, product_archetype};
+use hue::devicedb::{guess_archetype, product_data};
 use hue::xy::XY;
 
 use crate::api::{Device, Expose, ExposeList, ExposeNumeric};
+use crate::update::{DeviceUpdate, OtaState};
 
 pub trait ExtractExposeNumeric {
     fn extract_mirek_schema(&self) -> Option<MirekSchema>;
@@ -136,14 +138,38 @@ impl ExtractDeviceProductData for DeviceProductData {
             name.map_or("<unknown>", |v| v).to_string()
         }
 
-        let product_name = str_or_unknown(dev.definition.as_ref().map(|def| &def.model));
         let model_id = str_or_unknown(dev.model_id.as_ref());
+        let known = product_data(&model_id);
+
+        // prefer the real product name, then the description from zigbee2mqtt
+        // (e.g. "Hue white ambiance GU10 spot"), and finally the model
+        let product_name = known.as_ref().map_or_else(
+            || {
+                str_or_unknown(dev.definition.as_ref().map(|def| {
+                    if def.description.is_empty() {
+                        &def.model
+                    } else {
+                        &def.description
+                    }
+                }))
+            },
+            |pd| pd.product_name.clone(),
+        );
         let manufacturer_name = str_or_unknown(dev.manufacturer.as_ref());
         let certified = manufacturer_name == Self::SIGNIFY_MANUFACTURER_NAME;
         let software_version = str_or_unknown(dev.software_build_id.as_ref());
 
-        let product_archetype = product_archetype(&model_id).unwrap_or_default();
-        let hardware_platform_type = hardware_platform_type(&model_id).map(ToString::to_string);
+        let product_archetype = known
+            .as_ref()
+            .map(|pd| pd.product_archetype.clone())
+            .or_else(|| {
+                // only lights have a meaningful archetype
+                dev.expose_light()?;
+                let def = dev.definition.as_ref()?;
+                guess_archetype(&format!("{} {}", def.description, def.model))
+            })
+            .unwrap_or_default();
+        let hardware_platform_type = known.and_then(|pd| pd.hardware_platform_type);
 
         Self {
             model_id,
@@ -156,3 +182,217 @@ impl ExtractDeviceProductData for DeviceProductData {
         }
     }
 }
+
+impl From<OtaState> for SoftwareUpdateState {
+    fn from(value: OtaState) -> Self {
+        match value {
+            OtaState::Idle => Self::NoUpdate,
+            OtaState::Available => Self::ReadyToInstall,
+            OtaState::Scheduled => Self::UpdatePending,
+            OtaState::Updating => Self::Installing,
+        }
+    }
+}
+
+pub trait ExtractRotaryEvent {
+    #[must_use]
+    fn extract_rotary_event(&self) -> Option<RotaryEvent>;
+}
+
+impl ExtractRotaryEvent for DeviceUpdate {
+    /// Translate rotary actions from zigbee2mqtt into hue rotary events.
+    ///
+    /// Supported actions:
+    ///  - `dial_rotate_{left,right}_{step,slow,fast}` (Hue Tap Dial)
+    ///  - `rotate_{left,right}` (Ikea Symfonisk)
+    ///  - `brightness_step_{up,down}` (when sent from a rotary device)
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn extract_rotary_event(&self) -> Option<RotaryEvent> {
+        const DEFAULT_DURATION: u32 = 400;
+
+        let action = self.action.as_deref()?;
+
+        let (clockwise, steps) = if let Some(dial) = action.strip_prefix("dial_rotate_") {
+            let (dir, speed) = dial.split_once('_')?;
+            let steps = match speed {
+                "step" => 15,
+                "slow" => 30,
+                "fast" => 60,
+                _ => return None,
+            };
+            (dir == "right", steps)
+        } else if let Some(dir) = action.strip_prefix("rotate_") {
+            match dir {
+                "left" | "right" => (dir == "right", 30),
+                _ => return None,
+            }
+        } else if let Some(dir) = action.strip_prefix("brightness_step_") {
+            (dir == "up", 30)
+        } else {
+            return None;
+        };
+
+        let steps = self
+            .action_step_size
+            .map_or(steps, |size| size.abs().round() as u32);
+
+        // hue tap dial reports the time of the rotation in 1/10 seconds,
+        // brightness steps report the transition time in seconds
+        let duration = self
+            .action_time
+            .map(|time| time * 100.0)
+            .or_else(|| self.action_transition_time.map(|time| time * 1000.0))
+            .map_or(DEFAULT_DURATION, |ms| ms.round() as u32);
+
+        let action = if self.action_type.as_deref() == Some("rotate") {
+            RotaryAction::Repeat
+        } else {
+            RotaryAction::Start
+        };
+
+        let direction = if clockwise {
+            RotaryDirection::ClockWise
+        } else {
+            RotaryDirection::CounterClockWise
+        };
+
+        Some(RotaryEvent {
+            action,
+            rotation: Rotation {
+                direction,
+                steps,
+                duration,
+            },
+        })
+    }
+}
+
+/// Split a zigbee2mqtt button action into button name and hue event.
+///
+/// Supported actions:
+///  - `{name}_press`, `{name}_press_release`, `{name}_hold`,
+///    `{name}_hold_release` (Hue dimmer switch, Hue tap dial)
+///  - `{name}_single`, `{name}_double`, `{name}_release` (Aqara, Tuya)
+///  - `single`, `double`, `hold`, `release` (single-button devices)
+#[must_use]
+pub fn button_action(action: &str) -> Option<(&str, ButtonEvent)> {
+    const SUFFIXES: [(&str, ButtonEvent); 7] = [
+        ("press_release", ButtonEvent::ShortRelease),
+        ("hold_release", ButtonEvent::LongRelease),
+        ("press", ButtonEvent::InitialPress),
+        ("hold", ButtonEvent::Repeat),
+        ("single", ButtonEvent::ShortRelease),
+        ("double", ButtonEvent::DoubleShortRelease),
+        ("release", ButtonEvent::LongRelease),
+    ];
+
+    SUFFIXES.iter().find_map(|(suffix, event)| {
+        if action == *suffix {
+            return Some(("button", *event));
+        }
+        let name = action.strip_suffix(suffix)?.strip_suffix('_')?;
+        (!name.is_empty()).then_some((name, *event))
+    })
+}
+
+pub trait ExtractButtonEvent {
+    #[must_use]
+    fn extract_button_event(&self) -> Option<(&str, ButtonEvent)>;
+}
+
+impl ExtractButtonEvent for DeviceUpdate {
+    fn extract_button_event(&self) -> Option<(&str, ButtonEvent)> {
+        button_action(self.action.as_deref()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hue::api::{ButtonEvent, RotaryAction, RotaryDirection, SoftwareUpdateState};
+    use serde_json::json;
+
+    use crate::convert::{button_action, ExtractButtonEvent, ExtractRotaryEvent};
+    use crate::update::DeviceUpdate;
+
+    fn action(name: &str) -> DeviceUpdate {
+        DeviceUpdate {
+            action: Some(name.to_string()),
+            ..DeviceUpdate::default()
+        }
+    }
+
+    #[test]
+    fn tap_dial() {
+        let upd = DeviceUpdate {
+            action_type: Some("rotate".to_string()),
+            action_time: Some(5.0),
+            ..action("dial_rotate_left_fast")
+        };
+        let event = upd.extract_rotary_event().unwrap();
+
+        assert_eq!(event.action, RotaryAction::Repeat);
+        assert_eq!(event.rotation.direction, RotaryDirection::CounterClockWise);
+        assert_eq!(event.rotation.steps, 60);
+        assert_eq!(event.rotation.duration, 500);
+    }
+
+    #[test]
+    fn step_size() {
+        let upd = DeviceUpdate {
+            action_step_size: Some(-12.0),
+            ..action("brightness_step_up")
+        };
+        let event = upd.extract_rotary_event().unwrap();
+
+        assert_eq!(event.action, RotaryAction::Start);
+        assert_eq!(event.rotation.direction, RotaryDirection::ClockWise);
+        assert_eq!(event.rotation.steps, 12);
+    }
+
+    #[test]
+    fn not_rotary() {
+        assert!(action("rotate_stop").extract_rotary_event().is_none());
+        assert!(action("on_press").extract_rotary_event().is_none());
+        assert!(DeviceUpdate::default().extract_rotary_event().is_none());
+    }
+
+    #[test]
+    fn buttons() {
+        assert_eq!(
+            action("on_press_release").extract_button_event(),
+            Some(("on", ButtonEvent::ShortRelease))
+        );
+        assert_eq!(
+            button_action("button_1_hold"),
+            Some(("button_1", ButtonEvent::Repeat))
+        );
+        assert_eq!(
+            button_action("single"),
+            Some(("button", ButtonEvent::ShortRelease))
+        );
+        assert_eq!(button_action("dial_rotate_left_step"), None);
+        assert_eq!(button_action("_press"), None);
+        assert!(DeviceUpdate::default().extract_button_event().is_none());
+    }
+
+    #[test]
+    fn ota_update() {
+        let upd: DeviceUpdate = serde_json::from_value(json!({
+            "update": {
+                "state": "available",
+                "installed_version": 0x0100_2400,
+                "latest_version": 0x0100_2600,
+            }
+        }))
+        .unwrap();
+
+        let ota = upd.ota_update().unwrap();
+        assert_eq!(ota.installed_version, Some(0x0100_2400));
+        assert_eq!(
+            SoftwareUpdateState::from(ota.state),
+            SoftwareUpdateState::ReadyToInstall
+        );
+
+        assert!(DeviceUpdate::default().ota_update().is_none());
+    }
+}
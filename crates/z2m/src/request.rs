@@ -3,6 +3,29 @@ use serde_json::Value;
 
 use crate::update::DeviceUpdate;
 
+/// Effects supported by most zigbee lights (the zigbee "identify" cluster)
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceEffect {
+    Blink,
+    Breathe,
+    Okay,
+    ChannelChange,
+    FinishEffect,
+    StopEffect,
+}
+
+/// Scene definition for a single light, stored in its zigbee scene table
+#[derive(Clone, Debug, Serialize)]
+pub struct SceneAdd<'a> {
+    #[serde(rename = "ID")]
+    pub id: u32,
+    pub group_id: u32,
+    pub name: &'a str,
+    #[serde(flatten)]
+    pub state: &'a DeviceUpdate,
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Z2mRequest<'a> {
@@ -12,10 +35,14 @@ pub enum Z2mRequest<'a> {
         id: u32,
     },
 
+    SceneAdd(SceneAdd<'a>),
+
     SceneRecall(u32),
 
     SceneRemove(u32),
 
+    Effect(DeviceEffect),
+
     #[serde(untagged)]
     Update(&'a DeviceUpdate),
 
@@ -31,3 +58,38 @@ pub enum Z2mRequest<'a> {
         value: &'a Value,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::request::{SceneAdd, Z2mRequest};
+    use crate::update::DeviceUpdate;
+
+    #[test]
+    fn serialize_scene_add() {
+        let state = DeviceUpdate::default()
+            .with_state(Some(true))
+            .with_color_temp(Some(300));
+
+        let req = Z2mRequest::SceneAdd(SceneAdd {
+            id: 3,
+            group_id: 12,
+            name: "Relax",
+            state: &state,
+        });
+
+        assert_eq!(
+            serde_json::to_value(req).unwrap(),
+            json!({
+                "scene_add": {
+                    "ID": 3,
+                    "group_id": 12,
+                    "name": "Relax",
+                    "state": "ON",
+                    "color_temp": 300,
+                }
+            })
+        );
+    }
+}
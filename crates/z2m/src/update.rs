@@ -3,11 +3,18 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use hue::api::{LightGradientUpdate, On};
+use hue::api::{
+    ColorTemperatureDeltaUpdate, DeltaAction, DimmingDeltaUpdate, LightGradientUpdate, On,
+    PowerReading,
+};
 use hue::xy::XY;
 
 use crate::hexcolor::HexColor;
 
+/// Rate of brightness moves without a given speed (in zigbee2mqtt brightness
+/// units per second)
+pub const DEFAULT_BRIGHTNESS_RATE: f64 = 64.0;
+
 #[allow(clippy::pub_underscore_fields)]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct DeviceUpdate {
@@ -45,7 +52,43 @@ pub struct DeviceUpdate {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub battery: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_low: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tamper: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_direction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_time: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_step_size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_transition_time: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub transition: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness_step: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness_move: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_temp_step: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_temp_move: Option<i32>,
 
     /* all other fields */
     #[serde(skip_serializing_if = "HashMap::is_empty")]
@@ -59,6 +102,38 @@ impl DeviceUpdate {
         Self::default()
     }
 
+    /// Power meter readings in this update, if any
+    #[must_use]
+    pub const fn power_reading(&self) -> PowerReading {
+        PowerReading {
+            power: self.power,
+            voltage: self.voltage,
+            current: self.current,
+            energy: self.energy,
+        }
+    }
+
+    /// Firmware update (OTA) status, if reported
+    #[must_use]
+    pub fn ota_update(&self) -> Option<OtaUpdate> {
+        if self.update.is_empty() {
+            return None;
+        }
+
+        let obj = self.update.clone().into_iter().collect();
+        serde_json::from_value(Value::Object(obj)).ok()
+    }
+
+    /// Battery level (in percent), if reported
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn battery_level(&self) -> Option<u8> {
+        self.battery
+            .as_ref()
+            .and_then(Value::as_f64)
+            .map(|level| level.clamp(0.0, 100.0) as u8)
+    }
+
     #[must_use]
     pub fn with_state(self, state: Option<bool>) -> Self {
         Self {
@@ -97,6 +172,72 @@ impl DeviceUpdate {
         }
     }
 
+    /// Set transition time (given in milliseconds)
+    #[must_use]
+    pub fn with_transition(self, duration: Option<u32>) -> Self {
+        Self {
+            transition: duration.map(|ms| f64::from(ms) / 1000.0),
+            ..self
+        }
+    }
+
+    /// Relative brightness change, in zigbee2mqtt units (`0..=254`)
+    #[must_use]
+    pub fn with_brightness_step(self, step: i32) -> Self {
+        Self {
+            brightness_step: Some(step),
+            brightness_move: None,
+            ..self
+        }
+    }
+
+    /// Relative brightness change (from hue `dimming_delta`)
+    ///
+    /// See [`step_or_move`] for how `duration` is used.
+    #[must_use]
+    pub fn with_dimming_delta(
+        self,
+        delta: Option<DimmingDeltaUpdate>,
+        duration: Option<u32>,
+    ) -> Self {
+        let Some(delta) = delta else {
+            return self;
+        };
+
+        // hue brightness is in percent, zigbee2mqtt brightness is 0..254
+        let amount = delta.brightness_delta.unwrap_or_default() / 100.0 * 254.0;
+        let (step, mv) = step_or_move(delta.action, amount, duration, DEFAULT_BRIGHTNESS_RATE);
+
+        Self {
+            brightness_step: step,
+            brightness_move: mv,
+            ..self
+        }
+    }
+
+    /// Relative color temperature change (from hue `color_temperature_delta`)
+    ///
+    /// See [`step_or_move`] for how `duration` is used.
+    #[must_use]
+    pub fn with_color_temperature_delta(
+        self,
+        delta: Option<ColorTemperatureDeltaUpdate>,
+        duration: Option<u32>,
+    ) -> Self {
+        let Some(delta) = delta else {
+            return self;
+        };
+
+        let amount = f64::from(delta.mirek_delta.unwrap_or_default());
+        let (step, mv) = step_or_move(delta.action, amount, duration, 50.0);
+
+        Self {
+            color_temp_step: step,
+            color_temp_move: mv,
+            ..self
+        }
+    }
+
     #[must_use]
     pub fn with_gradient(self, grad: Option<LightGradientUpdate>) -> Self {
         Self {
@@ -114,6 +255,39 @@ impl DeviceUpdate {
     }
 }
 
+/// Translate a relative change into a zigbee2mqtt "step" or "move" command,
+/// returned as `(step, move)`.
+///
+///  - [`DeltaAction::Stop`] stops any ongoing move.
+///  - With a duration, the value moves continuously, at a rate that reaches
+///    `amount` when the duration has passed (or until stopped). This is how
+///    long-press dimming works.
+///  - Without a duration, the value changes by `amount` in a single step.
+///  - Without an amount, the value moves at `default_rate` until stopped.
+#[allow(clippy::cast_possible_truncation)]
+fn step_or_move(
+    action: DeltaAction,
+    amount: f64,
+    duration: Option<u32>,
+    default_rate: f64,
+) -> (Option<i32>, Option<i32>) {
+    let Some(sign) = action.signed(1.0) else {
+        return (None, Some(0));
+    };
+
+    if amount <= 0.0 {
+        return (None, Some((sign * default_rate) as i32));
+    }
+
+    duration.filter(|ms| *ms > 0).map_or_else(
+        || (Some((sign * amount).round() as i32), None),
+        |ms| {
+            let rate = sign * amount * 1000.0 / f64::from(ms);
+            (None, Some(rate.round() as i32))
+        },
+    )
+}
+
 #[derive(Copy, Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct DeviceColor {
@@ -155,6 +329,27 @@ impl DeviceColor {
     }
 }
 
+/// State of the zigbee2mqtt OTA update process for a device
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OtaState {
+    Idle,
+    Available,
+    Scheduled,
+    Updating,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OtaUpdate {
+    pub state: OtaState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+}
+
 #[derive(Copy, Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(deny_unknown_fields)]
 pub enum PowerOnBehavior {
@@ -223,9 +223,9 @@ impl ZclAttr {
             ZclDataType::Zcl8bitmap => ZclAttrValue::B8(rdr.read_u8()?),
             ZclDataType::Zcl16bitmap => ZclAttrValue::B16(rdr.read_u16::<LE>()?),
             ZclDataType::Zcl32bitmap => ZclAttrValue::B32(rdr.read_u32::<LE>()?),
-            ZclDataType::Zcl40bitmap => todo!(),
-            ZclDataType::Zcl48bitmap => todo!(),
-            ZclDataType::Zcl56bitmap => todo!(),
+            ZclDataType::Zcl40bitmap => ZclAttrValue::B40(rdr.read_uint::<LE>(5)?),
+            ZclDataType::Zcl48bitmap => ZclAttrValue::B48(rdr.read_uint::<LE>(6)?),
+            ZclDataType::Zcl56bitmap => ZclAttrValue::B56(rdr.read_uint::<LE>(7)?),
             ZclDataType::Zcl64bitmap => ZclAttrValue::B64(rdr.read_u64::<LE>()?),
             ZclDataType::ZclU8 => ZclAttrValue::U8(rdr.read_u8()?),
             ZclDataType::ZclU16 => ZclAttrValue::U16(rdr.read_u16::<LE>()?),
@@ -244,13 +244,17 @@ impl ZclAttr {
                 rdr.read_exact(&mut buf)?;
                 ZclAttrValue::String(String::from_utf8(buf)?)
             }
-            ZclDataType::ZclIeeeaddr => todo!(),
+            ZclDataType::ZclIeeeaddr => {
+                let mut buf = vec![0; 8];
+                rdr.read_exact(&mut buf)?;
+                ZclAttrValue::IeeeAddr(buf)
+            }
             ZclDataType::ZclSecurityKey => {
                 let mut buf = [0; 16];
                 rdr.read_exact(&mut buf)?;
                 ZclAttrValue::SecurityKey(buf)
             }
-            ZclDataType::ZclInvalid => todo!(),
+            ZclDataType::ZclInvalid => return Err(ZclError::UnsupportedAttrType(zdt)),
         };
 
         Ok(Self { key, value })
@@ -330,10 +334,13 @@ pub struct ZclDefaultResp {
 
 impl ZclDefaultResp {
     pub const fn parse(data: &[u8]) -> ZclResult<Self> {
-        Ok(Self {
-            cmd: data[0],
-            stat: data[1],
-        })
+        match data {
+            [cmd, stat, ..] => Ok(Self {
+                cmd: *cmd,
+                stat: *stat,
+            }),
+            _ => Err(ZclError::PackedStructError(PackingError::BufferTooSmall)),
+        }
     }
 }
 
@@ -349,3 +356,66 @@ impl ZclWriteAttrResp {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::attr::{ZclAttrValue, ZclDefaultResp, ZclReportAttr};
+
+    #[test]
+    fn wide_bitmaps() {
+        let data = [
+            0x01, 0x00, 0x1c, 1, 2, 3, 4, 5, // b40
+            0x02, 0x00, 0x1e, 1, 2, 3, 4, 5, 6, 7, // b56
+            0x03, 0x00, 0xf0, 1, 2, 3, 4, 5, 6, 7, 8, // ieee address
+        ];
+
+        let attr = ZclReportAttr::parse(&data).unwrap().attr;
+        assert!(matches!(attr[0].value, ZclAttrValue::B40(0x05_0403_0201)));
+        assert!(matches!(
+            attr[1].value,
+            ZclAttrValue::B56(0x07_0605_0403_0201)
+        ));
+        assert!(
+            matches!(&attr[2].value, ZclAttrValue::IeeeAddr(addr) if addr == &[1, 2, 3, 4, 5, 6, 7, 8])
+        );
+    }
+
+    #[test]
+    fn invalid_type() {
+        assert!(ZclReportAttr::parse(&[0x01, 0x00, 0xff]).is_err());
+    }
+
+    #[test]
+    fn default_resp_truncated() {
+        assert!(ZclDefaultResp::parse(&[0x01]).is_err());
+        assert_eq!(ZclDefaultResp::parse(&[0x01, 0x00]).unwrap().cmd, 0x01);
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use crate::attr::{
+        ZclDefaultResp, ZclReadAttr, ZclReadAttrResp, ZclReportAttr, ZclWriteAttr, ZclWriteAttrResp,
+    };
+
+    proptest! {
+        #[test]
+        fn parse_arbitrary(data in vec(any::<u8>(), 0..64)) {
+            let _ = ZclReadAttr::parse(&data);
+            let _ = ZclReadAttrResp::parse(&data);
+            let _ = ZclWriteAttr::parse(&data);
+            let _ = ZclWriteAttrResp::parse(&data);
+            let _ = ZclReportAttr::parse(&data);
+            let _ = ZclDefaultResp::parse(&data);
+        }
+
+        #[test]
+        fn read_attr_roundtrip(attrs in vec(any::<u16>(), 0..16)) {
+            let data: Vec<u8> = attrs.iter().flat_map(|attr| attr.to_le_bytes()).collect();
+            prop_assert_eq!(ZclReadAttr::parse(&data).unwrap().attr, attrs);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use packed_struct::PackingError;
+
 use crate::error::ZclResult;
 use crate::frame::ZclFrame;
 use hue::zigbee::HueEntFrame;
@@ -10,6 +12,9 @@ pub fn describe(frame: &ZclFrame, data: &[u8]) -> ZclResult<Option<String>> {
     match frame.cmd {
         0x00 => Ok(Some("ScanRequest".to_string())),
         0x02 => {
+            if data.len() < 4 {
+                return Err(PackingError::BufferTooSmall.into());
+            }
             let (data, csum) = data.split_at(data.len() - 4);
             let csum = u32::from_be_bytes([csum[0], csum[1], csum[2], csum[3]]);
             let hes = HueEntFrame::parse(data)?;
@@ -1,4 +1,5 @@
 use hue::zigbee::Flags;
+use packed_struct::PackingError;
 
 use crate::error::ZclResult;
 use crate::frame::ZclFrame;
@@ -10,8 +11,11 @@ pub fn describe(frame: &ZclFrame, data: &[u8]) -> ZclResult<Option<String>> {
 
     match frame.cmd {
         0x00 => {
-            let zflags = Flags::from_bits(u16::from(data[0]) | (u16::from(data[1]) << 8)).unwrap();
-            Ok(Some(format!("{:?} {}", zflags, hex::encode(&data[2..]))))
+            let [lo, hi, rest @ ..] = data else {
+                return Err(PackingError::BufferTooSmall.into());
+            };
+            let zflags = Flags::from_bits_retain(u16::from_le_bytes([*lo, *hi]));
+            Ok(Some(format!("{:?} {}", zflags, hex::encode(rest))))
         }
         _ => Ok(None),
     }
@@ -8,3 +8,37 @@ pub mod levelctrl;
 pub mod onoff;
 pub mod scenes;
 pub mod standard;
+
+#[cfg(test)]
+mod proptests {
+    use std::io::Cursor;
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use crate::cluster;
+    use crate::frame::ZclFrame;
+
+    proptest! {
+        #[test]
+        fn describe_arbitrary(data in vec(any::<u8>(), 3..48)) {
+            let mut cur = Cursor::new(&data);
+            let Ok(frame) = ZclFrame::parse(&mut cur) else {
+                return Ok(());
+            };
+            #[allow(clippy::cast_possible_truncation)]
+            let data = &data[cur.position() as usize..];
+
+            let _ = cluster::colorctrl::describe(&frame, data);
+            let _ = cluster::commissioning::describe(&frame, data);
+            let _ = cluster::effects::describe(&frame, data);
+            let _ = cluster::groups::describe(&frame, data);
+            let _ = cluster::hue_fc01::describe(&frame, data);
+            let _ = cluster::hue_fc03::describe(&frame, data);
+            let _ = cluster::levelctrl::describe(&frame, data);
+            let _ = cluster::onoff::describe(&frame, data);
+            let _ = cluster::scenes::describe(&frame, data);
+            let _ = cluster::standard::describe(&frame, data);
+        }
+    }
+}
@@ -9,10 +9,12 @@ pub fn describe(frame: &ZclFrame, data: &[u8]) -> Option<String> {
     if frame.manufacturer_specific() {
         if frame.flags.direction == ZclFrameDirection::ClientToServer {
             match frame.cmd {
-                0x02 => Some(format!(
-                    "SetComposite {:?}",
-                    Flags::from_bits(u16::from(data[3]) | (u16::from(data[4]) << 8)).unwrap()
-                )),
+                0x02 => data.get(3..5).map(|flags| {
+                    format!(
+                        "SetComposite {:?}",
+                        Flags::from_bits_retain(u16::from_le_bytes([flags[0], flags[1]]))
+                    )
+                }),
                 _ => None,
             }
         } else {
@@ -17,6 +17,9 @@ pub enum ZclError {
 
     #[error(transparent)]
     PackedStructError(#[from] packed_struct::PackingError),
+
+    #[error("Invalid OTA image: {0}")]
+    OtaInvalid(String),
 }
 
 pub type ZclResult<T> = Result<T, ZclError>;
@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 use std::io::Read;
 
-use byteorder::{BigEndian as BE, ReadBytesExt};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use packed_struct::prelude::*;
 
 use crate::error::ZclResult;
@@ -63,11 +63,40 @@ pub struct ZclFrame {
 }
 
 impl ZclFrame {
+    /// Client-to-server frame, with the default response disabled
+    #[must_use]
+    pub const fn new(frame_type: ZclFrameType, mfcode: Option<u16>, seqnr: u8, cmd: u8) -> Self {
+        Self {
+            flags: ZclFrameFlags {
+                frame_type,
+                manufacturer_specific: mfcode.is_some(),
+                direction: ZclFrameDirection::ClientToServer,
+                disable_default_response: true,
+            },
+            mfcode,
+            seqnr,
+            cmd,
+        }
+    }
+
+    /// Encode the frame header, followed by `payload`
+    pub fn to_vec(&self, payload: &[u8]) -> ZclResult<Vec<u8>> {
+        let mut res = Vec::with_capacity(payload.len() + 5);
+        res.extend(self.flags.pack()?);
+        if let Some(mfcode) = self.mfcode {
+            res.write_u16::<LE>(mfcode)?;
+        }
+        res.push(self.seqnr);
+        res.push(self.cmd);
+        res.extend(payload);
+        Ok(res)
+    }
+
     pub fn parse(data: &mut impl Read) -> ZclResult<Self> {
         let flags = ZclFrameFlags::unpack(&[data.read_u8()?])?;
 
         let mfcode = if flags.manufacturer_specific {
-            Some(data.read_u16::<BE>()?)
+            Some(data.read_u16::<LE>()?)
         } else {
             None
         };
@@ -98,3 +127,89 @@ impl ZclFrame {
         self.flags.manufacturer_specific
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::frame::{ZclFrame, ZclFrameType};
+
+    #[test]
+    fn manufacturer_specific_roundtrip() {
+        let frame = ZclFrame::new(ZclFrameType::ClusterSpecific, Some(0x100b), 7, 0x00);
+        let data = frame.to_vec(&[0xaa]).unwrap();
+        assert_eq!(data, [0x15, 0x0b, 0x10, 0x07, 0x00, 0xaa]);
+
+        let mut cur = Cursor::new(&data);
+        let parsed = ZclFrame::parse(&mut cur).unwrap();
+        assert_eq!(parsed.mfcode, Some(0x100b));
+        assert_eq!(parsed.seqnr, 7);
+        assert!(parsed.cluster_specific());
+        assert!(parsed.c2s());
+        assert_eq!(cur.position(), 5);
+    }
+
+    #[test]
+    fn profile_wide() {
+        let frame = ZclFrame::new(ZclFrameType::ProfileWide, None, 1, 0x00);
+        let data = frame.to_vec(&[0x04, 0x00]).unwrap();
+        assert_eq!(data, [0x10, 0x01, 0x00, 0x04, 0x00]);
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use std::io::Cursor;
+
+    use proptest::collection::vec;
+    use proptest::option::of;
+    use proptest::prelude::*;
+
+    use crate::frame::{ZclFrame, ZclFrameDirection, ZclFrameType};
+
+    proptest! {
+        #[test]
+        fn frame_roundtrip(
+            cluster_specific in any::<bool>(),
+            s2c in any::<bool>(),
+            ddr in any::<bool>(),
+            mfcode in of(any::<u16>()),
+            seqnr in any::<u8>(),
+            cmd in any::<u8>(),
+            payload in vec(any::<u8>(), 0..16),
+        ) {
+            let frame_type = if cluster_specific {
+                ZclFrameType::ClusterSpecific
+            } else {
+                ZclFrameType::ProfileWide
+            };
+            let mut frame = ZclFrame::new(frame_type, mfcode, seqnr, cmd);
+            if s2c {
+                frame.flags.direction = ZclFrameDirection::ServerToClient;
+            }
+            frame.flags.disable_default_response = ddr;
+
+            let data = frame.to_vec(&payload).unwrap();
+            let mut cur = Cursor::new(&data);
+            let res = ZclFrame::parse(&mut cur).unwrap();
+
+            prop_assert_eq!(res.mfcode, mfcode);
+            prop_assert_eq!(res.seqnr, seqnr);
+            prop_assert_eq!(res.cmd, cmd);
+            prop_assert_eq!(res.cluster_specific(), cluster_specific);
+            prop_assert_eq!(res.c2s(), !s2c);
+            prop_assert_eq!(res.flags.disable_default_response, ddr);
+            #[allow(clippy::cast_possible_truncation)]
+            let pos = cur.position() as usize;
+            prop_assert_eq!(&data[pos..], &payload[..]);
+        }
+
+        #[test]
+        fn frame_parse_arbitrary(data in vec(any::<u8>(), 0..8)) {
+            if let Ok(frame) = ZclFrame::parse(&mut Cursor::new(&data)) {
+                let header = if frame.manufacturer_specific() { 5 } else { 3 };
+                prop_assert!(data.len() >= header);
+            }
+        }
+    }
+}
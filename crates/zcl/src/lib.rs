@@ -2,3 +2,4 @@ pub mod attr;
 pub mod cluster;
 pub mod error;
 pub mod frame;
+pub mod ota;
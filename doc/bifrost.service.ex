@@ -3,7 +3,13 @@ Description=Bifrost Bridge
 After=network.target
 
 [Service]
-Type=simple
+# Bifrost tells systemd when all its services are up (and when it is
+# shutting down), so units ordered after it wait until it is ready
+Type=notify
+
+# Bifrost pings the systemd watchdog while it is responsive. If it stops
+# doing so for this long, systemd restarts it.
+WatchdogSec=60s
 
 # Make it possible for unprivileged processes to bind to low ports (< 1024)
 # This is needed to run port 80 + 443 without being root.
@@ -2,7 +2,8 @@
 //
 // cat samples/*.json | jq '.data? | .[]? | select(.product_data?.hardware_platform_type) | .product_data' | cargo run --example=convert-product-data
 //
-// Any output from the above command will be devices currently unknown in the device database.
+// Any output from the above command will be devices currently unknown in the
+// device database, formatted as entries for crates/hue/src/devicedb/quirks.toml
 
 use std::io::stdin;
 
@@ -13,16 +14,12 @@ use hue::devicedb::{product_data, SimpleProductData};
 
 fn print_std(obj: DeviceProductData) {
     let spd = SimpleProductData {
-        manufacturer_name: &obj.manufacturer_name,
-        product_name: &obj.product_name,
+        manufacturer_name: obj.manufacturer_name,
+        product_name: obj.product_name,
         product_archetype: obj.product_archetype,
-        hardware_platform_type: obj.hardware_platform_type.as_deref(),
+        hardware_platform_type: obj.hardware_platform_type,
     };
-    println!(
-        "{:?} => {},",
-        obj.model_id,
-        format!("{spd:?}").replace("SimpleProductData ", "SPD ")
-    );
+    println!("# {:?}: {spd:?}", obj.model_id);
 }
 
 fn main() {
@@ -42,10 +39,14 @@ fn main() {
         if pd.is_none() {
             if obj.manufacturer_name == DeviceProductData::SIGNIFY_MANUFACTURER_NAME {
                 if let Some(hpt) = obj.hardware_platform_type {
-                    println!(
-                        "{:?} => SPD::signify({:?}, {:?}, {:?}),",
-                        obj.model_id, obj.product_name, obj.product_archetype, hpt,
-                    );
+                    let archetype = serde_json::to_value(&obj.product_archetype)
+                        .ok()
+                        .and_then(|val| val.as_str().map(ToString::to_string))
+                        .unwrap_or_default();
+                    println!("\n[{:?}]", obj.model_id);
+                    println!("product_name = {:?}", obj.product_name);
+                    println!("archetype = {archetype:?}");
+                    println!("hardware_platform_type = {hpt:?}");
                     continue;
                 }
             }
@@ -17,7 +17,7 @@ fn main() -> ApiResult<()> {
     let args = Cli::parse();
 
     let secret_key = p256::SecretKey::random(&mut OsRng);
-    let cert = certificate::generate(&secret_key, args.mac)?;
+    let cert = certificate::generate(&secret_key, &hue::bridge_id(args.mac))?;
 
     let mut out = stdout().lock();
 
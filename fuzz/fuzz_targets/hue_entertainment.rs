@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use packed_struct::PackedStructSlice;
+
+use hue::zigbee::{HueEntFrame, HueEntSegmentConfig, HueEntSegmentLayout, HueEntStop};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(frame) = HueEntFrame::parse(data) {
+        frame.pack().unwrap();
+    }
+    let _ = HueEntSegmentConfig::parse(data);
+    let _ = HueEntSegmentLayout::parse(data);
+    let _ = HueEntStop::unpack_from_slice(data);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+use hue::zigbee::HueZigbeeUpdate;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = HueZigbeeUpdate::from_reader(&mut Cursor::new(data));
+
+    if let Ok(hz) = HueZigbeeUpdate::from_reader_lenient(&mut Cursor::new(data)) {
+        hz.to_vec().unwrap();
+    }
+});
@@ -0,0 +1,37 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+use zcl::attr::{
+    ZclDefaultResp, ZclReadAttr, ZclReadAttrResp, ZclReportAttr, ZclWriteAttr, ZclWriteAttrResp,
+};
+use zcl::cluster;
+use zcl::frame::ZclFrame;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cur = Cursor::new(data);
+    let Ok(frame) = ZclFrame::parse(&mut cur) else {
+        return;
+    };
+    let data = &data[cur.position() as usize..];
+
+    let _ = ZclReadAttr::parse(data);
+    let _ = ZclReadAttrResp::parse(data);
+    let _ = ZclWriteAttr::parse(data);
+    let _ = ZclWriteAttrResp::parse(data);
+    let _ = ZclReportAttr::parse(data);
+    let _ = ZclDefaultResp::parse(data);
+
+    let _ = cluster::colorctrl::describe(&frame, data);
+    let _ = cluster::commissioning::describe(&frame, data);
+    let _ = cluster::effects::describe(&frame, data);
+    let _ = cluster::groups::describe(&frame, data);
+    let _ = cluster::hue_fc01::describe(&frame, data);
+    let _ = cluster::hue_fc03::describe(&frame, data);
+    let _ = cluster::levelctrl::describe(&frame, data);
+    let _ = cluster::onoff::describe(&frame, data);
+    let _ = cluster::scenes::describe(&frame, data);
+    let _ = cluster::standard::describe(&frame, data);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use zcl::ota::OtaImage;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = OtaImage::parse(data);
+});
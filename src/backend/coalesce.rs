@@ -0,0 +1,356 @@
+//! Coalescing of rapid light updates
+//!
+//! While a slider is dragged, apps send a stream of updates for the same
+//! light, often dozens per second. Sending every one of them floods the
+//! zigbee mesh, and the light ends up lagging behind. Instead, each light is
+//! sent at most one update per `min_command_interval`, and updates waiting
+//! for their turn are merged, so only the most recent state is sent.
+//!
+//! Other requests are never reordered with light updates: a request that
+//! arrives while light updates are waiting is held back until those updates
+//! have been sent (each in its own turn), and updates that arrive after it
+//! wait for the request. Entertainment frames are the exception, since they
+//! do not touch the state of lights.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::time::{sleep_until, Instant};
+
+use hue::api::{LightUpdate, ResourceLink};
+
+use crate::backend::BackendRequest;
+use crate::error::ApiResult;
+
+pub struct Coalescer {
+    interval: Duration,
+    output: Sender<Arc<BackendRequest>>,
+    /// Light updates waiting to be sent, by light, with the epoch they
+    /// arrived in
+    queues: BTreeMap<ResourceLink, VecDeque<(u64, LightUpdate)>>,
+    /// Other requests waiting for earlier light updates, with their epoch
+    held: VecDeque<(u64, Arc<BackendRequest>)>,
+    /// Incremented each time a request is held, so light updates can be
+    /// ordered against held requests
+    epoch: u64,
+    /// Time of the last update sent to each light
+    last_sent: HashMap<ResourceLink, Instant>,
+}
+
+impl Coalescer {
+    #[must_use]
+    pub fn new(interval_ms: u64, output: Sender<Arc<BackendRequest>>) -> Self {
+        Self {
+            interval: Duration::from_millis(interval_ms),
+            output,
+            queues: BTreeMap::new(),
+            held: VecDeque::new(),
+            epoch: 0,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    fn send(&self, req: Arc<BackendRequest>) {
+        /* having no backends is not an error */
+        let _ = self.output.send(req);
+    }
+
+    fn send_light(&mut self, link: ResourceLink, upd: LightUpdate, now: Instant) {
+        self.last_sent.insert(link, now);
+        self.send(Arc::new(BackendRequest::LightUpdate(link, upd)));
+    }
+
+    /// Queue an update, merging it into the last waiting one if possible.
+    /// Relative updates cannot be merged, since they depend on the state
+    /// the previous update leaves behind, and updates are not merged across
+    /// a held request.
+    fn push(&mut self, link: ResourceLink, upd: LightUpdate) {
+        let epoch = self.epoch;
+        let queue = self.queues.entry(link).or_default();
+        match queue.pop_back() {
+            Some((last_epoch, last))
+                if last_epoch == epoch && !last.is_relative() && !upd.is_relative() =>
+            {
+                log::trace!("Coalescing update for {link:?}");
+                queue.push_back((epoch, last.merge(upd)));
+            }
+            Some(last) => {
+                queue.push_back(last);
+                queue.push_back((epoch, upd));
+            }
+            None => queue.push_back((epoch, upd)),
+        }
+    }
+
+    /// Updates from later epochs than the first held request must wait for it
+    fn barrier(&self) -> u64 {
+        self.held.front().map_or(u64::MAX, |(epoch, _)| *epoch)
+    }
+
+    /// Time at which the next update for `link` may be sent
+    fn due(&self, link: &ResourceLink) -> Option<Instant> {
+        self.last_sent.get(link).map(|last| *last + self.interval)
+    }
+
+    /// Lights with a waiting update that may be sent before any held request
+    fn sendable(&self) -> impl Iterator<Item = &ResourceLink> {
+        let barrier = self.barrier();
+        self.queues
+            .iter()
+            .filter(move |(_, queue)| queue.front().is_some_and(|(epoch, _)| *epoch <= barrier))
+            .map(|(link, _)| link)
+    }
+
+    /// Time at which the next waiting update is due
+    fn next_due(&self, now: Instant) -> Option<Instant> {
+        self.sendable()
+            .map(|link| self.due(link).unwrap_or(now))
+            .min()
+    }
+
+    /// Send the next waiting update of every light that is due, and any held
+    /// requests that no longer wait for earlier updates
+    fn send_due(&mut self, now: Instant) {
+        loop {
+            let ready: Vec<ResourceLink> = self
+                .sendable()
+                .filter(|link| self.due(link).map_or(true, |due| due <= now))
+                .copied()
+                .collect();
+
+            for link in ready {
+                if let Some((_, upd)) = self.queues.get_mut(&link).and_then(VecDeque::pop_front) {
+                    self.send_light(link, upd, now);
+                }
+            }
+            self.queues.retain(|_, queue| !queue.is_empty());
+
+            if !self.release_held() {
+                break;
+            }
+        }
+
+        let interval = self.interval;
+        self.last_sent.retain(|_, last| *last + interval > now);
+    }
+
+    /// Send held requests that no longer wait for any light update. Returns
+    /// true if any were sent.
+    fn release_held(&mut self) -> bool {
+        let mut released = false;
+        while let Some((epoch, _)) = self.held.front() {
+            let waiting = self.queues.values().any(|queue| {
+                queue
+                    .front()
+                    .is_some_and(|(upd_epoch, _)| upd_epoch <= epoch)
+            });
+            if waiting {
+                break;
+            }
+            if let Some((_, req)) = self.held.pop_front() {
+                self.send(req);
+                released = true;
+            }
+        }
+        released
+    }
+
+    fn handle_request(&mut self, req: Arc<BackendRequest>, now: Instant) {
+        match &*req {
+            BackendRequest::LightUpdate(link, upd) => {
+                self.push(*link, upd.clone());
+            }
+            BackendRequest::EntertainmentFrame(..) => return self.send(req),
+            _ => {
+                self.held.push_back((self.epoch, req));
+                self.epoch += 1;
+            }
+        }
+        self.send_due(now);
+    }
+
+    pub async fn run_forever(mut self, mut input: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        loop {
+            let next = self.next_due(Instant::now());
+            select! {
+                req = input.recv() => match req {
+                    Ok(req) => self.handle_request(req, Instant::now()),
+                    Err(RecvError::Lagged(count)) => {
+                        log::warn!("Backend request queue lagging, skipped {count} requests");
+                    }
+                    Err(err) => return Err(err.into()),
+                },
+                () = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                    self.send_due(Instant::now());
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::broadcast::{self, Receiver};
+    use tokio::time::Instant;
+
+    use hue::api::{DeltaAction, DimmingDeltaUpdate, LightUpdate, On, RType, ResourceLink};
+
+    use crate::backend::coalesce::Coalescer;
+    use crate::backend::BackendRequest;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn coalescer() -> (Coalescer, Receiver<Arc<BackendRequest>>) {
+        let (tx, rx) = broadcast::channel(32);
+        (Coalescer::new(100, tx), rx)
+    }
+
+    fn light(name: &str) -> ResourceLink {
+        RType::Light.deterministic(name)
+    }
+
+    fn update(link: ResourceLink, upd: LightUpdate) -> Arc<BackendRequest> {
+        Arc::new(BackendRequest::LightUpdate(link, upd))
+    }
+
+    fn brightness(bri: f64) -> LightUpdate {
+        LightUpdate::new().with_brightness(Some(bri))
+    }
+
+    fn dim_up() -> LightUpdate {
+        LightUpdate {
+            dimming_delta: Some(DimmingDeltaUpdate {
+                action: DeltaAction::Up,
+                brightness_delta: Some(10.0),
+            }),
+            ..LightUpdate::default()
+        }
+    }
+
+    /// Requests sent so far, as (light, brightness) for light updates, and
+    /// `None` for everything else
+    fn sent(rx: &mut Receiver<Arc<BackendRequest>>) -> Vec<Option<(ResourceLink, Option<f64>)>> {
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|req| match &*req {
+                BackendRequest::LightUpdate(link, upd) => {
+                    Some((*link, upd.dimming.map(|dim| dim.brightness)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_waiting_updates() {
+        let (mut co, mut rx) = coalescer();
+        let lamp = light("lamp");
+        let t0 = Instant::now();
+
+        co.handle_request(update(lamp, brightness(10.0)), t0);
+        assert_eq!(sent(&mut rx), [Some((lamp, Some(10.0)))]);
+
+        // within the interval, updates wait and are merged
+        co.handle_request(update(lamp, brightness(20.0)), t0);
+        co.handle_request(update(lamp, LightUpdate::new().with_on(On::new(true))), t0);
+        co.handle_request(update(lamp, brightness(30.0)), t0);
+        assert_eq!(sent(&mut rx), []);
+        assert_eq!(co.next_due(t0), Some(t0 + INTERVAL));
+
+        co.send_due(t0 + INTERVAL);
+        let reqs: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        let [req] = reqs.as_slice() else {
+            panic!("expected one update, got {reqs:?}");
+        };
+        let BackendRequest::LightUpdate(_, upd) = &**req else {
+            panic!("expected light update");
+        };
+        assert_eq!(upd.dimming.map(|dim| dim.brightness), Some(30.0));
+        assert!(upd.on.is_some_and(|on| on.on));
+    }
+
+    #[test]
+    fn relative_updates_are_not_merged() {
+        let (mut co, mut rx) = coalescer();
+        let lamp = light("lamp");
+        let t0 = Instant::now();
+
+        co.handle_request(update(lamp, brightness(10.0)), t0);
+        co.handle_request(update(lamp, dim_up()), t0);
+        co.handle_request(update(lamp, dim_up()), t0);
+        co.handle_request(update(lamp, brightness(50.0)), t0);
+        assert_eq!(sent(&mut rx).len(), 1);
+
+        // each relative update is sent in its own turn
+        for step in 1..=3 {
+            co.send_due(t0 + INTERVAL * step);
+            assert_eq!(sent(&mut rx).len(), 1, "step {step}");
+        }
+        assert_eq!(co.next_due(t0 + INTERVAL * 3), None);
+    }
+
+    #[test]
+    fn lights_have_separate_intervals() {
+        let (mut co, mut rx) = coalescer();
+        let (lamp, spot) = (light("lamp"), light("spot"));
+        let t0 = Instant::now();
+
+        co.handle_request(update(lamp, brightness(10.0)), t0);
+        co.handle_request(update(spot, brightness(10.0)), t0 + INTERVAL / 2);
+        co.handle_request(update(spot, brightness(20.0)), t0 + INTERVAL / 2);
+        co.handle_request(update(lamp, brightness(20.0)), t0 + INTERVAL / 2);
+        assert_eq!(
+            sent(&mut rx),
+            [Some((lamp, Some(10.0))), Some((spot, Some(10.0)))]
+        );
+
+        // the lamp is due first
+        let next = co.next_due(t0 + INTERVAL / 2);
+        assert_eq!(next, Some(t0 + INTERVAL));
+        co.send_due(t0 + INTERVAL);
+        assert_eq!(sent(&mut rx), [Some((lamp, Some(20.0)))]);
+
+        assert_eq!(co.next_due(t0 + INTERVAL), Some(t0 + INTERVAL * 3 / 2));
+        co.send_due(t0 + INTERVAL * 3 / 2);
+        assert_eq!(sent(&mut rx), [Some((spot, Some(20.0)))]);
+    }
+
+    #[test]
+    fn other_requests_wait_for_earlier_updates() {
+        let (mut co, mut rx) = coalescer();
+        let lamp = light("lamp");
+        let t0 = Instant::now();
+
+        co.handle_request(update(lamp, brightness(10.0)), t0);
+        co.handle_request(update(lamp, brightness(20.0)), t0);
+        co.handle_request(Arc::new(BackendRequest::GroupReconcile), t0);
+        co.handle_request(update(lamp, brightness(30.0)), t0);
+        assert_eq!(sent(&mut rx), [Some((lamp, Some(10.0)))]);
+
+        // the interval is kept, and the request is sent after the update
+        // that came before it, but before the one that came after it
+        co.send_due(t0 + INTERVAL);
+        assert_eq!(sent(&mut rx), [Some((lamp, Some(20.0))), None]);
+
+        co.send_due(t0 + INTERVAL * 2);
+        assert_eq!(sent(&mut rx), [Some((lamp, Some(30.0)))]);
+    }
+
+    #[test]
+    fn other_requests_pass_without_waiting_updates() {
+        let (mut co, mut rx) = coalescer();
+        let lamp = light("lamp");
+        let t0 = Instant::now();
+
+        co.handle_request(update(lamp, brightness(10.0)), t0);
+        co.handle_request(Arc::new(BackendRequest::GroupReconcile), t0);
+        assert_eq!(sent(&mut rx), [Some((lamp, Some(10.0))), None]);
+        assert_eq!(co.next_due(t0), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Zcl messages for lights: commands, attribute reads and reporting
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use byteorder::{WriteBytesExt, LE};
+
+use hue::api::{LightUpdate, On};
+use hue::xy::XY;
+use hue::zigbee::{HueZigbeeUpdate, ZigbeeMessage};
+use zcl::attr::ZclAttrValue;
+use zcl::frame::ZclFrameType;
+
+use crate::error::ApiResult;
+
+/// Clusters we know about
+pub const CLUSTER_BASIC: u16 = 0x0000;
+pub const CLUSTER_IDENTIFY: u16 = 0x0003;
+pub const CLUSTER_ON_OFF: u16 = 0x0006;
+pub const CLUSTER_LEVEL_CONTROL: u16 = 0x0008;
+pub const CLUSTER_COLOR_CONTROL: u16 = 0x0300;
+pub const CLUSTER_HUE: u16 = 0xFC03;
+
+/// Attributes we know about
+pub const ATTR_MANUFACTURER_NAME: u16 = 0x0004;
+pub const ATTR_MODEL_ID: u16 = 0x0005;
+const ATTR_ON_OFF: u16 = 0x0000;
+const ATTR_CURRENT_LEVEL: u16 = 0x0000;
+const ATTR_CURRENT_X: u16 = 0x0003;
+const ATTR_CURRENT_Y: u16 = 0x0004;
+const ATTR_COLOR_TEMPERATURE: u16 = 0x0007;
+const ATTR_COLOR_MODE: u16 = 0x0008;
+
+/// Profile-wide commands
+const CMD_READ_ATTRIBUTES: u8 = 0x00;
+pub const CMD_READ_ATTRIBUTES_RESPONSE: u8 = 0x01;
+const CMD_CONFIGURE_REPORTING: u8 = 0x06;
+pub const CMD_REPORT_ATTRIBUTES: u8 = 0x0A;
+
+/// Zcl data types used for attribute reporting
+const TYPE_BOOL: u8 = 0x10;
+const TYPE_U8: u8 = 0x20;
+const TYPE_U16: u8 = 0x21;
+const TYPE_ENUM8: u8 = 0x30;
+
+/// Color mode, when the light uses its color temperature
+const COLOR_MODE_TEMPERATURE: u64 = 2;
+
+/// Highest level of the level control cluster
+const LEVEL_MAX: f64 = 254.0;
+
+/// Scale of xy color coordinates
+const XY_SCALE: f64 = 65536.0;
+
+/// Attributes reported by lights: (cluster, attribute, type, reportable
+/// change). Discrete attributes have no reportable change.
+pub const REPORTING: &[(u16, u16, u8, Option<u16>)] = &[
+    (CLUSTER_ON_OFF, ATTR_ON_OFF, TYPE_BOOL, None),
+    (CLUSTER_LEVEL_CONTROL, ATTR_CURRENT_LEVEL, TYPE_U8, Some(1)),
+    (CLUSTER_COLOR_CONTROL, ATTR_CURRENT_X, TYPE_U16, Some(16)),
+    (CLUSTER_COLOR_CONTROL, ATTR_CURRENT_Y, TYPE_U16, Some(16)),
+    (
+        CLUSTER_COLOR_CONTROL,
+        ATTR_COLOR_TEMPERATURE,
+        TYPE_U16,
+        Some(1),
+    ),
+    (CLUSTER_COLOR_CONTROL, ATTR_COLOR_MODE, TYPE_ENUM8, None),
+];
+
+/// Numeric value of an attribute
+fn number(value: &ZclAttrValue) -> Option<u64> {
+    match value {
+        ZclAttrValue::Bool(val) => Some(u64::from(*val)),
+        ZclAttrValue::U8(val) | ZclAttrValue::E8(val) | ZclAttrValue::B8(val) => {
+            Some(u64::from(*val))
+        }
+        ZclAttrValue::U16(val) | ZclAttrValue::B16(val) => Some(u64::from(*val)),
+        ZclAttrValue::U32(val) | ZclAttrValue::B32(val) => Some(u64::from(*val)),
+        _ => None,
+    }
+}
+
+/// Zcl message for a profile-wide command
+fn profile_wide(cluster: u16, command: u8, data: Vec<u8>) -> ZigbeeMessage {
+    ZigbeeMessage {
+        frametype: ZclFrameType::ProfileWide as u8,
+        ..ZigbeeMessage::new(cluster, command, data).with_mfc(None)
+    }
+}
+
+pub fn read_attributes(cluster: u16, attributes: &[u16]) -> ApiResult<ZigbeeMessage> {
+    let mut data = vec![];
+    for attr in attributes {
+        data.write_u16::<LE>(*attr)?;
+    }
+    Ok(profile_wide(cluster, CMD_READ_ATTRIBUTES, data))
+}
+
+/// Ask for reports of the state attributes of `cluster`, at least every 5
+/// minutes
+pub fn configure_reporting(cluster: u16) -> ApiResult<ZigbeeMessage> {
+    let mut data = vec![];
+    for (_, attr, kind, change) in REPORTING.iter().filter(|rep| rep.0 == cluster) {
+        data.push(0x00);
+        data.write_u16::<LE>(*attr)?;
+        data.push(*kind);
+        data.write_u16::<LE>(0)?;
+        data.write_u16::<LE>(300)?;
+        match (*kind, change) {
+            (TYPE_U8, Some(change)) => data.push(u8::try_from(*change)?),
+            (_, Some(change)) => data.write_u16::<LE>(*change)?,
+            (_, None) => {}
+        }
+    }
+    Ok(profile_wide(cluster, CMD_CONFIGURE_REPORTING, data))
+}
+
+/// Standard zcl commands for a light update
+pub fn light_commands(upd: &LightUpdate) -> ApiResult<Vec<ZigbeeMessage>> {
+    /* zcl transition times are in tenths of a second */
+    let transition = upd
+        .dynamics
+        .as_ref()
+        .and_then(|dyn_| dyn_.duration)
+        .map_or(0, |ms| u16::try_from(ms / 100).unwrap_or(u16::MAX));
+    let command = |cluster, cmd, data| ZigbeeMessage::new(cluster, cmd, data).with_mfc(None);
+
+    let mut cmds = vec![];
+    match upd.on.map(|on| on.on) {
+        Some(false) => return Ok(vec![command(CLUSTER_ON_OFF, 0x00, vec![])]),
+        Some(true) if upd.dimming.is_none() => cmds.push(command(CLUSTER_ON_OFF, 0x01, vec![])),
+        _ => {}
+    }
+
+    if let Some(color) = &upd.color {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let coord = |value: f64| (value * XY_SCALE).round().clamp(0.0, 65279.0) as u16;
+        let mut data = vec![];
+        data.write_u16::<LE>(coord(color.xy.x))?;
+        data.write_u16::<LE>(coord(color.xy.y))?;
+        data.write_u16::<LE>(transition)?;
+        cmds.push(command(CLUSTER_COLOR_CONTROL, 0x07, data));
+    }
+    if let Some(ct) = &upd.color_temperature {
+        let mut data = vec![];
+        data.write_u16::<LE>(ct.mirek)?;
+        data.write_u16::<LE>(transition)?;
+        cmds.push(command(CLUSTER_COLOR_CONTROL, 0x0A, data));
+    }
+    if let Some(dim) = &upd.dimming {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let level = (dim.brightness / 100.0 * LEVEL_MAX)
+            .round()
+            .clamp(1.0, LEVEL_MAX) as u8;
+        let mut data = vec![level];
+        data.write_u16::<LE>(transition)?;
+        cmds.push(command(CLUSTER_LEVEL_CONTROL, 0x04, data));
+    }
+
+    Ok(cmds)
+}
+
+/// Hue-specific update (sent on the hue manufacturer cluster) for a light
+/// update
+pub fn hue_command(upd: &LightUpdate) -> ApiResult<ZigbeeMessage> {
+    let mut hz = HueZigbeeUpdate::new();
+    if let Some(on) = &upd.on {
+        hz = hz.with_on_off(on.on);
+    }
+    if let Some(dim) = &upd.dimming {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let level = (dim.brightness / 100.0 * LEVEL_MAX)
+            .round()
+            .clamp(1.0, LEVEL_MAX) as u8;
+        hz = hz.with_brightness(level);
+    }
+    if let Some(ct) = &upd.color_temperature {
+        hz = hz.with_color_mirek(ct.mirek);
+    }
+    if let Some(color) = &upd.color {
+        hz = hz.with_color_xy(color.xy);
+    }
+    if let Some(duration) = upd.dynamics.as_ref().and_then(|dyn_| dyn_.duration) {
+        hz = hz.with_transition(Duration::from_millis(u64::from(duration)));
+    }
+
+    Ok(ZigbeeMessage::new(CLUSTER_HUE, 0x00, hz.to_vec()?))
+}
+
+/// Light state, from cached attributes
+#[must_use]
+pub fn light_state(attrs: &BTreeMap<(u16, u16), ZclAttrValue>) -> LightUpdate {
+    let get = |cluster, attr| attrs.get(&(cluster, attr)).and_then(number);
+
+    let on = get(CLUSTER_ON_OFF, ATTR_ON_OFF).map(|on| On::new(on != 0));
+    #[allow(clippy::cast_precision_loss)]
+    let bri = get(CLUSTER_LEVEL_CONTROL, ATTR_CURRENT_LEVEL)
+        .map(|level| (level as f64 / LEVEL_MAX * 100.0).clamp(0.0, 100.0));
+
+    /* lights report both xy and color temperature, but only one of them is
+     * in use */
+    let mode = get(CLUSTER_COLOR_CONTROL, ATTR_COLOR_MODE);
+    let mirek = get(CLUSTER_COLOR_CONTROL, ATTR_COLOR_TEMPERATURE)
+        .filter(|_| mode == Some(COLOR_MODE_TEMPERATURE))
+        .and_then(|ct| u16::try_from(ct).ok());
+    #[allow(clippy::cast_precision_loss)]
+    let coord = |attr| get(CLUSTER_COLOR_CONTROL, attr).map(|value| value as f64 / XY_SCALE);
+    let xy = match (coord(ATTR_CURRENT_X), coord(ATTR_CURRENT_Y)) {
+        (Some(x), Some(y)) if mirek.is_none() => Some(XY::new(x, y)),
+        _ => None,
+    };
+
+    LightUpdate::new()
+        .with_on(on)
+        .with_brightness(bri)
+        .with_color_temperature(mirek)
+        .with_color_xy(xy)
+}
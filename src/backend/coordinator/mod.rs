@@ -0,0 +1,700 @@
+//! Zigbee adapter controlled directly by bifrost (experimental)
+//!
+//! For setups without zigbee2mqtt, bifrost can talk to a zigbee adapter
+//! itself. Only Texas Instruments ZNP adapters (speaking the Z-Stack MT
+//! api, see [`znp`]) are supported, and the adapter must already have a
+//! network formed (e.g. by a previous zigbee2mqtt install).
+//!
+//! New devices join while pairing is enabled (see `/bifrost/pairing`), and
+//! are interviewed: every device with an on/off cluster becomes a light.
+//! Lights are bound to the coordinator, so they report their state, and
+//! lights with the hue manufacturer cluster (`0xFC03`) are controlled with
+//! hue-specific updates, like the hue bridge does.
+//!
+//! Paired devices are kept in a yaml file, so they survive restarts.
+
+pub mod cluster;
+pub mod znp;
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use hue::api::{DeviceArchetype, DeviceProductData, Light, RType, Resource, ResourceLink};
+use hue::zigbee::ZigbeeMessage;
+use zcl::attr::{ZclAttr, ZclAttrValue, ZclReadAttrResp, ZclReportAttr};
+use zcl::frame::{ZclFrame, ZclFrameType};
+
+use crate::backend::coordinator::cluster::{
+    configure_reporting, hue_command, light_commands, light_state, read_attributes,
+    ATTR_MANUFACTURER_NAME, ATTR_MODEL_ID, CLUSTER_BASIC, CLUSTER_COLOR_CONTROL, CLUSTER_HUE,
+    CLUSTER_IDENTIFY, CLUSTER_LEVEL_CONTROL, CLUSTER_ON_OFF, CMD_READ_ATTRIBUTES_RESPONSE,
+    CMD_REPORT_ATTRIBUTES, REPORTING,
+};
+use crate::backend::coordinator::znp::{AfDataRequest, MtEvent, MtFrame};
+use crate::backend::virt::VirtualBackend;
+use crate::backend::{self, Backend, BackendRequest, IdentifyEffect};
+use crate::config::{CoordinatorConfig, VirtualLightConfig, VirtualLightKind};
+use crate::error::{ApiError, ApiResult};
+use crate::resource::Resources;
+
+type Reader = Box<dyn AsyncRead + Send + Unpin>;
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Prefix for resource ids of devices paired with the coordinator
+const KEY: &str = "coordinator";
+
+/// Endpoint of the coordinator itself
+const ENDPOINT: u8 = 1;
+
+fn device_key(ieee: u64) -> String {
+    format!("{ieee:016x}")
+}
+
+/// Paired device, as kept in the database
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct DeviceRecord {
+    nwk: u16,
+    /// Endpoint of the light
+    endpoint: u8,
+    in_clusters: Vec<u16>,
+    manufacturer: Option<String>,
+    model: Option<String>,
+}
+
+impl DeviceRecord {
+    fn has(&self, cluster: u16) -> bool {
+        self.in_clusters.contains(&cluster)
+    }
+
+    fn kind(&self) -> VirtualLightKind {
+        if self.has(CLUSTER_COLOR_CONTROL) {
+            VirtualLightKind::Color
+        } else if self.has(CLUSTER_LEVEL_CONTROL) {
+            VirtualLightKind::White
+        } else {
+            VirtualLightKind::OnOff
+        }
+    }
+
+    fn product_data(&self) -> DeviceProductData {
+        DeviceProductData {
+            model_id: self.model.clone().unwrap_or_else(|| "zigbee".to_string()),
+            manufacturer_name: self
+                .manufacturer
+                .clone()
+                .unwrap_or_else(|| "Zigbee".to_string()),
+            product_name: self
+                .model
+                .clone()
+                .unwrap_or_else(|| "Zigbee light".to_string()),
+            product_archetype: DeviceArchetype::default(),
+            certified: false,
+            software_version: String::new(),
+            hardware_platform_type: None,
+        }
+    }
+}
+
+/// Device that joined, and is being interviewed
+#[derive(Debug)]
+struct Interview {
+    ieee: u64,
+    /// Endpoints not described yet
+    endpoints: Vec<u8>,
+    /// Endpoint with an on/off cluster, and its clusters
+    light: Option<(u8, Vec<u16>)>,
+}
+
+pub struct CoordinatorBackend {
+    config: CoordinatorConfig,
+    state: Arc<Mutex<Resources>>,
+    /// Ieee address of the adapter
+    ieee: u64,
+    /// Paired devices, by ieee address
+    devices: BTreeMap<u64, DeviceRecord>,
+    /// Lights, with the ieee address of their device
+    lights: BTreeMap<Uuid, u64>,
+    /// Devices being interviewed, by network address
+    interviews: BTreeMap<u16, Interview>,
+    /// Last known attribute values, by ieee address
+    attrs: BTreeMap<u64, BTreeMap<(u16, u16), ZclAttrValue>>,
+    /// Sequence number of the next zcl message
+    seqnr: u8,
+}
+
+impl CoordinatorBackend {
+    #[must_use]
+    pub const fn new(config: CoordinatorConfig, state: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            config,
+            state,
+            ieee: 0,
+            devices: BTreeMap::new(),
+            lights: BTreeMap::new(),
+            interviews: BTreeMap::new(),
+            attrs: BTreeMap::new(),
+            seqnr: 0,
+        }
+    }
+
+    async fn load(&mut self) -> ApiResult<()> {
+        if !self.config.database.exists() {
+            return Ok(());
+        }
+
+        let data = std::fs::read_to_string(&self.config.database)?;
+        let devices: BTreeMap<String, DeviceRecord> = serde_yml::from_str(&data)?;
+        for (key, dev) in devices {
+            let ieee = u64::from_str_radix(&key, 16)?;
+            self.add_light(ieee, dev).await?;
+        }
+
+        log::info!(
+            "[coordinator] Loaded {} devices from {}",
+            self.devices.len(),
+            self.config.database
+        );
+
+        Ok(())
+    }
+
+    fn save(&self) -> ApiResult<()> {
+        let devices: BTreeMap<String, &DeviceRecord> = self
+            .devices
+            .iter()
+            .map(|(ieee, dev)| (device_key(*ieee), dev))
+            .collect();
+        std::fs::write(&self.config.database, serde_yml::to_string(&devices)?)?;
+        Ok(())
+    }
+
+    async fn add_light(&mut self, ieee: u64, dev: DeviceRecord) -> ApiResult<()> {
+        let dev_key = device_key(ieee);
+        let key = (KEY, dev_key.as_str());
+        let name = dev
+            .model
+            .as_ref()
+            .map_or_else(|| format!("Zigbee light {dev_key}"), Clone::clone);
+        let conf = VirtualLightConfig {
+            name: name.clone(),
+            model_id: None,
+            kind: dev.kind(),
+            gradient: false,
+            count: None,
+            room: None,
+        };
+
+        let mut res = self.state.lock().await;
+        for (link, mut obj) in VirtualBackend::light_resources(&conf, key, &name) {
+            if let Resource::Device(device) = &mut obj {
+                device.product_data = dev.product_data();
+            }
+            res.add(&link, obj)?;
+        }
+        drop(res);
+
+        self.lights
+            .insert(RType::Light.deterministic(key).rid, ieee);
+        self.devices.insert(ieee, dev);
+
+        Ok(())
+    }
+
+    async fn remove_device(&mut self, ieee: u64) -> ApiResult<()> {
+        if self.devices.remove(&ieee).is_none() {
+            return Ok(());
+        }
+        self.lights.retain(|_, dev| *dev != ieee);
+        self.attrs.remove(&ieee);
+
+        let dev_key = device_key(ieee);
+        let mut res = self.state.lock().await;
+        for rtype in [
+            RType::ZigbeeConnectivity,
+            RType::Entertainment,
+            RType::Light,
+            RType::Device,
+        ] {
+            let link = rtype.deterministic((KEY, dev_key.as_str()));
+            if res.get_resource_by_id(&link.rid).is_ok() {
+                res.delete(&link)?;
+            }
+        }
+        drop(res);
+
+        log::info!("[coordinator] Removed device {dev_key}");
+        self.save()
+    }
+
+    /// Ieee address of the device of a light (or the device itself)
+    fn find_device(&self, link: &ResourceLink) -> Option<u64> {
+        self.devices.keys().copied().find(|ieee| {
+            let key = device_key(*ieee);
+            RType::Light.deterministic((KEY, key.as_str())) == *link
+                || RType::Device.deterministic((KEY, key.as_str())) == *link
+        })
+    }
+
+    /// Network address and endpoint of a device, and whether it has the hue
+    /// manufacturer cluster
+    fn target(&self, ieee: u64) -> Option<((u16, u8), bool)> {
+        self.devices
+            .get(&ieee)
+            .map(|dev| ((dev.nwk, dev.endpoint), dev.has(CLUSTER_HUE)))
+    }
+
+    fn find_nwk(&self, nwk: u16) -> Option<u64> {
+        self.devices
+            .iter()
+            .find(|(_, dev)| dev.nwk == nwk)
+            .map(|(ieee, _)| *ieee)
+    }
+
+    async fn send(writer: &mut Writer, frame: &MtFrame) -> ApiResult<()> {
+        writer.write_all(&frame.to_vec()?).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Send a zcl message to a device
+    async fn send_zcl(
+        &mut self,
+        writer: &mut Writer,
+        (nwk, endpoint): (u16, u8),
+        msg: &ZigbeeMessage,
+    ) -> ApiResult<()> {
+        let frame_type = if msg.frametype == ZclFrameType::ProfileWide as u8 {
+            ZclFrameType::ProfileWide
+        } else {
+            ZclFrameType::ClusterSpecific
+        };
+        let mut hdr = ZclFrame::new(frame_type, msg.mfc, self.seqnr, msg.command);
+        hdr.flags.disable_default_response = msg.ddr;
+        let data = hdr.to_vec(&msg.data)?;
+
+        let req = AfDataRequest {
+            nwk,
+            dst_endpoint: endpoint,
+            src_endpoint: ENDPOINT,
+            cluster: msg.cluster,
+            trans_id: self.seqnr,
+            data: &data,
+        };
+        self.seqnr = self.seqnr.wrapping_add(1);
+
+        Self::send(writer, &znp::af_data_request(&req)?).await
+    }
+
+    /// Bind a new light to the coordinator, and read its current state
+    async fn setup_light(&mut self, writer: &mut Writer, ieee: u64) -> ApiResult<()> {
+        let Some(dev) = self.devices.get(&ieee).cloned() else {
+            return Ok(());
+        };
+        let target = (dev.nwk, dev.endpoint);
+
+        for cluster in [CLUSTER_ON_OFF, CLUSTER_LEVEL_CONTROL, CLUSTER_COLOR_CONTROL] {
+            if !dev.has(cluster) {
+                continue;
+            }
+            let bind = znp::zdo_bind_req(
+                (dev.nwk, ieee, dev.endpoint),
+                cluster,
+                (self.ieee, ENDPOINT),
+            )?;
+            Self::send(writer, &bind).await?;
+            self.send_zcl(writer, target, &configure_reporting(cluster)?)
+                .await?;
+
+            let attributes: Vec<u16> = REPORTING
+                .iter()
+                .filter(|rep| rep.0 == cluster)
+                .map(|rep| rep.1)
+                .collect();
+            self.send_zcl(writer, target, &read_attributes(cluster, &attributes)?)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Device announced itself (after joining, or rejoining)
+    async fn device_announce(&mut self, writer: &mut Writer, nwk: u16, ieee: u64) -> ApiResult<()> {
+        if let Some(dev) = self.devices.get_mut(&ieee) {
+            if dev.nwk != nwk {
+                log::info!(
+                    "[coordinator] Device {} moved to {nwk:04x}",
+                    device_key(ieee)
+                );
+                dev.nwk = nwk;
+                self.save()?;
+            }
+            return Ok(());
+        }
+
+        log::info!(
+            "[coordinator] New device {}, interviewing",
+            device_key(ieee)
+        );
+        self.interviews.insert(
+            nwk,
+            Interview {
+                ieee,
+                endpoints: vec![],
+                light: None,
+            },
+        );
+        Self::send(writer, &znp::zdo_active_ep_req(nwk)?).await
+    }
+
+    async fn simple_descriptor(
+        &mut self,
+        writer: &mut Writer,
+        nwk: u16,
+        endpoint: u8,
+        in_clusters: Vec<u16>,
+    ) -> ApiResult<()> {
+        let Some(interview) = self.interviews.get_mut(&nwk) else {
+            return Ok(());
+        };
+        interview.endpoints.retain(|ep| *ep != endpoint);
+
+        if interview.light.is_none() && in_clusters.contains(&CLUSTER_ON_OFF) {
+            interview.light = Some((endpoint, in_clusters));
+            let read = read_attributes(CLUSTER_BASIC, &[ATTR_MANUFACTURER_NAME, ATTR_MODEL_ID])?;
+            return self.send_zcl(writer, (nwk, endpoint), &read).await;
+        }
+
+        if interview.endpoints.is_empty() && interview.light.is_none() {
+            log::info!(
+                "[coordinator] Device {} is not a light, ignoring it",
+                device_key(interview.ieee)
+            );
+            self.interviews.remove(&nwk);
+        }
+
+        Ok(())
+    }
+
+    /// Finish the interview of a device, with the information from its
+    /// basic cluster
+    async fn interview_done(
+        &mut self,
+        writer: &mut Writer,
+        nwk: u16,
+        attrs: &[ZclAttr],
+    ) -> ApiResult<()> {
+        let Some(Interview {
+            ieee,
+            light: Some((endpoint, in_clusters)),
+            ..
+        }) = self.interviews.remove(&nwk)
+        else {
+            return Ok(());
+        };
+
+        let text = |key| {
+            attrs.iter().find_map(|attr| match &attr.value {
+                ZclAttrValue::String(value) if attr.key == key => Some(value.clone()),
+                _ => None,
+            })
+        };
+        let dev = DeviceRecord {
+            nwk,
+            endpoint,
+            in_clusters,
+            manufacturer: text(ATTR_MANUFACTURER_NAME),
+            model: text(ATTR_MODEL_ID),
+        };
+
+        log::info!(
+            "[coordinator] Paired {} ({:?} {:?})",
+            device_key(ieee),
+            dev.manufacturer,
+            dev.model
+        );
+
+        self.add_light(ieee, dev).await?;
+        self.save()?;
+        self.setup_light(writer, ieee).await
+    }
+
+    /// Zcl message from a device
+    async fn incoming(
+        &mut self,
+        writer: &mut Writer,
+        nwk: u16,
+        cluster: u16,
+        data: &[u8],
+    ) -> ApiResult<()> {
+        /* attribute parsing is only known to be safe for these clusters */
+        if ![
+            CLUSTER_BASIC,
+            CLUSTER_ON_OFF,
+            CLUSTER_LEVEL_CONTROL,
+            CLUSTER_COLOR_CONTROL,
+        ]
+        .contains(&cluster)
+        {
+            return Ok(());
+        }
+
+        let mut rdr = Cursor::new(data);
+        let hdr = ZclFrame::parse(&mut rdr)?;
+        if hdr.cluster_specific() || hdr.manufacturer_specific() {
+            return Ok(());
+        }
+        let payload = &data[usize::try_from(rdr.position())?..];
+        let attrs = match hdr.cmd {
+            CMD_READ_ATTRIBUTES_RESPONSE => ZclReadAttrResp::parse(payload)?.attr,
+            CMD_REPORT_ATTRIBUTES => ZclReportAttr::parse(payload)?.attr,
+            _ => return Ok(()),
+        };
+
+        if cluster == CLUSTER_BASIC {
+            return self.interview_done(writer, nwk, &attrs).await;
+        }
+
+        let Some(ieee) = self.find_nwk(nwk) else {
+            return Ok(());
+        };
+        let cache = self.attrs.entry(ieee).or_default();
+        for attr in attrs {
+            cache.insert((cluster, attr.key), attr.value);
+        }
+        let upd = light_state(cache);
+
+        let key = device_key(ieee);
+        let link = RType::Light.deterministic((KEY, key.as_str()));
+        self.state
+            .lock()
+            .await
+            .update::<Light>(&link.rid, |light| *light += upd)
+    }
+
+    async fn handle_event(&mut self, writer: &mut Writer, event: MtEvent) -> ApiResult<()> {
+        match event {
+            MtEvent::ExtAddr(ieee) => {
+                log::info!("[coordinator] Adapter address is {}", device_key(ieee));
+                self.ieee = ieee;
+            }
+            MtEvent::StateChange(state) => {
+                if state == znp::DEV_ZB_COORD {
+                    log::info!("[coordinator] Network started");
+                    let lights = self.lights.keys().copied();
+                    backend::set_connected(&self.state, KEY, lights, true).await;
+                } else {
+                    log::debug!("[coordinator] Adapter state changed to {state}");
+                }
+            }
+            MtEvent::DeviceAnnounce { nwk, ieee } => {
+                self.device_announce(writer, nwk, ieee).await?;
+            }
+            MtEvent::ActiveEndpoints { nwk, endpoints } => {
+                if let Some(interview) = self.interviews.get_mut(&nwk) {
+                    interview.endpoints.clone_from(&endpoints);
+                    for endpoint in endpoints {
+                        Self::send(writer, &znp::zdo_simple_desc_req(nwk, endpoint)?).await?;
+                    }
+                }
+            }
+            MtEvent::SimpleDescriptor {
+                nwk,
+                endpoint,
+                in_clusters,
+            } => {
+                self.simple_descriptor(writer, nwk, endpoint, in_clusters)
+                    .await?;
+            }
+            MtEvent::Leave { ieee } => self.remove_device(ieee).await?,
+            MtEvent::Incoming {
+                nwk, cluster, data, ..
+            } => self.incoming(writer, nwk, cluster, &data).await?,
+            MtEvent::Status { cmd0, cmd1, status } => {
+                if status != 0 {
+                    log::warn!(
+                        "[coordinator] Request {cmd0:02x}{cmd1:02x} failed with status {status:02x}"
+                    );
+                }
+            }
+            MtEvent::Other => {}
+        }
+
+        Ok(())
+    }
+
+    async fn handle_request(&mut self, writer: &mut Writer, req: &BackendRequest) -> ApiResult<()> {
+        match req {
+            BackendRequest::PermitJoin(seconds) => {
+                log::info!("[coordinator] Permitting joins for {seconds} seconds");
+                return Self::send(writer, &znp::zdo_permit_join(*seconds)?).await;
+            }
+            BackendRequest::Touchlink => {
+                log::warn!("[coordinator] Touchlink is not supported by this backend");
+                return Ok(());
+            }
+            BackendRequest::Identify(link, effect) => {
+                if let Some((dst, _)) = self.find_device(link).and_then(|ieee| self.target(ieee)) {
+                    let effect = match effect {
+                        IdentifyEffect::Blink => 0x00,
+                        IdentifyEffect::Breathe => 0x01,
+                    };
+                    let msg = ZigbeeMessage::new(CLUSTER_IDENTIFY, 0x40, vec![effect, 0x00])
+                        .with_mfc(None);
+                    self.send_zcl(writer, dst, &msg).await?;
+                }
+                return Ok(());
+            }
+            BackendRequest::ZigbeeRaw(link, msg) => {
+                if let Some((dst, _)) = self.find_device(link).and_then(|ieee| self.target(ieee)) {
+                    self.send_zcl(writer, dst, msg).await?;
+                }
+                return Ok(());
+            }
+            BackendRequest::Delete(link) if link.rtype == RType::Device => {
+                if let Some(ieee) = self.find_device(link) {
+                    if let Some(dev) = self.devices.get(&ieee) {
+                        let leave = znp::zdo_mgmt_leave_req(dev.nwk, ieee)?;
+                        Self::send(writer, &leave).await?;
+                    }
+                    self.remove_device(ieee).await?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let lock = self.state.lock().await;
+        let updates = backend::light_updates(&lock, req, |rid| self.lights.contains_key(rid))?;
+        drop(lock);
+
+        for (rid, upd) in updates {
+            let Some((dst, hue)) = self
+                .lights
+                .get(&rid)
+                .copied()
+                .and_then(|ieee| self.target(ieee))
+            else {
+                continue;
+            };
+            let msgs = if hue {
+                vec![hue_command(&upd)?]
+            } else {
+                light_commands(&upd)?
+            };
+            for msg in msgs {
+                self.send_zcl(writer, dst, &msg).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn open(&self) -> ApiResult<(Reader, Writer)> {
+        let port = &self.config.port;
+        if let Some(addr) = port.strip_prefix("tcp://") {
+            let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+            return Ok((Box::new(reader), Box::new(writer)));
+        }
+
+        /* the baud rate is not set here, see the config reference */
+        let file = OpenOptions::new().read(true).write(true).open(port).await?;
+        Ok((Box::new(file.try_clone().await?), Box::new(file)))
+    }
+
+    async fn event_loop(
+        &mut self,
+        chan: &mut Receiver<Arc<BackendRequest>>,
+        mut reader: Reader,
+        mut writer: Writer,
+    ) -> ApiResult<()> {
+        let clusters = [
+            CLUSTER_BASIC,
+            CLUSTER_IDENTIFY,
+            CLUSTER_ON_OFF,
+            CLUSTER_LEVEL_CONTROL,
+            CLUSTER_COLOR_CONTROL,
+            CLUSTER_HUE,
+        ];
+        Self::send(&mut writer, &znp::sys_get_extaddr()).await?;
+        Self::send(&mut writer, &znp::af_register(ENDPOINT, &clusters)?).await?;
+        Self::send(&mut writer, &znp::zdo_startup_from_app()).await?;
+
+        let mut buf = vec![];
+        let mut chunk = [0; 256];
+        loop {
+            tokio::select! {
+                len = reader.read(&mut chunk) => {
+                    let len = len?;
+                    if len == 0 {
+                        return Err(ApiError::UnexpectedCoordinatorEof);
+                    }
+                    buf.extend(&chunk[..len]);
+                    while let Some(frame) = MtFrame::take(&mut buf) {
+                        let res = match MtEvent::parse(&frame) {
+                            Ok(event) => self.handle_event(&mut writer, event).await,
+                            Err(err) => Err(err),
+                        };
+                        if let Err(err) = res {
+                            log::error!("[coordinator] Failed to handle message: {err}");
+                        }
+                    }
+                }
+                req = chan.recv() => {
+                    let req = match req {
+                        Ok(req) => req,
+                        Err(RecvError::Lagged(count)) => {
+                            log::warn!("[coordinator] Backend lagging, skipped {count} requests");
+                            continue;
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+                    if let Err(err) = self.handle_request(&mut writer, &req).await {
+                        log::error!("[coordinator] Failed to handle request: {err}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for CoordinatorBackend {
+    async fn run_forever(mut self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        self.load().await?;
+
+        loop {
+            log::info!(
+                "[coordinator] Opening zigbee adapter at {}",
+                self.config.port
+            );
+            self.state.lock().await.backend_connect_attempt(KEY);
+            match self.open().await {
+                Ok((reader, writer)) => {
+                    if let Err(err) = self.event_loop(&mut chan, reader, writer).await {
+                        log::error!("[coordinator] Event loop broke: {err}");
+                        self.state.lock().await.set_backend_error(KEY, &err);
+                    }
+                }
+                Err(err) => {
+                    log::error!("[coordinator] Cannot open adapter: {err}");
+                    self.state.lock().await.set_backend_error(KEY, &err);
+                }
+            }
+            backend::set_connected(&self.state, KEY, self.lights.keys().copied(), false).await;
+            self.interviews.clear();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
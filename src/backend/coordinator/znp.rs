@@ -0,0 +1,548 @@
+//! Monitor and Test (MT) api of Texas Instruments Z-Stack, as spoken by
+//! ZNP adapters (CC2652, CC1352, etc.)
+//!
+//! Every frame is `0xFE`, length, two command bytes, data and a checksum
+//! (xor of everything after `0xFE`). The first command byte holds the type
+//! (synchronous request or response, or asynchronous message) and the
+//! subsystem, the second one the command id.
+
+use std::io::{Cursor, Read};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+
+use crate::error::{ApiError, ApiResult};
+
+/// Start of frame marker
+const SOF: u8 = 0xFE;
+
+/// Maximum length of the data in a frame
+const MAX_DATA_LEN: u8 = 250;
+
+/// Frame types (upper 3 bits of the first command byte)
+const TYPE_SREQ: u8 = 0x20;
+const TYPE_AREQ: u8 = 0x40;
+const TYPE_SRSP: u8 = 0x60;
+const TYPE_MASK: u8 = 0xE0;
+
+/// Subsystems (lower 5 bits of the first command byte)
+const SUBSYS_SYS: u8 = 0x01;
+const SUBSYS_AF: u8 = 0x04;
+const SUBSYS_ZDO: u8 = 0x05;
+
+/// Home automation profile
+pub const PROFILE_HA: u16 = 0x0104;
+
+/// Broadcast address for all routers (and the coordinator)
+const BROADCAST_ROUTERS: u16 = 0xFFFC;
+
+/// Address modes
+const ADDR_MODE_64BIT: u8 = 0x03;
+const ADDR_MODE_BROADCAST: u8 = 0x0F;
+
+/// Device state of a started coordinator
+pub const DEV_ZB_COORD: u8 = 0x09;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MtFrame {
+    pub cmd0: u8,
+    pub cmd1: u8,
+    pub data: Vec<u8>,
+}
+
+impl MtFrame {
+    const fn sreq(subsys: u8, cmd: u8, data: Vec<u8>) -> Self {
+        Self {
+            cmd0: TYPE_SREQ | subsys,
+            cmd1: cmd,
+            data,
+        }
+    }
+
+    const fn is(&self, kind: u8, subsys: u8, cmd: u8) -> bool {
+        self.cmd0 == kind | subsys && self.cmd1 == cmd
+    }
+
+    #[must_use]
+    pub const fn is_srsp(&self) -> bool {
+        self.cmd0 & TYPE_MASK == TYPE_SRSP
+    }
+
+    /// Encode frame, including start marker and checksum
+    pub fn to_vec(&self) -> ApiResult<Vec<u8>> {
+        let len = u8::try_from(self.data.len())
+            .ok()
+            .filter(|len| *len <= MAX_DATA_LEN)
+            .ok_or(ApiError::CoordinatorFrameTooLarge(self.data.len()))?;
+
+        let mut res = vec![SOF, len, self.cmd0, self.cmd1];
+        res.extend(&self.data);
+        res.push(res[1..].iter().fold(0, |fcs, byte| fcs ^ byte));
+        Ok(res)
+    }
+
+    /// Take the first complete frame out of `buf`. Any garbage before it,
+    /// and frames with a bad checksum, are dropped.
+    pub fn take(buf: &mut Vec<u8>) -> Option<Self> {
+        loop {
+            let start = buf.iter().position(|byte| *byte == SOF)?;
+            buf.drain(..start);
+
+            let len = usize::from(*buf.get(1)?);
+            if buf.len() < len + 5 {
+                return None;
+            }
+
+            let frame: Vec<u8> = buf.drain(..len + 5).collect();
+            let fcs = frame[1..len + 4].iter().fold(0, |fcs, byte| fcs ^ byte);
+            if fcs != frame[len + 4] {
+                log::warn!("[coordinator] Dropping frame with bad checksum");
+                continue;
+            }
+
+            return Some(Self {
+                cmd0: frame[2],
+                cmd1: frame[3],
+                data: frame[4..len + 4].to_vec(),
+            });
+        }
+    }
+}
+
+/* requests */
+
+#[must_use]
+pub const fn sys_get_extaddr() -> MtFrame {
+    MtFrame::sreq(SUBSYS_SYS, 0x04, vec![])
+}
+
+#[must_use]
+pub fn zdo_startup_from_app() -> MtFrame {
+    MtFrame::sreq(SUBSYS_ZDO, 0x40, vec![0x00, 0x00])
+}
+
+/// Register an endpoint on the coordinator, for the given client clusters
+pub fn af_register(endpoint: u8, out_clusters: &[u16]) -> ApiResult<MtFrame> {
+    let mut data = vec![endpoint];
+    data.write_u16::<LE>(PROFILE_HA)?;
+    /* device id: configuration tool */
+    data.write_u16::<LE>(0x0005)?;
+    /* device version, latency */
+    data.extend([0x00, 0x00]);
+    /* no server clusters */
+    data.push(0);
+    data.push(u8::try_from(out_clusters.len())?);
+    for cluster in out_clusters {
+        data.write_u16::<LE>(*cluster)?;
+    }
+    Ok(MtFrame::sreq(SUBSYS_AF, 0x00, data))
+}
+
+pub struct AfDataRequest<'a> {
+    pub nwk: u16,
+    pub dst_endpoint: u8,
+    pub src_endpoint: u8,
+    pub cluster: u16,
+    pub trans_id: u8,
+    pub data: &'a [u8],
+}
+
+pub fn af_data_request(req: &AfDataRequest) -> ApiResult<MtFrame> {
+    let mut data = vec![];
+    data.write_u16::<LE>(req.nwk)?;
+    data.push(req.dst_endpoint);
+    data.push(req.src_endpoint);
+    data.write_u16::<LE>(req.cluster)?;
+    data.push(req.trans_id);
+    /* options: none, radius: default */
+    data.extend([0x00, 0x1E]);
+    data.push(u8::try_from(req.data.len())?);
+    data.extend(req.data);
+    Ok(MtFrame::sreq(SUBSYS_AF, 0x01, data))
+}
+
+pub fn zdo_permit_join(seconds: u8) -> ApiResult<MtFrame> {
+    let mut data = vec![ADDR_MODE_BROADCAST];
+    data.write_u16::<LE>(BROADCAST_ROUTERS)?;
+    data.extend([seconds, 0x00]);
+    Ok(MtFrame::sreq(SUBSYS_ZDO, 0x36, data))
+}
+
+pub fn zdo_active_ep_req(nwk: u16) -> ApiResult<MtFrame> {
+    let mut data = vec![];
+    data.write_u16::<LE>(nwk)?;
+    data.write_u16::<LE>(nwk)?;
+    Ok(MtFrame::sreq(SUBSYS_ZDO, 0x05, data))
+}
+
+pub fn zdo_simple_desc_req(nwk: u16, endpoint: u8) -> ApiResult<MtFrame> {
+    let mut data = vec![];
+    data.write_u16::<LE>(nwk)?;
+    data.write_u16::<LE>(nwk)?;
+    data.push(endpoint);
+    Ok(MtFrame::sreq(SUBSYS_ZDO, 0x04, data))
+}
+
+/// Bind a cluster of a device to the coordinator, so attribute reports are
+/// sent to us
+pub fn zdo_bind_req(
+    (nwk, ieee, endpoint): (u16, u64, u8),
+    cluster: u16,
+    (coordinator, coordinator_endpoint): (u64, u8),
+) -> ApiResult<MtFrame> {
+    let mut data = vec![];
+    data.write_u16::<LE>(nwk)?;
+    data.write_u64::<LE>(ieee)?;
+    data.push(endpoint);
+    data.write_u16::<LE>(cluster)?;
+    data.push(ADDR_MODE_64BIT);
+    data.write_u64::<LE>(coordinator)?;
+    data.push(coordinator_endpoint);
+    Ok(MtFrame::sreq(SUBSYS_ZDO, 0x21, data))
+}
+
+/// Ask a device to leave the network
+pub fn zdo_mgmt_leave_req(nwk: u16, ieee: u64) -> ApiResult<MtFrame> {
+    let mut data = vec![];
+    data.write_u16::<LE>(nwk)?;
+    data.write_u64::<LE>(ieee)?;
+    data.push(0x00);
+    Ok(MtFrame::sreq(SUBSYS_ZDO, 0x34, data))
+}
+
+/* responses and indications */
+
+#[derive(Clone, Debug)]
+pub enum MtEvent {
+    /// Ieee address of the coordinator
+    ExtAddr(u64),
+    /// Device state of the coordinator changed
+    StateChange(u8),
+    /// A device joined (or rejoined) the network
+    DeviceAnnounce {
+        nwk: u16,
+        ieee: u64,
+    },
+    ActiveEndpoints {
+        nwk: u16,
+        endpoints: Vec<u8>,
+    },
+    SimpleDescriptor {
+        nwk: u16,
+        endpoint: u8,
+        in_clusters: Vec<u16>,
+    },
+    /// A device left the network
+    Leave {
+        ieee: u64,
+    },
+    /// Zigbee message from a device
+    Incoming {
+        nwk: u16,
+        endpoint: u8,
+        cluster: u16,
+        data: Vec<u8>,
+    },
+    /// Status of a synchronous request
+    Status {
+        cmd0: u8,
+        cmd1: u8,
+        status: u8,
+    },
+    Other,
+}
+
+fn read_clusters(rdr: &mut impl Read) -> ApiResult<Vec<u16>> {
+    let count = rdr.read_u8()?;
+    (0..count).map(|_| Ok(rdr.read_u16::<LE>()?)).collect()
+}
+
+impl MtEvent {
+    pub fn parse(frame: &MtFrame) -> ApiResult<Self> {
+        let mut rdr = Cursor::new(&frame.data);
+
+        let event = if frame.is(TYPE_SRSP, SUBSYS_SYS, 0x04) {
+            Self::ExtAddr(rdr.read_u64::<LE>()?)
+        } else if frame.is_srsp() {
+            frame
+                .data
+                .first()
+                .map_or(Self::Other, |status| Self::Status {
+                    cmd0: frame.cmd0,
+                    cmd1: frame.cmd1,
+                    status: *status,
+                })
+        } else if frame.is(TYPE_AREQ, SUBSYS_ZDO, 0xC0) {
+            Self::StateChange(rdr.read_u8()?)
+        } else if frame.is(TYPE_AREQ, SUBSYS_ZDO, 0xC1) {
+            let _src = rdr.read_u16::<LE>()?;
+            let nwk = rdr.read_u16::<LE>()?;
+            let ieee = rdr.read_u64::<LE>()?;
+            Self::DeviceAnnounce { nwk, ieee }
+        } else if frame.is(TYPE_AREQ, SUBSYS_ZDO, 0x85) {
+            let _src = rdr.read_u16::<LE>()?;
+            let _status = rdr.read_u8()?;
+            let nwk = rdr.read_u16::<LE>()?;
+            let mut endpoints = vec![0; usize::from(rdr.read_u8()?)];
+            rdr.read_exact(&mut endpoints)?;
+            Self::ActiveEndpoints { nwk, endpoints }
+        } else if frame.is(TYPE_AREQ, SUBSYS_ZDO, 0x84) {
+            let _src = rdr.read_u16::<LE>()?;
+            let _status = rdr.read_u8()?;
+            let nwk = rdr.read_u16::<LE>()?;
+            let _len = rdr.read_u8()?;
+            let endpoint = rdr.read_u8()?;
+            /* profile, device id, device version */
+            let mut skip = [0; 5];
+            rdr.read_exact(&mut skip)?;
+            let in_clusters = read_clusters(&mut rdr)?;
+            Self::SimpleDescriptor {
+                nwk,
+                endpoint,
+                in_clusters,
+            }
+        } else if frame.is(TYPE_AREQ, SUBSYS_ZDO, 0xC9) {
+            let _src = rdr.read_u16::<LE>()?;
+            Self::Leave {
+                ieee: rdr.read_u64::<LE>()?,
+            }
+        } else if frame.is(TYPE_AREQ, SUBSYS_AF, 0x81) {
+            let _group = rdr.read_u16::<LE>()?;
+            let cluster = rdr.read_u16::<LE>()?;
+            let nwk = rdr.read_u16::<LE>()?;
+            let endpoint = rdr.read_u8()?;
+            /* dst endpoint, broadcast, link quality, security, timestamp,
+             * sequence number */
+            let mut skip = [0; 9];
+            rdr.read_exact(&mut skip)?;
+            let mut data = vec![0; usize::from(rdr.read_u8()?)];
+            rdr.read_exact(&mut data)?;
+            Self::Incoming {
+                nwk,
+                endpoint,
+                cluster,
+                data,
+            }
+        } else {
+            Self::Other
+        };
+
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::coordinator::znp::{
+        sys_get_extaddr, MtEvent, MtFrame, SOF, SUBSYS_AF, SUBSYS_SYS, SUBSYS_ZDO, TYPE_AREQ,
+        TYPE_SRSP,
+    };
+
+    fn frame(cmd0: u8, cmd1: u8, data: &[u8]) -> MtFrame {
+        MtFrame {
+            cmd0,
+            cmd1,
+            data: data.to_vec(),
+        }
+    }
+
+    fn areq(subsys: u8, cmd: u8, data: &[u8]) -> MtEvent {
+        MtEvent::parse(&frame(TYPE_AREQ | subsys, cmd, data)).unwrap()
+    }
+
+    #[test]
+    fn encode() {
+        let bytes = sys_get_extaddr().to_vec().unwrap();
+        assert_eq!(bytes, [SOF, 0x00, 0x21, 0x04, 0x25]);
+    }
+
+    #[test]
+    fn encode_max_len() {
+        assert!(frame(0x24, 0x01, &[0; 250]).to_vec().is_ok());
+        assert!(frame(0x24, 0x01, &[0; 251]).to_vec().is_err());
+        assert!(frame(0x24, 0x01, &[0; 256]).to_vec().is_err());
+    }
+
+    #[test]
+    fn take_roundtrip() {
+        let msg = frame(0x45, 0xC0, &[0x09]);
+        let mut buf = msg.to_vec().unwrap();
+        assert_eq!(MtFrame::take(&mut buf), Some(msg));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn take_resyncs_after_garbage() {
+        let msg = frame(0x45, 0xC0, &[0x09]);
+        let mut buf = vec![0x00, 0x13, 0x37];
+        buf.extend(msg.to_vec().unwrap());
+        assert_eq!(MtFrame::take(&mut buf), Some(msg));
+        assert!(buf.is_empty());
+
+        let mut buf = vec![0x00, 0x13, 0x37];
+        assert_eq!(MtFrame::take(&mut buf), None);
+    }
+
+    #[test]
+    fn take_drops_bad_checksum() {
+        let first = frame(0x45, 0xC0, &[0x09]);
+        let second = frame(0x45, 0xC0, &[0x08]);
+
+        let mut buf = first.to_vec().unwrap();
+        *buf.last_mut().unwrap() ^= 0xFF;
+        buf.extend(second.to_vec().unwrap());
+
+        assert_eq!(MtFrame::take(&mut buf), Some(second));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn take_waits_for_partial_frame() {
+        let msg = frame(0x45, 0xC0, &[0x09]);
+        let bytes = msg.to_vec().unwrap();
+
+        let mut buf = bytes[..3].to_vec();
+        assert_eq!(MtFrame::take(&mut buf), None);
+        assert_eq!(buf, bytes[..3]);
+
+        buf.extend(&bytes[3..]);
+        assert_eq!(MtFrame::take(&mut buf), Some(msg));
+    }
+
+    #[test]
+    fn take_leaves_next_frame() {
+        let first = frame(0x45, 0xC0, &[0x09]);
+        let second = sys_get_extaddr();
+
+        let mut buf = first.to_vec().unwrap();
+        buf.extend(second.to_vec().unwrap());
+
+        assert_eq!(MtFrame::take(&mut buf), Some(first));
+        assert_eq!(MtFrame::take(&mut buf), Some(second));
+        assert_eq!(MtFrame::take(&mut buf), None);
+    }
+
+    #[test]
+    fn parse_ext_addr() {
+        let data = 0x0011_2233_4455_6677u64.to_le_bytes();
+        let event = MtEvent::parse(&frame(TYPE_SRSP | SUBSYS_SYS, 0x04, &data)).unwrap();
+        assert!(matches!(event, MtEvent::ExtAddr(0x0011_2233_4455_6677)));
+    }
+
+    #[test]
+    fn parse_status() {
+        let event = MtEvent::parse(&frame(TYPE_SRSP | SUBSYS_AF, 0x01, &[0x02])).unwrap();
+        assert!(matches!(
+            event,
+            MtEvent::Status {
+                cmd0: 0x64,
+                cmd1: 0x01,
+                status: 0x02
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_state_change() {
+        let event = areq(SUBSYS_ZDO, 0xC0, &[0x09]);
+        assert!(matches!(event, MtEvent::StateChange(0x09)));
+    }
+
+    #[test]
+    fn parse_device_announce() {
+        let mut data = vec![0x34, 0x12, 0x78, 0x56];
+        data.extend(0x0011_2233_4455_6677u64.to_le_bytes());
+        data.push(0x8E);
+
+        let event = areq(SUBSYS_ZDO, 0xC1, &data);
+        assert!(matches!(
+            event,
+            MtEvent::DeviceAnnounce {
+                nwk: 0x5678,
+                ieee: 0x0011_2233_4455_6677
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_active_endpoints() {
+        let data = [0x78, 0x56, 0x00, 0x78, 0x56, 0x02, 0x01, 0x0B];
+        let MtEvent::ActiveEndpoints { nwk, endpoints } = areq(SUBSYS_ZDO, 0x85, &data) else {
+            panic!("wrong event type");
+        };
+        assert_eq!(nwk, 0x5678);
+        assert_eq!(endpoints, [0x01, 0x0B]);
+    }
+
+    #[test]
+    fn parse_simple_descriptor() {
+        let data = [
+            0x78, 0x56, 0x00, 0x78, 0x56, 0x0E, 0x0B, 0x04, 0x01, 0x0D, 0x01, 0x01, 0x02, 0x06,
+            0x00, 0x08, 0x00, 0x00,
+        ];
+        let MtEvent::SimpleDescriptor {
+            nwk,
+            endpoint,
+            in_clusters,
+        } = areq(SUBSYS_ZDO, 0x84, &data)
+        else {
+            panic!("wrong event type");
+        };
+        assert_eq!(nwk, 0x5678);
+        assert_eq!(endpoint, 0x0B);
+        assert_eq!(in_clusters, [0x0006, 0x0008]);
+    }
+
+    #[test]
+    fn parse_leave() {
+        let mut data = vec![0x78, 0x56];
+        data.extend(0x0011_2233_4455_6677u64.to_le_bytes());
+        data.extend([0x00, 0x00, 0x00]);
+
+        let event = areq(SUBSYS_ZDO, 0xC9, &data);
+        assert!(matches!(
+            event,
+            MtEvent::Leave {
+                ieee: 0x0011_2233_4455_6677
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_incoming() {
+        let mut data = vec![0x00, 0x00, 0x06, 0x00, 0x78, 0x56, 0x0B];
+        /* dst endpoint, broadcast, link quality, security, timestamp, sequence */
+        data.extend([0x01, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2A]);
+        data.extend([0x03, 0x18, 0x01, 0x0B]);
+
+        let MtEvent::Incoming {
+            nwk,
+            endpoint,
+            cluster,
+            data,
+        } = areq(SUBSYS_AF, 0x81, &data)
+        else {
+            panic!("wrong event type");
+        };
+        assert_eq!(nwk, 0x5678);
+        assert_eq!(endpoint, 0x0B);
+        assert_eq!(cluster, 0x0006);
+        assert_eq!(data, [0x18, 0x01, 0x0B]);
+    }
+
+    #[test]
+    fn parse_truncated() {
+        assert!(MtEvent::parse(&frame(TYPE_AREQ | SUBSYS_ZDO, 0xC0, &[])).is_err());
+        assert!(MtEvent::parse(&frame(TYPE_AREQ | SUBSYS_ZDO, 0xC1, &[0x34, 0x12])).is_err());
+        assert!(
+            MtEvent::parse(&frame(TYPE_AREQ | SUBSYS_ZDO, 0x85, &[0, 0, 0, 0, 0, 2, 1])).is_err()
+        );
+        assert!(MtEvent::parse(&frame(TYPE_AREQ | SUBSYS_AF, 0x81, &[0; 16])).is_err());
+        assert!(MtEvent::parse(&frame(TYPE_SRSP | SUBSYS_SYS, 0x04, &[0; 7])).is_err());
+    }
+
+    #[test]
+    fn parse_other() {
+        let event = MtEvent::parse(&frame(TYPE_AREQ | SUBSYS_SYS, 0x80, &[0x00])).unwrap();
+        assert!(matches!(event, MtEvent::Other));
+    }
+}
@@ -0,0 +1,759 @@
+//! Matter devices, through a matter controller
+//!
+//! Bifrost does not speak matter itself. Instead, it uses the websocket api
+//! of [python-matter-server](https://github.com/home-assistant-libs/python-matter-server),
+//! which holds the matter fabric, and talks to devices over Wi-Fi and
+//! Thread.
+//!
+//! An in-process controller (on top of `rs-matter`) would avoid the extra
+//! service, but `rs-matter` only implements the device side of matter: it
+//! cannot commission other devices, or hold a fabric as a controller.
+//!
+//! Every node with an on/off cluster becomes a light. Depending on the
+//! clusters found on the same endpoint, it is dimmable (level control), and
+//! has color temperature and/or xy color (color control).
+//!
+//! New devices are commissioned with their setup code (see
+//! `/bifrost/matter/commission`), and deleting a matter device through the
+//! hue api removes it from the fabric.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+use tokio_tungstenite::{connect_async, tungstenite, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use hue::api::{
+    DeviceArchetype, DeviceProductData, Light, LightUpdate, On, RType, Resource, ResourceLink,
+};
+use hue::xy::XY;
+
+use crate::backend::virt::VirtualBackend;
+use crate::backend::{self, Backend, BackendRequest};
+use crate::config::{MatterConfig, VirtualLightConfig, VirtualLightKind};
+use crate::error::{ApiError, ApiResult};
+use crate::resource::Resources;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Prefix for resource ids of matter devices
+const KEY: &str = "matter";
+
+/// Clusters we know about
+const CLUSTER_IDENTIFY: u32 = 0x0003;
+const CLUSTER_ON_OFF: u32 = 0x0006;
+const CLUSTER_LEVEL_CONTROL: u32 = 0x0008;
+const CLUSTER_BASIC_INFORMATION: u32 = 0x0028;
+const CLUSTER_COLOR_CONTROL: u32 = 0x0300;
+
+/// Attributes we know about
+const ATTR_ON_OFF: u32 = 0x0000;
+const ATTR_CURRENT_LEVEL: u32 = 0x0000;
+const ATTR_CURRENT_X: u32 = 0x0003;
+const ATTR_CURRENT_Y: u32 = 0x0004;
+const ATTR_COLOR_TEMPERATURE: u32 = 0x0007;
+const ATTR_COLOR_MODE: u32 = 0x0008;
+const ATTR_VENDOR_NAME: u32 = 0x0001;
+const ATTR_PRODUCT_NAME: u32 = 0x0003;
+const ATTR_NODE_LABEL: u32 = 0x0005;
+const ATTR_SOFTWARE_VERSION: u32 = 0x000A;
+
+/// Color mode, when the light uses its color temperature
+const COLOR_MODE_TEMPERATURE: u64 = 2;
+
+/// Highest level of the level control cluster
+const LEVEL_MAX: f64 = 254.0;
+
+/// Scale of xy color coordinates
+const XY_SCALE: f64 = 65536.0;
+
+/// Message id of the initial `start_listening` command
+const START_LISTENING: &str = "start-listening";
+
+/// Attribute path, as used by the matter server (`endpoint/cluster/attribute`)
+fn path(endpoint: u32, cluster: u32, attribute: u32) -> String {
+    format!("{endpoint}/{cluster}/{attribute}")
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    node_id: u64,
+    #[serde(default)]
+    attributes: BTreeMap<String, Value>,
+}
+
+impl Node {
+    fn basic_info(&self, attribute: u32) -> Option<&str> {
+        self.attributes
+            .get(&path(0, CLUSTER_BASIC_INFORMATION, attribute))
+            .and_then(Value::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn name(&self) -> String {
+        self.basic_info(ATTR_NODE_LABEL)
+            .or_else(|| self.basic_info(ATTR_PRODUCT_NAME))
+            .map_or_else(|| format!("Matter node {}", self.node_id), String::from)
+    }
+
+    fn product_data(&self) -> DeviceProductData {
+        DeviceProductData {
+            model_id: self
+                .basic_info(ATTR_PRODUCT_NAME)
+                .unwrap_or("matter")
+                .to_string(),
+            manufacturer_name: self
+                .basic_info(ATTR_VENDOR_NAME)
+                .unwrap_or("Matter")
+                .to_string(),
+            product_name: self
+                .basic_info(ATTR_PRODUCT_NAME)
+                .unwrap_or("Matter device")
+                .to_string(),
+            product_archetype: DeviceArchetype::default(),
+            certified: false,
+            software_version: self
+                .basic_info(ATTR_SOFTWARE_VERSION)
+                .unwrap_or_default()
+                .to_string(),
+            hardware_platform_type: None,
+        }
+    }
+
+    fn has(&self, endpoint: u32, cluster: u32, attribute: u32) -> bool {
+        self.attributes
+            .contains_key(&path(endpoint, cluster, attribute))
+    }
+
+    /// First endpoint with an on/off cluster, and the kind of light it is
+    fn light(&self) -> Option<(u32, VirtualLightKind)> {
+        let endpoint = self
+            .attributes
+            .keys()
+            .filter_map(|key| {
+                let mut parts = key.split('/').map(str::parse::<u32>);
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(ep)), Some(Ok(CLUSTER_ON_OFF)), Some(Ok(ATTR_ON_OFF))) if ep > 0 => {
+                        Some(ep)
+                    }
+                    _ => None,
+                }
+            })
+            .min()?;
+
+        let kind = if self.has(endpoint, CLUSTER_COLOR_CONTROL, ATTR_CURRENT_X) {
+            VirtualLightKind::Color
+        } else if self.has(endpoint, CLUSTER_COLOR_CONTROL, ATTR_COLOR_TEMPERATURE) {
+            VirtualLightKind::Ambiance
+        } else if self.has(endpoint, CLUSTER_LEVEL_CONTROL, ATTR_CURRENT_LEVEL) {
+            VirtualLightKind::White
+        } else {
+            VirtualLightKind::OnOff
+        };
+
+        Some((endpoint, kind))
+    }
+
+    /// Light update with the full state of the light on `endpoint`
+    fn light_state(&self, endpoint: u32) -> LightUpdate {
+        let get = |cluster, attribute| self.attributes.get(&path(endpoint, cluster, attribute));
+
+        let on = get(CLUSTER_ON_OFF, ATTR_ON_OFF)
+            .and_then(Value::as_bool)
+            .map(On::new);
+        let bri = get(CLUSTER_LEVEL_CONTROL, ATTR_CURRENT_LEVEL)
+            .and_then(Value::as_f64)
+            .map(|level| (level / LEVEL_MAX * 100.0).clamp(0.0, 100.0));
+
+        /* lights report both xy and color temperature, but only one of them
+         * is in use */
+        let mode = get(CLUSTER_COLOR_CONTROL, ATTR_COLOR_MODE).and_then(Value::as_u64);
+        let mirek = get(CLUSTER_COLOR_CONTROL, ATTR_COLOR_TEMPERATURE)
+            .filter(|_| mode == Some(COLOR_MODE_TEMPERATURE))
+            .and_then(Value::as_u64)
+            .and_then(|ct| u16::try_from(ct).ok());
+        let coord = |attribute| {
+            get(CLUSTER_COLOR_CONTROL, attribute)
+                .and_then(Value::as_f64)
+                .map(|value| value / XY_SCALE)
+        };
+        let xy = match (coord(ATTR_CURRENT_X), coord(ATTR_CURRENT_Y)) {
+            (Some(x), Some(y)) if mirek.is_none() => Some(XY::new(x, y)),
+            _ => None,
+        };
+
+        LightUpdate::new()
+            .with_on(on)
+            .with_brightness(bri)
+            .with_color_temperature(mirek)
+            .with_color_xy(xy)
+    }
+}
+
+/// Commands (cluster, command name, payload) for a light update
+fn light_commands(upd: &LightUpdate) -> Vec<(u32, &'static str, Value)> {
+    /* matter transition times are in tenths of a second */
+    let transition = upd
+        .dynamics
+        .as_ref()
+        .and_then(|dyn_| dyn_.duration)
+        .map_or(0, |ms| ms / 100);
+    let options = |mut payload: Value| {
+        payload["transitionTime"] = json!(transition);
+        payload["optionsMask"] = json!(0);
+        payload["optionsOverride"] = json!(0);
+        payload
+    };
+
+    let mut cmds = vec![];
+    match upd.on.map(|on| on.on) {
+        Some(false) => return vec![(CLUSTER_ON_OFF, "Off", json!({}))],
+        Some(true) if upd.dimming.is_none() => cmds.push((CLUSTER_ON_OFF, "On", json!({}))),
+        _ => {}
+    }
+
+    if let Some(color) = &upd.color {
+        let coord = |value: f64| (value * XY_SCALE).round().clamp(0.0, 65279.0);
+        cmds.push((
+            CLUSTER_COLOR_CONTROL,
+            "MoveToColor",
+            options(json!({"colorX": coord(color.xy.x), "colorY": coord(color.xy.y)})),
+        ));
+    }
+    if let Some(ct) = &upd.color_temperature {
+        cmds.push((
+            CLUSTER_COLOR_CONTROL,
+            "MoveToColorTemperature",
+            options(json!({"colorTemperatureMireds": ct.mirek})),
+        ));
+    }
+    if let Some(dim) = &upd.dimming {
+        let level = (dim.brightness / 100.0 * LEVEL_MAX)
+            .round()
+            .clamp(1.0, LEVEL_MAX);
+        cmds.push((
+            CLUSTER_LEVEL_CONTROL,
+            "MoveToLevelWithOnOff",
+            options(json!({"level": level})),
+        ));
+    }
+
+    cmds
+}
+
+pub struct MatterBackend {
+    config: MatterConfig,
+    state: Arc<Mutex<Resources>>,
+    /// Known nodes, by node id
+    nodes: BTreeMap<u64, Node>,
+    /// Lights, with their node id and endpoint
+    lights: BTreeMap<Uuid, (u64, u32)>,
+    /// Id of the next command sent to the server
+    message_id: u64,
+}
+
+impl MatterBackend {
+    #[must_use]
+    pub const fn new(config: MatterConfig, state: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            config,
+            state,
+            nodes: BTreeMap::new(),
+            lights: BTreeMap::new(),
+            message_id: 0,
+        }
+    }
+
+    fn light_for_node(&self, node_id: u64) -> Option<(Uuid, u32)> {
+        self.lights
+            .iter()
+            .find(|(_, (node, _))| *node == node_id)
+            .map(|(rid, (_, endpoint))| (*rid, *endpoint))
+    }
+
+    async fn add_node(&mut self, node: Node) -> ApiResult<()> {
+        let node_key = node.node_id.to_string();
+        let key = (KEY, node_key.as_str());
+
+        if let Some((endpoint, kind)) = node.light() {
+            let name = node.name();
+            let conf = VirtualLightConfig {
+                name: name.clone(),
+                model_id: None,
+                kind,
+                gradient: false,
+                count: None,
+                room: None,
+            };
+
+            let mut res = self.state.lock().await;
+            for (link, mut obj) in VirtualBackend::light_resources(&conf, key, &name) {
+                if let Resource::Device(dev) = &mut obj {
+                    dev.product_data = node.product_data();
+                }
+                res.add(&link, obj)?;
+            }
+
+            let link_light = RType::Light.deterministic(key);
+            let upd = node.light_state(endpoint);
+            res.update::<Light>(&link_light.rid, |light| *light += upd)?;
+            drop(res);
+
+            self.lights.insert(link_light.rid, (node.node_id, endpoint));
+            log::debug!("[matter] Added node {} ({name:?})", node.node_id);
+        }
+
+        self.nodes.insert(node.node_id, node);
+
+        Ok(())
+    }
+
+    async fn remove_node(&mut self, node_id: u64) -> ApiResult<()> {
+        self.nodes.remove(&node_id);
+        let Some((rid, _)) = self.light_for_node(node_id) else {
+            return Ok(());
+        };
+        self.lights.remove(&rid);
+
+        let node_key = node_id.to_string();
+        let mut res = self.state.lock().await;
+        for rtype in [
+            RType::ZigbeeConnectivity,
+            RType::Entertainment,
+            RType::Light,
+            RType::Device,
+        ] {
+            let link = rtype.deterministic((KEY, node_key.as_str()));
+            if res.get_resource_by_id(&link.rid).is_ok() {
+                res.delete(&link)?;
+            }
+        }
+        drop(res);
+
+        log::info!("[matter] Removed node {node_id}");
+
+        Ok(())
+    }
+
+    async fn attribute_updated(&mut self, data: &Value) -> ApiResult<()> {
+        let (Some(node_id), Some(key), Some(value)) = (
+            data.get(0).and_then(Value::as_u64),
+            data.get(1).and_then(Value::as_str),
+            data.get(2),
+        ) else {
+            return Ok(());
+        };
+
+        let Some(node) = self.nodes.get_mut(&node_id) else {
+            return Ok(());
+        };
+        node.attributes.insert(key.to_string(), value.clone());
+
+        let Some((rid, endpoint)) = self.light_for_node(node_id) else {
+            return Ok(());
+        };
+        let relevant = [CLUSTER_ON_OFF, CLUSTER_LEVEL_CONTROL, CLUSTER_COLOR_CONTROL]
+            .iter()
+            .any(|cluster| key.starts_with(&format!("{endpoint}/{cluster}/")));
+        if !relevant {
+            return Ok(());
+        }
+        let upd = self.nodes[&node_id].light_state(endpoint);
+
+        self.state
+            .lock()
+            .await
+            .update::<Light>(&rid, |light| *light += upd)
+    }
+
+    async fn handle_event(&mut self, event: &str, data: Value) -> ApiResult<()> {
+        match event {
+            "attribute_updated" => self.attribute_updated(&data).await,
+            "node_added" | "node_updated" => {
+                let node: Node = serde_json::from_value(data)?;
+                if event == "node_added" {
+                    log::info!("[matter] Node {} added", node.node_id);
+                }
+                self.add_node(node).await
+            }
+            "node_removed" => match data.as_u64() {
+                Some(node_id) => self.remove_node(node_id).await,
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    async fn handle_result(&mut self, msg: Value) -> ApiResult<()> {
+        let message_id = msg.get("message_id").cloned().unwrap_or_default();
+
+        if let Some(code) = msg.get("error_code") {
+            log::warn!(
+                "[matter] Command {message_id} failed ({code}): {}",
+                msg.get("details").unwrap_or(&Value::Null)
+            );
+            return Ok(());
+        }
+
+        if message_id.as_str() != Some(START_LISTENING) {
+            return Ok(());
+        }
+
+        let nodes: Vec<Node> =
+            serde_json::from_value(msg.get("result").cloned().unwrap_or_default())?;
+        let count = nodes.len();
+        for node in nodes {
+            self.add_node(node).await?;
+        }
+        backend::set_connected(&self.state, KEY, self.lights.keys().copied(), true).await;
+
+        log::info!(
+            "[matter] Found {} lights on {count} nodes",
+            self.lights.len()
+        );
+
+        Ok(())
+    }
+
+    async fn websocket_read(&mut self, pkt: tungstenite::Message) -> ApiResult<()> {
+        let tungstenite::Message::Text(txt) = pkt else {
+            return Ok(());
+        };
+
+        let mut msg: Value = serde_json::from_str(&txt)?;
+        if let Some(event) = msg.get("event").and_then(Value::as_str).map(String::from) {
+            let data = msg.get_mut("data").map(Value::take).unwrap_or_default();
+            return self.handle_event(&event, data).await;
+        }
+        if msg.get("message_id").is_some() {
+            return self.handle_result(msg).await;
+        }
+        if let Some(version) = msg.get("sdk_version") {
+            log::info!("[matter] Connected to matter server (sdk {version})");
+        }
+
+        Ok(())
+    }
+
+    async fn websocket_send(
+        &mut self,
+        socket: &mut Socket,
+        command: &str,
+        args: Value,
+    ) -> ApiResult<()> {
+        self.message_id += 1;
+        let msg = json!({
+            "message_id": self.message_id.to_string(),
+            "command": command,
+            "args": args,
+        });
+
+        let json = serde_json::to_string(&msg)?;
+        log::debug!("[matter] Sending {json}");
+        Ok(socket.send(tungstenite::Message::text(json)).await?)
+    }
+
+    async fn device_command(
+        &mut self,
+        socket: &mut Socket,
+        (node_id, endpoint): (u64, u32),
+        (cluster, command, payload): (u32, &str, Value),
+    ) -> ApiResult<()> {
+        let args = json!({
+            "node_id": node_id,
+            "endpoint_id": endpoint,
+            "cluster_id": cluster,
+            "command_name": command,
+            "payload": payload,
+        });
+        self.websocket_send(socket, "device_command", args).await
+    }
+
+    /// Node and endpoint of a light, or the device it belongs to
+    fn find_light(&self, link: &ResourceLink) -> Option<(u64, u32)> {
+        self.lights.iter().find_map(|(rid, (node_id, endpoint))| {
+            let device = RType::Device.deterministic((KEY, node_id.to_string().as_str()));
+            (*rid == link.rid || device == *link).then_some((*node_id, *endpoint))
+        })
+    }
+
+    async fn websocket_write(
+        &mut self,
+        socket: &mut Socket,
+        req: &BackendRequest,
+    ) -> ApiResult<()> {
+        match req {
+            BackendRequest::Commission(code) => {
+                log::info!("[matter] Commissioning new device");
+                let args = json!({"code": code, "network_only": false});
+                return self
+                    .websocket_send(socket, "commission_with_code", args)
+                    .await;
+            }
+            BackendRequest::Identify(link, _) => {
+                if let Some(target) = self.find_light(link) {
+                    let cmd = (CLUSTER_IDENTIFY, "Identify", json!({"identifyTime": 3}));
+                    self.device_command(socket, target, cmd).await?;
+                }
+                return Ok(());
+            }
+            BackendRequest::Delete(link) if link.rtype == RType::Device => {
+                if let Some((node_id, _)) = self.find_light(link) {
+                    log::info!("[matter] Removing node {node_id} from the fabric");
+                    let args = json!({"node_id": node_id});
+                    self.websocket_send(socket, "remove_node", args).await?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let lock = self.state.lock().await;
+        let updates = backend::light_updates(&lock, req, |rid| self.lights.contains_key(rid))?;
+        drop(lock);
+
+        for (rid, upd) in updates {
+            let Some(target) = self.lights.get(&rid).copied() else {
+                continue;
+            };
+            for cmd in light_commands(&upd) {
+                self.device_command(socket, target, cmd).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn event_loop(
+        &mut self,
+        chan: &mut Receiver<Arc<BackendRequest>>,
+        mut socket: Socket,
+    ) -> ApiResult<()> {
+        let start = json!({"message_id": START_LISTENING, "command": "start_listening"});
+        socket
+            .send(tungstenite::Message::text(serde_json::to_string(&start)?))
+            .await?;
+
+        loop {
+            tokio::select! {
+                pkt = chan.recv() => {
+                    let req = match pkt {
+                        Ok(req) => req,
+                        Err(RecvError::Lagged(count)) => {
+                            log::warn!("[matter] Backend lagging, skipped {count} requests");
+                            continue;
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+                    if let Err(err) = self.websocket_write(&mut socket, &req).await {
+                        log::error!("[matter] Failed to handle request: {err}");
+                    }
+                }
+                pkt = socket.next() => {
+                    let pkt = pkt.ok_or(ApiError::UnexpectedMatterEof)??;
+                    if let Err(err) = self.websocket_read(pkt).await {
+                        log::error!("[matter] Failed to handle message: {err}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for MatterBackend {
+    async fn run_forever(mut self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        loop {
+            log::info!("[matter] Connecting to {}", self.config.url);
+            self.state.lock().await.backend_connect_attempt(KEY);
+            match connect_async(self.config.url.as_str()).await {
+                Ok((socket, _)) => {
+                    if let Err(err) = self.event_loop(&mut chan, socket).await {
+                        log::error!("[matter] Event loop broke: {err}");
+                        self.state.lock().await.set_backend_error(KEY, &err);
+                    }
+                }
+                Err(err) => {
+                    log::error!("[matter] Connect failed: {err:?}");
+                    self.state.lock().await.set_backend_error(KEY, &err);
+                }
+            }
+            backend::set_connected(&self.state, KEY, self.lights.keys().copied(), false).await;
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hue::api::{LightUpdate, On};
+    use hue::xy::XY;
+    use serde_json::{json, Value};
+
+    use crate::backend::matter::{light_commands, Node};
+    use crate::config::VirtualLightKind;
+
+    fn node(attributes: Value) -> Node {
+        Node {
+            node_id: 7,
+            attributes: serde_json::from_value(attributes).unwrap(),
+        }
+    }
+
+    #[test]
+    fn on_off_light() {
+        let node = node(json!({"1/6/0": true}));
+        assert_eq!(node.light(), Some((1, VirtualLightKind::OnOff)));
+    }
+
+    #[test]
+    fn dimmable_light() {
+        let node = node(json!({"1/6/0": true, "1/8/0": 254}));
+        assert_eq!(node.light(), Some((1, VirtualLightKind::White)));
+    }
+
+    #[test]
+    fn ambiance_light() {
+        let node = node(json!({"1/6/0": true, "1/8/0": 254, "1/768/7": 370}));
+        assert_eq!(node.light(), Some((1, VirtualLightKind::Ambiance)));
+    }
+
+    #[test]
+    fn color_light() {
+        let node = node(json!({
+            "1/6/0": true,
+            "1/8/0": 254,
+            "1/768/3": 20000,
+            "1/768/4": 20000,
+            "1/768/7": 370,
+        }));
+        assert_eq!(node.light(), Some((1, VirtualLightKind::Color)));
+    }
+
+    #[test]
+    fn lowest_endpoint_is_used() {
+        /* endpoint 0 is the root node, which is never a light */
+        let node = node(json!({"0/6/0": true, "3/6/0": true, "2/6/0": false, "3/8/0": 1}));
+        assert_eq!(node.light(), Some((2, VirtualLightKind::OnOff)));
+    }
+
+    #[test]
+    fn clusters_on_other_endpoints_are_ignored() {
+        let node = node(json!({"1/6/0": true, "2/8/0": 254, "2/768/3": 0}));
+        assert_eq!(node.light(), Some((1, VirtualLightKind::OnOff)));
+    }
+
+    #[test]
+    fn not_a_light() {
+        let node = node(json!({"0/40/1": "Acme", "1/1026/0": 2150}));
+        assert_eq!(node.light(), None);
+    }
+
+    #[test]
+    fn name() {
+        assert_eq!(node(json!({})).name(), "Matter node 7");
+        assert_eq!(node(json!({"0/40/3": "Bulb"})).name(), "Bulb");
+        assert_eq!(
+            node(json!({"0/40/3": "Bulb", "0/40/5": "Desk"})).name(),
+            "Desk"
+        );
+        assert_eq!(node(json!({"0/40/3": "Bulb", "0/40/5": ""})).name(), "Bulb");
+    }
+
+    #[test]
+    fn state_in_temperature_mode() {
+        let node = node(json!({
+            "1/6/0": true,
+            "1/8/0": 127,
+            "1/768/3": 20000,
+            "1/768/4": 20000,
+            "1/768/7": 370,
+            "1/768/8": 2,
+        }));
+        let upd = node.light_state(1);
+
+        assert_eq!(upd.on, Some(On::new(true)));
+        assert!((upd.dimming.unwrap().brightness - 50.0).abs() < f64::EPSILON);
+        assert_eq!(upd.color_temperature.unwrap().mirek, 370);
+        assert!(upd.color.is_none());
+    }
+
+    #[test]
+    fn state_in_xy_mode() {
+        let node = node(json!({
+            "1/6/0": false,
+            "1/8/0": 254,
+            "1/768/3": 32768,
+            "1/768/4": 16384,
+            "1/768/7": 370,
+            "1/768/8": 1,
+        }));
+        let upd = node.light_state(1);
+
+        assert_eq!(upd.on, Some(On::new(false)));
+        assert!((upd.dimming.unwrap().brightness - 100.0).abs() < f64::EPSILON);
+        assert!(upd.color_temperature.is_none());
+        assert_eq!(upd.color.unwrap().xy, XY::new(0.5, 0.25));
+    }
+
+    #[test]
+    fn commands_off() {
+        let upd = LightUpdate::new()
+            .with_on(On::new(false))
+            .with_brightness(Some(50.0));
+        let cmds = light_commands(&upd);
+
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].0, 0x0006);
+        assert_eq!(cmds[0].1, "Off");
+    }
+
+    #[test]
+    fn commands_on() {
+        let cmds = light_commands(&LightUpdate::new().with_on(On::new(true)));
+
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].1, "On");
+    }
+
+    #[test]
+    fn commands_on_with_brightness() {
+        /* turning on is part of MoveToLevelWithOnOff */
+        let upd = LightUpdate::new()
+            .with_on(On::new(true))
+            .with_brightness(Some(50.0))
+            .with_duration(Some(400));
+        let cmds = light_commands(&upd);
+
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].0, 0x0008);
+        assert_eq!(cmds[0].1, "MoveToLevelWithOnOff");
+        assert_eq!(cmds[0].2["level"], 127.0);
+        assert_eq!(cmds[0].2["transitionTime"], 4);
+    }
+
+    #[test]
+    fn commands_color() {
+        let upd = LightUpdate::new()
+            .with_color_xy(XY::new(0.5, 0.25))
+            .with_color_temperature(250);
+        let cmds = light_commands(&upd);
+
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0].0, 0x0300);
+        assert_eq!(cmds[0].1, "MoveToColor");
+        assert_eq!(cmds[0].2["colorX"], 32768.0);
+        assert_eq!(cmds[0].2["colorY"], 16384.0);
+        assert_eq!(cmds[1].1, "MoveToColorTemperature");
+        assert_eq!(cmds[1].2["colorTemperatureMireds"], 250);
+    }
+}
@@ -1,15 +1,36 @@
+pub mod coalesce;
+pub mod coordinator;
+pub mod matter;
+pub mod mqttlight;
+pub mod remote;
+pub mod sink;
+pub mod traffic;
+pub mod virt;
 pub mod z2m;
+pub mod zwave;
 
+use std::collections::BTreeSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
-use hue::api::{GroupedLightUpdate, LightUpdate, ResourceLink, Scene, SceneUpdate};
+use hue::api::{
+    GroupedLight, GroupedLightUpdate, LightUpdate, ResourceLink, Scene, SceneUpdate,
+    ZigbeeConnectivityStatus,
+};
 use hue::stream::HueStreamLights;
+use hue::zigbee::ZigbeeMessage;
 
-use crate::error::ApiResult;
+use crate::error::{ApiError, ApiResult};
+use crate::resource::Resources;
 
 #[derive(Clone, Debug)]
 pub enum BackendRequest {
@@ -22,12 +43,207 @@ pub enum BackendRequest {
 
     Delete(ResourceLink),
 
+    /// Make a device (or light, or group of lights) visibly identify itself
+    Identify(ResourceLink, IdentifyEffect),
+
+    /// Entertainment requests, for the given entertainment area. Multiple
+    /// areas can stream at the same time, if they have no lights in common.
     EntertainmentStart(Uuid),
-    EntertainmentFrame(HueStreamLights),
-    EntertainmentStop(),
+    EntertainmentFrame(Uuid, HueStreamLights),
+    EntertainmentStop(Uuid),
+
+    /// Send a raw zigbee command to a device (or group of lights)
+    ZigbeeRaw(ResourceLink, ZigbeeMessage),
+
+    /// Add devices back to the zigbee groups they were dropped from
+    GroupReconcile,
+
+    /// Commission a new device, with its setup code (a matter `MT:` qr
+    /// code, or manual pairing code)
+    Commission(String),
+
+    /// Allow new zigbee devices to join for the given number of seconds (or
+    /// stop allowing it, with 0)
+    PermitJoin(u8),
+
+    /// Touchlink factory reset of the closest device that supports it (e.g.
+    /// a bulb stuck on another network), so it can be paired again
+    Touchlink,
+}
+
+/// Raw zigbee message received by a backend
+#[derive(Clone, Debug, Serialize)]
+pub struct ZigbeeFrame {
+    pub timestamp: DateTime<Utc>,
+    /// Name of the backend that received the message
+    pub backend: String,
+    pub device: String,
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub cluster: String,
+    pub endpoint: Option<u8>,
+    pub data: Value,
+}
+
+/// Outcome of a light update, for backends that can tell whether the device
+/// accepted it
+#[derive(Clone, Debug)]
+pub struct CommandResult {
+    pub link: ResourceLink,
+    /// Reason the command failed, if it did
+    pub error: Option<String>,
+}
+
+/// A device that is missing from a zigbee group it used to be a member of
+/// (usually because it was paired again)
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GroupDrift {
+    pub group: String,
+    pub device: String,
+    pub ieee_address: String,
+}
+
+/// Devices that a backend assigned to one bridge, when sharding
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ShardSummary {
+    pub lights: usize,
+    pub rooms: BTreeSet<String>,
+    pub devices: BTreeSet<String>,
+}
+
+/// Connection status of a backend
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BackendStatus {
+    /// Connected, and has received its initial state
+    pub ready: bool,
+    /// Time of the last change of `ready`
+    pub since: Option<DateTime<Utc>>,
+    /// Number of attempts to connect (including the first one)
+    pub connect_attempts: u64,
+    /// Number of times the backend became ready
+    pub connections: u64,
+    /// Most recent connection error
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdentifyEffect {
+    /// Short blink (v2 `identify`, v1 `alert: select`)
+    Blink,
+    /// Longer breathing effect (v1 `alert: lselect`)
+    Breathe,
 }
 
 #[async_trait]
 pub trait Backend {
     async fn run_forever(self, chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()>;
 }
+
+/// Mark a backend as (dis)connected, along with the devices of `services`,
+/// so clients can tell which devices cannot be reached
+pub async fn set_connected(
+    state: &Mutex<Resources>,
+    name: &str,
+    services: impl IntoIterator<Item = Uuid> + Send,
+    connected: bool,
+) {
+    let status = if connected {
+        ZigbeeConnectivityStatus::Connected
+    } else {
+        ZigbeeConnectivityStatus::ConnectivityIssue
+    };
+
+    let mut lock = state.lock().await;
+    lock.set_backend_ready(name, connected);
+    if let Err(err) = lock.set_connectivity(services, status) {
+        log::error!("[{name}] Failed to update device connectivity: {err}");
+    }
+}
+
+/// Extra time to wait for command results, so the backend's own timeout
+/// (which it reports with a better error) runs out first
+const CONFIRM_MARGIN: Duration = Duration::from_secs(1);
+
+/// Wait until the backend reports the outcome of a command sent to `link`,
+/// for a little longer than `timeout`
+pub async fn confirm_command(
+    mut results: Receiver<Arc<CommandResult>>,
+    link: ResourceLink,
+    timeout: Duration,
+) -> ApiResult<()> {
+    let outcome = async {
+        loop {
+            match results.recv().await {
+                Ok(res) if res.link == link => {
+                    return res
+                        .error
+                        .clone()
+                        .map_or(Ok(()), |err| Err(ApiError::CommandFailed(err)));
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout + CONFIRM_MARGIN, outcome)
+        .await
+        .map_err(|_| ApiError::CommandTimeout)?
+}
+
+/// Light updates that a request amounts to, for the lights selected by
+/// `ours`. For backends that control their lights one at a time, without
+/// groups or scenes of their own.
+pub fn light_updates(
+    res: &Resources,
+    req: &BackendRequest,
+    ours: impl Fn(&Uuid) -> bool,
+) -> ApiResult<Vec<(Uuid, LightUpdate)>> {
+    let mut found = vec![];
+    match req {
+        BackendRequest::LightUpdate(link, upd) => {
+            if ours(&link.rid) {
+                found.push((link.rid, upd.clone()));
+            }
+        }
+        BackendRequest::GroupedLightUpdate(link, upd) => {
+            let owner = res.get::<GroupedLight>(link)?.owner;
+            for (rid, light) in res.group_lights(&owner) {
+                if ours(&rid) {
+                    found.push((rid, upd.for_light(light)));
+                }
+            }
+        }
+        BackendRequest::SceneUpdate(link, upd) => {
+            let Some(recall) = &upd.recall else {
+                return Ok(found);
+            };
+            for act in &res.get::<Scene>(link)?.actions {
+                if !ours(&act.target.rid) {
+                    continue;
+                }
+                let upd = LightUpdate {
+                    on: act.action.on,
+                    dimming: recall.dimming.or(act.action.dimming),
+                    color: act.action.color,
+                    color_temperature: act.action.color_temperature,
+                    ..LightUpdate::default()
+                };
+                found.push((act.target.rid, upd));
+            }
+        }
+        BackendRequest::SceneCreate(..)
+        | BackendRequest::Delete(_)
+        | BackendRequest::Identify(..)
+        | BackendRequest::EntertainmentStart(_)
+        | BackendRequest::EntertainmentFrame(..)
+        | BackendRequest::EntertainmentStop(_)
+        | BackendRequest::ZigbeeRaw(..)
+        | BackendRequest::GroupReconcile
+        | BackendRequest::Commission(_)
+        | BackendRequest::PermitJoin(_)
+        | BackendRequest::Touchlink => {}
+    }
+
+    Ok(found)
+}
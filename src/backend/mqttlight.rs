@@ -0,0 +1,351 @@
+//! Wi-Fi lights controlled over mqtt
+//!
+//! Covers the long tail of lights that are not zigbee, and have no backend
+//! of their own, as long as they can be controlled with json over mqtt.
+//! Two message formats are supported:
+//!
+//! - `tasmota`: commands are sent as a `Backlog` (e.g. `Power ON;Dimmer
+//!   50`), so the command topic is usually `cmnd/<topic>/Backlog`. Results
+//!   (`stat/<topic>/RESULT`) are json, with `POWER`, `Dimmer`, `CT` and
+//!   `Color` fields.
+//! - `home_assistant`: the Home Assistant json schema, with `state`,
+//!   `brightness` (0-255), `color_temp` (mirek), `color` (`{x, y}`) and
+//!   `transition` (seconds) fields, for both commands and state.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Packet, QoS};
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+
+use hue::api::{Light, LightUpdate, On, RType};
+use hue::xy::XY;
+
+use crate::backend::virt::VirtualBackend;
+use crate::backend::{self, Backend, BackendRequest};
+use crate::config::{MqttLightConfig, MqttLightSchema, MqttLightsConfig, VirtualLightConfig};
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// Prefix for resource ids of mqtt lights
+const KEY: &str = "mqtt-light";
+
+/// Name of the backend, for its connection status
+const NAME: &str = "mqtt-lights";
+
+/// Brightness from a 0-255 value
+fn brightness_from_u8(value: f64) -> f64 {
+    (value / 255.0 * 100.0).clamp(0.0, 100.0)
+}
+
+/// Light update from a tasmota result
+fn parse_tasmota(obj: &Map<String, Value>) -> LightUpdate {
+    let on = obj
+        .get("POWER")
+        .and_then(Value::as_str)
+        .map(|power| On::new(power.eq_ignore_ascii_case("on")));
+    let bri = obj.get("Dimmer").and_then(Value::as_f64);
+    let mirek = obj
+        .get("CT")
+        .and_then(Value::as_u64)
+        .and_then(|ct| u16::try_from(ct).ok());
+    let xy = obj
+        .get("Color")
+        .and_then(Value::as_str)
+        .and_then(|color| color.get(..6))
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .map(|rgb| {
+            let [_, red, green, blue] = rgb.to_be_bytes();
+            XY::from_rgb(red, green, blue).0
+        });
+
+    LightUpdate::new()
+        .with_on(on)
+        .with_brightness(bri)
+        .with_color_temperature(mirek)
+        .with_color_xy(xy)
+}
+
+/// Light update from a home assistant json state
+fn parse_home_assistant(obj: &Map<String, Value>) -> LightUpdate {
+    let on = obj
+        .get("state")
+        .and_then(Value::as_str)
+        .map(|state| On::new(state.eq_ignore_ascii_case("on")));
+    let bri = obj
+        .get("brightness")
+        .and_then(Value::as_f64)
+        .map(brightness_from_u8);
+    let mirek = obj
+        .get("color_temp")
+        .and_then(Value::as_u64)
+        .and_then(|ct| u16::try_from(ct).ok());
+    let xy = obj.get("color").and_then(|color| {
+        let x = color.get("x")?.as_f64()?;
+        let y = color.get("y")?.as_f64()?;
+        Some(XY::new(x, y))
+    });
+
+    LightUpdate::new()
+        .with_on(on)
+        .with_brightness(bri)
+        .with_color_temperature(mirek)
+        .with_color_xy(xy)
+}
+
+/// Tasmota `Backlog` command for a light update
+fn tasmota_command(upd: &LightUpdate) -> String {
+    let mut cmds = vec![];
+    if let Some(on) = upd.on {
+        cmds.push(format!("Power {}", if on.on { "ON" } else { "OFF" }));
+    }
+    if let Some(color) = &upd.color {
+        let [red, green, blue] = color.xy.to_rgb(255.0);
+        cmds.push(format!("Color2 {red:02X}{green:02X}{blue:02X}"));
+    }
+    if let Some(ct) = &upd.color_temperature {
+        cmds.push(format!("CT {}", ct.mirek));
+    }
+    if let Some(dim) = &upd.dimming {
+        cmds.push(format!(
+            "Dimmer {}",
+            dim.brightness.round().clamp(0.0, 100.0)
+        ));
+    }
+    cmds.join(";")
+}
+
+/// Home assistant json command for a light update
+fn home_assistant_command(upd: &LightUpdate) -> Value {
+    let mut obj = Map::new();
+    if let Some(on) = upd.on {
+        obj.insert("state".into(), json!(if on.on { "ON" } else { "OFF" }));
+    }
+    if let Some(dim) = &upd.dimming {
+        let bri = (dim.brightness / 100.0 * 255.0).round().clamp(0.0, 255.0);
+        obj.insert("brightness".into(), json!(bri));
+    }
+    if let Some(ct) = &upd.color_temperature {
+        obj.insert("color_temp".into(), json!(ct.mirek));
+    }
+    if let Some(color) = &upd.color {
+        obj.insert("color".into(), json!({"x": color.xy.x, "y": color.xy.y}));
+    }
+    if let Some(duration) = upd.dynamics.as_ref().and_then(|dyn_| dyn_.duration) {
+        obj.insert("transition".into(), json!(f64::from(duration) / 1000.0));
+    }
+    Value::Object(obj)
+}
+
+pub struct MqttLightBackend {
+    config: MqttLightsConfig,
+    state: Arc<Mutex<Resources>>,
+    /// Configured lights, by light id
+    lights: BTreeMap<Uuid, MqttLightConfig>,
+}
+
+impl MqttLightBackend {
+    #[must_use]
+    pub fn new(config: MqttLightsConfig, state: Arc<Mutex<Resources>>) -> Self {
+        let lights = config
+            .lights
+            .iter()
+            .map(|light| {
+                let link = RType::Light.deterministic((KEY, light.name.as_str()));
+                (link.rid, light.clone())
+            })
+            .collect();
+
+        Self {
+            config,
+            state,
+            lights,
+        }
+    }
+
+    async fn add_lights(&self) -> ApiResult<()> {
+        let mut res = self.state.lock().await;
+        for light in self.lights.values() {
+            let conf = VirtualLightConfig {
+                name: light.name.clone(),
+                model_id: light.model_id.clone(),
+                kind: light.kind,
+                gradient: false,
+                count: None,
+                room: None,
+            };
+
+            let key = (KEY, light.name.as_str());
+            for (link, obj) in VirtualBackend::light_resources(&conf, key, &light.name) {
+                res.add(&link, obj)?;
+            }
+        }
+        drop(res);
+
+        Ok(())
+    }
+
+    /// Apply a state message from a light
+    async fn handle_state(&self, topic: &str, payload: &[u8]) -> ApiResult<()> {
+        let found = self
+            .lights
+            .iter()
+            .filter(|(_, light)| light.state_topic.as_deref() == Some(topic));
+
+        for (rid, light) in found {
+            let Ok(Value::Object(obj)) = serde_json::from_slice(payload) else {
+                log::debug!("[mqtt-light] Ignoring non-json state from {topic}");
+                return Ok(());
+            };
+
+            let upd = match light.schema {
+                MqttLightSchema::Tasmota => parse_tasmota(&obj),
+                MqttLightSchema::HomeAssistant => parse_home_assistant(&obj),
+            };
+
+            self.state
+                .lock()
+                .await
+                .update::<Light>(rid, |light| *light += upd)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send commands for a backend request, if it concerns any of our lights
+    async fn handle_request(&self, client: &AsyncClient, req: &BackendRequest) -> ApiResult<()> {
+        let lock = self.state.lock().await;
+        let updates = backend::light_updates(&lock, req, |rid| self.lights.contains_key(rid))?;
+        drop(lock);
+
+        for (rid, upd) in updates {
+            let Some(light) = self.lights.get(&rid) else {
+                continue;
+            };
+
+            let payload = match light.schema {
+                MqttLightSchema::Tasmota => tasmota_command(&upd).into_bytes(),
+                MqttLightSchema::HomeAssistant => {
+                    serde_json::to_vec(&home_assistant_command(&upd))?
+                }
+            };
+            if payload.is_empty() {
+                continue;
+            }
+
+            client
+                .publish(&light.command_topic, QoS::AtLeastOnce, false, payload)
+                .await?;
+
+            /* without a state topic, assume the light did as it was told */
+            if light.state_topic.is_none() {
+                self.state
+                    .lock()
+                    .await
+                    .update::<Light>(&rid, |light| *light += upd)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for MqttLightBackend {
+    async fn run_forever(self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        self.add_lights().await?;
+
+        let mut opts = MqttOptions::new(
+            format!("bifrost-lights-{}", Uuid::new_v4().simple()),
+            &self.config.host,
+            self.config.port,
+        );
+        opts.set_keep_alive(Duration::from_secs(30));
+        if let (Some(user), Some(pass)) = (&self.config.username, &self.config.password) {
+            opts.set_credentials(user, pass);
+        }
+
+        let (client, mut eventloop) = AsyncClient::new(opts, 64);
+
+        let topics: Vec<String> = self
+            .lights
+            .values()
+            .filter_map(|light| light.state_topic.clone())
+            .collect();
+
+        // subscribe again on every new connection, and pass on messages
+        let (tx, mut rx) = mpsc::channel(64);
+        let subscriber = client.clone();
+        let state = self.state.clone();
+        let lights: Vec<Uuid> = self.lights.keys().copied().collect();
+        let task = tokio::spawn(async move {
+            let mut connecting = true;
+            loop {
+                if connecting {
+                    state.lock().await.backend_connect_attempt(NAME);
+                    connecting = false;
+                }
+                match eventloop.poll().await {
+                    Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                        for topic in &topics {
+                            if let Err(err) = subscriber.subscribe(topic, QoS::AtLeastOnce).await {
+                                log::error!("[mqtt-light] Failed to subscribe to {topic}: {err}");
+                            }
+                        }
+                        backend::set_connected(&state, NAME, lights.clone(), true).await;
+                    }
+                    Ok(MqttEvent::Incoming(Packet::Publish(msg))) => {
+                        if tx.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::warn!("[mqtt-light] {err}");
+                        state.lock().await.set_backend_error(NAME, &err);
+                        backend::set_connected(&state, NAME, lights.clone(), false).await;
+                        connecting = true;
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+
+        log::info!(
+            "Controlling {} mqtt lights through mqtt://{}:{}",
+            self.lights.len(),
+            self.config.host,
+            self.config.port
+        );
+
+        let res = loop {
+            tokio::select! {
+                Some(msg) = rx.recv() => {
+                    if let Err(err) = self.handle_state(&msg.topic, &msg.payload).await {
+                        log::error!("[mqtt-light] Failed to handle state: {err}");
+                    }
+                }
+                req = chan.recv() => match req {
+                    Ok(req) => {
+                        if let Err(err) = self.handle_request(&client, &req).await {
+                            log::error!("[mqtt-light] Failed to handle request: {err}");
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        log::warn!("[mqtt-light] Backend lagging, skipped {count} requests");
+                    }
+                    Err(err) => break Err(err.into()),
+                },
+            }
+        };
+
+        task.abort();
+        res
+    }
+}
@@ -0,0 +1,391 @@
+//! Out-of-process backends (plugins)
+//!
+//! A plugin is a separate program, in any language, that adds lights from
+//! some other system. It listens on a unix socket, and bifrost connects to
+//! it. Both sides send newline-delimited json-rpc 2.0 messages.
+//!
+//! Plugin to bifrost:
+//!
+//! - `add_light` `{id, name, kind?, model_id?, gradient?}`: announce a light
+//!   (`kind` is one of `on_off`, `white`, `ambiance` and `color`). Plugins
+//!   announce all their lights every time bifrost connects.
+//! - `light_state` `{id, state}`: report the state of a light, as a hue
+//!   light update (e.g. `{"on": {"on": true}}`)
+//! - `remove` `{id}`: remove a light
+//!
+//! If a message from the plugin has an `id`, bifrost sends a reply.
+//!
+//! Bifrost to plugin (notifications):
+//!
+//! - `light_update` `{id, update}`: change a light, with a hue light update
+//! - `identify` `{id}`: make a light identify itself
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use hue::api::{Light, LightUpdate, RType, ResourceLink, ZigbeeConnectivityStatus};
+
+use crate::backend::virt::VirtualBackend;
+use crate::backend::{self, Backend, BackendRequest};
+use crate::config::{RemoteBackendConfig, VirtualLightConfig, VirtualLightKind};
+use crate::error::{ApiError, ApiResult};
+use crate::resource::Resources;
+
+/// json-rpc error codes
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const INTERNAL_ERROR: i32 = -32603;
+
+#[derive(Debug, Deserialize)]
+struct RpcMessage {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn params<T: DeserializeOwned>(params: Value) -> Result<T, Self> {
+        serde_json::from_value(params).map_err(|err| Self {
+            code: INVALID_PARAMS,
+            message: err.to_string(),
+        })
+    }
+}
+
+impl From<ApiError> for RpcError {
+    fn from(err: ApiError) -> Self {
+        Self {
+            code: INTERNAL_ERROR,
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AddLight {
+    id: String,
+    name: String,
+    #[serde(default)]
+    kind: VirtualLightKind,
+    model_id: Option<String>,
+    #[serde(default)]
+    gradient: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LightState {
+    id: String,
+    state: LightUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+struct Remove {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LightUpdateParams<'a> {
+    id: &'a str,
+    update: &'a LightUpdate,
+}
+
+pub struct RemoteBackend {
+    name: String,
+    /// Prefix for resource ids, so lights of different plugins never clash
+    key: String,
+    config: RemoteBackendConfig,
+    state: Arc<Mutex<Resources>>,
+    /// Lights announced by the plugin, with their plugin ids
+    lights: BTreeMap<Uuid, String>,
+}
+
+impl RemoteBackend {
+    #[must_use]
+    pub fn new(name: String, config: RemoteBackendConfig, state: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            key: format!("remote-{name}"),
+            name,
+            config,
+            state,
+            lights: BTreeMap::new(),
+        }
+    }
+
+    fn light_link(&self, id: &str) -> ResourceLink {
+        RType::Light.deterministic((self.key.as_str(), id))
+    }
+
+    async fn add_light(&mut self, req: AddLight) -> ApiResult<()> {
+        let conf = VirtualLightConfig {
+            name: req.name.clone(),
+            model_id: req.model_id,
+            kind: req.kind,
+            gradient: req.gradient,
+            count: None,
+            room: None,
+        };
+
+        let key = (self.key.as_str(), req.id.as_str());
+        let resources = VirtualBackend::light_resources(&conf, key, &req.name);
+
+        let mut res = self.state.lock().await;
+        for (link, obj) in resources {
+            res.add(&link, obj)?;
+        }
+        let light = self.light_link(&req.id);
+        res.set_connectivity([light.rid], ZigbeeConnectivityStatus::Connected)?;
+        drop(res);
+
+        log::debug!("[{}] Added light {:?} ({})", self.name, req.name, req.id);
+        self.lights.insert(light.rid, req.id);
+
+        Ok(())
+    }
+
+    async fn light_state(&self, req: LightState) -> ApiResult<()> {
+        let link = self.light_link(&req.id);
+        if !self.lights.contains_key(&link.rid) {
+            log::warn!("[{}] State for unknown light {:?}", self.name, req.id);
+            return Ok(());
+        }
+
+        self.state
+            .lock()
+            .await
+            .update::<Light>(&link.rid, |light| *light += req.state)
+    }
+
+    async fn remove(&mut self, req: Remove) -> ApiResult<()> {
+        let link = self.light_link(&req.id);
+        if self.lights.remove(&link.rid).is_none() {
+            return Ok(());
+        }
+
+        let key = (self.key.as_str(), req.id.as_str());
+        let mut res = self.state.lock().await;
+        for rtype in [
+            RType::ZigbeeConnectivity,
+            RType::Entertainment,
+            RType::Light,
+            RType::Device,
+        ] {
+            let link = rtype.deterministic(key);
+            if res.get_resource_by_id(&link.rid).is_ok() {
+                res.delete(&link)?;
+            }
+        }
+        drop(res);
+
+        log::debug!("[{}] Removed light {:?}", self.name, req.id);
+
+        Ok(())
+    }
+
+    async fn call(&mut self, method: &str, params: Value) -> Result<(), RpcError> {
+        match method {
+            "add_light" => self.add_light(RpcError::params(params)?).await?,
+            "light_state" => self.light_state(RpcError::params(params)?).await?,
+            "remove" => self.remove(RpcError::params(params)?).await?,
+            _ => {
+                return Err(RpcError {
+                    code: METHOD_NOT_FOUND,
+                    message: format!("unknown method {method:?}"),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a message from the plugin. Returns the reply, if one is
+    /// needed.
+    async fn handle_message(&mut self, line: &str) -> Option<Value> {
+        let msg: RpcMessage = match serde_json::from_str(line) {
+            Ok(msg) => msg,
+            Err(err) => {
+                log::warn!("[{}] Invalid message from plugin: {err}", self.name);
+                return None;
+            }
+        };
+
+        let result = self.call(&msg.method, msg.params).await;
+        if let Err(err) = &result {
+            log::warn!(
+                "[{}] Plugin call {:?} failed: {}",
+                self.name,
+                msg.method,
+                err.message
+            );
+        }
+
+        let id = msg.id?;
+        Some(match result {
+            Ok(()) => json!({"jsonrpc": "2.0", "id": id, "result": null}),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": err.code, "message": err.message},
+            }),
+        })
+    }
+
+    /// Notifications for the plugin, for a backend request (if it concerns
+    /// any of its lights)
+    async fn notifications(&self, req: &BackendRequest) -> ApiResult<Vec<Value>> {
+        let notify = |method: &str, params: Value| json!({"jsonrpc": "2.0", "method": method, "params": params});
+        let light_update = |id: &str, upd: &LightUpdate| -> ApiResult<Value> {
+            Ok(notify(
+                "light_update",
+                serde_json::to_value(LightUpdateParams { id, update: upd })?,
+            ))
+        };
+
+        if let BackendRequest::Identify(link, _) = req {
+            /* identify requests can be for the light, or its device */
+            let found = self.lights.iter().find(|(rid, id)| {
+                **rid == link.rid
+                    || RType::Device.deterministic((self.key.as_str(), id.as_str())) == *link
+            });
+            return Ok(found
+                .map(|(_, id)| notify("identify", json!({"id": id})))
+                .into_iter()
+                .collect());
+        }
+
+        let lock = self.state.lock().await;
+        let updates = backend::light_updates(&lock, req, |rid| self.lights.contains_key(rid))?;
+        drop(lock);
+
+        let mut res = vec![];
+        for (rid, upd) in updates {
+            if let Some(id) = self.lights.get(&rid) {
+                res.push(light_update(id, &upd)?);
+            }
+        }
+
+        Ok(res)
+    }
+
+    async fn send(writer: &mut OwnedWriteHalf, msg: &Value) -> ApiResult<()> {
+        let mut line = serde_json::to_vec(msg)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// Serve one connection to the plugin, until it closes
+    async fn serve(
+        &mut self,
+        stream: UnixStream,
+        chan: &mut Receiver<Arc<BackendRequest>>,
+    ) -> ApiResult<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else {
+                        return Ok(());
+                    };
+                    if let Some(reply) = self.handle_message(&line).await {
+                        Self::send(&mut writer, &reply).await?;
+                    }
+                }
+                req = chan.recv() => self.forward(&mut writer, req).await?,
+            }
+        }
+    }
+
+    /// Pass a backend request on to the plugin, if it concerns any of its
+    /// lights
+    async fn forward(
+        &self,
+        writer: &mut OwnedWriteHalf,
+        req: Result<Arc<BackendRequest>, RecvError>,
+    ) -> ApiResult<()> {
+        let req = match req {
+            Ok(req) => req,
+            Err(RecvError::Lagged(count)) => {
+                log::warn!(
+                    "[{}] Remote backend lagging, skipped {count} requests",
+                    self.name
+                );
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        match self.notifications(&req).await {
+            Ok(msgs) => {
+                for msg in msgs {
+                    Self::send(writer, &msg).await?;
+                }
+            }
+            Err(err) => log::error!("[{}] Failed to handle request: {err}", self.name),
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for RemoteBackend {
+    async fn run_forever(mut self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        let retry = Duration::from_secs(self.config.retry.max(1));
+
+        loop {
+            self.state.lock().await.backend_connect_attempt(&self.key);
+            match UnixStream::connect(&self.config.socket).await {
+                Ok(stream) => {
+                    log::info!(
+                        "[{}] Connected to plugin at {}",
+                        self.name,
+                        self.config.socket
+                    );
+                    /* the plugin announces its lights (and so makes them
+                     * reachable again) once connected */
+                    self.state.lock().await.set_backend_ready(&self.key, true);
+                    if let Err(err) = self.serve(stream, &mut chan).await {
+                        log::error!("[{}] Plugin connection failed: {err}", self.name);
+                        self.state.lock().await.set_backend_error(&self.key, &err);
+                    } else {
+                        log::warn!("[{}] Plugin closed the connection", self.name);
+                    }
+                    let lights = std::mem::take(&mut self.lights).into_keys();
+                    backend::set_connected(&self.state, &self.key, lights, false).await;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "[{}] Cannot connect to plugin at {}: {err}",
+                        self.name,
+                        self.config.socket
+                    );
+                    self.state.lock().await.set_backend_error(&self.key, &err);
+                }
+            }
+
+            /* requests sent while disconnected are not replayed */
+            tokio::time::sleep(retry).await;
+            chan = chan.resubscribe();
+        }
+    }
+}
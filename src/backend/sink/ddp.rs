@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+
+use crate::backend::sink::{self, EntertainmentSink, SinkFrame};
+use crate::error::ApiResult;
+
+/// Sink for the Distributed Display Protocol, as used by WLED and others
+///
+/// Each entertainment channel (in channel order) is mapped to a block of
+/// `pixels_per_channel` consecutive pixels.
+pub struct DdpSink {
+    socket: UdpSocket,
+    pixels_per_channel: usize,
+    seqnr: u8,
+}
+
+impl DdpSink {
+    pub const DEFAULT_PORT: u16 = 4048;
+
+    const FLAGS_VER1: u8 = 0x40;
+    const FLAGS_PUSH: u8 = 0x01;
+    const TYPE_RGB24: u8 = 0x0B;
+    const ID_DISPLAY: u8 = 0x01;
+    const MAX_DATA: usize = 1440;
+
+    pub async fn connect(address: &str, pixels_per_channel: usize) -> ApiResult<Self> {
+        let socket = sink::udp_connect(address, Some(Self::DEFAULT_PORT)).await?;
+
+        Ok(Self {
+            socket,
+            pixels_per_channel: pixels_per_channel.max(1),
+            seqnr: 0,
+        })
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn packet(&self, offset: usize, data: &[u8], push: bool) -> Vec<u8> {
+        let mut pkt = Vec::with_capacity(10 + data.len());
+        let flags = if push {
+            Self::FLAGS_VER1 | Self::FLAGS_PUSH
+        } else {
+            Self::FLAGS_VER1
+        };
+        pkt.extend([flags, self.seqnr, Self::TYPE_RGB24, Self::ID_DISPLAY]);
+        pkt.extend((offset as u32).to_be_bytes());
+        pkt.extend((data.len() as u16).to_be_bytes());
+        pkt.extend(data);
+        pkt
+    }
+}
+
+#[async_trait]
+impl EntertainmentSink for DdpSink {
+    async fn frame(&mut self, frame: &SinkFrame) -> ApiResult<()> {
+        let mut chans = frame.channels.clone();
+        chans.sort_by_key(|c| c.channel);
+
+        let data: Vec<u8> = chans
+            .iter()
+            .flat_map(|c| c.rgb.repeat(self.pixels_per_channel))
+            .collect();
+
+        // sequence numbers cycle through 1..=15 (0 means "not used")
+        self.seqnr = self.seqnr % 15 + 1;
+
+        let nchunks = data.len().div_ceil(Self::MAX_DATA);
+        for (idx, chunk) in data.chunks(Self::MAX_DATA).enumerate() {
+            let pkt = self.packet(idx * Self::MAX_DATA, chunk, idx + 1 == nchunks);
+            self.socket.send(&pkt).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use openssl::ssl::{Ssl, SslContext, SslMethod};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio_openssl::SslStream;
+use udp_stream::UdpStream;
+use uuid::Uuid;
+
+use hue::stream::{HueStreamLights, HueStreamPacket, Rgb16, Xy16};
+
+use crate::backend::sink::{EntertainmentSink, SinkFrame};
+use crate::error::{ApiError, ApiResult};
+
+/// Sink that forwards the entertainment stream to a real hue bridge
+///
+/// The entertainment area `area` on the real bridge is started, and the
+/// channels listed in `channels` (bifrost channel to real bridge channel)
+/// are streamed to it, over DTLS, just like a hue sync app would. This
+/// allows one sync session to drive lights on both systems.
+pub struct HueBridgeSink {
+    client: reqwest::Client,
+    address: String,
+    username: String,
+    area: Uuid,
+    channels: BTreeMap<u8, u8>,
+    stream: SslStream<UdpStream>,
+    seqnr: u8,
+}
+
+impl HueBridgeSink {
+    pub const STREAM_PORT: u16 = 2100;
+
+    const CIPHERS: &'static str = "PSK-AES128-GCM-SHA256";
+
+    pub async fn connect(
+        address: &str,
+        username: &str,
+        clientkey: &str,
+        area: Uuid,
+        channels: &BTreeMap<u8, u8>,
+    ) -> ApiResult<Self> {
+        let psk = hex::decode(clientkey)?;
+
+        // hue bridges use self-signed certificates
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()?;
+
+        Self::action(&client, address, username, area, "start").await?;
+
+        let mut bldr = SslContext::builder(SslMethod::dtls_client())?;
+        bldr.set_cipher_list(Self::CIPHERS)?;
+        let identity = username.as_bytes().to_vec();
+        bldr.set_psk_client_callback(move |_sslref, _hint, id, key| {
+            // identity must be nul-terminated
+            if identity.len() >= id.len() || psk.len() > key.len() {
+                return Ok(0);
+            }
+            id[..identity.len()].copy_from_slice(&identity);
+            id[identity.len()] = 0;
+            key[..psk.len()].copy_from_slice(&psk);
+            Ok(psk.len())
+        });
+        let ctx = bldr.build();
+
+        let addr = tokio::net::lookup_host((address, Self::STREAM_PORT))
+            .await?
+            .next()
+            .ok_or_else(|| ApiError::HueBridgeStream(format!("Cannot resolve {address}")))?;
+
+        let socket = UdpStream::connect(addr).await?;
+        let mut stream = SslStream::new(Ssl::new(&ctx)?, socket)?;
+        Pin::new(&mut stream)
+            .connect()
+            .await
+            .map_err(|err| ApiError::HueBridgeStream(err.to_string()))?;
+
+        log::info!("Streaming entertainment area {area} on hue bridge {address}");
+
+        Ok(Self {
+            client,
+            address: address.to_string(),
+            username: username.to_string(),
+            area,
+            channels: channels.clone(),
+            stream,
+            seqnr: 0,
+        })
+    }
+
+    /// Start or stop the entertainment area on the real bridge
+    async fn action(
+        client: &reqwest::Client,
+        address: &str,
+        username: &str,
+        area: Uuid,
+        action: &str,
+    ) -> ApiResult<()> {
+        let reply: Value = client
+            .put(format!(
+                "https://{address}/clip/v2/resource/entertainment_configuration/{area}"
+            ))
+            .header("hue-application-key", username)
+            .json(&json!({"action": action}))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(err) = reply
+            .pointer("/errors/0/description")
+            .and_then(Value::as_str)
+        {
+            return Err(ApiError::HueBridgeStream(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Channel on the real bridge for a bifrost channel. Without a channel
+    /// map, channels are forwarded as-is.
+    fn channel(&self, channel: u8) -> Option<u8> {
+        if self.channels.is_empty() {
+            Some(channel)
+        } else {
+            self.channels.get(&channel).copied()
+        }
+    }
+
+    fn remap(&self, lights: &HueStreamLights) -> HueStreamLights {
+        match lights {
+            HueStreamLights::Rgb(lights) => HueStreamLights::Rgb(
+                lights
+                    .iter()
+                    .filter_map(|light| {
+                        Some(Rgb16 {
+                            channel: self.channel(light.channel)?,
+                            ..*light
+                        })
+                    })
+                    .collect(),
+            ),
+            HueStreamLights::Xy(lights) => HueStreamLights::Xy(
+                lights
+                    .iter()
+                    .filter_map(|light| {
+                        Some(Xy16 {
+                            channel: self.channel(light.channel)?,
+                            ..*light
+                        })
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl EntertainmentSink for HueBridgeSink {
+    async fn frame(&mut self, frame: &SinkFrame) -> ApiResult<()> {
+        let lights = self.remap(&frame.lights);
+        if lights.is_empty() {
+            return Ok(());
+        }
+
+        let pkt = HueStreamPacket {
+            color_mode: lights.color_mode(),
+            area: self.area,
+            lights,
+        };
+
+        self.stream.write_all(&pkt.encode(self.seqnr)?).await?;
+        self.seqnr = self.seqnr.wrapping_add(1);
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> ApiResult<()> {
+        let _ = self.stream.shutdown().await;
+        Self::action(
+            &self.client,
+            &self.address,
+            &self.username,
+            self.area,
+            "stop",
+        )
+        .await
+    }
+}
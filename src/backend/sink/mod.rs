@@ -0,0 +1,300 @@
+pub mod ddp;
+pub mod huebridge;
+pub mod mqtt;
+pub mod udpjson;
+
+use std::collections::BTreeMap;
+use std::io::{Error as IoError, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use hue::api::EntertainmentConfiguration;
+use hue::stream::HueStreamLights;
+
+use crate::backend::sink::ddp::DdpSink;
+use crate::backend::sink::huebridge::HueBridgeSink;
+use crate::backend::sink::mqtt::MqttSink;
+use crate::backend::sink::udpjson::UdpJsonSink;
+use crate::backend::{Backend, BackendRequest};
+use crate::config::{AppConfig, SinkConfig};
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// A single entertainment frame, as delivered to sinks
+#[derive(Clone, Debug, Serialize)]
+pub struct SinkFrame {
+    pub area: Uuid,
+    pub channels: Vec<SinkChannel>,
+    /// The frame as received, for sinks that forward it as-is
+    #[serde(skip)]
+    pub lights: HueStreamLights,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SinkChannel {
+    pub channel: u8,
+    pub rgb: [u8; 3],
+}
+
+/// Where a udp sink sends to: an ip address, or a host name to resolve
+#[derive(Debug, PartialEq, Eq)]
+enum UdpTarget<'a> {
+    Addr(SocketAddr),
+    Host(&'a str, u16),
+}
+
+impl<'a> UdpTarget<'a> {
+    /// Parse an ip address or host name, with an optional port (`default_port`
+    /// if none is given). IPv6 addresses with a port must be in brackets
+    /// (e.g. `[fd00::5]:4048`).
+    fn parse(address: &'a str, default_port: Option<u16>) -> Result<Self, IoError> {
+        if let Ok(addr) = address.parse::<SocketAddr>() {
+            return Ok(Self::Addr(addr));
+        }
+
+        let missing_port =
+            || IoError::new(ErrorKind::InvalidInput, format!("{address}: missing port"));
+
+        if let Ok(ip) = address.parse::<IpAddr>() {
+            let port = default_port.ok_or_else(missing_port)?;
+            return Ok(Self::Addr(SocketAddr::new(ip, port)));
+        }
+
+        match address.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => {
+                let port = port.parse().map_err(|_| {
+                    IoError::new(ErrorKind::InvalidInput, format!("{address}: invalid port"))
+                })?;
+                Ok(Self::Host(host, port))
+            }
+            Some(_) => Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("{address}: invalid address"),
+            )),
+            None => Ok(Self::Host(address, default_port.ok_or_else(missing_port)?)),
+        }
+    }
+
+    async fn resolve(self) -> Result<SocketAddr, IoError> {
+        match self {
+            Self::Addr(addr) => Ok(addr),
+            Self::Host(host, port) => tokio::net::lookup_host((host, port))
+                .await?
+                .next()
+                .ok_or_else(|| IoError::new(ErrorKind::NotFound, format!("{host}: not found"))),
+        }
+    }
+}
+
+/// Open a udp socket to `address` (see [`UdpTarget::parse`]), bound to the
+/// unspecified address of the same family (IPv4 or IPv6)
+pub async fn udp_connect(address: &str, default_port: Option<u16>) -> ApiResult<UdpSocket> {
+    let target = UdpTarget::parse(address, default_port)?.resolve().await?;
+
+    let local: IpAddr = if target.is_ipv4() {
+        Ipv4Addr::UNSPECIFIED.into()
+    } else {
+        Ipv6Addr::UNSPECIFIED.into()
+    };
+
+    let socket = UdpSocket::bind((local, 0)).await?;
+    socket.connect(target).await?;
+
+    Ok(socket)
+}
+
+#[async_trait]
+pub trait EntertainmentSink: Send {
+    async fn frame(&mut self, frame: &SinkFrame) -> ApiResult<()>;
+
+    async fn stop(&mut self) -> ApiResult<()> {
+        Ok(())
+    }
+}
+
+/// Backend that tees entertainment streams to non-zigbee sinks (WLED, etc)
+pub struct SinkBackend {
+    config: Arc<AppConfig>,
+    state: Arc<Mutex<Resources>>,
+    /// Active sinks, by entertainment area
+    sinks: BTreeMap<Uuid, Vec<Box<dyn EntertainmentSink>>>,
+}
+
+impl SinkBackend {
+    #[must_use]
+    pub const fn new(config: Arc<AppConfig>, state: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            config,
+            state,
+            sinks: BTreeMap::new(),
+        }
+    }
+
+    /// Start a sink. `name` identifies the sink where the remote end needs it
+    /// (e.g. as mqtt client id), so it must be unique.
+    async fn build_sink(sink: &SinkConfig, name: &str) -> ApiResult<Box<dyn EntertainmentSink>> {
+        let res: Box<dyn EntertainmentSink> = match sink {
+            SinkConfig::Ddp {
+                address,
+                pixels_per_channel,
+            } => Box::new(DdpSink::connect(address, *pixels_per_channel).await?),
+            SinkConfig::UdpJson { address } => Box::new(UdpJsonSink::connect(address).await?),
+            SinkConfig::Mqtt { host, port, topic } => {
+                Box::new(MqttSink::connect(name, host, *port, topic))
+            }
+            SinkConfig::HueBridge {
+                address,
+                username,
+                clientkey,
+                area,
+                channels,
+            } => Box::new(
+                HueBridgeSink::connect(address, username, clientkey, *area, channels).await?,
+            ),
+        };
+
+        Ok(res)
+    }
+
+    async fn start(&mut self, id: Uuid) -> ApiResult<()> {
+        self.stop(id).await;
+
+        let lock = self.state.lock().await;
+        let ent: &EntertainmentConfiguration = lock.get_id(id)?;
+        let name = ent.metadata.name.clone();
+        drop(lock);
+
+        let Some(area) = self.config.entertainment.area(&id, &name) else {
+            return Ok(());
+        };
+
+        let mut sinks = vec![];
+        for (idx, sink) in area.sinks.iter().enumerate() {
+            match Self::build_sink(sink, &format!("bifrost-{id}-{idx}")).await {
+                Ok(snk) => sinks.push(snk),
+                Err(err) => log::error!("Failed to start entertainment sink {sink:?}: {err}"),
+            }
+        }
+
+        log::info!(
+            "Entertainment area {name:?}: streaming to {} extra sink(s)",
+            sinks.len()
+        );
+        self.sinks.insert(id, sinks);
+
+        Ok(())
+    }
+
+    async fn stop(&mut self, id: Uuid) {
+        for mut sink in self.sinks.remove(&id).unwrap_or_default() {
+            if let Err(err) = sink.stop().await {
+                log::warn!("Failed to stop entertainment sink: {err}");
+            }
+        }
+    }
+
+    async fn handle_request(&mut self, req: &BackendRequest) -> ApiResult<()> {
+        match req {
+            BackendRequest::EntertainmentStart(id) => self.start(*id).await?,
+            BackendRequest::EntertainmentFrame(area, lights) => {
+                let Some(sinks) = self.sinks.get_mut(area) else {
+                    return Ok(());
+                };
+
+                let frame = SinkFrame {
+                    area: *area,
+                    channels: lights
+                        .to_rgb8()
+                        .into_iter()
+                        .map(|(channel, rgb)| SinkChannel { channel, rgb })
+                        .collect(),
+                    lights: lights.clone(),
+                };
+
+                for sink in sinks {
+                    if let Err(err) = sink.frame(&frame).await {
+                        log::trace!("Entertainment sink error: {err}");
+                    }
+                }
+            }
+            BackendRequest::EntertainmentStop(id) => self.stop(*id).await,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for SinkBackend {
+    async fn run_forever(mut self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        loop {
+            let req = match chan.recv().await {
+                Ok(req) => req,
+                Err(RecvError::Lagged(n)) => {
+                    log::warn!("Entertainment sinks lagged behind by {n} requests");
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if let Err(err) = self.handle_request(&req).await {
+                log::error!("Entertainment sink request failed: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::backend::sink::{udp_connect, UdpTarget};
+
+    fn addr(text: &str) -> UdpTarget<'static> {
+        UdpTarget::Addr(text.parse::<SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn parse_ip_addresses() {
+        let parse = |text| UdpTarget::parse(text, Some(4048)).unwrap();
+
+        assert_eq!(parse("10.0.0.50"), addr("10.0.0.50:4048"));
+        assert_eq!(parse("10.0.0.50:21324"), addr("10.0.0.50:21324"));
+        assert_eq!(parse("fd00::50"), addr("[fd00::50]:4048"));
+        assert_eq!(parse("[fd00::50]:21324"), addr("[fd00::50]:21324"));
+    }
+
+    #[test]
+    fn parse_host_names() {
+        let parse = |text| UdpTarget::parse(text, Some(4048)).unwrap();
+
+        assert_eq!(parse("wled.local"), UdpTarget::Host("wled.local", 4048));
+        assert_eq!(parse("wled.local:99"), UdpTarget::Host("wled.local", 99));
+    }
+
+    #[test]
+    fn parse_errors() {
+        // no default port
+        assert!(UdpTarget::parse("10.0.0.50", None).is_err());
+        assert!(UdpTarget::parse("fd00::50", None).is_err());
+        assert!(UdpTarget::parse("wled.local", None).is_err());
+
+        assert!(UdpTarget::parse("wled.local:http", Some(4048)).is_err());
+        assert!(UdpTarget::parse("fd00::50::x", Some(4048)).is_err());
+    }
+
+    #[tokio::test]
+    async fn bind_matching_family() {
+        let socket = udp_connect("127.0.0.1", Some(4048)).await.unwrap();
+        assert!(socket.local_addr().unwrap().is_ipv4());
+    }
+}
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use tokio::task::JoinHandle;
+
+use crate::backend::sink::{EntertainmentSink, SinkFrame};
+use crate::error::ApiResult;
+
+/// Sink that publishes every frame as json on an mqtt topic
+pub struct MqttSink {
+    client: AsyncClient,
+    topic: String,
+    task: JoinHandle<()>,
+}
+
+impl MqttSink {
+    #[must_use]
+    pub fn connect(client_id: &str, host: &str, port: u16, topic: &str) -> Self {
+        let opts = MqttOptions::new(client_id, host, port);
+        let (client, mut eventloop) = AsyncClient::new(opts, 16);
+
+        let task = tokio::spawn(async move {
+            loop {
+                if let Err(err) = eventloop.poll().await {
+                    log::warn!("Entertainment mqtt sink: {err}");
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+            }
+        });
+
+        Self {
+            client,
+            topic: topic.to_string(),
+            task,
+        }
+    }
+}
+
+#[async_trait]
+impl EntertainmentSink for MqttSink {
+    async fn frame(&mut self, frame: &SinkFrame) -> ApiResult<()> {
+        let data = serde_json::to_vec(frame)?;
+
+        // frames are only useful in real time, so drop them if the queue is full
+        self.client
+            .try_publish(&self.topic, QoS::AtMostOnce, false, data)?;
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> ApiResult<()> {
+        let _ = self.client.disconnect().await;
+        self.task.abort();
+        Ok(())
+    }
+}
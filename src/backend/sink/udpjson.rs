@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+
+use crate::backend::sink::{self, EntertainmentSink, SinkFrame};
+use crate::error::ApiResult;
+
+/// Sink that sends every frame as a json datagram
+pub struct UdpJsonSink {
+    socket: UdpSocket,
+}
+
+impl UdpJsonSink {
+    pub async fn connect(address: &str) -> ApiResult<Self> {
+        let socket = sink::udp_connect(address, None).await?;
+
+        Ok(Self { socket })
+    }
+}
+
+#[async_trait]
+impl EntertainmentSink for UdpJsonSink {
+    async fn frame(&mut self, frame: &SinkFrame) -> ApiResult<()> {
+        let data = serde_json::to_vec(frame)?;
+        self.socket.send(&data).await?;
+
+        Ok(())
+    }
+}
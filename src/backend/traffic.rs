@@ -0,0 +1,310 @@
+//! Traffic classes for the zigbee send path
+//!
+//! Entertainment streaming produces a steady stream of frames, while
+//! regular commands (scene recalls, light changes from switches and apps)
+//! come in bursts. Both share the same connection, so requests are kept in a
+//! queue per class, and the queues take turns by weight. That way, neither
+//! class can hold up the other for long.
+//!
+//! Waiting entertainment frames are replaced by newer frames for the same
+//! area, since only the newest one is relevant.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::backend::BackendRequest;
+use crate::config::TrafficWeights;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficClass {
+    Entertainment,
+    Commands,
+}
+
+impl TrafficClass {
+    pub const ALL: [Self; 2] = [Self::Entertainment, Self::Commands];
+
+    #[must_use]
+    pub const fn of(req: &BackendRequest) -> Self {
+        match req {
+            BackendRequest::EntertainmentStart(_)
+            | BackendRequest::EntertainmentFrame(_, _)
+            | BackendRequest::EntertainmentStop(_) => Self::Entertainment,
+            _ => Self::Commands,
+        }
+    }
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Entertainment => "entertainment",
+            Self::Commands => "commands",
+        }
+    }
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+
+    const fn other(self) -> Self {
+        match self {
+            Self::Entertainment => Self::Commands,
+            Self::Commands => Self::Entertainment,
+        }
+    }
+}
+
+/// Queue depth and number of requests sent, per traffic class
+///
+/// Shared with the metrics endpoint, so all counters are atomic.
+#[derive(Debug, Default)]
+pub struct TrafficStats {
+    depth: [AtomicUsize; 2],
+    sent: [AtomicU64; 2],
+}
+
+impl TrafficStats {
+    #[must_use]
+    pub fn depth(&self, class: TrafficClass) -> usize {
+        self.depth[class.index()].load(Ordering::Relaxed)
+    }
+
+    #[must_use]
+    pub fn sent(&self, class: TrafficClass) -> u64 {
+        self.sent[class.index()].load(Ordering::Relaxed)
+    }
+}
+
+pub struct TrafficQueue {
+    weights: TrafficWeights,
+    queues: [VecDeque<Arc<BackendRequest>>; 2],
+    /// Class whose turn it is, and how many more requests it may send
+    turn: (TrafficClass, u32),
+    stats: Arc<TrafficStats>,
+}
+
+impl TrafficQueue {
+    #[must_use]
+    pub fn new(weights: TrafficWeights) -> Self {
+        Self {
+            weights,
+            queues: [VecDeque::new(), VecDeque::new()],
+            turn: (TrafficClass::Commands, 0),
+            stats: Arc::new(TrafficStats::default()),
+        }
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> Arc<TrafficStats> {
+        self.stats.clone()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    fn weight(&self, class: TrafficClass) -> u32 {
+        let weight = match class {
+            TrafficClass::Entertainment => self.weights.entertainment,
+            TrafficClass::Commands => self.weights.commands,
+        };
+        weight.max(1)
+    }
+
+    fn update_depth(&self, class: TrafficClass) {
+        self.stats.depth[class.index()].store(self.queues[class.index()].len(), Ordering::Relaxed);
+    }
+
+    pub fn push(&mut self, req: Arc<BackendRequest>) {
+        let class = TrafficClass::of(&req);
+        let queue = &mut self.queues[class.index()];
+
+        // replace the last waiting frame for the same area, unless the
+        // stream was started or stopped after it
+        if let BackendRequest::EntertainmentFrame(area, _) = *req {
+            let waiting = queue.iter().rposition(
+                |old| matches!(**old, BackendRequest::EntertainmentFrame(old_area, _) if old_area == area),
+            );
+            if let Some(pos) = waiting {
+                if queue
+                    .range(pos..)
+                    .all(|old| matches!(**old, BackendRequest::EntertainmentFrame(..)))
+                {
+                    log::trace!("Coalescing entertainment frame");
+                    queue[pos] = req;
+                    return;
+                }
+            }
+        }
+
+        queue.push_back(req);
+        self.update_depth(class);
+    }
+
+    /// Take the next request to send
+    pub fn pop(&mut self) -> Option<Arc<BackendRequest>> {
+        let (mut class, mut left) = self.turn;
+        if left == 0 || self.queues[class.index()].is_empty() {
+            if !self.queues[class.other().index()].is_empty() {
+                class = class.other();
+            }
+            left = self.weight(class);
+        }
+
+        let req = self.queues[class.index()].pop_front()?;
+        self.turn = (class, left - 1);
+        self.update_depth(class);
+        self.stats.sent[class.index()].fetch_add(1, Ordering::Relaxed);
+
+        Some(req)
+    }
+
+    /// Take all waiting requests (e.g. when the connection is lost), so the
+    /// caller can report them as failed
+    pub fn drain(&mut self) -> Vec<Arc<BackendRequest>> {
+        let mut dropped = vec![];
+        for class in TrafficClass::ALL {
+            dropped.extend(self.queues[class.index()].drain(..));
+            self.update_depth(class);
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use hue::api::{LightUpdate, RType};
+    use hue::stream::{HueStreamLights, Rgb16};
+
+    use crate::backend::traffic::{TrafficClass, TrafficQueue};
+    use crate::backend::BackendRequest;
+    use crate::config::TrafficWeights;
+
+    fn queue(entertainment: u32, commands: u32) -> TrafficQueue {
+        TrafficQueue::new(TrafficWeights {
+            entertainment,
+            commands,
+        })
+    }
+
+    fn command() -> Arc<BackendRequest> {
+        let link = RType::Light.deterministic("lamp");
+        Arc::new(BackendRequest::LightUpdate(link, LightUpdate::new()))
+    }
+
+    /// A frame for `area`, with `lights` lights, to tell frames apart
+    fn frame(area: Uuid, lights: usize) -> Arc<BackendRequest> {
+        let black = Rgb16 {
+            channel: 0,
+            r: 0,
+            g: 0,
+            b: 0,
+        };
+        let lights = HueStreamLights::Rgb(vec![black; lights]);
+        Arc::new(BackendRequest::EntertainmentFrame(area, lights))
+    }
+
+    fn frame_size(req: &BackendRequest) -> Option<usize> {
+        match req {
+            BackendRequest::EntertainmentFrame(_, HueStreamLights::Rgb(lights)) => {
+                Some(lights.len())
+            }
+            _ => None,
+        }
+    }
+
+    fn pop_classes(queue: &mut TrafficQueue) -> Vec<TrafficClass> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|req| TrafficClass::of(&req))
+            .collect()
+    }
+
+    #[test]
+    fn weighted_turns() {
+        use TrafficClass::{Commands as C, Entertainment as E};
+
+        let mut queue = queue(3, 1);
+        let area = Uuid::new_v4();
+        queue.push(Arc::new(BackendRequest::EntertainmentStart(area)));
+        for _ in 0..4 {
+            queue.push(command());
+        }
+        for lights in 1..=4 {
+            // start and stop keep waiting frames from being replaced
+            queue.push(frame(area, lights));
+            queue.push(Arc::new(BackendRequest::EntertainmentStop(area)));
+        }
+
+        assert_eq!(
+            pop_classes(&mut queue),
+            [E, E, E, C, E, E, E, C, E, E, E, C, C]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn zero_weight_still_gets_turns() {
+        use TrafficClass::{Commands as C, Entertainment as E};
+
+        let mut queue = queue(0, 0);
+        let area = Uuid::new_v4();
+        for _ in 0..2 {
+            queue.push(command());
+            queue.push(Arc::new(BackendRequest::EntertainmentStart(area)));
+        }
+
+        assert_eq!(pop_classes(&mut queue), [E, C, E, C]);
+    }
+
+    #[test]
+    fn frames_are_replaced() {
+        let mut queue = queue(1, 1);
+        let (area, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+        queue.push(frame(area, 1));
+        queue.push(frame(other, 2));
+        queue.push(frame(area, 3));
+        assert_eq!(queue.stats().depth(TrafficClass::Entertainment), 2);
+
+        // not replaced across a start or stop of the stream
+        queue.push(Arc::new(BackendRequest::EntertainmentStop(area)));
+        queue.push(frame(area, 4));
+
+        let sizes: Vec<_> = std::iter::from_fn(|| queue.pop())
+            .map(|req| frame_size(&req))
+            .collect();
+        assert_eq!(sizes, [Some(3), Some(2), None, Some(4)]);
+    }
+
+    #[test]
+    fn depth_and_sent_stats() {
+        let mut queue = queue(1, 1);
+        let stats = queue.stats();
+        let area = Uuid::new_v4();
+
+        queue.push(command());
+        queue.push(command());
+        assert_eq!(stats.depth(TrafficClass::Commands), 2);
+
+        queue.pop();
+        assert_eq!(stats.depth(TrafficClass::Commands), 1);
+        assert_eq!(stats.sent(TrafficClass::Commands), 1);
+
+        queue.push(frame(area, 1));
+        assert_eq!(stats.depth(TrafficClass::Entertainment), 1);
+        assert_eq!(stats.sent(TrafficClass::Entertainment), 0);
+
+        let dropped = queue.drain();
+        assert_eq!(dropped.len(), 2);
+        assert!(queue.is_empty());
+        assert_eq!(stats.depth(TrafficClass::Commands), 0);
+        assert_eq!(stats.depth(TrafficClass::Entertainment), 0);
+        assert_eq!(stats.sent(TrafficClass::Commands), 1);
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use maplit::btreeset;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use hue::api::{
+    BridgeHome, Button, ColorGamut, ColorTemperature, ColorTemperatureUpdate, DeviceArchetype,
+    DeviceProductData, Dimming, DimmingUpdate, Entertainment, EntertainmentSegment,
+    EntertainmentSegments, GamutType, GroupedLight, GroupedLightUpdate, Light, LightColor,
+    LightGradient, LightGradientMode, LightMetadata, LightUpdate, Metadata, MirekSchema, Motion,
+    RType, Resource, ResourceLink, Room, RoomArchetype, RoomMetadata, Scene, SceneActive,
+    SceneStatus, SceneUpdate, Stub, ZigbeeConnectivity, ZigbeeConnectivityStatus,
+};
+use hue::devicedb;
+use hue::xy::XY;
+
+use crate::backend::{Backend, BackendRequest};
+use crate::config::{AppConfig, VirtualLightConfig, VirtualLightKind};
+use crate::error::ApiResult;
+use crate::model::state::AuxData;
+use crate::resource::{Resources, Transaction};
+
+/// Backend that simulates lights and sensors, without any zigbee hardware.
+///
+/// All device state lives in [`Resources`], so requests are "executed" by
+/// updating the resources directly.
+pub struct VirtualBackend {
+    config: Arc<AppConfig>,
+    state: Arc<Mutex<Resources>>,
+    lights: HashSet<Uuid>,
+    rooms: HashSet<Uuid>,
+    groups: HashMap<Uuid, Vec<Uuid>>,
+}
+
+impl VirtualBackend {
+    #[must_use]
+    pub fn new(config: Arc<AppConfig>, state: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            config,
+            state,
+            lights: HashSet::new(),
+            rooms: HashSet::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    fn product_data(model_id: Option<&str>) -> DeviceProductData {
+        let model_id = model_id.unwrap_or("virtual");
+
+        devicedb::product_data(model_id).map_or_else(
+            || DeviceProductData {
+                model_id: model_id.to_string(),
+                manufacturer_name: "Bifrost".to_string(),
+                product_name: "Virtual device".to_string(),
+                product_archetype: DeviceArchetype::default(),
+                certified: false,
+                software_version: env!("CARGO_PKG_VERSION").to_string(),
+                hardware_platform_type: None,
+            },
+            |pd| DeviceProductData {
+                model_id: model_id.to_string(),
+                manufacturer_name: pd.manufacturer_name,
+                product_name: pd.product_name,
+                product_archetype: pd.product_archetype,
+                certified: true,
+                software_version: env!("CARGO_PKG_VERSION").to_string(),
+                hardware_platform_type: pd.hardware_platform_type,
+            },
+        )
+    }
+
+    fn zigbee_connectivity(owner: ResourceLink, key: (&str, &str)) -> ZigbeeConnectivity {
+        let link = RType::ZigbeeConnectivity.deterministic(key);
+        let bytes = link.rid.as_bytes();
+
+        ZigbeeConnectivity {
+            channel: None,
+            extended_pan_id: None,
+            mac_address: bytes[..8]
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(":"),
+            owner,
+            status: ZigbeeConnectivityStatus::Connected,
+        }
+    }
+
+    /// Resources (device and services) for a simulated light, with ids
+    /// derived from `key`
+    #[must_use]
+    pub fn light_resources(
+        conf: &VirtualLightConfig,
+        key: (&str, &str),
+        name: &str,
+    ) -> Vec<(ResourceLink, Resource)> {
+        let link_device = RType::Device.deterministic(key);
+        let link_light = RType::Light.deterministic(key);
+        let link_enttm = RType::Entertainment.deterministic(key);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(key);
+
+        let product_data = Self::product_data(conf.model_id.as_deref());
+        let metadata = LightMetadata::new(product_data.product_archetype.clone(), name);
+
+        let dev = hue::api::Device {
+            product_data,
+            metadata: metadata.clone().into(),
+            services: btreeset![link_zigcon, link_light, link_enttm],
+            identify: Some(Stub),
+            usertest: None,
+        };
+
+        let mut light = Light::new(link_device, metadata);
+
+        if conf.kind != VirtualLightKind::OnOff {
+            light.dimming = Some(Dimming {
+                brightness: 100.0,
+                min_dim_level: Some(0.01),
+            });
+        }
+
+        if matches!(
+            conf.kind,
+            VirtualLightKind::Ambiance | VirtualLightKind::Color
+        ) {
+            light.color_temperature = Some(ColorTemperature {
+                mirek: Some(366),
+                mirek_schema: MirekSchema::DEFAULT,
+                mirek_valid: true,
+            });
+        }
+
+        if conf.kind == VirtualLightKind::Color {
+            light.color = Some(LightColor {
+                gamut: Some(ColorGamut::GAMUT_C),
+                gamut_type: GamutType::C,
+                xy: XY::D65_WHITE_POINT,
+            });
+        }
+
+        if conf.gradient {
+            light.gradient = Some(LightGradient {
+                mode: LightGradientMode::InterpolatedPalette,
+                mode_values: BTreeSet::from([
+                    LightGradientMode::InterpolatedPalette,
+                    LightGradientMode::InterpolatedPaletteMirrored,
+                    LightGradientMode::RandomPixelated,
+                ]),
+                points_capable: 5,
+                points: vec![],
+                pixel_count: 7,
+            });
+        }
+
+        let segment_count = if conf.gradient { 7 } else { 1 };
+        let enttm = Entertainment {
+            equalizer: true,
+            owner: link_device,
+            proxy: true,
+            renderer: true,
+            max_streams: None,
+            renderer_reference: Some(link_light),
+            segments: Some(EntertainmentSegments {
+                configurable: false,
+                max_segments: if conf.gradient { 10 } else { 1 },
+                segments: (0..segment_count)
+                    .map(|x| EntertainmentSegment {
+                        start: x,
+                        length: 1,
+                    })
+                    .collect(),
+            }),
+        };
+
+        let zigcon = Self::zigbee_connectivity(link_device, key);
+
+        vec![
+            (link_device, Resource::Device(dev)),
+            (link_light, Resource::Light(light)),
+            (link_enttm, Resource::Entertainment(enttm)),
+            (link_zigcon, Resource::ZigbeeConnectivity(zigcon)),
+        ]
+    }
+
+    pub async fn add_light(&mut self, conf: &VirtualLightConfig, name: &str) -> ApiResult<()> {
+        let key = ("virtual", name);
+        self.lights.insert(RType::Light.deterministic(key).rid);
+
+        let mut res = self.state.lock().await;
+        for (link, obj) in Self::light_resources(conf, key, name) {
+            res.add(&link, obj)?;
+        }
+        drop(res);
+
+        Ok(())
+    }
+
+    pub async fn add_switch(&mut self, name: &str) -> ApiResult<()> {
+        let key = ("virtual", name);
+
+        let link_device = RType::Device.deterministic(key);
+        let link_button = RType::Button.deterministic(key);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(key);
+
+        let dev = hue::api::Device {
+            product_data: Self::product_data(None),
+            metadata: Metadata::new(DeviceArchetype::UnknownArchetype, name),
+            services: btreeset![link_button, link_zigcon],
+            identify: None,
+            usertest: None,
+        };
+
+        let button = Button::new(link_device, 1);
+
+        let zigcon = Self::zigbee_connectivity(link_device, key);
+
+        let mut res = self.state.lock().await;
+        res.add(&link_device, Resource::Device(dev))?;
+        res.add(&link_button, Resource::Button(button))?;
+        res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+        drop(res);
+
+        Ok(())
+    }
+
+    pub async fn add_motion_sensor(&mut self, name: &str) -> ApiResult<()> {
+        let key = ("virtual", name);
+
+        let link_device = RType::Device.deterministic(key);
+        let link_motion = RType::Motion.deterministic(key);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(key);
+
+        let dev = hue::api::Device {
+            product_data: Self::product_data(None),
+            metadata: Metadata::new(DeviceArchetype::UnknownArchetype, name),
+            services: btreeset![link_motion, link_zigcon],
+            identify: None,
+            usertest: None,
+        };
+
+        let motion = Motion {
+            enabled: true,
+            owner: link_device,
+            motion: json!({
+                "motion": false,
+                "motion_valid": true,
+            }),
+            sensitivity: json!({
+                "sensitivity": 2,
+                "sensitivity_max": 4,
+            }),
+        };
+
+        let zigcon = Self::zigbee_connectivity(link_device, key);
+
+        let mut res = self.state.lock().await;
+        res.add(&link_device, Resource::Device(dev))?;
+        res.add(&link_motion, Resource::Motion(motion))?;
+        res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+        drop(res);
+
+        Ok(())
+    }
+
+    pub async fn add_room(&mut self, topic: &str, members: &[String]) -> ApiResult<()> {
+        let link_room = RType::Room.deterministic(("virtual", topic));
+        let link_glight = RType::GroupedLight.deterministic(("virtual", topic));
+
+        let children = members
+            .iter()
+            .map(|name| RType::Device.deterministic(("virtual", name.as_str())))
+            .collect();
+
+        let archetype = RoomArchetype::from_name(topic).unwrap_or(RoomArchetype::Home);
+        let mut metadata = RoomMetadata::new(archetype, topic);
+        if let Some(room_conf) = self.config.rooms.get(topic) {
+            if let Some(name) = &room_conf.name {
+                metadata.name.clone_from(name);
+            }
+            if let Some(icon) = &room_conf.icon {
+                metadata.archetype = *icon;
+            }
+        }
+
+        let room = Room {
+            children,
+            metadata,
+            services: btreeset![link_glight],
+        };
+
+        self.rooms.insert(link_room.rid);
+        self.groups.insert(
+            link_glight.rid,
+            members
+                .iter()
+                .map(|name| RType::Light.deterministic(("virtual", name.as_str())).rid)
+                .collect(),
+        );
+
+        let mut res = self.state.lock().await;
+        let mut tx = Transaction::new();
+        for id in &res.get_resource_ids_by_type(RType::BridgeHome) {
+            tx.update(id, move |bh: &mut BridgeHome| {
+                bh.children.insert(link_room);
+            });
+        }
+
+        tx.add(&link_room, Resource::Room(room));
+        tx.add(
+            &link_glight,
+            Resource::GroupedLight(GroupedLight::new(link_room)),
+        );
+        res.commit(tx)?;
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn setup(&mut self) -> ApiResult<()> {
+        let config = self.config.clone();
+        let devices = &config.virtual_devices;
+
+        let mut rooms: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+        for conf in &devices.lights {
+            let names: Vec<String> = conf.count.map_or_else(
+                || vec![conf.name.clone()],
+                |count| (1..=count).map(|n| format!("{} {n}", conf.name)).collect(),
+            );
+
+            for name in names {
+                self.add_light(conf, &name).await?;
+                if let Some(room) = &conf.room {
+                    rooms.entry(room).or_default().push(name);
+                }
+            }
+        }
+
+        for conf in &devices.switches {
+            self.add_switch(&conf.name).await?;
+        }
+
+        for conf in &devices.motion_sensors {
+            self.add_motion_sensor(&conf.name).await?;
+        }
+
+        for (topic, members) in &rooms {
+            self.add_room(topic, members).await?;
+        }
+
+        log::info!(
+            "Virtual backend: {} lights, {} switches, {} motion sensors, {} rooms",
+            self.lights.len(),
+            devices.switches.len(),
+            devices.motion_sensors.len(),
+            rooms.len(),
+        );
+
+        Ok(())
+    }
+
+    fn apply_light(res: &mut Resources, id: &Uuid, upd: &LightUpdate) -> ApiResult<()> {
+        res.update::<Light>(id, |light| {
+            let mut upd = upd.clone();
+
+            // Updating a light clears the color temperature, unless a new
+            // one is given. That matches what a real light reports, but here
+            // the update is the only source of truth, so keep the current
+            // color temperature unless the color is changed.
+            if upd.color_temperature.is_none() && upd.color.is_none() {
+                upd.color_temperature = light.as_mirek_opt().map(ColorTemperatureUpdate::new);
+            }
+
+            // virtual lights have no fades, so relative changes are applied
+            // as a single step
+            if let Some(delta) = upd.dimming_delta.and_then(|delta| delta.delta()) {
+                if let Some(dim) = &light.dimming {
+                    let brightness = (dim.brightness + delta).clamp(1.0, 100.0);
+                    upd.dimming = Some(DimmingUpdate::new(brightness));
+                }
+            }
+
+            if let Some(delta) = upd.color_temperature_delta.and_then(|delta| delta.delta()) {
+                if let Some(ct) = &light.color_temperature {
+                    let schema = &ct.mirek_schema;
+                    let mirek = i64::from(ct.mirek.unwrap_or_default()) + i64::from(delta);
+                    let mirek = mirek.clamp(
+                        i64::from(schema.mirek_minimum),
+                        i64::from(schema.mirek_maximum),
+                    );
+                    upd.color_temperature =
+                        u16::try_from(mirek).ok().map(ColorTemperatureUpdate::new);
+                }
+            }
+
+            *light += upd;
+        })
+    }
+
+    async fn update_light(&self, link: &ResourceLink, upd: &LightUpdate) -> ApiResult<()> {
+        if !self.lights.contains(&link.rid) {
+            return Ok(());
+        }
+
+        let mut res = self.state.lock().await;
+        Self::apply_light(&mut res, &link.rid, upd)?;
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn update_grouped_light(
+        &self,
+        link: &ResourceLink,
+        upd: &GroupedLightUpdate,
+    ) -> ApiResult<()> {
+        let Some(members) = self.groups.get(&link.rid) else {
+            return Ok(());
+        };
+
+        let mut res = self.state.lock().await;
+        for id in members {
+            let light_upd = upd.for_light(res.get::<Light>(&RType::Light.link_to(*id))?);
+            Self::apply_light(&mut res, id, &light_upd)?;
+        }
+
+        res.update::<GroupedLight>(&link.rid, |glight| {
+            if let Some(on) = upd.on {
+                glight.on = Some(on);
+            }
+            if let Some(dim) = upd.dimming {
+                glight.dimming = Some(dim);
+            }
+        })?;
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn create_scene(&self, link: &ResourceLink, sid: u32, scene: Scene) -> ApiResult<()> {
+        if !self.rooms.contains(&scene.group.rid) {
+            return Ok(());
+        }
+
+        log::info!("New virtual scene: {link:?} ({})", scene.metadata.name);
+
+        let mut tx = Transaction::new();
+        tx.aux_set(link, AuxData::new().with_index(sid));
+        tx.add(link, Resource::Scene(scene));
+        self.state.lock().await.commit(tx)?;
+
+        Ok(())
+    }
+
+    async fn update_scene(&self, link: &ResourceLink, upd: &SceneUpdate) -> ApiResult<()> {
+        let mut res = self.state.lock().await;
+
+        let room = res.get::<Scene>(link)?.group.rid;
+        if !self.rooms.contains(&room) {
+            return Ok(());
+        }
+
+        if let Some(actions) = &upd.actions {
+            res.update::<Scene>(&link.rid, |scene| scene.actions.clone_from(actions))?;
+        }
+
+        let Some(recall) = &upd.recall else {
+            return Ok(());
+        };
+
+        let Some(active) = recall.active() else {
+            log::error!("Scene recall type not supported: {recall:?}");
+            return Ok(());
+        };
+
+        for rid in res.get_scenes_for_room(&room) {
+            res.update::<Scene>(&rid, |scn| {
+                if rid == link.rid {
+                    scn.status = Some(SceneStatus {
+                        active,
+                        last_recall: Some(Utc::now()),
+                    });
+                } else {
+                    scn.status = Some(SceneStatus {
+                        active: SceneActive::Inactive,
+                        last_recall: scn.status.as_ref().and_then(|st| st.last_recall),
+                    });
+                }
+            })?;
+        }
+
+        let actions = res.get::<Scene>(link)?.actions.clone();
+        for act in actions {
+            if !self.lights.contains(&act.target.rid) {
+                continue;
+            }
+
+            let upd = LightUpdate {
+                on: act.action.on,
+                dimming: recall.dimming.or(act.action.dimming),
+                color: act.action.color,
+                color_temperature: act.action.color_temperature,
+                gradient: act
+                    .action
+                    .gradient
+                    .and_then(|grad| serde_json::from_value(grad).ok()),
+                ..LightUpdate::default()
+            };
+
+            Self::apply_light(&mut res, &act.target.rid, &upd)?;
+        }
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn delete(&self, link: &ResourceLink) -> ApiResult<()> {
+        if link.rtype != RType::Scene {
+            return Ok(());
+        }
+
+        let mut res = self.state.lock().await;
+        if self.rooms.contains(&res.get::<Scene>(link)?.group.rid) {
+            res.delete(link)?;
+        }
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn handle_request(&self, req: &BackendRequest) -> ApiResult<()> {
+        match req {
+            BackendRequest::LightUpdate(link, upd) => self.update_light(link, upd).await,
+            BackendRequest::GroupedLightUpdate(link, upd) => {
+                self.update_grouped_light(link, upd).await
+            }
+            BackendRequest::SceneCreate(link, sid, scene) => {
+                self.create_scene(link, *sid, scene.clone()).await
+            }
+            BackendRequest::SceneUpdate(link, upd) => self.update_scene(link, upd).await,
+            BackendRequest::Delete(link) => self.delete(link).await,
+            // virtual lights have nothing to blink, or stream to
+            BackendRequest::Identify(..)
+            | BackendRequest::EntertainmentStart(_)
+            | BackendRequest::EntertainmentFrame(..)
+            | BackendRequest::EntertainmentStop(_)
+            | BackendRequest::ZigbeeRaw(..)
+            | BackendRequest::GroupReconcile
+            | BackendRequest::Commission(_)
+            | BackendRequest::PermitJoin(_)
+            | BackendRequest::Touchlink => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for VirtualBackend {
+    async fn run_forever(mut self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        self.setup().await?;
+
+        loop {
+            let req = match chan.recv().await {
+                Ok(req) => req,
+                Err(RecvError::Lagged(count)) => {
+                    log::warn!("Virtual backend lagging, skipped {count} requests");
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if let Err(err) = self.handle_request(&req).await {
+                log::error!("Virtual backend failed to handle request: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Apply scene actions with as few zigbee messages as possible
+//!
+//! Sending one update per light makes large rooms change one light at a time
+//! (the "popcorn effect"). When every member of a zigbee2mqtt group gets the
+//! same state, a single group command does the same job, and all lights
+//! change at once.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{RType, SceneAction, SceneActionElement};
+
+use crate::resource::Resources;
+
+#[derive(Clone, Debug)]
+pub enum Delivery {
+    /// Send action to all lights in a room at once
+    Group(Uuid, SceneAction),
+
+    /// Send action to a single light
+    Light(Uuid, SceneAction),
+}
+
+/// Split scene actions into group commands (for rooms where all lights get
+/// the same state) and individual light updates (for everything else).
+///
+/// Only rooms for which `has_group` returns true are considered for group
+/// commands.
+pub fn plan(
+    res: &Resources,
+    actions: &[SceneActionElement],
+    has_group: impl Fn(&Uuid) -> bool,
+) -> Vec<Delivery> {
+    let mut pending: BTreeMap<Uuid, (&SceneAction, Option<Value>)> = actions
+        .iter()
+        .filter(|act| act.target.rtype == RType::Light)
+        .map(|act| {
+            let key = serde_json::to_value(&act.action).ok();
+            (act.target.rid, (&act.action, key))
+        })
+        .collect();
+
+    let mut plan = vec![];
+
+    for rr in res.get_resources_by_type(RType::Room) {
+        if !has_group(&rr.id) {
+            continue;
+        }
+
+        let members = res.group_lights(&RType::Room.link_to(rr.id));
+        let Some(((first, _), _)) = members.split_first() else {
+            continue;
+        };
+        let Some((action, key)) = pending.get(first).cloned() else {
+            continue;
+        };
+
+        let shared = members
+            .iter()
+            .all(|(id, _)| pending.get(id).is_some_and(|(_, other)| *other == key));
+
+        if shared {
+            for (id, _) in &members {
+                pending.remove(id);
+            }
+            plan.push(Delivery::Group(rr.id, action.clone()));
+        }
+    }
+
+    plan.extend(
+        pending
+            .into_iter()
+            .map(|(id, (action, _))| Delivery::Light(id, action.clone())),
+    );
+
+    plan
+}
@@ -1,7 +1,11 @@
+pub mod groupcast;
+pub mod pending;
+pub mod reconcile;
+pub mod snapshot;
 pub mod stream;
 pub mod zclcommand;
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -12,44 +16,64 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 use tokio::net::TcpStream;
 use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::Mutex;
-use tokio::time::sleep;
+use tokio::time::{interval, sleep, MissedTickBehavior};
 use tokio_tungstenite::{connect_async, tungstenite, MaybeTlsStream, WebSocketStream};
 use uuid::Uuid;
 
 use hue::api::{
-    BridgeHome, Button, ButtonData, ButtonMetadata, ButtonReport, ColorTemperatureUpdate,
-    ColorUpdate, DeviceArchetype, DeviceProductData, DimmingUpdate, Entertainment,
+    BridgeHome, Button, ColorGamut, ColorTemperatureUpdate, ColorUpdate, Contact, DeviceArchetype,
+    DevicePower, DeviceProductData, DeviceSoftwareUpdate, DimmingUpdate, Entertainment,
     EntertainmentConfiguration, EntertainmentSegment, EntertainmentSegments, GroupedLight, Light,
     LightEffect, LightEffects, LightEffectsV2, LightEffectsV2Update, LightGradientMode,
-    LightMetadata, LightUpdate, Metadata, RType, Resource, ResourceLink, Room, RoomArchetype,
-    RoomMetadata, Scene, SceneAction, SceneActionElement, SceneActive, SceneMetadata, SceneRecall,
-    SceneStatus, SceneStatusUpdate, Stub, Taurus, ZigbeeConnectivity, ZigbeeConnectivityStatus,
+    LightMetadata, LightUpdate, Metadata, PowerMeasurement, RType, RelativeRotary, Resource,
+    ResourceLink, Room, RoomArchetype, RoomMetadata, Scene, SceneAction, SceneActionElement,
+    SceneActive, SceneMetadata, SceneRecall, SceneStatus, SoftwareUpdateState, Stub, Tamper,
+    Taurus, ZigbeeConnectivity, ZigbeeConnectivityStatus,
 };
 use hue::clamp::Clamp;
+use hue::devicedb::{self, Quirk};
 use hue::error::HueError;
+use hue::gradient;
 use hue::scene_icons;
 use hue::stream::HueStreamLights;
+use hue::xy::XY;
 use hue::zigbee::{
     EffectType, EntertainmentZigbeeStream, GradientParams, GradientStyle, HueEntFrameLightRecord,
     HueZigbeeUpdate, LightRecordMode, ZigbeeTarget, PHILIPS_HUE_ZIGBEE_VENDOR_ID,
 };
-use z2m::api::{ExposeLight, Message, RawMessage};
+use z2m::api::{Availability, AvailabilityPayload, ExposeLight, IeeeAddress, Message, RawMessage};
 use z2m::convert::{
-    ExtractColorTemperature, ExtractDeviceProductData, ExtractDimming, ExtractLightColor,
-    ExtractLightGradient,
+    ExtractButtonEvent, ExtractColorTemperature, ExtractDeviceProductData, ExtractDimming,
+    ExtractLightColor, ExtractLightGradient, ExtractRotaryEvent,
 };
 use z2m::hexcolor::HexColor;
-use z2m::request::Z2mRequest;
+use z2m::request::{DeviceEffect, SceneAdd, Z2mRequest};
 use z2m::update::{DeviceColor, DeviceUpdate};
+use zcl::ota::OtaFileVersion;
 
+use crate::backend::traffic::TrafficQueue;
+use crate::backend::z2m::groupcast::Delivery;
+use crate::backend::z2m::pending::PendingCommands;
+use crate::backend::z2m::reconcile::GroupReconciler;
 use crate::backend::z2m::stream::Z2mTarget;
-use crate::backend::{Backend, BackendRequest};
-use crate::config::{AppConfig, Z2mServer};
+use crate::backend::z2m::zclcommand::hue_zclcommand;
+use crate::backend::{
+    self, Backend, BackendRequest, CommandResult, IdentifyEffect, ShardSummary, ZigbeeFrame,
+};
+use crate::config::{AppConfig, BrightnessLimits, LightSettings, RoomConfig, Z2mServer};
 use crate::error::{ApiError, ApiResult};
+use crate::model::entertainment::{EntertainmentStats, SmoothingFilter};
 use crate::model::state::AuxData;
-use crate::resource::Resources;
+use crate::resource::{Resources, Transaction};
+
+/// Interval between checks for devices missing from their zigbee groups
+const RECONCILE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// How often to check for commands that were not confirmed in time
+const CONFIRM_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
 
 #[derive(Debug)]
 struct LearnScene {
@@ -60,9 +84,32 @@ struct LearnScene {
 
 struct EntStream {
     stream: EntertainmentZigbeeStream,
+    stats: Option<Arc<EntertainmentStats>>,
     target: Z2mTarget,
     addrs: BTreeMap<String, Vec<u16>>,
     modes: Vec<(u16, LightRecordMode)>,
+    strips: Vec<EntStrip>,
+    /// Per-light smoothing, by segment address
+    smoothing: SmoothingFilter<u16>,
+    /// Light records of the frame being built, kept to reuse the allocation
+    blks: Vec<HueEntFrameLightRecord>,
+}
+
+/// Light strip with more physical segments than entertainment channels. The
+/// channel colors are resampled onto all segments of the strip.
+struct EntStrip {
+    /// Indices of the channels (in [`EntStream::modes`]) for this strip
+    channels: std::ops::Range<usize>,
+    /// Last known color and brightness of each channel
+    colors: Vec<(XY, f64)>,
+    /// Addresses of the physical segments
+    addrs: Vec<u16>,
+}
+
+/// Brightness of an entertainment light record (1..=2047)
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn ent_brightness(bright: f64) -> u16 {
+    (bright / 255.0 * 2047.0).clamp(1.0, 2047.0) as u16
 }
 
 pub struct Z2mBackend {
@@ -75,8 +122,39 @@ pub struct Z2mBackend {
     learn: HashMap<Uuid, LearnScene>,
     ignore: HashSet<String>,
     network: HashMap<String, z2m::api::Device>,
-    entstream: Option<EntStream>,
-    counter: u32,
+    /// zigbee group id of each room
+    group_ids: HashMap<Uuid, u32>,
+    /// rooms created from configured device lists, instead of groups
+    manual_rooms: HashSet<Uuid>,
+    /// Active entertainment streams, by entertainment area
+    entstreams: HashMap<Uuid, EntStream>,
+    /// Frame counter of each entertainment area, kept across sessions
+    counters: HashMap<Uuid, u32>,
+    reconciler: GroupReconciler,
+    /// Light updates waiting for confirmation
+    pending: PendingCommands,
+    /// Requests waiting to be sent
+    traffic: TrafficQueue,
+    /// Settings of each light, resolved when the light was added
+    settings: HashMap<Uuid, LightSettings>,
+}
+
+/// Button service for the button called `name` (by zigbee2mqtt) on a device
+fn button_link(device: &ResourceLink, name: &str) -> ResourceLink {
+    RType::Button.deterministic((device.rid, name))
+}
+
+/// Resources that might have a zigbee2mqtt topic, for sending commands to
+/// `link` (devices have no topic of their own, but their services do)
+fn topic_candidates(res: &Resources, link: &ResourceLink) -> ApiResult<Vec<Uuid>> {
+    Ok(match link.rtype {
+        RType::Device => {
+            let dev = res.get::<hue::api::Device>(link)?;
+            dev.services.iter().map(|svc| svc.rid).collect()
+        }
+        RType::GroupedLight => vec![res.get::<GroupedLight>(link)?.owner.rid],
+        _ => vec![link.rid],
+    })
 }
 
 fn z2m_set_entertainment_brightness(brightness: u8) -> Z2mRequest<'static> {
@@ -104,7 +182,8 @@ impl Z2mBackend {
         let learn = HashMap::new();
         let ignore = HashSet::new();
         let network = HashMap::new();
-        let entstream = None;
+        let pending = PendingCommands::new(config.bifrost.command_timeout);
+        let traffic = TrafficQueue::new(server.traffic);
         Ok(Self {
             name,
             server,
@@ -115,11 +194,45 @@ impl Z2mBackend {
             learn,
             ignore,
             network,
-            entstream,
-            counter: 0,
+            group_ids: HashMap::new(),
+            manual_rooms: HashSet::new(),
+            entstreams: HashMap::new(),
+            counters: HashMap::new(),
+            reconciler: GroupReconciler::new(),
+            pending,
+            traffic,
+            settings: HashMap::new(),
         })
     }
 
+    /// Correct detected light capabilities with known data from the device
+    /// database
+    fn apply_quirk(light: &mut Light, quirk: &Quirk, effects: bool) {
+        if quirk.effects.unwrap_or(effects) {
+            log::trace!("Detected Hue light: enabling effects");
+            light.effects = Some(LightEffects::all());
+            light.effects_v2 = Some(LightEffectsV2::all());
+        }
+
+        if let (Some(color), Some(gamut_type)) = (&mut light.color, &quirk.gamut_type) {
+            log::trace!("Device database: gamut type {gamut_type:?}");
+            color.gamut = ColorGamut::for_type(gamut_type);
+            color.gamut_type = gamut_type.clone();
+        }
+
+        if let (Some(ct), Some(schema)) = (&mut light.color_temperature, quirk.mirek_schema()) {
+            log::trace!("Device database: mirek schema {schema:?}");
+            ct.mirek_schema = schema;
+            ct.mirek_valid = true;
+        }
+
+        if let (Some(gradient), Some(points)) = (&mut light.gradient, quirk.gradient_points) {
+            log::trace!("Device database: {points} gradient points");
+            gradient.points_capable = points;
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
     pub async fn add_light(
         &mut self,
         apidev: &z2m::api::Device,
@@ -133,11 +246,15 @@ impl Z2mBackend {
         let link_taurus = RType::Taurus.deterministic(&apidev.ieee_address);
         let link_zigcon = RType::ZigbeeConnectivity.deterministic(&apidev.ieee_address);
 
-        let product_data = DeviceProductData::guess_from_device(apidev);
+        let mut product_data = DeviceProductData::guess_from_device(apidev);
+        if product_data.product_archetype == DeviceArchetype::UnknownArchetype {
+            // lights of unknown shape are shown as regular bulbs
+            product_data.product_archetype = DeviceArchetype::ClassicBulb;
+        }
         let metadata = LightMetadata::new(product_data.product_archetype.clone(), name);
 
-        let effects =
-            apidev.manufacturer.as_deref() == Some(DeviceProductData::SIGNIFY_MANUFACTURER_NAME);
+        let quirk = devicedb::quirk(&product_data.model_id).unwrap_or_default();
+
         let gradient = apidev.expose_gradient();
 
         let dev = hue::api::Device {
@@ -171,11 +288,20 @@ impl Z2mBackend {
         light.gradient = gradient.and_then(ExtractLightGradient::extract_from_expose);
         log::trace!("Detected gradient support: {:?}", &light.gradient);
 
-        if effects {
-            log::trace!("Detected Hue light: enabling effects");
-            light.effects = Some(LightEffects::all());
-            light.effects_v2 = Some(LightEffectsV2::all());
+        let effects =
+            apidev.manufacturer.as_deref() == Some(DeviceProductData::SIGNIFY_MANUFACTURER_NAME);
+        Self::apply_quirk(&mut light, &quirk, effects);
+
+        let settings = self.resolve_light_settings(apidev);
+        if let (Some(color), Some(gamut_type)) = (&mut light.color, &settings.gamut) {
+            log::debug!(
+                "[{}] Configured gamut type {gamut_type:?} for {name}",
+                self.name
+            );
+            color.gamut = ColorGamut::for_type(gamut_type);
+            color.gamut_type = gamut_type.clone();
         }
+        self.settings.insert(link_light.rid, settings);
 
         let segments = if gradient.is_some() {
             EntertainmentSegments {
@@ -242,53 +368,266 @@ impl Z2mBackend {
         Ok(())
     }
 
-    pub async fn add_switch(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
+    /// Add an on/off-only device (e.g. a smart plug or relay). These are
+    /// modelled as lights without dimming or color, like a real hue bridge
+    /// does for hue smart plugs.
+    pub async fn add_on_off_light(&mut self, apidev: &z2m::api::Device) -> ApiResult<()> {
+        let name = &apidev.friendly_name;
+
+        let link_device = RType::Device.deterministic(&apidev.ieee_address);
+        let link_light = RType::Light.deterministic(&apidev.ieee_address);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(&apidev.ieee_address);
+
+        let mut product_data = DeviceProductData::guess_from_device(apidev);
+        if product_data.product_archetype == DeviceArchetype::UnknownArchetype {
+            product_data.product_archetype = DeviceArchetype::Plug;
+        }
+        let metadata = LightMetadata::new(product_data.product_archetype.clone(), name);
+
+        let dev = hue::api::Device {
+            product_data,
+            metadata: metadata.clone().into(),
+            services: btreeset![link_zigcon, link_light],
+            identify: Some(Stub),
+            usertest: None,
+        };
+
+        self.map.insert(name.clone(), link_light.rid);
+        self.rmap.insert(link_light.rid, name.clone());
+        let settings = self.resolve_light_settings(apidev);
+        self.settings.insert(link_light.rid, settings);
+
+        let light = Light::new_on_off(link_device, metadata);
+
+        let zigcon = ZigbeeConnectivity {
+            channel: None,
+            extended_pan_id: None,
+            mac_address: apidev.ieee_address.to_string(),
+            owner: link_device,
+            status: ZigbeeConnectivityStatus::Connected,
+        };
+
+        let mut res = self.state.lock().await;
+        res.aux_set(&link_light, AuxData::new().with_topic(name));
+        res.add(&link_device, Resource::Device(dev))?;
+        res.add(&link_light, Resource::Light(light))?;
+        res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+        drop(res);
+
+        Ok(())
+    }
+
+    /// Add a power meter service to a device. Devices that are not lights
+    /// (e.g. smart plugs) are created, with only the power meter service.
+    pub async fn add_power_meter(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
         let name = &dev.friendly_name;
 
         let link_device = RType::Device.deterministic(&dev.ieee_address);
-        let link_button = RType::Button.deterministic(&dev.ieee_address);
-        let link_zbc = RType::ZigbeeConnectivity.deterministic(&dev.ieee_address);
+        let link_power = RType::PowerMeasurement.deterministic(&dev.ieee_address);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(&dev.ieee_address);
 
-        let dev = hue::api::Device {
+        let mut res = self.state.lock().await;
+
+        if res.get::<hue::api::Device>(&link_device).is_err() {
+            let device = hue::api::Device {
+                product_data: DeviceProductData::guess_from_device(dev),
+                metadata: Metadata::new(DeviceArchetype::Plug, name),
+                services: btreeset![link_zigcon],
+                identify: None,
+                usertest: None,
+            };
+
+            let zigcon = ZigbeeConnectivity {
+                channel: None,
+                extended_pan_id: None,
+                mac_address: dev.ieee_address.to_string(),
+                owner: link_device,
+                status: ZigbeeConnectivityStatus::Connected,
+            };
+
+            res.add(&link_device, Resource::Device(device))?;
+            res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+
+            self.map.insert(name.clone(), link_power.rid);
+            self.rmap.insert(link_power.rid, name.clone());
+        }
+
+        let power = PowerMeasurement::new(link_device);
+        res.add(&link_power, Resource::PowerMeasurement(power))?;
+        res.update::<hue::api::Device>(&link_device.rid, |device| {
+            device.services.insert(link_power);
+        })?;
+        drop(res);
+
+        Ok(())
+    }
+
+    pub async fn add_contact_sensor(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
+        let name = &dev.friendly_name;
+
+        let link_device = RType::Device.deterministic(&dev.ieee_address);
+        let link_contact = RType::Contact.deterministic(&dev.ieee_address);
+        let link_tamper = RType::Tamper.deterministic(&dev.ieee_address);
+        let link_power = RType::DevicePower.deterministic(&dev.ieee_address);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(&dev.ieee_address);
+
+        let mut services = btreeset![link_contact, link_zigcon];
+        if dev.expose_binary("tamper") {
+            services.insert(link_tamper);
+        }
+        if dev.expose_battery() {
+            services.insert(link_power);
+        }
+
+        let device = hue::api::Device {
             product_data: DeviceProductData::guess_from_device(dev),
-            metadata: Metadata::new(DeviceArchetype::UnknownArchetype, "foo"),
-            services: btreeset![link_button, link_zbc],
+            metadata: Metadata::new(DeviceArchetype::UnknownArchetype, name),
+            services: services.clone(),
             identify: None,
             usertest: None,
         };
 
-        self.map.insert(name.to_string(), link_button.rid);
-        self.rmap.insert(link_button.rid, name.to_string());
+        let zigcon = ZigbeeConnectivity {
+            channel: None,
+            extended_pan_id: None,
+            mac_address: dev.ieee_address.to_string(),
+            owner: link_device,
+            status: ZigbeeConnectivityStatus::Connected,
+        };
+
+        self.map.insert(name.clone(), link_contact.rid);
+        self.rmap.insert(link_contact.rid, name.clone());
 
         let mut res = self.state.lock().await;
-        let button = Button {
-            owner: link_device,
-            metadata: ButtonMetadata { control_id: 0 },
-            button: ButtonData {
-                last_event: None,
-                button_report: Some(ButtonReport {
-                    updated: Utc::now(),
-                    event: String::from("initial_press"),
-                }),
-                repeat_interval: Some(100),
-                event_values: Some(json!(["initial_press", "repeat"])),
-            },
+        res.add(&link_device, Resource::Device(device))?;
+        res.add(&link_contact, Resource::Contact(Contact::new(link_device)))?;
+        if services.contains(&link_tamper) {
+            res.add(&link_tamper, Resource::Tamper(Tamper::new(link_device)))?;
+        }
+        if services.contains(&link_power) {
+            res.add(
+                &link_power,
+                Resource::DevicePower(DevicePower::new(link_device)),
+            )?;
+        }
+        res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+        drop(res);
+
+        Ok(())
+    }
+
+    pub async fn add_rotary(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
+        let name = &dev.friendly_name;
+
+        let link_device = RType::Device.deterministic(&dev.ieee_address);
+        let link_rotary = RType::RelativeRotary.deterministic(&dev.ieee_address);
+        let link_power = RType::DevicePower.deterministic(&dev.ieee_address);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(&dev.ieee_address);
+
+        let mut services = btreeset![link_rotary, link_zigcon];
+        if dev.expose_battery() {
+            services.insert(link_power);
+        }
+
+        let device = hue::api::Device {
+            product_data: DeviceProductData::guess_from_device(dev),
+            metadata: Metadata::new(DeviceArchetype::UnknownArchetype, name),
+            services: services.clone(),
+            identify: None,
+            usertest: None,
         };
 
-        let zbc = ZigbeeConnectivity {
-            owner: link_device,
-            mac_address: String::from("11:22:33:44:55:66:77:89"),
-            status: ZigbeeConnectivityStatus::ConnectivityIssue,
-            channel: Some(json!({
-                "status": "set",
-                "value": "channel_25",
-            })),
+        let zigcon = ZigbeeConnectivity {
+            channel: None,
             extended_pan_id: None,
+            mac_address: dev.ieee_address.to_string(),
+            owner: link_device,
+            status: ZigbeeConnectivityStatus::Connected,
         };
 
-        res.add(&link_device, Resource::Device(dev))?;
-        res.add(&link_button, Resource::Button(button))?;
-        res.add(&link_zbc, Resource::ZigbeeConnectivity(zbc))?;
+        self.map.insert(name.clone(), link_rotary.rid);
+        self.rmap.insert(link_rotary.rid, name.clone());
+
+        let mut res = self.state.lock().await;
+        res.add(&link_device, Resource::Device(device))?;
+        res.add(
+            &link_rotary,
+            Resource::RelativeRotary(RelativeRotary::new(link_device)),
+        )?;
+        if services.contains(&link_power) {
+            res.add(
+                &link_power,
+                Resource::DevicePower(DevicePower::new(link_device)),
+            )?;
+        }
+        res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+        drop(res);
+
+        Ok(())
+    }
+
+    /// Add a button service for each button of a device. Buttons are added
+    /// to an existing device (e.g. the rotary dial of a tap dial switch), or
+    /// a new device is created for them.
+    pub async fn add_switch(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
+        let name = &dev.friendly_name;
+
+        let link_device = RType::Device.deterministic(&dev.ieee_address);
+        let link_power = RType::DevicePower.deterministic(&dev.ieee_address);
+        let link_zigcon = RType::ZigbeeConnectivity.deterministic(&dev.ieee_address);
+
+        let buttons: Vec<ResourceLink> = dev
+            .expose_buttons()
+            .into_iter()
+            .map(|button| button_link(&link_device, button))
+            .collect();
+
+        let mut res = self.state.lock().await;
+
+        if res.get::<hue::api::Device>(&link_device).is_err() {
+            let mut services = btreeset![link_zigcon];
+            if dev.expose_battery() {
+                services.insert(link_power);
+                res.add(
+                    &link_power,
+                    Resource::DevicePower(DevicePower::new(link_device)),
+                )?;
+            }
+
+            let device = hue::api::Device {
+                product_data: DeviceProductData::guess_from_device(dev),
+                metadata: Metadata::new(DeviceArchetype::UnknownArchetype, name),
+                services,
+                identify: None,
+                usertest: None,
+            };
+
+            let zigcon = ZigbeeConnectivity {
+                channel: None,
+                extended_pan_id: None,
+                mac_address: dev.ieee_address.to_string(),
+                owner: link_device,
+                status: ZigbeeConnectivityStatus::Connected,
+            };
+
+            res.add(&link_device, Resource::Device(device))?;
+            res.add(&link_zigcon, Resource::ZigbeeConnectivity(zigcon))?;
+
+            if let Some(first) = buttons.first() {
+                self.map.insert(name.clone(), first.rid);
+                self.rmap.insert(first.rid, name.clone());
+            }
+        }
+
+        for (control_id, link_button) in (1..).zip(&buttons) {
+            res.add(
+                link_button,
+                Resource::Button(Button::new(link_device, control_id)),
+            )?;
+        }
+        res.update::<hue::api::Device>(&link_device.rid, |device| {
+            device.services.extend(&buttons);
+        })?;
         drop(res);
 
         Ok(())
@@ -313,12 +652,44 @@ impl Z2mBackend {
             room_name = &grp.friendly_name;
         }
 
+        if !self.server.is_room_allowed(&grp.friendly_name) {
+            log::debug!(
+                "[{}] Ignoring group not allowed by room policy: {}",
+                self.name,
+                grp.friendly_name
+            );
+            let link_room = RType::Room.deterministic(&grp.friendly_name);
+            let mut res = self.state.lock().await;
+            Self::remove_room(&mut res, &link_room)?;
+            drop(res);
+            return Ok(());
+        }
+
+        if !self
+            .config
+            .sharding
+            .as_ref()
+            .map_or(true, |sharding| sharding.is_local(&grp.friendly_name))
+        {
+            log::debug!(
+                "[{}] Ignoring group assigned to another bridge: {}",
+                self.name,
+                grp.friendly_name
+            );
+            let link_room = RType::Room.deterministic(&grp.friendly_name);
+            let mut res = self.state.lock().await;
+            Self::remove_room(&mut res, &link_room)?;
+            drop(res);
+            return Ok(());
+        }
+
         let link_room = RType::Room.deterministic(&grp.friendly_name);
         let link_glight = RType::GroupedLight.deterministic((link_room.rid, grp.id));
 
         let children = grp
             .members
             .iter()
+            .filter(|f| self.is_exposed(&f.ieee_address))
             .map(|f| RType::Device.deterministic(&f.ieee_address))
             .collect();
 
@@ -326,16 +697,44 @@ impl Z2mBackend {
 
         let mut res = self.state.lock().await;
 
+        // the room is created (or refreshed) in one go, including the removal
+        // of orphaned scenes, so a failure halfway changes nothing
+        let mut tx = Transaction::new();
         let mut scenes_new = HashSet::new();
 
         for scn in &grp.scenes {
+            let link_scene = RType::Scene.deterministic((link_room.rid, scn.id));
+
+            // with native scenes, the actions are what was written to the
+            // lights, so those are kept
+            let actions = if self.server.native_scenes {
+                res.get::<Scene>(&link_scene)
+                    .map(|scene| scene.actions.clone())
+                    .unwrap_or_default()
+            } else {
+                vec![]
+            };
+
+            // appdata and image are written by the hue app (to sort scenes
+            // and pick thumbnails), so those are kept as well
+            let (appdata, image) = res.get::<Scene>(&link_scene).map_or_else(
+                |_| (None, scene_icons::guess(&scn.name)),
+                |scene| {
+                    let image = scene
+                        .metadata
+                        .image
+                        .or_else(|| scene_icons::guess(&scn.name));
+                    (scene.metadata.appdata.clone(), image)
+                },
+            );
+
             let scene = Scene {
-                actions: vec![],
+                actions,
                 auto_dynamic: false,
                 group: link_room,
                 metadata: SceneMetadata {
-                    appdata: None,
-                    image: guess_scene_icon(&scn.name),
+                    appdata,
+                    image,
                     name: scn.name.to_string(),
                 },
                 palette: json!({
@@ -356,15 +755,13 @@ impl Z2mBackend {
                 }),
             };
 
-            let link_scene = RType::Scene.deterministic((link_room.rid, scn.id));
-
-            res.aux_set(
+            tx.aux_set(
                 &link_scene,
                 AuxData::new().with_topic(&topic).with_index(scn.id),
             );
 
             scenes_new.insert(link_scene.rid);
-            res.add(&link_scene, Resource::Scene(scene))?;
+            tx.add(&link_scene, Resource::Scene(scene));
         }
 
         if let Ok(room) = res.get::<Room>(&link_room) {
@@ -386,7 +783,7 @@ impl Z2mBackend {
                     "[{}] Deleting orphaned {uuid:?} in {link_room:?}",
                     self.name
                 );
-                let _ = res.delete(&RType::Scene.link_to(*uuid));
+                tx.delete(&RType::Scene.link_to(*uuid));
             }
         } else {
             log::debug!(
@@ -396,7 +793,14 @@ impl Z2mBackend {
             );
         }
 
-        let mut metadata = RoomMetadata::new(RoomArchetype::Home, room_name);
+        // keep the archetype of known rooms (it might have been changed
+        // through the api), and guess it from the name for new rooms
+        let archetype = res.get::<Room>(&link_room).map_or_else(
+            |_| RoomArchetype::from_name(room_name).unwrap_or(RoomArchetype::Home),
+            |room| room.metadata.archetype,
+        );
+
+        let mut metadata = RoomMetadata::new(archetype, room_name);
         if let Some(room_conf) = self.config.rooms.get(&topic) {
             if let Some(name) = &room_conf.name {
                 metadata.name = name.to_string();
@@ -412,22 +816,105 @@ impl Z2mBackend {
             services: btreeset![link_glight],
         };
 
+        for id in &res.get_resource_ids_by_type(RType::BridgeHome) {
+            tx.update(id, move |bh: &mut BridgeHome| {
+                bh.children.insert(link_room);
+            });
+        }
+
+        tx.add(&link_room, Resource::Room(room));
+
+        let glight = GroupedLight::new(link_room);
+
+        tx.add(&link_glight, Resource::GroupedLight(glight));
+        res.commit(tx)?;
+        drop(res);
+
         self.map.insert(topic.clone(), link_glight.rid);
         self.rmap.insert(link_glight.rid, topic.clone());
-        self.rmap.insert(link_room.rid, topic.clone());
+        self.rmap.insert(link_room.rid, topic);
+        self.group_ids.insert(link_room.rid, grp.id);
+
+        Ok(())
+    }
+
+    /// Remove a room (if it exists), along with its grouped light and scenes
+    fn remove_room(res: &mut Resources, link_room: &ResourceLink) -> ApiResult<()> {
+        let Ok(room) = res.get::<Room>(link_room) else {
+            return Ok(());
+        };
+
+        log::info!("Removing room {link_room:?} ({})", room.metadata.name);
 
+        let services = room.services.clone();
+        for id in res.get_scenes_for_room(&link_room.rid) {
+            res.delete(&RType::Scene.link_to(id))?;
+        }
+        for svc in &services {
+            res.delete(svc)?;
+        }
         for id in &res.get_resource_ids_by_type(RType::BridgeHome) {
             res.update(id, |bh: &mut BridgeHome| {
-                bh.children.insert(link_room);
+                bh.children.remove(link_room);
             })?;
         }
+        res.delete(link_room)
+    }
 
-        res.add(&link_room, Resource::Room(room))?;
+    /// Create rooms configured with an explicit list of devices
+    pub async fn add_manual_rooms(&mut self) -> ApiResult<()> {
+        for (key, conf) in &self.config.rooms {
+            if conf.devices.is_empty() {
+                continue;
+            }
 
-        let glight = GroupedLight::new(link_room);
+            let children: BTreeSet<ResourceLink> = conf
+                .devices
+                .iter()
+                .filter_map(|name| self.network.get(name))
+                .filter(|dev| self.is_visible(dev))
+                .map(|dev| RType::Device.deterministic(&dev.ieee_address))
+                .collect();
 
-        res.add(&link_glight, Resource::GroupedLight(glight))?;
-        drop(res);
+            // rooms can only be created by the server that has the devices
+            if children.is_empty() {
+                continue;
+            }
+
+            let link_room = RType::Room.deterministic(("manual", key.as_str()));
+            let link_glight = RType::GroupedLight.deterministic(("manual", key.as_str()));
+
+            let name = conf.name.as_deref().unwrap_or(key);
+            let archetype = conf
+                .icon
+                .or_else(|| RoomArchetype::from_name(name))
+                .unwrap_or(RoomArchetype::Home);
+
+            let room = Room {
+                children,
+                metadata: RoomMetadata::new(archetype, name),
+                services: btreeset![link_glight],
+            };
+
+            log::info!("[{}] Adding manual room {key:?}", self.name);
+
+            let mut res = self.state.lock().await;
+            let mut tx = Transaction::new();
+            for id in &res.get_resource_ids_by_type(RType::BridgeHome) {
+                tx.update(id, move |bh: &mut BridgeHome| {
+                    bh.children.insert(link_room);
+                });
+            }
+            tx.add(&link_room, Resource::Room(room));
+            if res.get::<GroupedLight>(&link_glight).is_err() {
+                let glight = GroupedLight::new(link_room);
+                tx.add(&link_glight, Resource::GroupedLight(glight));
+            }
+            res.commit(tx)?;
+            drop(res);
+
+            self.manual_rooms.insert(link_room.rid);
+        }
 
         Ok(())
     }
@@ -450,15 +937,20 @@ impl Z2mBackend {
             _ => {}
         }
 
+        if let Err(e) = self.handle_update_sensors(&obj, &upd).await {
+            log::error!("FAIL: {e:?} in {upd:?}");
+        }
+
         Ok(())
     }
 
     async fn handle_update_light(&mut self, uuid: &Uuid, devupd: &DeviceUpdate) -> ApiResult<()> {
         let mut res = self.state.lock().await;
+        let curve = self.light_settings(uuid).brightness_curve;
         res.update::<Light>(uuid, |light| {
             let upd = LightUpdate::new()
                 .with_on(devupd.state.map(Into::into))
-                .with_brightness(devupd.brightness.map(|b| b / 254.0 * 100.0))
+                .with_brightness(devupd.brightness.map(|b| curve.inverse(b / 254.0) * 100.0))
                 .with_color_temperature(devupd.color_temp)
                 .with_color_xy(devupd.color.and_then(|col| col.xy))
                 .with_gradient(
@@ -515,31 +1007,373 @@ impl Z2mBackend {
                 res.update::<Scene>(uuid, |scene| scene.actions = actions)?;
             }
         }
-        drop(res);
+        drop(res);
+
+        Ok(())
+    }
+
+    /// Update sensor services (buttons, contact, tamper, battery, power
+    /// meter) of the device that owns the resource
+    async fn handle_update_sensors(&self, obj: &Resource, upd: &DeviceUpdate) -> ApiResult<()> {
+        let Some(owner) = obj.owner() else {
+            return Ok(());
+        };
+
+        let mut res = self.state.lock().await;
+        let services = res.get::<hue::api::Device>(&owner)?.services.clone();
+
+        if let Some((button, event)) = upd.extract_button_event() {
+            let link = button_link(&owner, button);
+            if services.contains(&link) {
+                res.button_event(&link.rid, event)?;
+            }
+        }
+
+        if let Some(ota) = upd.ota_update() {
+            let link = RType::DeviceSoftwareUpdate.deterministic(owner.rid);
+            if !services.contains(&link) {
+                let swu = DeviceSoftwareUpdate::new(owner);
+                res.add(&link, Resource::DeviceSoftwareUpdate(swu))?;
+                res.update::<hue::api::Device>(&owner.rid, |device| {
+                    device.services.insert(link);
+                })?;
+            }
+
+            let state = SoftwareUpdateState::from(ota.state);
+            if res.get::<DeviceSoftwareUpdate>(&link)?.state != state {
+                let version = |ver: Option<u32>| ver.map(|v| OtaFileVersion(v).to_string());
+                log::info!(
+                    "[{}] Firmware update state for {}: {state:?} (installed: {:?}, latest: {:?})",
+                    self.name,
+                    res.describe_link(&owner),
+                    version(ota.installed_version),
+                    version(ota.latest_version),
+                );
+                res.update::<DeviceSoftwareUpdate>(&link.rid, |obj| obj.state = state)?;
+            }
+        }
+
+        for link in services {
+            match link.rtype {
+                RType::Contact => {
+                    if let Some(contact) = upd.contact {
+                        res.update::<Contact>(&link.rid, |obj| obj.report(contact.into()))?;
+                    }
+                }
+                RType::Tamper => {
+                    if let Some(tamper) = upd.tamper {
+                        res.update::<Tamper>(&link.rid, |obj| obj.report(tamper.into()))?;
+                    }
+                }
+                RType::DevicePower if upd.battery.is_some() || upd.battery_low.is_some() => {
+                    res.update::<DevicePower>(&link.rid, |obj| {
+                        obj.report(upd.battery_level(), upd.battery_low);
+                    })?;
+                }
+                RType::RelativeRotary => {
+                    if let Some(event) = upd.extract_rotary_event() {
+                        res.update::<RelativeRotary>(&link.rid, |obj| obj.report(event))?;
+                    }
+                }
+                RType::PowerMeasurement => {
+                    let reading = upd.power_reading();
+                    if !reading.is_empty() {
+                        res.update::<PowerMeasurement>(&link.rid, |obj| obj.report(&reading))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn handle_update_grouped_light(&self, uuid: &Uuid, upd: &DeviceUpdate) -> ApiResult<()> {
+        let curve = self.config.lights.brightness_curve.unwrap_or_default();
+        let mut res = self.state.lock().await;
+        res.update::<GroupedLight>(uuid, |glight| {
+            if let Some(state) = &upd.state {
+                glight.on = Some((*state).into());
+            }
+
+            if let Some(b) = upd.brightness {
+                glight.dimming = Some(DimmingUpdate {
+                    brightness: curve.inverse(b / 254.0) * 100.0,
+                });
+            }
+        })
+    }
+
+    /// True if the device with this address is not hidden by the device
+    /// filters. Unknown devices are not hidden.
+    fn is_exposed(&self, ieee: &IeeeAddress) -> bool {
+        self.network
+            .values()
+            .find(|dev| dev.ieee_address == *ieee)
+            .map_or(true, |dev| self.is_visible(dev))
+    }
+
+    /// True if the device should be visible through the hue bridge: not
+    /// hidden by the device filters or the light settings, and assigned to
+    /// this bridge
+    fn is_visible(&self, dev: &z2m::api::Device) -> bool {
+        let is_light = dev.expose_light().is_some() || dev.expose_on_off();
+
+        self.server.is_device_exposed(dev)
+            && self.in_shard(dev)
+            && (!is_light || self.resolve_light_settings(dev).expose)
+    }
+
+    /// Configuration of the room a device is in: a manual room that lists
+    /// it, or else the first (by name) of its rooms that is configured
+    fn device_room_config(&self, dev: &z2m::api::Device) -> Option<&RoomConfig> {
+        self.config
+            .rooms
+            .values()
+            .find(|conf| conf.devices.contains(&dev.friendly_name))
+            .or_else(|| {
+                self.reconciler
+                    .groups_of(&dev.ieee_address)?
+                    .iter()
+                    .filter(|grp| self.server.is_room_allowed(grp))
+                    .find_map(|grp| self.config.rooms.get(grp))
+            })
+    }
+
+    /// Settings for a light, from the global, room and per-light
+    /// configuration. Zigbee2mqtt sends the groups before the devices, so
+    /// the room is known by the time a light is added.
+    fn resolve_light_settings(&self, dev: &z2m::api::Device) -> LightSettings {
+        let id = RType::Light.deterministic(&dev.ieee_address).rid;
+        let model_id = DeviceProductData::guess_from_device(dev).model_id;
+        self.config.lights.settings(
+            &id,
+            &dev.friendly_name,
+            &model_id,
+            self.device_room_config(dev),
+        )
+    }
+
+    /// Resolved settings for a light (the defaults, if it is unknown)
+    fn light_settings(&self, id: &Uuid) -> LightSettings {
+        self.settings
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| self.config.lights.settings(id, "", "", None))
+    }
+
+    /// Room that decides which bridge a device is assigned to, when
+    /// sharding: the first (by name) of its groups that is a room
+    fn shard_room(&self, dev: &z2m::api::Device) -> Option<String> {
+        self.reconciler
+            .groups_of(&dev.ieee_address)?
+            .iter()
+            .find(|grp| self.server.is_room_allowed(grp))
+            .cloned()
+    }
+
+    /// True if the device is assigned to this bridge (always true, unless
+    /// sharding is enabled)
+    fn in_shard(&self, dev: &z2m::api::Device) -> bool {
+        self.config.sharding.as_ref().map_or(true, |sharding| {
+            let key = self
+                .shard_room(dev)
+                .unwrap_or_else(|| dev.ieee_address.to_string());
+            sharding.is_local(&key)
+        })
+    }
+
+    /// Move devices to or from this bridge, after group memberships have
+    /// changed, and report the distribution of devices over bridges
+    async fn update_shards(&mut self) -> ApiResult<()> {
+        let Some(sharding) = self.config.sharding.clone() else {
+            return Ok(());
+        };
+
+        let devices: Vec<z2m::api::Device> = self
+            .network
+            .values()
+            .filter(|dev| self.server.is_device_exposed(dev))
+            .cloned()
+            .collect();
+
+        let mut plan: BTreeMap<String, ShardSummary> = BTreeMap::new();
+        for dev in &devices {
+            let room = self.shard_room(dev);
+            let key = room.clone().unwrap_or_else(|| dev.ieee_address.to_string());
+
+            let summary = plan
+                .entry(sharding.bridge_for(&key).to_string())
+                .or_default();
+            if dev.expose_light().is_some() || dev.expose_on_off() {
+                summary.lights += 1;
+            }
+            summary.rooms.extend(room);
+            summary.devices.insert(dev.friendly_name.clone());
+
+            if self.map.contains_key(&dev.friendly_name) != sharding.is_local(&key) {
+                self.add_device(dev).await?;
+            }
+        }
+
+        for (bridge, summary) in &plan {
+            if summary.lights > sharding.max_lights {
+                log::warn!(
+                    "[{}] Bridge [{bridge}] is assigned {} lights (more than {})",
+                    self.name,
+                    summary.lights,
+                    sharding.max_lights,
+                );
+            }
+        }
+
+        self.state.lock().await.set_shard_plan(&self.name, plan);
 
         Ok(())
     }
 
-    async fn handle_update_grouped_light(&self, uuid: &Uuid, upd: &DeviceUpdate) -> ApiResult<()> {
+    /// Remove the hue resources of a device (e.g. because it is now
+    /// filtered out), if there are any
+    async fn remove_device(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
+        let link_device = RType::Device.deterministic(&dev.ieee_address);
+
         let mut res = self.state.lock().await;
-        res.update::<GroupedLight>(uuid, |glight| {
-            if let Some(state) = &upd.state {
-                glight.on = Some((*state).into());
-            }
+        let services = res
+            .get::<hue::api::Device>(&link_device)
+            .map(|device| device.services.clone());
 
-            if let Some(b) = upd.brightness {
-                glight.dimming = Some(DimmingUpdate {
-                    brightness: b / 254.0 * 100.0,
-                });
+        if let Ok(services) = services {
+            for svc in &services {
+                res.delete(svc)?;
             }
-        })
+            res.delete(&link_device)?;
+        }
+        drop(res);
+
+        self.map.remove(&dev.friendly_name);
+        self.rmap.retain(|_, name| *name != dev.friendly_name);
+        self.settings
+            .remove(&RType::Light.deterministic(&dev.ieee_address).rid);
+
+        Ok(())
+    }
+
+    /// Add the hue resources for a device announced by zigbee2mqtt
+    async fn add_device(&mut self, dev: &z2m::api::Device) -> ApiResult<()> {
+        self.network.insert(dev.friendly_name.clone(), dev.clone());
+        self.ignore.remove(&dev.friendly_name);
+
+        if !self.is_visible(dev) {
+            log::info!(
+                "[{}] Not exposing filtered (or sharded) device {:?}: [{}]",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+            );
+            self.ignore.insert(dev.friendly_name.clone());
+            return self.remove_device(dev).await;
+        }
+
+        if let Some(exp) = dev.expose_light() {
+            log::info!(
+                "[{}] Adding light {:?}: [{}] ({})",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+                dev.model_id.as_deref().unwrap_or("<unknown model>")
+            );
+            self.add_light(dev, exp).await?;
+        } else if dev.expose_on_off() {
+            log::info!(
+                "[{}] Adding on/off device {:?}: [{}] ({})",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+                dev.model_id.as_deref().unwrap_or("<unknown model>")
+            );
+            self.add_on_off_light(dev).await?;
+        }
+
+        if dev.expose_power() {
+            log::info!(
+                "[{}] Adding power meter {:?}: [{}]",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+            );
+            self.add_power_meter(dev).await?;
+        }
+
+        if dev.expose_binary("contact") {
+            log::info!(
+                "[{}] Adding contact sensor {:?}: [{}]",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+            );
+            self.add_contact_sensor(dev).await?;
+        }
+
+        if dev.expose_rotary() {
+            log::info!(
+                "[{}] Adding rotary dial {:?}: [{}]",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+            );
+            self.add_rotary(dev).await?;
+        }
+
+        if !dev.expose_buttons().is_empty() {
+            log::info!(
+                "[{}] Adding switch {:?}: [{}] ({})",
+                self.name,
+                dev.ieee_address,
+                dev.friendly_name,
+                dev.model_id.as_deref().unwrap_or("<unknown model>")
+            );
+            self.add_switch(dev).await?;
+        }
+
+        if dev.expose_light().is_none()
+            && !dev.expose_on_off()
+            && !dev.expose_power()
+            && !dev.expose_binary("contact")
+            && !dev.expose_rotary()
+            && dev.expose_buttons().is_empty()
+        {
+            log::debug!(
+                "[{}] Ignoring unsupported device {}",
+                self.name,
+                dev.friendly_name
+            );
+            self.ignore.insert(dev.friendly_name.clone());
+        }
+
+        Ok(())
     }
 
     async fn handle_bridge_message(&mut self, msg: Message) -> ApiResult<()> {
         #[allow(unused_variables)]
         match msg {
             Message::BridgeInfo(ref obj) => { /* println!("{obj:#?}"); */ }
-            Message::BridgeLogging(ref obj) => { /* println!("{obj:#?}"); */ }
+            Message::BridgeLogging(ref obj) => {
+                if let Some(err) = obj.publish_error() {
+                    self.finish_command(&err.device, Some(err.error)).await?;
+                }
+                if let Some(zm) = obj.zigbee_message() {
+                    self.state.lock().await.zigbee_frame(ZigbeeFrame {
+                        timestamp: Utc::now(),
+                        backend: self.name.clone(),
+                        device: zm.device,
+                        msg_type: zm.msg_type,
+                        cluster: zm.cluster,
+                        endpoint: zm.endpoint,
+                        data: zm.data,
+                    });
+                }
+            }
             Message::BridgeExtensions(ref obj) => { /* println!("{obj:#?}"); */ }
             Message::BridgeEvent(ref obj) => { /* println!("{obj:#?}"); */ }
             Message::BridgeDefinitions(ref obj) => { /* println!("{obj:#?}"); */ }
@@ -548,52 +1382,96 @@ impl Z2mBackend {
 
             Message::BridgeDevices(ref obj) => {
                 for dev in obj {
-                    self.network.insert(dev.friendly_name.clone(), dev.clone());
-                    if let Some(exp) = dev.expose_light() {
-                        log::info!(
-                            "[{}] Adding light {:?}: [{}] ({})",
-                            self.name,
-                            dev.ieee_address,
-                            dev.friendly_name,
-                            dev.model_id.as_deref().unwrap_or("<unknown model>")
-                        );
-                        self.add_light(dev, exp).await?;
-                    } else {
-                        log::debug!(
-                            "[{}] Ignoring unsupported device {}",
-                            self.name,
-                            dev.friendly_name
-                        );
-                        self.ignore.insert(dev.friendly_name.to_string());
-                    }
-                    /*
-                    if dev.expose_action() {
-                        log::info!(
-                            "[{}] Adding switch {:?}: [{}] ({})",
-                            self.name,
-                            dev.ieee_address,
-                            dev.friendly_name,
-                            dev.model_id.as_deref().unwrap_or("<unknown model>")
-                        );
-                        self.add_switch(dev).await?;
-                    }
-                    */
+                    self.add_device(dev).await?;
                 }
+                self.reconciler.update_devices(obj);
+
+                /* the device list is the last part of the initial state z2m
+                 * sends, so the backend is ready once it has been handled */
+                backend::set_connected(&self.state, &self.name, self.map.values().copied(), true)
+                    .await;
             }
 
             Message::BridgeGroups(ref obj) => {
                 /* println!("{obj:#?}"); */
+                self.reconciler.update_groups(obj);
+                self.update_shards().await?;
                 for grp in obj {
                     self.add_group(grp).await?;
                 }
+                self.add_manual_rooms().await?;
             }
         }
         Ok(())
     }
 
+    /// Mark the device behind `topic` as (un)reachable
+    async fn set_reachable(&self, topic: &str, reachable: bool) -> ApiResult<()> {
+        let Some(uuid) = self.map.get(topic) else {
+            return Ok(());
+        };
+
+        let status = if reachable {
+            ZigbeeConnectivityStatus::Connected
+        } else {
+            ZigbeeConnectivityStatus::ConnectivityIssue
+        };
+
+        self.state.lock().await.set_connectivity([*uuid], status)
+    }
+
+    /// Report the outcome of the command waiting for confirmation on
+    /// `topic` (if any)
+    async fn finish_command(&mut self, topic: &str, error: Option<String>) -> ApiResult<()> {
+        let link = self.pending.take(topic);
+        self.report_command(topic, link, error).await
+    }
+
+    /// Report the outcome of a command sent to `topic`. Failed commands mean
+    /// the device is unreachable.
+    async fn report_command(
+        &self,
+        topic: &str,
+        link: Option<ResourceLink>,
+        error: Option<String>,
+    ) -> ApiResult<()> {
+        if let Some(err) = &error {
+            log::warn!("[{}] Command to [{topic}] failed: {err}", self.name);
+        } else if link.is_none() {
+            return Ok(());
+        }
+
+        if let Some(link) = link {
+            let result = CommandResult {
+                link,
+                error: error.clone(),
+            };
+            self.state.lock().await.command_result(result);
+        }
+
+        self.set_reachable(topic, error.is_none()).await
+    }
+
+    /// Fail commands that were not confirmed in time
+    async fn expire_commands(&mut self) -> ApiResult<()> {
+        for (topic, link) in self.pending.expire() {
+            let error = Some("No response from device".to_string());
+            self.report_command(&topic, Some(link), error).await?;
+        }
+        Ok(())
+    }
+
     async fn handle_device_message(&mut self, msg: RawMessage) -> ApiResult<()> {
-        if msg.topic.ends_with("/availability") || msg.topic.ends_with("/action") {
-            // availability: https://www.zigbee2mqtt.io/guide/usage/mqtt_topics_and_messages.html#zigbee2mqtt-friendly-name-availability
+        // availability: https://www.zigbee2mqtt.io/guide/usage/mqtt_topics_and_messages.html#zigbee2mqtt-friendly-name-availability
+        if let Some(topic) = msg.topic.strip_suffix("/availability") {
+            if let Ok(avail) = serde_json::from_value::<AvailabilityPayload>(msg.payload) {
+                let online = avail.availability() == Availability::Online;
+                self.set_reachable(topic, online).await?;
+            }
+            return Ok(());
+        }
+
+        if msg.topic.ends_with("/action") {
             // action: https://www.home-assistant.io/integrations/device_trigger.mqtt/
             return Ok(());
         }
@@ -617,6 +1495,10 @@ impl Z2mBackend {
             );
         }
 
+        /* zigbee2mqtt publishes the device state once a command has been
+         * delivered, so this confirms any command sent to it */
+        self.finish_command(&msg.topic, None).await?;
+
         /* return Ok here, since we do not want to break the event loop */
         Ok(())
     }
@@ -687,6 +1569,118 @@ impl Z2mBackend {
         });
     }
 
+    /// Configuration of a room created from a zigbee2mqtt group
+    fn room_config(&self, room: &Uuid) -> Option<&RoomConfig> {
+        self.rmap
+            .get(room)
+            .and_then(|topic| self.config.rooms.get(topic))
+    }
+
+    /// Configured brightness limits for a room (or the global ones)
+    fn room_brightness_limits(&self, room: &Uuid) -> BrightnessLimits {
+        self.room_config(room)
+            .map(RoomConfig::brightness_limits)
+            .unwrap_or_default()
+            .or(self.config.lights.brightness_limits())
+    }
+
+    /// Configured transition time for a room (or the global one)
+    fn room_transition(&self, room: &Uuid) -> Option<u32> {
+        self.room_config(room)
+            .and_then(|conf| conf.transition)
+            .or(self.config.lights.default_transition)
+    }
+
+    /// Per-light states for storing a scene natively, if enabled and possible
+    fn native_scene_states(&self, scene: &Scene) -> Option<(u32, Vec<(String, DeviceUpdate)>)> {
+        if !self.server.native_scenes || scene.actions.is_empty() {
+            return None;
+        }
+
+        let group_id = *self.group_ids.get(&scene.group.rid)?;
+        let curve = self.config.lights.brightness_curve.unwrap_or_default();
+
+        let states = scene
+            .actions
+            .iter()
+            .filter_map(|act| {
+                let topic = self.rmap.get(&act.target.rid)?.clone();
+                let limits = self.light_settings(&act.target.rid).brightness_limits;
+                let state = DeviceUpdate::default()
+                    .with_state(act.action.on.map(|on| on.on))
+                    .with_brightness(
+                        act.action
+                            .dimming
+                            .map(|dim| curve.apply(limits.clamp(dim.brightness) / 100.0) * 254.0),
+                    )
+                    .with_color_temp(act.action.color_temperature.map(|ct| ct.mirek))
+                    .with_color_xy(act.action.color.map(|col| col.xy));
+                Some((topic, state))
+            })
+            .collect();
+
+        Some((group_id, states))
+    }
+
+    /// Write a scene to the zigbee scene table of each light
+    async fn store_native_scene(
+        &self,
+        socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+        group_id: u32,
+        sid: u32,
+        name: &str,
+        states: Vec<(String, DeviceUpdate)>,
+    ) -> ApiResult<()> {
+        log::info!(
+            "[{}] Storing scene {sid} ({name}) natively in {} lights",
+            self.name,
+            states.len()
+        );
+
+        for (topic, state) in &states {
+            let z2mreq = Z2mRequest::SceneAdd(SceneAdd {
+                id: sid,
+                group_id,
+                name,
+                state,
+            });
+            self.websocket_send(socket, topic, z2mreq).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send planned scene actions to zigbee2mqtt
+    async fn send_scene_actions(
+        &self,
+        socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+        plan: Vec<Delivery>,
+        recall: &SceneRecall,
+    ) -> ApiResult<()> {
+        let curve = self.config.lights.brightness_curve.unwrap_or_default();
+        let transition = recall.duration.or(self.config.lights.default_transition);
+
+        for delivery in plan {
+            let (Delivery::Group(id, action) | Delivery::Light(id, action)) = delivery;
+            let Some(topic) = self.rmap.get(&id) else {
+                continue;
+            };
+
+            let dimming = recall.dimming.or(action.dimming);
+            let payload = DeviceUpdate::default()
+                .with_state(action.on.map(|on| on.on))
+                .with_brightness(dimming.map(|dim| curve.apply(dim.brightness / 100.0) * 254.0))
+                .with_color_temp(action.color_temperature.map(|ct| ct.mirek))
+                .with_color_xy(action.color.map(|col| col.xy))
+                .with_transition(transition);
+
+            let z2mreq = Z2mRequest::Update(&payload);
+            self.websocket_send(socket, topic, z2mreq).await?;
+        }
+
+        Ok(())
+    }
+
     async fn learn_scene_recall(&mut self, lscene: &ResourceLink) -> ApiResult<()> {
         log::info!("[{}] Recall scene: {lscene:?}", self.name);
         let lock = self.state.lock().await;
@@ -759,6 +1753,49 @@ impl Z2mBackend {
         Ok(socket.send(msg).await?)
     }
 
+    /// Report devices missing from zigbee groups they used to be in, and
+    /// (if `repair` is true) add them back
+    async fn reconcile_groups(
+        &self,
+        socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+        repair: bool,
+    ) -> ApiResult<()> {
+        let drift = self.reconciler.drift(self.network.values());
+        self.state
+            .lock()
+            .await
+            .set_group_drift(&self.name, drift.clone());
+
+        if !repair {
+            for item in &drift {
+                log::warn!(
+                    "[{}] Device [{}] is missing from group [{}]",
+                    self.name,
+                    item.device,
+                    item.group
+                );
+            }
+            return Ok(());
+        }
+
+        for item in drift {
+            log::info!(
+                "[{}] Adding device [{}] back to group [{}]",
+                self.name,
+                item.device,
+                item.group
+            );
+            let api_req = RawMessage {
+                topic: "bridge/request/group/members/add".to_string(),
+                payload: json!({"group": item.group, "device": item.device}),
+            };
+            let msg = tungstenite::Message::text(serde_json::to_string(&api_req)?);
+            socket.send(msg).await?;
+        }
+
+        Ok(())
+    }
+
     #[allow(clippy::too_many_lines)]
     async fn websocket_write(
         &mut self,
@@ -782,10 +1819,21 @@ impl Z2mBackend {
                             }
                         })?;
                     }
-                    let hue_effects = lock.get::<Light>(&link)?.effects.is_some();
+                    let light = lock.get::<Light>(&link)?;
+                    let hue_effects = light.effects.is_some();
+                    let brightness: Vec<f64> = light.dimming.iter().map(|d| d.brightness).collect();
+                    let settings = self.light_settings(&link.rid);
+                    let transition = upd.duration().or(settings.transition);
+                    let curve = settings.brightness_curve;
+                    let limits = settings.brightness_limits;
                     drop(lock);
 
-                    if hue_effects {
+                    // relative changes are not supported by the hue-specific
+                    // update format, so send those as regular updates
+                    let delta =
+                        upd.dimming_delta.is_some() || upd.color_temperature_delta.is_some();
+
+                    if hue_effects && !delta {
                         let mut hz = HueZigbeeUpdate::new();
 
                         if let Some(on) = &upd.on {
@@ -817,7 +1865,9 @@ impl Z2mBackend {
 
                         if let Some(br) = &upd.dimming {
                             hz = hz.with_brightness(
-                                (br.brightness / 100.0).unit_to_u8_clamped_light(),
+                                curve
+                                    .apply(limits.clamp(br.brightness) / 100.0)
+                                    .unit_to_u8_clamped_light(),
                             );
                         }
 
@@ -857,7 +1907,9 @@ impl Z2mBackend {
                             }
                         }
 
-                        hz = hz.with_fade_speed(0x0001);
+                        hz = hz.with_transition(std::time::Duration::from_millis(
+                            transition.map_or(0, u64::from),
+                        ));
 
                         let data = hz.to_vec()?;
 
@@ -880,12 +1932,31 @@ impl Z2mBackend {
 
                         self.websocket_send(socket, topic, z2mreq).await?;
                     } else {
-                        let payload = DeviceUpdate::default()
+                        // relative dimming must keep the light within the limits
+                        let step = upd.dimming_delta.and_then(|delta| {
+                            limits.limit_delta(curve, &brightness, &delta, upd.duration())
+                        });
+
+                        let mut payload = DeviceUpdate::default()
                             .with_state(upd.on.map(|on| on.on))
-                            .with_brightness(upd.dimming.map(|dim| dim.brightness / 100.0 * 254.0))
+                            .with_brightness(upd.dimming.map(|dim| {
+                                curve.apply(limits.clamp(dim.brightness) / 100.0) * 254.0
+                            }))
                             .with_color_temp(upd.color_temperature.map(|ct| ct.mirek))
                             .with_color_xy(upd.color.map(|col| col.xy))
-                            .with_gradient(upd.gradient);
+                            .with_dimming_delta(upd.dimming_delta, upd.duration())
+                            .with_color_temperature_delta(
+                                upd.color_temperature_delta,
+                                upd.duration(),
+                            )
+                            .with_gradient(upd.gradient)
+                            .with_transition(transition);
+
+                        if let Some((step, time)) = step {
+                            payload = payload
+                                .with_brightness_step(step)
+                                .with_transition(time.or(transition));
+                        }
 
                         let z2mreq = Z2mRequest::Update(&payload);
 
@@ -897,27 +1968,63 @@ impl Z2mBackend {
                 if let Some(topic) = self.rmap.get(&scene.group.rid) {
                     log::info!("New scene: {link_scene:?} ({})", scene.metadata.name);
 
-                    lock.aux_set(
+                    let mut tx = Transaction::new();
+                    tx.aux_set(
                         &link_scene,
                         AuxData::new()
                             .with_topic(&scene.metadata.name)
                             .with_index(sid),
                     );
-                    let z2mreq = Z2mRequest::SceneStore {
-                        name: &scene.metadata.name.clone(),
-                        id: sid,
-                    };
 
-                    lock.add(&link_scene, Resource::Scene(scene))?;
+                    let name = scene.metadata.name.clone();
+                    let native = self.native_scene_states(&scene);
+
+                    tx.add(&link_scene, Resource::Scene(scene));
+                    lock.commit(tx)?;
                     drop(lock);
 
-                    self.websocket_send(socket, topic, z2mreq).await?;
+                    if let Some((group_id, states)) = native {
+                        self.store_native_scene(socket, group_id, sid, &name, states)
+                            .await?;
+                    } else {
+                        let z2mreq = Z2mRequest::SceneStore {
+                            name: &name,
+                            id: sid,
+                        };
+                        self.websocket_send(socket, topic, z2mreq).await?;
+                    }
+                } else if self.manual_rooms.contains(&scene.group.rid) {
+                    // manual rooms have no zigbee group to store scenes in,
+                    // so these are recalled from the scene actions instead
+                    log::info!("New scene: {link_scene:?} ({})", scene.metadata.name);
+
+                    let mut tx = Transaction::new();
+                    tx.aux_set(&link_scene, AuxData::new().with_index(sid));
+                    tx.add(&link_scene, Resource::Scene(scene));
+                    lock.commit(tx)?;
+                    drop(lock);
                 }
             }
             BackendRequest::SceneUpdate(link, upd) => {
+                if let Some(actions) = upd.actions.filter(|_| self.server.native_scenes) {
+                    lock.update::<Scene>(&link.rid, |scene| scene.actions = actions)?;
+
+                    let scene = lock.get::<Scene>(&link)?;
+                    let index = lock.aux_get(&link)?.index;
+                    let native = self.native_scene_states(scene);
+                    let name = scene.metadata.name.clone();
+
+                    if let (Some(sid), Some((group_id, states))) = (index, native) {
+                        drop(lock);
+                        self.store_native_scene(socket, group_id, sid, &name, states)
+                            .await?;
+                        lock = self.state.lock().await;
+                    }
+                }
+
                 if let Some(recall) = upd.recall {
                     let scene = lock.get::<Scene>(&link)?;
-                    if recall.action == Some(SceneStatusUpdate::Active) {
+                    if let Some(active) = recall.active() {
                         let index = lock
                             .aux_get(&link)?
                             .index
@@ -926,24 +2033,43 @@ impl Z2mBackend {
                         let scenes = lock.get_scenes_for_room(&scene.group.rid);
                         for rid in scenes {
                             lock.update::<Scene>(&rid, |scn| {
-                                scn.status = Some(SceneStatus {
-                                    active: if rid == link.rid {
-                                        SceneActive::Static
-                                    } else {
-                                        SceneActive::Inactive
-                                    },
-                                    last_recall: None,
-                                });
+                                if rid == link.rid {
+                                    scn.status = Some(SceneStatus {
+                                        active,
+                                        last_recall: Some(Utc::now()),
+                                    });
+                                } else {
+                                    scn.status = Some(SceneStatus {
+                                        active: SceneActive::Inactive,
+                                        last_recall: scn
+                                            .status
+                                            .as_ref()
+                                            .and_then(|st| st.last_recall),
+                                    });
+                                }
                             })?;
                         }
 
-                        let room = lock.get::<Scene>(&link)?.group.rid;
-                        drop(lock);
+                        let scene = lock.get::<Scene>(&link)?;
+                        let room = scene.group.rid;
 
-                        if let Some(topic) = self.rmap.get(&room).cloned() {
+                        // scenes are stored in the lights of the room, so
+                        // those can be recalled natively. zone scenes (and
+                        // recalls with a brightness override) are applied
+                        // from the scene actions instead.
+                        if let (Some(topic), None) = (self.rmap.get(&room).cloned(), recall.dimming)
+                        {
+                            drop(lock);
                             self.learn_scene_recall(&link).await?;
                             let z2mreq = Z2mRequest::SceneRecall(index);
                             self.websocket_send(socket, &topic, z2mreq).await?;
+                        } else {
+                            let plan = groupcast::plan(&lock, &scene.actions, |id| {
+                                self.rmap.contains_key(id)
+                            });
+                            drop(lock);
+
+                            self.send_scene_actions(socket, plan, &recall).await?;
                         }
                     } else {
                         log::error!("Scene recall type not supported: {recall:?}");
@@ -951,19 +2077,96 @@ impl Z2mBackend {
                 }
             }
             BackendRequest::GroupedLightUpdate(link, upd) => {
-                let room = lock.get::<GroupedLight>(&link)?.owner.rid;
+                let owner = lock.get::<GroupedLight>(&link)?.owner;
+                let room = owner.rid;
+                let topic = self.rmap.get(&room).cloned();
+                let members = lock.group_lights(&owner);
+
+                // a zigbee group command sets the same color mode on all
+                // members, so if some members cannot show the requested color
+                // (or the group has no zigbee2mqtt topic at all, like zones),
+                // the color is sent to each member light individually.
+                let split = topic.is_none() || !members.iter().all(|(_, l)| upd.is_supported_by(l));
+                let member_updates: Vec<(Uuid, LightUpdate, Option<f64>)> = if split {
+                    members
+                        .iter()
+                        .map(|(id, light)| {
+                            let brightness = light.dimming.map(|dim| dim.brightness);
+                            (*id, upd.for_light(light), brightness)
+                        })
+                        .collect()
+                } else {
+                    vec![]
+                };
+                let brightness: Vec<f64> = members
+                    .iter()
+                    .filter_map(|(_, light)| light.dimming.map(|dim| dim.brightness))
+                    .collect();
                 drop(lock);
 
-                let payload = DeviceUpdate::default()
-                    .with_state(upd.on.map(|on| on.on))
-                    .with_brightness(upd.dimming.map(|dim| dim.brightness / 100.0 * 254.0))
-                    .with_color_temp(upd.color_temperature.map(|ct| ct.mirek))
-                    .with_color_xy(upd.color.map(|col| col.xy));
+                // groups are sent to zigbee2mqtt as a whole, so only the
+                // global brightness curve can be applied here
+                let curve = self.config.lights.brightness_curve.unwrap_or_default();
+                let limits = self.room_brightness_limits(&room);
+                let transition = upd.duration().or_else(|| self.room_transition(&room));
+                // relative dimming must keep every light within the limits
+                let payload_for = |upd: &LightUpdate, brightness: &[f64]| {
+                    let payload = DeviceUpdate::default()
+                        .with_state(upd.on.map(|on| on.on))
+                        .with_brightness(
+                            upd.dimming.map(|dim| {
+                                curve.apply(limits.clamp(dim.brightness) / 100.0) * 254.0
+                            }),
+                        )
+                        .with_color_temp(upd.color_temperature.map(|ct| ct.mirek))
+                        .with_color_xy(upd.color.map(|col| col.xy))
+                        .with_dimming_delta(upd.dimming_delta, upd.duration())
+                        .with_color_temperature_delta(upd.color_temperature_delta, upd.duration())
+                        .with_transition(transition);
+
+                    match upd.dimming_delta.and_then(|delta| {
+                        limits.limit_delta(curve, brightness, &delta, upd.duration())
+                    }) {
+                        Some((step, time)) => payload
+                            .with_brightness_step(step)
+                            .with_transition(time.or(transition)),
+                        None => payload,
+                    }
+                };
 
-                if let Some(topic) = self.rmap.get(&room) {
+                if let Some(topic) = &topic {
+                    let mut group_upd = LightUpdate::from(upd.clone());
+                    if split {
+                        group_upd.color = None;
+                        group_upd.color_temperature = None;
+                    }
+                    let payload = payload_for(&group_upd, &brightness);
                     let z2mreq = Z2mRequest::Update(&payload);
                     self.websocket_send(socket, topic, z2mreq).await?;
                 }
+
+                for (id, mut light_upd, light_brightness) in member_updates {
+                    let Some(light_topic) = self.rmap.get(&id) else {
+                        continue;
+                    };
+
+                    // everything but the color was already sent to the group
+                    if topic.is_some() {
+                        light_upd = LightUpdate {
+                            color: light_upd.color,
+                            color_temperature: light_upd.color_temperature,
+                            dynamics: light_upd.dynamics,
+                            ..LightUpdate::default()
+                        };
+                        if light_upd.color.is_none() && light_upd.color_temperature.is_none() {
+                            continue;
+                        }
+                    }
+
+                    let payload = payload_for(&light_upd, light_brightness.as_slice());
+                    let z2mreq = Z2mRequest::Update(&payload);
+                    self.websocket_send(socket, light_topic, z2mreq).await?;
+                }
             }
             BackendRequest::Delete(link) => {
                 if link.rtype != RType::Scene {
@@ -982,13 +2185,54 @@ impl Z2mBackend {
                     self.websocket_send(socket, topic, z2mreq).await?;
                 }
             }
+            BackendRequest::Identify(link, effect) => {
+                let ids = topic_candidates(&lock, &link)?;
+                drop(lock);
+
+                if let Some(topic) = ids.iter().find_map(|id| self.rmap.get(id)) {
+                    let effect = match effect {
+                        IdentifyEffect::Blink => DeviceEffect::Blink,
+                        IdentifyEffect::Breathe => DeviceEffect::Breathe,
+                    };
+                    let z2mreq = Z2mRequest::Effect(effect);
+                    self.websocket_send(socket, topic, z2mreq).await?;
+                }
+            }
+
+            BackendRequest::ZigbeeRaw(link, msg) => {
+                let ids = topic_candidates(&lock, &link)?;
+                drop(lock);
+
+                if let Some(topic) = ids.iter().find_map(|id| self.rmap.get(id)) {
+                    log::info!(
+                        "[{}] Sending raw zigbee command to {topic}: cluster {:#06x}, command {:#04x}",
+                        self.name,
+                        msg.cluster,
+                        msg.command
+                    );
+                    let z2mreq = Z2mRequest::Raw(hue_zclcommand(msg.cluster, &msg));
+                    self.websocket_send(socket, topic, z2mreq).await?;
+                }
+            }
 
             BackendRequest::EntertainmentStart(ent_id) => {
                 let ent: &EntertainmentConfiguration = lock.get_id(ent_id)?;
+                let settings = lock.area_entertainment_settings(
+                    &self.config.entertainment,
+                    &ent_id,
+                    &ent.metadata.name,
+                );
+
+                let stats = lock.area_entertainment_stats(&ent_id);
 
                 let mut chans = ent.channels.clone();
 
+                let segment_maps = lock.segment_maps(&ent_id);
+
                 let mut addrs: BTreeMap<String, Vec<u16>> = BTreeMap::new();
+                let mut physical: BTreeMap<String, (u16, usize)> = BTreeMap::new();
+                let mut custom: BTreeMap<String, Vec<u16>> = BTreeMap::new();
+                let mut smoothing = SmoothingFilter::new();
                 let mut targets = vec![];
                 chans.sort_by_key(|c| c.channel_id);
 
@@ -1008,6 +2252,19 @@ impl Z2mBackend {
                             .ok_or(HueError::NotFound(member.service.rid))?;
 
                         let segment_addr = dev.network_address + member.index;
+                        smoothing.set_strength(
+                            segment_addr,
+                            self.light_settings(&light_id.rid).entertainment_smoothing,
+                        );
+
+                        let nsegments = ent.segments.as_ref().map_or(1, |seg| seg.segments.len());
+                        physical
+                            .insert(dev.friendly_name.clone(), (dev.network_address, nsegments));
+
+                        if let Some(map) = segment_maps.get(&member.service.rid) {
+                            let map = map.iter().map(|idx| dev.network_address + idx).collect();
+                            custom.insert(dev.friendly_name.clone(), map);
+                        }
 
                         addrs
                             .entry(dev.friendly_name.clone())
@@ -1022,24 +2279,49 @@ impl Z2mBackend {
 
                 if let Some(target) = targets.first() {
                     let mut modes = vec![];
+                    let mut strips = vec![];
 
-                    for segments in addrs.values() {
-                        let mode = if segments.len() <= 1 {
+                    for (dev, segments) in &mut addrs {
+                        let mode = if segments.len() <= 1 && !custom.contains_key(dev) {
                             LightRecordMode::Device
                         } else {
                             LightRecordMode::Segment
                         };
 
-                        for seg in segments {
+                        let first = modes.len();
+                        for seg in segments.iter() {
                             modes.push((*seg, mode));
                         }
+
+                        /* custom segment maps take the place of resampling */
+                        let (base, nsegments) = physical[dev];
+                        if segments.len() > 1
+                            && nsegments > segments.len()
+                            && !custom.contains_key(dev)
+                        {
+                            let strip_addrs: Vec<u16> = (base..).take(nsegments).collect();
+                            strips.push(EntStrip {
+                                channels: first..modes.len(),
+                                colors: vec![(XY::D65_WHITE_POINT, 0.0); segments.len()],
+                                addrs: strip_addrs.clone(),
+                            });
+                            *segments = strip_addrs;
+                        }
                     }
 
+                    let counter = self.counters.get(&ent_id).copied().unwrap_or_default();
+                    let mut stream = EntertainmentZigbeeStream::new(counter);
+                    stream.set_smoothing(settings.smoothing);
+
                     let mut es = EntStream {
-                        stream: EntertainmentZigbeeStream::new(self.counter),
+                        stream,
+                        stats,
                         target: Z2mTarget::new(target),
                         addrs,
                         modes,
+                        strips,
+                        smoothing,
+                        blks: vec![],
                     };
 
                     log::debug!("Entertainment addrs: {:#?}", &es.addrs);
@@ -1048,10 +2330,13 @@ impl Z2mBackend {
                         let z2mreq = z2m_set_entertainment_brightness(0xFE);
                         self.websocket_send(socket, dev, z2mreq).await?;
 
-                        if segments.len() <= 1 {
-                            continue;
-                        }
+                        let segments = match custom.get(dev) {
+                            Some(map) => map,
+                            None if segments.len() <= 1 => continue,
+                            None => segments,
+                        };
 
+                        log::debug!("Segment map for {dev}: {segments:04x?}");
                         let z2mreq = es.target.send(es.stream.segment_mapping(segments)?)?;
                         self.websocket_send(socket, dev, z2mreq).await?;
                     }
@@ -1062,23 +2347,37 @@ impl Z2mBackend {
                         self.websocket_send(socket, topic, z2mreq.clone()).await?;
                     }
 
-                    self.entstream = Some(es);
+                    self.entstreams.insert(ent_id, es);
                 }
             }
 
-            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-            BackendRequest::EntertainmentFrame(frame) => {
-                if let Some(es) = &mut self.entstream {
-                    let mut blks = vec![];
+            BackendRequest::EntertainmentFrame(ent_id, frame) => {
+                if let Some(es) = self.entstreams.get_mut(&ent_id) {
+                    let mut blks = std::mem::take(&mut es.blks);
+                    blks.clear();
 
                     if let HueStreamLights::Rgb(rgb) = frame {
                         for light in rgb {
-                            let (xy, bright) = light.to_xy();
+                            let index = light.channel as usize % es.modes.len();
+                            let (chan, mode) = es.modes[index];
+                            let (xy, bright) = es.smoothing.apply(chan, light.to_xy());
+
+                            if let Some(strip) = es
+                                .strips
+                                .iter_mut()
+                                .find(|strip| strip.channels.contains(&index))
+                            {
+                                strip.colors[index - strip.channels.start] = (xy, bright);
+                                continue;
+                            }
 
-                            let brightness = (bright / 255.0 * 2047.0).clamp(1.0, 2047.0) as u16;
-                            let (chan, mode) = es.modes[light.channel as usize % es.modes.len()];
                             let raw = xy.to_quant();
-                            let lrec = HueEntFrameLightRecord::new(chan, brightness, mode, raw);
+                            let lrec = HueEntFrameLightRecord::new(
+                                chan,
+                                ent_brightness(bright),
+                                mode,
+                                raw,
+                            );
 
                             blks.push(lrec);
                         }
@@ -1087,22 +2386,72 @@ impl Z2mBackend {
                         unimplemented!();
                     }
 
-                    let z2mreq = es.target.send(es.stream.frame(blks)?)?;
+                    for strip in &es.strips {
+                        let colors = gradient::resample(
+                            &strip.colors,
+                            strip.addrs.len(),
+                            GradientStyle::Linear,
+                        );
+                        for (addr, (xy, bright)) in strip.addrs.iter().zip(colors) {
+                            blks.push(HueEntFrameLightRecord::new(
+                                *addr,
+                                ent_brightness(bright),
+                                LightRecordMode::Segment,
+                                xy.to_quant(),
+                            ));
+                        }
+                    }
+
+                    let z2mreq = es.target.send(es.stream.frame(&blks)?)?;
+                    es.blks = blks;
                     let device = es.target.device.clone();
+                    let stats = es.stats.clone();
                     self.websocket_send(socket, &device, z2mreq).await?;
+
+                    if let Some(stats) = stats {
+                        stats.frame_sent();
+                    }
                 }
             }
-            BackendRequest::EntertainmentStop() => {
-                log::debug!("Stopping entertainment mode..");
-                if let Some(es) = &mut self.entstream.take() {
+            BackendRequest::GroupReconcile => {
+                drop(lock);
+                self.reconcile_groups(socket, true).await?;
+            }
+            BackendRequest::EntertainmentStop(ent_id) => {
+                log::debug!("Stopping entertainment mode for {ent_id}..");
+                if let Some(es) = &mut self.entstreams.remove(&ent_id) {
                     let z2mreq = es.target.send(es.stream.reset()?)?;
                     for topic in es.addrs.keys() {
                         log::debug!("Sending stop to {topic}");
                         self.websocket_send(socket, topic, z2mreq.clone()).await?;
                     }
-                    self.counter = es.stream.counter();
+                    self.counters.insert(ent_id, es.stream.counter());
                 }
             }
+            // zigbee devices join through permit_join, not setup codes
+            BackendRequest::Commission(_) => {}
+            BackendRequest::PermitJoin(secs) => {
+                drop(lock);
+                log::info!("[{}] Permitting devices to join for {secs}s", self.name);
+                let api_req = RawMessage {
+                    topic: "bridge/request/permit_join".to_string(),
+                    payload: json!({"time": secs}),
+                };
+                let msg = tungstenite::Message::text(serde_json::to_string(&api_req)?);
+                socket.send(msg).await?;
+            }
+            BackendRequest::Touchlink => {
+                drop(lock);
+                log::info!("[{}] Starting touchlink factory reset", self.name);
+                /* without a target, zigbee2mqtt scans, and resets the first
+                 * device found */
+                let api_req = RawMessage {
+                    topic: "bridge/request/touchlink/factory_reset".to_string(),
+                    payload: json!({}),
+                };
+                let msg = tungstenite::Message::text(serde_json::to_string(&api_req)?);
+                socket.send(msg).await?;
+            }
         }
 
         Ok(())
@@ -1113,17 +2462,45 @@ impl Z2mBackend {
         chan: &mut Receiver<Arc<BackendRequest>>,
         mut socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
     ) -> ApiResult<()> {
+        let mut reconcile = interval(RECONCILE_INTERVAL);
+        reconcile.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut confirm = interval(CONFIRM_INTERVAL);
+        confirm.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
             select! {
                 pkt = chan.recv() => {
-                    let api_req = pkt?;
-                    self.websocket_write(&mut socket, api_req).await?;
-                    // FIXME: this used to be our "throttle" feature, but it breaks entertainment mode
-                    /* tokio::time::sleep(std::time::Duration::from_millis(100)).await; */
+                    match pkt {
+                        Ok(req) => self.traffic.push(req),
+                        Err(RecvError::Lagged(count)) => {
+                            log::warn!("[{}] Backend lagging, skipped {count} requests", self.name);
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                },
+                () = std::future::ready(()), if !self.traffic.is_empty() => {
+                    // If zigbee can't keep up with entertainment mode, frames
+                    // pile up in the queue. The traffic queue only keeps the
+                    // newest one (for each area), and takes turns between
+                    // entertainment and other requests.
+                    if let Some(req) = self.traffic.pop() {
+                        if let BackendRequest::LightUpdate(link, _) = &*req {
+                            if let Some(topic) = self.rmap.get(&link.rid) {
+                                self.pending.insert(topic, *link);
+                            }
+                        }
+                        self.websocket_write(&mut socket, req).await?;
+                    }
                 },
                 pkt = socket.next() => {
                     self.websocket_read(pkt.ok_or(ApiError::UnexpectedZ2mEof)??).await?;
                 },
+                _ = reconcile.tick() => {
+                    self.reconcile_groups(&mut socket, self.server.group_repair).await?;
+                },
+                _ = confirm.tick() => {
+                    self.expire_commands().await?;
+                },
             };
         }
     }
@@ -1136,7 +2513,9 @@ impl Backend for Z2mBackend {
         let sanitized_url = self.server.get_sanitized_url();
         let url = self.server.get_url();
 
-        if url != self.server.url {
+        // a separately configured token is always added to the url, so
+        // that is not worth mentioning
+        if url != self.server.url && self.server.token.is_none() {
             log::info!(
                 "[{}] Rewrote url for compatibility with z2m 2.x.",
                 self.name
@@ -1148,48 +2527,43 @@ impl Backend for Z2mBackend {
             );
         }
 
+        self.state
+            .lock()
+            .await
+            .set_traffic_stats(&self.name, self.traffic.stats());
+
         loop {
             log::info!("[{}] Connecting to {}", self.name, &sanitized_url);
+            self.state.lock().await.backend_connect_attempt(&self.name);
             match connect_async(url.as_str()).await {
                 Ok((socket, _)) => {
                     let res = self.event_loop(&mut chan, socket).await;
                     if let Err(err) = res {
                         log::error!("[{}] Event loop broke: {err}", self.name);
+                        self.state.lock().await.set_backend_error(&self.name, &err);
                     }
                 }
                 Err(err) => {
                     log::error!("[{}] Connect failed: {err:?}", self.name);
+                    self.state.lock().await.set_backend_error(&self.name, &err);
+                }
+            }
+            let error = Some("Connection to zigbee2mqtt lost".to_string());
+            for req in self.traffic.drain() {
+                if let BackendRequest::LightUpdate(link, _) = &*req {
+                    if let Some(topic) = self.rmap.get(&link.rid) {
+                        self.report_command(topic, Some(*link), error.clone())
+                            .await?;
+                    }
                 }
             }
+            for (topic, link) in self.pending.drain() {
+                self.report_command(&topic, Some(link), error.clone())
+                    .await?;
+            }
+            backend::set_connected(&self.state, &self.name, self.map.values().copied(), false)
+                .await;
             sleep(std::time::Duration::from_millis(2000)).await;
         }
     }
 }
-
-#[allow(clippy::match_same_arms)]
-fn guess_scene_icon(name: &str) -> Option<ResourceLink> {
-    let icon = match name {
-        /* Built-in names */
-        "Bright" => scene_icons::BRIGHT,
-        "Relax" => scene_icons::RELAX,
-        "Night Light" => scene_icons::NIGHT_LIGHT,
-        "Rest" => scene_icons::REST,
-        "Concentrate" => scene_icons::CONCENTRATE,
-        "Dimmed" => scene_icons::DIMMED,
-        "Energize" => scene_icons::ENERGIZE,
-        "Read" => scene_icons::READ,
-        "Cool Bright" => scene_icons::COOL_BRIGHT,
-
-        /* Aliases */
-        "Night" => scene_icons::NIGHT_LIGHT,
-        "Cool" => scene_icons::COOL_BRIGHT,
-        "Dim" => scene_icons::DIMMED,
-
-        _ => return None,
-    };
-
-    Some(ResourceLink {
-        rid: icon,
-        rtype: RType::PublicImage,
-    })
-}
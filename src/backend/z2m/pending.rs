@@ -0,0 +1,68 @@
+//! Confirmation of light updates
+//!
+//! zigbee2mqtt does not reply to `set` requests. Instead, it publishes the
+//! new state of the device once a command has been delivered, and logs an
+//! error if delivery failed. Commands are remembered (by device topic) until
+//! one of those happens, or until they time out.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use hue::api::ResourceLink;
+
+#[derive(Debug, Default)]
+pub struct PendingCommands {
+    /// Confirmation timeout. Commands are not tracked without one.
+    timeout: Option<Duration>,
+    /// Light and deadline of the last command sent to each topic
+    pending: HashMap<String, (ResourceLink, Instant)>,
+}
+
+impl PendingCommands {
+    #[must_use]
+    pub fn new(timeout_ms: Option<u64>) -> Self {
+        Self {
+            timeout: timeout_ms.map(Duration::from_millis),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Remember a command sent to `topic`. A newer command for the same
+    /// topic replaces an older one, since either of them being confirmed
+    /// means the device is reachable.
+    pub fn insert(&mut self, topic: &str, link: ResourceLink) {
+        if let Some(timeout) = self.timeout {
+            self.pending
+                .insert(topic.to_string(), (link, Instant::now() + timeout));
+        }
+    }
+
+    /// Stop waiting for a command sent to `topic`, returning its light
+    pub fn take(&mut self, topic: &str) -> Option<ResourceLink> {
+        self.pending.remove(topic).map(|(link, _)| link)
+    }
+
+    /// Remove all commands that are past their deadline
+    pub fn expire(&mut self) -> Vec<(String, ResourceLink)> {
+        let now = Instant::now();
+        let (expired, pending): (HashMap<_, _>, HashMap<_, _>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|(_, (_, deadline))| *deadline <= now);
+        self.pending = pending;
+
+        expired
+            .into_iter()
+            .map(|(topic, (link, _))| (topic, link))
+            .collect()
+    }
+
+    /// Remove all commands (e.g. when the connection is lost)
+    pub fn drain(&mut self) -> Vec<(String, ResourceLink)> {
+        self.pending
+            .drain()
+            .map(|(topic, (link, _))| (topic, link))
+            .collect()
+    }
+}
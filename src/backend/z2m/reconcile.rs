@@ -0,0 +1,127 @@
+//! Zigbee group membership reconciliation
+//!
+//! When a device is removed from zigbee2mqtt and paired again (e.g. after a
+//! factory reset), zigbee2mqtt drops it from all of its groups, so it
+//! silently disappears from its rooms. To catch this, the groups of each
+//! device are remembered when it leaves the network. If it joins again, but
+//! is missing from any of those groups, that is reported as drift, and can
+//! be repaired by adding it back.
+//!
+//! Devices that are removed from a group while they stay in the network are
+//! left alone, since that is what the user asked for.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use z2m::api::{Device, Group, IeeeAddress};
+
+use crate::backend::GroupDrift;
+
+#[derive(Debug, Default)]
+pub struct GroupReconciler {
+    /// Names of all known groups
+    groups: BTreeSet<String>,
+    /// Current group memberships of each device
+    memberships: HashMap<IeeeAddress, BTreeSet<String>>,
+    /// Groups each device was removed from since the last device list. If
+    /// the device has left the network, these were probably lost with it.
+    removed: HashMap<IeeeAddress, BTreeSet<String>>,
+    /// Groups of devices that have left the network
+    lost: HashMap<IeeeAddress, BTreeSet<String>>,
+    /// Devices in the last device list
+    present: HashSet<IeeeAddress>,
+}
+
+impl GroupReconciler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current groups of a device (as of the last group list)
+    #[must_use]
+    pub fn groups_of(&self, ieee: &IeeeAddress) -> Option<&BTreeSet<String>> {
+        self.memberships.get(ieee)
+    }
+
+    /// Update from a zigbee2mqtt group list
+    pub fn update_groups(&mut self, groups: &[Group]) {
+        self.groups = groups.iter().map(|grp| grp.friendly_name.clone()).collect();
+
+        let mut memberships: HashMap<IeeeAddress, BTreeSet<String>> = HashMap::new();
+        for grp in groups {
+            for member in &grp.members {
+                memberships
+                    .entry(member.ieee_address.clone())
+                    .or_default()
+                    .insert(grp.friendly_name.clone());
+            }
+        }
+
+        for (ieee, old) in &self.memberships {
+            let new = memberships.get(ieee);
+            let gone = old
+                .iter()
+                .filter(|grp| new.map_or(true, |new| !new.contains(*grp)));
+            self.removed
+                .entry(ieee.clone())
+                .or_default()
+                .extend(gone.cloned());
+        }
+        self.removed.retain(|_, gone| !gone.is_empty());
+
+        /* lost memberships that are back, or whose groups are gone, are done */
+        for (ieee, lost) in &mut self.lost {
+            let current = memberships.get(ieee);
+            lost.retain(|grp| {
+                self.groups.contains(grp) && current.map_or(true, |cur| !cur.contains(grp))
+            });
+        }
+        self.lost.retain(|_, lost| !lost.is_empty());
+
+        self.memberships = memberships;
+    }
+
+    /// Update from a zigbee2mqtt device list
+    pub fn update_devices(&mut self, devices: &[Device]) {
+        let present: HashSet<IeeeAddress> =
+            devices.iter().map(|dev| dev.ieee_address.clone()).collect();
+
+        for (ieee, gone) in std::mem::take(&mut self.removed) {
+            if !present.contains(&ieee) {
+                self.lost.entry(ieee).or_default().extend(gone);
+            }
+        }
+
+        for (ieee, groups) in &self.memberships {
+            if !present.contains(ieee) && !groups.is_empty() {
+                self.lost
+                    .entry(ieee.clone())
+                    .or_default()
+                    .extend(groups.iter().cloned());
+            }
+        }
+
+        self.present = present;
+    }
+
+    /// Devices that are back in the network, but missing from groups they
+    /// were in before they left
+    #[must_use]
+    pub fn drift<'a>(&self, network: impl IntoIterator<Item = &'a Device>) -> Vec<GroupDrift> {
+        let mut res: Vec<GroupDrift> = network
+            .into_iter()
+            .filter(|dev| self.present.contains(&dev.ieee_address))
+            .filter_map(|dev| Some((dev, self.lost.get(&dev.ieee_address)?)))
+            .flat_map(|(dev, lost)| {
+                lost.iter().map(|grp| GroupDrift {
+                    group: grp.clone(),
+                    device: dev.friendly_name.clone(),
+                    ieee_address: dev.ieee_address.to_string(),
+                })
+            })
+            .collect();
+        res.sort();
+
+        res
+    }
+}
@@ -0,0 +1,51 @@
+//! One-off download of the groups and devices of a zigbee2mqtt server
+//!
+//! zigbee2mqtt sends its group and device lists to every new websocket
+//! client, so these can be read without running a backend (e.g. to check a
+//! configuration file against the actual network).
+
+use futures::StreamExt;
+use tokio_tungstenite::{connect_async, tungstenite};
+
+use z2m::api::{Device, Group, Message};
+
+use crate::config::Z2mServer;
+use crate::error::{ApiError, ApiResult};
+
+/// Groups and devices known to a zigbee2mqtt server
+#[derive(Clone, Debug, Default)]
+pub struct Z2mNetwork {
+    pub groups: Vec<Group>,
+    pub devices: Vec<Device>,
+}
+
+impl Z2mNetwork {
+    /// Connect to the server, and wait for its group and device lists
+    pub async fn fetch(server: &Z2mServer) -> ApiResult<Self> {
+        let (mut socket, _) = connect_async(server.get_url().as_str()).await?;
+
+        let mut groups = None;
+        let mut devices = None;
+
+        while groups.is_none() || devices.is_none() {
+            let pkt = socket.next().await.ok_or(ApiError::UnexpectedZ2mEof)??;
+            let tungstenite::Message::Text(txt) = pkt else {
+                continue;
+            };
+
+            /* device messages do not parse as bridge messages, and are skipped */
+            match serde_json::from_str(&txt) {
+                Ok(Message::BridgeGroups(obj)) => groups = Some(obj),
+                Ok(Message::BridgeDevices(obj)) => devices = Some(obj),
+                _ => {}
+            }
+        }
+
+        let _ = socket.close(None).await;
+
+        Ok(Self {
+            groups: groups.unwrap_or_default(),
+            devices: devices.unwrap_or_default(),
+        })
+    }
+}
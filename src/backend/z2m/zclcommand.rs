@@ -10,8 +10,11 @@ use serde_json::{json, Value};
 /// This is the case for z2m version 2.1.1 and newer.
 ///
 /// Older versions WILL NOT WORK.
+///
+/// The cluster can be given by name (e.g. "manuSpecificPhilips3") or by id.
 #[must_use]
-pub fn hue_zclcommand(cluster: &str, msg: &ZigbeeMessage) -> Value {
+pub fn hue_zclcommand(cluster: impl Into<Value>, msg: &ZigbeeMessage) -> Value {
+    let cluster = cluster.into();
     json!({
         "zclcommand": {
             "cluster": cluster,
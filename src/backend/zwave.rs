@@ -0,0 +1,569 @@
+//! Z-Wave devices, through a Z-Wave JS server
+//!
+//! Bifrost connects to the websocket api of the server (e.g. the one built
+//! into Z-Wave JS UI), and asks for the state of all nodes. Nodes with a
+//! multilevel switch (dimmers) or a binary switch become lights, and nodes
+//! that send central scene notifications (scene controllers, and many wall
+//! dimmers) get a button for each scene.
+//!
+//! Nodes included after bifrost connected show up after the next
+//! reconnect.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use maplit::btreeset;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+use tokio_tungstenite::{connect_async, tungstenite, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use hue::api::{
+    Button, ButtonEvent, DeviceArchetype, DeviceProductData, Light, LightUpdate, Metadata, On,
+    RType, Resource,
+};
+
+use crate::backend::virt::VirtualBackend;
+use crate::backend::{self, Backend, BackendRequest};
+use crate::config::{VirtualLightConfig, VirtualLightKind, ZwaveServerConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::resource::Resources;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Command classes we know about
+const CC_SWITCH_BINARY: u32 = 0x25;
+const CC_SWITCH_MULTILEVEL: u32 = 0x26;
+const CC_CENTRAL_SCENE: u32 = 0x5B;
+
+/// Highest level of a multilevel switch (`255` means "previous level")
+const LEVEL_MAX: f64 = 99.0;
+const LEVEL_PREVIOUS: u8 = 255;
+
+/// Message id of the initial `start_listening` command
+const START_LISTENING: &str = "start-listening";
+
+/// Central scene key attributes
+const KEY_PRESSED: u64 = 0;
+const KEY_RELEASED: u64 = 1;
+const KEY_HELD_DOWN: u64 = 2;
+const KEY_PRESSED_2X: u64 = 3;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceConfig {
+    label: Option<String>,
+    manufacturer: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeValue {
+    command_class: u32,
+    #[serde(default)]
+    endpoint: u32,
+    property: Value,
+    property_key: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    #[serde(rename = "nodeId")]
+    id: u32,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    device_config: Option<DeviceConfig>,
+    #[serde(default)]
+    values: Vec<NodeValue>,
+}
+
+impl Node {
+    fn name(&self) -> String {
+        self.name
+            .as_deref()
+            .filter(|name| !name.is_empty())
+            .or_else(|| self.device_config.as_ref()?.label.as_deref())
+            .map_or_else(|| format!("Z-Wave node {}", self.id), String::from)
+    }
+
+    fn product_data(&self) -> DeviceProductData {
+        let conf = self.device_config.as_ref();
+        DeviceProductData {
+            model_id: conf
+                .and_then(|conf| conf.label.clone())
+                .unwrap_or_else(|| "zwave".to_string()),
+            manufacturer_name: conf
+                .and_then(|conf| conf.manufacturer.clone())
+                .unwrap_or_else(|| "Z-Wave".to_string()),
+            product_name: conf
+                .and_then(|conf| conf.description.clone())
+                .unwrap_or_else(|| "Z-Wave device".to_string()),
+            product_archetype: DeviceArchetype::default(),
+            certified: false,
+            software_version: String::new(),
+            hardware_platform_type: None,
+        }
+    }
+
+    /// The switch this node can be controlled with, if any. Multilevel
+    /// switches are preferred over binary switches.
+    fn switch(&self) -> Option<Switch> {
+        let find = |cc| {
+            self.values.iter().find(|val| {
+                val.command_class == cc && val.property.as_str() == Some("currentValue")
+            })
+        };
+
+        find(CC_SWITCH_MULTILEVEL)
+            .map(|val| Switch::Multilevel(val.endpoint))
+            .or_else(|| find(CC_SWITCH_BINARY).map(|val| Switch::Binary(val.endpoint)))
+    }
+
+    /// Keys of the central scenes of this node (e.g. `"001"`)
+    fn scenes(&self) -> Vec<String> {
+        self.values
+            .iter()
+            .filter(|val| {
+                val.command_class == CC_CENTRAL_SCENE && val.property.as_str() == Some("scene")
+            })
+            .filter_map(|val| val.property_key.as_ref().and_then(scene_key))
+            .collect()
+    }
+}
+
+/// Switch of a node, with its endpoint
+#[derive(Clone, Copy, Debug)]
+enum Switch {
+    Binary(u32),
+    Multilevel(u32),
+}
+
+impl Switch {
+    /// Target value for a light update, if it changes anything this switch
+    /// can do
+    fn target(self, upd: &LightUpdate) -> Option<Value> {
+        let on = upd.on.map(|on| on.on);
+        match self {
+            Self::Binary(_) => on.map(Value::from),
+            Self::Multilevel(_) => {
+                if on == Some(false) {
+                    return Some(json!(0));
+                }
+                if let Some(dim) = &upd.dimming {
+                    let level = (dim.brightness / 100.0 * LEVEL_MAX).round();
+                    return Some(json!(level.clamp(1.0, LEVEL_MAX)));
+                }
+                on.map(|_| json!(LEVEL_PREVIOUS))
+            }
+        }
+    }
+
+    /// Light update for a reported switch value
+    fn report(self, value: &Value) -> Option<LightUpdate> {
+        match self {
+            Self::Binary(_) => value
+                .as_bool()
+                .map(|on| LightUpdate::new().with_on(On::new(on))),
+            Self::Multilevel(_) => value.as_f64().map(|level| {
+                let upd = LightUpdate::new().with_on(On::new(level > 0.0));
+                if level > 0.0 {
+                    upd.with_brightness(Some((level / LEVEL_MAX * 100.0).min(100.0)))
+                } else {
+                    upd
+                }
+            }),
+        }
+    }
+
+    const fn command_class(self) -> u32 {
+        match self {
+            Self::Binary(_) => CC_SWITCH_BINARY,
+            Self::Multilevel(_) => CC_SWITCH_MULTILEVEL,
+        }
+    }
+
+    const fn endpoint(self) -> u32 {
+        match self {
+            Self::Binary(ep) | Self::Multilevel(ep) => ep,
+        }
+    }
+}
+
+/// Central scene keys are strings (`"001"`) or numbers, depending on the
+/// api schema
+fn scene_key(key: &Value) -> Option<String> {
+    match key {
+        Value::String(key) => Some(key.clone()),
+        Value::Number(num) => Some(format!("{:03}", num.as_u64()?)),
+        _ => None,
+    }
+}
+
+/// Button events for a central scene notification, given the last event
+/// of the button
+fn scene_events(attribute: u64, last: Option<ButtonEvent>) -> Vec<ButtonEvent> {
+    match attribute {
+        KEY_PRESSED => vec![ButtonEvent::InitialPress, ButtonEvent::ShortRelease],
+        KEY_PRESSED_2X => vec![ButtonEvent::InitialPress, ButtonEvent::DoubleShortRelease],
+        KEY_HELD_DOWN if last == Some(ButtonEvent::Repeat) => vec![ButtonEvent::Repeat],
+        KEY_HELD_DOWN => vec![ButtonEvent::InitialPress, ButtonEvent::Repeat],
+        KEY_RELEASED => vec![ButtonEvent::LongRelease],
+        _ => vec![],
+    }
+}
+
+pub struct ZwaveBackend {
+    name: String,
+    /// Prefix for resource ids, so devices of different servers never clash
+    key: String,
+    server: ZwaveServerConfig,
+    state: Arc<Mutex<Resources>>,
+    /// Lights, with their node id and switch
+    lights: BTreeMap<Uuid, (u32, Switch)>,
+    /// Buttons, by node id and scene key
+    buttons: BTreeMap<(u32, String), Uuid>,
+    /// Id of the next command sent to the server
+    message_id: u64,
+}
+
+impl ZwaveBackend {
+    #[must_use]
+    pub fn new(name: String, server: ZwaveServerConfig, state: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            key: format!("zwave-{name}"),
+            name,
+            server,
+            state,
+            lights: BTreeMap::new(),
+            buttons: BTreeMap::new(),
+            message_id: 0,
+        }
+    }
+
+    fn light_for_node(&self, node_id: u32) -> Option<(Uuid, Switch)> {
+        self.lights
+            .iter()
+            .find(|(_, (node, _))| *node == node_id)
+            .map(|(rid, (_, switch))| (*rid, *switch))
+    }
+
+    async fn add_node(&mut self, node: &Node) -> ApiResult<()> {
+        let name = node.name();
+        let node_key = node.id.to_string();
+        let key = (self.key.as_str(), node_key.as_str());
+        let link_device = RType::Device.deterministic(key);
+
+        let mut res = self.state.lock().await;
+
+        let switch = node.switch();
+        if let Some(switch) = switch {
+            let conf = VirtualLightConfig {
+                name: name.clone(),
+                model_id: None,
+                kind: match switch {
+                    Switch::Binary(_) => VirtualLightKind::OnOff,
+                    Switch::Multilevel(_) => VirtualLightKind::White,
+                },
+                gradient: false,
+                count: None,
+                room: None,
+            };
+
+            for (link, mut obj) in VirtualBackend::light_resources(&conf, key, &name) {
+                if let Resource::Device(dev) = &mut obj {
+                    dev.product_data = node.product_data();
+                }
+                res.add(&link, obj)?;
+            }
+            self.lights
+                .insert(RType::Light.deterministic(key).rid, (node.id, switch));
+        }
+
+        let scenes = node.scenes();
+        if scenes.is_empty() {
+            drop(res);
+            return Ok(());
+        }
+
+        if switch.is_none() {
+            let dev = hue::api::Device {
+                product_data: node.product_data(),
+                metadata: Metadata::new(DeviceArchetype::UnknownArchetype, &name),
+                services: btreeset![],
+                identify: None,
+                usertest: None,
+            };
+            res.add(&link_device, Resource::Device(dev))?;
+        }
+
+        let mut links = vec![];
+        for scene in scenes {
+            let link = RType::Button.deterministic((key.0, format!("{node_key}/{scene}").as_str()));
+            let control_id = scene.parse().unwrap_or(1);
+            res.add(
+                &link,
+                Resource::Button(Button::new(link_device, control_id)),
+            )?;
+            self.buttons.insert((node.id, scene), link.rid);
+            links.push(link);
+        }
+        res.update::<hue::api::Device>(&link_device.rid, |device| {
+            device.services.extend(&links);
+        })?;
+        drop(res);
+
+        log::debug!("[{}] Added node {} ({name:?})", self.name, node.id);
+
+        Ok(())
+    }
+
+    async fn handle_result(&mut self, msg: &Value) -> ApiResult<()> {
+        let success = msg.get("success").and_then(Value::as_bool) == Some(true);
+        if !success {
+            log::warn!(
+                "[{}] Command {} failed: {}",
+                self.name,
+                msg.get("messageId").unwrap_or(&Value::Null),
+                msg.get("errorCode").unwrap_or(&Value::Null)
+            );
+            return Ok(());
+        }
+
+        if msg.get("messageId").and_then(Value::as_str) != Some(START_LISTENING) {
+            return Ok(());
+        }
+
+        let nodes = msg
+            .pointer("/result/state/nodes")
+            .cloned()
+            .unwrap_or_default();
+        let nodes: Vec<Node> = serde_json::from_value(nodes)?;
+        for node in &nodes {
+            self.add_node(node).await?;
+        }
+
+        let services = self.lights.keys().chain(self.buttons.values()).copied();
+        backend::set_connected(&self.state, &self.key, services, true).await;
+
+        log::info!(
+            "[{}] Found {} lights and {} buttons on {} nodes",
+            self.name,
+            self.lights.len(),
+            self.buttons.len(),
+            nodes.len()
+        );
+
+        Ok(())
+    }
+
+    async fn value_updated(&self, node_id: u32, args: &Value) -> ApiResult<()> {
+        let Some((rid, switch)) = self.light_for_node(node_id) else {
+            return Ok(());
+        };
+
+        let cc = args.get("commandClass").and_then(Value::as_u64);
+        let endpoint = args.get("endpoint").and_then(Value::as_u64).unwrap_or(0);
+        let property = args.get("property").and_then(Value::as_str);
+        if cc != Some(u64::from(switch.command_class()))
+            || endpoint != u64::from(switch.endpoint())
+            || property != Some("currentValue")
+        {
+            return Ok(());
+        }
+
+        let Some(upd) = args.get("newValue").and_then(|val| switch.report(val)) else {
+            return Ok(());
+        };
+
+        self.state
+            .lock()
+            .await
+            .update::<Light>(&rid, |light| *light += upd)
+    }
+
+    async fn value_notification(&self, node_id: u32, args: &Value) -> ApiResult<()> {
+        if args.get("commandClass").and_then(Value::as_u64) != Some(u64::from(CC_CENTRAL_SCENE)) {
+            return Ok(());
+        }
+
+        let Some(scene) = args.get("propertyKey").and_then(scene_key) else {
+            return Ok(());
+        };
+        let Some(rid) = self.buttons.get(&(node_id, scene)) else {
+            return Ok(());
+        };
+        let Some(attribute) = args.get("value").and_then(Value::as_u64) else {
+            return Ok(());
+        };
+
+        let mut res = self.state.lock().await;
+        let last = res.get_id::<Button>(*rid)?.button.last_event;
+        for event in scene_events(attribute, last) {
+            res.button_event(rid, event)?;
+        }
+        drop(res);
+
+        Ok(())
+    }
+
+    async fn handle_event(&self, event: &Value) -> ApiResult<()> {
+        if event.get("source").and_then(Value::as_str) != Some("node") {
+            return Ok(());
+        }
+        let Some(node_id) = event
+            .get("nodeId")
+            .and_then(Value::as_u64)
+            .and_then(|id| u32::try_from(id).ok())
+        else {
+            return Ok(());
+        };
+        let args = event.get("args").unwrap_or(&Value::Null);
+
+        match event.get("event").and_then(Value::as_str) {
+            Some("value updated") => self.value_updated(node_id, args).await,
+            Some("value notification") => self.value_notification(node_id, args).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn websocket_read(&mut self, pkt: tungstenite::Message) -> ApiResult<()> {
+        let tungstenite::Message::Text(txt) = pkt else {
+            return Ok(());
+        };
+
+        let msg: Value = serde_json::from_str(&txt)?;
+        match msg.get("type").and_then(Value::as_str) {
+            Some("version") => {
+                log::info!(
+                    "[{}] Connected to Z-Wave JS server {}",
+                    self.name,
+                    msg.get("serverVersion").unwrap_or(&Value::Null)
+                );
+                Ok(())
+            }
+            Some("result") => self.handle_result(&msg).await,
+            Some("event") => match msg.get("event") {
+                Some(event) => self.handle_event(event).await,
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    async fn websocket_send(&mut self, socket: &mut Socket, mut cmd: Value) -> ApiResult<()> {
+        if cmd.get("messageId").is_none() {
+            self.message_id += 1;
+            cmd["messageId"] = json!(self.message_id.to_string());
+        }
+
+        let json = serde_json::to_string(&cmd)?;
+        log::debug!("[{}] Sending {json}", self.name);
+        Ok(socket.send(tungstenite::Message::text(json)).await?)
+    }
+
+    /// Send commands for a backend request, if it concerns any of our lights
+    async fn websocket_write(
+        &mut self,
+        socket: &mut Socket,
+        req: &BackendRequest,
+    ) -> ApiResult<()> {
+        let lock = self.state.lock().await;
+        let updates = backend::light_updates(&lock, req, |rid| self.lights.contains_key(rid))?;
+        drop(lock);
+
+        for (rid, upd) in updates {
+            let Some((node_id, switch)) = self.lights.get(&rid).copied() else {
+                continue;
+            };
+            let Some(value) = switch.target(&upd) else {
+                continue;
+            };
+
+            let mut cmd = json!({
+                "command": "node.set_value",
+                "nodeId": node_id,
+                "valueId": {
+                    "commandClass": switch.command_class(),
+                    "endpoint": switch.endpoint(),
+                    "property": "targetValue",
+                },
+                "value": value,
+            });
+            if let Some(duration) = upd.dynamics.as_ref().and_then(|dyn_| dyn_.duration) {
+                cmd["options"] = json!({"transitionDuration": format!("{}s", duration / 1000)});
+            }
+
+            self.websocket_send(socket, cmd).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn event_loop(
+        &mut self,
+        chan: &mut Receiver<Arc<BackendRequest>>,
+        mut socket: Socket,
+    ) -> ApiResult<()> {
+        let start = json!({"messageId": START_LISTENING, "command": "start_listening"});
+        self.websocket_send(&mut socket, start).await?;
+
+        loop {
+            tokio::select! {
+                pkt = chan.recv() => {
+                    let req = match pkt {
+                        Ok(req) => req,
+                        Err(RecvError::Lagged(count)) => {
+                            log::warn!("[{}] Backend lagging, skipped {count} requests", self.name);
+                            continue;
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+                    if let Err(err) = self.websocket_write(&mut socket, &req).await {
+                        log::error!("[{}] Failed to handle request: {err}", self.name);
+                    }
+                }
+                pkt = socket.next() => {
+                    let pkt = pkt.ok_or(ApiError::UnexpectedZwaveEof)??;
+                    if let Err(err) = self.websocket_read(pkt).await {
+                        log::error!("[{}] Failed to handle message: {err}", self.name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for ZwaveBackend {
+    async fn run_forever(mut self, mut chan: Receiver<Arc<BackendRequest>>) -> ApiResult<()> {
+        loop {
+            log::info!("[{}] Connecting to {}", self.name, self.server.url);
+            self.state.lock().await.backend_connect_attempt(&self.key);
+            match connect_async(self.server.url.as_str()).await {
+                Ok((socket, _)) => {
+                    if let Err(err) = self.event_loop(&mut chan, socket).await {
+                        log::error!("[{}] Event loop broke: {err}", self.name);
+                        self.state.lock().await.set_backend_error(&self.key, &err);
+                    }
+                }
+                Err(err) => {
+                    log::error!("[{}] Connect failed: {err:?}", self.name);
+                    self.state.lock().await.set_backend_error(&self.key, &err);
+                }
+            }
+            let services = self.lights.keys().chain(self.buttons.values()).copied();
+            backend::set_connected(&self.state, &self.key, services, false).await;
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use url::Url;
+use uuid::Uuid;
+
+use bifrost::backend::z2m::snapshot::Z2mNetwork;
+use bifrost::config::starter::{self, StarterBridge};
+use bifrost::config::Z2mServer;
+use bifrost::error::{ApiError, ApiResult};
+use bifrost::model::state::State;
+use bifrost::model::statediff::{self, DisplayValue, ResourceDiff};
+use hue::error::HueError;
+
+#[derive(Parser, Debug)]
+#[command(version, long_about = None)]
+#[command(about("Bifrost command line tools"))]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect bifrost state files
+    #[command(subcommand)]
+    State(StateCommand),
+
+    /// Manage scenes on a running bifrost server
+    #[command(subcommand)]
+    Scene(SceneCommand),
+
+    /// Create bifrost configuration files
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum StateCommand {
+    /// Show differences between two state files
+    Diff { old: Utf8PathBuf, new: Utf8PathBuf },
+
+    /// Show a single resource, and all links to and from it
+    Show {
+        /// State file to read
+        #[arg(short, long, default_value = "state.yaml")]
+        file: Utf8PathBuf,
+
+        id: Uuid,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SceneCommand {
+    /// Copy a scene to another room or zone
+    Copy {
+        /// Address of the bifrost server
+        #[arg(short, long, default_value = "http://localhost")]
+        url: Url,
+
+        /// Api key configured with `admin: true` on the bifrost server
+        #[arg(short, long)]
+        key: String,
+
+        /// Name of the new scene (defaults to the name of the copied scene)
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Scene to copy
+        scene: Uuid,
+
+        /// Room or zone to copy the scene to
+        target: Uuid,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Generate a starter configuration, from the groups and devices of a
+    /// zigbee2mqtt server
+    Generate {
+        /// Websocket address of the zigbee2mqtt server
+        #[arg(short, long)]
+        url: Url,
+
+        /// Zigbee2mqtt auth token, if not given in the url
+        #[arg(short, long)]
+        token: Option<String>,
+
+        /// File to write (defaults to standard output). Existing files are
+        /// not overwritten.
+        #[arg(short, long)]
+        output: Option<Utf8PathBuf>,
+    },
+}
+
+/// How long to wait for the zigbee2mqtt server
+const Z2M_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn load(path: &Utf8Path) -> ApiResult<State> {
+    State::from_reader(File::open(path)?)
+}
+
+fn state_diff(old: &Utf8Path, new: &Utf8Path) -> ApiResult<()> {
+    let old = load(old)?;
+    let new = load(new)?;
+
+    for change in statediff::diff(&old, &new)? {
+        match &change {
+            ResourceDiff::Added(link) => println!("+ {}", new.describe_link(link)),
+            ResourceDiff::Removed(link) => println!("- {}", old.describe_link(link)),
+            ResourceDiff::Changed(link, fields) => {
+                println!("~ {}", new.describe_link(link));
+                for field in fields {
+                    let before = DisplayValue {
+                        state: &old,
+                        value: field.old.as_ref(),
+                    };
+                    let after = DisplayValue {
+                        state: &new,
+                        value: field.new.as_ref(),
+                    };
+                    println!("    {}: {before} -> {after}", field.path);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn state_show(file: &Utf8Path, id: &Uuid) -> ApiResult<()> {
+    let state = load(file)?;
+    let obj = state.get(id)?;
+    let link = obj.rtype().link_to(*id);
+    let value = serde_json::to_value(obj)?;
+
+    println!("{}", state.describe_link(&link));
+    if let Some(aux) = state.try_aux_get(id) {
+        println!("  aux: {}", serde_json::to_string(aux)?);
+    }
+    println!("{}", serde_json::to_string_pretty(&value)?);
+
+    println!("\nLinks to:");
+    for target in statediff::collect_links(&value) {
+        let missing = if state.try_get(&target.rid).is_none() {
+            " [MISSING]"
+        } else {
+            ""
+        };
+        println!("  {}{missing}", state.describe_link(&target));
+    }
+
+    println!("\nLinked from:");
+    for source in state.references_to(id) {
+        println!("  {}", state.describe_link(&source));
+    }
+
+    Ok(())
+}
+
+fn scene_copy(
+    url: &Url,
+    key: &str,
+    scene: &Uuid,
+    target: &Uuid,
+    name: Option<&str>,
+) -> ApiResult<()> {
+    let url = url.join(&format!("bifrost/scenes/{scene}/copy"))?;
+    let body = json!({"target": target, "name": name});
+
+    let reply: Value = tokio::runtime::Runtime::new()?.block_on(async {
+        reqwest::Client::new()
+            .post(url)
+            .header("hue-application-key", key)
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await
+    })?;
+
+    match reply
+        .pointer("/errors/0/description")
+        .and_then(Value::as_str)
+    {
+        Some(err) => eprintln!("Failed to copy scene: {err}"),
+        None => println!("Created scene {reply}"),
+    }
+
+    Ok(())
+}
+
+fn config_generate(url: &Url, token: Option<&str>, output: Option<&Utf8Path>) -> ApiResult<()> {
+    let mut server = Z2mServer::new(url.clone());
+    server.token = token.map(ToString::to_string);
+
+    let network = tokio::runtime::Runtime::new()?.block_on(async {
+        tokio::time::timeout(Z2M_TIMEOUT, Z2mNetwork::fetch(&server))
+            .await
+            .map_err(|_| ApiError::Z2mTimeout)?
+    })?;
+
+    let bridge = StarterBridge::detect(url);
+    let text = starter::generate(&bridge, url, token, &network.groups, &network.devices);
+
+    match output {
+        Some(path) => {
+            let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+            file.write_all(text.as_bytes())?;
+            eprintln!("Wrote {path}");
+        }
+        None => print!("{text}"),
+    }
+
+    Ok(())
+}
+
+fn main() -> ApiResult<()> {
+    pretty_env_logger::formatted_builder()
+        .filter_level(log::LevelFilter::Warn)
+        .parse_default_env()
+        .init();
+
+    let args = Args::parse();
+
+    let res = match &args.command {
+        Command::State(StateCommand::Diff { old, new }) => state_diff(old, new),
+        Command::State(StateCommand::Show { file, id }) => state_show(file, id),
+        Command::Scene(SceneCommand::Copy {
+            url,
+            key,
+            name,
+            scene,
+            target,
+        }) => scene_copy(url, key, scene, target, name.as_deref()),
+        Command::Config(ConfigCommand::Generate { url, token, output }) => {
+            config_generate(url, token.as_deref(), output.as_deref())
+        }
+    };
+
+    if let Err(ApiError::HueError(HueError::NotFound(id))) = &res {
+        eprintln!("Resource {id} not found");
+        std::process::exit(1);
+    }
+
+    res
+}
@@ -0,0 +1,360 @@
+//! Finding keys and values in a configuration file, for pointing out where
+//! a problem is.
+//!
+//! This is not a full yaml or toml parser. It understands block mappings and
+//! sequences (yaml), and tables, arrays of tables and dotted keys (toml),
+//! which covers the way configuration files are normally written. When a
+//! path cannot be followed all the way (e.g. into an inline table), the
+//! position of the deepest key that was found is used.
+
+use camino::Utf8Path;
+
+use crate::error::ApiResult;
+
+/// Line and column, both starting at 1
+pub type Position = (usize, usize);
+
+/// The text of a configuration file
+pub struct ConfigSource {
+    pub text: String,
+    pub toml: bool,
+}
+
+/// A line of yaml, without indentation. Blank lines and comments are `None`
+type YamlLine<'a> = Option<(usize, &'a str)>;
+
+fn unquote(key: &str) -> &str {
+    let key = key.trim();
+    key.strip_prefix('"')
+        .and_then(|k| k.strip_suffix('"'))
+        .or_else(|| key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')))
+        .unwrap_or(key)
+}
+
+/// True if `key` looks like a (possibly dotted or quoted) toml key, and not
+/// e.g. a string in a multi-line array
+fn is_toml_key(key: &str) -> bool {
+    let key = key.trim();
+    !key.is_empty()
+        && key.matches('"').count() % 2 == 0
+        && key.matches('\'').count() % 2 == 0
+        && (key.starts_with(['"', '\''])
+            || key.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+/// True if `line` is the key `key` of a mapping
+fn is_yaml_key(line: YamlLine, key: &str) -> bool {
+    line.is_some_and(|(_, content)| {
+        content.split_once(':').is_some_and(|(name, rest)| {
+            unquote(name) == key && (rest.is_empty() || rest.starts_with([' ', '\t']))
+        })
+    })
+}
+
+/// True if `line` starts an item of a sequence
+fn is_yaml_item(line: YamlLine) -> bool {
+    line.is_some_and(|(_, content)| content == "-" || content.starts_with("- "))
+}
+
+fn column(line: &str, byte: usize) -> usize {
+    line[..byte].chars().count() + 1
+}
+
+impl ConfigSource {
+    pub fn read(filename: &Utf8Path) -> ApiResult<Self> {
+        Ok(Self {
+            text: std::fs::read_to_string(filename)?,
+            toml: super::is_toml(filename),
+        })
+    }
+
+    /// Position of a byte offset into the text
+    #[must_use]
+    pub fn position(&self, offset: usize) -> Position {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+
+    /// Position of the key at `path` (e.g. `["rooms", "kitchen", "name"]`,
+    /// with sequence items given by their index)
+    #[must_use]
+    pub fn find_key(&self, path: &[&str]) -> Option<Position> {
+        self.find(path).map(|(pos, _)| pos)
+    }
+
+    /// Position of `value`, in the value of the key at `path` (e.g. an item
+    /// of a list)
+    #[must_use]
+    pub fn find_value(&self, path: &[&str], value: &str) -> Option<Position> {
+        let (pos, lines) = self.find(path)?;
+        let quoted = [format!("\"{value}\""), format!("'{value}'")];
+
+        self.text
+            .lines()
+            .enumerate()
+            .skip(pos.0 - 1)
+            .take(lines)
+            .find_map(|(idx, line)| {
+                let found = quoted
+                    .iter()
+                    .find_map(|q| line.find(q.as_str()))
+                    .or_else(|| if self.toml { None } else { line.find(value) })?;
+                Some((idx + 1, column(line, found)))
+            })
+            .or(Some(pos))
+    }
+
+    /// Position of the deepest key of `path` found, and the number of lines
+    /// its value spans
+    fn find(&self, path: &[&str]) -> Option<(Position, usize)> {
+        if self.toml {
+            self.find_toml(path)
+        } else {
+            self.find_yaml(path)
+        }
+    }
+
+    fn find_yaml(&self, path: &[&str]) -> Option<(Position, usize)> {
+        let mut lines: Vec<YamlLine> = self
+            .text
+            .lines()
+            .map(|line| {
+                let content = line.trim_start();
+                if content.is_empty() || content.starts_with('#') || content.starts_with("---") {
+                    None
+                } else {
+                    Some((line.len() - content.len(), content))
+                }
+            })
+            .collect();
+
+        // end of the block starting at `start`, with children indented more
+        // than `indent`
+        let block_end = |lines: &[YamlLine], start: usize, end: usize, indent: usize| {
+            (start + 1..end)
+                .find(|idx| lines[*idx].is_some_and(|(ind, _)| ind <= indent))
+                .unwrap_or(end)
+        };
+
+        let (mut lo, mut hi) = (0, lines.len());
+        let mut found = None;
+
+        for segment in path {
+            let Some(child) = lines[lo..hi].iter().flatten().map(|(ind, _)| *ind).min() else {
+                break;
+            };
+
+            let at_child = |idx: &usize| lines[*idx].is_some_and(|(ind, _)| ind == child);
+
+            let index = segment.parse::<usize>().ok();
+            let hit = index.map_or_else(
+                || {
+                    (lo..hi)
+                        .filter(at_child)
+                        .find(|idx| is_yaml_key(lines[*idx], segment))
+                },
+                |index| {
+                    (lo..hi)
+                        .filter(at_child)
+                        .filter(|idx| is_yaml_item(lines[*idx]))
+                        .nth(index)
+                },
+            );
+
+            let Some(idx) = hit else {
+                break;
+            };
+
+            let end = block_end(&lines, idx, hi, child);
+            found = Some(((idx + 1, child + 1), end - idx));
+
+            if let (Some(_), Some((ind, content))) = (index, lines[idx]) {
+                // the item itself may start with a key ("- name: x"), which
+                // is a child of the item
+                let rest = content[1..].trim_start();
+                lines[idx] = (!rest.is_empty()).then(|| (ind + content.len() - rest.len(), rest));
+                (lo, hi) = (idx, end);
+            } else {
+                (lo, hi) = (idx + 1, end);
+            }
+        }
+
+        found
+    }
+
+    fn find_toml(&self, path: &[&str]) -> Option<(Position, usize)> {
+        let lines: Vec<&str> = self.text.lines().collect();
+
+        // full path of each table header and key, in order
+        let mut table: Vec<String> = vec![];
+        let mut arrays: Vec<Vec<String>> = vec![];
+        let mut keys: Vec<(usize, usize, Vec<String>)> = vec![];
+
+        for (idx, line) in lines.iter().enumerate() {
+            let content = line.trim_start();
+            let indent = line.len() - content.len();
+
+            if let Some(name) = content.strip_prefix("[[") {
+                let name = name.split("]]").next().unwrap_or_default();
+                let name: Vec<String> = name.split('.').map(|s| unquote(s).to_string()).collect();
+                let count = arrays.iter().filter(|arr| **arr == name).count();
+                arrays.push(name.clone());
+                table = name;
+                table.push(count.to_string());
+                keys.push((idx, indent, table.clone()));
+            } else if let Some(name) = content.strip_prefix('[') {
+                let name = name.split(']').next().unwrap_or_default();
+                table = name.split('.').map(|s| unquote(s).to_string()).collect();
+                keys.push((idx, indent, table.clone()));
+            } else if let Some((key, _)) = content.split_once('=') {
+                if !is_toml_key(key) {
+                    continue;
+                }
+                let mut full = table.clone();
+                full.extend(key.split('.').map(|s| unquote(s).to_string()));
+                keys.push((idx, indent, full));
+            }
+        }
+
+        let depth = |full: &[String]| {
+            let common = full.iter().zip(path).take_while(|(a, b)| a == *b).count();
+            (common == full.len()).then_some(common)
+        };
+
+        // the first of the deepest matches
+        let (pos, (idx, indent, _)) = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, key)| Some((depth(&key.2)?, pos, key)))
+            .max_by_key(|(depth, pos, _)| (*depth, std::cmp::Reverse(*pos)))
+            .map(|(_, pos, key)| (pos, key))?;
+
+        let end = keys.get(pos + 1).map_or(lines.len(), |(next, _, _)| *next);
+        Some(((idx + 1, indent + 1), end - idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::locate::ConfigSource;
+
+    fn yaml(text: &str) -> ConfigSource {
+        ConfigSource {
+            text: text.to_string(),
+            toml: false,
+        }
+    }
+
+    fn toml(text: &str) -> ConfigSource {
+        ConfigSource {
+            text: text.to_string(),
+            toml: true,
+        }
+    }
+
+    const YAML: &str = "\
+bridge:
+  name: Bifrost
+rooms:
+  # the name key of a room
+  name:
+    name: Living room
+  kitchen:
+    name: Kitchen
+    devices: [Lamp, Spot]
+virtual:
+  lights:
+    - name: Lamp
+      room: kitchen
+    -
+      name: Spot
+";
+
+    #[test]
+    fn yaml_keys_within_parent() {
+        let src = yaml(YAML);
+        assert_eq!(src.find_key(&["bridge", "name"]), Some((2, 3)));
+        assert_eq!(src.find_key(&["rooms", "name"]), Some((5, 3)));
+        assert_eq!(src.find_key(&["rooms", "kitchen", "name"]), Some((8, 5)));
+        assert_eq!(
+            src.find_key(&["virtual", "lights", "0", "room"]),
+            Some((13, 7))
+        );
+        assert_eq!(
+            src.find_key(&["virtual", "lights", "1", "name"]),
+            Some((15, 7))
+        );
+    }
+
+    #[test]
+    fn yaml_partial_path() {
+        // the deepest key found is used
+        let src = yaml(YAML);
+        assert_eq!(src.find_key(&["rooms", "kitchen", "missing"]), Some((7, 3)));
+        assert_eq!(src.find_key(&["missing"]), None);
+    }
+
+    #[test]
+    fn yaml_values() {
+        let src = yaml(YAML);
+        assert_eq!(
+            src.find_value(&["rooms", "kitchen", "devices"], "Spot"),
+            Some((9, 21))
+        );
+    }
+
+    const TOML: &str = "\
+[bridge]
+name = \"Bifrost\"
+
+[rooms.kitchen]
+name = \"Kitchen\"
+devices = [
+  \"Lamp\",
+  \"Spot\",
+]
+
+[[virtual.lights]]
+name = \"Lamp\"
+
+[[virtual.lights]]
+name = \"Spot\"
+room.name = \"kitchen\"
+";
+
+    #[test]
+    fn toml_keys_within_parent() {
+        let src = toml(TOML);
+        assert_eq!(src.find_key(&["bridge", "name"]), Some((2, 1)));
+        assert_eq!(src.find_key(&["rooms", "kitchen", "name"]), Some((5, 1)));
+        assert_eq!(
+            src.find_key(&["virtual", "lights", "1", "name"]),
+            Some((15, 1))
+        );
+        assert_eq!(
+            src.find_key(&["virtual", "lights", "1", "room", "name"]),
+            Some((16, 1))
+        );
+        assert_eq!(src.find_key(&["rooms", "kitchen", "missing"]), Some((4, 1)));
+    }
+
+    #[test]
+    fn toml_values() {
+        let src = toml(TOML);
+        assert_eq!(
+            src.find_value(&["rooms", "kitchen", "devices"], "Spot"),
+            Some((8, 3))
+        );
+    }
+
+    #[test]
+    fn offset_position() {
+        let src = yaml("a: 1\nbé: x\n");
+        assert_eq!(src.position(0), (1, 1));
+        assert_eq!(src.position(9), (2, 4));
+    }
+}
@@ -0,0 +1,2182 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use config::{Config, ConfigError, Environment, FileFormat};
+use ipnet::IpNet;
+use mac_address::MacAddress;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use hue::api::{DimmingDeltaUpdate, GamutType, RType, RoomArchetype};
+use hue::curve::BrightnessCurve;
+use hue::devicedb::QuirkDb;
+use hue::zigbee::EntertainmentZigbeeStream;
+use z2m::api::IeeeAddress;
+use z2m::update::DEFAULT_BRIGHTNESS_RATE;
+
+use crate::config::locate::ConfigSource;
+use crate::error::{ApiError, ApiResult};
+
+pub mod locate;
+pub mod starter;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BridgeConfig {
+    pub name: String,
+    pub mac: MacAddress,
+    pub ipaddress: Ipv4Addr,
+    #[serde(default = "default_http_port")]
+    pub http_port: u16,
+    #[serde(default = "default_https_port")]
+    pub https_port: u16,
+    #[serde(default = "default_entm_port")]
+    pub entm_port: u16,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub timezone: String,
+    pub swversion: Option<u64>,
+    pub apiversion: Option<String>,
+    pub bridge_id: Option<String>,
+    #[serde(default)]
+    pub listen: Vec<ListenConfig>,
+}
+
+/// An address to listen on, for the http, https and entertainment services
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ListenConfig {
+    pub address: IpAddr,
+
+    /// Address advertised over mDNS for this interface, if different from
+    /// the listen address (required for wildcard addresses)
+    pub advertise: Option<IpAddr>,
+}
+
+impl BridgeConfig {
+    /// Bridge id (16 lowercase hex digits). Derived from [`Self::mac`],
+    /// unless set explicitly.
+    #[must_use]
+    pub fn bridge_id(&self) -> String {
+        self.bridge_id
+            .as_ref()
+            .map_or_else(|| hue::bridge_id(self.mac), |id| id.to_ascii_lowercase())
+    }
+
+    fn validate(&self) -> ApiResult<()> {
+        if let Some(id) = &self.bridge_id {
+            if id.len() != 16 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ApiError::InvalidBridgeId(id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Addresses to listen on. Defaults to [`Self::ipaddress`] if no
+    /// addresses are configured.
+    #[must_use]
+    pub fn listen_addrs(&self) -> Vec<ListenConfig> {
+        if self.listen.is_empty() {
+            return vec![ListenConfig {
+                address: self.ipaddress.into(),
+                advertise: None,
+            }];
+        }
+        self.listen.clone()
+    }
+
+    /// Addresses to advertise over mDNS. Wildcard listen addresses are only
+    /// advertised through their `advertise` address.
+    #[must_use]
+    pub fn advertised_addrs(&self) -> Vec<IpAddr> {
+        let mut addrs: Vec<IpAddr> = self
+            .listen_addrs()
+            .iter()
+            .filter_map(|listen| {
+                listen
+                    .advertise
+                    .or_else(|| Some(listen.address).filter(|addr| !addr.is_unspecified()))
+            })
+            .collect();
+
+        if addrs.is_empty() {
+            addrs.push(self.ipaddress.into());
+        }
+        addrs.dedup();
+        addrs
+    }
+}
+
+#[must_use]
+pub const fn default_http_port() -> u16 {
+    80
+}
+
+const fn default_https_port() -> u16 {
+    443
+}
+
+const fn default_entm_port() -> u16 {
+    2100
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BifrostConfig {
+    #[serde(default = "default_state_file")]
+    pub state_file: Utf8PathBuf,
+    #[serde(default = "default_automation_file")]
+    pub automation_file: Utf8PathBuf,
+    #[serde(default = "default_crash_file")]
+    pub crash_file: Utf8PathBuf,
+    #[serde(default = "default_cert_file")]
+    pub cert_file: Utf8PathBuf,
+    /// Private key for the certificate, if it is not in the certificate
+    /// file itself (e.g. to keep it in a secret store)
+    pub key_file: Option<Utf8PathBuf>,
+    #[serde(default = "default_audit_file")]
+    pub audit_file: Utf8PathBuf,
+    #[serde(default = "default_audit_max_entries")]
+    pub audit_max_entries: usize,
+    #[serde(default)]
+    pub strict_json: bool,
+    /// Reject changes made through the hue api until every backend has
+    /// connected once, instead of accepting changes that cannot be sent
+    #[serde(default)]
+    pub read_only_startup: bool,
+    /// Delete orphaned resources at startup. When disabled, the consistency
+    /// check only reports what it would change.
+    #[serde(default)]
+    pub gc_on_startup: bool,
+    /// Time (in milliseconds) to wait for devices to confirm light changes
+    /// made through the hue api. Without it, changes are not confirmed.
+    pub command_timeout: Option<u64>,
+    /// Shortest time (in milliseconds) between updates sent to the same
+    /// light. Updates made in between are merged.
+    #[serde(default = "default_min_command_interval")]
+    pub min_command_interval: u64,
+    pub quirks_file: Option<Utf8PathBuf>,
+    pub ota_dir: Option<Utf8PathBuf>,
+    #[serde(default = "default_image_dir")]
+    pub image_dir: Utf8PathBuf,
+}
+
+impl Default for BifrostConfig {
+    fn default() -> Self {
+        Self {
+            state_file: default_state_file(),
+            automation_file: default_automation_file(),
+            crash_file: default_crash_file(),
+            cert_file: default_cert_file(),
+            key_file: None,
+            audit_file: default_audit_file(),
+            audit_max_entries: default_audit_max_entries(),
+            strict_json: false,
+            read_only_startup: false,
+            gc_on_startup: false,
+            command_timeout: None,
+            min_command_interval: default_min_command_interval(),
+            quirks_file: None,
+            ota_dir: None,
+            image_dir: default_image_dir(),
+        }
+    }
+}
+
+fn default_state_file() -> Utf8PathBuf {
+    "state.yaml".into()
+}
+
+fn default_automation_file() -> Utf8PathBuf {
+    "automations.yaml".into()
+}
+
+fn default_crash_file() -> Utf8PathBuf {
+    "crash.json".into()
+}
+
+fn default_cert_file() -> Utf8PathBuf {
+    "cert.pem".into()
+}
+
+fn default_audit_file() -> Utf8PathBuf {
+    "audit.log".into()
+}
+
+const fn default_audit_max_entries() -> usize {
+    10000
+}
+
+const fn default_min_command_interval() -> u64 {
+    100
+}
+
+fn default_image_dir() -> Utf8PathBuf {
+    "images".into()
+}
+
+impl BifrostConfig {
+    /// File to load the private key of the certificate from
+    #[must_use]
+    pub fn key_file(&self) -> &Utf8Path {
+        self.key_file.as_deref().unwrap_or(&self.cert_file)
+    }
+
+    /// Load the device database: the bundled one, with the user database
+    /// (if configured) on top.
+    pub fn load_quirks(&self) -> ApiResult<QuirkDb> {
+        let db = match &self.quirks_file {
+            Some(path) => QuirkDb::load(path.as_std_path())?,
+            None => QuirkDb::bundled(),
+        };
+        Ok(db)
+    }
+
+    /// Path of the named firmware file, which must be a plain file name
+    /// inside [`Self::ota_dir`]
+    pub fn ota_path(&self, name: &str) -> ApiResult<Utf8PathBuf> {
+        let dir = self.ota_dir.as_ref().ok_or(ApiError::OtaDisabled)?;
+
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(ApiError::OtaFileName(name.to_string()));
+        }
+
+        Ok(dir.join(name))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Z2mConfig {
+    #[serde(flatten)]
+    pub servers: HashMap<String, Z2mServer>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Z2mServer {
+    pub url: Url,
+    /// Auth token, if not given in the url
+    pub token: Option<String>,
+    pub token_file: Option<Utf8PathBuf>,
+    pub group_prefix: Option<String>,
+    #[serde(default)]
+    pub native_scenes: bool,
+    #[serde(default)]
+    pub room_policy: RoomPolicy,
+    #[serde(default)]
+    pub room_allowlist: BTreeSet<String>,
+    #[serde(default)]
+    pub group_repair: bool,
+    #[serde(default)]
+    pub traffic: TrafficWeights,
+    #[serde(default)]
+    pub include: Vec<DeviceMatch>,
+    #[serde(default)]
+    pub exclude: Vec<DeviceMatch>,
+}
+
+/// Relative share of the connection for each traffic class, when both have
+/// requests waiting (see [`crate::backend::traffic`])
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct TrafficWeights {
+    #[serde(default = "default_entertainment_weight")]
+    pub entertainment: u32,
+    #[serde(default = "default_commands_weight")]
+    pub commands: u32,
+}
+
+const fn default_entertainment_weight() -> u32 {
+    4
+}
+
+const fn default_commands_weight() -> u32 {
+    1
+}
+
+impl Default for TrafficWeights {
+    fn default() -> Self {
+        Self {
+            entertainment: default_entertainment_weight(),
+            commands: default_commands_weight(),
+        }
+    }
+}
+
+/// Selects zigbee2mqtt devices by friendly name (glob pattern), ieee
+/// address or tag. All conditions that are given must match.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct DeviceMatch {
+    pub name: Option<String>,
+    pub ieee: Option<IeeeAddress>,
+    pub tag: Option<String>,
+}
+
+impl DeviceMatch {
+    /// True if the rule has at least one condition, and all of them match
+    #[must_use]
+    pub fn matches(&self, dev: &z2m::api::Device) -> bool {
+        if self.name.is_none() && self.ieee.is_none() && self.tag.is_none() {
+            return false;
+        }
+
+        self.name
+            .as_ref()
+            .map_or(true, |pat| glob_match(pat, &dev.friendly_name))
+            && self
+                .ieee
+                .as_ref()
+                .map_or(true, |ieee| *ieee == dev.ieee_address)
+            && self
+                .tag
+                .as_ref()
+                .map_or(true, |tag| dev.tags().any(|t| t == tag))
+    }
+}
+
+/// Match `text` against a glob pattern, where `*` matches any number of
+/// characters, and `?` matches a single character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut pos, mut idx) = (0, 0);
+    /* position of the last '*' seen, and where in the text it started */
+    let mut star = None;
+
+    while idx < text.len() {
+        match pattern.get(pos) {
+            Some('*') => {
+                star = Some((pos, idx));
+                pos += 1;
+            }
+            Some(&ch) if ch == '?' || ch == text[idx] => {
+                pos += 1;
+                idx += 1;
+            }
+            _ => {
+                let Some((star_pos, star_idx)) = star else {
+                    return false;
+                };
+                star = Some((star_pos, star_idx + 1));
+                pos = star_pos + 1;
+                idx = star_idx + 1;
+            }
+        }
+    }
+
+    pattern[pos..].iter().all(|ch| *ch == '*')
+}
+
+/// Which zigbee2mqtt groups are turned into rooms
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomPolicy {
+    /// Every group becomes a room
+    #[default]
+    All,
+
+    /// Only groups listed in `room_allowlist` become rooms
+    Allowlist,
+
+    /// No groups become rooms. Rooms are only created from the "devices"
+    /// lists in the rooms section.
+    Manual,
+}
+
+impl Z2mServer {
+    /// Server with the given url, and default settings
+    #[must_use]
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            token: None,
+            token_file: None,
+            group_prefix: None,
+            native_scenes: false,
+            room_policy: RoomPolicy::default(),
+            room_allowlist: BTreeSet::new(),
+            group_repair: false,
+            traffic: TrafficWeights::default(),
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+
+    /// True if the given zigbee2mqtt group should be turned into a room
+    #[must_use]
+    pub fn is_room_allowed(&self, friendly_name: &str) -> bool {
+        match self.room_policy {
+            RoomPolicy::All => true,
+            RoomPolicy::Allowlist => self.room_allowlist.contains(friendly_name),
+            RoomPolicy::Manual => false,
+        }
+    }
+
+    /// True if the given zigbee2mqtt device should be visible through the
+    /// hue bridge: it must match an `include` rule (if there are any), and
+    /// must not match any `exclude` rule.
+    #[must_use]
+    pub fn is_device_exposed(&self, dev: &z2m::api::Device) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|rule| rule.matches(dev)))
+            && !self.exclude.iter().any(|rule| rule.matches(dev))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct RoomConfig {
+    pub name: Option<String>,
+    pub icon: Option<RoomArchetype>,
+
+    /// Lowest brightness (in percent) for lights in this room
+    pub min_brightness: Option<f64>,
+
+    /// Highest brightness (in percent) for lights in this room
+    pub max_brightness: Option<f64>,
+
+    /// Transition time (in milliseconds) for lights in this room
+    pub transition: Option<u32>,
+
+    /// Color gamut for lights in this room (overriding the detected one)
+    pub gamut: Option<GamutType>,
+
+    /// Whether lights in this room are visible through the hue bridge
+    pub expose: Option<bool>,
+
+    /// Devices (by zigbee2mqtt friendly name) in this room. If given, the
+    /// room is created from these devices, instead of from a group.
+    #[serde(default)]
+    pub devices: Vec<String>,
+}
+
+impl RoomConfig {
+    #[must_use]
+    pub const fn brightness_limits(&self) -> BrightnessLimits {
+        BrightnessLimits {
+            min: self.min_brightness,
+            max: self.max_brightness,
+        }
+    }
+}
+
+/// Allowed brightness range (in percent), where each end is optional
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BrightnessLimits {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl BrightnessLimits {
+    /// Fill in unset limits from `other`
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            min: self.min.or(other.min),
+            max: self.max.or(other.max),
+        }
+    }
+
+    #[must_use]
+    pub fn clamp(self, brightness: f64) -> f64 {
+        let brightness = self.min.map_or(brightness, |min| brightness.max(min));
+        self.max.map_or(brightness, |max| brightness.min(max))
+    }
+    /// Limit a relative brightness change (from hue `dimming_delta`), so
+    /// that none of the lights it applies to ends up outside the limits, or
+    /// turned off.
+    ///
+    /// `current` is the brightness of each light (in percent). Steps are
+    /// shortened to fit, and moves become a step to the nearest limit, at the
+    /// same rate. Returns the step (in zigbee2mqtt units) and its transition
+    /// time (in milliseconds, if not the default), or `None` when stopping.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn limit_delta(
+        self,
+        curve: BrightnessCurve,
+        current: &[f64],
+        delta: &DimmingDeltaUpdate,
+        duration: Option<u32>,
+    ) -> Option<(i32, Option<u32>)> {
+        let sign = delta.action.signed(1.0)?;
+        let level = |pct: f64| curve.apply(pct / 100.0) * 254.0;
+        let low = level(self.min.unwrap_or(0.0)).max(1.0);
+        let high = level(self.max.unwrap_or(100.0));
+
+        // how far the brightness can go, for the light with the least room
+        let room = current
+            .iter()
+            .map(|br| {
+                if sign > 0.0 {
+                    high - level(*br)
+                } else {
+                    level(*br) - low
+                }
+            })
+            .fold(high - low, f64::min)
+            .max(0.0);
+
+        let amount = delta.brightness_delta.unwrap_or_default() / 100.0 * 254.0;
+        let rate = match duration.filter(|ms| *ms > 0) {
+            None if amount > 0.0 => return Some(((sign * amount.min(room)).round() as i32, None)),
+            Some(ms) if amount > 0.0 => amount * 1000.0 / f64::from(ms),
+            _ => DEFAULT_BRIGHTNESS_RATE,
+        };
+
+        let step = (sign * room).round() as i32;
+        Some((step, Some((room * 1000.0 / rate).round() as u32)))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntertainmentConfig {
+    /// Maximum number of frames per second forwarded to zigbee
+    #[serde(default = "default_max_fps")]
+    pub max_fps: u32,
+
+    /// Smoothing value sent with each zigbee entertainment frame
+    #[serde(default = "default_smoothing")]
+    pub smoothing: u16,
+
+    /// If set, every entertainment session is recorded to this directory
+    pub record_dir: Option<Utf8PathBuf>,
+
+    /// What happens to the lights when a session ends
+    #[serde(default)]
+    pub restore: EntertainmentRestore,
+
+    /// Per-area settings, keyed by entertainment area name (or id)
+    #[serde(default)]
+    pub areas: HashMap<String, EntertainmentAreaConfig>,
+
+    /// DMX universes (Art-Net or sACN) that drive entertainment areas
+    #[serde(default)]
+    pub dmx: Vec<DmxInputConfig>,
+}
+
+impl Default for EntertainmentConfig {
+    fn default() -> Self {
+        Self {
+            max_fps: default_max_fps(),
+            smoothing: default_smoothing(),
+            record_dir: None,
+            restore: EntertainmentRestore::default(),
+            areas: HashMap::new(),
+            dmx: vec![],
+        }
+    }
+}
+
+impl EntertainmentConfig {
+    #[must_use]
+    pub fn area(&self, id: &Uuid, name: &str) -> Option<&EntertainmentAreaConfig> {
+        self.areas
+            .get(&id.to_string())
+            .or_else(|| self.areas.get(name))
+    }
+
+    /// Path of the named recording, which must be a plain file name inside
+    /// [`Self::record_dir`]
+    pub fn recording_path(&self, name: &str) -> ApiResult<Utf8PathBuf> {
+        let dir = self
+            .record_dir
+            .as_ref()
+            .ok_or(ApiError::EntRecordingDisabled)?;
+
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(ApiError::EntRecordingName(name.to_string()));
+        }
+
+        Ok(dir.join(name))
+    }
+}
+
+/// How lights are restored when an entertainment session ends
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntertainmentRestore {
+    /// Recall the scenes that were active before streaming started, and
+    /// restore the state of any other lights
+    #[default]
+    Auto,
+
+    /// Restore the state of each light, ignoring scenes
+    State,
+
+    /// Leave the lights as the last frame set them
+    None,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DmxProtocol {
+    /// Art-Net (`ArtDmx` packets, udp port 6454)
+    Artnet,
+
+    /// E1.31 streaming ACN (multicast, udp port 5568)
+    Sacn,
+}
+
+/// A DMX universe mapped onto the channels of an entertainment area. Each
+/// channel takes 3 DMX slots (red, green, blue), in channel order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DmxInputConfig {
+    pub protocol: DmxProtocol,
+    pub universe: u16,
+
+    /// Name (or id) of the entertainment area
+    pub area: String,
+
+    /// DMX address (1-512) of the first slot of the first channel
+    #[serde(default = "default_dmx_address")]
+    pub address: u16,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct EntertainmentAreaConfig {
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+
+    /// Overrides [`EntertainmentConfig::max_fps`] for this area
+    pub max_fps: Option<u32>,
+
+    /// Overrides [`EntertainmentConfig::smoothing`] for this area
+    pub smoothing: Option<u16>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Distributed Display Protocol (supported by WLED, among others)
+    Ddp {
+        address: String,
+        #[serde(default = "default_pixels_per_channel")]
+        pixels_per_channel: usize,
+    },
+
+    /// Plain json frames over udp
+    UdpJson { address: String },
+
+    /// Json frames published to an mqtt topic
+    Mqtt {
+        host: String,
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        topic: String,
+    },
+
+    /// The entertainment stream of an area on a real hue bridge
+    HueBridge {
+        address: String,
+        username: String,
+        clientkey: String,
+        area: Uuid,
+        /// Bifrost channel to real bridge channel. If empty, all channels
+        /// are forwarded unchanged.
+        #[serde(default)]
+        channels: BTreeMap<u8, u8>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct LightsConfig {
+    /// Fade duration (in milliseconds) for light changes that do not
+    /// specify one themselves
+    pub default_transition: Option<u32>,
+
+    /// Brightness curve for all lights, unless overridden
+    pub brightness_curve: Option<BrightnessCurve>,
+
+    /// Lowest brightness (in percent) for all lights, unless overridden
+    pub min_brightness: Option<f64>,
+
+    /// Highest brightness (in percent) for all lights, unless overridden
+    pub max_brightness: Option<f64>,
+
+    /// Color gamut for all lights, unless overridden
+    pub gamut: Option<GamutType>,
+
+    /// Whether lights are visible through the hue bridge, unless overridden
+    /// [default: true]
+    pub expose: Option<bool>,
+
+    /// Per-model settings, keyed by model id (e.g. "LCT015")
+    #[serde(default)]
+    pub per_model: HashMap<String, LightConfig>,
+
+    /// Per-light settings, keyed by light name (or id)
+    #[serde(default)]
+    pub per_light: HashMap<String, LightConfig>,
+}
+
+impl LightsConfig {
+    #[must_use]
+    pub fn light(&self, id: &Uuid, name: &str) -> Option<&LightConfig> {
+        self.per_light
+            .get(&id.to_string())
+            .or_else(|| self.per_light.get(name))
+    }
+
+    /// All settings that apply to a light, most specific first
+    fn matching<'a>(
+        &'a self,
+        id: &Uuid,
+        name: &str,
+        model_id: &str,
+    ) -> impl Iterator<Item = &'a LightConfig> {
+        self.light(id, name)
+            .into_iter()
+            .chain(self.per_model.get(model_id))
+    }
+
+    /// Brightness limits for all lights, unless overridden
+    #[must_use]
+    pub const fn brightness_limits(&self) -> BrightnessLimits {
+        BrightnessLimits {
+            min: self.min_brightness,
+            max: self.max_brightness,
+        }
+    }
+
+    /// Resolve the settings for a light, from (most specific first) the
+    /// per-light and per-model settings, the room the light is in, and the
+    /// global defaults
+    #[must_use]
+    pub fn settings(
+        &self,
+        id: &Uuid,
+        name: &str,
+        model_id: &str,
+        room: Option<&RoomConfig>,
+    ) -> LightSettings {
+        let matching: Vec<&LightConfig> = self.matching(id, name, model_id).collect();
+
+        let brightness_limits = matching
+            .iter()
+            .map(|cfg| cfg.brightness_limits())
+            .chain(room.map(RoomConfig::brightness_limits))
+            .fold(BrightnessLimits::default(), BrightnessLimits::or)
+            .or(self.brightness_limits());
+
+        LightSettings {
+            transition: matching
+                .iter()
+                .find_map(|cfg| cfg.transition)
+                .or_else(|| room.and_then(|room| room.transition))
+                .or(self.default_transition),
+            brightness_curve: matching
+                .iter()
+                .find_map(|cfg| cfg.brightness_curve)
+                .or(self.brightness_curve)
+                .unwrap_or_default(),
+            brightness_limits,
+            gamut: matching
+                .iter()
+                .find_map(|cfg| cfg.gamut.clone())
+                .or_else(|| room.and_then(|room| room.gamut.clone()))
+                .or_else(|| self.gamut.clone()),
+            expose: matching
+                .iter()
+                .find_map(|cfg| cfg.expose)
+                .or_else(|| room.and_then(|room| room.expose))
+                .or(self.expose)
+                .unwrap_or(true),
+            entertainment_smoothing: matching
+                .iter()
+                .find_map(|cfg| cfg.entertainment_smoothing)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Settings for a single light, resolved from the global, room and
+/// per-light configuration (see [`LightsConfig::settings`])
+#[derive(Clone, Debug, Default)]
+pub struct LightSettings {
+    /// Transition time (in milliseconds), if none was requested
+    pub transition: Option<u32>,
+    pub brightness_curve: BrightnessCurve,
+    pub brightness_limits: BrightnessLimits,
+    /// Color gamut to report, instead of the detected one
+    pub gamut: Option<GamutType>,
+    pub expose: bool,
+    /// Strength of entertainment smoothing (see
+    /// [`crate::model::entertainment::SmoothingFilter`]), 0.0 if off
+    pub entertainment_smoothing: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct LightConfig {
+    /// Overrides [`LightsConfig::default_transition`]
+    pub transition: Option<u32>,
+
+    /// Overrides [`LightsConfig::brightness_curve`]
+    pub brightness_curve: Option<BrightnessCurve>,
+
+    /// Lowest brightness (in percent) this light can be set to
+    pub min_brightness: Option<f64>,
+
+    /// Highest brightness (in percent) this light can be set to
+    pub max_brightness: Option<f64>,
+
+    /// Overrides [`LightsConfig::gamut`], e.g. for lights that report the
+    /// wrong gamut
+    pub gamut: Option<GamutType>,
+
+    /// Overrides [`LightsConfig::expose`]
+    pub expose: Option<bool>,
+
+    /// Extra smoothing of entertainment colors for this light, from 0.0
+    /// (off) to 1.0, on top of the smoothing of its entertainment area
+    pub entertainment_smoothing: Option<f64>,
+}
+
+impl LightConfig {
+    #[must_use]
+    pub const fn brightness_limits(&self) -> BrightnessLimits {
+        BrightnessLimits {
+            min: self.min_brightness,
+            max: self.max_brightness,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct LoggingConfig {
+    /// Log level per module (e.g. `bifrost::backend::z2m: trace`)
+    #[serde(default)]
+    pub levels: BTreeMap<String, String>,
+}
+
+/// Restrictions for api keys, keyed by api key
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ApiKeysConfig {
+    /// Refuse changes made with keys that are not listed. Defaults to true
+    /// if any listed key is restricted, since restrictions could otherwise
+    /// be avoided by making up a key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deny_unknown: Option<bool>,
+
+    #[serde(flatten)]
+    pub keys: HashMap<String, ApiKeyConfig>,
+}
+
+impl ApiKeysConfig {
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&ApiKeyConfig> {
+        self.keys.get(key)
+    }
+
+    #[must_use]
+    pub fn deny_unknown(&self) -> bool {
+        self.deny_unknown
+            .unwrap_or_else(|| self.keys.values().any(ApiKeyConfig::is_restricted))
+    }
+}
+
+/// Restrictions for a single api key (i.e. hue "username")
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    /// Only allow reading, not changing anything
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Only allow control of lights, groups and scenes in these rooms (by
+    /// name). All rooms are allowed if empty.
+    #[serde(default)]
+    pub rooms: Vec<String>,
+
+    /// Allow entertainment streaming, and changes to entertainment areas
+    #[serde(default = "default_true")]
+    pub entertainment: bool,
+
+    /// Allow access to low-level bifrost apis (e.g. raw zigbee commands)
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl ApiKeyConfig {
+    #[must_use]
+    pub fn is_restricted(&self) -> bool {
+        self.read_only || !self.rooms.is_empty() || !self.entertainment
+    }
+}
+
+/// Response rewrites for a specific app, for apps that break on newer (or
+/// unexpected) fields
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CompatRule {
+    /// Apply to clients whose User-Agent header contains this
+    pub user_agent: Option<String>,
+
+    /// Apply to clients that paired with a devicetype containing this
+    pub devicetype: Option<String>,
+
+    /// Fields to remove from json responses (at any depth)
+    #[serde(default)]
+    pub omit: BTreeSet<String>,
+
+    /// Fields to rename in json responses (at any depth)
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+}
+
+impl CompatRule {
+    /// True if the rule has at least one condition, and all of them match
+    #[must_use]
+    pub fn matches(&self, user_agent: Option<&str>, devicetype: Option<&str>) -> bool {
+        let check = |pattern: &Option<String>, value: Option<&str>| {
+            pattern
+                .as_ref()
+                .map(|pat| value.is_some_and(|val| val.contains(pat.as_str())))
+        };
+
+        match (
+            check(&self.user_agent, user_agent),
+            check(&self.devicetype, devicetype),
+        ) {
+            (None, None) => false,
+            (ua, dt) => ua.unwrap_or(true) && dt.unwrap_or(true),
+        }
+    }
+}
+
+/// Export of light and sensor state to `InfluxDB` (or anything else that
+/// accepts the influx line protocol over http)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InfluxConfig {
+    /// Write endpoint, e.g. `http://influx:8086/api/v2/write?org=home&bucket=hue`
+    pub url: Url,
+
+    /// Api token, sent as `Authorization: Token <token>`
+    pub token: Option<String>,
+    pub token_file: Option<Utf8PathBuf>,
+
+    /// Measurement name for all points
+    #[serde(default = "default_influx_measurement")]
+    pub measurement: String,
+
+    /// Number of points sent in one request
+    #[serde(default = "default_influx_batch_size")]
+    pub batch_size: usize,
+
+    /// Seconds between writes, when a batch is not full
+    #[serde(default = "default_influx_flush_interval")]
+    pub flush_interval: u64,
+}
+
+fn default_influx_measurement() -> String {
+    "hue".to_string()
+}
+
+const fn default_influx_batch_size() -> usize {
+    100
+}
+
+const fn default_influx_flush_interval() -> u64 {
+    10
+}
+
+/// In-memory history of hue events, for the `/bifrost/events` api
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Hours of events to keep (0 disables the history)
+    #[serde(default = "default_history_hours")]
+    pub hours: u32,
+
+    /// If set, events are also written to this file (as json lines), so the
+    /// history survives a restart
+    pub file: Option<Utf8PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            hours: default_history_hours(),
+            file: None,
+        }
+    }
+}
+
+const fn default_history_hours() -> u32 {
+    24
+}
+
+/// Cross-origin access for browser-based clients
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Origins allowed to make requests ("*" allows any origin)
+    pub allowed_origins: Vec<String>,
+
+    /// Request headers browsers may send, besides the ones always allowed
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+
+    /// Seconds browsers may cache the result of a preflight request
+    #[serde(default = "default_cors_max_age")]
+    pub max_age: u64,
+}
+
+fn default_cors_headers() -> Vec<String> {
+    vec![
+        "content-type".to_string(),
+        "hue-application-key".to_string(),
+    ]
+}
+
+const fn default_cors_max_age() -> u64 {
+    600
+}
+
+/// Token bucket rate limit for changes made through the api
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Sustained number of requests per second, per client
+    pub requests_per_second: f64,
+
+    /// Number of requests a client can make in a burst
+    pub burst: u32,
+}
+
+impl RateLimitConfig {
+    fn validate(&self) -> ApiResult<()> {
+        let rate = self.requests_per_second;
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(ApiError::InvalidRateLimit(rate));
+        }
+        Ok(())
+    }
+}
+
+/// Url that receives hue events as json POST requests
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: Url,
+
+    /// Only send events for these resource types (all types if empty)
+    #[serde(default)]
+    pub types: BTreeSet<RType>,
+
+    /// Number of retries for failed requests, before the event is dropped
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+}
+
+/// Publish bridge state and events to an mqtt broker
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MqttPublishConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub password_file: Option<Utf8PathBuf>,
+
+    /// Topic prefix for all published messages
+    #[serde(default = "default_mqtt_prefix")]
+    pub prefix: String,
+
+    /// Publish Home Assistant mqtt discovery messages
+    #[serde(default)]
+    pub discovery: bool,
+
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+/// Simulated devices, that exist only inside bifrost
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct VirtualConfig {
+    #[serde(default)]
+    pub lights: Vec<VirtualLightConfig>,
+
+    #[serde(default)]
+    pub switches: Vec<VirtualDeviceConfig>,
+
+    #[serde(default)]
+    pub motion_sensors: Vec<VirtualDeviceConfig>,
+}
+
+impl VirtualConfig {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty() && self.switches.is_empty() && self.motion_sensors.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VirtualLightConfig {
+    pub name: String,
+
+    /// Model id to report (e.g. "LCT015"). Known hue models get matching
+    /// product data.
+    pub model_id: Option<String>,
+
+    #[serde(default)]
+    pub kind: VirtualLightKind,
+
+    #[serde(default)]
+    pub gradient: bool,
+
+    /// Create this many lights, named "<name> 1", "<name> 2", etc.
+    pub count: Option<u32>,
+
+    /// Room (i.e. group) to place this light in
+    pub room: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VirtualLightKind {
+    OnOff,
+    White,
+    Ambiance,
+    #[default]
+    Color,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VirtualDeviceConfig {
+    pub name: String,
+}
+
+const fn default_max_fps() -> u32 {
+    30
+}
+
+const fn default_smoothing() -> u16 {
+    EntertainmentZigbeeStream::DEFAULT_SMOOTHING
+}
+
+const fn default_dmx_address() -> u16 {
+    1
+}
+
+const fn default_pixels_per_channel() -> usize {
+    1
+}
+
+fn default_mqtt_prefix() -> String {
+    "bifrost".to_string()
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+const fn default_webhook_retries() -> u32 {
+    5
+}
+
+const fn default_mqtt_port() -> u16 {
+    1883
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AppConfig {
+    pub bridge: BridgeConfig,
+    pub z2m: Z2mConfig,
+    #[serde(default)]
+    pub bifrost: BifrostConfig,
+    #[serde(default)]
+    pub rooms: HashMap<String, RoomConfig>,
+    #[serde(default)]
+    pub entertainment: EntertainmentConfig,
+    #[serde(default)]
+    pub lights: LightsConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub api_keys: ApiKeysConfig,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub cors: Option<CorsConfig>,
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    #[serde(default)]
+    pub compat: Vec<CompatRule>,
+    pub mqtt: Option<MqttPublishConfig>,
+    pub influx: Option<InfluxConfig>,
+    #[serde(default, rename = "virtual")]
+    pub virtual_devices: VirtualConfig,
+    #[serde(default)]
+    pub personalities: BTreeMap<String, PersonalityConfig>,
+    pub sharding: Option<ShardingConfig>,
+    pub vacation: Option<VacationConfig>,
+    pub scripting: Option<ScriptingConfig>,
+    #[serde(default)]
+    pub remote: BTreeMap<String, RemoteBackendConfig>,
+    pub mqtt_lights: Option<MqttLightsConfig>,
+    #[serde(default)]
+    pub zwave: BTreeMap<String, ZwaveServerConfig>,
+    pub matter: Option<MatterConfig>,
+    pub coordinator: Option<CoordinatorConfig>,
+}
+
+/// Zigbee adapter controlled by bifrost itself, without zigbee2mqtt
+/// (experimental)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CoordinatorConfig {
+    /// Serial device of the adapter (e.g. `/dev/ttyUSB0`), or
+    /// `tcp://<host>:<port>` for network adapters
+    pub port: String,
+
+    /// File to keep the list of paired devices in
+    #[serde(default = "default_coordinator_database")]
+    pub database: Utf8PathBuf,
+}
+
+fn default_coordinator_database() -> Utf8PathBuf {
+    "coordinator.yaml".into()
+}
+
+/// Matter controller (python-matter-server), controlled through its
+/// websocket api
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatterConfig {
+    pub url: Url,
+}
+
+/// Z-Wave JS server, controlled through its websocket api
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZwaveServerConfig {
+    pub url: Url,
+}
+
+/// Out-of-process backend (a plugin), speaking json-rpc over a unix socket
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RemoteBackendConfig {
+    /// Unix socket the plugin listens on
+    pub socket: Utf8PathBuf,
+
+    /// Seconds to wait before connecting again, after the connection to the
+    /// plugin failed
+    #[serde(default = "default_remote_retry")]
+    pub retry: u64,
+}
+
+const fn default_remote_retry() -> u64 {
+    10
+}
+
+/// Wi-Fi lights controlled over mqtt (e.g. running tasmota), on the given
+/// broker
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MqttLightsConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub password_file: Option<Utf8PathBuf>,
+
+    #[serde(default)]
+    pub lights: Vec<MqttLightConfig>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MqttLightConfig {
+    pub name: String,
+
+    /// Model id to report (e.g. "LCT015"). Known hue models get matching
+    /// product data.
+    pub model_id: Option<String>,
+
+    #[serde(default)]
+    pub kind: VirtualLightKind,
+
+    #[serde(default)]
+    pub schema: MqttLightSchema,
+
+    /// Topic to send commands to
+    pub command_topic: String,
+
+    /// Topic the light reports its state on. Without one, the light is
+    /// assumed to follow every command.
+    pub state_topic: Option<String>,
+}
+
+/// Message format of an mqtt light
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttLightSchema {
+    /// Tasmota: `Backlog` commands, json results
+    #[default]
+    Tasmota,
+    /// Home Assistant json schema
+    HomeAssistant,
+}
+
+/// User scripts (in the rhai language), for automations bifrost does not
+/// have built in
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScriptingConfig {
+    /// Directory to load scripts (`*.rhai`) from
+    pub dir: Utf8PathBuf,
+
+    /// Seconds between checks for new, changed or removed scripts
+    #[serde(default = "default_script_reload_interval")]
+    pub reload_interval: u64,
+
+    /// Most operations a script may run for a single event, to stop
+    /// scripts that never finish
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,
+}
+
+const fn default_script_reload_interval() -> u64 {
+    5
+}
+
+const fn default_script_max_operations() -> u64 {
+    100_000
+}
+
+/// Presence simulation while nobody is home: light changes from the event
+/// history are replayed, shifted by a fixed time and randomized a bit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VacationConfig {
+    /// Hours to look back in the event history (limited to `history.hours`)
+    #[serde(default = "default_vacation_lookback")]
+    pub lookback_hours: u32,
+
+    /// Largest random shift of each replayed change, in minutes (earlier or
+    /// later)
+    #[serde(default = "default_vacation_jitter")]
+    pub jitter_minutes: u32,
+
+    /// Chance (0..1) that a change is left out
+    #[serde(default = "default_vacation_skip")]
+    pub skip: f64,
+
+    /// Start in away mode
+    #[serde(default)]
+    pub away: bool,
+
+    /// Turn away mode on when the last geofence client leaves home, and off
+    /// when the first one arrives
+    #[serde(default)]
+    pub geofence: bool,
+}
+
+const fn default_vacation_lookback() -> u32 {
+    24
+}
+
+const fn default_vacation_jitter() -> u32 {
+    15
+}
+
+const fn default_vacation_skip() -> f64 {
+    0.1
+}
+
+/// Distribution of zigbee2mqtt devices over several bridges (the main
+/// bridge, and some personalities), to stay below the number of lights the
+/// hue app handles well on one bridge.
+///
+/// Devices are assigned by their room (or by ieee address, if they are not
+/// in a room), using rendezvous hashing: every bridge computes the same
+/// assignment, and adding or removing a bridge only moves the rooms that
+/// have to move.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShardingConfig {
+    /// Personalities to distribute devices over, together with the main
+    /// bridge
+    pub bridges: Vec<String>,
+
+    /// Number of lights per bridge above which a warning is logged
+    #[serde(default = "default_shard_max_lights")]
+    pub max_lights: usize,
+
+    /// Only report the planned distribution, without applying it
+    #[serde(default)]
+    pub preview: bool,
+
+    /// Bridge this configuration is for (`None` for the main bridge)
+    #[serde(skip)]
+    pub current: Option<String>,
+}
+
+impl ShardingConfig {
+    /// Name of the main bridge, in the sharding plan
+    pub const MAIN: &'static str = "main";
+
+    /// Name of the bridge this configuration is for
+    #[must_use]
+    pub fn current(&self) -> &str {
+        self.current.as_deref().unwrap_or(Self::MAIN)
+    }
+
+    /// Bridge that the given key (room or device) is assigned to
+    #[must_use]
+    pub fn bridge_for(&self, key: &str) -> &str {
+        let score = |bridge: &str| {
+            let hash = Sha256::digest(format!("{bridge}/{key}"));
+            u64::from_be_bytes(hash[..8].try_into().unwrap_or_default())
+        };
+
+        std::iter::once(Self::MAIN)
+            .chain(self.bridges.iter().map(String::as_str))
+            .max_by_key(|bridge| score(bridge))
+            .unwrap_or(Self::MAIN)
+    }
+
+    /// True if the given key should be exposed on this bridge
+    #[must_use]
+    pub fn is_local(&self, key: &str) -> bool {
+        self.preview || self.bridge_for(key) == self.current()
+    }
+}
+
+const fn default_shard_max_lights() -> usize {
+    60
+}
+
+/// An additional bridge, served from the same process. Each personality has
+/// its own identity, ports, resources and state files, and exposes (a part
+/// of) the zigbee2mqtt devices.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersonalityConfig {
+    pub name: Option<String>,
+    pub mac: MacAddress,
+    pub bridge_id: Option<String>,
+    pub ipaddress: Option<Ipv4Addr>,
+    pub http_port: Option<u16>,
+    pub https_port: Option<u16>,
+    pub entm_port: Option<u16>,
+    #[serde(default)]
+    pub listen: Vec<ListenConfig>,
+
+    /// Directory for the state, automation, certificate, audit and image
+    /// files of this personality
+    pub dir: Option<Utf8PathBuf>,
+
+    /// Zigbee2mqtt servers to use (by name). Defaults to all servers.
+    #[serde(default)]
+    pub z2m: BTreeSet<String>,
+
+    /// Device filters, replacing the filters of the zigbee2mqtt servers
+    pub include: Option<Vec<DeviceMatch>>,
+    pub exclude: Option<Vec<DeviceMatch>>,
+}
+
+/// Directories where secret files given by a relative name are looked for,
+/// before the current directory: systemd credentials (`LoadCredential=`),
+/// and docker secrets
+fn secret_dirs() -> Vec<Utf8PathBuf> {
+    std::env::var("CREDENTIALS_DIRECTORY")
+        .ok()
+        .map(Utf8PathBuf::from)
+        .into_iter()
+        .chain(std::iter::once("/run/secrets".into()))
+        .collect()
+}
+
+/// Path of a secret file, looking up relative names in [`secret_dirs`]
+fn secret_path(path: &Utf8Path) -> Utf8PathBuf {
+    if path.is_relative() {
+        if let Some(found) = secret_dirs()
+            .into_iter()
+            .map(|dir| dir.join(path))
+            .find(|found| found.is_file())
+        {
+            return found;
+        }
+    }
+    path.to_owned()
+}
+
+/// Fill in a secret from its `*_file` variant, if that is set. Trailing
+/// newlines in the file are ignored.
+fn load_secret(key: &str, value: &mut Option<String>, file: Option<&Utf8PathBuf>) -> ApiResult<()> {
+    let Some(file) = file else {
+        return Ok(());
+    };
+
+    if value.is_some() {
+        let msg = format!("both {key} and {key}_file are set");
+        return Err(ApiError::Secret(key.to_string(), msg));
+    }
+
+    let path = secret_path(file);
+    let secret = std::fs::read_to_string(&path)
+        .map_err(|err| ApiError::Secret(key.to_string(), format!("{path}: {err}")))?;
+    *value = Some(secret.trim_end_matches(['\r', '\n']).to_string());
+
+    Ok(())
+}
+
+impl AppConfig {
+    /// Load secrets given as files (`password_file`, `token_file`, etc),
+    /// instead of in the configuration itself
+    fn load_secrets(&mut self) -> ApiResult<()> {
+        for (name, server) in &mut self.z2m.servers {
+            let key = format!("z2m.{name}.token");
+            load_secret(&key, &mut server.token, server.token_file.as_ref())?;
+        }
+        if let Some(mqtt) = &mut self.mqtt {
+            load_secret(
+                "mqtt.password",
+                &mut mqtt.password,
+                mqtt.password_file.as_ref(),
+            )?;
+        }
+        if let Some(mqtt) = &mut self.mqtt_lights {
+            let key = "mqtt_lights.password";
+            load_secret(key, &mut mqtt.password, mqtt.password_file.as_ref())?;
+        }
+        if let Some(influx) = &mut self.influx {
+            load_secret(
+                "influx.token",
+                &mut influx.token,
+                influx.token_file.as_ref(),
+            )?;
+        }
+        if let Some(key_file) = &mut self.bifrost.key_file {
+            *key_file = secret_path(key_file);
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> ApiResult<()> {
+        self.bridge.validate()?;
+
+        if let Some(rate_limit) = &self.rate_limit {
+            rate_limit.validate()?;
+        }
+
+        if let Some(sharding) = &self.sharding {
+            if let Some(name) = sharding
+                .bridges
+                .iter()
+                .find(|name| !self.personalities.contains_key(*name))
+            {
+                return Err(ApiError::Personality(
+                    name.clone(),
+                    "sharding bridge is not a configured personality".to_string(),
+                ));
+            }
+        }
+
+        let mut ids = BTreeSet::from([self.bridge.bridge_id()]);
+        for (name, conf) in self.personalities() {
+            conf.bridge.validate()?;
+            if !ids.insert(conf.bridge.bridge_id()) {
+                let msg = format!("bridge id {} is already in use", conf.bridge.bridge_id());
+                return Err(ApiError::Personality(name, msg));
+            }
+            if let Some(server) = self.personalities[&name]
+                .z2m
+                .iter()
+                .find(|server| !self.z2m.servers.contains_key(*server))
+            {
+                let msg = format!("unknown zigbee2mqtt server {server:?}");
+                return Err(ApiError::Personality(name, msg));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Configuration of each additional bridge personality.
+    ///
+    /// Personalities share the zigbee2mqtt, rooms, lights and api key
+    /// settings with the main bridge. Services that talk to the outside
+    /// world on their own (mqtt, influx, webhooks, dmx) and virtual devices
+    /// belong to the main bridge only.
+    #[must_use]
+    pub fn personalities(&self) -> Vec<(String, Self)> {
+        self.personalities
+            .iter()
+            .map(|(name, conf)| (name.clone(), self.personality(name, conf)))
+            .collect()
+    }
+
+    fn personality(&self, name: &str, conf: &PersonalityConfig) -> Self {
+        let mut res = self.clone();
+        res.personalities.clear();
+        if let Some(sharding) = &mut res.sharding {
+            sharding.current = Some(name.to_string());
+        }
+
+        let bridge = &mut res.bridge;
+        bridge.name = conf.name.clone().unwrap_or_else(|| name.to_string());
+        bridge.mac = conf.mac;
+        bridge.bridge_id.clone_from(&conf.bridge_id);
+        bridge.ipaddress = conf.ipaddress.unwrap_or(bridge.ipaddress);
+        bridge.http_port = conf.http_port.unwrap_or(bridge.http_port);
+        bridge.https_port = conf.https_port.unwrap_or(bridge.https_port);
+        bridge.entm_port = conf.entm_port.unwrap_or(bridge.entm_port);
+        if !conf.listen.is_empty() {
+            bridge.listen.clone_from(&conf.listen);
+        } else if conf.ipaddress.is_some() {
+            bridge.listen.clear();
+        }
+
+        let dir = conf.dir.clone().unwrap_or_else(|| {
+            self.bifrost
+                .state_file
+                .parent()
+                .map_or_else(|| name.into(), |base| base.join(name))
+        });
+        let rebase = |path: &Utf8Path| dir.join(path.file_name().unwrap_or(path.as_str()));
+        let files = &mut res.bifrost;
+        files.state_file = rebase(&files.state_file);
+        files.automation_file = rebase(&files.automation_file);
+        files.crash_file = rebase(&files.crash_file);
+        files.cert_file = rebase(&files.cert_file);
+        // each personality has its own certificate, generated with its own key
+        files.key_file = None;
+        files.audit_file = rebase(&files.audit_file);
+        files.image_dir = rebase(&files.image_dir);
+
+        if !conf.z2m.is_empty() {
+            res.z2m
+                .servers
+                .retain(|server, _| conf.z2m.contains(server));
+        }
+        for server in res.z2m.servers.values_mut() {
+            if let Some(include) = &conf.include {
+                server.include.clone_from(include);
+            }
+            if let Some(exclude) = &conf.exclude {
+                server.exclude.clone_from(exclude);
+            }
+        }
+
+        res.webhooks.clear();
+        res.scripting = None;
+        res.remote.clear();
+        res.mqtt = None;
+        res.mqtt_lights = None;
+        res.zwave.clear();
+        res.matter = None;
+        res.coordinator = None;
+        res.influx = None;
+        res.history.file = None;
+        res.entertainment.dmx.clear();
+        res.virtual_devices = VirtualConfig::default();
+
+        res
+    }
+}
+
+impl Z2mServer {
+    #[must_use]
+    pub fn get_url(&self) -> Url {
+        let mut url = self.url.clone();
+        // z2m version 1.x allows both / and /api as endpoints for the
+        // websocket, but version 2.x only allows /api. By adding /api (if
+        // missing), we ensure compatibility with both versions.
+        if !url.path().ends_with("/api") {
+            if let Ok(mut path) = url.path_segments_mut() {
+                path.push("api");
+            }
+        }
+
+        // z2m version 2.x requires an auth token on the websocket. If one is
+        // not specified in the z2m configuration, the literal string
+        // `your-secret-token` is used!
+        //
+        // To be compatible, we mirror this behavior here. If "token" is set
+        // manually by the user, we do nothing.
+        if !url.query_pairs().any(|(key, _)| key == "token") {
+            url.query_pairs_mut().append_pair(
+                "token",
+                self.token.as_deref().unwrap_or("your-secret-token"),
+            );
+        }
+
+        url
+    }
+
+    #[must_use]
+    #[allow(clippy::option_if_let_else)]
+    fn sanitize_url(url: &str) -> String {
+        match url.find("token=") {
+            Some(offset) => {
+                let token = &url[offset + "token=".len()..];
+                if token == "your-secret-token" {
+                    // this is the standard "blank" token, it's safe to show
+                    url.to_string()
+                } else {
+                    // this is an actual secret token, blank it out with a
+                    // standard-length placeholder.
+                    format!("{}token={}", &url[..offset], "<<REDACTED>>")
+                }
+            }
+            None => url.to_string(),
+        }
+    }
+
+    #[must_use]
+    pub fn get_sanitized_url(&self) -> String {
+        Self::sanitize_url(self.get_url().as_str())
+    }
+}
+
+/// Configuration files that are looked for, when none is given
+pub const DEFAULT_FILES: &[&str] = &["config.yaml", "config.toml"];
+
+/// The first of [`DEFAULT_FILES`] that exists (or the first one, if none do)
+#[must_use]
+pub fn default_path() -> Utf8PathBuf {
+    DEFAULT_FILES
+        .iter()
+        .map(Utf8PathBuf::from)
+        .find(|path| path.exists())
+        .unwrap_or_else(|| DEFAULT_FILES[0].into())
+}
+
+/// True if the file should be read as TOML (by extension), instead of YAML
+fn is_toml(filename: &Utf8Path) -> bool {
+    filename.extension() == Some("toml")
+}
+
+/// Prefix of environment variables that override configuration keys. Each
+/// level of nesting is separated by a double underscore, so
+/// `BIFROST_BRIDGE__HTTP_PORT` overrides `bridge.http_port`.
+pub const ENV_PREFIX: &str = "BIFROST";
+
+/// Settings given on the command line, which take precedence over both the
+/// configuration file and the environment
+#[derive(Clone, Debug, Default)]
+pub struct ConfigOverrides {
+    pub mac: Option<MacAddress>,
+    pub ipaddress: Option<Ipv4Addr>,
+    pub http_port: Option<u16>,
+    pub https_port: Option<u16>,
+    pub entm_port: Option<u16>,
+    pub state_file: Option<Utf8PathBuf>,
+
+    /// Url of the zigbee2mqtt server. Only allowed if there is (at most)
+    /// one server configured.
+    pub z2m_url: Option<Url>,
+}
+
+impl ConfigOverrides {
+    /// Name of the zigbee2mqtt server used when none is configured
+    const Z2M_SERVER: &str = "default";
+
+    fn apply(&self, base: &Config) -> Result<Config, ConfigError> {
+        let mut builder = Config::builder()
+            .add_source(base.clone())
+            .set_override_option("bridge.mac", self.mac.map(|mac| mac.to_string()))?
+            .set_override_option("bridge.ipaddress", self.ipaddress.map(|ip| ip.to_string()))?
+            .set_override_option("bridge.http_port", self.http_port)?
+            .set_override_option("bridge.https_port", self.https_port)?
+            .set_override_option("bridge.entm_port", self.entm_port)?
+            .set_override_option(
+                "bifrost.state_file",
+                self.state_file.as_ref().map(ToString::to_string),
+            )?;
+
+        if let Some(url) = &self.z2m_url {
+            let servers = base.get_table("z2m").unwrap_or_default();
+            let name = match servers.keys().collect::<Vec<_>>().as_slice() {
+                [] => Self::Z2M_SERVER,
+                [name] => name.as_str(),
+                _ => {
+                    return Err(ConfigError::Message(
+                        "zigbee2mqtt url override is ambiguous with several servers".to_string(),
+                    ))
+                }
+            };
+            builder = builder.set_override(format!("z2m.{name}.url"), url.as_str())?;
+        }
+
+        builder.build()
+    }
+}
+
+/// Load the configuration file, with environment variables (see
+/// [`ENV_PREFIX`]) and command line overrides on top
+pub fn parse(filename: &Utf8Path, overrides: &ConfigOverrides) -> Result<AppConfig, ConfigError> {
+    let format = if is_toml(filename) {
+        FileFormat::Toml
+    } else {
+        FileFormat::Yaml
+    };
+
+    let env = Environment::with_prefix(ENV_PREFIX)
+        .prefix_separator("_")
+        .separator("__")
+        .try_parsing(true);
+
+    let settings = Config::builder()
+        .add_source(config::File::new(filename.as_str(), format))
+        .add_source(env)
+        .build()?;
+
+    let mut config: AppConfig = overrides.apply(&settings)?.try_deserialize()?;
+    config
+        .load_secrets()
+        .and_then(|()| config.validate())
+        .map_err(|err| ConfigError::Message(err.to_string()))?;
+
+    Ok(config)
+}
+
+/// A problem found while checking a configuration file
+#[derive(Clone, Debug)]
+pub struct ConfigIssue {
+    /// Line and column (starting at 1) of the problem, if known
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    /// Issue about the key at `path`
+    fn at_key(src: &ConfigSource, path: &[&str], message: String) -> Self {
+        let position = src.find_key(path);
+        Self { position, message }
+    }
+
+    /// Issue about `value`, in the value of the key at `path`
+    fn at_value(src: &ConfigSource, path: &[&str], value: &str, message: String) -> Self {
+        let position = src.find_value(path, value);
+        Self { position, message }
+    }
+}
+
+/// Keys (and sequence indices) of a path to an unknown key
+fn key_path(path: &serde_ignored::Path, keys: &mut Vec<String>) {
+    match path {
+        serde_ignored::Path::Root => {}
+        serde_ignored::Path::Seq { parent, index } => {
+            key_path(parent, keys);
+            keys.push(index.to_string());
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            key_path(parent, keys);
+            keys.push(key.clone());
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => key_path(parent, keys),
+    }
+}
+
+/// Message for a deserialization error, prefixed with the path of the key
+/// (unless the error message already starts with it)
+fn describe(path: &serde_path_to_error::Path, msg: &str) -> String {
+    let path = path.to_string();
+    if path == "." || msg.starts_with(&path) {
+        msg.to_string()
+    } else {
+        format!("{path}: {msg}")
+    }
+}
+
+/// Check a configuration file more strictly than [`parse`]: besides type
+/// errors (reported with their line and column), unknown keys are reported
+/// as well, since they are usually typos.
+///
+/// Returns the configuration (if it could be read), and all problems found.
+pub fn check(filename: &Utf8Path) -> ApiResult<(Option<AppConfig>, Vec<ConfigIssue>)> {
+    Ok(check_source(&ConfigSource::read(filename)?))
+}
+
+fn check_source(src: &ConfigSource) -> (Option<AppConfig>, Vec<ConfigIssue>) {
+    let mut unknown = vec![];
+    let mut on_unknown = |path: serde_ignored::Path| {
+        let mut keys = vec![];
+        key_path(&path, &mut keys);
+        unknown.push(keys);
+    };
+
+    let res: Result<AppConfig, ConfigIssue> = if src.toml {
+        let de = toml::Deserializer::new(&src.text);
+        serde_path_to_error::deserialize(serde_ignored::Deserializer::new(de, &mut on_unknown))
+            .map_err(|err| ConfigIssue {
+                position: err.inner().span().map(|span| src.position(span.start)),
+                message: describe(err.path(), err.inner().message()),
+            })
+    } else {
+        let de = serde_yml::Deserializer::from_str(&src.text);
+        serde_path_to_error::deserialize(serde_ignored::Deserializer::new(de, &mut on_unknown))
+            .map_err(|err| {
+                let location = err.inner().location();
+                let mut msg = err.inner().to_string();
+                if let Some(loc) = &location {
+                    let suffix = format!(" at line {} column {}", loc.line(), loc.column());
+                    msg.truncate(msg.strip_suffix(&suffix).map_or(msg.len(), str::len));
+                    // serde_yml starts the message with its own form of the
+                    // path (e.g. "a.\[1\].b: ..."), which is replaced
+                    if let Some((head, rest)) = msg.split_once(": ") {
+                        if err.path().to_string() != "." && !head.contains(' ') {
+                            msg = rest.to_string();
+                        }
+                    }
+                }
+                ConfigIssue {
+                    position: location.map(|loc| (loc.line(), loc.column())),
+                    message: describe(err.path(), &msg),
+                }
+            })
+    };
+
+    let mut issues: Vec<ConfigIssue> = unknown
+        .iter()
+        .map(|keys| {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            let msg = format!("unknown key {:?}", keys.join("."));
+            ConfigIssue::at_key(src, &keys, msg)
+        })
+        .collect();
+
+    let mut config: AppConfig = match res {
+        Ok(config) => config,
+        Err(issue) => {
+            issues.push(issue);
+            return (None, issues);
+        }
+    };
+
+    if let Err(err) = config.load_secrets().and_then(|()| config.validate()) {
+        issues.push(ConfigIssue {
+            position: None,
+            message: err.to_string(),
+        });
+    }
+
+    (Some(config), issues)
+}
+
+/// Check references from the configuration to zigbee2mqtt groups and
+/// devices, against those of all zigbee2mqtt servers.
+///
+/// This covers rooms, manual room devices, per-light settings and room
+/// allowlists.
+/// `src` is the configuration file, for finding the position of problems.
+#[must_use]
+pub fn check_network(
+    src: &ConfigSource,
+    config: &AppConfig,
+    groups: &[z2m::api::Group],
+    devices: &[z2m::api::Device],
+) -> Vec<ConfigIssue> {
+    let group_names: BTreeSet<&str> = groups.iter().map(|g| g.friendly_name.as_str()).collect();
+    let device_names: BTreeSet<&str> = devices.iter().map(|d| d.friendly_name.as_str()).collect();
+
+    let mut issues = vec![];
+
+    for (key, room) in &config.rooms {
+        if room.devices.is_empty() {
+            if !group_names.contains(key.as_str()) {
+                let msg = format!("room {key:?} does not match any zigbee2mqtt group");
+                issues.push(ConfigIssue::at_key(src, &["rooms", key], msg));
+            }
+            continue;
+        }
+
+        for name in room
+            .devices
+            .iter()
+            .filter(|n| !device_names.contains(n.as_str()))
+        {
+            let msg = format!("device {name:?} in room {key:?} does not exist in zigbee2mqtt");
+            let path = ["rooms", key, "devices"];
+            issues.push(ConfigIssue::at_value(src, &path, name, msg));
+        }
+    }
+
+    for key in config.lights.per_light.keys() {
+        if key.parse::<Uuid>().is_err() && !device_names.contains(key.as_str()) {
+            let msg = format!("light {key:?} does not match any zigbee2mqtt device");
+            let path = ["lights", "per_light", key];
+            issues.push(ConfigIssue::at_key(src, &path, msg));
+        }
+    }
+
+    for (name, server) in &config.z2m.servers {
+        for grp in server
+            .room_allowlist
+            .iter()
+            .filter(|grp| !group_names.contains(grp.as_str()))
+        {
+            let msg = format!("group {grp:?} in room_allowlist of {name:?} does not exist");
+            let path = ["z2m", name, "room_allowlist"];
+            issues.push(ConfigIssue::at_value(src, &path, grp, msg));
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use hue::api::{DeltaAction, DimmingDeltaUpdate};
+    use hue::curve::BrightnessCurve;
+
+    use crate::config::locate::ConfigSource;
+    use crate::config::{check_source, BrightnessLimits};
+
+    const LIMITS: BrightnessLimits = BrightnessLimits {
+        min: Some(20.0),
+        max: Some(95.0),
+    };
+
+    const fn delta(action: DeltaAction, brightness_delta: Option<f64>) -> DimmingDeltaUpdate {
+        DimmingDeltaUpdate {
+            action,
+            brightness_delta,
+        }
+    }
+
+    #[test]
+    fn step_stops_at_limit() {
+        let curve = BrightnessCurve::Linear;
+        let down = delta(DeltaAction::Down, Some(50.0));
+        assert_eq!(
+            LIMITS.limit_delta(curve, &[30.0], &down, None),
+            Some((-25, None))
+        );
+
+        let up = delta(DeltaAction::Up, Some(10.0));
+        assert_eq!(
+            LIMITS.limit_delta(curve, &[50.0], &up, None),
+            Some((25, None))
+        );
+        assert_eq!(
+            LIMITS.limit_delta(curve, &[40.0, 90.0], &up, None),
+            Some((13, None))
+        );
+        assert_eq!(
+            LIMITS.limit_delta(curve, &[10.0], &down, None),
+            Some((0, None))
+        );
+    }
+
+    #[test]
+    fn move_becomes_step_to_limit() {
+        let curve = BrightnessCurve::Linear;
+        let down = delta(DeltaAction::Down, Some(100.0));
+        assert_eq!(
+            LIMITS.limit_delta(curve, &[60.0], &down, Some(1000)),
+            Some((-102, Some(400)))
+        );
+
+        // without an amount, the default rate is used
+        let up = delta(DeltaAction::Up, None);
+        assert_eq!(
+            LIMITS.limit_delta(curve, &[95.0 - 64.0 / 2.54], &up, None),
+            Some((64, Some(1000)))
+        );
+    }
+
+    #[test]
+    fn never_turns_off() {
+        let none = BrightnessLimits::default();
+        let down = delta(DeltaAction::Down, Some(100.0));
+        let (step, _) = none
+            .limit_delta(BrightnessCurve::Linear, &[10.0], &down, None)
+            .unwrap();
+        assert_eq!(step, -24);
+    }
+
+    #[test]
+    fn stop_is_unchanged() {
+        let stop = delta(DeltaAction::Stop, None);
+        let res = LIMITS.limit_delta(BrightnessCurve::Cie1931, &[50.0], &stop, Some(1000));
+        assert_eq!(res, None);
+    }
+
+    const BRIDGE: &str = "\
+bridge:
+  name: Bifrost
+  mac: 00:11:22:33:44:55
+  ipaddress: 10.0.0.2
+  netmask: 255.255.255.0
+  gateway: 10.0.0.1
+  timezone: UTC
+z2m: {}
+";
+
+    fn yaml(text: &str) -> ConfigSource {
+        ConfigSource {
+            text: text.to_string(),
+            toml: false,
+        }
+    }
+
+    #[test]
+    fn unknown_key_in_parent() {
+        // "name" appears before the unknown key, but in another section
+        let src = yaml(&format!(
+            "{BRIDGE}rooms:\n  hall:\n    name: Hall\n    nmae: Hall\n"
+        ));
+        let (config, issues) = check_source(&src);
+
+        assert!(config.is_some());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message, "unknown key \"rooms.hall.nmae\"");
+        assert_eq!(issues[0].position, Some((12, 5)));
+    }
+
+    #[test]
+    fn type_error_position() {
+        let src = yaml(&BRIDGE.replace("UTC", "UTC\n  http_port: eighty"));
+        let (config, issues) = check_source(&src);
+
+        assert!(config.is_none());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].position, Some((8, 14)));
+        assert!(
+            issues[0]
+                .message
+                .starts_with("bridge.http_port: invalid type"),
+            "{}",
+            issues[0].message
+        );
+    }
+
+    #[test]
+    fn toml_type_error_position() {
+        let src = ConfigSource {
+            text: "[bridge]\nname = \"Bifrost\"\nhttp_port = \"eighty\"\n".to_string(),
+            toml: true,
+        };
+        let (config, issues) = check_source(&src);
+
+        assert!(config.is_none());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].position, Some((3, 13)));
+        assert!(
+            issues[0]
+                .message
+                .starts_with("bridge.http_port: invalid type"),
+            "{}",
+            issues[0].message
+        );
+    }
+
+    #[test]
+    fn invalid_rate_limit() {
+        for rate in ["0", "-1", ".nan", ".inf"] {
+            let src = yaml(&format!(
+                "{BRIDGE}rate_limit:\n  requests_per_second: {rate}\n  burst: 5\n"
+            ));
+            let (config, issues) = check_source(&src);
+
+            assert!(config.is_some());
+            assert_eq!(issues.len(), 1, "{rate}");
+            assert!(
+                issues[0].message.starts_with("Invalid rate limit"),
+                "{}",
+                issues[0].message
+            );
+        }
+
+        let src = yaml(&format!(
+            "{BRIDGE}rate_limit:\n  requests_per_second: 2.5\n  burst: 5\n"
+        ));
+        assert!(check_source(&src).1.is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+//! Starter configuration, generated from a zigbee2mqtt network
+//!
+//! The result is commented yaml text (not a serialized [`super::AppConfig`]),
+//! meant as a first draft for the user to review and edit.
+
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+use if_addrs::IfAddr;
+use mac_address::MacAddress;
+use url::Url;
+
+use hue::api::{DeviceProductData, RoomArchetype};
+use z2m::api::{Device, DeviceType, Group};
+
+/// Fallback for the local address, if the zigbee2mqtt host is this machine
+const PUBLIC_PROBE: (Ipv4Addr, u16) = (Ipv4Addr::new(1, 1, 1, 1), 53);
+
+/// A network interface (with an ipv4 address) that bifrost could serve on
+#[derive(Clone, Debug)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub mac: MacAddress,
+    pub ipaddress: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+}
+
+/// All non-loopback network interfaces with a mac and ipv4 address
+#[must_use]
+pub fn network_interfaces() -> Vec<NetworkInterface> {
+    let ifaces = if_addrs::get_if_addrs().unwrap_or_else(|err| {
+        log::warn!("Cannot list network interfaces: {err}");
+        vec![]
+    });
+
+    ifaces
+        .into_iter()
+        .filter(|iface| !iface.is_loopback())
+        .filter_map(|iface| {
+            let IfAddr::V4(addr) = &iface.addr else {
+                return None;
+            };
+            let mac = mac_address::mac_address_by_name(&iface.name).ok()??;
+            Some(NetworkInterface {
+                mac,
+                ipaddress: addr.ip,
+                netmask: addr.netmask,
+                name: iface.name,
+            })
+        })
+        .collect()
+}
+
+/// Bridge settings, detected from the machine bifrost is going to run on
+#[derive(Clone, Debug, Default)]
+pub struct StarterBridge {
+    pub mac: Option<MacAddress>,
+    pub ipaddress: Option<Ipv4Addr>,
+    pub netmask: Option<Ipv4Addr>,
+    pub timezone: Option<String>,
+}
+
+impl StarterBridge {
+    /// Detect the bridge settings. The address is the one this machine uses
+    /// to reach `peer` (no packets are sent).
+    #[must_use]
+    pub fn detect(peer: &Url) -> Self {
+        let target = peer
+            .host_str()
+            .map(|host| (host, peer.port_or_known_default().unwrap_or(8080)));
+
+        let ipaddress = target
+            .and_then(local_ipv4)
+            .filter(|ip| !ip.is_loopback())
+            .or_else(|| local_ipv4(PUBLIC_PROBE));
+
+        if let Some(iface) = network_interfaces()
+            .iter()
+            .find(|iface| Some(iface.ipaddress) == ipaddress)
+        {
+            return Self::for_interface(iface);
+        }
+
+        Self {
+            mac: mac_address::get_mac_address().ok().flatten(),
+            ipaddress,
+            netmask: None,
+            timezone: iana_time_zone::get_timezone().ok(),
+        }
+    }
+
+    /// Bridge settings for serving on the given interface
+    #[must_use]
+    pub fn for_interface(iface: &NetworkInterface) -> Self {
+        Self {
+            mac: Some(iface.mac),
+            ipaddress: Some(iface.ipaddress),
+            netmask: Some(iface.netmask),
+            timezone: iana_time_zone::get_timezone().ok(),
+        }
+    }
+}
+
+fn local_ipv4(target: impl std::net::ToSocketAddrs) -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(target).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(_) => None,
+    }
+}
+
+/// Quote a string for yaml (json strings are valid yaml)
+fn quote(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
+/// Room name from a group name, e.g. `living_room` -> "Living room"
+fn room_name(group: &str) -> String {
+    let name = group.replace('_', " ");
+    let mut chars = name.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+fn is_light(dev: &Device) -> bool {
+    dev.expose_light().is_some() || dev.expose_on_off()
+}
+
+/// Reason to suggest excluding a device, if any
+fn exclude_reason(dev: &Device) -> Option<&'static str> {
+    if dev.disabled {
+        Some("disabled in zigbee2mqtt")
+    } else if !dev.interview_completed {
+        Some("interview not completed")
+    } else if dev.supported == Some(false) {
+        Some("not supported by zigbee2mqtt")
+    } else {
+        None
+    }
+}
+
+/// Lights that can take part in hue entertainment streaming (color lights
+/// from Signify, which understand the hue streaming commands)
+fn is_entertainment_capable(dev: &Device) -> bool {
+    dev.manufacturer.as_deref() == Some(DeviceProductData::SIGNIFY_MANUFACTURER_NAME)
+        && dev
+            .expose_light()
+            .is_some_and(|light| light.feature("color_xy").is_some())
+}
+
+fn describe(dev: &Device) -> String {
+    let name = quote(&dev.friendly_name);
+    if let Some(model) = &dev.model_id {
+        format!("{name} ({model})")
+    } else {
+        name
+    }
+}
+
+/// Generate a starter configuration for a single zigbee2mqtt server
+#[must_use]
+#[allow(clippy::too_many_lines)]
+pub fn generate(
+    bridge: &StarterBridge,
+    url: &Url,
+    token: Option<&str>,
+    groups: &[Group],
+    devices: &[Device],
+) -> String {
+    let mut out = vec![
+        "# Bifrost configuration, generated from zigbee2mqtt".to_string(),
+        "#".to_string(),
+        "# Review the bridge section (some network settings are guesses), and see".to_string(),
+        "# doc/config-reference.md for everything else that can be configured.".to_string(),
+        String::new(),
+        "bridge:".to_string(),
+        "  name: Bifrost".to_string(),
+    ];
+
+    if let Some(mac) = bridge.mac {
+        out.push(format!("  mac: {mac}"));
+    } else {
+        out.push("  mac: 00:00:00:00:00:00  # not detected, fill in".to_string());
+    }
+
+    if let Some(ip) = bridge.ipaddress {
+        out.push(format!("  ipaddress: {ip}"));
+        if let Some(netmask) = bridge.netmask {
+            out.push(format!("  netmask: {netmask}"));
+        } else {
+            out.push("  netmask: 255.255.255.0  # guessed, check".to_string());
+        }
+        /* the first address of the network is a common choice of gateway */
+        let mask = u32::from(bridge.netmask.unwrap_or(Ipv4Addr::new(255, 255, 255, 0)));
+        let gateway = Ipv4Addr::from((u32::from(ip) & mask) + 1);
+        out.push(format!("  gateway: {gateway}  # guessed, check"));
+    } else {
+        out.push("  ipaddress: 0.0.0.0  # not detected, fill in".to_string());
+        out.push("  netmask: 255.255.255.0  # fill in".to_string());
+        out.push("  gateway: 0.0.0.0  # fill in".to_string());
+    }
+
+    if let Some(tz) = &bridge.timezone {
+        out.push(format!("  timezone: {}", quote(tz)));
+    } else {
+        out.push("  timezone: Europe/London  # not detected, fill in".to_string());
+    }
+
+    out.push(String::new());
+    out.push("z2m:".to_string());
+    out.push("  default:".to_string());
+    out.push(format!("    url: {}", quote(url.as_str())));
+    if let Some(token) = token {
+        out.push(format!("    token: {}", quote(token)));
+    }
+
+    let devices: Vec<&Device> = devices
+        .iter()
+        .filter(|dev| !matches!(dev.device_type, DeviceType::Coordinator))
+        .collect();
+
+    let excluded: Vec<(&Device, &str)> = devices
+        .iter()
+        .filter_map(|dev| exclude_reason(dev).map(|reason| (*dev, reason)))
+        .collect();
+
+    if !excluded.is_empty() {
+        out.push("    # Devices that are probably not worth exposing:".to_string());
+        out.push("    exclude:".to_string());
+        for (dev, reason) in &excluded {
+            out.push(format!(
+                "      - name: {}  # {reason}",
+                quote(&dev.friendly_name)
+            ));
+        }
+    }
+
+    let mut groups: Vec<&Group> = groups.iter().collect();
+    groups.sort_by(|a, b| a.friendly_name.cmp(&b.friendly_name));
+
+    let (rooms, empty): (Vec<&Group>, Vec<&Group>) =
+        groups.into_iter().partition(|grp| !grp.members.is_empty());
+
+    let grouped: HashSet<_> = rooms
+        .iter()
+        .flat_map(|grp| grp.members.iter().map(|mbr| &mbr.ieee_address))
+        .collect();
+
+    out.push(String::new());
+    if rooms.is_empty() {
+        out.push("# No zigbee2mqtt groups found. Rooms can be made from groups, or".to_string());
+        out.push("# from a list of devices:".to_string());
+        out.push("#".to_string());
+        out.push("# rooms:".to_string());
+        out.push("#   living_room:".to_string());
+        out.push("#     name: Living room".to_string());
+        out.push("#     devices: [\"Ceiling light\", \"Floor lamp\"]".to_string());
+    } else {
+        out.push("# Rooms, from zigbee2mqtt groups".to_string());
+        out.push("rooms:".to_string());
+        for grp in &rooms {
+            let name = room_name(&grp.friendly_name);
+            let members = match grp.members.len() {
+                1 => "1 member".to_string(),
+                n => format!("{n} members"),
+            };
+            out.push(format!("  {}:  # {members}", quote(&grp.friendly_name)));
+            out.push(format!("    name: {}", quote(&name)));
+            if let Some(icon) = RoomArchetype::from_name(&name)
+                .and_then(|arch| serde_json::to_value(arch).ok())
+                .and_then(|val| val.as_str().map(ToString::to_string))
+            {
+                out.push(format!("    icon: {icon}"));
+            }
+        }
+    }
+
+    if !empty.is_empty() {
+        out.push(String::new());
+        out.push("# Groups without members (not turned into rooms):".to_string());
+        for grp in &empty {
+            out.push(format!("#   {}", quote(&grp.friendly_name)));
+        }
+    }
+
+    let ungrouped: Vec<&Device> = devices
+        .iter()
+        .filter(|dev| is_light(dev) && exclude_reason(dev).is_none())
+        .filter(|dev| !grouped.contains(&dev.ieee_address))
+        .copied()
+        .collect();
+
+    if !ungrouped.is_empty() {
+        out.push(String::new());
+        out.push("# Lights not in any group (add them to a group in zigbee2mqtt, or".to_string());
+        out.push("# list them in the \"devices\" of a room):".to_string());
+        for dev in &ungrouped {
+            out.push(format!("#   {}", describe(dev)));
+        }
+    }
+
+    let entertainment: Vec<&Device> = devices
+        .iter()
+        .filter(|dev| exclude_reason(dev).is_none() && is_entertainment_capable(dev))
+        .copied()
+        .collect();
+
+    out.push(String::new());
+    if entertainment.is_empty() {
+        out.push("# No lights with hue entertainment (sync) support found.".to_string());
+    } else {
+        out.push(
+            "# Lights with hue entertainment (sync) support, which can be used in".to_string(),
+        );
+        out.push("# entertainment areas from the hue app:".to_string());
+        for dev in &entertainment {
+            out.push(format!("#   {}", describe(dev)));
+        }
+    }
+
+    out.push(String::new());
+    out.join("\n")
+}
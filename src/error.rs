@@ -6,6 +6,7 @@ use thiserror::Error;
 use tokio::task::JoinError;
 use uuid::Uuid;
 
+use hue::api::EntertainmentLocationError;
 use hue::event::EventBlock;
 use hue::legacy_api::ApiResourceType;
 use svc::error::SvcError;
@@ -84,12 +85,18 @@ pub enum ApiError {
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
 
+    #[error(transparent)]
+    UrlParseError(#[from] url::ParseError),
+
     #[error(transparent)]
     UuidError(#[from] uuid::Error),
 
     #[error(transparent)]
     HueError(#[from] hue::error::HueError),
 
+    #[error(transparent)]
+    ZclError(#[from] zcl::error::ZclError),
+
     #[error(transparent)]
     OpenSslError(#[from] openssl::error::Error),
 
@@ -99,6 +106,9 @@ pub enum ApiError {
     #[error(transparent)]
     SslError(#[from] openssl::ssl::Error),
 
+    #[error(transparent)]
+    MqttClientError(#[from] rumqttc::ClientError),
+
     #[error("Service error: {0}")]
     SvcError(String),
 
@@ -106,13 +116,43 @@ pub enum ApiError {
     #[error("Unexpected eof on z2m socket")]
     UnexpectedZ2mEof,
 
+    #[error("Timeout waiting for zigbee2mqtt server")]
+    Z2mTimeout,
+
     #[error("Unexpected z2m message: {0:?}")]
     UnexpectedZ2mReply(tokio_tungstenite::tungstenite::Message),
 
+    #[error("Device did not accept command: {0}")]
+    CommandFailed(String),
+
+    #[error("Timeout waiting for device to confirm command")]
+    CommandTimeout,
+
+    /* matter errors */
+    #[error("Unexpected eof on matter server socket")]
+    UnexpectedMatterEof,
+
+    #[error("Matter is not configured")]
+    MatterDisabled,
+
+    /* z-wave js errors */
+    #[error("Unexpected eof on z-wave js socket")]
+    UnexpectedZwaveEof,
+
+    /* zigbee coordinator errors */
+    #[error("Unexpected eof from zigbee adapter")]
+    UnexpectedCoordinatorEof,
+
+    #[error("Zigbee adapter frame too large ({0} bytes of data)")]
+    CoordinatorFrameTooLarge(usize),
+
     /* hue api v1 errors */
     #[error("Cannot create resources of type: {0:?}")]
     V1CreateUnsupported(ApiResourceType),
 
+    #[error("Cannot delete resources of type: {0:?}")]
+    V1DeleteUnsupported(ApiResourceType),
+
     /* hue api v2 errors */
     #[error("Resource {0} could not be deleted")]
     DeleteDenied(Uuid),
@@ -142,8 +182,95 @@ pub enum ApiError {
     #[error("Entertainment Stream desynchronized")]
     EntStreamDesync,
 
+    #[error("Entertainment area {0:?} shares lights with active area {1:?}")]
+    EntAreaConflict(String, String),
+
+    #[error("Entertainment area {0:?} not found")]
+    EntAreaNotFound(String),
+
+    #[error("Entertainment recording is not enabled")]
+    EntRecordingDisabled,
+
+    #[error("Invalid entertainment recording")]
+    EntRecordingInvalid,
+
+    #[error("Entertainment recording stopped")]
+    EntRecordingFailed,
+
+    #[error("Invalid entertainment recording name: {0:?}")]
+    EntRecordingName(String),
+
+    #[error("Invalid entertainment segment map: {0}")]
+    EntSegmentMap(String),
+
+    #[error("Hue bridge entertainment stream failed: {0}")]
+    HueBridgeStream(String),
+
+    #[error("Invalid entertainment configuration ({} problems found)", .0.len())]
+    EntConfigInvalid(Vec<EntertainmentLocationError>),
+
     #[error("Invalid zigbee message")]
     ZigbeeMessageError,
+
+    #[error("Import failed: {0}")]
+    ImportFailed(String),
+
+    #[error("Invalid log level: {0:?}")]
+    InvalidLogLevel(String),
+
+    #[error("Invalid time zone: {0:?}")]
+    InvalidTimeZone(String),
+
+    #[error("Access denied: {0}")]
+    Forbidden(String),
+
+    #[error("Too many requests")]
+    TooManyRequests,
+
+    #[error("Backends are starting, the api is read-only until they have connected")]
+    BackendsStarting,
+
+    #[error("Invalid json: {0}")]
+    InvalidJson(String),
+
+    #[error("Model {0:?} not found in device database")]
+    UnknownModel(String),
+
+    #[error("Firmware file serving is not enabled")]
+    OtaDisabled,
+
+    #[error("Invalid firmware file name: {0:?}")]
+    OtaFileName(String),
+
+    #[error("Image {0} not found")]
+    ImageNotFound(Uuid),
+
+    #[error("Snapshot {0} not found")]
+    SnapshotNotFound(Uuid),
+
+    #[error("Unsupported image type: {0:?}")]
+    ImageType(String),
+
+    #[error("Invalid bridge id: {0:?} (expected 16 hex digits)")]
+    InvalidBridgeId(String),
+
+    #[error("Invalid rate limit: {0} requests per second (expected a positive number)")]
+    InvalidRateLimit(f64),
+
+    #[error("Invalid bridge personality {0:?}: {1}")]
+    Personality(String, String),
+
+    #[error("Cannot load secret {0:?}: {1}")]
+    Secret(String, String),
+
+    #[error("Unknown network interface: {0:?}")]
+    UnknownInterface(String),
+
+    #[error("Setup was aborted before completion")]
+    SetupAborted,
+
+    #[error("Missing certificate file {0:?} (not generated, since key_file is set)")]
+    CertificateMissing(Utf8PathBuf),
 }
 
 impl From<SvcError> for ApiError {
@@ -0,0 +1,285 @@
+//! Consistency checks for resources
+//!
+//! Resources link to each other in many ways (owners, room children, scene
+//! groups, etc). If a resource is removed without cleaning up everything
+//! that refers to it, the state is left with dangling links, which can
+//! confuse clients. This module finds and fixes those.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use hue::api::{Device, RType, Resource, ResourceLink, Room, Scene, Zone};
+
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+#[derive(Debug, Default, Serialize)]
+pub struct GcReport {
+    /// If true, nothing was changed; the report shows what would be done
+    pub dry_run: bool,
+    pub deleted: Vec<String>,
+    pub repaired: Vec<String>,
+}
+
+impl GcReport {
+    fn deleted(&mut self, msg: String) {
+        if self.dry_run {
+            log::info!("Consistency check: would delete {msg}");
+        } else {
+            log::info!("Consistency check: deleting {msg}");
+        }
+        self.deleted.push(msg);
+    }
+
+    fn repaired(&mut self, msg: String) {
+        if self.dry_run {
+            log::info!("Consistency check: would repair {msg}");
+        } else {
+            log::info!("Consistency check: repairing {msg}");
+        }
+        self.repaired.push(msg);
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.deleted.is_empty() && self.repaired.is_empty()
+    }
+}
+
+fn count_missing<'a>(
+    links: impl IntoIterator<Item = &'a ResourceLink>,
+    live: &BTreeSet<Uuid>,
+) -> usize {
+    links
+        .into_iter()
+        .filter(|link| !live.contains(&link.rid))
+        .count()
+}
+
+/// If `obj` cannot exist on its own, and the resource it belongs to is
+/// missing, return the reason
+fn find_orphan(obj: &Resource, live: &BTreeSet<Uuid>) -> Option<String> {
+    if let Some(owner) = obj.owner() {
+        if !live.contains(&owner.rid) {
+            return Some(format!("owner {} is missing", owner.rid));
+        }
+    }
+
+    match obj {
+        Resource::Scene(scene) if !live.contains(&scene.group.rid) => {
+            Some(format!("room or zone {} is missing", scene.group.rid))
+        }
+        _ => None,
+    }
+}
+
+impl Resources {
+    /// Remove dangling links from resource, returning the number of links
+    /// removed
+    fn repair_links(
+        &mut self,
+        link: &ResourceLink,
+        live: &BTreeSet<Uuid>,
+        dry_run: bool,
+    ) -> ApiResult<usize> {
+        let exists = |link: &ResourceLink| live.contains(&link.rid);
+
+        let missing = match self.get_resource_by_id(&link.rid)?.obj {
+            Resource::Device(dev) => count_missing(&dev.services, live),
+            Resource::Room(room) => count_missing(room.children.iter().chain(&room.services), live),
+            Resource::Zone(zone) => count_missing(zone.children.iter().chain(&zone.services), live),
+            Resource::Scene(scene) => {
+                count_missing(scene.actions.iter().map(|act| &act.target), live)
+            }
+            _ => 0,
+        };
+
+        if missing == 0 || dry_run {
+            return Ok(missing);
+        }
+
+        match link.rtype {
+            RType::Device => self.update::<Device>(&link.rid, |dev| {
+                dev.services.retain(exists);
+            })?,
+            RType::Room => self.update::<Room>(&link.rid, |room| {
+                room.children.retain(exists);
+                room.services.retain(exists);
+            })?,
+            RType::Zone => self.update::<Zone>(&link.rid, |zone| {
+                zone.children.retain(exists);
+                zone.services.retain(exists);
+            })?,
+            RType::Scene => self.update::<Scene>(&link.rid, |scene| {
+                scene.actions.retain(|act| exists(&act.target));
+            })?,
+            _ => {}
+        }
+
+        Ok(missing)
+    }
+
+    /// Find resources with links to missing resources.
+    ///
+    /// Resources that depend on a missing resource (services of a removed
+    /// device, scenes of a removed room, etc) are deleted. Resources that
+    /// merely list missing resources (room children, scene actions, etc) are
+    /// repaired, by removing the dangling links.
+    ///
+    /// If `dry_run` is true, nothing is changed.
+    pub fn collect_garbage(&mut self, dry_run: bool) -> ApiResult<GcReport> {
+        let mut report = GcReport {
+            dry_run,
+            ..GcReport::default()
+        };
+
+        let mut live: BTreeSet<Uuid> = self.get_resources().iter().map(|rec| rec.id).collect();
+
+        /* deleting a resource can leave other resources orphaned (e.g. a
+         * device, and then its services), so repeat until nothing changes */
+        loop {
+            let mut orphans = vec![];
+            for rec in self.get_resources() {
+                if !live.contains(&rec.id) {
+                    continue;
+                }
+                if let Some(reason) = find_orphan(&rec.obj, &live) {
+                    orphans.push((rec.obj.rtype().link_to(rec.id), reason));
+                }
+            }
+
+            if orphans.is_empty() {
+                break;
+            }
+
+            for (link, reason) in orphans {
+                report.deleted(format!("{}: {reason}", self.describe_link(&link)));
+                live.remove(&link.rid);
+                if !dry_run {
+                    self.delete(&link)?;
+                }
+            }
+        }
+
+        for rec in self.get_resources() {
+            if !live.contains(&rec.id) {
+                continue;
+            }
+            let link = rec.obj.rtype().link_to(rec.id);
+            let removed = self.repair_links(&link, &live, dry_run)?;
+            if removed > 0 {
+                report.repaired(format!(
+                    "{}: {removed} links to missing resources",
+                    self.describe_link(&link)
+                ));
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use maplit::btreeset;
+    use uuid::Uuid;
+
+    use hue::api::{
+        Device, DeviceArchetype, DeviceProductData, GroupedLight, Metadata, RType, Resource, Room,
+        RoomArchetype, RoomMetadata,
+    };
+    use hue::version::SwVersion;
+
+    use crate::config::EntertainmentConfig;
+    use crate::gc::find_orphan;
+    use crate::model::entertainment::EntertainmentSettings;
+    use crate::model::state::State;
+    use crate::resource::Resources;
+
+    fn resources() -> Resources {
+        Resources::new(
+            SwVersion::default(),
+            State::new(),
+            EntertainmentSettings::from_config(&EntertainmentConfig::default()),
+        )
+    }
+
+    fn device(name: &str) -> Resource {
+        Resource::Device(Device {
+            product_data: DeviceProductData::hue_bridge_v2(&SwVersion::default()),
+            metadata: Metadata::new(DeviceArchetype::SultanBulb, name),
+            services: btreeset![],
+            usertest: None,
+            identify: None,
+        })
+    }
+
+    #[test]
+    fn orphan_needs_missing_owner() {
+        let room = RType::Room.deterministic("office");
+        let glight = Resource::GroupedLight(GroupedLight::new(room));
+
+        assert!(find_orphan(&glight, &BTreeSet::from([room.rid])).is_none());
+        let reason = find_orphan(&glight, &BTreeSet::new()).unwrap();
+        assert!(reason.contains(&room.rid.to_string()), "{reason}");
+
+        // resources without owners are never orphans
+        assert!(find_orphan(&device("lamp"), &BTreeSet::new()).is_none());
+    }
+
+    #[test]
+    fn orphans_are_deleted_in_cascade() {
+        let mut res = resources();
+        let room = RType::Room.deterministic("gone");
+        let first = RType::GroupedLight.deterministic("first");
+        let second = RType::GroupedLight.deterministic("second");
+        res.add(&first, Resource::GroupedLight(GroupedLight::new(room)))
+            .unwrap();
+        res.add(&second, Resource::GroupedLight(GroupedLight::new(first)))
+            .unwrap();
+
+        let report = res.collect_garbage(true).unwrap();
+        assert_eq!(report.deleted.len(), 2, "{report:?}");
+        assert!(res.get::<GroupedLight>(&second).is_ok());
+
+        let report = res.collect_garbage(false).unwrap();
+        assert_eq!(report.deleted.len(), 2, "{report:?}");
+        assert!(res.get::<GroupedLight>(&first).is_err());
+        assert!(res.get::<GroupedLight>(&second).is_err());
+
+        assert!(res.collect_garbage(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dangling_links_are_repaired() {
+        let mut res = resources();
+        let lamp = RType::Device.deterministic("lamp");
+        let room = RType::Room.deterministic("office");
+        let missing = RType::Device.link_to(Uuid::new_v4());
+        res.add(&lamp, device("lamp")).unwrap();
+        res.add(
+            &room,
+            Resource::Room(Room {
+                children: btreeset![lamp, missing],
+                metadata: RoomMetadata::new(RoomArchetype::Office, "Office"),
+                services: btreeset![RType::GroupedLight.link_to(Uuid::new_v4())],
+            }),
+        )
+        .unwrap();
+
+        let report = res.collect_garbage(true).unwrap();
+        assert_eq!(report.repaired.len(), 1, "{report:?}");
+        assert!(report.repaired[0].contains("2 links"), "{report:?}");
+        assert_eq!(res.get::<Room>(&room).unwrap().children.len(), 2);
+
+        let report = res.collect_garbage(false).unwrap();
+        assert!(report.deleted.is_empty(), "{report:?}");
+        let obj = res.get::<Room>(&room).unwrap();
+        assert_eq!(obj.children, btreeset![lamp]);
+        assert!(obj.services.is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{
+    DeviceArchetype, Metadata, RType, ResourceLink, Room, RoomArchetype, Scene, SceneAction,
+    SceneActionElement, SceneMetadata, SceneRecall,
+};
+use hue::legacy_api::{ApiLightStateUpdate, ApiSceneAppData};
+use hue::scene_icons;
+
+use crate::error::ApiResult;
+use crate::import::{self, BifrostNames, ImportReport, SceneCreator};
+use crate::resource::Resources;
+
+/// The parts of a diyHue `config.json` that bifrost can import
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DiyHueConfig {
+    pub lights: BTreeMap<String, DiyHueLight>,
+    pub groups: BTreeMap<String, DiyHueGroup>,
+    pub scenes: BTreeMap<String, DiyHueScene>,
+    pub config: DiyHueBridgeConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DiyHueLight {
+    pub name: String,
+    pub uniqueid: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DiyHueGroup {
+    pub name: String,
+    pub lights: Vec<String>,
+    #[serde(rename = "type")]
+    pub group_type: String,
+    pub class: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DiyHueScene {
+    pub name: String,
+    pub group: Option<String>,
+    pub lightstates: BTreeMap<String, ApiLightStateUpdate>,
+    pub appdata: ApiSceneAppData,
+    pub image: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DiyHueBridgeConfig {
+    pub whitelist: BTreeMap<String, Value>,
+}
+
+/// The id spaces of diyHue, which numbers lights, groups and scenes
+/// separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IdSpace {
+    Light,
+    Group,
+    Scene,
+}
+
+/// Keeps track of v1 ids assigned during the import.
+///
+/// Bifrost has a single v1 id space for lights, groups and scenes, so the
+/// same number can only be kept by one of them. Lights are imported first,
+/// and keep their ids.
+#[derive(Default)]
+struct IdTracker {
+    claimed: HashMap<u32, (IdSpace, String)>,
+}
+
+impl IdTracker {
+    /// Try to give `uuid` the v1 id it had in diyHue, returning the id if
+    /// it was kept. Ids already used in bifrost are never taken over.
+    fn claim(
+        &mut self,
+        res: &mut Resources,
+        space: IdSpace,
+        id: &str,
+        uuid: Uuid,
+        desc: String,
+        report: &mut ImportReport,
+    ) -> Option<u32> {
+        let Ok(num) = id.parse::<u32>() else {
+            report.skipped(format!(
+                "v1 id {id:?} for {desc}: bifrost only has numeric ids"
+            ));
+            return None;
+        };
+
+        match self.claimed.get(&num) {
+            Some((other_space, other)) if *other_space == space => {
+                report.skipped(format!("v1 id {num} for {desc}: already used by {other}"));
+                return None;
+            }
+            Some((_, other)) => {
+                report.skipped(format!(
+                    "v1 id {num} for {desc}: used by {other} (bifrost has one id space for lights, groups and scenes)"
+                ));
+                return None;
+            }
+            None => {}
+        }
+
+        // never move the id of an existing resource, since apps and
+        // accessories paired with bifrost refer to it
+        if let Some(other) = res.from_id_v1(num).ok().filter(|other| *other != uuid) {
+            let other = res.get_resource_by_id(&other).map_or_else(
+                |_| other.to_string(),
+                |rec| res.describe_link(&rec.obj.rtype().link_to(other)),
+            );
+            report.skipped(format!(
+                "v1 id {num} for {desc}: already used by {other} in bifrost"
+            ));
+            return None;
+        }
+
+        res.set_id_v1(uuid, num);
+        self.claimed.insert(num, (space, desc));
+        Some(num)
+    }
+}
+
+/// Describe the v1 id a resource ended up with
+fn v1_id(res: &Resources, kept: Option<u32>, uuid: Uuid) -> String {
+    match (kept, res.get_id_v1_index(uuid)) {
+        (Some(id), _) => format!("v1 id {id}"),
+        (None, Ok(id)) => format!("v1 id {id} in bifrost"),
+        (None, Err(_)) => "v1 id given by bifrost".to_string(),
+    }
+}
+
+impl DiyHueConfig {
+    /// Recreate groups and scenes from diyHue in bifrost, keeping the v1 ids
+    /// of lights, and those of groups and scenes where they do not clash
+    /// (see [`IdTracker`]).
+    ///
+    /// Lights are matched by zigbee address (`uniqueid`), or by name.
+    pub fn import(&self, res: &mut Resources) -> ApiResult<ImportReport> {
+        let mut report = ImportReport::default();
+        let mut ids = IdTracker::default();
+        let names = BifrostNames::new(res);
+
+        let lights = self.import_lights(res, &names, &mut ids, &mut report);
+        let groups = self.import_groups(res, &names, &lights, &mut ids, &mut report)?;
+        self.import_scenes(res, &lights, &groups, &mut ids, &mut report)?;
+
+        for (key, user) in &self.config.whitelist {
+            let name = user.get("name").and_then(Value::as_str).unwrap_or(key);
+            report.imported(format!(
+                "api user {name:?}: accepted as-is (bifrost does not check usernames)"
+            ));
+        }
+
+        Ok(report)
+    }
+
+    fn import_lights(
+        &self,
+        res: &mut Resources,
+        names: &BifrostNames,
+        ids: &mut IdTracker,
+        report: &mut ImportReport,
+    ) -> HashMap<String, ResourceLink> {
+        let mut lights = HashMap::new();
+
+        for (id, light) in &self.lights {
+            let name = &light.name;
+            let Some(link) = names
+                .map_light_by_mac(&light.uniqueid)
+                .or_else(|| names.map_light(name))
+            else {
+                report.skipped(format!("light {name:?}: no match"));
+                continue;
+            };
+
+            let desc = format!("light {name:?}");
+            let kept = ids.claim(res, IdSpace::Light, id, link.rid, desc, report);
+            lights.insert(id.clone(), link);
+            report.imported(format!("light {name:?} ({})", v1_id(res, kept, link.rid)));
+        }
+
+        lights
+    }
+
+    fn import_groups(
+        &self,
+        res: &mut Resources,
+        names: &BifrostNames,
+        lights: &HashMap<String, ResourceLink>,
+        ids: &mut IdTracker,
+        report: &mut ImportReport,
+    ) -> ApiResult<HashMap<String, ResourceLink>> {
+        let mut groups = HashMap::new();
+
+        for (id, group) in &self.groups {
+            let name = &group.name;
+            let link = match group.group_type.as_str() {
+                // rooms come from zigbee2mqtt groups, so only existing rooms are used
+                "Room" => {
+                    let Some(link) = names.room(name).copied() else {
+                        report.skipped(format!(
+                            "room {name:?}: no bifrost room with that name (create a zigbee2mqtt group)"
+                        ));
+                        continue;
+                    };
+
+                    if let Some(archetype) = group.class.as_deref().and_then(room_archetype) {
+                        res.update::<Room>(&link.rid, |room| room.metadata.archetype = archetype)?;
+                    }
+
+                    link
+                }
+
+                "Zone" => {
+                    let children: BTreeSet<_> = group
+                        .lights
+                        .iter()
+                        .filter_map(|light| lights.get(light).copied())
+                        .collect();
+
+                    let metadata = Metadata::new(DeviceArchetype::default(), name);
+                    import::create_zone(res, ("diyhue", id), metadata, children)?
+                }
+
+                other => {
+                    report.skipped(format!("group {name:?}: type {other:?} not supported"));
+                    continue;
+                }
+            };
+
+            let kind = group.group_type.to_lowercase();
+            let desc = format!("{kind} {name:?}");
+            let kept = ids.claim(res, IdSpace::Group, id, link.rid, desc, report);
+            groups.insert(id.clone(), link);
+            report.imported(format!("{kind} {name:?} ({})", v1_id(res, kept, link.rid)));
+        }
+
+        Ok(groups)
+    }
+
+    fn import_scenes(
+        &self,
+        res: &mut Resources,
+        lights: &HashMap<String, ResourceLink>,
+        groups: &HashMap<String, ResourceLink>,
+        ids: &mut IdTracker,
+        report: &mut ImportReport,
+    ) -> ApiResult<()> {
+        let mut creator = SceneCreator::default();
+
+        for (id, scn) in &self.scenes {
+            let name = &scn.name;
+            let Some(group) = scn.group.as_ref().and_then(|grp| groups.get(grp)) else {
+                report.skipped(format!("scene {name:?}: room or zone was not imported"));
+                continue;
+            };
+
+            if import::find_scene(res, group, name).is_some() {
+                report.skipped(format!("scene {name:?}: already exists"));
+                continue;
+            }
+
+            let mut actions = vec![];
+            for (light, state) in &scn.lightstates {
+                let Some(target) = lights.get(light) else {
+                    report.skipped(format!("light {light} in scene {name:?}: not imported"));
+                    continue;
+                };
+                actions.push(SceneActionElement {
+                    action: SceneAction::from(state.clone()),
+                    target: *target,
+                });
+            }
+
+            let scene = Scene {
+                actions,
+                auto_dynamic: false,
+                group: *group,
+                metadata: SceneMetadata {
+                    appdata: scn.appdata.data.clone(),
+                    image: scn
+                        .image
+                        .map(|img| RType::PublicImage.link_to(img))
+                        .or_else(|| scene_icons::guess(name)),
+                    name: name.clone(),
+                },
+                palette: Value::Null,
+                speed: 0.5,
+                status: None,
+                recall: SceneRecall::default(),
+            };
+
+            let link = creator.create(res, *group, scene)?;
+            let desc = format!("scene {name:?}");
+            let kept = ids.claim(res, IdSpace::Scene, id, link.rid, desc, report);
+            report.imported(format!("scene {name:?} ({})", v1_id(res, kept, link.rid)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Map a diyHue room class (e.g. "Living room") to a room archetype
+fn room_archetype(class: &str) -> Option<RoomArchetype> {
+    let name = class.to_lowercase().replace(' ', "_");
+    serde_json::from_value(Value::String(name)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreeset;
+    use serde_json::json;
+
+    use hue::api::{
+        Device, DeviceArchetype, DeviceProductData, Metadata, RType, Resource, ResourceLink, Room,
+        RoomArchetype, RoomMetadata,
+    };
+    use hue::version::SwVersion;
+
+    use crate::config::EntertainmentConfig;
+    use crate::import::diyhue::DiyHueConfig;
+    use crate::model::entertainment::EntertainmentSettings;
+    use crate::model::state::State;
+    use crate::resource::Resources;
+
+    /// Bifrost with one room ("Office"), holding one light ("Desk lamp")
+    fn office() -> (Resources, ResourceLink, ResourceLink) {
+        let version = SwVersion::default();
+        let mut res = Resources::new(
+            version.clone(),
+            State::new(),
+            EntertainmentSettings::from_config(&EntertainmentConfig::default()),
+        );
+
+        let link_dev = RType::Device.deterministic("desk");
+        let link_light = RType::Light.deterministic("desk");
+        let link_room = RType::Room.deterministic("office");
+
+        let dev = Device {
+            product_data: DeviceProductData::hue_bridge_v2(&version),
+            metadata: Metadata::new(DeviceArchetype::SultanBulb, "Desk lamp"),
+            services: btreeset![link_light],
+            usertest: None,
+            identify: None,
+        };
+        let room = Room {
+            children: btreeset![link_dev],
+            metadata: RoomMetadata::new(RoomArchetype::Office, "Office"),
+            services: btreeset![],
+        };
+        res.add(&link_dev, Resource::Device(dev)).unwrap();
+        res.add(&link_room, Resource::Room(room)).unwrap();
+
+        (res, link_light, link_room)
+    }
+
+    #[test]
+    fn light_and_group_share_id() {
+        let (mut res, link_light, link_room) = office();
+        // keep bifrost's own ids out of the way
+        res.set_id_v1(link_room.rid, 10);
+
+        let config: DiyHueConfig = serde_json::from_value(json!({
+            "lights": {"1": {"name": "Desk lamp", "uniqueid": ""}},
+            "groups": {
+                "1": {"name": "Office", "type": "Room", "lights": ["1"]},
+                "2": {"name": "Reading", "type": "Zone", "lights": ["1"]},
+            },
+            "scenes": {"a1b2c3": {"name": "Relax", "group": "2"}},
+        }))
+        .unwrap();
+
+        let report = config.import(&mut res).unwrap();
+
+        let link_zone = RType::Zone.deterministic(("diyhue", "2"));
+        assert_eq!(res.get_id_v1_index(link_light.rid).unwrap(), 1);
+        assert_ne!(res.get_id_v1_index(link_room.rid).unwrap(), 1);
+        assert_eq!(res.get_id_v1_index(link_zone.rid).unwrap(), 2);
+
+        assert!(
+            report
+                .skipped
+                .iter()
+                .any(|msg| msg.starts_with("v1 id 1 for room") && msg.contains("Desk lamp")),
+            "{report:?}"
+        );
+        assert!(
+            report
+                .skipped
+                .iter()
+                .any(|msg| msg.starts_with("v1 id \"a1b2c3\"")),
+            "{report:?}"
+        );
+        assert!(
+            report
+                .imported
+                .iter()
+                .any(|msg| msg.starts_with("scene \"Relax\"")),
+            "{report:?}"
+        );
+    }
+
+    #[test]
+    fn existing_ids_are_kept() {
+        let (mut res, link_light, link_room) = office();
+        let room_id = res.get_id_v1_index(link_room.rid).unwrap();
+
+        let config: DiyHueConfig = serde_json::from_value(json!({
+            "lights": {room_id.to_string(): {"name": "Desk lamp", "uniqueid": ""}},
+        }))
+        .unwrap();
+
+        let report = config.import(&mut res).unwrap();
+
+        assert_eq!(res.get_id_v1_index(link_room.rid).unwrap(), room_id);
+        assert_ne!(res.get_id_v1_index(link_light.rid).ok(), Some(room_id));
+        assert!(
+            report.skipped.iter().any(|msg| {
+                msg.starts_with(&format!("v1 id {room_id} for light")) && msg.contains("Office")
+            }),
+            "{report:?}"
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{
+    BehaviorInstance, ButtonAction, ButtonActions, ButtonTarget, Metadata, RType, Resource,
+    ResourceLink, Room, RoomArchetype, Scene, SceneCycleExtended, SceneRecallStep,
+};
+
+use crate::error::{ApiError, ApiResult};
+use crate::import::{self, BifrostNames, ImportReport, SceneCreator};
+use crate::resource::Resources;
+
+/// All resources from a real hue bridge, as raw json
+pub struct BridgeResources {
+    res: BTreeMap<Uuid, Value>,
+}
+
+impl BridgeResources {
+    pub async fn fetch(address: &str, key: &str) -> ApiResult<Self> {
+        // hue bridges use self-signed certificates
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()?;
+
+        let reply: Value = client
+            .get(format!("https://{address}/clip/v2/resource"))
+            .header("hue-application-key", key)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(err) = reply
+            .pointer("/errors/0/description")
+            .and_then(Value::as_str)
+        {
+            return Err(ApiError::ImportFailed(err.to_string()));
+        }
+
+        let Some(data) = reply.get("data").and_then(Value::as_array) else {
+            return Err(ApiError::ImportFailed(
+                "Unexpected reply from hue bridge".to_string(),
+            ));
+        };
+
+        Ok(Self::from_records(data))
+    }
+
+    #[must_use]
+    pub fn from_records(data: &[Value]) -> Self {
+        let res = data
+            .iter()
+            .filter_map(|obj| {
+                let id = obj.get("id").and_then(Value::as_str)?.parse().ok()?;
+                Some((id, obj.clone()))
+            })
+            .collect();
+
+        Self { res }
+    }
+
+    fn by_type<'a>(&'a self, rtype: &'a str) -> impl Iterator<Item = (&'a Uuid, &'a Value)> {
+        self.res
+            .iter()
+            .filter(move |(_, obj)| obj.get("type").and_then(Value::as_str) == Some(rtype))
+    }
+
+    fn name(&self, id: &Uuid) -> Option<&str> {
+        self.res
+            .get(id)?
+            .pointer("/metadata/name")
+            .and_then(Value::as_str)
+    }
+
+    /// Name of the device a light (or device) belongs to
+    fn device_name(&self, link: &ResourceLink) -> Option<&str> {
+        if link.rtype == RType::Device {
+            return self.name(&link.rid);
+        }
+
+        let owner: ResourceLink =
+            serde_json::from_value(self.res.get(&link.rid)?.get("owner")?.clone()).ok()?;
+        self.name(&owner.rid)
+    }
+
+    /// Name of the device a button belongs to, and its control id
+    fn button(&self, id: &Uuid) -> Option<(&str, u32)> {
+        let control_id = self
+            .res
+            .get(id)?
+            .pointer("/metadata/control_id")?
+            .as_u64()?;
+        let device = self.device_name(&RType::Button.link_to(*id))?;
+        Some((device, u32::try_from(control_id).ok()?))
+    }
+
+    fn links(obj: &Value, key: &str) -> Vec<ResourceLink> {
+        obj.get(key)
+            .cloned()
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+
+    /// Recreate rooms, zones, scenes and accessory configurations from the
+    /// bridge in bifrost
+    ///
+    /// Devices are matched by name, so devices must have the same names in
+    /// zigbee2mqtt (or in bifrost) as on the hue bridge.
+    pub fn import(&self, res: &mut Resources) -> ApiResult<ImportReport> {
+        let mut report = ImportReport::default();
+        let names = BifrostNames::new(res);
+
+        let mut groups = HashMap::new();
+        let mut scenes = HashMap::new();
+        self.import_rooms(res, &names, &mut groups, &mut report)?;
+        self.import_zones(res, &names, &mut groups, &mut report)?;
+        self.import_scenes(res, &names, &groups, &mut scenes, &mut report)?;
+        self.import_accessories(res, &names, &groups, &scenes, &mut report)?;
+
+        Ok(report)
+    }
+
+    /// Rooms come from zigbee2mqtt groups, so only existing rooms are updated
+    fn import_rooms(
+        &self,
+        res: &mut Resources,
+        names: &BifrostNames,
+        groups: &mut HashMap<Uuid, ResourceLink>,
+        report: &mut ImportReport,
+    ) -> ApiResult<()> {
+        for (id, obj) in self.by_type("room") {
+            let name = self.name(id).unwrap_or_default();
+            let Some(link) = names.room(name) else {
+                report.skipped(format!(
+                    "room {name:?}: no bifrost room with that name (create a zigbee2mqtt group)"
+                ));
+                continue;
+            };
+
+            if let Some(archetype) = obj
+                .pointer("/metadata/archetype")
+                .cloned()
+                .and_then(|val| serde_json::from_value::<RoomArchetype>(val).ok())
+            {
+                res.update::<Room>(&link.rid, |room| room.metadata.archetype = archetype)?;
+            }
+
+            for child in Self::links(obj, "children") {
+                let dev = self.device_name(&child).unwrap_or_default();
+                if !names.has_device(dev) {
+                    report.skipped(format!("device {dev:?} in room {name:?}: no match"));
+                }
+            }
+
+            groups.insert(*id, *link);
+            report.imported(format!("room {name:?}"));
+        }
+
+        Ok(())
+    }
+
+    /// Zones are not backed by anything in zigbee2mqtt, so they are created
+    fn import_zones(
+        &self,
+        res: &mut Resources,
+        names: &BifrostNames,
+        groups: &mut HashMap<Uuid, ResourceLink>,
+        report: &mut ImportReport,
+    ) -> ApiResult<()> {
+        for (id, obj) in self.by_type("zone") {
+            let name = self.name(id).unwrap_or_default();
+            let children = Self::links(obj, "children")
+                .iter()
+                .filter_map(|child| names.map_light(self.device_name(child)?))
+                .collect();
+
+            let metadata = Metadata {
+                name: name.to_string(),
+                archetype: obj
+                    .pointer("/metadata/archetype")
+                    .cloned()
+                    .and_then(|val| serde_json::from_value(val).ok())
+                    .unwrap_or_default(),
+            };
+
+            let link_zone = import::create_zone(res, id, metadata, children)?;
+
+            groups.insert(*id, link_zone);
+            report.imported(format!("zone {name:?}"));
+        }
+
+        Ok(())
+    }
+
+    fn import_scenes(
+        &self,
+        res: &mut Resources,
+        names: &BifrostNames,
+        groups: &HashMap<Uuid, ResourceLink>,
+        scenes: &mut HashMap<Uuid, ResourceLink>,
+        report: &mut ImportReport,
+    ) -> ApiResult<()> {
+        let mut creator = SceneCreator::default();
+        for (id, obj) in self.by_type("scene") {
+            let name = self.name(id).unwrap_or_default();
+
+            let mut scene: Scene = match serde_json::from_value(obj.clone()) {
+                Ok(scene) => scene,
+                Err(err) => {
+                    report.skipped(format!("scene {name:?}: {err}"));
+                    continue;
+                }
+            };
+
+            let Some(group) = groups.get(&scene.group.rid) else {
+                report.skipped(format!("scene {name:?}: room or zone was not imported"));
+                continue;
+            };
+
+            if let Some(link) = import::find_scene(res, group, name) {
+                // accessories can still recall the existing scene
+                scenes.insert(*id, link);
+                report.skipped(format!("scene {name:?}: already exists"));
+                continue;
+            }
+
+            scene.actions.retain_mut(|act| {
+                let dev = self.device_name(&act.target).unwrap_or_default();
+                names.map_light(dev).map_or_else(
+                    || {
+                        report.skipped(format!("light {dev:?} in scene {name:?}: no match"));
+                        false
+                    },
+                    |light| {
+                        act.target = light;
+                        true
+                    },
+                )
+            });
+
+            let link = creator.create(res, *group, scene)?;
+            scenes.insert(*id, link);
+
+            report.imported(format!("scene {name:?}"));
+        }
+
+        Ok(())
+    }
+
+    /// Accessory (button) configurations are created with the buttons, rooms
+    /// and scenes they refer to mapped to their bifrost counterparts
+    fn import_accessories(
+        &self,
+        res: &mut Resources,
+        names: &BifrostNames,
+        groups: &HashMap<Uuid, ResourceLink>,
+        scenes: &HashMap<Uuid, ResourceLink>,
+        report: &mut ImportReport,
+    ) -> ApiResult<()> {
+        for (id, obj) in self.by_type("behavior_instance") {
+            let name = self.name(id).unwrap_or_default();
+
+            let mut inst: BehaviorInstance = match serde_json::from_value(obj.clone()) {
+                Ok(inst) => inst,
+                Err(err) => {
+                    report.skipped(format!("accessory {name:?}: {err}"));
+                    continue;
+                }
+            };
+
+            let mut config = match inst.button_configuration() {
+                Some(Ok(config)) => config,
+                Some(Err(err)) => {
+                    report.skipped(format!("accessory {name:?}: {err}"));
+                    continue;
+                }
+                None => {
+                    report.skipped(format!(
+                        "behavior {name:?}: only accessory configurations are supported"
+                    ));
+                    continue;
+                }
+            };
+
+            let link = RType::BehaviorInstance.deterministic(id);
+            if res.get::<BehaviorInstance>(&link).is_ok() {
+                report.skipped(format!("accessory {name:?}: already exists"));
+                continue;
+            }
+
+            let dev = self.device_name(&config.device).unwrap_or_default();
+            let Some(device) = names.device(dev) else {
+                report.skipped(format!("accessory {name:?}: device {dev:?} has no match"));
+                continue;
+            };
+            config.device = *device;
+
+            if !remap_targets(&mut config.targets, groups) {
+                report.skipped(format!(
+                    "room or zone of accessory {name:?}: was not imported"
+                ));
+            }
+
+            let mut buttons = BTreeMap::new();
+            for (button, mut actions) in std::mem::take(&mut config.buttons) {
+                let Some(link_button) = self
+                    .button(&button)
+                    .and_then(|(dev, control_id)| names.map_button(dev, control_id))
+                else {
+                    report.skipped(format!("button {button} of accessory {name:?}: no match"));
+                    continue;
+                };
+
+                if !remap_targets(&mut actions.targets, groups) {
+                    report.skipped(format!(
+                        "room or zone of button {button} in accessory {name:?}: was not imported"
+                    ));
+                }
+                if !remap_actions(&mut actions, scenes) {
+                    report.skipped(format!(
+                        "scene of button {button} in accessory {name:?}: was not imported"
+                    ));
+                }
+
+                buttons.insert(link_button.rid, actions);
+            }
+            config.buttons = buttons;
+
+            inst.configuration = serde_json::to_value(&config)?;
+            res.add(&link, Resource::BehaviorInstance(inst))?;
+
+            report.imported(format!("accessory {name:?}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Map rooms and zones to the imported ones, dropping those that were not
+/// imported. Returns false if any were dropped.
+fn remap_targets(targets: &mut Vec<ButtonTarget>, groups: &HashMap<Uuid, ResourceLink>) -> bool {
+    let count = targets.len();
+    targets.retain_mut(|target| {
+        groups
+            .get(&target.group.rid)
+            .map(|group| target.group = *group)
+            .is_some()
+    });
+    targets.len() == count
+}
+
+/// Map recalled scenes to the imported ones, dropping actions that recall a
+/// scene that was not imported. Returns false if any were dropped.
+fn remap_actions(actions: &mut ButtonActions, scenes: &HashMap<Uuid, ResourceLink>) -> bool {
+    let remap = |steps: &mut Vec<SceneRecallStep>| {
+        steps.iter_mut().all(|step| {
+            scenes
+                .get(&step.action.recall.rid)
+                .map(|scene| step.action.recall = *scene)
+                .is_some()
+        })
+    };
+
+    let mut complete = true;
+    for slot in [
+        &mut actions.on_short_release,
+        &mut actions.on_long_press,
+        &mut actions.on_repeat,
+    ] {
+        let found = match slot {
+            Some(ButtonAction::RecallSingle(steps)) => remap(steps),
+            Some(
+                ButtonAction::SceneCycle(slots)
+                | ButtonAction::SceneCycleExtended(SceneCycleExtended { slots, .. }),
+            ) => slots.iter_mut().all(remap),
+            Some(ButtonAction::Action(_) | ButtonAction::Other(_)) | None => true,
+        };
+        if !found {
+            *slot = None;
+            complete = false;
+        }
+    }
+
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreeset;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use hue::api::{
+        BehaviorInstance, BehaviorScript, Button, ButtonAction, ButtonEvent, Device,
+        DeviceArchetype, DeviceProductData, Metadata, RType, Resource, ResourceLink,
+    };
+    use hue::version::SwVersion;
+
+    use crate::config::EntertainmentConfig;
+    use crate::import::hue_bridge::BridgeResources;
+    use crate::model::entertainment::EntertainmentSettings;
+    use crate::model::state::State;
+    use crate::resource::Resources;
+
+    /// Bifrost with one switch ("Hallway switch"), with one button
+    fn hallway() -> (Resources, ResourceLink, ResourceLink) {
+        let version = SwVersion::default();
+        let mut res = Resources::new(
+            version.clone(),
+            State::new(),
+            EntertainmentSettings::from_config(&EntertainmentConfig::default()),
+        );
+
+        let link_dev = RType::Device.deterministic("switch");
+        let link_button = RType::Button.deterministic("switch");
+        let dev = Device {
+            product_data: DeviceProductData::hue_bridge_v2(&version),
+            metadata: Metadata::new(DeviceArchetype::UnknownArchetype, "Hallway switch"),
+            services: btreeset![link_button],
+            usertest: None,
+            identify: None,
+        };
+        res.add(&link_dev, Resource::Device(dev)).unwrap();
+        res.add(&link_button, Resource::Button(Button::new(link_dev, 1)))
+            .unwrap();
+
+        (res, link_dev, link_button)
+    }
+
+    #[test]
+    fn accessory_is_remapped() {
+        let (mut res, link_dev, link_button) = hallway();
+
+        let [dev, button, zone, scene, missing, inst] = [(); 6].map(|()| Uuid::new_v4());
+        let bridge = BridgeResources::from_records(&[
+            json!({"id": dev, "type": "device", "metadata": {"name": "Hallway switch"}}),
+            json!({
+                "id": button, "type": "button",
+                "owner": {"rid": dev, "rtype": "device"},
+                "metadata": {"control_id": 1},
+            }),
+            json!({
+                "id": zone, "type": "zone", "children": [],
+                "metadata": {"name": "Upstairs", "archetype": "home"},
+            }),
+            json!({
+                "id": scene, "type": "scene", "actions": [],
+                "group": {"rid": zone, "rtype": "zone"},
+                "metadata": {"name": "Night"}, "speed": 0.5, "status": null,
+            }),
+            json!({
+                "id": inst, "type": "behavior_instance",
+                "enabled": true,
+                "script_id": BehaviorScript::BUTTON_SCRIPT_ID,
+                "metadata": {"name": "Hallway switch"},
+                "last_error": null,
+                "status": null,
+                "configuration": {
+                    "device": {"rid": dev, "rtype": "device"},
+                    "buttons": {
+                        button.to_string(): {
+                            "on_short_release": {
+                                "recall_single": [{"action": {"recall": {"rid": scene, "rtype": "scene"}}}]
+                            },
+                            "on_long_press": {
+                                "recall_single": [{"action": {"recall": {"rid": missing, "rtype": "scene"}}}]
+                            },
+                        }
+                    },
+                    "where": [{"group": {"rid": zone, "rtype": "zone"}}],
+                },
+            }),
+        ]);
+
+        let report = bridge.import(&mut res).unwrap();
+        assert!(
+            report
+                .imported
+                .contains(&"accessory \"Hallway switch\"".to_string()),
+            "{report:?}"
+        );
+
+        let link_zone = RType::Zone.deterministic(zone);
+        let link_inst = RType::BehaviorInstance.deterministic(inst);
+        let config = res
+            .get::<BehaviorInstance>(&link_inst)
+            .unwrap()
+            .button_configuration()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(config.device, link_dev);
+        assert_eq!(config.targets(&link_button.rid), vec![link_zone]);
+
+        let actions = &config.buttons[&link_button.rid];
+        let Some(ButtonAction::RecallSingle(steps)) = actions.for_event(ButtonEvent::ShortRelease)
+        else {
+            panic!("expected scene recall");
+        };
+        let recalled = steps[0].action.recall;
+        assert_eq!(recalled.rtype, RType::Scene);
+        assert_ne!(recalled.rid, scene);
+        assert!(res
+            .get_scenes_for_room(&link_zone.rid)
+            .contains(&recalled.rid));
+
+        // the scene that was not imported is dropped, and reported
+        assert!(actions.for_event(ButtonEvent::LongPress).is_none());
+        assert!(
+            report
+                .skipped
+                .iter()
+                .any(|msg| msg.starts_with("scene of button")),
+            "{report:?}"
+        );
+
+        // importing again leaves the accessory alone
+        let report = bridge.import(&mut res).unwrap();
+        assert!(
+            report
+                .skipped
+                .contains(&"accessory \"Hallway switch\": already exists".to_string()),
+            "{report:?}"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+pub mod diyhue;
+pub mod hue_bridge;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use hue::api::{
+    Device, GroupedLight, LightUpdate, Metadata, RType, Resource, ResourceLink, Scene, SceneActive,
+    SceneStatus, Zone,
+};
+
+use crate::backend::BackendRequest;
+use crate::error::ApiResult;
+use crate::model::state::AuxData;
+use crate::resource::Resources;
+
+/// Summary of an import, returned to the user
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn imported(&mut self, msg: String) {
+        log::info!("Import: {msg}");
+        self.imported.push(msg);
+    }
+
+    pub fn skipped(&mut self, msg: String) {
+        log::warn!("Import: skipped {msg}");
+        self.skipped.push(msg);
+    }
+}
+
+/// Lookup tables for matching imported resources to bifrost resources
+pub(crate) struct BifrostNames {
+    devices: HashMap<String, ResourceLink>,
+    macs: HashMap<String, ResourceLink>,
+    lights: HashMap<Uuid, ResourceLink>,
+    buttons: HashMap<(Uuid, u32), ResourceLink>,
+    rooms: HashMap<String, ResourceLink>,
+}
+
+impl BifrostNames {
+    pub(crate) fn new(res: &Resources) -> Self {
+        let mut devices = HashMap::new();
+        let mut macs = HashMap::new();
+        let mut lights = HashMap::new();
+        let mut buttons = HashMap::new();
+        let mut rooms = HashMap::new();
+
+        for rr in res.get_resources() {
+            match rr.obj {
+                Resource::Device(Device {
+                    metadata, services, ..
+                }) => {
+                    if let Some(light) = services.iter().find(|svc| svc.rtype == RType::Light) {
+                        lights.insert(rr.id, *light);
+                    }
+                    devices.insert(metadata.name, RType::Device.link_to(rr.id));
+                }
+                Resource::Button(btn) => {
+                    buttons.insert(
+                        (btn.owner.rid, btn.metadata.control_id),
+                        RType::Button.link_to(rr.id),
+                    );
+                }
+                Resource::ZigbeeConnectivity(zc) => {
+                    macs.insert(normalize_mac(&zc.mac_address), zc.owner);
+                }
+                Resource::Room(room) => {
+                    rooms.insert(room.metadata.name, RType::Room.link_to(rr.id));
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            devices,
+            macs,
+            lights,
+            buttons,
+            rooms,
+        }
+    }
+
+    pub(crate) fn has_device(&self, name: &str) -> bool {
+        self.devices.contains_key(name)
+    }
+
+    pub(crate) fn device(&self, name: &str) -> Option<&ResourceLink> {
+        self.devices.get(name)
+    }
+
+    pub(crate) fn room(&self, name: &str) -> Option<&ResourceLink> {
+        self.rooms.get(name)
+    }
+
+    /// Find the light service of the device with the given name
+    pub(crate) fn map_light(&self, device_name: &str) -> Option<ResourceLink> {
+        let dev = self.devices.get(device_name)?;
+        self.lights.get(&dev.rid).copied()
+    }
+
+    /// Find the button with the given control id, on the device with the
+    /// given name
+    pub(crate) fn map_button(&self, device_name: &str, control_id: u32) -> Option<ResourceLink> {
+        let dev = self.devices.get(device_name)?;
+        self.buttons.get(&(dev.rid, control_id)).copied()
+    }
+
+    /// Find the light service of the device with the given zigbee address
+    pub(crate) fn map_light_by_mac(&self, mac: &str) -> Option<ResourceLink> {
+        let dev = self.macs.get(&normalize_mac(mac))?;
+        self.lights.get(&dev.rid).copied()
+    }
+}
+
+/// Normalize zigbee addresses from different sources, e.g.
+/// `0x001788010b4f1e2a` and `00:17:88:01:0b:4f:1e:2a-0b`
+fn normalize_mac(mac: &str) -> String {
+    let mac = mac.split('-').next().unwrap_or_default();
+    let mac = mac.strip_prefix("0x").unwrap_or(mac);
+    mac.chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Creates a zone (and its grouped light) with a deterministic id
+pub(crate) fn create_zone(
+    res: &mut Resources,
+    key: impl Hash + Copy,
+    metadata: Metadata,
+    children: BTreeSet<ResourceLink>,
+) -> ApiResult<ResourceLink> {
+    let link_zone = RType::Zone.deterministic(key);
+    let link_glight = RType::GroupedLight.deterministic(key);
+
+    let zone = Zone {
+        metadata,
+        children,
+        services: BTreeSet::from([link_glight]),
+    };
+
+    res.add(&link_zone, Resource::Zone(zone))?;
+    res.add(
+        &link_glight,
+        Resource::GroupedLight(GroupedLight::new(link_zone)),
+    )?;
+
+    Ok(link_zone)
+}
+
+/// Find the scene with the given name, in a room or zone
+#[must_use]
+pub(crate) fn find_scene(
+    res: &Resources,
+    group: &ResourceLink,
+    name: &str,
+) -> Option<ResourceLink> {
+    res.get_scenes_for_room(&group.rid)
+        .into_iter()
+        .map(|sid| RType::Scene.link_to(sid))
+        .find(|link| {
+            res.get::<Scene>(link)
+                .is_ok_and(|scn| scn.metadata.name == name)
+        })
+}
+
+/// Creates scenes, keeping track of scene ids handed out during the import
+#[derive(Default)]
+pub(crate) struct SceneCreator {
+    reserved: HashMap<Uuid, HashSet<u32>>,
+}
+
+impl SceneCreator {
+    pub(crate) fn create(
+        &mut self,
+        res: &mut Resources,
+        group: ResourceLink,
+        mut scene: Scene,
+    ) -> ApiResult<ResourceLink> {
+        scene.group = group;
+        scene.status = Some(SceneStatus {
+            active: SceneActive::Inactive,
+            last_recall: None,
+        });
+
+        let used = self.reserved.entry(group.rid).or_default();
+        let sid = res.get_next_scene_id_except(&group, used)?;
+        used.insert(sid);
+        let link_scene = RType::Scene.deterministic((group.rid, sid));
+
+        if group.rtype == RType::Zone {
+            // zones are not handled by any backend
+            res.aux_set(&link_scene, AuxData::new().with_index(sid));
+            res.add(&link_scene, Resource::Scene(scene))?;
+        } else {
+            // backends store scenes from the current light state, so
+            // set the lights to the scene first
+            for act in &scene.actions {
+                let upd = LightUpdate {
+                    on: act.action.on,
+                    dimming: act.action.dimming,
+                    color: act.action.color,
+                    color_temperature: act.action.color_temperature,
+                    ..LightUpdate::default()
+                };
+                res.backend_request(BackendRequest::LightUpdate(act.target, upd))?;
+            }
+            res.backend_request(BackendRequest::SceneCreate(link_scene, sid, scene))?;
+        }
+
+        Ok(link_scene)
+    }
+}
@@ -1,6 +1,9 @@
 pub mod backend;
 pub mod config;
 pub mod error;
+pub mod gc;
+pub mod import;
+pub mod logging;
 pub mod mdns;
 pub mod model;
 pub mod resource;
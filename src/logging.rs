@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use pretty_env_logger::env_logger::{self, filter::Filter, Logger};
+
+use crate::error::{ApiError, ApiResult};
+
+/// Per-module log levels, which override the base filters (from `RUST_LOG`),
+/// and can be changed at runtime.
+static LEVELS: RwLock<BTreeMap<String, LevelFilter>> = RwLock::new(BTreeMap::new());
+
+/// Most verbose level allowed by the base filters
+static BASE_LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+struct DynamicLogger {
+    inner: Logger,
+    base: Filter,
+}
+
+/// Find the override for a log target. The most specific module wins, so
+/// `bifrost::backend::z2m` takes precedence over `bifrost`.
+fn override_for(levels: &BTreeMap<String, LevelFilter>, target: &str) -> Option<LevelFilter> {
+    levels
+        .iter()
+        .filter(|(module, _)| {
+            target
+                .strip_prefix(module.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+        .max_by_key(|(module, _)| module.len())
+        .map(|(_, level)| *level)
+}
+
+fn update_max_level(levels: &BTreeMap<String, LevelFilter>) {
+    let max = levels
+        .values()
+        .chain(BASE_LEVEL.get())
+        .max()
+        .copied()
+        .unwrap_or(LevelFilter::Off);
+
+    log::set_max_level(max);
+}
+
+impl Log for DynamicLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let level = override_for(
+            &LEVELS.read().unwrap_or_else(PoisonError::into_inner),
+            metadata.target(),
+        );
+        level.map_or_else(
+            || self.base.enabled(metadata),
+            |level| metadata.level() <= level,
+        )
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Install the global logger.
+///
+/// Output is formatted by `builder`, while filtering is done by `filters`
+/// (in `RUST_LOG` syntax), combined with the per-module levels.
+pub fn init(mut builder: env_logger::Builder, filters: &str) -> ApiResult<()> {
+    let base = env_logger::filter::Builder::new().parse(filters).build();
+    let _ = BASE_LEVEL.set(base.filter());
+
+    let inner = builder.filter_level(LevelFilter::Trace).build();
+    log::set_boxed_logger(Box::new(DynamicLogger { inner, base }))?;
+
+    update_max_level(&LEVELS.read().unwrap_or_else(PoisonError::into_inner));
+
+    Ok(())
+}
+
+pub fn parse_level(level: &str) -> ApiResult<LevelFilter> {
+    LevelFilter::from_str(level).map_err(|_| ApiError::InvalidLogLevel(level.to_string()))
+}
+
+/// Current per-module levels
+#[must_use]
+pub fn levels() -> BTreeMap<String, LevelFilter> {
+    LEVELS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+pub fn set_level(module: &str, level: LevelFilter) {
+    let mut levels = LEVELS.write().unwrap_or_else(PoisonError::into_inner);
+    levels.insert(module.to_string(), level);
+    update_max_level(&levels);
+    drop(levels);
+}
+
+/// Remove the level for `module`, returning `false` if none was set
+pub fn clear_level(module: &str) -> bool {
+    let mut levels = LEVELS.write().unwrap_or_else(PoisonError::into_inner);
+    let removed = levels.remove(module).is_some();
+    update_max_level(&levels);
+    drop(levels);
+    removed
+}
+
+/// Set levels from the configuration file (see [`crate::config::LoggingConfig`])
+pub fn set_levels(levels: &BTreeMap<String, String>) -> ApiResult<()> {
+    for (module, level) in levels {
+        set_level(module, parse_level(level)?);
+    }
+    Ok(())
+}
@@ -1,15 +1,96 @@
 use std::io::Write;
+use std::net::Ipv4Addr;
+use std::time::Duration;
 
-use svc::manager::ServiceManager;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use mac_address::MacAddress;
+use url::Url;
 
+use hue::devicedb;
+use svc::manager::{ServiceManager, SvmClient};
+
+use bifrost::backend::coalesce::Coalescer;
+use bifrost::backend::coordinator::CoordinatorBackend;
+use bifrost::backend::matter::MatterBackend;
+use bifrost::backend::mqttlight::MqttLightBackend;
+use bifrost::backend::remote::RemoteBackend;
+use bifrost::backend::sink::SinkBackend;
+use bifrost::backend::virt::VirtualBackend;
+use bifrost::backend::z2m::snapshot::Z2mNetwork;
 use bifrost::backend::z2m::Z2mBackend;
+use bifrost::backend::zwave::ZwaveBackend;
 use bifrost::backend::Backend;
-use bifrost::config;
+use bifrost::config::locate::ConfigSource;
+use bifrost::config::{self, ConfigOverrides};
 use bifrost::error::ApiResult;
+use bifrost::logging;
 use bifrost::mdns;
 use bifrost::server;
 use bifrost::server::appstate::AppState;
+use bifrost::server::history::EventHistory;
 use bifrost::server::http::HttpServer;
+use bifrost::server::setup;
+
+/// How long to wait for each zigbee2mqtt server, when checking the config
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug)]
+#[command(version, long_about = None)]
+#[command(about("Hue bridge emulator"))]
+struct Args {
+    /// Configuration file (YAML, or TOML if it ends in .toml). Defaults to
+    /// config.yaml, or config.toml if that exists instead.
+    #[arg(short, long)]
+    config: Option<Utf8PathBuf>,
+
+    /// Check the configuration file (including references to zigbee2mqtt
+    /// groups and devices), report any problems, and exit
+    #[arg(long)]
+    check_config: bool,
+
+    /// Mac address of the bridge (overrides `bridge.mac`)
+    #[arg(long)]
+    mac: Option<MacAddress>,
+
+    /// Ip address of the bridge (overrides `bridge.ipaddress`)
+    #[arg(long)]
+    ipaddress: Option<Ipv4Addr>,
+
+    /// Port for http (overrides `bridge.http_port`)
+    #[arg(long)]
+    http_port: Option<u16>,
+
+    /// Port for https (overrides `bridge.https_port`)
+    #[arg(long)]
+    https_port: Option<u16>,
+
+    /// Port for entertainment streaming (overrides `bridge.entm_port`)
+    #[arg(long)]
+    entm_port: Option<u16>,
+
+    /// State file (overrides `bifrost.state_file`)
+    #[arg(long)]
+    state_file: Option<Utf8PathBuf>,
+
+    /// Url of the zigbee2mqtt server, if there is only one
+    #[arg(long)]
+    z2m_url: Option<Url>,
+}
+
+impl Args {
+    fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            mac: self.mac,
+            ipaddress: self.ipaddress,
+            http_port: self.http_port,
+            https_port: self.https_port,
+            entm_port: self.entm_port,
+            state_file: self.state_file.clone(),
+            z2m_url: self.z2m_url.clone(),
+        }
+    }
+}
 
 /*
  * Formatter function to output in syslog format. This makes sense when running
@@ -48,112 +129,454 @@ fn init_logging() -> ApiResult<()> {
     let log_filters = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTERS.join(","));
 
     /* Detect if we need syslog or human-readable formatting */
-    if std::env::var("SYSTEMD_EXEC_PID").is_ok_and(|pid| pid == std::process::id().to_string()) {
-        Ok(pretty_env_logger::env_logger::builder()
-            .format(syslog_format)
-            .parse_filters(&log_filters)
-            .try_init()?)
+    let builder = if std::env::var("SYSTEMD_EXEC_PID")
+        .is_ok_and(|pid| pid == std::process::id().to_string())
+    {
+        let mut builder = pretty_env_logger::env_logger::builder();
+        builder.format(syslog_format);
+        builder
     } else {
-        Ok(pretty_env_logger::formatted_timed_builder()
-            .parse_filters(&log_filters)
-            .try_init()?)
-    }
+        pretty_env_logger::formatted_timed_builder()
+    };
+
+    logging::init(builder, &log_filters)
 }
 
+/// Service name, prefixed with the bridge personality (if any)
+fn service_name(personality: Option<&str>, base: &str) -> String {
+    personality.map_or_else(|| base.to_string(), |prefix| format!("{prefix}/{base}"))
+}
+
+/// Register the http, https and entertainment services, for every address
+/// to listen on
 #[allow(clippy::similar_names)]
-async fn build_tasks(appstate: &AppState) -> ApiResult<()> {
+async fn build_listeners(
+    appstate: &AppState,
+    personality: Option<&str>,
+    mgr: &mut SvmClient,
+) -> ApiResult<()> {
+    let bconf = &appstate.config().bridge;
+    let svc = server::build_service(appstate.clone(), false);
+    #[cfg(any(feature = "tls-rustls", feature = "tls-openssl"))]
+    let tls_svc = server::build_service(appstate.clone(), true);
+
+    for (idx, listen) in bconf.listen_addrs().iter().enumerate() {
+        let addr = listen.address;
+        let name = |base: &str| match idx {
+            0 => service_name(personality, base),
+            _ => service_name(personality, &format!("{base}-{idx}")),
+        };
+
+        // register plain http service
+        let http_service = HttpServer::http(addr, bconf.http_port, svc.clone());
+        mgr.register_service(name("http"), http_service).await?;
+
+        // if openssl is enabled, use that for https (since it also supports DTLS)
+        #[cfg(feature = "tls-openssl")]
+        let https_service = HttpServer::https_openssl(
+            addr,
+            bconf.https_port,
+            tls_svc.clone(),
+            &appstate.config().bifrost.cert_file,
+            appstate.config().bifrost.key_file(),
+        )?;
+
+        // .. otherwise, if rustls is enabled, use that
+        #[cfg(all(feature = "tls-rustls", not(feature = "tls-openssl")))]
+        let https_service = HttpServer::https_rustls(
+            addr,
+            bconf.https_port,
+            tls_svc.clone(),
+            &appstate.config().bifrost.cert_file,
+            appstate.config().bifrost.key_file(),
+        )
+        .await?;
+
+        // .. if either tls backend is enabled, register https service
+        #[cfg(any(feature = "tls-rustls", feature = "tls-openssl"))]
+        mgr.register_service(name("https"), https_service).await?;
+
+        // register entertainment streaming listener
+        let svc = server::entertainment::EntertainmentService::new(
+            addr,
+            bconf.entm_port,
+            appstate.config(),
+            appstate.res.clone(),
+        )?;
+        mgr.register_service(name("entertainment"), svc).await?;
+    }
+
+    Ok(())
+}
+
+/// Register the services that act on lights by themselves: presence
+/// simulation and user scripts
+async fn build_automations(
+    appstate: &AppState,
+    personality: Option<&str>,
+    mgr: &mut SvmClient,
+) -> ApiResult<()> {
+    let name = |base: &str| service_name(personality, base);
+
+    // register presence simulation, if configured
+    if let Some(conf) = &appstate.config().vacation {
+        if appstate.config().history.hours == 0 {
+            log::warn!("Presence simulation needs the event history, which is disabled");
+        } else {
+            let sim = server::vacation::VacationSim::new(
+                conf.clone(),
+                appstate.config().history.hours,
+                appstate.res.clone(),
+                appstate.history(),
+                appstate.away().subscribe(),
+            );
+            mgr.register_function(name("vacation"), sim.run_forever())
+                .await?;
+        }
+    }
+
+    // register script host, if configured
+    if let Some(conf) = &appstate.config().scripting {
+        #[cfg(feature = "scripting")]
+        {
+            let host = server::scripting::ScriptHost::new(conf.clone(), appstate.res.clone());
+            mgr.register_function(name("scripting"), host.run_forever())
+                .await?;
+        }
+        #[cfg(not(feature = "scripting"))]
+        log::warn!(
+            "Scripts in {} are not loaded: bifrost is built without scripting",
+            conf.dir
+        );
+    }
+
+    Ok(())
+}
+
+/// Register the backends for devices that are not on zigbee
+async fn build_backends(
+    appstate: &AppState,
+    personality: Option<&str>,
+    mgr: &mut SvmClient,
+) -> ApiResult<()> {
+    let name = |base: &str| service_name(personality, base);
+
+    // register remote backends (plugins), if any are configured
+    for (remote_name, conf) in &appstate.config().remote {
+        let client = RemoteBackend::new(remote_name.clone(), conf.clone(), appstate.res.clone());
+        let key = format!("remote-{remote_name}");
+        appstate.res.lock().await.register_backend(&key);
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name(&key), svc).await?;
+    }
+
+    // register mqtt light backend, if any lights are configured
+    if let Some(conf) = &appstate.config().mqtt_lights {
+        let client = MqttLightBackend::new(conf.clone(), appstate.res.clone());
+        appstate.res.lock().await.register_backend("mqtt-lights");
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name("mqtt-lights"), svc).await?;
+    }
+
+    // register z-wave js backends, if any are configured
+    for (server_name, server) in &appstate.config().zwave {
+        let client = ZwaveBackend::new(server_name.clone(), server.clone(), appstate.res.clone());
+        let key = format!("zwave-{server_name}");
+        appstate.res.lock().await.register_backend(&key);
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name(&key), svc).await?;
+    }
+
+    // register matter backend, if configured
+    if let Some(conf) = &appstate.config().matter {
+        let client = MatterBackend::new(conf.clone(), appstate.res.clone());
+        appstate.res.lock().await.register_backend("matter");
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name("matter"), svc).await?;
+    }
+
+    // register native zigbee coordinator, if configured
+    if let Some(conf) = &appstate.config().coordinator {
+        let client = CoordinatorBackend::new(conf.clone(), appstate.res.clone());
+        appstate.res.lock().await.register_backend("coordinator");
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name("coordinator"), svc).await?;
+    }
+
+    Ok(())
+}
+
+/// Register all services for one bridge. Services of additional bridge
+/// personalities are named "<personality>/<service>".
+async fn build_tasks(appstate: &AppState, personality: Option<&str>) -> ApiResult<()> {
     let bconf = &appstate.config().bridge;
-    let _mdns = mdns::register_mdns(bconf.mac, bconf.ipaddress);
+    let bridge_id = bconf.bridge_id();
+    let _mdns = mdns::register_mdns(bconf.mac, &bridge_id, &bconf.advertised_addrs());
 
     let mut mgr = appstate.manager();
+    let name = |base: &str| service_name(personality, base);
 
-    let svc = server::build_service(appstate.clone());
-
-    log::info!("Serving mac [{}]", bconf.mac);
-
-    // register plain http service
-    let http_service = HttpServer::http(bconf.ipaddress, bconf.http_port, svc.clone());
-    mgr.register_service("http", http_service).await?;
-
-    // if openssl is enabled, use that for https (since it also supports DTLS)
-    #[cfg(feature = "tls-openssl")]
-    let https_service = HttpServer::https_openssl(
-        bconf.ipaddress,
-        bconf.https_port,
-        svc.clone(),
-        &appstate.config().bifrost.cert_file,
-    )?;
-
-    // .. otherwise, if rustls is enabled, use that
-    #[cfg(all(feature = "tls-rustls", not(feature = "tls-openssl")))]
-    let https_service = HttpServer::https_rustls(
-        bconf.ipaddress,
-        bconf.https_port,
-        svc.clone(),
-        &appstate.config().bifrost.cert_file,
-    )
-    .await?;
+    log::info!("Serving mac [{}] as bridge [{bridge_id}]", bconf.mac);
 
-    // .. if either tls backend is enabled, register https service
-    #[cfg(any(feature = "tls-rustls", feature = "tls-openssl"))]
-    mgr.register_service("https", https_service).await?;
+    build_listeners(appstate, personality, &mut mgr).await?;
+
+    // register backend request coalescing, before any backends
+    let (input, output) = {
+        let lock = appstate.res.lock().await;
+        (lock.backend_request_stream(), lock.backend_sender())
+    };
+    let coalescer = Coalescer::new(appstate.config().bifrost.min_command_interval, output);
+    mgr.register_function(name("coalescer"), coalescer.run_forever(input))
+        .await?;
 
     // register config writer
     let svc = server::config_writer(
         appstate.res.clone(),
         appstate.config().bifrost.state_file.clone(),
     );
-    mgr.register_function("config_writer", svc).await?;
+    mgr.register_function(name("config_writer"), svc).await?;
+
+    // register automation state writer
+    let svc = server::automation_writer(
+        appstate.res.clone(),
+        appstate.config().bifrost.automation_file.clone(),
+    );
+    mgr.register_function(name("automation_writer"), svc)
+        .await?;
 
     // register version updater
     let svc = server::version_updater(appstate.res.clone(), appstate.updater());
-    mgr.register_function("version_updater", svc).await?;
+    mgr.register_function(name("version_updater"), svc).await?;
 
-    // register entertainment streaming listener
-    let svc = server::entertainment::EntertainmentService::new(
-        bconf.ipaddress,
-        bconf.entm_port,
-        appstate.res.clone(),
-    )?;
-    mgr.register_service("entertainment", svc).await?;
+    // register event history recorder, unless disabled
+    if appstate.config().history.hours > 0 {
+        let svc = EventHistory::run_forever(appstate.history(), appstate.res.clone());
+        mgr.register_function(name("event_history"), svc).await?;
+    }
+
+    // register animation clock for dynamic scenes
+    let svc = server::dynamics::SceneDynamics::new(appstate.res.clone()).run_forever();
+    mgr.register_function(name("scene_dynamics"), svc).await?;
+
+    // register webhooks, if any are configured
+    for (idx, conf) in appstate.config().webhooks.iter().enumerate() {
+        let hook = server::webhook::Webhook::new(conf.clone())?;
+        let svc = hook.run_forever(appstate.res.clone());
+
+        mgr.register_function(name(&format!("webhook-{idx}")), svc)
+            .await?;
+    }
+
+    build_automations(appstate, personality, &mut mgr).await?;
+
+    // register mqtt publisher, if configured
+    if let Some(conf) = &appstate.config().mqtt {
+        let (publisher, eventloop) =
+            server::mqtt::MqttPublisher::new(conf.clone(), appstate.res.clone());
+        let svc = publisher.run_forever(eventloop);
+
+        mgr.register_function(name("mqtt-publisher"), svc).await?;
+    }
+
+    // register influx exporter, if configured
+    if let Some(conf) = &appstate.config().influx {
+        let exporter = server::influx::InfluxExporter::new(conf.clone(), appstate.res.clone())?;
+
+        mgr.register_function(name("influx-exporter"), exporter.run_forever())
+            .await?;
+    }
+
+    // register dmx input, if any universes are configured
+    if !appstate.config().entertainment.dmx.is_empty() {
+        let input =
+            server::dmx::DmxInput::new(appstate.config(), appstate.res.clone(), bconf.ipaddress);
+
+        mgr.register_function(name("dmx-input"), input.run_forever())
+            .await?;
+    }
 
     // register all z2m backends as services
-    for (name, server) in &appstate.config().z2m.servers {
+    for (server_name, server) in &appstate.config().z2m.servers {
         let client = Z2mBackend::new(
-            name.clone(),
+            server_name.clone(),
             server.clone(),
             appstate.config(),
             appstate.res.clone(),
         )?;
+        appstate.res.lock().await.register_backend(server_name);
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name(&format!("z2m-{server_name}")), svc)
+            .await?;
+    }
+
+    // register entertainment sinks, if any are configured
+    if !appstate.config().entertainment.areas.is_empty() {
+        let client = SinkBackend::new(appstate.config(), appstate.res.clone());
         let stream = appstate.res.lock().await.backend_event_stream();
-        let name = format!("z2m-{name}");
         let svc = client.run_forever(stream);
 
-        mgr.register_function(name, svc).await?;
+        mgr.register_function(name("entertainment-sinks"), svc)
+            .await?;
     }
 
-    // finally, iterate over all services and start them
-    for (id, _name) in mgr.list().await? {
-        mgr.start(id).await?;
+    // register virtual device backend, if any devices are configured
+    if !appstate.config().virtual_devices.is_empty() {
+        let client = VirtualBackend::new(appstate.config(), appstate.res.clone());
+        let stream = appstate.res.lock().await.backend_event_stream();
+        let svc = client.run_forever(stream);
+
+        mgr.register_function(name("virtual"), svc).await?;
     }
 
+    build_backends(appstate, personality, &mut mgr).await?;
+
     Ok(())
 }
 
-async fn run() -> ApiResult<()> {
+/// Check a configuration file, and print the problems found. Returns true
+/// if there were none.
+async fn check_config(filename: &Utf8Path) -> ApiResult<bool> {
+    let (config, mut issues) = config::check(filename)?;
+
+    if let Some(config) = &config {
+        let mut groups = vec![];
+        let mut devices = vec![];
+        let mut complete = true;
+
+        for (name, server) in &config.z2m.servers {
+            match tokio::time::timeout(CHECK_TIMEOUT, Z2mNetwork::fetch(server)).await {
+                Ok(Ok(network)) => {
+                    groups.extend(network.groups);
+                    devices.extend(network.devices);
+                }
+                Ok(Err(err)) => {
+                    log::warn!("[{name}] Cannot reach zigbee2mqtt server: {err}");
+                    complete = false;
+                }
+                Err(_) => {
+                    log::warn!("[{name}] Timeout waiting for zigbee2mqtt server");
+                    complete = false;
+                }
+            }
+        }
+
+        if complete {
+            let src = ConfigSource::read(filename)?;
+            issues.extend(config::check_network(&src, config, &groups, &devices));
+        } else {
+            log::warn!("Not checking references to zigbee2mqtt groups and devices");
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.position);
+    for issue in &issues {
+        match issue.position {
+            Some((line, column)) => println!("{filename}:{line}:{column}: {}", issue.message),
+            None => println!("{filename}: {}", issue.message),
+        }
+    }
+
+    if issues.is_empty() {
+        println!("{filename}: configuration is valid");
+    }
+
+    Ok(issues.is_empty())
+}
+
+async fn run(args: Args) -> ApiResult<()> {
     init_logging()?;
 
+    let filename = args.config.clone().unwrap_or_else(config::default_path);
+
+    if args.check_config {
+        if !check_config(&filename).await? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     #[cfg(feature = "server-banner")]
     server::banner::print()?;
 
-    let config = config::parse("config.yaml".into())?;
+    let (client, future) = ServiceManager::spawn();
+
+    // without a config file, run the setup wizard to create one first
+    let setup = if filename.exists() {
+        None
+    } else {
+        let port = args.http_port.unwrap_or(config::default_http_port());
+        Some(setup::run(&filename, port, client.clone()).await?)
+    };
+
+    let config = config::parse(&filename, &args.overrides())?;
     log::debug!("Configuration loaded successfully");
 
-    let (client, future) = ServiceManager::spawn();
+    logging::set_levels(&config.logging.levels)?;
+
+    devicedb::set_quirks(config.bifrost.load_quirks()?);
+
+    let personalities = config.personalities();
+
+    let appstate = AppState::from_config(config, client.clone()).await?;
+
+    // save the initial state right away, and remember the new api key
+    if let Some(setup) = setup {
+        let state_file = &appstate.config().bifrost.state_file;
+        if !state_file.exists() {
+            if let Some(dir) = state_file.parent().filter(|dir| !dir.as_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(state_file, appstate.res.lock().await.serialize()?)?;
+        }
+        appstate
+            .register_devicetype(setup.app_key, setup::DEVICETYPE.to_string())
+            .await;
+    }
+
+    // install panic hook, and keep its diagnostic snapshot up to date
+    let crash = server::crash::CrashReporter::new(
+        appstate.config().bifrost.crash_file.clone(),
+        appstate.res.clone(),
+        appstate.manager(),
+    );
+    crash.install();
+    appstate
+        .manager()
+        .register_function("crash_reporter", crash.run_forever())
+        .await?;
 
-    let appstate = AppState::from_config(config, client).await?;
+    build_tasks(&appstate, None).await?;
 
-    build_tasks(&appstate).await?;
+    // build additional bridge personalities, each with its own state
+    for (name, config) in personalities {
+        if let Some(dir) = config.bifrost.state_file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        log::info!("Starting bridge personality [{name}]");
+        let state = AppState::from_config(config, client.clone()).await?;
+        build_tasks(&state, Some(&name)).await?;
+    }
+
+    // finally, iterate over all services and start them (except for the
+    // setup wizard, which is done at this point)
+    let mut mgr = appstate.manager();
+    for (id, name) in mgr.list().await? {
+        if name != setup::SERVICE_NAME {
+            mgr.start(id).await?;
+        }
+    }
 
     tokio::spawn(async move {
         if matches!(tokio::signal::ctrl_c().await, Ok(())) {
@@ -169,7 +592,9 @@ async fn run() -> ApiResult<()> {
 
 #[tokio::main]
 async fn main() {
-    if let Err(err) = run().await {
+    let args = Args::parse();
+
+    if let Err(err) = run(args).await {
         log::error!("Bifrost error: {err}");
         log::error!("Fatal error encountered, cannot continue.");
     }
@@ -1,11 +1,15 @@
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use mac_address::MacAddress;
 use mdns_sd::{ServiceDaemon, ServiceInfo};
 
 use crate::error::ApiResult;
 
-pub fn register_mdns(mac: MacAddress, ip: Ipv4Addr) -> ApiResult<ServiceDaemon> {
+pub fn register_mdns(
+    mac: MacAddress,
+    bridge_id: &str,
+    addrs: &[IpAddr],
+) -> ApiResult<ServiceDaemon> {
     /* Create a new mDNS daemon. */
     let mdns = ServiceDaemon::new()?;
     let service_type = "_hue._tcp.local.";
@@ -17,12 +21,16 @@ pub fn register_mdns(mac: MacAddress, ip: Ipv4Addr) -> ApiResult<ServiceDaemon>
     );
 
     let service_hostname = format!("{instance_name}.{service_type}");
-    let service_addr = ip.to_string();
+    let service_addr = addrs
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
     let service_port = 80;
 
     let properties = [
         ("modelid", hue::HUE_BRIDGE_V2_MODEL_ID),
-        ("bridgeid", &hue::bridge_id(mac)),
+        ("bridgeid", bridge_id),
     ];
 
     let service_info = ServiceInfo::new(
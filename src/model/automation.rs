@@ -0,0 +1,135 @@
+//! Runtime state of automations (schedules, rules and behavior instances)
+//!
+//! The definitions of automations live in the resource state, but their
+//! bookkeeping (when they last fired, what happened, when they fire next)
+//! changes far more often. Keeping it in a separate file avoids rewriting
+//! the state file every time a button is pressed, and makes the automation
+//! history easy to inspect on its own.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+
+use camino::Utf8Path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ApiResult;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationKind {
+    Schedule,
+    Rule,
+    BehaviorInstance,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "status", content = "error")]
+pub enum AutomationResult {
+    Success,
+    Failed(String),
+}
+
+impl<T, E: ToString> From<&Result<T, E>> for AutomationResult {
+    fn from(value: &Result<T, E>) -> Self {
+        match value {
+            Ok(_) => Self::Success,
+            Err(err) => Self::Failed(err.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AutomationEntry {
+    pub kind: AutomationKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_fire: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_fire: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_result: Option<AutomationResult>,
+    #[serde(default)]
+    pub fire_count: u64,
+}
+
+impl AutomationEntry {
+    #[must_use]
+    pub const fn new(kind: AutomationKind) -> Self {
+        Self {
+            kind,
+            next_fire: None,
+            last_fire: None,
+            last_result: None,
+            fire_count: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AutomationStore {
+    automations: BTreeMap<Uuid, AutomationEntry>,
+}
+
+impl AutomationStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the store from `path`. A missing or unreadable file gives an
+    /// empty store, since automation bookkeeping can always be rebuilt.
+    #[must_use]
+    pub fn load(path: &Utf8Path) -> Self {
+        let Ok(fd) = File::open(path) else {
+            return Self::new();
+        };
+
+        serde_yml::from_reader(fd).unwrap_or_else(|err| {
+            log::warn!("Ignoring invalid automation file {path}: {err}");
+            Self::new()
+        })
+    }
+
+    pub fn serialize(&self) -> ApiResult<String> {
+        Ok(serde_yml::to_string(self)?)
+    }
+
+    #[must_use]
+    pub fn get(&self, id: &Uuid) -> Option<&AutomationEntry> {
+        self.automations.get(id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&Uuid, &AutomationEntry)> {
+        self.automations.iter()
+    }
+
+    fn entry(&mut self, id: Uuid, kind: AutomationKind) -> &mut AutomationEntry {
+        self.automations
+            .entry(id)
+            .or_insert_with(|| AutomationEntry::new(kind))
+    }
+
+    /// Record that an automation has fired, with the outcome
+    pub fn record_fire(&mut self, id: Uuid, kind: AutomationKind, result: AutomationResult) {
+        let entry = self.entry(id, kind);
+        entry.last_fire = Some(Utc::now());
+        entry.last_result = Some(result);
+        entry.fire_count += 1;
+    }
+
+    pub fn set_next_fire(&mut self, id: Uuid, kind: AutomationKind, next: Option<DateTime<Utc>>) {
+        self.entry(id, kind).next_fire = next;
+    }
+
+    /// Forget an automation (e.g. because it was deleted). Returns true if
+    /// anything was removed.
+    pub fn remove(&mut self, id: &Uuid) -> bool {
+        self.automations.remove(id).is_some()
+    }
+
+    /// Keep only the automations for which `func` returns true
+    pub fn retain(&mut self, mut func: impl FnMut(&Uuid, &AutomationEntry) -> bool) {
+        self.automations.retain(|id, entry| func(id, entry));
+    }
+}
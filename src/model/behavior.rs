@@ -0,0 +1,157 @@
+//! Accessory behaviors: what happens when a button is pressed
+//!
+//! The hue app configures accessories (dimmer switches, tap switches, etc)
+//! by creating `behavior_instance` resources for the accessory behavior
+//! script. Each instance maps button events to scene recalls or simple
+//! actions on rooms and zones, which bifrost executes when the button
+//! events arrive from the backend.
+
+use uuid::Uuid;
+
+use hue::api::{
+    BehaviorInstance, ButtonAction, ButtonEvent, DeltaAction, DimmingDeltaUpdate,
+    GroupedLightUpdate, On, RType, ResourceLink, Room, Scene, SceneActive, SceneRecallStep,
+    SceneStatus, SceneUpdate, SimpleAction, Zone,
+};
+
+use crate::backend::BackendRequest;
+use crate::resource::Resources;
+
+/// Brightness change (in percent) for each repeat event while dimming
+const DIM_STEP: f64 = 10.0;
+
+/// Backend requests for all enabled behaviors that react to `event` on the
+/// given button, by behavior instance
+#[must_use]
+pub fn button_requests(
+    res: &Resources,
+    button: &Uuid,
+    event: ButtonEvent,
+) -> Vec<(Uuid, Vec<BackendRequest>)> {
+    let mut requests = vec![];
+
+    for id in res.get_resource_ids_by_type(RType::BehaviorInstance) {
+        let Ok(inst) = res.get_id::<BehaviorInstance>(id) else {
+            continue;
+        };
+
+        if !inst.enabled {
+            continue;
+        }
+
+        let config = match inst.button_configuration() {
+            Some(Ok(config)) => config,
+            Some(Err(err)) => {
+                log::warn!(
+                    "Invalid accessory configuration in {}: {err}",
+                    res.describe_link(&RType::BehaviorInstance.link_to(id))
+                );
+                continue;
+            }
+            None => continue,
+        };
+
+        let Some(action) = config
+            .buttons
+            .get(button)
+            .and_then(|actions| actions.for_event(event))
+        else {
+            continue;
+        };
+
+        requests.push((id, action_requests(res, action, &config.targets(button))));
+    }
+
+    requests
+}
+
+fn action_requests(
+    res: &Resources,
+    action: &ButtonAction,
+    targets: &[ResourceLink],
+) -> Vec<BackendRequest> {
+    let upd = match action {
+        ButtonAction::RecallSingle(steps) => return recall(steps),
+        ButtonAction::SceneCycle(slots) => return scene_cycle(res, slots),
+        ButtonAction::SceneCycleExtended(cycle) => return scene_cycle(res, &cycle.slots),
+        ButtonAction::Action(SimpleAction::AllOff) => {
+            GroupedLightUpdate::new().with_on(Some(On::new(false)))
+        }
+        ButtonAction::Action(SimpleAction::DimUp) => dim(DeltaAction::Up),
+        ButtonAction::Action(SimpleAction::DimDown) => dim(DeltaAction::Down),
+        ButtonAction::Action(SimpleAction::DoNothing) => return vec![],
+        ButtonAction::Other(value) => {
+            log::debug!("Unsupported button action: {value}");
+            return vec![];
+        }
+    };
+
+    targets
+        .iter()
+        .filter_map(|target| grouped_light(res, target))
+        .map(|glight| BackendRequest::GroupedLightUpdate(glight, upd.clone()))
+        .collect()
+}
+
+fn dim(action: DeltaAction) -> GroupedLightUpdate {
+    GroupedLightUpdate {
+        dimming_delta: Some(DimmingDeltaUpdate {
+            action,
+            brightness_delta: Some(DIM_STEP),
+        }),
+        ..GroupedLightUpdate::new()
+    }
+}
+
+fn recall(steps: &[SceneRecallStep]) -> Vec<BackendRequest> {
+    let status = SceneStatus {
+        active: SceneActive::Static,
+        last_recall: None,
+    };
+
+    steps
+        .iter()
+        .map(|step| step.action.recall)
+        .filter(|link| link.rtype == RType::Scene)
+        .map(|link| {
+            BackendRequest::SceneUpdate(link, SceneUpdate::new().with_recall_action(Some(status)))
+        })
+        .collect()
+}
+
+/// Recall the slot after the one that is currently active (or the first
+/// slot, if none of them are)
+fn scene_cycle(res: &Resources, slots: &[Vec<SceneRecallStep>]) -> Vec<BackendRequest> {
+    let is_active = |step: &SceneRecallStep| {
+        res.get::<Scene>(&step.action.recall).is_ok_and(|scene| {
+            scene
+                .status
+                .is_some_and(|st| st.active != SceneActive::Inactive)
+        })
+    };
+
+    let next = slots
+        .iter()
+        .position(|slot| slot.iter().any(is_active))
+        .map_or(0, |idx| idx + 1);
+
+    slots
+        .get(next)
+        .or_else(|| slots.first())
+        .map(|slot| recall(slot))
+        .unwrap_or_default()
+}
+
+/// The grouped light service of a room or zone
+fn grouped_light(res: &Resources, target: &ResourceLink) -> Option<ResourceLink> {
+    let services = match target.rtype {
+        RType::Room => &res.get::<Room>(target).ok()?.services,
+        RType::Zone => &res.get::<Zone>(target).ok()?.services,
+        _ => return None,
+    };
+
+    services
+        .iter()
+        .find(|link| link.rtype == RType::GroupedLight)
+        .copied()
+}
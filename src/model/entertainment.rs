@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use hue::xy::XY;
+
+use crate::config::EntertainmentConfig;
+
+/// Runtime settings for entertainment streaming
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EntertainmentSettings {
+    pub max_fps: u32,
+    pub smoothing: u16,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct EntertainmentSettingsUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fps: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<u16>,
+}
+
+impl EntertainmentSettings {
+    pub const MAX_FPS_LIMIT: u32 = 60;
+
+    #[must_use]
+    pub fn from_config(config: &EntertainmentConfig) -> Self {
+        let mut res = Self {
+            max_fps: 0,
+            smoothing: config.smoothing,
+        };
+        res += &EntertainmentSettingsUpdate::new().with_max_fps(Some(config.max_fps));
+        res
+    }
+
+    /// Apply per-area overrides (if any) from the config, and then the
+    /// settings changed at runtime, which take precedence over both
+    #[must_use]
+    pub fn for_area(
+        mut self,
+        config: &EntertainmentConfig,
+        runtime: &EntertainmentSettingsUpdate,
+        id: &Uuid,
+        name: &str,
+    ) -> Self {
+        if let Some(area) = config.area(id, name) {
+            self += &EntertainmentSettingsUpdate {
+                max_fps: area.max_fps,
+                smoothing: area.smoothing,
+            };
+        }
+        self += runtime;
+        self
+    }
+}
+
+impl EntertainmentSettingsUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn with_max_fps(self, max_fps: Option<u32>) -> Self {
+        Self { max_fps, ..self }
+    }
+
+    #[must_use]
+    pub const fn with_smoothing(self, smoothing: Option<u16>) -> Self {
+        Self { smoothing, ..self }
+    }
+}
+
+impl AddAssign<&Self> for EntertainmentSettingsUpdate {
+    fn add_assign(&mut self, upd: &Self) {
+        self.max_fps = upd.max_fps.or(self.max_fps);
+        self.smoothing = upd.smoothing.or(self.smoothing);
+    }
+}
+
+impl AddAssign<&EntertainmentSettingsUpdate> for EntertainmentSettings {
+    fn add_assign(&mut self, upd: &EntertainmentSettingsUpdate) {
+        if let Some(max_fps) = upd.max_fps {
+            self.max_fps = max_fps.clamp(1, Self::MAX_FPS_LIMIT);
+        }
+
+        if let Some(smoothing) = upd.smoothing {
+            self.smoothing = smoothing;
+        }
+    }
+}
+
+/// Smoothing of entertainment colors, with its own strength and state for
+/// each light
+///
+/// The smoothing value in the zigbee frame header applies to every light in
+/// an area. This filter blends each new color with the previous one (an
+/// exponential moving average), so single lights can be made calmer than
+/// the rest of the area.
+#[derive(Debug)]
+pub struct SmoothingFilter<K> {
+    /// Weight of the previous color, for lights with smoothing enabled
+    strength: HashMap<K, f64>,
+    /// Last color and brightness sent to each light
+    last: HashMap<K, (XY, f64)>,
+}
+
+impl<K> Default for SmoothingFilter<K> {
+    fn default() -> Self {
+        Self {
+            strength: HashMap::new(),
+            last: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Copy> SmoothingFilter<K> {
+    /// Highest strength, so lights never stop following the stream
+    pub const MAX_STRENGTH: f64 = 0.99;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the smoothing strength of a light, from 0.0 (off) to 1.0
+    pub fn set_strength(&mut self, key: K, strength: f64) {
+        if strength.is_finite() && strength > 0.0 {
+            self.strength.insert(key, strength.min(Self::MAX_STRENGTH));
+        } else {
+            self.strength.remove(&key);
+            self.last.remove(&key);
+        }
+    }
+
+    /// Smooth the next color and brightness for a light
+    pub fn apply(&mut self, key: K, (xy, bright): (XY, f64)) -> (XY, f64) {
+        let Some(strength) = self.strength.get(&key).copied() else {
+            return (xy, bright);
+        };
+
+        let blend = |old: f64, new: f64| old.mul_add(strength, new * (1.0 - strength));
+        let next = self
+            .last
+            .get(&key)
+            .map_or((xy, bright), |(last_xy, last_bright)| {
+                (
+                    XY::new(blend(last_xy.x, xy.x), blend(last_xy.y, xy.y)),
+                    blend(*last_bright, bright),
+                )
+            });
+
+        self.last.insert(key, next);
+        next
+    }
+}
+
+/// Statistics for a single entertainment streaming session
+///
+/// Shared between the entertainment server (which receives frames) and the
+/// backend (which sends them to zigbee), so all counters are atomic.
+#[derive(Debug)]
+pub struct EntertainmentStats {
+    area: Uuid,
+    name: String,
+    channels: usize,
+    started: DateTime<Utc>,
+    base: Instant,
+    ended: Mutex<Option<DateTime<Utc>>>,
+    frames_received: AtomicU64,
+    frames_forwarded: AtomicU64,
+    frames_sent: AtomicU64,
+    last_forwarded_us: AtomicU64,
+    latency_us: AtomicU64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EntertainmentStatsReport {
+    pub area: Uuid,
+    pub name: String,
+    pub active: bool,
+    pub started: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended: Option<DateTime<Utc>>,
+    pub duration: f64,
+    pub channels: usize,
+    pub frames_received: u64,
+    pub frames_forwarded: u64,
+    pub frames_sent: u64,
+    pub frames_dropped: u64,
+    pub fps: f64,
+    pub latency: f64,
+}
+
+impl EntertainmentStats {
+    #[must_use]
+    pub fn new(area: Uuid, name: &str, channels: usize) -> Self {
+        Self {
+            area,
+            name: name.to_string(),
+            channels,
+            started: Utc::now(),
+            base: Instant::now(),
+            ended: Mutex::new(None),
+            frames_received: AtomicU64::new(0),
+            frames_forwarded: AtomicU64::new(0),
+            frames_sent: AtomicU64::new(0),
+            last_forwarded_us: AtomicU64::new(0),
+            latency_us: AtomicU64::new(0),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn elapsed_us(&self) -> u64 {
+        self.base.elapsed().as_micros() as u64
+    }
+
+    /// A frame was received from the streaming client
+    pub fn frame_received(&self) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A frame was passed on to the backends
+    pub fn frame_forwarded(&self) {
+        self.frames_forwarded.fetch_add(1, Ordering::Relaxed);
+        self.last_forwarded_us
+            .store(self.elapsed_us(), Ordering::Relaxed);
+    }
+
+    /// A frame was sent to zigbee by a backend
+    pub fn frame_sent(&self) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+
+        // latency estimate: time since the most recent frame was forwarded,
+        // smoothed as an exponential moving average
+        let sample = self
+            .elapsed_us()
+            .saturating_sub(self.last_forwarded_us.load(Ordering::Relaxed));
+        let old = self.latency_us.load(Ordering::Relaxed);
+        let new = if old == 0 {
+            sample
+        } else {
+            (old * 7 + sample) / 8
+        };
+        self.latency_us.store(new, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        if let Ok(mut ended) = self.ended.lock() {
+            ended.get_or_insert_with(Utc::now);
+        }
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn report(&self) -> EntertainmentStatsReport {
+        let ended = self.ended.lock().ok().and_then(|e| *e);
+        let duration = (ended.unwrap_or_else(Utc::now) - self.started)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let frames_received = self.frames_received.load(Ordering::Relaxed);
+        let frames_sent = self.frames_sent.load(Ordering::Relaxed);
+
+        EntertainmentStatsReport {
+            area: self.area,
+            name: self.name.clone(),
+            active: ended.is_none(),
+            started: self.started,
+            ended,
+            duration,
+            channels: self.channels,
+            frames_received,
+            frames_forwarded: self.frames_forwarded.load(Ordering::Relaxed),
+            frames_sent,
+            frames_dropped: frames_received.saturating_sub(frames_sent),
+            fps: if duration > 0.0 {
+                frames_sent as f64 / duration
+            } else {
+                0.0
+            },
+            latency: self.latency_us.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        }
+    }
+}
+
+impl Display for EntertainmentStatsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} ({} channels): {:.1}s, {} frames received, {} sent to zigbee, {} dropped, {:.1} fps, latency {:.1}ms",
+            self.name,
+            self.channels,
+            self.duration,
+            self.frames_received,
+            self.frames_sent,
+            self.frames_dropped,
+            self.fps,
+            self.latency * 1000.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hue::xy::XY;
+
+    use crate::model::entertainment::SmoothingFilter;
+
+    const RED: XY = XY::new(0.7, 0.3);
+    const BLUE: XY = XY::new(0.15, 0.06);
+
+    fn assert_close(a: (XY, f64), b: (XY, f64)) {
+        assert!(
+            (a.0.x - b.0.x).abs() < 1e-9
+                && (a.0.y - b.0.y).abs() < 1e-9
+                && (a.1 - b.1).abs() < 1e-9,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn lights_without_smoothing_pass_through() {
+        let mut filter = SmoothingFilter::new();
+        filter.set_strength(1, 0.5);
+
+        assert_close(filter.apply(2, (RED, 255.0)), (RED, 255.0));
+        assert_close(filter.apply(2, (BLUE, 0.0)), (BLUE, 0.0));
+    }
+
+    #[test]
+    fn smoothing_blends_with_previous_color() {
+        let mut filter = SmoothingFilter::new();
+        filter.set_strength(1, 0.75);
+
+        // the first color is taken as-is
+        assert_close(filter.apply(1, (RED, 200.0)), (RED, 200.0));
+
+        let mid = XY::new(
+            0.7f64.mul_add(0.75, 0.15 * 0.25),
+            0.3f64.mul_add(0.75, 0.06 * 0.25),
+        );
+        assert_close(filter.apply(1, (BLUE, 0.0)), (mid, 150.0));
+
+        // and keeps moving towards the new color
+        let (xy, bright) = filter.apply(1, (BLUE, 0.0));
+        assert!(xy.x < mid.x && bright < 150.0);
+    }
+
+    #[test]
+    fn state_is_per_light() {
+        let mut filter = SmoothingFilter::new();
+        filter.set_strength(1, 0.5);
+        filter.set_strength(2, 0.5);
+
+        filter.apply(1, (RED, 200.0));
+        assert_close(filter.apply(2, (BLUE, 100.0)), (BLUE, 100.0));
+        assert_close(filter.apply(1, (RED, 100.0)), (RED, 150.0));
+    }
+
+    #[test]
+    fn strength_limits() {
+        let mut filter = SmoothingFilter::new();
+
+        // full strength would freeze the light
+        filter.set_strength(1, 1.0);
+        filter.apply(1, (RED, 0.0));
+        let (_, bright) = filter.apply(1, (RED, 100.0));
+        assert!(bright > 0.0);
+
+        // zero (or invalid) strength turns smoothing off, and forgets the
+        // previous color
+        for strength in [0.0, f64::NAN] {
+            filter.set_strength(1, strength);
+            assert_close(filter.apply(1, (BLUE, 50.0)), (BLUE, 50.0));
+        }
+    }
+}
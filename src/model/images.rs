@@ -0,0 +1,145 @@
+//! Scene images (the `public_image` resource)
+//!
+//! Scenes link to a `public_image` for their thumbnail. The standard scene
+//! icons are always available, as simple placeholders (the real artwork is
+//! not distributed with bifrost). Users can add their own images, which are
+//! stored in `bifrost.image_dir`, named by their id.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use uuid::Uuid;
+
+use hue::scene_icons;
+
+use crate::error::{ApiError, ApiResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageType {
+    Png,
+    Jpeg,
+    Svg,
+}
+
+impl ImageType {
+    const ALL: [Self; 3] = [Self::Png, Self::Jpeg, Self::Svg];
+
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Svg => "svg",
+        }
+    }
+
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Svg => "image/svg+xml",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> ApiResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|typ| typ.content_type() == content_type)
+            .ok_or_else(|| ApiError::ImageType(content_type.to_string()))
+    }
+
+    fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|typ| typ.extension() == ext)
+    }
+}
+
+/// Placeholder image for one of the standard scene icons
+#[must_use]
+pub fn placeholder(id: &Uuid) -> Option<String> {
+    let (idx, (_, name)) = scene_icons::ALL
+        .iter()
+        .enumerate()
+        .find(|(_, (icon, _))| icon == id)?;
+
+    let hue = idx * 360 / scene_icons::ALL.len();
+    let initial = name.chars().next().unwrap_or(' ');
+
+    Some(format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 64 64">"#,
+            r#"<circle cx="32" cy="32" r="30" fill="hsl({hue}, 60%, 55%)"/>"#,
+            r#"<text x="32" y="41" font-family="sans-serif" font-size="26" "#,
+            r#"text-anchor="middle" fill="white">{initial}</text>"#,
+            "</svg>"
+        ),
+        hue = hue,
+        initial = initial,
+    ))
+}
+
+pub struct ImageStore {
+    dir: Utf8PathBuf,
+}
+
+impl ImageStore {
+    #[must_use]
+    pub fn new(dir: &Utf8Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn path(&self, id: &Uuid, typ: ImageType) -> Utf8PathBuf {
+        self.dir.join(format!("{id}.{}", typ.extension()))
+    }
+
+    /// All user images, with their type. A missing image directory has no
+    /// images.
+    #[must_use]
+    pub fn list(&self) -> Vec<(Uuid, ImageType)> {
+        let Ok(entries) = self.dir.read_dir_utf8() else {
+            return vec![];
+        };
+
+        let mut res: Vec<(Uuid, ImageType)> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                let id = path.file_stem()?.parse().ok()?;
+                let typ = ImageType::from_extension(path.extension()?)?;
+                Some((id, typ))
+            })
+            .collect();
+        res.sort_by_key(|(id, _)| *id);
+
+        res
+    }
+
+    /// Find a user image
+    #[must_use]
+    pub fn find(&self, id: &Uuid) -> Option<ImageType> {
+        ImageType::ALL
+            .into_iter()
+            .find(|typ| self.path(id, *typ).is_file())
+    }
+
+    pub fn load(&self, id: &Uuid) -> ApiResult<(ImageType, Vec<u8>)> {
+        let typ = self.find(id).ok_or(ApiError::ImageNotFound(*id))?;
+        Ok((typ, std::fs::read(self.path(id, typ))?))
+    }
+
+    /// Store a new user image, and return its id
+    pub fn save(&self, typ: ImageType, data: &[u8]) -> ApiResult<Uuid> {
+        let id = Uuid::new_v4();
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(&id, typ), data)?;
+        log::info!("Stored {} image {id}", typ.extension());
+        Ok(id)
+    }
+
+    pub fn delete(&self, id: &Uuid) -> ApiResult<()> {
+        let typ = self.find(id).ok_or(ApiError::ImageNotFound(*id))?;
+        std::fs::remove_file(self.path(id, typ))?;
+        log::info!("Deleted image {id}");
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+//! Upgrades for old state files.
+//!
+//! Migrations operate on the raw yaml value of the state, so they can handle
+//! data that no longer deserializes into the current types (renamed fields,
+//! new required fields, etc).
+//!
+//! To change the state format:
+//!
+//!  1. Add a new version to [`StateVersion`], and update
+//!     [`StateVersion::CURRENT`]
+//!  2. Add a function here that upgrades the previous version to the new one,
+//!     and append it to [`MIGRATIONS`]
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_yml::{Mapping, Value};
+use uuid::Uuid;
+
+use crate::error::ApiResult;
+use crate::model::state::{AuxData, IdMap, StateVersion};
+
+type Migration = fn(Value) -> ApiResult<Value>;
+
+/// Migration steps, indexed by the version they upgrade from
+const MIGRATIONS: &[Migration] = &[v0_to_v1];
+
+/// Upgrade state from `version` to [`StateVersion::CURRENT`]
+pub fn migrate(mut state: Value, version: StateVersion) -> ApiResult<Value> {
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!("Upgrading state from version {from} to {}..", from + 1);
+        state = step(state)?;
+    }
+
+    Ok(state)
+}
+
+/// Convert to a yaml value, the same way it would be written to a file.
+///
+/// [`serde_yml::to_value`] is not a human readable serializer, so it would
+/// store uuids as byte sequences, which do not load back as uuid strings.
+fn to_value(value: impl Serialize) -> ApiResult<Value> {
+    Ok(serde_yml::from_str(&serde_yml::to_string(&value)?)?)
+}
+
+/// Version 0 was a (`res`, `aux`) tuple, without version or `id_v1` fields
+fn v0_to_v1(state: Value) -> ApiResult<Value> {
+    let (res, v0aux): (Mapping, Mapping) = serde_yml::from_value(state)?;
+
+    log::debug!("Importing aux data from old v0 state..");
+    let mut aux: BTreeMap<Uuid, AuxData> = BTreeMap::new();
+    for (key, value) in v0aux {
+        log::debug!("  {key:?}: {value:?}");
+        aux.insert(serde_yml::from_value(key)?, serde_yml::from_value(value)?);
+    }
+
+    /* generate all missing id_v1 entries */
+    log::debug!("Synthesizing id_v1 entries for all resources..");
+    let mut id_v1 = IdMap::new();
+    for key in res.keys() {
+        id_v1.add(serde_yml::from_value(key.clone())?);
+    }
+
+    let mut state = Mapping::new();
+    state.insert("version".into(), to_value(StateVersion::V1)?);
+    state.insert("aux".into(), to_value(aux)?);
+    state.insert("id_v1".into(), to_value(id_v1)?);
+    state.insert("res".into(), Value::Mapping(res));
+
+    Ok(Value::Mapping(state))
+}
@@ -1,2 +1,12 @@
+pub mod automation;
+pub mod behavior;
+pub mod entertainment;
+pub mod images;
+pub mod migration;
+pub mod query;
+pub mod recording;
+pub mod snapshot;
 pub mod state;
+pub mod statediff;
+pub mod takeover;
 pub mod throttle;
@@ -0,0 +1,361 @@
+//! Server-side filtering of resources
+//!
+//! Used by `GET /clip/v2/resource` (and `/clip/v2/resource/<type>`) when a
+//! query string is given, and by the admin search in `/bifrost/resources`,
+//! e.g. `?type=light&capability=gradient&room=Living%20room`
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use hue::api::{RType, Resource};
+
+use crate::error::{ApiError, ApiResult};
+use crate::model::state::State;
+
+/// Light features that can be searched for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Dimming,
+    ColorTemperature,
+    Color,
+    Gradient,
+    Effects,
+}
+
+impl Capability {
+    /// True if `obj` is a light with this capability
+    #[must_use]
+    pub const fn supported_by(self, obj: &Resource) -> bool {
+        let Resource::Light(light) = obj else {
+            return false;
+        };
+
+        match self {
+            Self::Dimming => light.dimming.is_some(),
+            Self::ColorTemperature => light.color_temperature.is_some(),
+            Self::Color => light.color.is_some(),
+            Self::Gradient => light.gradient.is_some(),
+            Self::Effects => light.effects.is_some() || light.effects_v2.is_some(),
+        }
+    }
+}
+
+/// Filters for resource queries. All given filters must match, except for
+/// `type`, which may be repeated to match any of several types.
+#[derive(Clone, Debug, Default)]
+pub struct ResourceQuery {
+    pub types: BTreeSet<RType>,
+    pub owner: Option<Uuid>,
+    /// Room or zone, by id or name
+    pub room: Option<String>,
+    /// Case-insensitive part of the resource name
+    pub name: Option<String>,
+    pub capabilities: BTreeSet<Capability>,
+    pub limit: Option<usize>,
+}
+
+/// A room or zone, and the ids of its children
+#[derive(Clone, Debug)]
+pub struct RoomScope {
+    group: Uuid,
+    children: BTreeSet<Uuid>,
+}
+
+fn parse_enum<T: for<'de> Deserialize<'de>>(what: &str, value: &str) -> ApiResult<T> {
+    serde_json::from_value(value.into())
+        .map_err(|_| ApiError::InvalidJson(format!("unknown {what} {value:?}")))
+}
+
+impl ResourceQuery {
+    pub fn parse(query: Option<&str>) -> ApiResult<Self> {
+        let mut res = Self::default();
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match &*key {
+                "type" => {
+                    res.types.insert(parse_enum("resource type", &value)?);
+                }
+                "owner" => {
+                    let id = value
+                        .parse()
+                        .map_err(|_| ApiError::InvalidJson(format!("invalid owner {value:?}")))?;
+                    res.owner = Some(id);
+                }
+                "room" => res.room = Some(value.into_owned()),
+                "name" => res.name = Some(value.to_lowercase()),
+                "capability" => {
+                    res.capabilities.insert(parse_enum("capability", &value)?);
+                }
+                "limit" => res.limit = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+
+    /// True if no filters are given (a limit alone does not count)
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+            && self.owner.is_none()
+            && self.room.is_none()
+            && self.name.is_none()
+            && self.capabilities.is_empty()
+    }
+
+    /// Find the room or zone named by the `room` filter, if any
+    pub fn room_scope(&self, state: &State) -> ApiResult<Option<RoomScope>> {
+        let Some(room) = &self.room else {
+            return Ok(None);
+        };
+
+        let is_group = |obj: &Resource| matches!(obj, Resource::Room(_) | Resource::Zone(_));
+
+        let group = room.parse::<Uuid>().map_or_else(
+            |_| {
+                state
+                    .res
+                    .iter()
+                    .filter(|(_, obj)| is_group(obj))
+                    .find(|(id, _)| {
+                        state
+                            .resource_name(id)
+                            .is_some_and(|name| name.eq_ignore_ascii_case(room))
+                    })
+                    .map(|(id, _)| *id)
+            },
+            |id| state.try_get(&id).filter(|obj| is_group(obj)).map(|_| id),
+        );
+
+        let unknown = || ApiError::InvalidJson(format!("unknown room {room:?}"));
+
+        let group = group.ok_or_else(unknown)?;
+        let children = match state.try_get(&group) {
+            Some(Resource::Room(obj)) => &obj.children,
+            Some(Resource::Zone(obj)) => &obj.children,
+            _ => return Err(unknown()),
+        };
+
+        Ok(Some(RoomScope {
+            group,
+            children: children.iter().map(|link| link.rid).collect(),
+        }))
+    }
+
+    /// True if the resource matches all filters. Resources are in a room if
+    /// they are a child of it, belong to one of its children, or belong to
+    /// the room itself (scenes and grouped lights).
+    #[must_use]
+    pub fn matches(
+        &self,
+        state: &State,
+        scope: Option<&RoomScope>,
+        id: &Uuid,
+        obj: &Resource,
+    ) -> bool {
+        let owner = obj.owner().map(|link| link.rid);
+
+        if !self.types.is_empty() && !self.types.contains(&obj.rtype()) {
+            return false;
+        }
+
+        if self.owner.is_some() && owner != self.owner {
+            return false;
+        }
+
+        if let Some(scope) = scope {
+            let group = match obj {
+                Resource::Scene(scene) => Some(scene.group.rid),
+                _ => owner,
+            };
+            let in_room = scope.children.contains(id)
+                || owner.is_some_and(|owner| scope.children.contains(&owner))
+                || group == Some(scope.group);
+            if !in_room {
+                return false;
+            }
+        }
+
+        if !self.capabilities.iter().all(|cap| cap.supported_by(obj)) {
+            return false;
+        }
+
+        self.name.as_ref().map_or(true, |needle| {
+            state
+                .resource_name(id)
+                .is_some_and(|name| name.to_lowercase().contains(needle))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreeset;
+
+    use hue::api::{
+        Device, DeviceArchetype, DeviceProductData, Dimming, GroupedLight, Light, LightMetadata,
+        Metadata, RType, Resource, ResourceLink, Room, RoomArchetype, RoomMetadata,
+    };
+    use hue::version::SwVersion;
+
+    use crate::model::query::{Capability, ResourceQuery};
+    use crate::model::state::State;
+
+    struct Home {
+        state: State,
+        room: ResourceLink,
+        device: ResourceLink,
+        lamp: ResourceLink,
+        plug: ResourceLink,
+        glight: ResourceLink,
+    }
+
+    /// A room with one device, which has a dimmable lamp. The plug belongs to
+    /// a device outside of the room.
+    fn home() -> Home {
+        let room = RType::Room.deterministic("kitchen");
+        let device = RType::Device.deterministic("ceiling");
+        let lamp = RType::Light.deterministic("ceiling");
+        let plug = RType::Light.deterministic("plug");
+        let glight = RType::GroupedLight.deterministic("kitchen");
+
+        let mut state = State::new();
+        state.insert(
+            room.rid,
+            Resource::Room(Room {
+                children: btreeset![device],
+                metadata: RoomMetadata::new(RoomArchetype::Kitchen, "Kitchen"),
+                services: btreeset![glight],
+            }),
+        );
+        state.insert(
+            device.rid,
+            Resource::Device(Device {
+                product_data: DeviceProductData::hue_bridge_v2(&SwVersion::default()),
+                metadata: Metadata::new(DeviceArchetype::CeilingRound, "Ceiling"),
+                services: btreeset![lamp],
+                usertest: None,
+                identify: None,
+            }),
+        );
+
+        let mut light = Light::new(
+            device,
+            LightMetadata::new(DeviceArchetype::CeilingRound, "Ceiling"),
+        );
+        light.dimming = Some(Dimming {
+            brightness: 50.0,
+            min_dim_level: None,
+        });
+        state.insert(lamp.rid, Resource::Light(light));
+
+        let other = RType::Device.deterministic("plug");
+        let light = Light::new(other, LightMetadata::new(DeviceArchetype::Plug, "Plug"));
+        state.insert(plug.rid, Resource::Light(light));
+
+        state.insert(glight.rid, Resource::GroupedLight(GroupedLight::new(room)));
+
+        Home {
+            state,
+            room,
+            device,
+            lamp,
+            plug,
+            glight,
+        }
+    }
+
+    /// Ids matched by `query`, in order
+    fn find(home: &Home, query: &str) -> Vec<ResourceLink> {
+        let query = ResourceQuery::parse(Some(query)).unwrap();
+        let scope = query.room_scope(&home.state).unwrap();
+        let mut found: Vec<ResourceLink> = home
+            .state
+            .res
+            .iter()
+            .filter(|(id, obj)| query.matches(&home.state, scope.as_ref(), id, obj))
+            .map(|(id, obj)| ResourceLink::new(*id, obj.rtype()))
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn sorted<const N: usize>(mut links: [ResourceLink; N]) -> Vec<ResourceLink> {
+        links.sort();
+        links.to_vec()
+    }
+
+    #[test]
+    fn parse_filters() {
+        let owner = RType::Device.deterministic("owner").rid;
+        let query = ResourceQuery::parse(Some(&format!(
+            "type=light&type=room&owner={owner}&room=Living%20room&name=Desk&capability=gradient&limit=5&other=1"
+        )))
+        .unwrap();
+
+        assert_eq!(query.types, btreeset![RType::Light, RType::Room]);
+        assert_eq!(query.owner, Some(owner));
+        assert_eq!(query.room.as_deref(), Some("Living room"));
+        assert_eq!(query.name.as_deref(), Some("desk"));
+        assert_eq!(query.capabilities, btreeset![Capability::Gradient]);
+        assert_eq!(query.limit, Some(5));
+        assert!(!query.is_empty());
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(ResourceQuery::parse(None).unwrap().is_empty());
+
+        // a limit alone is not a filter
+        let query = ResourceQuery::parse(Some("limit=2")).unwrap();
+        assert!(query.is_empty());
+        assert_eq!(query.limit, Some(2));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(ResourceQuery::parse(Some("type=lamp")).is_err());
+        assert!(ResourceQuery::parse(Some("capability=sound")).is_err());
+        assert!(ResourceQuery::parse(Some("owner=nobody")).is_err());
+    }
+
+    #[test]
+    fn match_type_and_owner() {
+        let home = home();
+
+        assert_eq!(find(&home, "type=light"), sorted([home.lamp, home.plug]));
+        assert_eq!(
+            find(&home, "type=light&type=grouped_light"),
+            sorted([home.lamp, home.plug, home.glight])
+        );
+        assert_eq!(
+            find(&home, &format!("owner={}", home.device.rid)),
+            [home.lamp]
+        );
+    }
+
+    #[test]
+    fn match_room() {
+        let home = home();
+
+        // by name (case-insensitive) or id, including the room's own services
+        let expected = sorted([home.device, home.lamp, home.glight]);
+        assert_eq!(find(&home, "room=kitchen"), expected);
+        assert_eq!(find(&home, &format!("room={}", home.room.rid)), expected);
+
+        let query = ResourceQuery::parse(Some("room=attic")).unwrap();
+        assert!(query.room_scope(&home.state).is_err());
+    }
+
+    #[test]
+    fn match_name_and_capability() {
+        let home = home();
+
+        // the name of a service is the name of its owner, if it has none
+        assert_eq!(find(&home, "type=light&name=CEIL"), [home.lamp]);
+        assert_eq!(find(&home, "capability=dimming"), [home.lamp]);
+        assert!(find(&home, "capability=gradient").is_empty());
+    }
+}
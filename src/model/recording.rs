@@ -0,0 +1,260 @@
+//! File format for recorded entertainment streams
+//!
+//! A recording starts with [`MAGIC`], followed by any number of frames. Each
+//! frame is stored as a little-endian u64 timestamp (microseconds since the
+//! start of the recording), a little-endian u16 length, and the raw
+//! (decrypted) `HueStream` packet.
+//!
+//! Streams are recorded and replayed from async code, so the file access
+//! happens on blocking tasks ([`RecordingTask`] and [`StreamPlayer::spawn`]),
+//! connected to the stream by channels.
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+use crate::error::{ApiError, ApiResult};
+
+pub const MAGIC: &[u8; 16] = b"bifrost-hs-rec01";
+
+pub const EXTENSION: &str = "hsrec";
+
+#[derive(Clone, Debug)]
+pub struct RecordedFrame {
+    pub offset: Duration,
+    pub data: Vec<u8>,
+}
+
+pub struct StreamRecorder<W: Write> {
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> StreamRecorder<W> {
+    pub fn new(mut out: W) -> ApiResult<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Self {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, data: &[u8]) -> ApiResult<()> {
+        self.write_frame(self.start.elapsed(), data)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write_frame(&mut self, offset: Duration, data: &[u8]) -> ApiResult<()> {
+        let offset = offset.as_micros() as u64;
+        let len = u16::try_from(data.len())?;
+
+        self.out.write_all(&offset.to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(data)?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> ApiResult<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Records a stream from async code. Frames are timestamped when they are
+/// received, and written by a blocking task, so a slow disk never stalls the
+/// stream.
+pub struct RecordingTask<W> {
+    tx: mpsc::Sender<RecordedFrame>,
+    start: Instant,
+    task: JoinHandle<ApiResult<W>>,
+    dropped: u64,
+}
+
+impl<W: Write + Send + 'static> RecordingTask<W> {
+    /// Frames waiting to be written, before new frames are dropped
+    pub const QUEUE_SIZE: usize = 256;
+
+    /// Start recording to the writer returned by `open`, which is called on
+    /// the blocking task
+    pub fn spawn(open: impl FnOnce() -> ApiResult<W> + Send + 'static) -> Self {
+        let (tx, mut rx) = mpsc::channel::<RecordedFrame>(Self::QUEUE_SIZE);
+
+        let task = tokio::task::spawn_blocking(move || {
+            let mut rec = StreamRecorder::new(open()?)?;
+            while let Some(frame) = rx.blocking_recv() {
+                rec.write_frame(frame.offset, &frame.data)?;
+            }
+            rec.finish()
+        });
+
+        Self {
+            tx,
+            start: Instant::now(),
+            task,
+            dropped: 0,
+        }
+    }
+
+    /// Queue a frame for writing. Fails if the writer has stopped (because of
+    /// an error, which is returned by [`RecordingTask::finish`])
+    pub fn record(&mut self, data: &[u8]) -> ApiResult<()> {
+        let frame = RecordedFrame {
+            offset: self.start.elapsed(),
+            data: data.to_vec(),
+        };
+
+        match self.tx.try_send(frame) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(ApiError::EntRecordingFailed),
+        }
+    }
+
+    /// Write the remaining frames, and return the writer
+    pub async fn finish(self) -> ApiResult<W> {
+        drop(self.tx);
+        if self.dropped > 0 {
+            log::warn!(
+                "Recording fell behind: {} frames were left out",
+                self.dropped
+            );
+        }
+        self.task.await?
+    }
+}
+
+pub struct StreamPlayer<R: Read> {
+    rdr: R,
+}
+
+impl<R: Read> StreamPlayer<R> {
+    pub fn new(mut rdr: R) -> ApiResult<Self> {
+        let mut magic = [0u8; MAGIC.len()];
+        rdr.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ApiError::EntRecordingInvalid);
+        }
+
+        Ok(Self { rdr })
+    }
+
+    pub fn next_frame(&mut self) -> ApiResult<Option<RecordedFrame>> {
+        let mut offset = [0u8; 8];
+        match self.rdr.read_exact(&mut offset) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+
+        let mut len = [0u8; 2];
+        self.rdr.read_exact(&mut len)?;
+
+        let mut data = vec![0u8; usize::from(u16::from_le_bytes(len))];
+        self.rdr.read_exact(&mut data)?;
+
+        Ok(Some(RecordedFrame {
+            offset: Duration::from_micros(u64::from_le_bytes(offset)),
+            data,
+        }))
+    }
+}
+
+impl<R: Read + Send + 'static> StreamPlayer<R> {
+    /// Read frames from the reader returned by `open` on a blocking task, and
+    /// pass them on through a channel. Reading stops at the first error,
+    /// which is passed on as the last item.
+    pub fn spawn(
+        open: impl FnOnce() -> ApiResult<R> + Send + 'static,
+    ) -> mpsc::Receiver<ApiResult<RecordedFrame>> {
+        let (tx, rx) = mpsc::channel(64);
+
+        tokio::task::spawn_blocking(move || {
+            let player = match open().and_then(Self::new) {
+                Ok(player) => player,
+                Err(err) => return drop(tx.blocking_send(Err(err))),
+            };
+
+            for frame in player {
+                let failed = frame.is_err();
+                if tx.blocking_send(frame).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+}
+
+impl<R: Read> Iterator for StreamPlayer<R> {
+    type Item = ApiResult<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use crate::error::ApiError;
+    use crate::model::recording::{RecordingTask, StreamPlayer, MAGIC};
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let frames: [&[u8]; 3] = [b"first", b"", b"third frame"];
+
+        let mut rec = RecordingTask::spawn(|| Ok(Vec::new()));
+        for data in frames {
+            rec.record(data).unwrap();
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        let file = rec.finish().await.unwrap();
+        assert!(file.starts_with(MAGIC));
+
+        let mut rx = StreamPlayer::spawn(move || Ok(Cursor::new(file)));
+        let mut replayed = vec![];
+        while let Some(frame) = rx.recv().await {
+            replayed.push(frame.unwrap());
+        }
+
+        assert_eq!(replayed.len(), frames.len());
+        for (frame, data) in replayed.iter().zip(frames) {
+            assert_eq!(frame.data, data);
+        }
+        assert!(replayed.windows(2).all(|w| w[0].offset < w[1].offset));
+    }
+
+    #[tokio::test]
+    async fn replay_invalid_file() {
+        let mut rx = StreamPlayer::spawn(|| Ok(Cursor::new(b"not a recording".to_vec())));
+
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert!(matches!(
+            err,
+            ApiError::IOError(_) | ApiError::EntRecordingInvalid
+        ));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn replay_truncated_file() {
+        let mut rec = RecordingTask::spawn(|| Ok(Vec::new()));
+        rec.record(b"complete").unwrap();
+        rec.record(b"truncated").unwrap();
+        let mut file = rec.finish().await.unwrap();
+        file.truncate(file.len() - 3);
+
+        let mut rx = StreamPlayer::spawn(move || Ok(Cursor::new(file)));
+        assert_eq!(rx.recv().await.unwrap().unwrap().data, b"complete");
+        assert!(rx.recv().await.unwrap().is_err());
+        assert!(rx.recv().await.is_none());
+    }
+}
@@ -0,0 +1,212 @@
+//! Light state snapshots
+//!
+//! A snapshot captures the state of a set of lights (and any active scenes
+//! controlling them), so it can be put back later. This is used to restore
+//! lights after entertainment streaming, and by users through the admin api
+//! (e.g. to restore the lights after a doorbell automation flashed them).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use hue::api::{
+    Light, LightUpdate, RType, ResourceLink, Scene, SceneActive, SceneStatus, SceneUpdate,
+};
+
+use crate::backend::BackendRequest;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// A set of lights, given one by one, or as all lights of a room or zone
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LightSelection {
+    #[serde(default)]
+    pub lights: Vec<Uuid>,
+    pub group: Option<Uuid>,
+}
+
+impl LightSelection {
+    pub fn resolve(&self, res: &Resources) -> ApiResult<BTreeSet<ResourceLink>> {
+        let mut members: BTreeSet<ResourceLink> = self
+            .lights
+            .iter()
+            .map(|id| RType::Light.link_to(*id))
+            .collect();
+
+        if let Some(group) = self.group {
+            let rtype = res.get_resource_by_id(&group)?.obj.rtype();
+            let lights = res.group_lights(&rtype.link_to(group));
+            members.extend(lights.iter().map(|(id, _)| RType::Light.link_to(*id)));
+        }
+
+        Ok(members)
+    }
+}
+
+/// State of a set of lights, at the time the snapshot was taken
+#[derive(Clone, Debug)]
+pub struct LightSnapshot {
+    lights: Vec<(ResourceLink, LightUpdate)>,
+    scenes: Vec<(ResourceLink, SceneActive, BTreeSet<ResourceLink>)>,
+}
+
+impl LightSnapshot {
+    pub fn take(res: &Resources, members: &BTreeSet<ResourceLink>) -> ApiResult<Self> {
+        let mut lights = vec![];
+        for link in members {
+            let light: &Light = res.get(link)?;
+
+            let mut upd = LightUpdate::new()
+                .with_on(light.on)
+                .with_brightness(light.dimming.map(|dim| dim.brightness));
+
+            upd = match (light.as_mirek_opt(), light.as_gradient_opt()) {
+                (_, Some(points)) => upd.with_gradient(Some(points)),
+                (Some(mirek), None) => upd.with_color_temperature(mirek),
+                (None, None) => upd.with_color_xy(light.as_color_opt()),
+            };
+
+            lights.push((*link, upd));
+        }
+
+        let mut scenes = vec![];
+        for id in res.get_resource_ids_by_type(RType::Scene) {
+            let scene: &Scene = res.get_id(id)?;
+            let Some(status) = scene.status.filter(|st| st.active != SceneActive::Inactive) else {
+                continue;
+            };
+
+            let targets: BTreeSet<ResourceLink> = scene
+                .actions
+                .iter()
+                .map(|act| act.target)
+                .filter(|target| members.contains(target))
+                .collect();
+
+            if !targets.is_empty() {
+                scenes.push((RType::Scene.link_to(id), status.active, targets));
+            }
+        }
+
+        Ok(Self { lights, scenes })
+    }
+
+    /// Number of lights in the snapshot
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Backend requests to put the lights back the way they were. With
+    /// `scenes`, scenes that were active are recalled instead of restoring
+    /// their lights one by one.
+    #[must_use]
+    pub fn restore_requests(&self, scenes: bool) -> Vec<BackendRequest> {
+        let mut requests = vec![];
+        let mut restored: BTreeSet<ResourceLink> = BTreeSet::new();
+
+        if scenes {
+            for (link, active, targets) in &self.scenes {
+                let status = SceneStatus {
+                    active: *active,
+                    last_recall: None,
+                };
+                let upd = SceneUpdate::new().with_recall_action(Some(status));
+                requests.push(BackendRequest::SceneUpdate(*link, upd));
+                restored.extend(targets);
+            }
+        }
+
+        for (link, upd) in &self.lights {
+            if !restored.contains(link) {
+                requests.push(BackendRequest::LightUpdate(*link, upd.clone()));
+            }
+        }
+
+        requests
+    }
+
+    pub fn restore(&self, res: &Resources, scenes: bool) -> ApiResult<()> {
+        for req in self.restore_requests(scenes) {
+            res.backend_request(req)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Summary of a stored snapshot
+#[derive(Clone, Debug, Serialize)]
+pub struct SnapshotInfo {
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub created: DateTime<Utc>,
+    pub lights: usize,
+}
+
+/// Snapshots taken through the admin api, kept in memory
+#[derive(Debug, Default)]
+pub struct SnapshotStore {
+    snapshots: BTreeMap<Uuid, (SnapshotInfo, LightSnapshot)>,
+}
+
+impl SnapshotStore {
+    /// Snapshots beyond this number replace the oldest one
+    pub const MAX_SNAPSHOTS: usize = 32;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: Option<String>, snapshot: LightSnapshot) -> SnapshotInfo {
+        while self.snapshots.len() >= Self::MAX_SNAPSHOTS {
+            let oldest = self
+                .snapshots
+                .values()
+                .min_by_key(|(info, _)| info.created)
+                .map(|(info, _)| info.id);
+            if let Some(id) = oldest {
+                self.snapshots.remove(&id);
+            }
+        }
+
+        let info = SnapshotInfo {
+            id: Uuid::new_v4(),
+            name,
+            created: Utc::now(),
+            lights: snapshot.len(),
+        };
+        self.snapshots.insert(info.id, (info.clone(), snapshot));
+
+        info
+    }
+
+    #[must_use]
+    pub fn list(&self) -> Vec<SnapshotInfo> {
+        let mut res: Vec<SnapshotInfo> = self
+            .snapshots
+            .values()
+            .map(|(info, _)| info.clone())
+            .collect();
+        res.sort_by_key(|info| info.created);
+        res
+    }
+
+    #[must_use]
+    pub fn get(&self, id: &Uuid) -> Option<&LightSnapshot> {
+        self.snapshots.get(id).map(|(_, snapshot)| snapshot)
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<LightSnapshot> {
+        self.snapshots.remove(id).map(|(_, snapshot)| snapshot)
+    }
+}
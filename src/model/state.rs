@@ -1,20 +1,27 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Read;
 
 use serde::{Deserialize, Serialize};
 use serde_yml::Value;
 use uuid::Uuid;
 
-use hue::api::{DeviceArchetype, Resource, ResourceLink};
+use hue::api::{DeviceArchetype, RType, Resource, ResourceLink};
 use hue::error::{HueError, HueResult};
+use hue::legacy_api::{ApiResourceLink, ApiSensor};
 use hue::version::SwVersion;
 
 use crate::error::{ApiError, ApiResult};
+use crate::model::migration;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct AuxData {
     pub topic: Option<String>,
     pub index: Option<u32>,
+
+    /// Custom segment maps (for entertainment configurations), by
+    /// entertainment service id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub segment_maps: BTreeMap<Uuid, Vec<u16>>,
 }
 
 impl AuxData {
@@ -40,6 +47,25 @@ impl AuxData {
     }
 }
 
+/// Resources that only exist in the v1 api
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LegacyState {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resourcelinks: BTreeMap<u32, ApiResourceLink>,
+
+    /// Virtual (CLIP) sensors. Their v1 ids are kept in the id map, so they
+    /// cannot collide with sensors presented for v2 resources.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sensors: BTreeMap<Uuid, ApiSensor>,
+}
+
+impl LegacyState {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.resourcelinks.is_empty() && self.sensors.is_empty()
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct IdMap {
     forward: BTreeMap<Uuid, u32>,
@@ -84,6 +110,28 @@ impl IdMap {
         self.reverse.get(id).copied()
     }
 
+    /// Assign a specific id to `uuid`, replacing any id it had before.
+    ///
+    /// If the id was already used by another uuid, that uuid is moved to a
+    /// new id, and returned.
+    pub fn set(&mut self, uuid: Uuid, id: u32) -> Option<Uuid> {
+        if self.reverse.get(&id) == Some(&uuid) {
+            return None;
+        }
+
+        self.remove(&uuid);
+        let displaced = self.reverse.remove(&id);
+        self.forward.insert(uuid, id);
+        self.reverse.insert(id, uuid);
+
+        if let Some(other) = displaced {
+            self.forward.remove(&other);
+            self.add(other);
+        }
+
+        displaced
+    }
+
     pub fn remove(&mut self, uuid: &Uuid) {
         if let Some(id) = self.forward.remove(uuid) {
             self.reverse.remove(&id);
@@ -91,7 +139,12 @@ impl IdMap {
     }
 }
 
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+/// Version of the state file format.
+///
+/// When making an incompatible change to the serialized state (including the
+/// serde layout of any [`Resource`]), add a new version here, and a matching
+/// step in [`crate::model::migration`].
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum StateVersion {
     /// Version 0: (`res`, `aux`) tuple, no version field in state
     V0 = 0,
@@ -101,12 +154,57 @@ pub enum StateVersion {
     V1 = 1,
 }
 
+impl StateVersion {
+    pub const CURRENT: Self = Self::V1;
+}
+
+/// Result of loading a state file
+pub struct LoadedState {
+    pub state: State,
+
+    /// Version of the state file, before migration
+    pub version: StateVersion,
+
+    /// Resources that could not be loaded, and were left out
+    pub dropped: Vec<Uuid>,
+}
+
+impl LoadedState {
+    /// True if the loaded state differs from the file it was loaded from
+    #[must_use]
+    pub fn is_changed(&self) -> bool {
+        self.version != StateVersion::CURRENT || !self.dropped.is_empty()
+    }
+}
+
+/// Same layout as [`State`], but with unparsed resources, so broken
+/// resources can be skipped without failing the whole state
+#[derive(Deserialize)]
+struct RawState {
+    aux: BTreeMap<Uuid, AuxData>,
+    id_v1: IdMap,
+    res: BTreeMap<Uuid, Value>,
+    #[serde(default)]
+    legacy: LegacyState,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct State {
     version: StateVersion,
     aux: BTreeMap<Uuid, AuxData>,
     id_v1: IdMap,
     pub res: BTreeMap<Uuid, Resource>,
+    #[serde(default, skip_serializing_if = "LegacyState::is_empty")]
+    pub legacy: LegacyState,
+
+    /// Resource ids by type, kept up to date by [`State::insert`] and
+    /// [`State::remove`]
+    #[serde(skip)]
+    by_type: BTreeMap<RType, BTreeSet<Uuid>>,
+
+    /// Resource ids by owner id (see [`State::by_type`])
+    #[serde(skip)]
+    by_owner: BTreeMap<Uuid, BTreeSet<Uuid>>,
 }
 
 impl State {
@@ -157,51 +255,56 @@ impl State {
         }
     }
 
-    pub fn from_v0(state: Value) -> ApiResult<Self> {
-        let (v0res, v0aux): (serde_yml::Mapping, serde_yml::Mapping) =
-            serde_yml::from_value(state)?;
+    /// Load state of any version, migrating it to the current version.
+    ///
+    /// Resources that cannot be deserialized (even after migration) are
+    /// logged and skipped, instead of failing the entire state.
+    pub fn load(state: Value) -> ApiResult<LoadedState> {
+        let version = Self::version(&state)?;
+        let state = migration::migrate(state, version)?;
+
+        let RawState {
+            mut aux,
+            mut id_v1,
+            res: raw,
+            legacy,
+        } = serde_yml::from_value(state)?;
 
-        let mut aux = BTreeMap::new();
         let mut res = BTreeMap::new();
-
-        log::debug!("Importing aux data from old v0 state..");
-        for (key, value) in v0aux {
-            log::debug!("  {key:?}: {value:?}");
-            aux.insert(serde_yml::from_value(key)?, serde_yml::from_value(value)?);
-        }
-
-        log::debug!("Importing res data from old v0 state..");
-        for (key, value) in v0res {
-            log::debug!("  {key:?}: {value:?}");
-            res.insert(serde_yml::from_value(key)?, serde_yml::from_value(value)?);
-        }
-
-        /* generate all missing id_v1 entries */
-        log::debug!("Synthesizing id_v1 entries for all resources..");
-        let mut id_v1 = IdMap::new();
-        for key in res.keys() {
-            id_v1.add(*key);
+        let mut dropped = vec![];
+        for (id, value) in raw {
+            match serde_yml::from_value(value) {
+                Ok(obj) => {
+                    res.insert(id, obj);
+                }
+                Err(err) => {
+                    log::error!("Could not load resource {id}, skipping it: {err}");
+                    aux.remove(&id);
+                    id_v1.remove(&id);
+                    dropped.push(id);
+                }
+            }
         }
 
-        /* construct upgraded state */
-        Ok(Self {
-            version: StateVersion::V1,
+        let mut state = Self {
+            version: StateVersion::CURRENT,
             aux,
             id_v1,
             res,
+            legacy,
+            ..Self::default()
+        };
+        state.reindex();
+
+        Ok(LoadedState {
+            state,
+            version,
+            dropped,
         })
     }
 
-    pub fn from_v1(state: Value) -> ApiResult<Self> {
-        Ok(serde_yml::from_value(state)?)
-    }
-
     pub fn from_reader(rdr: impl Read) -> ApiResult<Self> {
-        let state = serde_yml::from_reader(rdr)?;
-        match Self::version(&state)? {
-            StateVersion::V0 => Self::from_v0(state),
-            StateVersion::V1 => Self::from_v1(state),
-        }
+        Ok(Self::load(serde_yml::from_reader(rdr)?)?.state)
     }
 
     #[must_use]
@@ -218,6 +321,10 @@ impl State {
         self.aux.insert(link.rid, aux);
     }
 
+    pub fn aux_remove(&mut self, id: &Uuid) {
+        self.aux.remove(id);
+    }
+
     #[must_use]
     pub fn try_get(&self, id: &Uuid) -> Option<&Resource> {
         self.res.get(id)
@@ -227,11 +334,19 @@ impl State {
         self.try_get(id).ok_or(HueError::NotFound(*id))
     }
 
+    /// Mutable access to a resource.
+    ///
+    /// The type and owner of a resource must not be changed this way, since
+    /// they are indexed. Use [`State::insert`] to replace a resource.
     pub fn get_mut(&mut self, id: &Uuid) -> HueResult<&mut Resource> {
         self.res.get_mut(id).ok_or(HueError::NotFound(*id))
     }
 
     pub fn insert(&mut self, key: Uuid, value: Resource) {
+        if let Some(old) = self.res.remove(&key) {
+            self.unindex(&key, &old);
+        }
+        self.index(key, &value);
         self.res.insert(key, value);
         self.id_v1.add(key);
     }
@@ -239,10 +354,55 @@ impl State {
     pub fn remove(&mut self, id: &Uuid) -> ApiResult<()> {
         self.aux.remove(id);
         self.id_v1.remove(id);
-        self.res.remove(id).ok_or(HueError::NotFound(*id))?;
+        let obj = self.res.remove(id).ok_or(HueError::NotFound(*id))?;
+        self.unindex(id, &obj);
         Ok(())
     }
 
+    /// Ids of all resources of type `rtype`, in order
+    pub fn ids_by_type(&self, rtype: RType) -> impl Iterator<Item = &Uuid> {
+        self.by_type.get(&rtype).into_iter().flatten()
+    }
+
+    /// Ids of all resources owned by `owner`, in order
+    pub fn ids_by_owner(&self, owner: &Uuid) -> impl Iterator<Item = &Uuid> {
+        self.by_owner.get(owner).into_iter().flatten()
+    }
+
+    fn index(&mut self, id: Uuid, obj: &Resource) {
+        self.by_type.entry(obj.rtype()).or_default().insert(id);
+        if let Some(owner) = obj.owner() {
+            self.by_owner.entry(owner.rid).or_default().insert(id);
+        }
+    }
+
+    fn unindex(&mut self, id: &Uuid, obj: &Resource) {
+        if let Some(ids) = self.by_type.get_mut(&obj.rtype()) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.by_type.remove(&obj.rtype());
+            }
+        }
+        if let Some(owner) = obj.owner() {
+            if let Some(ids) = self.by_owner.get_mut(&owner.rid) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.by_owner.remove(&owner.rid);
+                }
+            }
+        }
+    }
+
+    fn reindex(&mut self) {
+        let res = std::mem::take(&mut self.res);
+        self.by_type.clear();
+        self.by_owner.clear();
+        for (id, obj) in &res {
+            self.index(*id, obj);
+        }
+        self.res = res;
+    }
+
     #[must_use]
     pub fn id_v1(&self, uuid: &Uuid) -> Option<u32> {
         self.id_v1.id(uuid)
@@ -252,4 +412,168 @@ impl State {
     pub fn from_id_v1(&self, id: &u32) -> Option<Uuid> {
         self.id_v1.uuid(id)
     }
+
+    pub fn set_id_v1(&mut self, uuid: Uuid, id: u32) -> Option<Uuid> {
+        self.id_v1.set(uuid, id)
+    }
+
+    /// Add a virtual sensor, returning its v1 id
+    pub fn clip_sensor_insert(&mut self, sensor: ApiSensor) -> u32 {
+        let uuid = Uuid::new_v4();
+        self.legacy.sensors.insert(uuid, sensor);
+        self.id_v1.add(uuid)
+    }
+
+    pub fn clip_sensor_remove(&mut self, id: u32) -> HueResult<ApiSensor> {
+        let uuid = self.from_id_v1(&id).ok_or(HueError::V1NotFound(id))?;
+        let sensor = self
+            .legacy
+            .sensors
+            .remove(&uuid)
+            .ok_or(HueError::V1NotFound(id))?;
+        self.id_v1.remove(&uuid);
+        Ok(sensor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use serde_yml::{Mapping, Value};
+    use uuid::Uuid;
+
+    use hue::api::{GroupedLight, RType, Resource};
+
+    use crate::model::state::{AuxData, IdMap, State, StateVersion};
+
+    // serialize through text, like a state file (uuids are only written as
+    // strings by human readable serializers)
+    fn yaml(value: impl Serialize) -> Value {
+        serde_yml::from_str(&serde_yml::to_string(&value).unwrap()).unwrap()
+    }
+
+    fn grouped_light() -> (Uuid, Value) {
+        let room = RType::Room.deterministic("office");
+        let glight = Resource::GroupedLight(GroupedLight::new(room));
+        (
+            RType::GroupedLight.deterministic("office").rid,
+            yaml(glight),
+        )
+    }
+
+    fn v1_state(res: &[(Uuid, Value)]) -> Value {
+        let mut aux = Mapping::new();
+        let mut id_v1 = IdMap::new();
+        let mut map = Mapping::new();
+        for (id, value) in res {
+            let topic = AuxData::new().with_topic("office");
+            aux.insert(id.to_string().into(), yaml(topic));
+            id_v1.add(*id);
+            map.insert(id.to_string().into(), value.clone());
+        }
+
+        let mut state = Mapping::new();
+        state.insert("version".into(), yaml(StateVersion::V1));
+        state.insert("aux".into(), Value::Mapping(aux));
+        state.insert("id_v1".into(), yaml(id_v1));
+        state.insert("res".into(), Value::Mapping(map));
+        Value::Mapping(state)
+    }
+
+    #[test]
+    fn load_current_version() {
+        let (id, value) = grouped_light();
+        let loaded = State::load(v1_state(&[(id, value)])).unwrap();
+
+        assert_eq!(loaded.version, StateVersion::V1);
+        assert!(!loaded.is_changed());
+        assert!(loaded.state.try_get(&id).is_some());
+        assert!(loaded.state.id_v1(&id).is_some());
+    }
+
+    #[test]
+    fn migrate_v0_to_v1() {
+        let (id, value) = grouped_light();
+        let mut res = Mapping::new();
+        res.insert(id.to_string().into(), value);
+        let mut aux = Mapping::new();
+        aux.insert(id.to_string().into(), yaml(AuxData::new().with_index(2)));
+        let v0 = Value::Sequence(vec![Value::Mapping(res), Value::Mapping(aux)]);
+
+        let loaded = State::load(v0).unwrap();
+
+        assert_eq!(loaded.version, StateVersion::V0);
+        assert!(loaded.is_changed());
+        assert!(loaded.dropped.is_empty());
+        assert!(loaded.state.try_get(&id).is_some());
+        assert_eq!(loaded.state.try_aux_get(&id).unwrap().index, Some(2));
+        // v0 had no v1 ids, so they are synthesized
+        assert!(loaded.state.id_v1(&id).is_some());
+    }
+
+    #[test]
+    fn broken_resource_is_dropped() {
+        let (good, value) = grouped_light();
+        let bad = RType::Light.deterministic("broken").rid;
+        let broken: Value = serde_yml::from_str("{ type: light, unknown: true }").unwrap();
+
+        let loaded = State::load(v1_state(&[(good, value), (bad, broken)])).unwrap();
+
+        assert!(loaded.is_changed());
+        assert_eq!(loaded.dropped, [bad]);
+        assert!(loaded.state.try_get(&good).is_some());
+        assert!(loaded.state.try_get(&bad).is_none());
+        assert!(loaded.state.try_aux_get(&bad).is_none());
+        assert!(loaded.state.id_v1(&bad).is_none());
+        assert!(loaded.state.try_aux_get(&good).is_some());
+        assert!(loaded.state.id_v1(&good).is_some());
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut state = v1_state(&[]);
+        state["version"] = "V99".into();
+
+        assert!(State::load(state).is_err());
+    }
+
+    #[test]
+    fn indices_follow_insert_and_remove() {
+        let room = RType::Room.deterministic("office");
+        let zone = RType::Zone.deterministic("upstairs");
+        let id = RType::GroupedLight.deterministic("office").rid;
+
+        let mut state = State::new();
+        state.insert(id, Resource::GroupedLight(GroupedLight::new(room)));
+        assert_eq!(
+            state.ids_by_type(RType::GroupedLight).collect::<Vec<_>>(),
+            [&id]
+        );
+        assert_eq!(state.ids_by_owner(&room.rid).collect::<Vec<_>>(), [&id]);
+
+        // replacing a resource moves it to its new owner
+        state.insert(id, Resource::GroupedLight(GroupedLight::new(zone)));
+        assert_eq!(state.ids_by_owner(&room.rid).count(), 0);
+        assert_eq!(state.ids_by_owner(&zone.rid).collect::<Vec<_>>(), [&id]);
+
+        state.remove(&id).unwrap();
+        assert_eq!(state.ids_by_type(RType::GroupedLight).count(), 0);
+        assert_eq!(state.ids_by_owner(&zone.rid).count(), 0);
+    }
+
+    #[test]
+    fn indices_are_built_on_load() {
+        let (id, value) = grouped_light();
+        let loaded = State::load(v1_state(&[(id, value)])).unwrap();
+
+        let room = RType::Room.deterministic("office").rid;
+        assert_eq!(
+            loaded
+                .state
+                .ids_by_type(RType::GroupedLight)
+                .collect::<Vec<_>>(),
+            [&id]
+        );
+        assert_eq!(loaded.state.ids_by_owner(&room).collect::<Vec<_>>(), [&id]);
+    }
 }
@@ -0,0 +1,213 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::ResourceLink;
+
+use crate::error::ApiResult;
+use crate::model::state::State;
+
+/// A single changed value inside a resource, identified by its json path
+#[derive(Clone, Debug)]
+pub struct FieldDiff {
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ResourceDiff {
+    Added(ResourceLink),
+    Removed(ResourceLink),
+    Changed(ResourceLink, Vec<FieldDiff>),
+}
+
+impl ResourceDiff {
+    #[must_use]
+    pub const fn link(&self) -> &ResourceLink {
+        match self {
+            Self::Added(link) | Self::Removed(link) | Self::Changed(link, _) => link,
+        }
+    }
+}
+
+impl State {
+    /// Human-readable name of resource, if one can be found.
+    ///
+    /// Resources without a name of their own (e.g. `grouped_light`) are named
+    /// after their owner.
+    #[must_use]
+    pub fn resource_name(&self, id: &Uuid) -> Option<String> {
+        let obj = self.try_get(id)?;
+
+        let name = serde_json::to_value(obj)
+            .ok()?
+            .pointer("/metadata/name")
+            .and_then(Value::as_str)
+            .map(ToString::to_string);
+
+        name.or_else(|| self.resource_name(&obj.owner()?.rid))
+    }
+
+    /// Describe resource link, e.g. `light "Desk lamp" (<uuid>)`
+    #[must_use]
+    pub fn describe_link(&self, link: &ResourceLink) -> String {
+        let rtype = serde_json::to_value(link.rtype)
+            .ok()
+            .and_then(|v| v.as_str().map(ToString::to_string))
+            .unwrap_or_else(|| format!("{:?}", link.rtype));
+
+        self.resource_name(&link.rid).map_or_else(
+            || format!("{rtype} ({})", link.rid),
+            |name| format!("{rtype} {name:?} ({})", link.rid),
+        )
+    }
+
+    /// All resources that contain a link to the given resource
+    #[must_use]
+    pub fn references_to(&self, id: &Uuid) -> Vec<ResourceLink> {
+        self.res
+            .iter()
+            .filter(|(_, obj)| {
+                serde_json::to_value(obj)
+                    .is_ok_and(|val| collect_links(&val).iter().any(|link| link.rid == *id))
+            })
+            .map(|(rid, obj)| obj.rtype().link_to(*rid))
+            .collect()
+    }
+}
+
+/// Find all [`ResourceLink`]s in a json value
+#[must_use]
+pub fn collect_links(value: &Value) -> BTreeSet<ResourceLink> {
+    fn walk(value: &Value, res: &mut BTreeSet<ResourceLink>) {
+        if let Some(link) = as_link(value) {
+            res.insert(link);
+            return;
+        }
+
+        match value {
+            Value::Array(items) => items.iter().for_each(|item| walk(item, res)),
+            Value::Object(map) => map.values().for_each(|item| walk(item, res)),
+            _ => {}
+        }
+    }
+
+    let mut res = BTreeSet::new();
+    walk(value, &mut res);
+    res
+}
+
+fn as_link(value: &Value) -> Option<ResourceLink> {
+    let map = value.as_object()?;
+    if map.len() != 2 || !map.contains_key("rid") || !map.contains_key("rtype") {
+        return None;
+    }
+    serde_json::from_value(value.clone()).ok()
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, res: &mut Vec<FieldDiff>) {
+    if old == new {
+        return;
+    }
+
+    let change = |res: &mut Vec<FieldDiff>, old: Option<&Value>, new: Option<&Value>| {
+        res.push(FieldDiff {
+            path: path.to_string(),
+            old: old.cloned(),
+            new: new.cloned(),
+        });
+    };
+
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) if as_link(old).is_none() => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let path = format!("{path}.{key}");
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => diff_values(&path, x, y, res),
+                    (x, y) => res.push(FieldDiff {
+                        path,
+                        old: x.cloned(),
+                        new: y.cloned(),
+                    }),
+                }
+            }
+        }
+
+        // lists of links (room children, device services, etc) are sets,
+        // so report added and removed elements instead of positions
+        (Value::Array(a), Value::Array(b))
+            if a.iter().chain(b).all(|item| as_link(item).is_some()) =>
+        {
+            for item in a.iter().filter(|item| !b.contains(item)) {
+                change(res, Some(item), None);
+            }
+            for item in b.iter().filter(|item| !a.contains(item)) {
+                change(res, None, Some(item));
+            }
+        }
+
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (idx, (x, y)) in a.iter().zip(b).enumerate() {
+                diff_values(&format!("{path}[{idx}]"), x, y, res);
+            }
+        }
+
+        _ => change(res, Some(old), Some(new)),
+    }
+}
+
+/// Compute the semantic differences between two states
+pub fn diff(old: &State, new: &State) -> ApiResult<Vec<ResourceDiff>> {
+    let ids: BTreeSet<&Uuid> = old.res.keys().chain(new.res.keys()).collect();
+
+    let mut res = vec![];
+    for id in ids {
+        match (old.try_get(id), new.try_get(id)) {
+            (Some(a), Some(b)) => {
+                let mut changes = vec![];
+                diff_values(
+                    "",
+                    &serde_json::to_value(a)?,
+                    &serde_json::to_value(b)?,
+                    &mut changes,
+                );
+                diff_values(
+                    ".<aux>",
+                    &serde_json::to_value(old.try_aux_get(id))?,
+                    &serde_json::to_value(new.try_aux_get(id))?,
+                    &mut changes,
+                );
+                if !changes.is_empty() {
+                    res.push(ResourceDiff::Changed(b.rtype().link_to(*id), changes));
+                }
+            }
+            (Some(a), None) => res.push(ResourceDiff::Removed(a.rtype().link_to(*id))),
+            (None, Some(b)) => res.push(ResourceDiff::Added(b.rtype().link_to(*id))),
+            (None, None) => {}
+        }
+    }
+
+    Ok(res)
+}
+
+/// Formats a value for display, resolving resource links into names
+pub struct DisplayValue<'a> {
+    pub state: &'a State,
+    pub value: Option<&'a Value>,
+}
+
+impl Display for DisplayValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            None => write!(f, "<none>"),
+            Some(value) => match as_link(value) {
+                Some(link) => write!(f, "<{}>", self.state.describe_link(&link)),
+                None => write!(f, "{value}"),
+            },
+        }
+    }
+}
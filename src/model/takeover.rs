@@ -0,0 +1,58 @@
+//! Entertainment takeover: restoring lights after streaming
+//!
+//! While an entertainment area is streaming, its lights show whatever the
+//! streaming client sends. When the session starts, the state of the lights
+//! (and any active scenes controlling them) is saved, so it can be put back
+//! when the session ends or times out.
+
+use std::collections::BTreeSet;
+
+use uuid::Uuid;
+
+use hue::api::{EntertainmentConfiguration, ResourceLink};
+
+use crate::backend::BackendRequest;
+use crate::config::EntertainmentRestore;
+use crate::error::ApiResult;
+use crate::model::snapshot::LightSnapshot;
+use crate::resource::Resources;
+
+/// State of the lights in an entertainment area, from before streaming
+#[derive(Clone, Debug)]
+pub struct EntertainmentSnapshot {
+    lights: LightSnapshot,
+}
+
+impl EntertainmentSnapshot {
+    pub fn take(res: &Resources, ent_id: &Uuid) -> ApiResult<Self> {
+        let ent: &EntertainmentConfiguration = res.get_id(*ent_id)?;
+        let members: BTreeSet<ResourceLink> = ent.light_services.iter().copied().collect();
+
+        Ok(Self {
+            lights: LightSnapshot::take(res, &members)?,
+        })
+    }
+
+    /// Backend requests to put the lights back the way they were
+    #[must_use]
+    pub fn restore_requests(&self, mode: EntertainmentRestore) -> Vec<BackendRequest> {
+        match mode {
+            EntertainmentRestore::Auto => self.lights.restore_requests(true),
+            EntertainmentRestore::State => self.lights.restore_requests(false),
+            EntertainmentRestore::None => vec![],
+        }
+    }
+
+    pub fn restore(&self, res: &Resources, mode: EntertainmentRestore) -> ApiResult<()> {
+        let requests = self.restore_requests(mode);
+        if !requests.is_empty() {
+            log::info!("Restoring lights after entertainment session ({mode:?})");
+        }
+
+        for req in requests {
+            res.backend_request(req)?;
+        }
+
+        Ok(())
+    }
+}
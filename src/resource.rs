@@ -1,7 +1,10 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::io::{Read, Write};
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 
+use bytes::Bytes;
+use chrono::Utc;
+use chrono_tz::Tz;
 use hue::error::{HueError, HueResult};
 use maplit::btreeset;
 use serde_json::{json, Value};
@@ -10,28 +13,165 @@ use tokio::sync::Notify;
 use uuid::Uuid;
 
 use hue::api::{
-    Bridge, BridgeHome, Device, DeviceArchetype, DeviceProductData, DeviceUpdate, DimmingUpdate,
-    Entertainment, EntertainmentConfiguration, EntertainmentConfigurationLocationsUpdate,
+    BehaviorInstanceUpdate, BehaviorScript, Bridge, BridgeHome, BridgeHomeUpdate, BridgeUpdate,
+    Button, ButtonEvent, ButtonUpdate, ContactUpdate, Device, DeviceArchetype, DevicePowerUpdate,
+    DeviceProductData, DeviceSoftwareUpdateUpdate, DeviceUpdate, DimmingUpdate, Entertainment,
+    EntertainmentConfiguration, EntertainmentConfigurationLocationsUpdate,
     EntertainmentConfigurationStatus, EntertainmentConfigurationStreamProxyMode,
-    EntertainmentConfigurationStreamProxyUpdate, EntertainmentConfigurationUpdate, GroupedLight,
-    GroupedLightUpdate, Light, LightMode, LightUpdate, Metadata, On, RType, Resource, ResourceLink,
-    ResourceRecord, RoomUpdate, SceneUpdate, Stub, TimeZone, Update, ZigbeeConnectivity,
-    ZigbeeConnectivityStatus, ZigbeeDeviceDiscovery,
+    EntertainmentConfigurationStreamProxyUpdate, EntertainmentConfigurationUpdate, GeofenceClient,
+    GeofenceClientUpdate, GroupedLight, GroupedLightUpdate, Light, LightMode, LightUpdate,
+    Metadata, On, PowerMeasurementUpdate, PublicImage, RType, RelativeRotaryUpdate, Resource,
+    ResourceLink, ResourceRecord, Room, RoomUpdate, SceneUpdate, Stub, TamperUpdate, TimeZone,
+    Update, ZigbeeConnectivity, ZigbeeConnectivityStatus, ZigbeeDeviceDiscovery, Zone,
 };
 use hue::event::EventBlock;
+use hue::legacy_api::{ApiResourceLink, ApiSensor};
 use hue::version::SwVersion;
 
-use crate::backend::BackendRequest;
-use crate::error::ApiResult;
+use crate::backend::traffic::TrafficStats;
+use crate::backend::{
+    BackendRequest, BackendStatus, CommandResult, GroupDrift, ShardSummary, ZigbeeFrame,
+};
+use crate::config::EntertainmentConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::model::automation::{AutomationKind, AutomationResult, AutomationStore};
+use crate::model::behavior;
+use crate::model::entertainment::{
+    EntertainmentSettings, EntertainmentSettingsUpdate, EntertainmentStats,
+};
+use crate::model::query::ResourceQuery;
 use crate::model::state::{AuxData, State};
 use crate::server::hueevents::HueEventStream;
 
+/// Serialized resource list, and the state generation it was made at. Clones
+/// start out empty, since their state goes its own way.
+#[derive(Debug, Default)]
+struct ResourceListCache(std::sync::Mutex<Option<(u64, Bytes)>>);
+
+impl ResourceListCache {
+    fn get(&self, generation: u64) -> Option<Bytes> {
+        let cache = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        cache
+            .as_ref()
+            .filter(|(gen, _)| *gen == generation)
+            .map(|(_, json)| json.clone())
+    }
+
+    fn set(&self, generation: u64, json: Bytes) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some((generation, json));
+    }
+}
+
+impl Clone for ResourceListCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+type UpdateFn<'a> = Box<dyn FnOnce(&mut Resource) -> ApiResult<()> + Send + 'a>;
+
+enum Change<'a> {
+    Add(ResourceLink, Box<Resource>),
+    Update(Uuid, UpdateFn<'a>),
+    Delete(ResourceLink),
+    AuxSet(ResourceLink, AuxData),
+}
+
+/// Previous value of something changed by a transaction
+enum Undo {
+    /// Resource was added. Its v1 id and aux data may have been assigned
+    /// before it existed, and are kept on rollback.
+    Add(ResourceLink, Option<u32>, Option<AuxData>),
+    /// Resource was deleted, along with its v1 id and aux data
+    Delete(ResourceLink, Box<Resource>, Option<u32>, Option<AuxData>),
+    Resource(Uuid, Box<Resource>),
+    Aux(ResourceLink, Option<AuxData>),
+}
+
+/// Staged changes, applied together by [`Resources::commit`]. If any change
+/// fails, the ones before it are rolled back, and no events are sent.
+#[derive(Default)]
+pub struct Transaction<'a> {
+    changes: Vec<Change<'a>>,
+}
+
+impl<'a> Transaction<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn add(&mut self, link: &ResourceLink, obj: Resource) {
+        Resources::assert_link_type(link, &obj);
+
+        self.changes.push(Change::Add(*link, Box::new(obj)));
+    }
+
+    pub fn try_update<T>(
+        &mut self,
+        id: &Uuid,
+        func: impl FnOnce(&mut T) -> ApiResult<()> + Send + 'a,
+    ) where
+        for<'b> &'b mut T: TryFrom<&'b mut Resource, Error = HueError>,
+    {
+        self.changes.push(Change::Update(
+            *id,
+            Box::new(move |obj| func(obj.try_into()?)),
+        ));
+    }
+
+    pub fn update<T>(&mut self, id: &Uuid, func: impl FnOnce(&mut T) + Send + 'a)
+    where
+        for<'b> &'b mut T: TryFrom<&'b mut Resource, Error = HueError>,
+    {
+        self.try_update(id, |obj: &mut T| {
+            func(obj);
+            Ok(())
+        });
+    }
+
+    pub fn delete(&mut self, link: &ResourceLink) {
+        self.changes.push(Change::Delete(*link));
+    }
+
+    pub fn aux_set(&mut self, link: &ResourceLink, aux: AuxData) {
+        self.changes.push(Change::AuxSet(*link, aux));
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Resources {
     state: State,
     version: SwVersion,
+    entertainment: EntertainmentSettings,
+    /// Entertainment settings changed through the api, which take
+    /// precedence over the per-area settings from the config
+    entertainment_runtime: EntertainmentSettingsUpdate,
+    entertainment_stats: BTreeMap<Uuid, Arc<EntertainmentStats>>,
+    /// Send queue statistics of backends, by name
+    traffic_stats: BTreeMap<String, Arc<TrafficStats>>,
+    automations: AutomationStore,
     state_updates: Arc<Notify>,
+    /// Incremented on every state change
+    generation: u64,
+    /// Serialized list of all resources, and the generation it was made at
+    resource_list: ResourceListCache,
+    automation_updates: Arc<Notify>,
+    /// Requests, before coalescing (see [`crate::backend::coalesce`])
+    backend_requests: Sender<Arc<BackendRequest>>,
+    /// Requests, as sent to the backends
     backend_updates: Sender<Arc<BackendRequest>>,
+    zigbee_frames: Sender<Arc<ZigbeeFrame>>,
+    command_results: Sender<Arc<CommandResult>>,
+    /// Connection status of backends, by name
+    backends: BTreeMap<String, BackendStatus>,
+    group_drift: BTreeMap<String, Vec<GroupDrift>>,
+    shard_plan: BTreeMap<String, BTreeMap<String, ShardSummary>>,
     hue_event_stream: HueEventStream,
 }
 
@@ -41,12 +181,26 @@ impl Resources {
 
     #[allow(clippy::new_without_default)]
     #[must_use]
-    pub fn new(version: SwVersion, state: State) -> Self {
+    pub fn new(version: SwVersion, state: State, entertainment: EntertainmentSettings) -> Self {
         Self {
             state,
             version,
+            entertainment,
+            entertainment_runtime: EntertainmentSettingsUpdate::default(),
+            entertainment_stats: BTreeMap::new(),
+            traffic_stats: BTreeMap::new(),
+            automations: AutomationStore::new(),
             state_updates: Arc::new(Notify::new()),
+            generation: 0,
+            resource_list: ResourceListCache::default(),
+            automation_updates: Arc::new(Notify::new()),
+            backend_requests: Sender::new(32),
             backend_updates: Sender::new(32),
+            zigbee_frames: Sender::new(64),
+            command_results: Sender::new(32),
+            backends: BTreeMap::new(),
+            group_drift: BTreeMap::new(),
+            shard_plan: BTreeMap::new(),
             hue_event_stream: HueEventStream::new(Self::HUE_EVENTS_BUFFER_SIZE),
         }
     }
@@ -54,7 +208,93 @@ impl Resources {
     pub fn update_bridge_version(&mut self, version: SwVersion) {
         self.version = version;
         self.state.patch_bridge_version(&self.version);
-        self.state_updates.notify_one();
+        self.state_changed();
+    }
+
+    #[must_use]
+    pub const fn entertainment_settings(&self) -> EntertainmentSettings {
+        self.entertainment
+    }
+
+    /// Change the global entertainment settings, returning the result
+    pub fn update_entertainment_settings(
+        &mut self,
+        upd: &EntertainmentSettingsUpdate,
+    ) -> EntertainmentSettings {
+        self.entertainment += upd;
+        self.entertainment_runtime += upd;
+        self.entertainment
+    }
+
+    /// Entertainment settings for an area (see
+    /// [`EntertainmentSettings::for_area`])
+    #[must_use]
+    pub fn area_entertainment_settings(
+        &self,
+        config: &EntertainmentConfig,
+        id: &Uuid,
+        name: &str,
+    ) -> EntertainmentSettings {
+        self.entertainment
+            .for_area(config, &self.entertainment_runtime, id, name)
+    }
+
+    /// Statistics for the current (or most recent) entertainment session of
+    /// each entertainment area
+    #[must_use]
+    pub fn entertainment_stats(&self) -> Vec<Arc<EntertainmentStats>> {
+        self.entertainment_stats.values().cloned().collect()
+    }
+
+    /// Statistics for the current (or most recent) entertainment session of
+    /// the given area
+    #[must_use]
+    pub fn area_entertainment_stats(&self, area: &Uuid) -> Option<Arc<EntertainmentStats>> {
+        self.entertainment_stats.get(area).cloned()
+    }
+
+    /// Send queue statistics of backends, by name
+    #[must_use]
+    pub const fn traffic_stats(&self) -> &BTreeMap<String, Arc<TrafficStats>> {
+        &self.traffic_stats
+    }
+
+    pub fn set_traffic_stats(&mut self, name: &str, stats: Arc<TrafficStats>) {
+        self.traffic_stats.insert(name.to_string(), stats);
+    }
+
+    pub fn set_entertainment_stats(&mut self, area: Uuid, stats: Arc<EntertainmentStats>) {
+        self.entertainment_stats.insert(area, stats);
+    }
+
+    /// Check that an entertainment area can start streaming: none of its
+    /// lights may be part of another active area.
+    pub fn check_entertainment_conflict(&self, ent_id: &Uuid) -> ApiResult<()> {
+        let ent: &EntertainmentConfiguration = self.get_id(*ent_id)?;
+
+        for id in self.get_resource_ids_by_type(RType::EntertainmentConfiguration) {
+            if id == *ent_id {
+                continue;
+            }
+
+            let other: &EntertainmentConfiguration = self.get_id(id)?;
+            if other.status != EntertainmentConfigurationStatus::Active {
+                continue;
+            }
+
+            if other
+                .light_services
+                .iter()
+                .any(|light| ent.light_services.contains(light))
+            {
+                return Err(ApiError::EntAreaConflict(
+                    ent.metadata.name.clone(),
+                    other.metadata.name.clone(),
+                ));
+            }
+        }
+
+        Ok(())
     }
 
     pub fn reset_all_streaming(&mut self) -> ApiResult<()> {
@@ -86,9 +326,17 @@ impl Resources {
 
     pub fn read(&mut self, rdr: impl Read) -> ApiResult<()> {
         self.state = State::from_reader(rdr)?;
+        self.generation += 1;
         Ok(())
     }
 
+    /// Record a change of the state, so it is saved, and cached
+    /// serializations are refreshed
+    fn state_changed(&mut self) {
+        self.generation += 1;
+        self.state_updates.notify_one();
+    }
+
     pub fn write(&self, wr: impl Write) -> ApiResult<()> {
         Ok(serde_yml::to_writer(wr, &self.state)?)
     }
@@ -97,6 +345,66 @@ impl Resources {
         Ok(serde_yml::to_string(&self.state)?)
     }
 
+    #[must_use]
+    pub const fn automations(&self) -> &AutomationStore {
+        &self.automations
+    }
+
+    /// Replace the automation store (e.g. with one loaded from disk),
+    /// dropping entries for behavior instances that no longer exist
+    pub fn set_automations(&mut self, mut automations: AutomationStore) {
+        automations.retain(|id, entry| {
+            entry.kind != AutomationKind::BehaviorInstance || self.state.get(id).is_ok()
+        });
+        self.automations = automations;
+        self.automation_updates.notify_one();
+    }
+
+    pub fn serialize_automations(&self) -> ApiResult<String> {
+        self.automations.serialize()
+    }
+
+    /// Record that an automation has fired, with the outcome
+    pub fn record_automation(&mut self, id: Uuid, kind: AutomationKind, result: AutomationResult) {
+        self.automations.record_fire(id, kind, result);
+        self.automation_updates.notify_one();
+    }
+
+    /// The bridge time zone, as seen by api clients
+    #[must_use]
+    pub fn time_zone(&self) -> TimeZone {
+        self.get_resource_ids_by_type(RType::Bridge)
+            .first()
+            .and_then(|id| self.get_id::<Bridge>(*id).ok())
+            .map_or_else(TimeZone::best_guess, |bridge| bridge.time_zone.clone())
+    }
+
+    /// The bridge time zone, for converting to local time of day. Names
+    /// unknown to the time zone database fall back to UTC.
+    #[must_use]
+    pub fn local_tz(&self) -> Tz {
+        let name = self.time_zone().time_zone;
+        name.parse().unwrap_or_else(|_| {
+            log::debug!("Unknown time zone {name:?}, using UTC for local time");
+            Tz::UTC
+        })
+    }
+
+    pub fn set_time_zone(&mut self, name: &str) -> ApiResult<()> {
+        if !TimeZone::is_valid_name(name) {
+            return Err(ApiError::InvalidTimeZone(name.to_string()));
+        }
+
+        for id in self.get_resource_ids_by_type(RType::Bridge) {
+            if self.get_id::<Bridge>(id)?.time_zone.time_zone != name {
+                log::info!("Setting bridge time zone to {name:?}");
+                self.update::<Bridge>(&id, |bridge| bridge.time_zone = TimeZone::new(name))?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn init(&mut self, bridge_id: &str) -> ApiResult<()> {
         self.add_bridge(bridge_id.to_owned())
     }
@@ -109,6 +417,31 @@ impl Resources {
         self.state.aux_set(link, aux);
     }
 
+    /// Custom segment maps of an entertainment configuration, by
+    /// entertainment service id
+    #[must_use]
+    pub fn segment_maps(&self, ent_id: &Uuid) -> BTreeMap<Uuid, Vec<u16>> {
+        self.state
+            .try_aux_get(ent_id)
+            .map(|aux| aux.segment_maps.clone())
+            .unwrap_or_default()
+    }
+
+    /// Set (or with `None`, remove) the custom segment map of an
+    /// entertainment service in an entertainment configuration
+    pub fn set_segment_map(&mut self, ent_id: &Uuid, service: Uuid, map: Option<Vec<u16>>) {
+        let mut aux = self.state.try_aux_get(ent_id).cloned().unwrap_or_default();
+        if let Some(map) = map {
+            aux.segment_maps.insert(service, map);
+        } else {
+            aux.segment_maps.remove(&service);
+        }
+        self.state
+            .aux_set(&RType::EntertainmentConfiguration.link_to(*ent_id), aux);
+        self.state_changed();
+    }
+
+    #[allow(clippy::too_many_lines)]
     fn generate_update(obj: &Resource) -> HueResult<Option<Update>> {
         match obj {
             Resource::Light(light) => {
@@ -145,7 +478,12 @@ impl Resources {
 
                 Ok(Some(Update::Room(upd)))
             }
-            Resource::BridgeHome(_home) => Ok(None),
+            Resource::Bridge(bridge) => {
+                let upd = BridgeUpdate::new().with_time_zone(Some(bridge.time_zone.clone()));
+
+                Ok(Some(Update::Bridge(upd)))
+            }
+            Resource::BridgeHome(_) | Resource::Zone(_) => Ok(None),
             Resource::EntertainmentConfiguration(ent) => {
                 let upd = EntertainmentConfigurationUpdate {
                     configuration_type: Some(ent.configuration_type.clone()),
@@ -175,6 +513,71 @@ impl Resources {
 
                 Ok(Some(Update::EntertainmentConfiguration(upd)))
             }
+            Resource::Contact(contact) => {
+                let upd = ContactUpdate::new()
+                    .with_enabled(Some(contact.enabled))
+                    .with_contact_report(contact.contact_report);
+
+                Ok(Some(Update::Contact(upd)))
+            }
+            Resource::Tamper(tamper) => {
+                let upd = TamperUpdate {
+                    tamper_reports: Some(tamper.tamper_reports.clone()),
+                };
+
+                Ok(Some(Update::Tamper(upd)))
+            }
+            Resource::RelativeRotary(rotary) => {
+                let upd = RelativeRotaryUpdate {
+                    relative_rotary: rotary.relative_rotary,
+                };
+
+                Ok(Some(Update::RelativeRotary(upd)))
+            }
+            Resource::DevicePower(power) => {
+                let upd = DevicePowerUpdate {
+                    power_state: Some(power.power_state),
+                };
+
+                Ok(Some(Update::DevicePower(upd)))
+            }
+            Resource::DeviceSoftwareUpdate(swu) => {
+                let upd = DeviceSoftwareUpdateUpdate {
+                    state: Some(swu.state),
+                };
+
+                Ok(Some(Update::DeviceSoftwareUpdate(upd)))
+            }
+            Resource::PowerMeasurement(pm) => {
+                let upd = PowerMeasurementUpdate {
+                    power: Some(pm.power.clone()),
+                };
+
+                Ok(Some(Update::PowerMeasurement(upd)))
+            }
+            Resource::Button(button) => {
+                let upd = ButtonUpdate {
+                    button: Some(button.button.clone()),
+                };
+
+                Ok(Some(Update::Button(upd)))
+            }
+            Resource::BehaviorInstance(inst) => {
+                let upd = BehaviorInstanceUpdate {
+                    enabled: Some(inst.enabled),
+                    configuration: Some(inst.configuration.clone()),
+                    metadata: Some(inst.metadata.clone()),
+                };
+
+                Ok(Some(Update::BehaviorInstance(upd)))
+            }
+            Resource::GeofenceClient(client) => {
+                let upd = GeofenceClientUpdate::new()
+                    .with_name(Some(client.name.clone()))
+                    .with_is_at_home(Some(client.is_at_home));
+
+                Ok(Some(Update::GeofenceClient(upd)))
+            }
             obj => Err(HueError::UpdateUnsupported(obj.rtype())),
         }
     }
@@ -197,7 +600,7 @@ impl Resources {
                 .hue_event(EventBlock::update(id, id_v1, delta)?);
         }
 
-        self.state_updates.notify_one();
+        self.state_changed();
 
         Ok(())
     }
@@ -232,22 +635,33 @@ impl Resources {
             .collect()
     }
 
-    pub fn add(&mut self, link: &ResourceLink, obj: Resource) -> ApiResult<()> {
+    fn assert_link_type(link: &ResourceLink, obj: &Resource) {
         assert!(
             link.rtype == obj.rtype(),
             "Link type failed: {:?} expected but {:?} given",
             link.rtype,
             obj.rtype()
         );
+    }
 
-        if self.state.res.contains_key(&link.rid) {
+    fn is_known(&self, link: &ResourceLink) -> bool {
+        let known = self.state.res.contains_key(&link.rid);
+        if known {
             log::trace!("Resource {link:?} is already known");
+        }
+        known
+    }
+
+    pub fn add(&mut self, link: &ResourceLink, obj: Resource) -> ApiResult<()> {
+        Self::assert_link_type(link, &obj);
+
+        if self.is_known(link) {
             return Ok(());
         }
 
         self.state.insert(link.rid, obj);
 
-        self.state_updates.notify_one();
+        self.state_changed();
 
         let evt = EventBlock::add(serde_json::to_value(self.get_resource_by_id(&link.rid)?)?);
 
@@ -258,11 +672,179 @@ impl Resources {
         Ok(())
     }
 
+    /// Apply all changes in `tx`, or none of them. Like [`Self::add`], adding
+    /// a resource that already exists does nothing.
+    pub fn commit(&mut self, tx: Transaction) -> ApiResult<()> {
+        let mut undo = vec![];
+        let mut events = vec![];
+
+        if let Err(err) = self.apply_changes(tx, &mut undo, &mut events) {
+            log::warn!(
+                "Transaction failed, rolling back {} changes: {err}",
+                undo.len()
+            );
+            self.rollback(undo);
+            return Err(err);
+        }
+
+        if !undo.is_empty() {
+            self.state_changed();
+        }
+
+        for entry in &undo {
+            if let Undo::Delete(link, ..) = entry {
+                if self.automations.remove(&link.rid) {
+                    self.automation_updates.notify_one();
+                }
+            }
+        }
+
+        for evt in events {
+            log::trace!("Send event: {evt:?}");
+            self.hue_event_stream.hue_event(evt);
+        }
+
+        Ok(())
+    }
+
+    fn apply_changes(
+        &mut self,
+        tx: Transaction,
+        undo: &mut Vec<Undo>,
+        events: &mut Vec<EventBlock>,
+    ) -> ApiResult<()> {
+        for change in tx.changes {
+            match change {
+                Change::Add(link, obj) => {
+                    if self.is_known(&link) {
+                        continue;
+                    }
+                    let id_v1 = self.state.id_v1(&link.rid);
+                    let aux = self.state.try_aux_get(&link.rid).cloned();
+                    self.state.insert(link.rid, *obj);
+                    undo.push(Undo::Add(link, id_v1, aux));
+
+                    let obj = self.get_resource_by_id(&link.rid)?;
+                    events.push(EventBlock::add(serde_json::to_value(obj)?));
+                }
+                Change::Update(id, func) => {
+                    let obj = self.state.get_mut(&id)?;
+                    undo.push(Undo::Resource(id, Box::new(obj.clone())));
+                    func(obj)?;
+
+                    if let Some(delta) = Self::generate_update(obj)? {
+                        let id_v1 = self.state.id_v1(&id);
+                        events.push(EventBlock::update(&id, id_v1, delta)?);
+                    }
+                }
+                Change::Delete(link) => {
+                    log::info!("Deleting {link:?}..");
+                    let obj = self.state.get(&link.rid)?.clone();
+                    let id_v1 = self.state.id_v1(&link.rid);
+                    let aux = self.state.try_aux_get(&link.rid).cloned();
+                    self.state.remove(&link.rid)?;
+                    undo.push(Undo::Delete(link, Box::new(obj), id_v1, aux));
+
+                    events.push(EventBlock::delete(&link)?);
+                }
+                Change::AuxSet(link, aux) => {
+                    let old = self.state.try_aux_get(&link.rid).cloned();
+                    undo.push(Undo::Aux(link, old));
+                    self.state.aux_set(&link, aux);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rollback(&mut self, undo: Vec<Undo>) {
+        for entry in undo.into_iter().rev() {
+            match entry {
+                Undo::Add(link, id_v1, aux) => {
+                    let _ = self.state.remove(&link.rid);
+                    if let Some(id) = id_v1 {
+                        self.state.set_id_v1(link.rid, id);
+                    }
+                    if let Some(aux) = aux {
+                        self.state.aux_set(&link, aux);
+                    }
+                }
+                Undo::Delete(link, obj, id_v1, aux) => {
+                    self.state.insert(link.rid, *obj);
+                    if let Some(id) = id_v1 {
+                        self.state.set_id_v1(link.rid, id);
+                    }
+                    if let Some(aux) = aux {
+                        self.state.aux_set(&link, aux);
+                    }
+                }
+                Undo::Resource(id, old) => {
+                    if let Ok(obj) = self.state.get_mut(&id) {
+                        *obj = *old;
+                    }
+                }
+                Undo::Aux(link, Some(old)) => self.state.aux_set(&link, old),
+                Undo::Aux(link, None) => self.state.aux_remove(&link.rid),
+            }
+        }
+    }
+
+    /// True if anybody is at home, according to the geofence clients
+    /// (`None` if there are none)
+    #[must_use]
+    pub fn is_at_home(&self) -> Option<bool> {
+        let mut clients = self
+            .get_resource_ids_by_type(RType::GeofenceClient)
+            .into_iter()
+            .filter_map(|id| self.get_id::<GeofenceClient>(id).ok())
+            .peekable();
+
+        clients.peek()?;
+        Some(clients.any(|client| client.is_at_home))
+    }
+
+    /// Update the at-home state of the bridge home from the geofence
+    /// clients. If it changed, an update event is sent, and the new state is
+    /// returned.
+    pub fn update_at_home(&mut self) -> ApiResult<Option<bool>> {
+        let at_home = self.is_at_home();
+
+        let mut changed = None;
+        for id in self.get_resource_ids_by_type(RType::BridgeHome) {
+            let bh: &mut BridgeHome = self.state.get_mut(&id)?.try_into()?;
+            if bh.is_at_home == at_home {
+                continue;
+            }
+            bh.is_at_home = at_home;
+
+            let upd = Update::BridgeHome(BridgeHomeUpdate {
+                is_at_home: at_home,
+            });
+            let id_v1 = self.state.id_v1(&id);
+            self.hue_event_stream
+                .hue_event(EventBlock::update(&id, id_v1, upd)?);
+            self.state_changed();
+            changed = at_home;
+        }
+
+        match changed {
+            Some(true) => log::info!("Geofence: first person arrived home"),
+            Some(false) => log::info!("Geofence: last person left home"),
+            None => {}
+        }
+
+        Ok(changed)
+    }
+
     pub fn delete(&mut self, link: &ResourceLink) -> ApiResult<()> {
         log::info!("Deleting {link:?}..");
         self.state.remove(&link.rid)?;
 
-        self.state_updates.notify_one();
+        self.state_changed();
+        if self.automations.remove(&link.rid) {
+            self.automation_updates.notify_one();
+        }
 
         let evt = EventBlock::delete(link)?;
 
@@ -271,6 +853,15 @@ impl Resources {
         Ok(())
     }
 
+    /// Make sure there is a `public_image` resource for each image
+    pub fn add_public_images(&mut self, ids: impl IntoIterator<Item = Uuid>) -> ApiResult<()> {
+        for id in ids {
+            let link = RType::PublicImage.link_to(id);
+            self.add(&link, Resource::PublicImage(PublicImage {}))?;
+        }
+        Ok(())
+    }
+
     pub fn add_bridge(&mut self, bridge_id: String) -> ApiResult<()> {
         let link_bridge = RType::Bridge.deterministic(&bridge_id);
         let link_bridge_home = RType::BridgeHome.deterministic(format!("{bridge_id}HOME"));
@@ -280,6 +871,7 @@ impl Resources {
         let link_zbdd = RType::ZigbeeDeviceDiscovery.deterministic(link_bridge.rid);
         let link_zbc = RType::ZigbeeConnectivity.deterministic(link_bridge.rid);
         let link_bhome_glight = RType::GroupedLight.deterministic(link_bridge_home.rid);
+        let link_button_script = RType::BehaviorScript.link_to(BehaviorScript::BUTTON_SCRIPT_ID);
 
         let bridge_dev = Device {
             product_data: DeviceProductData::hue_bridge_v2(&self.version),
@@ -306,6 +898,7 @@ impl Resources {
         let bridge_home = BridgeHome {
             children: btreeset![link_bridge_dev],
             services: btreeset![link_bhome_glight],
+            is_at_home: None,
         };
 
         let bhome_glight = GroupedLight {
@@ -367,12 +960,49 @@ impl Resources {
         self.add(&link_zbc, Resource::ZigbeeConnectivity(zbc))?;
         self.add(&link_bridge_ent, Resource::Entertainment(brent))?;
         self.add(&link_bhome_glight, Resource::GroupedLight(bhome_glight))?;
+        self.add(
+            &link_button_script,
+            Resource::BehaviorScript(BehaviorScript::button()),
+        )?;
 
         Ok(())
     }
 
+    /// Lights in a room or zone (for rooms, through their devices)
+    #[must_use]
+    pub fn group_lights(&self, owner: &ResourceLink) -> Vec<(Uuid, &Light)> {
+        let children = match owner.rtype {
+            RType::Room => self.get::<Room>(owner).map(|room| &room.children),
+            RType::Zone => self.get::<Zone>(owner).map(|zone| &zone.children),
+            _ => return vec![],
+        };
+
+        children
+            .into_iter()
+            .flatten()
+            .filter_map(|child| match child.rtype {
+                RType::Device => self.get::<Device>(child).ok()?.light_service(),
+                RType::Light => Some(child),
+                _ => None,
+            })
+            .filter_map(|link| Some((link.rid, self.get::<Light>(link).ok()?)))
+            .collect()
+    }
+
     pub fn get_next_scene_id(&self, room: &ResourceLink) -> HueResult<u32> {
-        let mut set: HashSet<u32> = HashSet::new();
+        self.get_next_scene_id_except(room, &HashSet::new())
+    }
+
+    /// Like [`Self::get_next_scene_id`], but also skips the ids in `reserved`.
+    ///
+    /// Useful when creating several scenes at once, since new scenes are only
+    /// added once the backend has processed them.
+    pub fn get_next_scene_id_except(
+        &self,
+        room: &ResourceLink,
+        reserved: &HashSet<u32>,
+    ) -> HueResult<u32> {
+        let mut set: HashSet<u32> = reserved.clone();
 
         for scene in self.get_resources_by_type(RType::Scene) {
             let Resource::Scene(scn) = scene.obj else {
@@ -440,6 +1070,7 @@ impl Resources {
             Resource::GroupedLight(_) => Some(format!("/groups/{id}")),
             Resource::Light(_) => Some(format!("/lights/{id}")),
             Resource::Scene(_) => Some(format!("/scenes/{id}")),
+            Resource::PowerMeasurement(_) => Some(format!("/sensors/{id}")),
 
             /* Rooms map to their grouped_light service's id_v1 */
             Resource::Room(room) => room
@@ -473,6 +1104,7 @@ impl Resources {
             | Resource::BehaviorScript(_)
             | Resource::Bridge(_)
             | Resource::Button(_)
+            | Resource::Contact(_)
             | Resource::GeofenceClient(_)
             | Resource::Geolocation(_)
             | Resource::GroupedMotion(_)
@@ -485,6 +1117,7 @@ impl Resources {
             | Resource::PublicImage(_)
             | Resource::RelativeRotary(_)
             | Resource::SmartScene(_)
+            | Resource::Tamper(_)
             | Resource::Taurus(_)
             | Resource::Temperature(_)
             | Resource::ZigbeeConnectivity(_)
@@ -512,6 +1145,16 @@ impl Resources {
             .map(|res| self.make_resource_record(id, res))
     }
 
+    #[must_use]
+    pub fn resource_name(&self, id: &Uuid) -> Option<String> {
+        self.state.resource_name(id)
+    }
+
+    #[must_use]
+    pub fn describe_link(&self, link: &ResourceLink) -> String {
+        self.state.describe_link(link)
+    }
+
     #[must_use]
     pub fn get_resources(&self) -> Vec<ResourceRecord> {
         self.state
@@ -521,36 +1164,78 @@ impl Resources {
             .collect()
     }
 
+    /// All resources, serialized as a v2 api reply. Apps fetch this on every
+    /// start, so it is cached until the state changes.
+    pub fn resource_list_json(&self) -> ApiResult<Bytes> {
+        if let Some(json) = self.resource_list.get(self.generation) {
+            return Ok(json);
+        }
+
+        let reply = json!({"data": self.get_resources(), "errors": []});
+        let json = Bytes::from(serde_json::to_vec(&reply)?);
+        self.resource_list.set(self.generation, json.clone());
+
+        Ok(json)
+    }
+
+    /// Number of resources of each type
+    #[must_use]
+    pub fn resource_counts(&self) -> BTreeMap<RType, usize> {
+        let mut counts = BTreeMap::new();
+        for res in self.state.res.values() {
+            *counts.entry(res.rtype()).or_default() += 1;
+        }
+        counts
+    }
+
     #[must_use]
     pub fn get_resources_by_type(&self, ty: RType) -> Vec<ResourceRecord> {
         self.state
-            .res
-            .iter()
-            .filter(|(_, r)| r.rtype() == ty)
-            .map(|(id, res)| self.make_resource_record(id, res))
+            .ids_by_type(ty)
+            .map(|id| self.make_resource_record(id, &self.state.res[id]))
             .collect()
     }
 
     #[must_use]
     pub fn get_resource_ids_by_type(&self, ty: RType) -> Vec<Uuid> {
-        self.state
-            .res
-            .iter()
-            .filter(|(_, r)| r.rtype() == ty)
-            .map(|(id, _res)| *id)
-            .collect()
+        self.state.ids_by_type(ty).copied().collect()
     }
 
     #[must_use]
     pub fn get_resources_by_owner(&self, owner: ResourceLink) -> Vec<ResourceRecord> {
         self.state
-            .res
-            .iter()
-            .filter(|(_, r)| r.owner() == Some(owner))
+            .ids_by_owner(&owner.rid)
+            .map(|id| (id, &self.state.res[id]))
+            .filter(|(_, res)| res.owner() == Some(owner))
             .map(|(id, res)| self.make_resource_record(id, res))
             .collect()
     }
 
+    /// Resources matching all filters of `query`
+    pub fn query(&self, query: &ResourceQuery) -> ApiResult<Vec<ResourceRecord>> {
+        let scope = query.room_scope(&self.state)?;
+
+        /* start from the smallest index that applies, and only check the
+         * remaining filters for those */
+        let candidates: BTreeSet<&Uuid> = match &query.owner {
+            Some(owner) => self.state.ids_by_owner(owner).collect(),
+            None if query.types.is_empty() => self.state.res.keys().collect(),
+            None => query
+                .types
+                .iter()
+                .flat_map(|ty| self.state.ids_by_type(*ty))
+                .collect(),
+        };
+
+        Ok(candidates
+            .into_iter()
+            .map(|id| (id, &self.state.res[id]))
+            .filter(|(id, res)| query.matches(&self.state, scope.as_ref(), id, res))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(|(id, res)| self.make_resource_record(id, res))
+            .collect())
+    }
+
     pub fn get_id_v1_index(&self, uuid: Uuid) -> HueResult<u32> {
         self.state.id_v1(&uuid).ok_or(HueError::NotFound(uuid))
     }
@@ -563,11 +1248,111 @@ impl Resources {
         self.state.from_id_v1(&id).ok_or(HueError::V1NotFound(id))
     }
 
+    /// Assign a specific v1 id to a resource (e.g. when importing from
+    /// another bridge). Any resource that had this id before is given a new
+    /// one, and returned.
+    pub fn set_id_v1(&mut self, uuid: Uuid, id: u32) -> Option<Uuid> {
+        let res = self.state.set_id_v1(uuid, id);
+        self.state_changed();
+        res
+    }
+
+    #[must_use]
+    pub const fn resourcelinks(&self) -> &BTreeMap<u32, ApiResourceLink> {
+        &self.state.legacy.resourcelinks
+    }
+
+    pub fn add_resourcelink(&mut self, link: ApiResourceLink) -> u32 {
+        let links = &mut self.state.legacy.resourcelinks;
+        let id = links.keys().next_back().map_or(1, |id| id + 1);
+        links.insert(id, link);
+        self.state_changed();
+        id
+    }
+
+    pub fn update_resourcelink(
+        &mut self,
+        id: u32,
+        func: impl FnOnce(&mut ApiResourceLink),
+    ) -> HueResult<()> {
+        let link = self
+            .state
+            .legacy
+            .resourcelinks
+            .get_mut(&id)
+            .ok_or(HueError::V1NotFound(id))?;
+        func(link);
+        self.state_changed();
+        Ok(())
+    }
+
+    pub fn delete_resourcelink(&mut self, id: u32) -> HueResult<()> {
+        self.state
+            .legacy
+            .resourcelinks
+            .remove(&id)
+            .ok_or(HueError::V1NotFound(id))?;
+        self.state_changed();
+        Ok(())
+    }
+
+    /// Virtual (CLIP) sensors, by v1 id
+    pub fn clip_sensors(&self) -> impl Iterator<Item = (u32, &ApiSensor)> {
+        self.state
+            .legacy
+            .sensors
+            .iter()
+            .filter_map(|(uuid, sensor)| Some((self.state.id_v1(uuid)?, sensor)))
+    }
+
+    pub fn clip_sensor(&self, id: u32) -> HueResult<&ApiSensor> {
+        let uuid = self.from_id_v1(id)?;
+        self.state
+            .legacy
+            .sensors
+            .get(&uuid)
+            .ok_or(HueError::V1NotFound(id))
+    }
+
+    pub fn add_clip_sensor(&mut self, sensor: ApiSensor) -> u32 {
+        let id = self.state.clip_sensor_insert(sensor);
+        self.state_changed();
+        id
+    }
+
+    pub fn update_clip_sensor<T>(
+        &mut self,
+        id: u32,
+        func: impl FnOnce(&mut ApiSensor) -> HueResult<T>,
+    ) -> HueResult<T> {
+        let uuid = self.from_id_v1(id)?;
+        let sensor = self
+            .state
+            .legacy
+            .sensors
+            .get_mut(&uuid)
+            .ok_or(HueError::V1NotFound(id))?;
+        let res = func(sensor)?;
+        self.state_changed();
+        Ok(res)
+    }
+
+    pub fn delete_clip_sensor(&mut self, id: u32) -> HueResult<()> {
+        self.state.clip_sensor_remove(id)?;
+        self.state_changed();
+        Ok(())
+    }
+
     #[must_use]
     pub fn state_channel(&self) -> Arc<Notify> {
         self.state_updates.clone()
     }
 
+    #[must_use]
+    pub fn automation_channel(&self) -> Arc<Notify> {
+        self.automation_updates.clone()
+    }
+
     #[must_use]
     pub const fn hue_event_stream(&self) -> &HueEventStream {
         &self.hue_event_stream
@@ -578,13 +1363,338 @@ impl Resources {
         self.backend_updates.subscribe()
     }
 
+    /// Requests as they are made, before coalescing
+    #[must_use]
+    pub fn backend_request_stream(&self) -> Receiver<Arc<BackendRequest>> {
+        self.backend_requests.subscribe()
+    }
+
+    /// Sender for coalesced requests, which the backends receive
+    #[must_use]
+    pub fn backend_sender(&self) -> Sender<Arc<BackendRequest>> {
+        self.backend_updates.clone()
+    }
+
+    #[must_use]
+    pub fn zigbee_frame_stream(&self) -> Receiver<Arc<ZigbeeFrame>> {
+        self.zigbee_frames.subscribe()
+    }
+
+    /// Outcomes of commands sent to `link`, if its backend reports them
+    /// (only zigbee2mqtt lights, for now)
+    #[must_use]
+    pub fn command_result_stream(
+        &self,
+        link: &ResourceLink,
+    ) -> Option<Receiver<Arc<CommandResult>>> {
+        self.aux_get(link).ok()?.topic.as_ref()?;
+        Some(self.command_results.subscribe())
+    }
+
+    pub fn command_result(&self, result: CommandResult) {
+        let _ = self.command_results.send(Arc::new(result));
+    }
+
+    /// Make a backend known, before it has connected for the first time
+    pub fn register_backend(&mut self, name: &str) {
+        self.backends.entry(name.to_string()).or_default();
+    }
+
+    pub fn set_backend_ready(&mut self, name: &str, ready: bool) {
+        let status = self.backends.entry(name.to_string()).or_default();
+        if status.ready == ready && status.since.is_some() {
+            return;
+        }
+
+        log::debug!("Backend [{name}] ready: {ready}");
+        status.ready = ready;
+        status.since = Some(Utc::now());
+        if ready {
+            status.connections += 1;
+        }
+    }
+
+    /// Record an attempt of a backend to connect
+    pub fn backend_connect_attempt(&mut self, name: &str) {
+        self.backends
+            .entry(name.to_string())
+            .or_default()
+            .connect_attempts += 1;
+    }
+
+    /// Record a connection error of a backend
+    pub fn set_backend_error(&mut self, name: &str, err: &impl ToString) {
+        self.backends
+            .entry(name.to_string())
+            .or_default()
+            .last_error = Some(err.to_string());
+    }
+
+    #[must_use]
+    pub const fn backend_status(&self) -> &BTreeMap<String, BackendStatus> {
+        &self.backends
+    }
+
+    /// True if the named backend is connected, and has received its
+    /// initial state
+    #[must_use]
+    pub fn is_backend_ready(&self, name: &str) -> bool {
+        self.backends.get(name).is_some_and(|status| status.ready)
+    }
+
+    /// True once every known backend has been ready at least once
+    #[must_use]
+    pub fn backends_started(&self) -> bool {
+        self.backends.values().all(|status| status.connections > 0)
+    }
+
+    /// Set the zigbee connectivity status of the devices owning `services`
+    /// (e.g. when the backend controlling them disconnects)
+    pub fn set_connectivity(
+        &mut self,
+        services: impl IntoIterator<Item = Uuid>,
+        status: ZigbeeConnectivityStatus,
+    ) -> ApiResult<()> {
+        let devices: BTreeSet<Uuid> = services
+            .into_iter()
+            .filter_map(|id| self.state.get(&id).ok()?.owner())
+            .map(|link| link.rid)
+            .collect();
+
+        for id in self.get_resource_ids_by_type(RType::ZigbeeConnectivity) {
+            let link = RType::ZigbeeConnectivity.link_to(id);
+            let zigcon = self.get::<ZigbeeConnectivity>(&link)?;
+            if devices.contains(&zigcon.owner.rid) && zigcon.status != status {
+                self.update::<ZigbeeConnectivity>(&id, |zigcon| zigcon.status = status)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the zigbee group drift found by a backend
+    pub fn set_group_drift(&mut self, name: &str, drift: Vec<GroupDrift>) {
+        self.group_drift.insert(name.to_string(), drift);
+    }
+
+    /// Zigbee group drift, by backend name
+    #[must_use]
+    pub const fn group_drift(&self) -> &BTreeMap<String, Vec<GroupDrift>> {
+        &self.group_drift
+    }
+
+    /// Record the distribution of devices over bridges, planned by a backend
+    pub fn set_shard_plan(&mut self, name: &str, plan: BTreeMap<String, ShardSummary>) {
+        self.shard_plan.insert(name.to_string(), plan);
+    }
+
+    /// Planned distribution of devices over bridges, by backend name
+    #[must_use]
+    pub const fn shard_plan(&self) -> &BTreeMap<String, BTreeMap<String, ShardSummary>> {
+        &self.shard_plan
+    }
+
+    /// Report a raw zigbee message to anyone listening (it is dropped if
+    /// nobody is)
+    pub fn zigbee_frame(&self, frame: ZigbeeFrame) {
+        let _ = self.zigbee_frames.send(Arc::new(frame));
+    }
+
+    /// Report a button event, and run the accessory behaviors configured
+    /// for it
+    pub fn button_event(&mut self, id: &Uuid, event: ButtonEvent) -> ApiResult<()> {
+        let previous = self.get_id::<Button>(*id)?.button.last_event;
+        self.update::<Button>(id, |button| button.report(event))?;
+
+        // zigbee2mqtt only reports "hold" (repeatedly), so the first repeat
+        // after a press is also the start of a long press
+        let mut events = vec![event];
+        if event == ButtonEvent::Repeat && previous == Some(ButtonEvent::InitialPress) {
+            events.insert(0, ButtonEvent::LongPress);
+        }
+
+        for event in events {
+            for (inst, requests) in behavior::button_requests(self, id, event) {
+                let res = requests
+                    .into_iter()
+                    .try_for_each(|req| self.backend_request(req));
+                self.record_automation(inst, AutomationKind::BehaviorInstance, (&res).into());
+                res?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn backend_request(&self, req: BackendRequest) -> ApiResult<()> {
-        if !matches!(req, BackendRequest::EntertainmentFrame(_)) {
+        if !matches!(req, BackendRequest::EntertainmentFrame(..)) {
             log::debug!("z2m request: {req:#?}");
         }
 
-        self.backend_updates.send(Arc::new(req))?;
+        self.backend_requests.send(Arc::new(req))?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreeset;
+    use uuid::Uuid;
+
+    use hue::api::{RType, Resource, ResourceLink, Room, RoomArchetype, RoomMetadata};
+    use hue::version::SwVersion;
+
+    use crate::config::EntertainmentConfig;
+    use crate::model::entertainment::EntertainmentSettings;
+    use crate::model::state::{AuxData, State};
+    use crate::resource::{Resources, Transaction};
+
+    fn resources() -> Resources {
+        Resources::new(
+            SwVersion::default(),
+            State::new(),
+            EntertainmentSettings::from_config(&EntertainmentConfig::default()),
+        )
+    }
+
+    fn room(name: &str) -> Resource {
+        Resource::Room(Room {
+            children: btreeset![],
+            metadata: RoomMetadata::new(RoomArchetype::Office, name),
+            services: btreeset![],
+        })
+    }
+
+    fn event_count(res: &Resources) -> usize {
+        res.hue_event_stream().recent_events(usize::MAX).len()
+    }
+
+    /// Add a change to `tx` that always fails
+    fn fail(tx: &mut Transaction) {
+        tx.update::<Room>(&Uuid::new_v4(), |_| {});
+    }
+
+    #[test]
+    fn commit_applies_all_changes() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+
+        let mut tx = Transaction::new();
+        tx.add(&link, room("office"));
+        tx.aux_set(&link, AuxData::new().with_topic("office"));
+        tx.update::<Room>(&link.rid, |room| room.metadata.name = "Office".into());
+        res.commit(tx).unwrap();
+
+        let obj: &Room = res.get(&link).unwrap();
+        assert_eq!(obj.metadata.name, "Office");
+        assert_eq!(
+            res.state.aux_get(&link).unwrap().topic.as_deref(),
+            Some("office")
+        );
+        assert_eq!(event_count(&res), 2);
+    }
+
+    #[test]
+    fn failed_add_is_rolled_back_without_events() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+
+        let mut tx = Transaction::new();
+        tx.add(&link, room("office"));
+        tx.aux_set(&link, AuxData::new().with_topic("office"));
+        fail(&mut tx);
+        assert!(res.commit(tx).is_err());
+
+        assert!(res.state.try_get(&link.rid).is_none());
+        assert!(res.state.try_aux_get(&link.rid).is_none());
+        assert!(res.state.id_v1(&link.rid).is_none());
+        assert_eq!(event_count(&res), 0);
+    }
+
+    #[test]
+    fn failed_update_is_rolled_back() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+        res.add(&link, room("office")).unwrap();
+        let events = event_count(&res);
+
+        let mut tx = Transaction::new();
+        tx.update::<Room>(&link.rid, |room| room.metadata.name = "Kitchen".into());
+        fail(&mut tx);
+        assert!(res.commit(tx).is_err());
+
+        let obj: &Room = res.get(&link).unwrap();
+        assert_eq!(obj.metadata.name, "office");
+        assert_eq!(event_count(&res), events);
+    }
+
+    #[test]
+    fn rollback_restores_aux() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+        res.add(&link, room("office")).unwrap();
+        res.state.aux_set(&link, AuxData::new().with_index(3));
+
+        let mut tx = Transaction::new();
+        tx.aux_set(&link, AuxData::new().with_index(7));
+        fail(&mut tx);
+        assert!(res.commit(tx).is_err());
+
+        assert_eq!(res.state.aux_get(&link).unwrap().index, Some(3));
+    }
+
+    #[test]
+    fn rollback_keeps_assigned_v1_id() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+        res.state.set_id_v1(link.rid, 42);
+        res.state.aux_set(&link, AuxData::new().with_index(5));
+
+        let mut tx = Transaction::new();
+        tx.add(&link, room("office"));
+        fail(&mut tx);
+        assert!(res.commit(tx).is_err());
+
+        assert!(res.state.try_get(&link.rid).is_none());
+        assert_eq!(res.state.id_v1(&link.rid), Some(42));
+        assert_eq!(res.state.aux_get(&link).unwrap().index, Some(5));
+    }
+
+    #[test]
+    fn failed_delete_is_rolled_back() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+        res.add(&link, room("office")).unwrap();
+        res.state.set_id_v1(link.rid, 42);
+        res.state.aux_set(&link, AuxData::new().with_index(5));
+        let events = event_count(&res);
+
+        let other: ResourceLink = RType::Room.deterministic("kitchen");
+        let mut tx = Transaction::new();
+        tx.delete(&link);
+        tx.add(&other, room("kitchen"));
+        fail(&mut tx);
+        assert!(res.commit(tx).is_err());
+
+        assert!(res.get::<Room>(&link).is_ok());
+        assert!(res.state.try_get(&other.rid).is_none());
+        assert_eq!(res.state.id_v1(&link.rid), Some(42));
+        assert_eq!(res.state.aux_get(&link).unwrap().index, Some(5));
+        assert_eq!(event_count(&res), events);
+    }
+
+    #[test]
+    fn delete_sends_event() {
+        let mut res = resources();
+        let link = RType::Room.deterministic("office");
+        res.add(&link, room("office")).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.delete(&link);
+        res.commit(tx).unwrap();
+
+        assert!(res.state.try_get(&link.rid).is_none());
+        assert_eq!(event_count(&res), 2);
+    }
+}
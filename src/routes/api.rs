@@ -1,40 +1,47 @@
 use std::collections::HashMap;
-
-use axum::extract::{Path, State};
-use axum::response::IntoResponse;
-use axum::routing::{get, post, put};
+use std::time::Duration;
+
+use axum::extract::{Path, Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
 use axum::Router;
 use bytes::Bytes;
 use chrono::Utc;
 use hue::error::{HueError, HueResult};
 use log::{info, warn};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use tokio::sync::MutexGuard;
 use uuid::Uuid;
 
 use hue::api::{
     Device, EntertainmentConfiguration, EntertainmentConfigurationStatus, GroupedLight,
-    GroupedLightUpdate, Light, LightUpdate, On, RType, Resource, ResourceLink, Room, Scene,
-    SceneActive, SceneStatus, SceneUpdate, V1Reply,
+    GroupedLightUpdate, Light, LightUpdate, On, PowerMeasurement, RType, Resource, ResourceLink,
+    Room, Scene, SceneActive, SceneStatus, SceneUpdate, V1Reply,
 };
 use hue::legacy_api::{
-    ApiGroup, ApiGroupActionUpdate, ApiGroupUpdate2, ApiLight, ApiLightStateUpdate,
+    ApiAlert, ApiConfigUpdate, ApiErrorType, ApiGroup, ApiGroupActionUpdate, ApiGroupUpdate2,
+    ApiLight, ApiLightStateUpdate, ApiResourceLink, ApiResourceLinkNew, ApiResourceLinkUpdate,
     ApiResourceType, ApiScene, ApiSceneAppData, ApiSceneType, ApiSceneVersion, ApiSensor,
-    ApiUserConfig, Capabilities, HueApiResult, NewUser, NewUserReply,
+    ApiSensorNew, ApiSensorUpdate, ApiUserConfig, Capabilities, HueApiResult, NewUser,
+    NewUserReply,
 };
 
-use crate::backend::BackendRequest;
+use crate::backend::{self, BackendRequest, IdentifyEffect};
 use crate::error::{ApiError, ApiResult};
 use crate::resource::Resources;
 use crate::routes::auth::STANDARD_CLIENT_KEY;
-use crate::routes::extractor::Json;
+use crate::routes::extractor::{self, Json};
+use crate::routes::V1ErrorInfo;
 use crate::server::appstate::AppState;
 
 async fn get_api_config(State(state): State<AppState>) -> impl IntoResponse {
     Json(state.api_short_config().await)
 }
 
-async fn post_api(bytes: Bytes) -> ApiResult<impl IntoResponse> {
+async fn post_api(State(state): State<AppState>, bytes: Bytes) -> ApiResult<impl IntoResponse> {
     info!("post: {bytes:?}");
     let json: NewUser = serde_json::from_slice(&bytes)?;
 
@@ -46,9 +53,21 @@ async fn post_api(bytes: Bytes) -> ApiResult<impl IntoResponse> {
         },
         username: Uuid::new_v4().as_simple().to_string(),
     };
+
+    state
+        .register_devicetype(res.username.clone(), json.devicetype)
+        .await;
     Ok(Json(vec![HueApiResult::Success(res)]))
 }
 
+const fn identify_effect(alert: Option<ApiAlert>) -> Option<IdentifyEffect> {
+    match alert {
+        Some(ApiAlert::Select) => Some(IdentifyEffect::Blink),
+        Some(ApiAlert::LSelect) => Some(IdentifyEffect::Breathe),
+        Some(ApiAlert::None) | None => None,
+    }
+}
+
 fn get_lights(res: &MutexGuard<Resources>) -> ApiResult<HashMap<String, ApiLight>> {
     let mut lights = HashMap::new();
 
@@ -133,11 +152,7 @@ pub fn get_scene(res: &Resources, owner: String, scene: &Scene) -> ApiResult<Api
         owner,
         recycle: false,
         locked: false,
-        /* Some clients (e.g. Hue Essentials) require .appdata */
-        appdata: ApiSceneAppData {
-            data: Some(format!("xxxxx_r{room_id}")),
-            version: Some(1),
-        },
+        appdata: ApiSceneAppData::new(scene.metadata.appdata.as_deref(), room_id),
         picture: String::new(),
         lastupdated: Utc::now(),
         version: ApiSceneVersion::V2 as u32,
@@ -146,6 +161,33 @@ pub fn get_scene(res: &Resources, owner: String, scene: &Scene) -> ApiResult<Api
     })
 }
 
+fn get_sensors(res: &MutexGuard<Resources>) -> ApiResult<HashMap<u32, ApiSensor>> {
+    let mut sensors = HashMap::from([(1, ApiSensor::builtin_daylight_sensor())]);
+
+    for rr in res.get_resources_by_type(RType::PowerMeasurement) {
+        let power: &PowerMeasurement = (&rr.obj).try_into()?;
+        let dev = res.get::<Device>(&power.owner)?;
+
+        sensors.insert(
+            res.get_id_v1_index(rr.id)?,
+            ApiSensor::from_power_measurement(dev, power),
+        );
+    }
+
+    for (id, sensor) in res.clip_sensors() {
+        sensors.insert(id, sensor.clone());
+    }
+
+    Ok(sensors)
+}
+
+fn get_resourcelinks(res: &MutexGuard<Resources>) -> HashMap<u32, ApiResourceLink> {
+    res.resourcelinks()
+        .iter()
+        .map(|(id, link)| (*id, link.clone()))
+        .collect()
+}
+
 fn get_scenes(owner: &str, res: &MutexGuard<Resources>) -> ApiResult<HashMap<String, ApiScene>> {
     let mut scenes = HashMap::new();
 
@@ -169,51 +211,110 @@ async fn get_api_user(
     let lock = state.res.lock().await;
 
     Ok(Json(ApiUserConfig {
-        config: state.api_config(username.clone()).await,
+        config: state.api_config(username.clone(), &lock).await,
         groups: get_groups(&lock, false)?,
         lights: get_lights(&lock)?,
-        resourcelinks: HashMap::new(),
+        resourcelinks: get_resourcelinks(&lock),
         rules: HashMap::new(),
         scenes: get_scenes(&username, &lock)?,
         schedules: HashMap::new(),
-        sensors: HashMap::from([(1, ApiSensor::builtin_daylight_sensor())]),
+        sensors: get_sensors(&lock)?,
     }))
 }
 
+#[allow(clippy::significant_drop_tightening)]
 async fn get_api_user_resource(
     State(state): State<AppState>,
     Path((username, artype)): Path<(String, ApiResourceType)>,
 ) -> ApiResult<Json<Value>> {
     let lock = &state.res.lock().await;
     match artype {
-        ApiResourceType::Config => Ok(Json(json!(state.api_config(username).await))),
+        ApiResourceType::Config => Ok(Json(json!(state.api_config(username, lock).await))),
         ApiResourceType::Lights => Ok(Json(json!(get_lights(lock)?))),
         ApiResourceType::Groups => Ok(Json(json!(get_groups(lock, false)?))),
         ApiResourceType::Scenes => Ok(Json(json!(get_scenes(&username, lock)?))),
-        ApiResourceType::Resourcelinks
-        | ApiResourceType::Rules
-        | ApiResourceType::Schedules
-        | ApiResourceType::Sensors => Ok(Json(json!({}))),
+        ApiResourceType::Sensors => Ok(Json(json!(get_sensors(lock)?))),
+        ApiResourceType::Resourcelinks => Ok(Json(json!(get_resourcelinks(lock)))),
+        ApiResourceType::Rules | ApiResourceType::Schedules => Ok(Json(json!({}))),
         ApiResourceType::Capabilities => Ok(Json(json!(Capabilities::new()))),
     }
 }
 
 async fn post_api_user_resource(
-    Path((_username, resource)): Path<(String, ApiResourceType)>,
+    State(state): State<AppState>,
+    Path((username, resource)): Path<(String, ApiResourceType)>,
     Json(req): Json<Value>,
 ) -> ApiResult<Json<Value>> {
-    warn!("POST v1 user resource unsupported");
-    warn!("Request: {req:?}");
-    Err(ApiError::V1CreateUnsupported(resource))
+    let id = match resource {
+        ApiResourceType::Resourcelinks => {
+            let new: ApiResourceLinkNew = extractor::parse(&state, &req)?;
+            let link = ApiResourceLink::new(username, new);
+            state.res.lock().await.add_resourcelink(link)
+        }
+        ApiResourceType::Sensors => {
+            let new: ApiSensorNew = extractor::parse(&state, &req)?;
+            let sensor = ApiSensor::clip(new, Utc::now())?;
+            state.res.lock().await.add_clip_sensor(sensor)
+        }
+        _ => {
+            warn!("POST v1 user resource unsupported");
+            warn!("Request: {req:?}");
+            return Err(ApiError::V1CreateUnsupported(resource));
+        }
+    };
+
+    Ok(Json(json!([{"success": {"id": id.to_string()}}])))
 }
 
 async fn put_api_user_resource(
-    Path((_username, _resource)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Path((_username, resource)): Path<(String, String)>,
     Json(req): Json<Value>,
-) -> impl IntoResponse {
+) -> ApiResult<Json<Value>> {
+    if resource == "config" {
+        let upd: ApiConfigUpdate = extractor::parse(&state, &req)?;
+
+        if let Some(timezone) = &upd.timezone {
+            state.res.lock().await.set_time_zone(timezone)?;
+        }
+
+        let swupd = upd.swupdate2.unwrap_or_default();
+        if swupd.checkforupdate.is_some() || swupd.install.is_some() {
+            /* bring the emulated update up to date, before acting on it */
+            state.swupdate_status().await;
+
+            let now = Utc::now();
+            let lock = state.swupdate();
+            let mut lock = lock.lock().await;
+            if swupd.checkforupdate == Some(true) {
+                lock.check_for_update(now);
+            }
+            if swupd.install == Some(true) {
+                lock.install(now);
+            }
+            log::info!("Emulated bridge software update is now {:?}", lock.state());
+            drop(lock);
+        }
+
+        if upd.touchlink == Some(true) {
+            state
+                .res
+                .lock()
+                .await
+                .backend_request(BackendRequest::Touchlink)?;
+        }
+
+        let reply = V1Reply::new("/config".to_string())
+            .add_option("timezone", upd.timezone)?
+            .add_option("swupdate2/checkforupdate", swupd.checkforupdate)?
+            .add_option("swupdate2/install", swupd.install)?
+            .add_option("touchlink", upd.touchlink)?;
+        return Ok(Json(reply.json()));
+    }
+
     warn!("PUT v1 user resource {req:?}");
     //Json(format!("user {username} resource {resource}"))
-    Json(vec![HueApiResult::Success(req)])
+    Ok(Json(json!(vec![HueApiResult::Success(req)])))
 }
 
 #[allow(clippy::significant_drop_tightening)]
@@ -249,6 +350,22 @@ async fn get_api_user_resource_id(
 
             json!(group)
         }
+        ApiResourceType::Sensors => {
+            let lock = state.res.lock().await;
+            let sensors = get_sensors(&lock)?;
+            let sensor = sensors.get(&id).ok_or(HueError::V1NotFound(id))?;
+
+            json!(sensor)
+        }
+        ApiResourceType::Resourcelinks => {
+            let lock = state.res.lock().await;
+            let link = lock
+                .resourcelinks()
+                .get(&id)
+                .ok_or(HueError::V1NotFound(id))?;
+
+            json!(link)
+        }
         _ => Err(HueError::V1NotFound(id))?,
     };
 
@@ -265,7 +382,7 @@ async fn put_api_user_resource_id(
     log::debug!("JSON: {req:?}");
     match artype {
         ApiResourceType::Groups => {
-            let upd: ApiGroupUpdate2 = serde_json::from_value(req)?;
+            let upd: ApiGroupUpdate2 = extractor::parse(&state, &req)?;
             let mut lock = state.res.lock().await;
 
             let uuid = lock.from_id_v1(id)?;
@@ -285,13 +402,43 @@ async fn put_api_user_resource_id(
 
             Ok(Json(V1Reply::for_group(id).json()))
         }
+        ApiResourceType::Resourcelinks => {
+            let upd: ApiResourceLinkUpdate = extractor::parse(&state, &req)?;
+            let reply = V1Reply::new(format!("/resourcelinks/{id}"))
+                .add_option("name", upd.name.as_ref())?
+                .add_option("description", upd.description.as_ref())?
+                .add_option("classid", upd.classid)?
+                .add_option("links", upd.links.as_ref())?
+                .json();
+
+            state
+                .res
+                .lock()
+                .await
+                .update_resourcelink(id, |link| link.update(upd))?;
+
+            Ok(Json(reply))
+        }
+        ApiResourceType::Sensors => {
+            let upd: ApiSensorUpdate = extractor::parse(&state, &req)?;
+            let reply = V1Reply::new(format!("/sensors/{id}"))
+                .add_option("name", upd.name.as_ref())?
+                .json();
+
+            state.res.lock().await.update_clip_sensor(id, |sensor| {
+                if let Some(name) = upd.name {
+                    sensor.name = name;
+                }
+                Ok(())
+            })?;
+
+            Ok(Json(reply))
+        }
         ApiResourceType::Config
         | ApiResourceType::Lights
-        | ApiResourceType::Resourcelinks
         | ApiResourceType::Rules
         | ApiResourceType::Scenes
         | ApiResourceType::Schedules
-        | ApiResourceType::Sensors
         | ApiResourceType::Capabilities => Err(ApiError::V1CreateUnsupported(artype)),
     }
 }
@@ -311,18 +458,28 @@ async fn put_api_user_resource_id_path(
             let lock = state.res.lock().await;
             let uuid = lock.from_id_v1(id)?;
             let link = ResourceLink::new(uuid, RType::Light);
-            let updv1: ApiLightStateUpdate = serde_json::from_value(req)?;
+            let updv1: ApiLightStateUpdate = extractor::parse(&state, &req)?;
 
             let upd = LightUpdate::new()
                 .with_on(updv1.on.map(On::new))
                 .with_brightness(updv1.bri)
                 .with_color_temperature(updv1.ct)
                 .with_color_hs(updv1.hs.map(Into::into))
-                .with_color_xy(updv1.xy.map(Into::into));
+                .with_color_xy(updv1.xy.map(Into::into))
+                .with_duration(updv1.duration());
 
+            lock.get::<Light>(&link)?.check_update(&upd)?;
+            let results = lock.command_result_stream(&link);
             lock.backend_request(BackendRequest::LightUpdate(link, upd))?;
+            if let Some(effect) = identify_effect(updv1.alert) {
+                lock.backend_request(BackendRequest::Identify(link, effect))?;
+            }
             drop(lock);
 
+            if let (Some(results), Some(ms)) = (results, state.config().bifrost.command_timeout) {
+                backend::confirm_command(results, link, Duration::from_millis(ms)).await?;
+            }
+
             let reply = V1Reply::for_light(id, &path).with_light_state_update(&updv1)?;
 
             Ok(Json(reply.json()))
@@ -341,7 +498,7 @@ async fn put_api_user_resource_id_path(
             let room: &Room = lock.get(&link)?;
             let glight = room.grouped_light_service().unwrap();
 
-            let updv1: ApiGroupActionUpdate = serde_json::from_value(req)?;
+            let updv1: ApiGroupActionUpdate = extractor::parse(&state, &req)?;
 
             let reply = match updv1 {
                 ApiGroupActionUpdate::LightUpdate(upd) => {
@@ -349,9 +506,13 @@ async fn put_api_user_resource_id_path(
                         .with_on(upd.on.map(On::new))
                         .with_brightness(upd.bri.map(f64::from))
                         .with_color_xy(upd.xy.map(Into::into))
-                        .with_color_temperature(upd.ct);
+                        .with_color_temperature(upd.ct)
+                        .with_duration(upd.duration());
 
                     lock.backend_request(BackendRequest::GroupedLightUpdate(*glight, updv2))?;
+                    if let Some(effect) = identify_effect(upd.alert) {
+                        lock.backend_request(BackendRequest::Identify(*glight, effect))?;
+                    }
                     drop(lock);
 
                     V1Reply::for_group_path(id, &path).with_light_state_update(&upd)?
@@ -373,16 +534,56 @@ async fn put_api_user_resource_id_path(
 
             Ok(Json(reply.json()))
         }
+        ApiResourceType::Sensors => {
+            if path != "state" && path != "config" {
+                return Err(HueError::V1NotFound(id))?;
+            }
+
+            let upd: Map<String, Value> = extractor::parse(&state, &req)?;
+            let now = Utc::now();
+            state.res.lock().await.update_clip_sensor(id, |sensor| {
+                if path == "state" {
+                    sensor.update_state(&upd, now)
+                } else {
+                    sensor.update_config(&upd)
+                }
+            })?;
+
+            let mut reply = V1Reply::new(format!("/sensors/{id}/{path}"));
+            for (key, value) in &upd {
+                reply = reply.add(key, value)?;
+            }
+
+            Ok(Json(reply.json()))
+        }
         ApiResourceType::Config
         | ApiResourceType::Resourcelinks
         | ApiResourceType::Rules
         | ApiResourceType::Scenes
         | ApiResourceType::Schedules
-        | ApiResourceType::Sensors
         | ApiResourceType::Capabilities => Err(ApiError::V1CreateUnsupported(artype)),
     }
 }
 
+async fn delete_api_user_resource_id(
+    State(state): State<AppState>,
+    Path((_username, artype, id)): Path<(String, ApiResourceType, u32)>,
+) -> ApiResult<Json<Value>> {
+    let address = match artype {
+        ApiResourceType::Resourcelinks => {
+            state.res.lock().await.delete_resourcelink(id)?;
+            format!("/resourcelinks/{id}")
+        }
+        ApiResourceType::Sensors => {
+            state.res.lock().await.delete_clip_sensor(id)?;
+            format!("/sensors/{id}")
+        }
+        _ => return Err(ApiError::V1DeleteUnsupported(artype)),
+    };
+
+    Ok(Json(json!([{"success": format!("{address} deleted")}])))
+}
+
 /// This generates a workaround necessary for iConnectHue (iPhone app)
 ///
 /// For some reason, iConnectHue has been observed to try the endpoint GET /api/newUser,
@@ -393,6 +594,65 @@ pub async fn workaround_iconnect_hue() -> impl IntoResponse {
     Json(json!([{"error":{"type":1,"address":"/","description":"unauthorized user"}}]))
 }
 
+/// Reply to failed requests the way a hue bridge does: with status 200, and
+/// a list of errors, each with a numeric error type.
+pub async fn v1_errors(req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    if path != "/api" && !path.starts_with("/api/") {
+        return next.run(req).await;
+    }
+
+    /* the address is the path relative to the user, e.g. "/lights/1/state" */
+    let address = format!("/{}", path.split('/').skip(3).collect::<Vec<_>>().join("/"));
+    let method = req.method().clone();
+
+    let res = next.run(req).await;
+
+    let status = res.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return res;
+    }
+
+    /* requests rejected before reaching a handler have no error info */
+    let fallback = || match status {
+        StatusCode::NOT_FOUND => (ApiErrorType::ResourceNotAvailable, String::new()),
+        StatusCode::METHOD_NOT_ALLOWED => (
+            ApiErrorType::MethodNotAvailable,
+            format!("method, {method}, not available for resource, {address}"),
+        ),
+        _ if status.is_client_error() => (
+            ApiErrorType::InvalidJson,
+            "body contains invalid json".to_string(),
+        ),
+        _ => (ApiErrorType::InternalError, "internal error".to_string()),
+    };
+
+    let (typ, description) = res
+        .extensions()
+        .get::<V1ErrorInfo>()
+        .map_or_else(fallback, |info| (info.typ, info.description.clone()));
+
+    let description = match typ {
+        ApiErrorType::ResourceNotAvailable => format!("resource, {address}, not available"),
+        ApiErrorType::UnauthorizedUser => "unauthorized user".to_string(),
+        ApiErrorType::TooManyRequests => "too many requests".to_string(),
+        _ => description,
+    };
+
+    let error = hue::legacy_api::HueError::new(typ, &address, description);
+    let mut reply = Json(vec![HueApiResult::<Value>::Error(error)]).into_response();
+
+    // rate limited clients get a real status code, so they know to back off
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        *reply.status_mut() = status;
+    }
+    if let Some(retry_after) = res.headers().get(RETRY_AFTER) {
+        reply.headers_mut().insert(RETRY_AFTER, retry_after.clone());
+    }
+
+    reply
+}
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", post(post_api))
@@ -405,8 +665,60 @@ pub fn router() -> Router<AppState> {
         .route("/{user}/{rtype}", put(put_api_user_resource))
         .route("/{user}/{rtype}/{id}", get(get_api_user_resource_id))
         .route("/{user}/{rtype}/{id}", put(put_api_user_resource_id))
+        .route("/{user}/{rtype}/{id}", delete(delete_api_user_resource_id))
         .route(
             "/{user}/{rtype}/{id}/{key}",
             put(put_api_user_resource_id_path),
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::extract::Request;
+    use axum::http::header::RETRY_AFTER;
+    use axum::http::{HeaderValue, StatusCode};
+    use axum::middleware;
+    use axum::response::IntoResponse;
+    use axum::routing::put;
+    use axum::Router;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use crate::error::ApiError;
+    use crate::routes::api::v1_errors;
+
+    #[tokio::test]
+    async fn too_many_requests() {
+        let app = Router::new()
+            .route(
+                "/api/{user}/lights/{id}/state",
+                put(|| async {
+                    let mut res = ApiError::TooManyRequests.into_response();
+                    res.headers_mut()
+                        .insert(RETRY_AFTER, HeaderValue::from_static("3"));
+                    res
+                }),
+            )
+            .layer(middleware::from_fn(v1_errors));
+
+        let req = Request::put("/api/user/lights/1/state")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[RETRY_AFTER], "3");
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!([{"error": {
+                "type": 429,
+                "address": "/lights/1/state",
+                "description": "too many requests",
+            }}])
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use bytes::{Bytes, BytesMut};
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use serde_json::Value;
+
+use crate::routes::auth::{app_key, header};
+use crate::routes::proxy::ClientInfo;
+use crate::server::appstate::AppState;
+use crate::server::audit::AuditEntry;
+
+/// Larger request bodies are not recorded
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+fn is_json(headers: &HeaderMap) -> bool {
+    header(headers, "content-type").map_or(true, |ctype| ctype.starts_with("application/json"))
+}
+
+/// Read up to `limit` bytes of `body`.
+///
+/// Returns the complete body if it fits. Otherwise (or if reading fails), the
+/// body is passed on unchanged: the chunks read so far, followed by the rest
+/// of the stream.
+pub(crate) async fn read_body(body: Body, limit: usize) -> (Body, Option<Bytes>) {
+    let mut stream = body.into_data_stream();
+    let mut buf = BytesMut::new();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) if buf.len() + chunk.len() <= limit => buf.extend_from_slice(&chunk),
+            chunk => {
+                let head = stream::iter([Ok(buf.freeze()), chunk]);
+                return (Body::from_stream(head.chain(stream)), None);
+            }
+        }
+    }
+    let bytes = buf.freeze();
+    (Body::from(bytes.clone()), Some(bytes))
+}
+
+/// Record all mutating requests in the audit log
+pub async fn record(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+
+    let (body, json) = if is_json(&parts.headers) {
+        let (body, bytes) = read_body(body, MAX_BODY_SIZE).await;
+        let json = bytes
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or(Value::Null);
+        (body, json)
+    } else {
+        (body, Value::Null)
+    };
+
+    let path = parts.uri.path().to_string();
+    let client = parts.extensions.get::<ClientInfo>();
+    let mut entry = AuditEntry {
+        timestamp: Utc::now(),
+        key: app_key(&parts.headers, &path),
+        client: client.map(|info| info.addr),
+        https: client.is_some_and(|info| info.https),
+        user_agent: header(&parts.headers, "user-agent"),
+        method: parts.method.to_string(),
+        path,
+        status: 0,
+        body: json,
+    };
+
+    let res = next.run(Request::from_parts(parts, body)).await;
+
+    entry.status = res.status().as_u16();
+    let result = state.audit().lock().await.record(entry);
+    if let Err(err) = result {
+        log::error!("Failed to write audit log: {err}");
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+
+    use crate::routes::audit::{read_body, MAX_BODY_SIZE};
+
+    #[tokio::test]
+    async fn small_body_is_recorded() {
+        let (body, bytes) = read_body(Body::from("{\"on\":true}"), MAX_BODY_SIZE).await;
+        assert_eq!(bytes.unwrap().as_ref(), b"{\"on\":true}");
+        assert_eq!(
+            to_bytes(body, usize::MAX).await.unwrap().as_ref(),
+            b"{\"on\":true}"
+        );
+    }
+
+    #[tokio::test]
+    async fn large_body_is_forwarded_intact() {
+        let data = vec![b'x'; MAX_BODY_SIZE * 3];
+        let chunks: Vec<_> = data
+            .chunks(1000)
+            .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+            .collect();
+        let body = Body::from_stream(futures::stream::iter(chunks));
+
+        let (body, bytes) = read_body(body, MAX_BODY_SIZE).await;
+        assert!(bytes.is_none());
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), data);
+    }
+}
@@ -1,5 +1,7 @@
+use axum::extract::{Request, State};
 use axum::http::HeaderValue;
-use axum::response::IntoResponse;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
 use hyper::HeaderMap;
@@ -7,6 +9,7 @@ use serde_json::json;
 
 use hue::api::HueStreamKey;
 
+use crate::error::{ApiError, ApiResult};
 use crate::routes::extractor::Json;
 use crate::server::appstate::AppState;
 
@@ -15,6 +18,52 @@ pub const STANDARD_APPLICATION_ID: &str = "01010101-0202-0303-0404-050505050505"
 /// This 16-byte key is used for all DTLS entertainment streams
 pub const STANDARD_CLIENT_KEY: HueStreamKey = HueStreamKey::new(*b"BifrostHueTlsKey");
 
+#[must_use]
+pub fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string)
+}
+
+/// Find application key, either from the header (v2 api), or from the path
+/// (v1 api, e.g. "/api/<key>/lights/1/state")
+#[must_use]
+pub fn app_key(headers: &HeaderMap, path: &str) -> Option<String> {
+    header(headers, "hue-application-key").or_else(|| {
+        path.strip_prefix("/api/")
+            .and_then(|rest| rest.split('/').next())
+            .filter(|key| !key.is_empty())
+            .map(ToString::to_string)
+    })
+}
+
+/// Reject requests not made with an api key configured with `admin: true`
+pub fn require_admin(state: &AppState, headers: &HeaderMap, what: &str) -> ApiResult<()> {
+    let config = state.config();
+    let admin = header(headers, "hue-application-key")
+        .and_then(|key| config.api_keys.get(&key))
+        .is_some_and(|conf| conf.admin);
+
+    if admin {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(format!(
+            "{what} requires an admin api key"
+        )))
+    }
+}
+
+/// Only let admin api keys use the bifrost api (/bifrost)
+pub async fn admin_only(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    require_admin(&state, req.headers(), "the bifrost api")?;
+    Ok(next.run(req).await)
+}
+
 pub async fn auth_v1() -> impl IntoResponse {
     let value = HeaderValue::from_static(STANDARD_APPLICATION_ID);
 
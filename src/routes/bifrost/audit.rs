@@ -0,0 +1,66 @@
+use axum::extract::{RawQuery, State};
+use axum::routing::get;
+use axum::Router;
+
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+use crate::server::audit::AuditEntry;
+
+const DEFAULT_LIMIT: usize = 100;
+
+/// Filters for audit log queries, e.g. `?key=abc&path=scene&limit=10`
+#[derive(Debug, Default)]
+struct AuditQuery {
+    key: Option<String>,
+    path: Option<String>,
+    limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn parse(query: Option<&str>) -> Self {
+        let mut res = Self::default();
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match &*key {
+                "key" => res.key = Some(value.into_owned()),
+                "path" => res.path = Some(value.into_owned()),
+                "limit" => res.limit = value.parse().ok(),
+                _ => {}
+            }
+        }
+        res
+    }
+
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.key
+            .as_ref()
+            .map_or(true, |key| entry.key.as_ref() == Some(key))
+            && self
+                .path
+                .as_ref()
+                .map_or(true, |path| entry.path.contains(path.as_str()))
+    }
+}
+
+/// Audit log entries, newest first
+async fn get_audit(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+) -> Json<Vec<AuditEntry>> {
+    let query = AuditQuery::parse(query.as_deref());
+
+    let audit = state.audit();
+    let lock = audit.lock().await;
+    let entries = lock
+        .entries()
+        .filter(|entry| query.matches(entry))
+        .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+        .cloned()
+        .collect();
+    drop(lock);
+
+    Json(entries)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_audit))
+}
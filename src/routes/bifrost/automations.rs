@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Path, State};
+use axum::routing::get;
+use axum::Router;
+use uuid::Uuid;
+
+use hue::error::HueError;
+
+use crate::error::ApiResult;
+use crate::model::automation::AutomationEntry;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+async fn get_automations(State(state): State<AppState>) -> Json<BTreeMap<Uuid, AutomationEntry>> {
+    let lock = state.res.lock().await;
+    let entries = lock
+        .automations()
+        .entries()
+        .map(|(id, entry)| (*id, entry.clone()))
+        .collect();
+    drop(lock);
+
+    Json(entries)
+}
+
+async fn get_automation(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<AutomationEntry>> {
+    let lock = state.res.lock().await;
+    let entry = lock
+        .automations()
+        .get(&id)
+        .cloned()
+        .ok_or(HueError::NotFound(id))?;
+    drop(lock);
+
+    Ok(Json(entry))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_automations))
+        .route("/{id}", get(get_automation))
+}
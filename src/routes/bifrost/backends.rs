@@ -0,0 +1,23 @@
+//! Connection status of backends
+//!
+//! Lists every backend (zigbee2mqtt servers, plugins, z-wave, matter, etc.)
+//! with whether it is connected, since when, how often it has tried to
+//! connect, and the last connection error.
+
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+
+use crate::backend::BackendStatus;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+async fn get_backends(State(state): State<AppState>) -> Json<BTreeMap<String, BackendStatus>> {
+    Json(state.res.lock().await.backend_status().clone())
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_backends))
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Path, State};
+use axum::routing::{get, post, put};
+use axum::Router;
+use serde::Serialize;
+use uuid::Uuid;
+
+use hue::api::{Entertainment, EntertainmentConfiguration, RType, ResourceLink};
+
+use crate::error::{ApiError, ApiResult};
+use crate::model::entertainment::{
+    EntertainmentSettings, EntertainmentSettingsUpdate, EntertainmentStatsReport,
+};
+use crate::model::recording;
+use crate::resource::Resources;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+use crate::server::entertainment;
+
+/// Segment layout of a light in an entertainment area
+#[derive(Debug, Serialize)]
+struct SegmentMapReport {
+    service: ResourceLink,
+    /// Number of physical segments
+    segments: usize,
+    /// Segments addressed by the channels of the area, in channel order
+    channels: Vec<u16>,
+    /// Custom segment map: for each physical segment, the segment (as
+    /// addressed by the channels) it shows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom: Option<Vec<u16>>,
+}
+
+fn segment_maps(res: &Resources, ent_id: Uuid) -> ApiResult<Vec<SegmentMapReport>> {
+    let ent: &EntertainmentConfiguration = res.get_id(ent_id)?;
+    let mut custom = res.segment_maps(&ent_id);
+
+    let mut chans = ent.channels.clone();
+    chans.sort_by_key(|c| c.channel_id);
+
+    let mut lights: BTreeMap<ResourceLink, Vec<u16>> = BTreeMap::new();
+    for member in chans.iter().flat_map(|chan| &chan.members) {
+        lights.entry(member.service).or_default().push(member.index);
+    }
+
+    let mut reports = vec![];
+    for (service, channels) in lights {
+        let svc: &Entertainment = res.get(&service)?;
+        reports.push(SegmentMapReport {
+            service,
+            segments: svc.segments.as_ref().map_or(1, |seg| seg.segments.len()),
+            channels,
+            custom: custom.remove(&service.rid),
+        });
+    }
+
+    Ok(reports)
+}
+
+async fn get_segment_maps(
+    State(state): State<AppState>,
+    Path(ent_id): Path<Uuid>,
+) -> ApiResult<Json<Vec<SegmentMapReport>>> {
+    Ok(Json(segment_maps(&*state.res.lock().await, ent_id)?))
+}
+
+async fn put_segment_map(
+    State(state): State<AppState>,
+    Path((ent_id, service)): Path<(Uuid, Uuid)>,
+    Json(map): Json<Vec<u16>>,
+) -> ApiResult<Json<Vec<SegmentMapReport>>> {
+    let mut lock = state.res.lock().await;
+
+    let report = segment_maps(&lock, ent_id)?;
+    let light = report
+        .iter()
+        .find(|rep| rep.service.rid == service)
+        .ok_or_else(|| ApiError::EntSegmentMap(format!("{service} is not part of this area")))?;
+
+    if map.len() != light.segments {
+        return Err(ApiError::EntSegmentMap(format!(
+            "expected {} segments, got {}",
+            light.segments,
+            map.len()
+        )));
+    }
+
+    if let Some(idx) = map.iter().find(|idx| usize::from(**idx) >= light.segments) {
+        return Err(ApiError::EntSegmentMap(format!(
+            "segment {idx} out of range"
+        )));
+    }
+
+    log::info!(
+        "Custom segment map for {}: {map:?}",
+        lock.describe_link(&RType::Entertainment.link_to(service))
+    );
+    lock.set_segment_map(&ent_id, service, Some(map));
+
+    let report = segment_maps(&lock, ent_id)?;
+    drop(lock);
+
+    Ok(Json(report))
+}
+
+async fn delete_segment_map(
+    State(state): State<AppState>,
+    Path((ent_id, service)): Path<(Uuid, Uuid)>,
+) -> ApiResult<Json<Vec<SegmentMapReport>>> {
+    let mut lock = state.res.lock().await;
+    lock.get_id::<EntertainmentConfiguration>(ent_id)?;
+    lock.set_segment_map(&ent_id, service, None);
+
+    let report = segment_maps(&lock, ent_id)?;
+    drop(lock);
+
+    Ok(Json(report))
+}
+
+async fn get_settings(State(state): State<AppState>) -> ApiResult<Json<EntertainmentSettings>> {
+    Ok(Json(state.res.lock().await.entertainment_settings()))
+}
+
+async fn put_settings(
+    State(state): State<AppState>,
+    Json(upd): Json<EntertainmentSettingsUpdate>,
+) -> ApiResult<Json<EntertainmentSettings>> {
+    let mut lock = state.res.lock().await;
+
+    let settings = lock.update_entertainment_settings(&upd);
+    drop(lock);
+
+    log::info!("Entertainment settings changed: {settings:?}");
+
+    Ok(Json(settings))
+}
+
+async fn get_stats(State(state): State<AppState>) -> Json<Vec<EntertainmentStatsReport>> {
+    let sessions = state.res.lock().await.entertainment_stats();
+    Json(sessions.iter().map(|session| session.report()).collect())
+}
+
+async fn get_recordings(State(state): State<AppState>) -> ApiResult<Json<Vec<String>>> {
+    let config = state.config();
+    let dir = config
+        .entertainment
+        .record_dir
+        .as_ref()
+        .ok_or(ApiError::EntRecordingDisabled)?;
+
+    let mut res = vec![];
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.path().extension() == Some(recording::EXTENSION) {
+            res.push(entry.file_name().to_string());
+        }
+    }
+    res.sort();
+
+    Ok(Json(res))
+}
+
+async fn post_recording_replay(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> ApiResult<Json<String>> {
+    let path = state.config().entertainment.recording_path(&name)?;
+    if !path.is_file() {
+        return Err(ApiError::EntRecordingName(name));
+    }
+
+    tokio::spawn(async move {
+        let restore = state.config().entertainment.restore;
+        if let Err(err) = entertainment::replay(state.res.clone(), &path, restore).await {
+            log::error!("Failed to replay {path}: {err}");
+        }
+    });
+
+    Ok(Json(name))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_settings).put(put_settings))
+        .route("/stats", get(get_stats))
+        .route("/recordings", get(get_recordings))
+        .route("/recordings/{name}/replay", post(post_recording_replay))
+        .route("/areas/{id}/segments", get(get_segment_maps))
+        .route(
+            "/areas/{id}/segments/{service}",
+            put(put_segment_map).delete(delete_segment_map),
+        )
+}
@@ -0,0 +1,113 @@
+//! Query the history of hue events
+//!
+//! `GET /bifrost/events` lists recorded events (newest first), optionally
+//! filtered by time, resource type and resource id, e.g.
+//! `?since=2025-01-01T03:00:00Z&until=2025-01-01T04:00:00Z&type=light`
+
+use std::collections::BTreeSet;
+
+use axum::extract::{RawQuery, State};
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use hue::api::RType;
+use hue::event::{Event, EventBlock};
+
+use crate::error::{ApiError, ApiResult};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+use crate::server::hueevents;
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Default)]
+struct EventQuery {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    types: BTreeSet<RType>,
+    id: Option<Uuid>,
+    limit: Option<usize>,
+}
+
+fn parse_time(value: &str) -> ApiResult<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|err| ApiError::InvalidJson(format!("invalid time {value:?}: {err}")))
+}
+
+impl EventQuery {
+    fn parse(query: Option<&str>) -> ApiResult<Self> {
+        let mut res = Self::default();
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match &*key {
+                "since" => res.since = Some(parse_time(&value)?),
+                "until" => res.until = Some(parse_time(&value)?),
+                "type" => {
+                    let rtype = serde_json::from_value(value.clone().into()).map_err(|_| {
+                        ApiError::InvalidJson(format!("unknown resource type {value:?}"))
+                    })?;
+                    res.types.insert(rtype);
+                }
+                "id" => {
+                    let id = value
+                        .parse()
+                        .map_err(|_| ApiError::InvalidJson(format!("invalid id {value:?}")))?;
+                    res.id = Some(id);
+                }
+                "limit" => res.limit = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+
+    fn in_range(&self, block: &EventBlock) -> bool {
+        self.since.map_or(true, |since| block.creationtime >= since)
+            && self.until.map_or(true, |until| block.creationtime <= until)
+    }
+
+    /// The parts of `block` matching the type and id filters, if any
+    fn filter(&self, block: &EventBlock) -> Option<EventBlock> {
+        let mut block = hueevents::filter_types(block.clone(), &self.types)?;
+
+        if let Some(id) = self.id {
+            let data = match &mut block.event {
+                Event::Add(add) => &mut add.data,
+                Event::Update(update) => &mut update.data,
+                Event::Delete(delete) => &mut delete.data,
+                Event::Error(_) => return None,
+            };
+            data.retain(|obj| obj.get("id").and_then(|val| val.as_str()) == Some(&id.to_string()));
+            if data.is_empty() {
+                return None;
+            }
+        }
+
+        Some(block)
+    }
+}
+
+async fn get_events(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+) -> ApiResult<Json<Vec<EventBlock>>> {
+    let query = EventQuery::parse(query.as_deref())?;
+
+    let history = state.history();
+    let lock = history.lock().await;
+    let events = lock
+        .events()
+        .filter(|block| query.in_range(block))
+        .filter_map(|block| query.filter(block))
+        .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+        .collect();
+    drop(lock);
+
+    Ok(Json(events))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_events))
+}
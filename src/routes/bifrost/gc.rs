@@ -0,0 +1,25 @@
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+
+use crate::error::ApiResult;
+use crate::gc::GcReport;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+/// Report what a consistency check would change, without changing anything
+async fn get_gc(State(state): State<AppState>) -> ApiResult<Json<GcReport>> {
+    let report = state.res.lock().await.collect_garbage(true)?;
+
+    Ok(Json(report))
+}
+
+async fn post_gc(State(state): State<AppState>) -> ApiResult<Json<GcReport>> {
+    let report = state.res.lock().await.collect_garbage(false)?;
+
+    Ok(Json(report))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_gc).post(post_gc))
+}
@@ -0,0 +1,42 @@
+//! Zigbee group membership reconciliation
+//!
+//! Devices that were paired again are often missing from the zigbee groups
+//! they used to be in. The backends check for this periodically (see
+//! `z2m.<server>.group_repair`). The report of what was found is available
+//! here, and a repair can be started by hand.
+
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::Router;
+
+use crate::backend::{BackendRequest, GroupDrift};
+use crate::error::ApiResult;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+/// Devices missing from their groups, by backend (as of the last check).
+/// Nothing is changed.
+async fn get_drift(State(state): State<AppState>) -> Json<BTreeMap<String, Vec<GroupDrift>>> {
+    Json(state.res.lock().await.group_drift().clone())
+}
+
+/// Add devices back to the groups they are missing from. Returns the drift
+/// that is being repaired.
+async fn post_reconcile(
+    State(state): State<AppState>,
+) -> ApiResult<Json<BTreeMap<String, Vec<GroupDrift>>>> {
+    let lock = state.res.lock().await;
+    let drift = lock.group_drift().clone();
+    lock.backend_request(BackendRequest::GroupReconcile)?;
+    drop(lock);
+
+    Ok(Json(drift))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/drift", get(get_drift))
+        .route("/reconcile", post(post_reconcile))
+}
@@ -0,0 +1,124 @@
+//! Scene images
+//!
+//! Serves the standard scene icons (as placeholders) and user images, and
+//! allows uploading and deleting user images. Every image is also available
+//! as a `public_image` resource, so scenes can link to it.
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use uuid::Uuid;
+
+use hue::api::{RType, Scene};
+use hue::scene_icons;
+
+use crate::error::{ApiError, ApiResult};
+use crate::model::images::{self, ImageStore, ImageType};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Serialize)]
+struct ImageEntry {
+    id: Uuid,
+    content_type: &'static str,
+    builtin: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'static str>,
+}
+
+fn store(state: &AppState) -> ImageStore {
+    ImageStore::new(&state.config().bifrost.image_dir)
+}
+
+async fn get_images(State(state): State<AppState>) -> Json<Vec<ImageEntry>> {
+    let builtin = scene_icons::ALL.iter().map(|(id, name)| ImageEntry {
+        id: *id,
+        content_type: ImageType::Svg.content_type(),
+        builtin: true,
+        name: Some(name),
+    });
+
+    let user = store(&state)
+        .list()
+        .into_iter()
+        .map(|(id, typ)| ImageEntry {
+            id,
+            content_type: typ.content_type(),
+            builtin: false,
+            name: None,
+        });
+
+    Json(builtin.chain(user).collect())
+}
+
+async fn get_image(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<impl IntoResponse> {
+    if let Some(svg) = images::placeholder(&id) {
+        return Ok((
+            [(CONTENT_TYPE, ImageType::Svg.content_type())],
+            svg.into_bytes(),
+        ));
+    }
+
+    let (typ, data) = store(&state).load(&id)?;
+
+    Ok(([(CONTENT_TYPE, typ.content_type())], data))
+}
+
+async fn post_image(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<Json<Uuid>> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|val| val.to_str().ok())
+        .and_then(|val| val.split(';').next())
+        .unwrap_or_default()
+        .trim();
+    let typ = ImageType::from_content_type(content_type)?;
+
+    let id = store(&state).save(typ, &body)?;
+    state.res.lock().await.add_public_images([id])?;
+
+    Ok(Json(id))
+}
+
+/// Delete a user image, and unlink it from any scenes using it
+async fn delete_image(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Uuid>> {
+    if images::placeholder(&id).is_some() {
+        return Err(ApiError::DeleteDenied(id));
+    }
+
+    store(&state).delete(&id)?;
+
+    let link = RType::PublicImage.link_to(id);
+    let mut lock = state.res.lock().await;
+    for scene_id in lock.get_resource_ids_by_type(RType::Scene) {
+        if lock.get_id::<Scene>(scene_id)?.metadata.image == Some(link) {
+            lock.update::<Scene>(&scene_id, |scene| scene.metadata.image = None)?;
+        }
+    }
+    if lock.get_resource_by_id(&id).is_ok() {
+        lock.delete(&link)?;
+    }
+    drop(lock);
+
+    Ok(Json(id))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_images).post(post_image))
+        .route("/{id}", get(get_image).delete(delete_image))
+}
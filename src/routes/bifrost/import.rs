@@ -0,0 +1,51 @@
+use axum::extract::State;
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+
+use crate::error::ApiResult;
+use crate::import::diyhue::DiyHueConfig;
+use crate::import::hue_bridge::BridgeResources;
+use crate::import::ImportReport;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Deserialize)]
+struct HueBridgeImport {
+    address: String,
+    key: String,
+}
+
+async fn post_hue_bridge(
+    State(state): State<AppState>,
+    Json(req): Json<HueBridgeImport>,
+) -> ApiResult<Json<ImportReport>> {
+    log::info!("Importing from hue bridge at {}", req.address);
+
+    let bridge = BridgeResources::fetch(&req.address, &req.key).await?;
+
+    let mut lock = state.res.lock().await;
+    let report = bridge.import(&mut lock)?;
+    drop(lock);
+
+    Ok(Json(report))
+}
+
+async fn post_diyhue(
+    State(state): State<AppState>,
+    Json(config): Json<DiyHueConfig>,
+) -> ApiResult<Json<ImportReport>> {
+    log::info!("Importing diyHue configuration");
+
+    let mut lock = state.res.lock().await;
+    let report = config.import(&mut lock)?;
+    drop(lock);
+
+    Ok(Json(report))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/hue", post(post_hue_bridge))
+        .route("/diyhue", post(post_diyhue))
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use axum::extract::Path;
+use axum::routing::{get, put};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiResult;
+use crate::logging;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Serialize)]
+struct LogLevels {
+    levels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogLevelUpdate {
+    level: String,
+}
+
+fn current_levels() -> Json<LogLevels> {
+    let levels = logging::levels()
+        .into_iter()
+        .map(|(module, level)| (module, level.to_string().to_lowercase()))
+        .collect();
+
+    Json(LogLevels { levels })
+}
+
+async fn get_levels() -> Json<LogLevels> {
+    current_levels()
+}
+
+async fn put_level(
+    Path(module): Path<String>,
+    Json(upd): Json<LogLevelUpdate>,
+) -> ApiResult<Json<LogLevels>> {
+    let level = logging::parse_level(&upd.level)?;
+    log::info!("Setting log level for {module:?} to {level}");
+    logging::set_level(&module, level);
+
+    Ok(current_levels())
+}
+
+async fn delete_level(Path(module): Path<String>) -> Json<LogLevels> {
+    if logging::clear_level(&module) {
+        log::info!("Cleared log level for {module:?}");
+    }
+
+    current_levels()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_levels))
+        .route("/{module}", put(put_level).delete(delete_level))
+}
@@ -0,0 +1,47 @@
+//! Matter device commissioning
+//!
+//! New matter devices are added to the fabric of the matter server with
+//! their setup code: the `MT:...` payload of the qr code, or the manual
+//! pairing code printed next to it. Commissioning takes a while; the device
+//! shows up as a light when it is done.
+
+use axum::extract::State;
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+
+use crate::backend::BackendRequest;
+use crate::error::{ApiError, ApiResult};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Deserialize)]
+struct CommissionRequest {
+    code: String,
+}
+
+async fn post_commission(
+    State(state): State<AppState>,
+    Json(req): Json<CommissionRequest>,
+) -> ApiResult<Json<()>> {
+    if state.config().matter.is_none() {
+        return Err(ApiError::MatterDisabled);
+    }
+
+    let code = req.code.trim();
+    if code.is_empty() {
+        return Err(ApiError::InvalidJson("code: must not be empty".to_string()));
+    }
+
+    state
+        .res
+        .lock()
+        .await
+        .backend_request(BackendRequest::Commission(code.to_string()))?;
+
+    Ok(Json(()))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/commission", post(post_commission))
+}
@@ -0,0 +1,297 @@
+use std::fmt::Write;
+
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+use crate::backend::traffic::{TrafficClass, TrafficStats};
+use crate::backend::BackendStatus;
+use crate::model::entertainment::EntertainmentStatsReport;
+use crate::server::appstate::AppState;
+
+/// A metric, and all its samples
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    samples: Vec<(String, String)>,
+}
+
+/// Helper for writing metrics in the prometheus text exposition format.
+///
+/// Samples are collected per metric, since all samples of a metric must
+/// follow its (single) `# HELP` and `# TYPE` lines.
+#[derive(Default)]
+struct Metrics(Vec<Family>);
+
+/// Format label pairs, escaping the values as prometheus expects
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let mut res = String::new();
+    for (idx, (name, value)) in pairs.iter().enumerate() {
+        if idx > 0 {
+            res.push(',');
+        }
+        res.push_str(name);
+        res.push_str("=\"");
+        for ch in value.chars() {
+            match ch {
+                '\\' => res.push_str("\\\\"),
+                '"' => res.push_str("\\\""),
+                '\n' => res.push_str("\\n"),
+                _ => res.push(ch),
+            }
+        }
+        res.push('"');
+    }
+    res
+}
+
+impl Metrics {
+    fn metric(
+        &mut self,
+        name: &'static str,
+        kind: &'static str,
+        help: &'static str,
+        labels: &str,
+        value: impl std::fmt::Display,
+    ) {
+        let sample = (labels.to_string(), value.to_string());
+        if let Some(family) = self.0.iter_mut().find(|family| family.name == name) {
+            family.samples.push(sample);
+        } else {
+            self.0.push(Family {
+                name,
+                kind,
+                help,
+                samples: vec![sample],
+            });
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut res = String::new();
+
+        /* writing to a String cannot fail */
+        for family in &self.0 {
+            let name = family.name;
+            let _ = writeln!(res, "# HELP bifrost_{name} {}", family.help);
+            let _ = writeln!(res, "# TYPE bifrost_{name} {}", family.kind);
+            for (labels, value) in &family.samples {
+                let _ = writeln!(res, "bifrost_{name}{{{labels}}} {value}");
+            }
+        }
+
+        res
+    }
+
+    fn backend(&mut self, name: &str, status: &BackendStatus) {
+        let labels = labels(&[("backend", name)]);
+
+        self.metric(
+            "backend_ready",
+            "gauge",
+            "Whether the backend is connected, and has received its initial state",
+            &labels,
+            u8::from(status.ready),
+        );
+        self.metric(
+            "backend_connect_attempts_total",
+            "counter",
+            "Attempts of the backend to connect",
+            &labels,
+            status.connect_attempts,
+        );
+        self.metric(
+            "backend_connections_total",
+            "counter",
+            "Times the backend became ready",
+            &labels,
+            status.connections,
+        );
+    }
+
+    fn traffic(&mut self, name: &str, stats: &TrafficStats) {
+        for class in TrafficClass::ALL {
+            let labels = labels(&[("backend", name), ("class", class.as_str())]);
+
+            self.metric(
+                "backend_queue_depth",
+                "gauge",
+                "Requests waiting to be sent by the backend",
+                &labels,
+                stats.depth(class),
+            );
+            self.metric(
+                "backend_requests_sent_total",
+                "counter",
+                "Requests sent by the backend",
+                &labels,
+                stats.sent(class),
+            );
+        }
+    }
+
+    fn entertainment(&mut self, rep: &EntertainmentStatsReport) {
+        let labels = labels(&[("area", &rep.area.to_string()), ("name", &rep.name)]);
+
+        self.metric(
+            "entertainment_active",
+            "gauge",
+            "Whether the entertainment session is active",
+            &labels,
+            u8::from(rep.active),
+        );
+        self.metric(
+            "entertainment_channels",
+            "gauge",
+            "Number of channels in the entertainment session",
+            &labels,
+            rep.channels,
+        );
+        self.metric(
+            "entertainment_frames_received_total",
+            "counter",
+            "Frames received from the streaming client",
+            &labels,
+            rep.frames_received,
+        );
+        self.metric(
+            "entertainment_frames_forwarded_total",
+            "counter",
+            "Frames passed on to backends, after rate limiting",
+            &labels,
+            rep.frames_forwarded,
+        );
+        self.metric(
+            "entertainment_frames_sent_total",
+            "counter",
+            "Frames sent to zigbee",
+            &labels,
+            rep.frames_sent,
+        );
+        self.metric(
+            "entertainment_frames_dropped_total",
+            "counter",
+            "Frames received, but never sent to zigbee",
+            &labels,
+            rep.frames_dropped,
+        );
+        self.metric(
+            "entertainment_latency_seconds",
+            "gauge",
+            "Estimated latency from frame reception to zigbee transmission",
+            &labels,
+            rep.latency,
+        );
+    }
+}
+
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let mut res = Metrics::default();
+
+    let lock = state.res.lock().await;
+    for (name, status) in lock.backend_status() {
+        res.backend(name, status);
+    }
+    for (name, stats) in lock.traffic_stats() {
+        res.traffic(name, stats);
+    }
+    let sessions = lock.entertainment_stats();
+    drop(lock);
+
+    for session in sessions {
+        res.entertainment(&session.report());
+    }
+
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], res.render())
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_metrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use uuid::Uuid;
+
+    use crate::backend::traffic::TrafficStats;
+    use crate::backend::BackendStatus;
+    use crate::model::entertainment::EntertainmentStats;
+    use crate::routes::bifrost::metrics::Metrics;
+
+    /// Parse the label set of a sample, undoing the escaping
+    fn parse_labels(text: &str) -> BTreeMap<String, String> {
+        let mut res = BTreeMap::new();
+        let mut chars = text.chars();
+        loop {
+            let name: String = chars.by_ref().take_while(|ch| *ch != '=').collect();
+            if name.is_empty() {
+                return res;
+            }
+            assert_eq!(chars.next(), Some('"'));
+            let mut value = String::new();
+            loop {
+                match chars.next().unwrap() {
+                    '"' => break,
+                    '\\' => match chars.next().unwrap() {
+                        'n' => value.push('\n'),
+                        ch @ ('\\' | '"') => value.push(ch),
+                        ch => panic!("invalid escape \\{ch}"),
+                    },
+                    '\n' => panic!("raw newline in label value"),
+                    ch => value.push(ch),
+                }
+            }
+            res.insert(name, value);
+            if chars.next().is_none() {
+                return res;
+            }
+        }
+    }
+
+    #[test]
+    fn families_are_declared_once() {
+        let mut metrics = Metrics::default();
+        let backend = BackendStatus::default();
+        metrics.backend("z2m", &backend);
+        metrics.backend("other", &backend);
+        metrics.traffic("z2m", &TrafficStats::default());
+        let name = "TV \"front\\\"\nroom";
+        let stats = EntertainmentStats::new(Uuid::new_v4(), name, 3);
+        metrics.entertainment(&stats.report());
+
+        let text = metrics.render();
+
+        let mut declared = BTreeSet::new();
+        let mut current = None;
+        let mut names = BTreeSet::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let family = rest.split(' ').next().unwrap().to_string();
+                assert!(declared.insert(family.clone()), "{family} declared twice");
+                current = Some(family);
+                continue;
+            }
+            if line.starts_with("# HELP ") {
+                continue;
+            }
+
+            let (sample, value) = line.rsplit_once(' ').unwrap();
+            value.parse::<f64>().unwrap();
+            let (family, labels) = sample.split_once('{').unwrap();
+            assert_eq!(Some(family), current.as_deref(), "{line}");
+            let labels = parse_labels(labels.strip_suffix('}').unwrap());
+            if let Some(name) = labels.get("name") {
+                names.insert(name.clone());
+            }
+        }
+
+        assert!(declared.contains("bifrost_backend_ready"));
+        assert!(declared.contains("bifrost_backend_queue_depth"));
+        assert_eq!(names, BTreeSet::from([name.to_string()]));
+    }
+}
@@ -0,0 +1,54 @@
+use axum::Router;
+
+use crate::server::appstate::AppState;
+
+pub mod audit;
+pub mod automations;
+pub mod backends;
+pub mod entertainment;
+pub mod events;
+pub mod gc;
+pub mod groups;
+pub mod images;
+pub mod import;
+pub mod logging;
+pub mod matter;
+pub mod metrics;
+pub mod notify;
+pub mod ota;
+pub mod pairing;
+pub mod quirks;
+pub mod resources;
+pub mod scenes;
+pub mod sensors;
+pub mod sharding;
+pub mod snapshots;
+pub mod vacation;
+pub mod zigbee;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .nest("/audit", audit::router())
+        .nest("/automations", automations::router())
+        .nest("/backends", backends::router())
+        .nest("/entertainment", entertainment::router())
+        .nest("/events", events::router())
+        .nest("/gc", gc::router())
+        .nest("/groups", groups::router())
+        .nest("/images", images::router())
+        .nest("/import", import::router())
+        .nest("/logging", logging::router())
+        .nest("/matter", matter::router())
+        .nest("/metrics", metrics::router())
+        .nest("/notify", notify::router())
+        .nest("/ota", ota::router())
+        .nest("/pairing", pairing::router())
+        .nest("/quirks", quirks::router())
+        .nest("/resources", resources::router())
+        .nest("/scenes", scenes::router())
+        .nest("/sensors", sensors::router())
+        .nest("/sharding", sharding::router())
+        .nest("/snapshots", snapshots::router())
+        .nest("/vacation", vacation::router())
+        .nest("/zigbee", zigbee::router())
+}
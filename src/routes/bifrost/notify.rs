@@ -0,0 +1,142 @@
+//! Notification flashes
+//!
+//! Flash some lights (listed one by one, or all lights in a room or zone) in
+//! a given color a number of times, e.g. for a doorbell. Afterwards, the
+//! lights are put back the way they were, using a light snapshot, so any
+//! active scenes are recalled again.
+//!
+//! The request returns as soon as the flashing has started.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use hue::api::{Light, LightUpdate, On, ResourceLink};
+use hue::xy::XY;
+
+use crate::backend::BackendRequest;
+use crate::error::ApiResult;
+use crate::model::snapshot::{LightSelection, LightSnapshot};
+use crate::resource::Resources;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+/// Most flashes allowed in a single notification
+const MAX_COUNT: u32 = 20;
+
+/// Longest on or off time allowed, in milliseconds
+const MAX_PERIOD_MS: u64 = 10_000;
+
+const fn default_color() -> [u8; 3] {
+    [0xFF, 0x00, 0x00]
+}
+
+const fn default_count() -> u32 {
+    3
+}
+
+const fn default_period() -> u64 {
+    500
+}
+
+const fn default_brightness() -> f64 {
+    100.0
+}
+
+#[derive(Debug, Deserialize)]
+struct NotifyRequest {
+    #[serde(flatten)]
+    select: LightSelection,
+    /// Flash color, as `[red, green, blue]`
+    #[serde(default = "default_color")]
+    color: [u8; 3],
+    #[serde(default = "default_count")]
+    count: u32,
+    #[serde(default = "default_brightness")]
+    brightness: f64,
+    #[serde(default = "default_period")]
+    on_ms: u64,
+    #[serde(default = "default_period")]
+    off_ms: u64,
+}
+
+async fn flash(
+    res: Arc<Mutex<Resources>>,
+    snapshot: LightSnapshot,
+    on: Vec<(ResourceLink, LightUpdate)>,
+    req: NotifyRequest,
+) -> ApiResult<()> {
+    let on_time = Duration::from_millis(req.on_ms.min(MAX_PERIOD_MS));
+    let off_time = Duration::from_millis(req.off_ms.min(MAX_PERIOD_MS));
+    let off = LightUpdate::new()
+        .with_on(On::new(false))
+        .with_duration(Some(0));
+
+    for _ in 0..req.count.clamp(1, MAX_COUNT) {
+        let lock = res.lock().await;
+        for (link, upd) in &on {
+            lock.backend_request(BackendRequest::LightUpdate(*link, upd.clone()))?;
+        }
+        drop(lock);
+        tokio::time::sleep(on_time).await;
+
+        let lock = res.lock().await;
+        for (link, _) in &on {
+            lock.backend_request(BackendRequest::LightUpdate(*link, off.clone()))?;
+        }
+        drop(lock);
+        tokio::time::sleep(off_time).await;
+    }
+
+    snapshot.restore(&*res.lock().await, true)
+}
+
+/// Start flashing the selected lights. Returns the lights that will flash.
+async fn post_notify(
+    State(state): State<AppState>,
+    Json(req): Json<NotifyRequest>,
+) -> ApiResult<Json<BTreeSet<ResourceLink>>> {
+    let lock = state.res.lock().await;
+    let members = req.select.resolve(&lock)?;
+    let snapshot = LightSnapshot::take(&lock, &members)?;
+
+    let [red, green, blue] = req.color;
+    let (xy, _) = XY::from_rgb(red, green, blue);
+
+    let mut on = vec![];
+    for link in &members {
+        let light: &Light = lock.get(link)?;
+        let mut upd = LightUpdate::new()
+            .with_on(On::new(true))
+            .with_duration(Some(0));
+        if light.dimming.is_some() {
+            upd = upd.with_brightness(Some(req.brightness.clamp(1.0, 100.0)));
+        }
+        if light.color.is_some() {
+            upd = upd.with_color_xy(xy);
+        }
+        on.push((*link, upd));
+    }
+    drop(lock);
+
+    log::info!("Flashing {} lights for notification", on.len());
+
+    let res = state.res.clone();
+    tokio::spawn(async move {
+        if let Err(err) = flash(res, snapshot, on, req).await {
+            log::error!("Notification flash failed: {err}");
+        }
+    });
+
+    Ok(Json(members))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", post(post_notify))
+}
@@ -0,0 +1,114 @@
+//! Firmware (OTA) files for zigbee2mqtt
+//!
+//! Serves the zigbee OTA files found in `bifrost.ota_dir`, along with an
+//! index in the format zigbee2mqtt expects for its override index
+//! (`ota.zigbee_ota_override_index_location`). Files that are not valid OTA
+//! images are left out of the index.
+
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+
+use zcl::ota::OtaImage;
+
+use crate::error::{ApiError, ApiResult};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexEntry {
+    file_name: String,
+    file_version: u32,
+    file_size: u32,
+    url: String,
+    image_type: u16,
+    manufacturer_code: u16,
+    ota_header_string: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hardware_version_min: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hardware_version_max: Option<u16>,
+}
+
+impl IndexEntry {
+    fn new(file_name: &str, base_url: &str, img: &OtaImage) -> Self {
+        let hdr = &img.header;
+        Self {
+            file_name: file_name.to_string(),
+            file_version: hdr.file_version.0,
+            file_size: hdr.total_image_size,
+            url: format!("{base_url}/{file_name}"),
+            image_type: hdr.image_type,
+            manufacturer_code: hdr.manufacturer_code,
+            ota_header_string: hdr.header_string.clone(),
+            hardware_version_min: hdr.hardware_versions.map(|(min, _)| min),
+            hardware_version_max: hdr.hardware_versions.map(|(_, max)| max),
+        }
+    }
+}
+
+async fn get_index(State(state): State<AppState>) -> ApiResult<Json<Vec<IndexEntry>>> {
+    let config = state.config();
+    let dir = config
+        .bifrost
+        .ota_dir
+        .as_ref()
+        .ok_or(ApiError::OtaDisabled)?;
+
+    let base_url = format!(
+        "http://{}:{}/bifrost/ota",
+        config.bridge.ipaddress, config.bridge.http_port
+    );
+
+    let mut res = vec![];
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() || entry.file_name().starts_with('.') {
+            continue;
+        }
+
+        let data = std::fs::read(entry.path())?;
+        match OtaImage::parse(&data) {
+            Ok(img) => res.push(IndexEntry::new(entry.file_name(), &base_url, &img)),
+            Err(err) => log::warn!("Skipping firmware file {}: {err}", entry.path()),
+        }
+    }
+    res.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok(Json(res))
+}
+
+/// Serve a firmware file, without any vendor wrapping in front of the OTA
+/// header
+async fn get_file(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let path = state.config().bifrost.ota_path(&name)?;
+    if !path.is_file() {
+        return Err(ApiError::OtaFileName(name));
+    }
+
+    let mut data = std::fs::read(&path)?;
+    let img = OtaImage::parse(&data)?;
+
+    log::info!(
+        "Serving firmware file {name} (version {})",
+        img.header.file_version
+    );
+
+    data.drain(..img.wrapper_size);
+    data.truncate(img.header.total_image_size as usize);
+
+    Ok(([(CONTENT_TYPE, "application/octet-stream")], data))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/index.json", get(get_index))
+        .route("/{name}", get(get_file))
+}
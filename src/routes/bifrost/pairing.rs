@@ -0,0 +1,51 @@
+//! Pairing of new zigbee devices
+//!
+//! Allows new devices to join the zigbee network for a while, through
+//! zigbee2mqtt or the native coordinator. Sending `seconds: 0` stops
+//! pairing early.
+
+use axum::extract::State;
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+
+use crate::backend::BackendRequest;
+use crate::error::{ApiError, ApiResult};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+/// Longest pairing window; 255 would mean "forever" to zigbee routers
+const MAX_SECONDS: u8 = 254;
+
+const fn default_seconds() -> u8 {
+    60
+}
+
+#[derive(Debug, Deserialize)]
+struct PairingRequest {
+    #[serde(default = "default_seconds")]
+    seconds: u8,
+}
+
+async fn post_pairing(
+    State(state): State<AppState>,
+    Json(req): Json<PairingRequest>,
+) -> ApiResult<Json<()>> {
+    if req.seconds > MAX_SECONDS {
+        return Err(ApiError::InvalidJson(format!(
+            "seconds: must be at most {MAX_SECONDS}"
+        )));
+    }
+
+    state
+        .res
+        .lock()
+        .await
+        .backend_request(BackendRequest::PermitJoin(req.seconds))?;
+
+    Ok(Json(()))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", post(post_pairing))
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Path, State};
+use axum::routing::{get, post};
+use axum::Router;
+use serde::Serialize;
+
+use hue::devicedb::{self, Quirk};
+
+use crate::error::{ApiError, ApiResult};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Serialize)]
+struct ReloadReport {
+    models: usize,
+}
+
+async fn get_quirks() -> Json<BTreeMap<String, Quirk>> {
+    Json(devicedb::quirks().models().clone())
+}
+
+async fn get_quirk(Path(model_id): Path<String>) -> ApiResult<Json<Quirk>> {
+    devicedb::quirk(&model_id)
+        .map(Json)
+        .ok_or(ApiError::UnknownModel(model_id))
+}
+
+async fn post_reload(State(state): State<AppState>) -> ApiResult<Json<ReloadReport>> {
+    let db = state.config().bifrost.load_quirks()?;
+    let models = db.models().len();
+
+    log::info!("Reloaded device database: {models} models");
+    devicedb::set_quirks(db);
+
+    Ok(Json(ReloadReport { models }))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_quirks))
+        .route("/reload", post(post_reload))
+        .route("/{model_id}", get(get_quirk))
+}
@@ -0,0 +1,56 @@
+//! Resource search, for debugging and tooling
+//!
+//! `GET /bifrost/resources` takes the same filters as `GET /clip/v2/resource`
+//! (see [`crate::model::query`]), but replies with a short summary of each
+//! match, e.g. `?name=desk&capability=color`
+//!
+//! Only available to api keys configured with `admin: true`.
+
+use axum::extract::{RawQuery, State};
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+
+use hue::api::ResourceLink;
+
+use crate::error::ApiResult;
+use crate::model::query::ResourceQuery;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Serialize)]
+struct SearchResult {
+    #[serde(flatten)]
+    link: ResourceLink,
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<ResourceLink>,
+}
+
+async fn get_search(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+) -> ApiResult<Json<Vec<SearchResult>>> {
+    let mut query = ResourceQuery::parse(query.as_deref())?;
+    query.limit.get_or_insert(DEFAULT_LIMIT);
+
+    let lock = state.res.lock().await;
+    let results = lock
+        .query(&query)?
+        .into_iter()
+        .map(|rec| SearchResult {
+            link: rec.obj.rtype().link_to(rec.id),
+            name: lock.resource_name(&rec.id),
+            owner: rec.obj.owner(),
+        })
+        .collect();
+    drop(lock);
+
+    Ok(Json(results))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_search))
+}
@@ -0,0 +1,82 @@
+//! Scene tools
+//!
+//! Copies a scene to another room or zone. The actions of the scene are
+//! mapped onto the lights of the target, matching lights by capability (so
+//! color lights get the colors, and on/off plugs just get switched on).
+
+use axum::extract::{Path, State};
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use hue::api::{remap_actions, Light, RType, ResourceLink, Scene};
+use hue::error::HueError;
+
+use crate::backend::BackendRequest;
+use crate::error::ApiResult;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Deserialize)]
+struct SceneCopy {
+    /// Room or zone to copy the scene to
+    target: Uuid,
+    /// Name of the new scene (defaults to the name of the copied scene)
+    name: Option<String>,
+}
+
+/// Copy a scene to another room or zone, and return the new scene
+async fn post_copy(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<SceneCopy>,
+) -> ApiResult<Json<ResourceLink>> {
+    let lock = state.res.lock().await;
+
+    let scene: &Scene = lock.get_id(id)?;
+
+    let target = lock.get_resource_by_id(&req.target)?.obj.rtype();
+    if !matches!(target, RType::Room | RType::Zone) {
+        return Err(HueError::WrongType(RType::Room, target).into());
+    }
+    let target = target.link_to(req.target);
+
+    let source: Vec<_> = scene
+        .actions
+        .iter()
+        .filter_map(|elem| Some((elem, lock.get::<Light>(&elem.target).ok()?)))
+        .collect();
+    let lights: Vec<_> = lock
+        .group_lights(&target)
+        .into_iter()
+        .map(|(id, light)| (RType::Light.link_to(id), light))
+        .collect();
+
+    let mut copy = scene.clone();
+    copy.actions = remap_actions(&source, &lights);
+    copy.group = target;
+    copy.status = None;
+    if let Some(name) = req.name {
+        copy.metadata.name = name;
+    }
+
+    let sid = lock.get_next_scene_id(&target)?;
+    let link_scene = RType::Scene.deterministic((target.rid, sid));
+
+    log::info!(
+        "Copying scene {:?} to {target:?}, as {:?} ({} actions)",
+        scene.metadata.name,
+        copy.metadata.name,
+        copy.actions.len()
+    );
+
+    lock.backend_request(BackendRequest::SceneCreate(link_scene, sid, copy))?;
+    drop(lock);
+
+    Ok(Json(link_scene))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/{id}/copy", post(post_copy))
+}
@@ -0,0 +1,157 @@
+//! Virtual (CLIP) sensors
+//!
+//! The V2 API has no virtual sensors, so the sensors created through the V1
+//! API are available here, by uuid. Both APIs work on the same sensors, so
+//! rules and external systems can use them to share state.
+
+use axum::extract::{Path, State};
+use axum::routing::{get, put};
+use axum::Router;
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use hue::legacy_api::{ApiSensor, ApiSensorNew, ApiSensorUpdate};
+
+use crate::error::ApiResult;
+use crate::resource::Resources;
+use crate::routes::extractor::{self, Json};
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Serialize)]
+struct VirtualSensor {
+    id: Uuid,
+    id_v1: String,
+    #[serde(flatten)]
+    sensor: ApiSensor,
+}
+
+impl VirtualSensor {
+    fn get(res: &Resources, id_v1: u32) -> ApiResult<Self> {
+        Ok(Self {
+            id: res.from_id_v1(id_v1)?,
+            id_v1: format!("/sensors/{id_v1}"),
+            sensor: res.clip_sensor(id_v1)?.clone(),
+        })
+    }
+}
+
+async fn get_sensors(State(state): State<AppState>) -> ApiResult<Json<Vec<VirtualSensor>>> {
+    let lock = state.res.lock().await;
+    let sensors = lock
+        .clip_sensors()
+        .map(|(id, _)| VirtualSensor::get(&lock, id))
+        .collect::<ApiResult<_>>()?;
+    drop(lock);
+
+    Ok(Json(sensors))
+}
+
+async fn post_sensor(
+    State(state): State<AppState>,
+    Json(req): Json<Value>,
+) -> ApiResult<Json<VirtualSensor>> {
+    let new: ApiSensorNew = extractor::parse(&state, &req)?;
+    let sensor = ApiSensor::clip(new, Utc::now())?;
+
+    let mut lock = state.res.lock().await;
+    let id = lock.add_clip_sensor(sensor);
+    log::info!("Created virtual sensor /sensors/{id}");
+
+    let sensor = VirtualSensor::get(&lock, id)?;
+    drop(lock);
+
+    Ok(Json(sensor))
+}
+
+async fn get_sensor(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<VirtualSensor>> {
+    let lock = state.res.lock().await;
+    let sensor = VirtualSensor::get(&lock, lock.get_id_v1_index(id)?)?;
+    drop(lock);
+
+    Ok(Json(sensor))
+}
+
+async fn put_sensor(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<Value>,
+) -> ApiResult<Json<VirtualSensor>> {
+    let upd: ApiSensorUpdate = extractor::parse(&state, &req)?;
+
+    let mut lock = state.res.lock().await;
+    let id_v1 = lock.get_id_v1_index(id)?;
+    lock.update_clip_sensor(id_v1, |sensor| {
+        if let Some(name) = upd.name {
+            sensor.name = name;
+        }
+        Ok(())
+    })?;
+
+    let sensor = VirtualSensor::get(&lock, id_v1)?;
+    drop(lock);
+
+    Ok(Json(sensor))
+}
+
+async fn put_sensor_state(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<Value>,
+) -> ApiResult<Json<VirtualSensor>> {
+    let upd: Map<String, Value> = extractor::parse(&state, &req)?;
+    let now = Utc::now();
+
+    let mut lock = state.res.lock().await;
+    let id_v1 = lock.get_id_v1_index(id)?;
+    lock.update_clip_sensor(id_v1, |sensor| sensor.update_state(&upd, now))?;
+
+    let sensor = VirtualSensor::get(&lock, id_v1)?;
+    drop(lock);
+
+    Ok(Json(sensor))
+}
+
+async fn put_sensor_config(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<Value>,
+) -> ApiResult<Json<VirtualSensor>> {
+    let upd: Map<String, Value> = extractor::parse(&state, &req)?;
+
+    let mut lock = state.res.lock().await;
+    let id_v1 = lock.get_id_v1_index(id)?;
+    lock.update_clip_sensor(id_v1, |sensor| sensor.update_config(&upd))?;
+
+    let sensor = VirtualSensor::get(&lock, id_v1)?;
+    drop(lock);
+
+    Ok(Json(sensor))
+}
+
+async fn delete_sensor(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Uuid>> {
+    let mut lock = state.res.lock().await;
+    let id_v1 = lock.get_id_v1_index(id)?;
+    lock.delete_clip_sensor(id_v1)?;
+    drop(lock);
+
+    Ok(Json(id))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_sensors).post(post_sensor))
+        .route(
+            "/{id}",
+            get(get_sensor).put(put_sensor).delete(delete_sensor),
+        )
+        .route("/{id}/state", put(put_sensor_state))
+        .route("/{id}/config", put(put_sensor_config))
+}
@@ -0,0 +1,27 @@
+//! Distribution of devices over bridges
+//!
+//! With `sharding` configured, the zigbee2mqtt backends assign every room
+//! (and every device outside a room) to one of the bridges. The plan shows
+//! which devices go where, and is available in preview mode too, so a
+//! distribution can be checked before it is applied.
+
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+
+use crate::backend::ShardSummary;
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+/// Planned distribution of devices over bridges, by backend
+async fn get_plan(
+    State(state): State<AppState>,
+) -> Json<BTreeMap<String, BTreeMap<String, ShardSummary>>> {
+    Json(state.res.lock().await.shard_plan().clone())
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_plan))
+}
@@ -0,0 +1,99 @@
+//! Light state snapshots
+//!
+//! Take a snapshot of some lights (listed one by one, or all lights in a
+//! room or zone), and restore it later. Active scenes are recalled again,
+//! unless `?scenes=false` is given on restore. Restoring a snapshot removes
+//! it, unless `?keep=true` is given.
+//!
+//! Snapshots are kept in memory only, and the oldest ones are dropped when
+//! there are too many.
+
+use axum::extract::{Path, RawQuery, State};
+use axum::routing::{delete, get, post};
+use axum::Router;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::model::snapshot::{LightSelection, LightSnapshot, SnapshotInfo};
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Deserialize)]
+struct SnapshotRequest {
+    #[serde(flatten)]
+    select: LightSelection,
+    name: Option<String>,
+}
+
+async fn get_snapshots(State(state): State<AppState>) -> Json<Vec<SnapshotInfo>> {
+    Json(state.snapshots().lock().await.list())
+}
+
+async fn post_snapshot(
+    State(state): State<AppState>,
+    Json(req): Json<SnapshotRequest>,
+) -> ApiResult<Json<SnapshotInfo>> {
+    let lock = state.res.lock().await;
+
+    let members = req.select.resolve(&lock)?;
+    let snapshot = LightSnapshot::take(&lock, &members)?;
+    drop(lock);
+
+    let info = state.snapshots().lock().await.insert(req.name, snapshot);
+    log::info!("Took snapshot {} of {} lights", info.id, info.lights);
+
+    Ok(Json(info))
+}
+
+/// Restore a snapshot (and remove it, unless `?keep=true`)
+async fn post_restore(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    RawQuery(query): RawQuery,
+) -> ApiResult<Json<Uuid>> {
+    let query = query.unwrap_or_default();
+    let flag = |key: &str| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+            .map(|value| value == "true")
+    };
+    let scenes = flag("scenes").unwrap_or(true);
+    let keep = flag("keep").unwrap_or(false);
+
+    let store = state.snapshots();
+    let mut snapshots = store.lock().await;
+    let snapshot = snapshots.get(&id).ok_or(ApiError::SnapshotNotFound(id))?;
+
+    log::info!("Restoring snapshot {id}");
+    snapshot.restore(&*state.res.lock().await, scenes)?;
+
+    if !keep {
+        snapshots.remove(&id);
+    }
+    drop(snapshots);
+
+    Ok(Json(id))
+}
+
+async fn delete_snapshot(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Uuid>> {
+    state
+        .snapshots()
+        .lock()
+        .await
+        .remove(&id)
+        .ok_or(ApiError::SnapshotNotFound(id))?;
+
+    Ok(Json(id))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_snapshots).post(post_snapshot))
+        .route("/{id}", delete(delete_snapshot))
+        .route("/{id}/restore", post(post_restore))
+}
@@ -0,0 +1,45 @@
+//! Away mode, for the presence simulation
+//!
+//! With `vacation` configured, turning away mode on starts replaying light
+//! changes from the event history, until it is turned off again. Away mode
+//! is kept in memory only, so after a restart it is back to the configured
+//! value.
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AwayMode {
+    away: bool,
+    /// True if the presence simulation is configured
+    #[serde(default, skip_deserializing)]
+    simulation: bool,
+}
+
+fn away_mode(state: &AppState) -> AwayMode {
+    AwayMode {
+        away: *state.away().borrow(),
+        simulation: state.config().vacation.is_some(),
+    }
+}
+
+async fn get_away(State(state): State<AppState>) -> Json<AwayMode> {
+    Json(away_mode(&state))
+}
+
+async fn put_away(State(state): State<AppState>, Json(req): Json<AwayMode>) -> Json<AwayMode> {
+    if state.away().send_replace(req.away) != req.away {
+        log::info!("Away mode turned {}", if req.away { "on" } else { "off" });
+    }
+
+    Json(away_mode(&state))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(get_away).put(put_away))
+}
@@ -0,0 +1,103 @@
+//! Raw zigbee access, for reverse engineering and debugging
+//!
+//! Like the rest of the bifrost api, only available to api keys configured
+//! with `admin: true`.
+
+use axum::extract::{RawQuery, State};
+use axum::response::sse::{Event, Sse};
+use axum::routing::{get, post};
+use axum::Router;
+use futures::stream::Stream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio_stream::wrappers::BroadcastStream;
+
+use hue::api::ResourceLink;
+use hue::zigbee::ZigbeeMessage;
+
+use crate::backend::BackendRequest;
+use crate::error::{ApiError, ApiResult};
+use crate::routes::extractor::{self, Json};
+use crate::server::appstate::AppState;
+
+const fn default_frametype() -> u8 {
+    1
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ZigbeeSend {
+    target: ResourceLink,
+    cluster: u16,
+    command: u8,
+    /// Command payload, as hex
+    #[serde(default)]
+    payload: String,
+    #[serde(default = "default_frametype")]
+    frametype: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manufacturer_code: Option<u16>,
+    #[serde(default = "default_true")]
+    disable_default_response: bool,
+}
+
+async fn post_send(
+    State(state): State<AppState>,
+    Json(req): Json<serde_json::Value>,
+) -> ApiResult<Json<ResourceLink>> {
+    let req: ZigbeeSend = extractor::parse(&state, &req)?;
+    let data = hex::decode(&req.payload)
+        .map_err(|err| ApiError::InvalidJson(format!("payload: {err}")))?;
+
+    let msg = ZigbeeMessage::new(req.cluster, req.command, data)
+        .with_mfc(req.manufacturer_code)
+        .with_ddr(req.disable_default_response);
+    let msg = ZigbeeMessage {
+        frametype: req.frametype,
+        ..msg
+    };
+
+    let lock = state.res.lock().await;
+    lock.get_resource_by_id(&req.target.rid)?;
+    log::info!(
+        "Raw zigbee command for {}: {msg:?}",
+        lock.describe_link(&req.target)
+    );
+    lock.backend_request(BackendRequest::ZigbeeRaw(req.target, msg))?;
+    drop(lock);
+
+    Ok(Json(req.target))
+}
+
+/// Incoming zigbee messages, as a stream of events. Can be limited to a
+/// single device with `?device=<name>`
+async fn get_frames(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+) -> ApiResult<Sse<impl Stream<Item = ApiResult<Event>>>> {
+    let device = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .find(|(key, _)| key == "device")
+        .map(|(_, value)| value.into_owned());
+
+    let channel = state.res.lock().await.zigbee_frame_stream();
+    let stream = BroadcastStream::new(channel)
+        .filter(move |frame| {
+            let keep = match (frame, &device) {
+                (Ok(frame), Some(device)) => &frame.device == device,
+                _ => true,
+            };
+            futures::future::ready(keep)
+        })
+        .map(|frame| Ok(Event::default().json_data(&*frame?)?));
+
+    Ok(Sse::new(stream))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/send", post(post_send))
+        .route("/frames", get(get_frames))
+}
@@ -0,0 +1,113 @@
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, post, put};
+use axum::Router;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{BehaviorInstance, BehaviorInstanceUpdate, RType, Resource, ResourceLink};
+
+use crate::error::{ApiError, ApiResult};
+use crate::routes::clip::generic::get_resource;
+use crate::routes::clip::ApiV2Result;
+use crate::routes::extractor::{self, Json};
+use crate::routes::V2Reply;
+use crate::server::appstate::AppState;
+
+/// Make sure accessory configurations can be executed, before accepting them
+fn validate(inst: &BehaviorInstance) -> ApiResult<()> {
+    if let Some(Err(err)) = inst.button_configuration() {
+        return Err(ApiError::InvalidJson(format!("configuration: {err}")));
+    }
+
+    Ok(())
+}
+
+async fn post_behavior_instance(
+    State(state): State<AppState>,
+    Json(req): Json<Value>,
+) -> ApiV2Result {
+    log::info!("POST behavior_instance");
+    log::debug!("json data\n{}", serde_json::to_string_pretty(&req)?);
+
+    let inst: BehaviorInstance = extractor::parse(&state, &req)?;
+    validate(&inst)?;
+
+    let rlink = ResourceLink::new(Uuid::new_v4(), RType::BehaviorInstance);
+
+    let mut lock = state.res.lock().await;
+    lock.add(&rlink, Resource::BehaviorInstance(inst))?;
+    drop(lock);
+
+    V2Reply::ok(rlink)
+}
+
+async fn put_behavior_instance(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(put): Json<Value>,
+) -> ApiV2Result {
+    log::info!("PUT behavior_instance/{id}");
+    log::debug!("json data\n{}", serde_json::to_string_pretty(&put)?);
+
+    let rlink = RType::BehaviorInstance.link_to(id);
+
+    let upd: BehaviorInstanceUpdate = extractor::parse(&state, &put)?;
+
+    let mut lock = state.res.lock().await;
+
+    let mut inst = lock.get::<BehaviorInstance>(&rlink)?.clone();
+    if let Some(enabled) = upd.enabled {
+        inst.enabled = enabled;
+    }
+    if let Some(configuration) = upd.configuration {
+        inst.configuration = configuration;
+    }
+    if let Some(metadata) = upd.metadata {
+        inst.metadata = metadata;
+    }
+    validate(&inst)?;
+
+    lock.update::<BehaviorInstance>(&id, |obj| *obj = inst)?;
+    drop(lock);
+
+    V2Reply::ok(rlink)
+}
+
+async fn get_behavior_instance(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV2Result {
+    V2Reply::ok(
+        state
+            .res
+            .lock()
+            .await
+            .get_resource(RType::BehaviorInstance, &id)?,
+    )
+}
+
+async fn delete_behavior_instance(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiV2Result {
+    log::info!("DELETE behavior_instance/{id}");
+
+    let rlink = RType::BehaviorInstance.link_to(id);
+
+    let mut lock = state.res.lock().await;
+    lock.get::<BehaviorInstance>(&rlink)?;
+    lock.delete(&rlink)?;
+    drop(lock);
+
+    V2Reply::ok(rlink)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::BehaviorInstance), query)),
+        )
+        .route("/", post(post_behavior_instance))
+        .route("/{id}", get(get_behavior_instance))
+        .route("/{id}", put(put_behavior_instance))
+        .route("/{id}", delete(delete_behavior_instance))
+}
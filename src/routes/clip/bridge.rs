@@ -0,0 +1,50 @@
+use axum::extract::{Path, State};
+use axum::routing::{get, put};
+use axum::Router;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{Bridge, BridgeUpdate, RType};
+
+use crate::routes::clip::generic::get_resource;
+use crate::routes::clip::ApiV2Result;
+use crate::routes::extractor::{self, Json};
+use crate::routes::V2Reply;
+use crate::server::appstate::AppState;
+
+async fn put_bridge(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(put): Json<Value>,
+) -> ApiV2Result {
+    log::info!("PUT bridge/{id}");
+    log::debug!("json data\n{}", serde_json::to_string_pretty(&put)?);
+
+    let rlink = RType::Bridge.link_to(id);
+
+    let upd: BridgeUpdate = extractor::parse(&state, &put)?;
+
+    let mut lock = state.res.lock().await;
+    lock.get::<Bridge>(&rlink)?;
+    if let Some(tz) = upd.time_zone {
+        lock.set_time_zone(&tz.time_zone)?;
+    }
+    drop(lock);
+
+    V2Reply::ok(rlink)
+}
+
+async fn get_bridge(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV2Result {
+    V2Reply::ok(state.res.lock().await.get_resource(RType::Bridge, &id)?)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::Bridge), query)),
+        )
+        .route("/{id}", get(get_bridge))
+        .route("/{id}", put(put_bridge))
+}
@@ -0,0 +1,56 @@
+use axum::extract::{Path, State};
+use axum::routing::{get, put};
+use axum::Router;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{Contact, ContactUpdate, RType};
+use hue::error::HueError;
+
+use crate::routes::clip::generic::get_resource;
+use crate::routes::clip::ApiV2Result;
+use crate::routes::extractor::{self, Json};
+use crate::routes::V2Reply;
+use crate::server::appstate::AppState;
+
+async fn put_contact(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(put): Json<Value>,
+) -> ApiV2Result {
+    log::info!("PUT contact/{id}");
+    log::debug!("json data\n{}", serde_json::to_string_pretty(&put)?);
+
+    let rlink = RType::Contact.link_to(id);
+
+    let upd: ContactUpdate = extractor::parse(&state, &put)?;
+
+    // contact reports come from the sensor
+    if upd.contact_report.is_some() {
+        return Err(HueError::UpdateUnsupported(RType::Contact))?;
+    }
+
+    let mut lock = state.res.lock().await;
+    lock.get::<Contact>(&rlink)?;
+    if let Some(enabled) = upd.enabled {
+        lock.update::<Contact>(&id, |contact| contact.enabled = enabled)?;
+    }
+    drop(lock);
+
+    V2Reply::ok(rlink)
+}
+
+async fn get_contact(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV2Result {
+    V2Reply::ok(state.res.lock().await.get_resource(RType::Contact, &id)?)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::Contact), query)),
+        )
+        .route("/{id}", get(get_contact))
+        .route("/{id}", put(put_contact))
+}
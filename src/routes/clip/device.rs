@@ -7,9 +7,10 @@ use uuid::Uuid;
 
 use hue::api::{Device, DeviceUpdate, RType};
 
+use crate::backend::{BackendRequest, IdentifyEffect};
 use crate::routes::clip::generic::get_resource;
 use crate::routes::clip::ApiV2Result;
-use crate::routes::extractor::Json;
+use crate::routes::extractor::{self, Json};
 use crate::routes::V2Reply;
 use crate::server::appstate::AppState;
 
@@ -23,13 +24,14 @@ async fn put_device(
 
     let rlink = RType::Device.link_to(id);
 
-    let upd: DeviceUpdate = serde_json::from_value(put)?;
+    let upd: DeviceUpdate = extractor::parse(&state, &put)?;
 
-    state
-        .res
-        .lock()
-        .await
-        .update::<Device>(&id, |obj| *obj += upd)?;
+    let mut lock = state.res.lock().await;
+    if upd.identify.is_some() {
+        lock.backend_request(BackendRequest::Identify(rlink, IdentifyEffect::Blink))?;
+    }
+    lock.update::<Device>(&id, |obj| *obj += upd)?;
+    drop(lock);
 
     V2Reply::ok(rlink)
 }
@@ -40,7 +42,10 @@ async fn get_device(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV
 
 pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/", get(|state| get_resource(state, Path(RType::Device))))
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::Device), query)),
+        )
         .route("/{id}", get(get_device))
         .route("/{id}", put(put_device))
 }
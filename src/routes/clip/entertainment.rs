@@ -1,4 +1,4 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, RawQuery, State};
 use axum::routing::get;
 use axum::Router;
 use uuid::Uuid;
@@ -9,8 +9,8 @@ use crate::routes::clip::generic;
 use crate::routes::clip::ApiV2Result;
 use crate::server::appstate::AppState;
 
-pub async fn get_resource(state: State<AppState>) -> ApiV2Result {
-    generic::get_resource(state, Path(RType::Entertainment)).await
+pub async fn get_resource(state: State<AppState>, query: RawQuery) -> ApiV2Result {
+    generic::get_resource(state, Path(RType::Entertainment), query).await
 }
 
 async fn get_resource_id(state: State<AppState>, Path(id): Path<Uuid>) -> ApiV2Result {
@@ -1,4 +1,4 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, RawQuery, State};
 use axum::response::IntoResponse;
 use axum::routing::{delete, get, post, put};
 use axum::Router;
@@ -7,24 +7,24 @@ use serde_json::Value;
 use uuid::{uuid, Uuid};
 
 use hue::api::{
-    Bridge, Device, Entertainment, EntertainmentConfiguration, EntertainmentConfigurationAction,
-    EntertainmentConfigurationChannels, EntertainmentConfigurationLocations,
-    EntertainmentConfigurationNew, EntertainmentConfigurationServiceLocations,
-    EntertainmentConfigurationStatus, EntertainmentConfigurationStreamMembers,
-    EntertainmentConfigurationStreamProxy, EntertainmentConfigurationStreamProxyMode,
-    EntertainmentConfigurationStreamProxyUpdate, EntertainmentConfigurationUpdate, Light,
-    LightMode, Position, RType, Resource, ResourceLink,
+    check_service_locations, Bridge, Device, Entertainment, EntertainmentConfiguration,
+    EntertainmentConfigurationAction, EntertainmentConfigurationChannels,
+    EntertainmentConfigurationLocations, EntertainmentConfigurationNew,
+    EntertainmentConfigurationServiceLocations, EntertainmentConfigurationStatus,
+    EntertainmentConfigurationStreamMembers, EntertainmentConfigurationStreamProxy,
+    EntertainmentConfigurationStreamProxyMode, EntertainmentConfigurationStreamProxyUpdate,
+    EntertainmentConfigurationUpdate, Light, LightMode, Position, RType, Resource, ResourceLink,
 };
 
 use crate::error::{ApiError, ApiResult};
 use crate::resource::Resources;
 use crate::routes::auth::STANDARD_APPLICATION_ID;
 use crate::routes::clip::{generic, ApiV2Result, V2Reply};
-use crate::routes::extractor::Json;
+use crate::routes::extractor::{self, Json};
 use crate::server::appstate::AppState;
 
-pub async fn get_resource(state: State<AppState>) -> ApiV2Result {
-    generic::get_resource(state, Path(RType::EntertainmentConfiguration)).await
+pub async fn get_resource(state: State<AppState>, query: RawQuery) -> ApiV2Result {
+    generic::get_resource(state, Path(RType::EntertainmentConfiguration), query).await
 }
 
 async fn post_resource(State(state): State<AppState>, Json(req): Json<Value>) -> impl IntoResponse {
@@ -33,7 +33,7 @@ async fn post_resource(State(state): State<AppState>, Json(req): Json<Value>) ->
         serde_json::to_string(&req)?
     );
 
-    let new: EntertainmentConfigurationNew = serde_json::from_value(req)?;
+    let new: EntertainmentConfigurationNew = extractor::parse(&state, &req)?;
 
     let mut lock = state.res.lock().await;
 
@@ -46,6 +46,7 @@ async fn post_resource(State(state): State<AppState>, Json(req): Json<Value>) ->
             .collect(),
     };
 
+    check_locations(&lock, &locations.service_locations)?;
     let channels = make_channels(&lock, &locations.service_locations)?;
     let light_services = make_services(&lock, &locations.service_locations)?;
 
@@ -93,6 +94,20 @@ async fn get_resource_id(state: State<AppState>, Path(id): Path<Uuid>) -> ApiV2R
     generic::get_resource_id(state, Path((RType::EntertainmentConfiguration, id))).await
 }
 
+/// Reject service locations that cannot be streamed to, instead of failing
+/// when the stream is started
+fn check_locations(
+    lock: &Resources,
+    locations: &[EntertainmentConfigurationServiceLocations],
+) -> ApiResult<()> {
+    let errors = check_service_locations(locations, |link| lock.get(link).ok());
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::EntConfigInvalid(errors))
+    }
+}
+
 fn make_channels(
     lock: &Resources,
     locations: &[EntertainmentConfigurationServiceLocations],
@@ -216,7 +231,7 @@ async fn put_resource_id(
     log::info!("PUT {rtype:?}/{id}");
     log::debug!("json data\n{}", serde_json::to_string_pretty(&put)?);
 
-    let upd: EntertainmentConfigurationUpdate = serde_json::from_value(put)?;
+    let upd: EntertainmentConfigurationUpdate = extractor::parse(&state, &put)?;
 
     let mut lock = state.res.lock().await;
 
@@ -233,12 +248,17 @@ async fn put_resource_id(
                 .map(Into::into)
                 .collect(),
         };
+        check_locations(&lock, &newlocs.service_locations)?;
         channels = make_channels(&lock, &newlocs.service_locations)?;
         light_services = make_services(&lock, &newlocs.service_locations)?;
         locations = Some(newlocs);
     }
 
     if let Some(action) = &upd.action {
+        if *action == EntertainmentConfigurationAction::Start {
+            lock.check_entertainment_conflict(&id)?;
+        }
+
         let ent: &EntertainmentConfiguration =
             lock.get(&RType::EntertainmentConfiguration.link_to(id))?;
         let svc = ent.light_services.clone();
@@ -1,5 +1,6 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
+    http::header::CONTENT_TYPE,
     response::IntoResponse,
     routing::{delete, get, post, put},
     Router,
@@ -10,17 +11,43 @@ use uuid::Uuid;
 use hue::api::{RType, Resource, ResourceLink};
 use hue::error::HueError;
 
-use crate::error::ApiError;
+use crate::error::{ApiError, ApiResult};
+use crate::model::query::ResourceQuery;
 use crate::routes::clip::{ApiV2Result, V2Reply};
 use crate::routes::extractor::Json;
 use crate::server::appstate::AppState;
 
-async fn get_root(State(state): State<AppState>) -> impl IntoResponse {
-    V2Reply::list(state.res.lock().await.get_resources())
+async fn get_root(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+) -> ApiResult<impl IntoResponse> {
+    let query = ResourceQuery::parse(query.as_deref())?;
+
+    // the full (unfiltered) list is cached, so only filter when asked to
+    if query.is_empty() && query.limit.is_none() {
+        let json = state.res.lock().await.resource_list_json()?;
+        return Ok(([(CONTENT_TYPE, "application/json")], json).into_response());
+    }
+
+    let data = state.res.lock().await.query(&query)?;
+    Ok(V2Reply::list(data).into_response())
 }
 
-pub async fn get_resource(State(state): State<AppState>, Path(rtype): Path<RType>) -> ApiV2Result {
-    V2Reply::list(state.res.lock().await.get_resources_by_type(rtype))
+pub async fn get_resource(
+    State(state): State<AppState>,
+    Path(rtype): Path<RType>,
+    RawQuery(query): RawQuery,
+) -> ApiV2Result {
+    let mut query = ResourceQuery::parse(query.as_deref())?;
+
+    /* a ?type= filter can only narrow down the type from the path, so a
+     * different type matches nothing */
+    if !query.types.is_empty() && !query.types.contains(&rtype) {
+        return V2Reply::<Value>::list(vec![]);
+    }
+    query.types = [rtype].into();
+
+    V2Reply::list(state.res.lock().await.query(&query)?)
 }
 
 async fn post_resource(
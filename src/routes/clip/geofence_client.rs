@@ -0,0 +1,117 @@
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, post, put};
+use axum::Router;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{GeofenceClient, GeofenceClientUpdate, RType, Resource};
+
+use crate::routes::clip::generic::get_resource;
+use crate::routes::clip::ApiV2Result;
+use crate::routes::extractor::{self, Json};
+use crate::routes::V2Reply;
+use crate::server::appstate::AppState;
+
+/// Follow a change of the at-home state with away mode, if the presence
+/// simulation is configured to
+fn follow_at_home(state: &AppState, changed: Option<bool>) {
+    let follow = state
+        .config()
+        .vacation
+        .as_ref()
+        .is_some_and(|vac| vac.geofence);
+
+    if let (true, Some(at_home)) = (follow, changed) {
+        state.away().send_replace(!at_home);
+    }
+}
+
+async fn post_geofence_client(
+    State(state): State<AppState>,
+    Json(req): Json<Value>,
+) -> ApiV2Result {
+    log::info!("POST: geofence_client {}", serde_json::to_string(&req)?);
+
+    let client: GeofenceClient = extractor::parse(&state, &req)?;
+
+    let rlink = RType::GeofenceClient.link_to(Uuid::new_v4());
+    let mut lock = state.res.lock().await;
+    lock.add(&rlink, Resource::GeofenceClient(client))?;
+    let changed = lock.update_at_home()?;
+    drop(lock);
+
+    follow_at_home(&state, changed);
+
+    V2Reply::ok(rlink)
+}
+
+async fn put_geofence_client(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(put): Json<Value>,
+) -> ApiV2Result {
+    log::info!("PUT geofence_client/{id}");
+    log::debug!("json data\n{}", serde_json::to_string_pretty(&put)?);
+
+    let rlink = RType::GeofenceClient.link_to(id);
+
+    let upd: GeofenceClientUpdate = extractor::parse(&state, &put)?;
+
+    let mut lock = state.res.lock().await;
+    lock.update::<GeofenceClient>(&id, |client| {
+        if let Some(name) = upd.name {
+            client.name = name;
+        }
+        if let Some(is_at_home) = upd.is_at_home {
+            client.is_at_home = is_at_home;
+        }
+    })?;
+    let changed = lock.update_at_home()?;
+    drop(lock);
+
+    follow_at_home(&state, changed);
+
+    V2Reply::ok(rlink)
+}
+
+async fn get_geofence_client(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV2Result {
+    V2Reply::ok(
+        state
+            .res
+            .lock()
+            .await
+            .get_resource(RType::GeofenceClient, &id)?,
+    )
+}
+
+async fn delete_geofence_client(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiV2Result {
+    log::info!("DELETE geofence_client/{id}");
+
+    let rlink = RType::GeofenceClient.link_to(id);
+
+    let mut lock = state.res.lock().await;
+    lock.get::<GeofenceClient>(&rlink)?;
+    lock.delete(&rlink)?;
+    let changed = lock.update_at_home()?;
+    drop(lock);
+
+    follow_at_home(&state, changed);
+
+    V2Reply::ok(rlink)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::GeofenceClient), query)),
+        )
+        .route("/", post(post_geofence_client))
+        .route("/{id}", get(get_geofence_client))
+        .route("/{id}", put(put_geofence_client))
+        .route("/{id}", delete(delete_geofence_client))
+}
@@ -9,7 +9,7 @@ use hue::api::{GroupedLight, GroupedLightUpdate, RType};
 use crate::backend::BackendRequest;
 use crate::routes::clip::generic::get_resource;
 use crate::routes::clip::{ApiV2Result, V2Reply};
-use crate::routes::extractor::Json;
+use crate::routes::extractor::{self, Json};
 use crate::server::appstate::AppState;
 
 async fn put_grouped_light(
@@ -26,7 +26,7 @@ async fn put_grouped_light(
 
     log::info!("PUT grouped_light/{id}: updating");
 
-    let upd: GroupedLightUpdate = serde_json::from_value(put)?;
+    let upd: GroupedLightUpdate = extractor::parse(&state, &put)?;
 
     lock.backend_request(BackendRequest::GroupedLightUpdate(rlink, upd))?;
 
@@ -47,7 +47,10 @@ async fn get_grouped_light(State(state): State<AppState>, Path(id): Path<Uuid>)
 
 pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/", get(|st| get_resource(st, Path(RType::GroupedLight))))
+        .route(
+            "/",
+            get(|st, query| get_resource(st, Path(RType::GroupedLight), query)),
+        )
         .route("/{id}", get(get_grouped_light))
         .route("/{id}", put(put_grouped_light))
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use axum::extract::{Path, State};
 use axum::routing::{get, put};
 use axum::Router;
@@ -6,10 +8,10 @@ use uuid::Uuid;
 
 use hue::api::{Light, LightUpdate, RType};
 
-use crate::backend::BackendRequest;
+use crate::backend::{self, BackendRequest};
 use crate::routes::clip::generic::get_resource;
 use crate::routes::clip::{ApiV2Result, V2Reply};
-use crate::routes::extractor::Json;
+use crate::routes::extractor::{self, Json};
 use crate::server::appstate::AppState;
 
 async fn put_light(
@@ -23,14 +25,20 @@ async fn put_light(
     let rlink = RType::Light.link_to(id);
     let lock = state.res.lock().await;
 
-    let _ = lock.get::<Light>(&rlink)?;
+    let light = lock.get::<Light>(&rlink)?;
 
-    let upd: LightUpdate = serde_json::from_value(put)?;
+    let upd: LightUpdate = extractor::parse(&state, &put)?;
+    light.check_update(&upd)?;
 
+    let results = lock.command_result_stream(&rlink);
     lock.backend_request(BackendRequest::LightUpdate(rlink, upd))?;
 
     drop(lock);
 
+    if let (Some(results), Some(ms)) = (results, state.config().bifrost.command_timeout) {
+        backend::confirm_command(results, rlink, Duration::from_millis(ms)).await?;
+    }
+
     V2Reply::ok(rlink)
 }
 
@@ -40,7 +48,10 @@ async fn get_light(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV2
 
 pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/", get(|state| get_resource(state, Path(RType::Light))))
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::Light), query)),
+        )
         .route("/{id}", get(get_light))
         .route("/{id}", put(put_light))
 }
@@ -1,23 +1,38 @@
+pub mod behavior_instance;
+pub mod bridge;
+pub mod contact;
 pub mod device;
 pub mod entertainment;
 pub mod entertainment_configuration;
 pub mod generic;
+pub mod geofence_client;
 pub mod grouped_light;
 pub mod light;
+pub mod room;
 pub mod scene;
 
+use axum::body::to_bytes;
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
 use axum::Router;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::error::ApiResult;
 use crate::routes::extractor::Json;
+use crate::routes::V1ErrorInfo;
 use crate::server::appstate::AppState;
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct V2Error {
+    pub description: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct V2Reply<T> {
     pub data: Vec<T>,
-    pub errors: Vec<String>,
+    pub errors: Vec<V2Error>,
 }
 
 type ApiV2Result = ApiResult<Json<V2Reply<Value>>>;
@@ -43,12 +58,51 @@ impl<T: Serialize> V2Reply<T> {
     }
 }
 
+/// Make sure all failed requests get a hue-style error reply, including
+/// those rejected before reaching a handler (invalid json, unknown routes)
+pub async fn v2_errors(req: Request, next: Next) -> Response {
+    const MAX_ERROR_SIZE: usize = 4096;
+
+    if !req.uri().path().starts_with("/clip/v2/") {
+        return next.run(req).await;
+    }
+
+    let res = next.run(req).await;
+
+    let status = res.status();
+    let is_error = status.is_client_error() || status.is_server_error();
+    if !is_error || res.extensions().get::<V1ErrorInfo>().is_some() {
+        return res;
+    }
+
+    let body = to_bytes(res.into_body(), MAX_ERROR_SIZE)
+        .await
+        .unwrap_or_default();
+    let description = if body.is_empty() {
+        status.canonical_reason().unwrap_or_default().to_string()
+    } else {
+        String::from_utf8_lossy(&body).to_string()
+    };
+
+    let reply = V2Reply::<Value> {
+        data: vec![],
+        errors: vec![V2Error { description }],
+    };
+
+    (status, Json(reply)).into_response()
+}
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .nest("/scene", scene::router())
         .nest("/light", light::router())
+        .nest("/contact", contact::router())
+        .nest("/behavior_instance", behavior_instance::router())
+        .nest("/bridge", bridge::router())
         .nest("/device", device::router())
+        .nest("/geofence_client", geofence_client::router())
         .nest("/grouped_light", grouped_light::router())
+        .nest("/room", room::router())
         .nest(
             "/entertainment_configuration",
             entertainment_configuration::router(),
@@ -0,0 +1,57 @@
+use axum::extract::{Path, State};
+use axum::routing::{get, put};
+use axum::Router;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use hue::api::{RType, Room, RoomUpdate};
+use hue::error::HueError;
+
+use crate::routes::clip::generic::get_resource;
+use crate::routes::clip::ApiV2Result;
+use crate::routes::extractor::{self, Json};
+use crate::routes::V2Reply;
+use crate::server::appstate::AppState;
+
+async fn put_room(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(put): Json<Value>,
+) -> ApiV2Result {
+    log::info!("PUT room/{id}");
+    log::debug!("json data\n{}", serde_json::to_string_pretty(&put)?);
+
+    let rlink = RType::Room.link_to(id);
+
+    // invalid archetypes are rejected here, with a list of valid ones
+    let upd: RoomUpdate = extractor::parse(&state, &put)?;
+
+    // room members come from the backend (e.g. zigbee2mqtt groups)
+    if upd.children.is_some() {
+        return Err(HueError::UpdateUnsupported(RType::Room))?;
+    }
+
+    let mut lock = state.res.lock().await;
+    lock.get::<Room>(&rlink)?;
+    if let Some(md) = upd.metadata {
+        lock.update::<Room>(&id, |room| room.metadata += md)?;
+    }
+    drop(lock);
+
+    V2Reply::ok(rlink)
+}
+
+async fn get_room(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiV2Result {
+    V2Reply::ok(state.res.lock().await.get_resource(RType::Room, &id)?)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::Room), query)),
+        )
+        .route("/{id}", get(get_room))
+        .route("/{id}", put(put_room))
+}
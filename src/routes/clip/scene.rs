@@ -11,7 +11,7 @@ use crate::backend::BackendRequest;
 use crate::error::{ApiError, ApiResult};
 use crate::routes::clip::generic::get_resource;
 use crate::routes::clip::{ApiV2Result, V2Reply};
-use crate::routes::extractor::Json;
+use crate::routes::extractor::{self, Json};
 use crate::server::appstate::AppState;
 
 async fn post_scene(
@@ -20,7 +20,7 @@ async fn post_scene(
 ) -> ApiResult<impl IntoResponse> {
     log::info!("POST: scene {}", serde_json::to_string(&req)?);
 
-    let scene: Scene = serde_json::from_value(req)?;
+    let scene: Scene = extractor::parse(&state, &req)?;
 
     let lock = state.res.lock().await;
 
@@ -48,7 +48,7 @@ async fn put_scene(
 
     log::info!("PUT scene/{id}: updating");
 
-    let upd: SceneUpdate = serde_json::from_value(put)?;
+    let upd: SceneUpdate = extractor::parse(&state, &put)?;
 
     if let Some(md) = &upd.metadata {
         lock.update::<Scene>(&id, |scn| scn.metadata += md.clone())?;
@@ -83,7 +83,10 @@ async fn delete_scene(State(state): State<AppState>, Path(id): Path<Uuid>) -> Ap
 
 pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/", get(|state| get_resource(state, Path(RType::Scene))))
+        .route(
+            "/",
+            get(|state, query| get_resource(state, Path(RType::Scene), query)),
+        )
         .route("/", post(post_scene))
         .route("/{id}", put(put_scene))
         .route("/{id}", delete(delete_scene))
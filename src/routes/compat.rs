@@ -0,0 +1,172 @@
+//! Per-app compatibility rewrites, as configured in the `compat` section
+//!
+//! Some apps break on fields they do not expect (typically ones added in
+//! newer hue api versions). For clients matching a rule (by User-Agent, or
+//! by the devicetype they paired with), json responses are rewritten to
+//! leave out or rename such fields.
+
+use axum::body::{Body, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::header::CONTENT_LENGTH;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde_json::{Map, Value};
+
+use crate::config::CompatRule;
+use crate::routes::audit::read_body;
+use crate::routes::auth::{app_key, header};
+use crate::server::appstate::AppState;
+
+/// Larger responses are passed through unchanged
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Apply the omit and rename rules to `value`, at any depth
+fn rewrite(value: &mut Value, rule: &CompatRule) {
+    match value {
+        Value::Object(obj) => {
+            let fields = std::mem::take(obj);
+            *obj = fields
+                .into_iter()
+                .filter(|(key, _)| !rule.omit.contains(key))
+                .map(|(key, mut val)| {
+                    rewrite(&mut val, rule);
+                    let key = rule.rename.get(&key).cloned().unwrap_or(key);
+                    (key, val)
+                })
+                .collect::<Map<String, Value>>();
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite(item, rule);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rewrite json responses for clients that match one or more compat rules
+pub async fn rewrite_response(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let config = state.config();
+    if config.compat.is_empty() {
+        return next.run(req).await;
+    }
+
+    let user_agent = header(req.headers(), "user-agent");
+    let devicetype = match app_key(req.headers(), req.uri().path()) {
+        Some(key) => state.devicetype(&key).await,
+        None => None,
+    };
+
+    let rules: Vec<&CompatRule> = config
+        .compat
+        .iter()
+        .filter(|rule| rule.matches(user_agent.as_deref(), devicetype.as_deref()))
+        .collect();
+
+    let res = next.run(req).await;
+    if rules.is_empty() {
+        return res;
+    }
+
+    let is_json = header(res.headers(), "content-type")
+        .is_some_and(|ctype| ctype.starts_with("application/json"));
+    if !is_json {
+        return res;
+    }
+
+    let too_large = res.body().size_hint().lower() > MAX_BODY_SIZE as u64
+        || header(res.headers(), "content-length")
+            .and_then(|len| len.parse::<u64>().ok())
+            .is_some_and(|len| len > MAX_BODY_SIZE as u64);
+    if too_large {
+        return res;
+    }
+
+    // bodies of unknown size can still turn out to be too large (or fail to
+    // read), and are then passed on as they are
+    let (mut parts, body) = res.into_parts();
+    let (body, bytes) = read_body(body, MAX_BODY_SIZE).await;
+    let Some(bytes) = bytes else {
+        return Response::from_parts(parts, body);
+    };
+
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    for rule in rules {
+        rewrite(&mut json, rule);
+    }
+
+    let body = serde_json::to_vec(&json).unwrap_or_else(|_| bytes.to_vec());
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::config::CompatRule;
+    use crate::routes::compat::rewrite;
+
+    fn rule(omit: &[&str], rename: &[(&str, &str)]) -> CompatRule {
+        CompatRule {
+            user_agent: Some("app".to_string()),
+            devicetype: None,
+            omit: omit.iter().map(ToString::to_string).collect(),
+            rename: rename
+                .iter()
+                .map(|(from, to)| ((*from).to_string(), (*to).to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn omit() {
+        let mut value = json!({"id": "1", "dynamics": {"speed": 0.5}, "on": {"on": true}});
+        rewrite(&mut value, &rule(&["dynamics"], &[]));
+        assert_eq!(value, json!({"id": "1", "on": {"on": true}}));
+    }
+
+    #[test]
+    fn rename() {
+        let mut value =
+            json!({"id": "1", "metadata": {"name": "Hall", "archetype": "classic_bulb"}});
+        rewrite(&mut value, &rule(&[], &[("archetype", "type")]));
+        assert_eq!(
+            value,
+            json!({"id": "1", "metadata": {"name": "Hall", "type": "classic_bulb"}})
+        );
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let mut value = json!({
+            "errors": [],
+            "data": [
+                {"id": "1", "effects": {"status": "no_effect"}, "services": [[{"rtype": "light"}]]},
+                {"id": "2", "effects": null},
+            ],
+        });
+        rewrite(&mut value, &rule(&["effects"], &[("rtype", "type")]));
+        assert_eq!(
+            value,
+            json!({
+                "errors": [],
+                "data": [
+                    {"id": "1", "services": [[{"type": "light"}]]},
+                    {"id": "2"},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn scalars_unchanged() {
+        let mut value = json!(["effects", 1, null]);
+        rewrite(&mut value, &rule(&["effects"], &[("effects", "x")]));
+        assert_eq!(value, json!(["effects", 1, null]));
+    }
+}
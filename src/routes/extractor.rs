@@ -1,9 +1,15 @@
+use std::fmt::Write;
+
 use axum::extract::rejection::JsonRejection;
 use axum::extract::{FromRequest, Request};
 use axum::response::IntoResponse;
 use bytes::Bytes;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::{ApiError, ApiResult};
+use crate::server::appstate::AppState;
 
 // Simple wrapper around axum::Json, which skips the header requirements.
 //
@@ -30,3 +36,103 @@ impl<T: Serialize> IntoResponse for Json<T> {
         axum::Json(self.0).into_response()
     }
 }
+
+/// Json pointer to the value at `path`, and a readable form of it (like
+/// `.metadata.name` or `.actions[0]`)
+fn pointer(path: &serde_ignored::Path, ptr: &mut String, name: &mut String) {
+    match path {
+        serde_ignored::Path::Root => {}
+        serde_ignored::Path::Seq { parent, index } => {
+            pointer(parent, ptr, name);
+            /* writing to a String cannot fail */
+            let _ = write!(ptr, "/{index}");
+            let _ = write!(name, "[{index}]");
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            pointer(parent, ptr, name);
+            ptr.push('/');
+            ptr.push_str(&key.replace('~', "~0").replace('/', "~1"));
+            name.push('.');
+            name.push_str(key);
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => pointer(parent, ptr, name),
+    }
+}
+
+/// Parse `value` into `T`, returning the paths of fields unknown to `T`
+fn deserialize<T: DeserializeOwned>(value: &Value) -> ApiResult<(T, Vec<String>)> {
+    let mut unknown = vec![];
+    let mut on_unknown = |path: serde_ignored::Path| {
+        let (mut ptr, mut name) = (String::new(), String::new());
+        pointer(&path, &mut ptr, &mut name);
+        match value.pointer(&ptr) {
+            Some(Value::Null) => {}
+            /* clients often include the resource type in requests */
+            _ if ptr == "/type" => {}
+            _ => unknown.push(name),
+        }
+    };
+
+    let de = serde_ignored::Deserializer::new(value, &mut on_unknown);
+    let res: T = serde_path_to_error::deserialize(de).map_err(|err| {
+        let path = err.path().to_string();
+        ApiError::InvalidJson(format!("{path}: {}", err.into_inner()))
+    })?;
+
+    Ok((res, unknown))
+}
+
+/// Parse request json into `T`, reporting the path of any invalid value.
+///
+/// Fields unknown to `T` are rejected when `bifrost.strict_json` is
+/// enabled, and logged (but otherwise ignored) when it is not.
+pub fn parse<T: DeserializeOwned>(state: &AppState, value: &Value) -> ApiResult<T> {
+    let (res, unknown) = deserialize(value)?;
+
+    if !unknown.is_empty() {
+        let fields = unknown.join(", ");
+        if state.config().bifrost.strict_json {
+            return Err(ApiError::InvalidJson(format!("unknown fields: {fields}")));
+        }
+        log::warn!("Ignoring unknown fields in request: {fields}");
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use hue::api::{BehaviorInstanceUpdate, ButtonConfiguration};
+
+    use crate::routes::extractor::deserialize;
+
+    #[test]
+    fn skipped_fields_are_known() {
+        let value = json!({
+            "device": {"rid": Uuid::new_v4(), "rtype": "device"},
+            "buttons": {},
+            "where": [],
+        });
+        let (_, unknown) = deserialize::<ButtonConfiguration>(&value).unwrap();
+        assert_eq!(unknown, Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let value = json!({
+            "type": "behavior_instance",
+            "enabled": true,
+            "metadata": {"name": "Switch", "color": "red"},
+            "extra": [1],
+            "ignored": null,
+        });
+        let (upd, unknown) = deserialize::<BehaviorInstanceUpdate>(&value).unwrap();
+        assert_eq!(upd.enabled, Some(true));
+        assert_eq!(unknown, [".metadata.color", ".extra"]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Liveness and readiness checks, for container orchestrators and monitoring
+//!
+//! `/health/live` reports whether bifrost is responsive at all (the service
+//! manager answers, and the resource state can be locked). `/health/ready`
+//! additionally requires all services to be running, and all zigbee2mqtt
+//! backends to be connected with their device lists loaded.
+//!
+//! Both reply with 200 when healthy, and 503 otherwise.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+
+use svc::traits::ServiceState;
+
+use crate::routes::extractor::Json;
+use crate::server::appstate::AppState;
+
+/// Longer waits for the service manager or resource lock count as failure
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+struct Health {
+    ok: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    services: BTreeMap<String, ServiceState>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    backends: BTreeMap<String, bool>,
+}
+
+impl IntoResponse for Health {
+    fn into_response(self) -> axum::response::Response {
+        let status = if self.ok {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// State of all registered services, or `None` if the service manager does
+/// not answer
+async fn services(state: &AppState) -> Option<BTreeMap<String, ServiceState>> {
+    let mut mgr = state.manager();
+    let list = tokio::time::timeout(TIMEOUT, mgr.list()).await.ok()?.ok()?;
+
+    let mut res = BTreeMap::new();
+    for (id, name) in list {
+        let status = tokio::time::timeout(TIMEOUT, mgr.status(id))
+            .await
+            .ok()?
+            .ok()?;
+        res.insert(name, status);
+    }
+    Some(res)
+}
+
+async fn get_live(State(state): State<AppState>) -> Health {
+    let manager = services(&state).await.is_some();
+    let resources = tokio::time::timeout(TIMEOUT, state.res.lock())
+        .await
+        .is_ok();
+
+    Health {
+        ok: manager && resources,
+        services: BTreeMap::new(),
+        backends: BTreeMap::new(),
+    }
+}
+
+async fn get_ready(State(state): State<AppState>) -> Health {
+    let Some(services) = services(&state).await else {
+        return Health {
+            ok: false,
+            services: BTreeMap::new(),
+            backends: BTreeMap::new(),
+        };
+    };
+
+    let config = state.config();
+    let backends: BTreeMap<String, bool> = tokio::time::timeout(TIMEOUT, state.res.lock())
+        .await
+        .map_or_else(
+            |_| BTreeMap::new(),
+            |lock| {
+                config
+                    .z2m
+                    .servers
+                    .keys()
+                    .map(|name| (name.clone(), lock.is_backend_ready(name)))
+                    .collect()
+            },
+        );
+
+    let ok = services.values().all(|st| *st == ServiceState::Running)
+        && backends.len() == config.z2m.servers.len()
+        && backends.values().all(|ready| *ready);
+
+    Health {
+        ok,
+        services,
+        backends,
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/live", get(get_live))
+        .route("/ready", get(get_ready))
+}
@@ -1,30 +1,105 @@
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method};
 use axum::response::{IntoResponse, Response};
-use axum::Router;
+use axum::{middleware, Router};
 use hue::error::HueError;
+use hue::legacy_api::ApiErrorType;
 use hyper::StatusCode;
-use serde_json::{json, Value};
+use serde_json::Value;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
+use crate::config::CorsConfig;
 use crate::error::ApiError;
-use crate::routes::clip::V2Reply;
+use crate::routes::clip::{V2Error, V2Reply};
 use crate::routes::extractor::Json;
+use crate::routes::ratelimit::RateLimiter;
 use crate::server::appstate::AppState;
 
 pub mod api;
+pub mod audit;
 pub mod auth;
+pub mod bifrost;
 pub mod clip;
+pub mod compat;
 pub mod eventstream;
 pub mod extractor;
+pub mod health;
 pub mod licenses;
+pub mod proxy;
+pub mod ratelimit;
+pub mod scope;
+pub mod startup;
+pub mod ws;
+
+/// Error details attached to error responses, so the v1 api can reply in
+/// its own format (see [`api::v1_errors`])
+#[derive(Clone, Debug)]
+pub struct V1ErrorInfo {
+    pub typ: ApiErrorType,
+    pub description: String,
+}
+
+impl ApiError {
+    #[must_use]
+    pub const fn v1_error_type(&self) -> ApiErrorType {
+        match self {
+            Self::HueError(
+                HueError::NotFound(_) | HueError::V1NotFound(_) | HueError::AuxNotFound(_),
+            )
+            | Self::CommandFailed(_)
+            | Self::CommandTimeout => ApiErrorType::ResourceNotAvailable,
+            Self::HueError(HueError::SerdeJson(_)) | Self::SerdeJson(_) | Self::InvalidJson(_) => {
+                ApiErrorType::InvalidJson
+            }
+            Self::HueError(
+                HueError::UpdateUnsupported(_)
+                | HueError::FeatureUnsupported(_, _)
+                | HueError::WrongType(_, _),
+            ) => ApiErrorType::ParameterNotAvailable,
+            Self::HueError(HueError::Full(_)) => ApiErrorType::TooManyItems,
+            Self::HueError(HueError::V1ParameterNotAvailable(_)) => {
+                ApiErrorType::ParameterNotAvailable
+            }
+            Self::HueError(HueError::V1InvalidValue(_, _)) => ApiErrorType::InvalidValue,
+            Self::V1CreateUnsupported(_) | Self::V1DeleteUnsupported(_) => {
+                ApiErrorType::MethodNotAvailable
+            }
+            Self::DeleteDenied(_) => ApiErrorType::ParameterNotModifiable,
+            Self::Forbidden(_) => ApiErrorType::UnauthorizedUser,
+            Self::TooManyRequests => ApiErrorType::TooManyRequests,
+            _ => ApiErrorType::InternalError,
+        }
+    }
+}
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let error_msg = format!("{self}");
         log::error!("Request failed: {error_msg}");
+
+        // invalid entertainment configurations report every problem found
+        let errors = match &self {
+            Self::EntConfigInvalid(errors) => errors
+                .iter()
+                .map(|err| V2Error {
+                    description: err.to_string(),
+                })
+                .collect(),
+            _ => vec![V2Error {
+                description: error_msg.clone(),
+            }],
+        };
         let res = Json(V2Reply::<Value> {
             data: vec![],
-            errors: vec![json!({"description": error_msg}).to_string()],
+            errors,
         });
 
+        let info = V1ErrorInfo {
+            typ: self.v1_error_type(),
+            description: error_msg,
+        };
+
         let status = match self {
             Self::HueError(err) => match err {
                 HueError::FromUtf8Error(_)
@@ -34,34 +109,148 @@ impl IntoResponse for ApiError {
                 | HueError::PackedStructError(_)
                 | HueError::UuidError(_)
                 | HueError::HueEntertainmentBadHeader
-                | HueError::HueZigbeeUnknownFlags(_) => StatusCode::BAD_REQUEST,
-                HueError::UpdateUnsupported(_) | HueError::WrongType(_, _) => {
-                    StatusCode::NOT_ACCEPTABLE
-                }
+                | HueError::HueZigbeeUnknownFlags(_)
+                | HueError::V1ParameterNotAvailable(_)
+                | HueError::V1InvalidValue(_, _) => StatusCode::BAD_REQUEST,
+                HueError::UpdateUnsupported(_)
+                | HueError::FeatureUnsupported(_, _)
+                | HueError::WrongType(_, _) => StatusCode::NOT_ACCEPTABLE,
                 HueError::NotFound(_) | HueError::V1NotFound(_) | HueError::AuxNotFound(_) => {
                     StatusCode::NOT_FOUND
                 }
                 HueError::Full(_) => StatusCode::INSUFFICIENT_STORAGE,
 
-                HueError::IOError(_) | HueError::HueZigbeeDecodeError => {
+                HueError::IOError(_) | HueError::Toml(_) | HueError::HueZigbeeDecodeError => {
                     StatusCode::INTERNAL_SERVER_ERROR
                 }
             },
-            Self::DeleteDenied(_) => StatusCode::FORBIDDEN,
-            Self::V1CreateUnsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            Self::DeleteDenied(_) | Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::EntRecordingInvalid
+            | Self::EntRecordingName(_)
+            | Self::EntSegmentMap(_)
+            | Self::EntConfigInvalid(_)
+            | Self::InvalidLogLevel(_)
+            | Self::InvalidTimeZone(_)
+            | Self::OtaFileName(_)
+            | Self::InvalidJson(_) => StatusCode::BAD_REQUEST,
+            Self::EntRecordingDisabled
+            | Self::EntAreaNotFound(_)
+            | Self::UnknownModel(_)
+            | Self::ImageNotFound(_)
+            | Self::SnapshotNotFound(_)
+            | Self::OtaDisabled
+            | Self::MatterDisabled => StatusCode::NOT_FOUND,
+            Self::ImageType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::ImportFailed(_) | Self::CommandFailed(_) => StatusCode::BAD_GATEWAY,
+            Self::CommandTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            Self::BackendsStarting => StatusCode::SERVICE_UNAVAILABLE,
+            Self::EntAreaConflict(_, _) => StatusCode::CONFLICT,
+            Self::V1CreateUnsupported(_) | Self::V1DeleteUnsupported(_) => {
+                StatusCode::NOT_IMPLEMENTED
+            }
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
-        (status, res).into_response()
+        let mut res = (status, res).into_response();
+        res.extensions_mut().insert(info);
+        res
     }
 }
 
+/// Cors layer for the routes browser-based clients use, as configured in
+/// the `cors` section
+fn cors_layer(conf: &CorsConfig) -> CorsLayer {
+    let origins = if conf.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(conf.allowed_origins.iter().filter_map(|origin| {
+            HeaderValue::from_str(origin)
+                .inspect_err(|_| log::warn!("Ignoring invalid cors origin {origin:?}"))
+                .ok()
+        }))
+    };
+
+    let headers: Vec<HeaderName> = conf
+        .allowed_headers
+        .iter()
+        .filter_map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .inspect_err(|_| log::warn!("Ignoring invalid cors header {name:?}"))
+                .ok()
+        })
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_headers(headers)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .max_age(Duration::from_secs(conf.max_age))
+}
+
 pub fn router(appstate: AppState) -> Router<()> {
-    Router::new()
+    let mut clip = clip::router();
+    let mut events = eventstream::router();
+    let mut admin = bifrost::router().route_layer(middleware::from_fn_with_state(
+        appstate.clone(),
+        auth::admin_only,
+    ));
+
+    if let Some(conf) = &appstate.config().cors {
+        let cors = cors_layer(conf);
+        clip = clip.layer(cors.clone());
+        events = events.layer(cors.clone());
+        admin = admin.layer(cors);
+    }
+
+    let mut router = Router::new()
         .nest("/api", api::router())
         .nest("/auth", auth::router())
         .nest("/licenses", licenses::router())
-        .nest("/clip/v2/resource", clip::router())
-        .nest("/eventstream", eventstream::router())
+        .nest("/clip/v2/resource", clip)
+        .nest("/eventstream", events)
+        .nest("/ws", ws::router())
+        .nest("/bifrost", admin)
+        .nest("/health", health::router())
+        .layer(middleware::from_fn_with_state(
+            appstate.clone(),
+            scope::enforce,
+        ));
+
+    if let Some(conf) = &appstate.config().rate_limit {
+        router = router.layer(middleware::from_fn_with_state(
+            RateLimiter::new(conf.clone()),
+            ratelimit::limit,
+        ));
+    }
+
+    if appstate.config().bifrost.read_only_startup {
+        router = router.layer(middleware::from_fn_with_state(
+            appstate.clone(),
+            startup::read_only,
+        ));
+    }
+
+    router
+        .layer(middleware::from_fn_with_state(
+            appstate.clone(),
+            audit::record,
+        ))
+        .layer(middleware::from_fn(api::v1_errors))
+        .layer(middleware::from_fn(clip::v2_errors))
+        .layer(middleware::from_fn_with_state(
+            appstate.clone(),
+            compat::rewrite_response,
+        ))
+        .layer(middleware::from_fn_with_state(
+            appstate.clone(),
+            proxy::resolve_client,
+        ))
         .with_state(appstate)
 }
@@ -0,0 +1,105 @@
+//! Client addresses for requests made through trusted reverse proxies
+//!
+//! When bifrost runs behind a reverse proxy (Traefik, Caddy, nginx, ..),
+//! every connection comes from the proxy. For proxies listed in
+//! `trusted_proxies`, the real client address is taken from the
+//! `X-Forwarded-For` header, and the protocol from `X-Forwarded-Proto`.
+//! These headers are ignored for all other peers, since any client can send
+//! them.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use ipnet::IpNet;
+
+use crate::routes::auth::header;
+use crate::server::appstate::AppState;
+
+/// The client behind a request, as found by [`resolve_client`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub addr: IpAddr,
+    /// The client connected over https (as far as we can tell)
+    pub https: bool,
+}
+
+/// The listener a request came in on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Listener {
+    /// The listener accepts https connections
+    pub https: bool,
+}
+
+fn is_trusted(proxies: &[IpNet], addr: &IpAddr) -> bool {
+    proxies.iter().any(|net| net.contains(addr))
+}
+
+/// Find the client address and protocol for a request from `peer`.
+///
+/// `X-Forwarded-For` lists the addresses a request passed through, with
+/// each proxy appending the address it received the request from. Walking
+/// it from the right, the first address not belonging to a trusted proxy is
+/// the client.
+#[must_use]
+pub fn client_info(proxies: &[IpNet], peer: SocketAddr, headers: &HeaderMap) -> ClientInfo {
+    let direct = ClientInfo {
+        addr: peer.ip(),
+        https: false,
+    };
+
+    if !is_trusted(proxies, &direct.addr) {
+        return direct;
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|addr| addr.trim().parse().ok())
+        .collect();
+
+    let addr = forwarded
+        .iter()
+        .rev()
+        .find(|addr| !is_trusted(proxies, addr))
+        .or_else(|| forwarded.first())
+        .copied()
+        .unwrap_or(direct.addr);
+
+    let https = header(headers, "x-forwarded-proto").is_some_and(|proto| {
+        proto
+            .split(',')
+            .next()
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+    });
+
+    ClientInfo { addr, https }
+}
+
+/// Attach a [`ClientInfo`] to every request, for the middleware and
+/// handlers that need the real client address
+pub async fn resolve_client(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+
+    if let Some(peer) = peer {
+        let mut info = client_info(&state.config().trusted_proxies, peer, req.headers());
+        info.https |= req
+            .extensions()
+            .get::<Listener>()
+            .is_some_and(|listener| listener.https);
+        req.extensions_mut().insert(info);
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::config::RateLimitConfig;
+use crate::error::ApiError;
+use crate::routes::proxy::ClientInfo;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Token bucket rate limiter, with one bucket per client address
+#[derive(Debug)]
+pub struct RateLimiter {
+    conf: RateLimitConfig,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Forget clients that have been idle for this long
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+    /// Interval between looking for idle clients
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Maximum number of clients to track. When full, the client that was
+    /// seen least recently is forgotten.
+    const MAX_CLIENTS: usize = 4096;
+
+    /// Create a rate limiter, and a task that forgets idle clients. The task
+    /// ends when the rate limiter is dropped.
+    #[must_use]
+    pub fn new(conf: RateLimitConfig) -> Arc<Self> {
+        let limiter = Arc::new(Self {
+            conf,
+            buckets: Mutex::new(HashMap::new()),
+        });
+
+        let weak = Arc::downgrade(&limiter);
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(Self::PRUNE_INTERVAL);
+            loop {
+                timer.tick().await;
+                let Some(limiter) = weak.upgrade() else {
+                    break;
+                };
+                limiter.prune(Instant::now());
+            }
+        });
+
+        limiter
+    }
+
+    /// Forget clients that have been idle since before `now - IDLE_TIMEOUT`
+    fn prune(&self, now: Instant) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        buckets.retain(|_, bucket| now - bucket.last < Self::IDLE_TIMEOUT);
+    }
+
+    /// Take a token for `client`, or return the time until one is available
+    fn take(&self, client: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let burst = f64::from(self.conf.burst.max(1));
+        let rate = self.conf.requests_per_second;
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if buckets.len() >= Self::MAX_CLIENTS && !buckets.contains_key(&client) {
+            let oldest = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.last)
+                .map(|(addr, _)| *addr);
+            if let Some(addr) = oldest {
+                buckets.remove(&addr);
+            }
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            last: now,
+        });
+
+        bucket.tokens = (now - bucket.last)
+            .as_secs_f64()
+            .mul_add(rate, bucket.tokens)
+            .min(burst);
+        bucket.last = now;
+
+        let res = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::try_from_secs_f64((1.0 - bucket.tokens) / rate).unwrap_or(Duration::MAX))
+        };
+        drop(buckets);
+
+        res
+    }
+}
+
+/// Limit changes made through the hue apis (v1 and v2)
+///
+/// Clients are identified by address (as resolved through trusted proxies),
+/// since application keys are chosen by the client, and could be rotated to
+/// get a fresh bucket for each request.
+pub async fn limit(State(limiter): State<Arc<RateLimiter>>, req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    let is_hue_api = path.starts_with("/api/") || path.starts_with("/clip/v2/");
+
+    if !is_hue_api || matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+
+    // requests without client info (which should not happen) share a bucket
+    let client = req
+        .extensions()
+        .get::<ClientInfo>()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |info| info.addr);
+
+    if let Err(wait) = limiter.take(client) {
+        log::warn!("Rate limit exceeded for {client}: {} {path}", req.method());
+
+        let mut res = ApiError::TooManyRequests.into_response();
+        let secs = wait.as_secs().saturating_add(1);
+        res.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(secs));
+        return res;
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    use crate::config::RateLimitConfig;
+    use crate::routes::ratelimit::RateLimiter;
+
+    fn limiter(burst: u32) -> std::sync::Arc<RateLimiter> {
+        RateLimiter::new(RateLimitConfig {
+            requests_per_second: 1.0,
+            burst,
+        })
+    }
+
+    fn addr(n: u32) -> IpAddr {
+        IpAddr::V4(n.into())
+    }
+
+    #[tokio::test]
+    async fn bucket_per_address() {
+        let limiter = limiter(2);
+
+        assert!(limiter.take(addr(1)).is_ok());
+        assert!(limiter.take(addr(1)).is_ok());
+        assert!(limiter.take(addr(1)).is_err());
+
+        assert!(limiter.take(addr(2)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn client_limit() {
+        let limiter = limiter(1);
+
+        let max = u32::try_from(RateLimiter::MAX_CLIENTS).unwrap();
+        for n in 0..=max {
+            assert!(limiter.take(addr(n)).is_ok());
+        }
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), RateLimiter::MAX_CLIENTS);
+        assert!(buckets.contains_key(&addr(max)));
+        drop(buckets);
+    }
+
+    #[tokio::test]
+    async fn prune_idle() {
+        let limiter = limiter(1);
+        assert!(limiter.take(addr(1)).is_ok());
+
+        limiter.prune(Instant::now() + Duration::from_secs(30));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+
+        limiter.prune(Instant::now() + RateLimiter::IDLE_TIMEOUT);
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+}
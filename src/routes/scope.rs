@@ -0,0 +1,314 @@
+//! Restrictions for api keys, as configured in the `api_keys` section
+//!
+//! Bifrost has no real authentication, so these restrictions only apply to
+//! clients that identify themselves with a restricted key. To keep clients
+//! from getting around them by making up a key, unknown keys are refused
+//! when any key is restricted (see [`ApiKeysConfig::deny_unknown`]).
+
+use std::collections::BTreeSet;
+
+use axum::extract::{Request, State};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use uuid::Uuid;
+
+use hue::api::{RType, Resource, ResourceLink, Room};
+
+use crate::config::{ApiKeyConfig, ApiKeysConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::resource::Resources;
+use crate::routes::auth::app_key;
+use crate::server::appstate::AppState;
+
+/// Find the resource targeted by a request path, if any
+fn target(res: &Resources, path: &str) -> ApiResult<ResourceLink> {
+    if let Some(rest) = path.strip_prefix("/clip/v2/resource/") {
+        let mut parts = rest.split('/');
+        let rtype = parts.next().unwrap_or_default();
+        let rtype: RType = serde_json::from_value(rtype.into())
+            .map_err(|_| ApiError::Forbidden(format!("unknown resource type {rtype:?}")))?;
+        let Some(id) = parts.next() else {
+            return Err(ApiError::Forbidden(
+                "key is limited to certain rooms, and cannot create resources".to_string(),
+            ));
+        };
+        return Ok(rtype.link_to(id.parse()?));
+    }
+
+    /* v1 api, e.g. /api/<key>/lights/<id>/state */
+    let parts: Vec<&str> = path.split('/').skip(3).collect();
+    if let ["lights" | "groups" | "scenes", id, ..] = parts.as_slice() {
+        let id: u32 = id
+            .parse()
+            .map_err(|_| ApiError::Forbidden(format!("invalid id {id:?}")))?;
+        if parts[0] == "groups" && id == 0 {
+            return Err(ApiError::Forbidden(
+                "key is limited to certain rooms, and cannot control all lights".to_string(),
+            ));
+        }
+        let uuid = res.from_id_v1(id)?;
+        return Ok(res.get_resource_by_id(&uuid)?.obj.rtype().link_to(uuid));
+    }
+
+    Err(ApiError::Forbidden(format!(
+        "key is limited to certain rooms, and cannot change {path}"
+    )))
+}
+
+/// Rooms containing the device
+fn device_rooms(res: &Resources, device: &ResourceLink) -> BTreeSet<Uuid> {
+    res.get_resources_by_type(RType::Room)
+        .into_iter()
+        .filter(|rec| matches!(&rec.obj, Resource::Room(room) if room.children.contains(device)))
+        .map(|rec| rec.id)
+        .collect()
+}
+
+/// Rooms that are affected by changes to a resource, or `None` if the
+/// resource is not part of any room
+fn rooms_of(res: &Resources, link: &ResourceLink) -> ApiResult<Option<BTreeSet<Uuid>>> {
+    let rooms = match res.get_resource_by_id(&link.rid)?.obj {
+        Resource::Room(_) => BTreeSet::from([link.rid]),
+        Resource::Device(_) => device_rooms(res, link),
+        Resource::Light(light) => device_rooms(res, &light.owner),
+        Resource::GroupedLight(glight) => return rooms_of(res, &glight.owner),
+        Resource::Scene(scene) => return rooms_of(res, &scene.group),
+        Resource::Zone(zone) => {
+            let mut rooms = BTreeSet::new();
+            for child in &zone.children {
+                match rooms_of(res, child)? {
+                    Some(child_rooms) => rooms.extend(child_rooms),
+                    None => return Ok(None),
+                }
+            }
+            rooms
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(rooms).filter(|rooms| !rooms.is_empty()))
+}
+
+/// True if the request changes an entertainment area. In the v1 api,
+/// entertainment areas are groups, and streaming is started by updating the
+/// group, so the path alone is not enough to tell.
+fn is_entertainment(res: &Resources, path: &str) -> bool {
+    if path.contains("entertainment") {
+        return true;
+    }
+
+    let parts: Vec<&str> = path.split('/').skip(3).collect();
+    let ["groups", id, ..] = parts.as_slice() else {
+        return false;
+    };
+
+    id.parse()
+        .ok()
+        .and_then(|id| res.from_id_v1(id).ok())
+        .and_then(|uuid| res.get_resource_by_id(&uuid).ok())
+        .is_some_and(|rec| rec.obj.rtype() == RType::EntertainmentConfiguration)
+}
+
+fn check(conf: &ApiKeyConfig, res: &Resources, path: &str) -> ApiResult<()> {
+    if conf.read_only {
+        return Err(ApiError::Forbidden("key is read-only".to_string()));
+    }
+
+    if !conf.entertainment && is_entertainment(res, path) {
+        return Err(ApiError::Forbidden(
+            "key is not allowed to use entertainment".to_string(),
+        ));
+    }
+
+    if conf.rooms.is_empty() {
+        return Ok(());
+    }
+
+    let allowed: BTreeSet<Uuid> = res
+        .get_resources_by_type(RType::Room)
+        .into_iter()
+        .filter_map(|rec| {
+            let room: Room = rec.obj.try_into().ok()?;
+            conf.rooms.contains(&room.metadata.name).then_some(rec.id)
+        })
+        .collect();
+
+    let link = target(res, path)?;
+    match rooms_of(res, &link)? {
+        Some(rooms) if rooms.is_subset(&allowed) => Ok(()),
+        _ => Err(ApiError::Forbidden(format!(
+            "key is limited to certain rooms, and cannot change {}",
+            res.describe_link(&link)
+        ))),
+    }
+}
+
+/// Find the configuration of the api key used for a change, if it has one.
+/// Unknown keys (or no key at all) are refused if `deny_unknown` is on,
+/// except for pairing new apps.
+fn key_config<'a>(
+    keys: &'a ApiKeysConfig,
+    key: Option<&str>,
+    path: &str,
+) -> ApiResult<Option<&'a ApiKeyConfig>> {
+    if let Some(conf) = key.and_then(|key| keys.get(key)) {
+        return Ok(Some(conf));
+    }
+
+    let pairing = key.is_none() && path.trim_end_matches('/') == "/api";
+    if keys.deny_unknown() && !pairing {
+        return Err(ApiError::Forbidden(
+            "unknown api key (only keys in the api_keys section are allowed)".to_string(),
+        ));
+    }
+
+    Ok(None)
+}
+
+/// Reject changes not allowed for the api key used
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+
+    let config = state.config();
+    let path = req.uri().path();
+    let key = app_key(req.headers(), path);
+    let conf = match key_config(&config.api_keys, key.as_deref(), path) {
+        Ok(Some(conf)) => conf,
+        Ok(None) => return next.run(req).await,
+        Err(err) => return err.into_response(),
+    };
+
+    if conf.is_restricted() {
+        let result = check(conf, &*state.res.lock().await, path);
+        if let Err(err) = result {
+            return err.into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreeset;
+
+    use hue::api::{
+        EntertainmentConfiguration, EntertainmentConfigurationLocations,
+        EntertainmentConfigurationMetadata, EntertainmentConfigurationStatus,
+        EntertainmentConfigurationStreamProxy, EntertainmentConfigurationStreamProxyMode,
+        EntertainmentConfigurationType, RType, Resource, Room, RoomArchetype, RoomMetadata,
+    };
+    use hue::version::SwVersion;
+
+    use crate::config::{ApiKeyConfig, ApiKeysConfig, EntertainmentConfig};
+    use crate::model::entertainment::EntertainmentSettings;
+    use crate::model::state::State;
+    use crate::resource::Resources;
+    use crate::routes::scope::{check, key_config};
+
+    #[test]
+    fn v1_stream_needs_entertainment() {
+        let mut res = Resources::new(
+            SwVersion::default(),
+            State::new(),
+            EntertainmentSettings::from_config(&EntertainmentConfig::default()),
+        );
+
+        let link_room = RType::Room.deterministic("living");
+        let link_ent = RType::EntertainmentConfiguration.deterministic("tv");
+
+        let room = Room {
+            children: btreeset![],
+            metadata: RoomMetadata::new(RoomArchetype::LivingRoom, "Living room"),
+            services: btreeset![],
+        };
+        let ent = EntertainmentConfiguration {
+            name: "TV area".to_string(),
+            configuration_type: EntertainmentConfigurationType::Screen,
+            metadata: EntertainmentConfigurationMetadata {
+                name: "TV area".to_string(),
+            },
+            status: EntertainmentConfigurationStatus::Inactive,
+            stream_proxy: EntertainmentConfigurationStreamProxy {
+                mode: EntertainmentConfigurationStreamProxyMode::Auto,
+                node: RType::Entertainment.deterministic("bridge"),
+            },
+            locations: EntertainmentConfigurationLocations {
+                service_locations: vec![],
+            },
+            light_services: vec![],
+            channels: vec![],
+            active_streamer: None,
+        };
+        res.add(&link_room, Resource::Room(room)).unwrap();
+        res.add(&link_ent, Resource::EntertainmentConfiguration(ent))
+            .unwrap();
+
+        let conf = ApiKeyConfig {
+            read_only: false,
+            rooms: vec![],
+            entertainment: false,
+            admin: false,
+        };
+
+        let room_id = res.get_id_v1_index(link_room.rid).unwrap();
+        let ent_id = res.get_id_v1_index(link_ent.rid).unwrap();
+
+        assert!(check(&conf, &res, &format!("/api/key/groups/{room_id}/action")).is_ok());
+        assert!(check(&conf, &res, &format!("/api/key/groups/{ent_id}")).is_err());
+        let path = format!(
+            "/clip/v2/resource/entertainment_configuration/{}",
+            link_ent.rid
+        );
+        assert!(check(&conf, &res, &path).is_err());
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let guest = ApiKeyConfig {
+            read_only: true,
+            rooms: vec![],
+            entertainment: true,
+            admin: false,
+        };
+        let mut keys = ApiKeysConfig::default();
+        keys.keys.insert("guest".to_string(), guest);
+
+        // a made-up key does not get around the restrictions of a real one
+        let path = "/api/made-up/lights/1/state";
+        assert!(keys.deny_unknown());
+        assert!(key_config(&keys, Some("made-up"), path).is_err());
+        assert!(key_config(&keys, None, "/clip/v2/resource/light").is_err());
+        assert!(key_config(&keys, Some("guest"), path).unwrap().is_some());
+
+        // pairing still works, so new keys can be created
+        assert!(key_config(&keys, None, "/api").unwrap().is_none());
+
+        let keys: ApiKeysConfig =
+            serde_yml::from_str("deny_unknown: false\nguest:\n  read_only: true\n").unwrap();
+        assert!(keys.get("guest").is_some_and(|conf| conf.read_only));
+        assert!(key_config(&keys, Some("made-up"), path).unwrap().is_none());
+    }
+
+    #[test]
+    fn unknown_keys_allowed_without_restrictions() {
+        let admin = ApiKeyConfig {
+            read_only: false,
+            rooms: vec![],
+            entertainment: true,
+            admin: true,
+        };
+        let mut keys = ApiKeysConfig::default();
+        keys.keys.insert("admin".to_string(), admin);
+
+        assert!(!keys.deny_unknown());
+        assert!(
+            key_config(&keys, Some("other"), "/api/other/lights/1/state")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
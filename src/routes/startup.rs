@@ -0,0 +1,29 @@
+use axum::extract::{Request, State};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::error::ApiError;
+use crate::server::appstate::AppState;
+
+/// Reject changes made through the hue apis (v1 and v2) until every backend
+/// has connected once, so clients do not change state that cannot be sent
+/// anywhere yet. Reading the saved state works all along.
+pub async fn read_only(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    let is_hue_api = path.starts_with("/api/") || path.starts_with("/clip/v2/");
+
+    if !is_hue_api || matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+
+    if !state.res.lock().await.backends_started() {
+        log::warn!(
+            "Rejecting {} {path} while backends are starting",
+            req.method()
+        );
+        return ApiError::BackendsStarting.into_response();
+    }
+
+    next.run(req).await
+}
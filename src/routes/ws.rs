@@ -0,0 +1,124 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{RawQuery, State};
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use hue::api::RType;
+
+use crate::error::ApiResult;
+use crate::server::appstate::AppState;
+use crate::server::hueevents;
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Close connection if nothing is heard from the client for this long
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Sent by the client to change which resource types it receives events
+/// for. An empty list means all types.
+#[derive(Debug, Default, Deserialize)]
+struct Subscription {
+    #[serde(default)]
+    types: BTreeSet<RType>,
+}
+
+impl Subscription {
+    /// Parse query string like `types=light,grouped_light`
+    fn from_query(query: Option<&str>) -> Self {
+        let types = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+            .filter(|(key, _)| key == "types")
+            .flat_map(|(_, value)| {
+                value
+                    .split(',')
+                    .filter_map(|rtype| {
+                        serde_json::from_value(Value::String(rtype.trim().to_string())).ok()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        Self { types }
+    }
+}
+
+async fn event_stream(
+    mut socket: WebSocket,
+    state: AppState,
+    mut sub: Subscription,
+) -> ApiResult<()> {
+    let mut chan = state.res.lock().await.hue_event_stream().subscribe();
+
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_seen = Instant::now();
+
+    loop {
+        select! {
+            _ = ping.tick() => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    log::debug!("Websocket client timed out");
+                    break;
+                }
+                socket.send(Message::Ping(vec![].into())).await?;
+            }
+
+            msg = socket.recv() => {
+                let Some(msg) = msg else { break };
+                last_seen = Instant::now();
+                match msg? {
+                    Message::Text(text) => match serde_json::from_str(&text) {
+                        Ok(new_sub) => sub = new_sub,
+                        Err(err) => log::warn!("Invalid websocket subscription: {err}"),
+                    },
+                    Message::Close(_) => break,
+                    Message::Ping(_) | Message::Pong(_) | Message::Binary(_) => {}
+                }
+            }
+
+            record = chan.recv() => {
+                let record = match record {
+                    Ok(record) => record,
+                    Err(RecvError::Lagged(num)) => {
+                        log::warn!("Websocket client lagging: dropped {num} events");
+                        continue;
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+
+                if let Some(block) = hueevents::filter_types(record.block, &sub.types) {
+                    let json = serde_json::to_string(&[block])?;
+                    socket.send(Message::Text(json.into())).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_clip_v2(
+    ws: WebSocketUpgrade,
+    RawQuery(query): RawQuery,
+    State(state): State<AppState>,
+) -> Response {
+    let sub = Subscription::from_query(query.as_deref());
+
+    ws.on_upgrade(|socket| async move {
+        if let Err(err) = event_stream(socket, state, sub).await {
+            log::debug!("Websocket event stream closed: {err}");
+        }
+    })
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/clip/v2", get(get_clip_v2))
+}
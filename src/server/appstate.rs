@@ -3,17 +3,26 @@ use std::fs::{self, File};
 use std::sync::Arc;
 
 use camino::Utf8Path;
-use chrono::Utc;
-use tokio::sync::Mutex;
+use chrono::{Duration, Utc};
+use tokio::sync::{watch, Mutex};
 
-use hue::legacy_api::{ApiConfig, ApiShortConfig, Whitelist};
+use hue::api::TimeZone;
+use hue::legacy_api::{ApiConfig, ApiShortConfig, SoftwareUpdate2, Whitelist};
+use hue::scene_icons;
+use hue::version::SwVersion;
 use svc::manager::SvmClient;
 
 use crate::config::AppConfig;
-use crate::error::ApiResult;
+use crate::error::{ApiError, ApiResult};
+use crate::model::automation::AutomationStore;
+use crate::model::entertainment::EntertainmentSettings;
+use crate::model::images::ImageStore;
+use crate::model::snapshot::SnapshotStore;
 use crate::model::state::{State, StateVersion};
 use crate::resource::Resources;
+use crate::server::audit::AuditLog;
 use crate::server::certificate;
+use crate::server::history::EventHistory;
 use crate::server::updater::VersionUpdater;
 
 #[derive(Clone)]
@@ -21,6 +30,12 @@ pub struct AppState {
     conf: Arc<AppConfig>,
     upd: Arc<Mutex<VersionUpdater>>,
     svm: SvmClient,
+    audit: Arc<Mutex<AuditLog>>,
+    history: Arc<Mutex<EventHistory>>,
+    swupdate: Arc<Mutex<SoftwareUpdate2>>,
+    devicetypes: Arc<Mutex<HashMap<String, String>>>,
+    snapshots: Arc<Mutex<SnapshotStore>>,
+    away: Arc<watch::Sender<bool>>,
     pub res: Arc<Mutex<Resources>>,
 }
 
@@ -30,48 +45,113 @@ impl AppState {
 
         let certpath = Utf8Path::new(certfile);
         if certpath.is_file() {
-            certificate::check_certificate(certpath, config.bridge.mac)?;
+            certificate::check_certificate(certpath, &config.bridge.bridge_id())?;
+        } else if config.bifrost.key_file.is_some() {
+            return Err(ApiError::CertificateMissing(certfile.clone()));
         } else {
             log::warn!("Missing certificate file [{certfile}], generating..");
-            certificate::generate_and_save(certpath, config.bridge.mac)?;
+            certificate::generate_and_save(certpath, &config.bridge.bridge_id())?;
         }
 
         let mut res;
-        let upd = Arc::new(Mutex::new(VersionUpdater::new()));
+        let upd = match config.bridge.swversion {
+            Some(version) => {
+                let name = config.bridge.apiversion.clone().unwrap_or_default();
+                VersionUpdater::with_fixed_version(SwVersion::new(version, name))
+            }
+            None => VersionUpdater::new(),
+        };
+        let upd = Arc::new(Mutex::new(upd));
         let swversion = upd.lock().await.get().await.clone();
 
+        let entm = EntertainmentSettings::from_config(&config.entertainment);
+
         if let Ok(fd) = File::open(&config.bifrost.state_file) {
             log::debug!("Existing state file found, loading..");
             let yaml = serde_yml::from_reader(fd)?;
-            let state = match State::version(&yaml)? {
-                StateVersion::V0 => {
-                    log::info!("Detected state file version 0. Upgrading to new version..");
-                    let backup_path = &config.bifrost.state_file.with_extension("v0.bak");
-                    fs::rename(&config.bifrost.state_file, backup_path)?;
-                    log::info!("  ..saved old state file as {backup_path}");
-                    State::from_v0(yaml)?
-                }
-                StateVersion::V1 => {
-                    log::info!("Detected state file version 1. Loading..");
-                    State::from_v1(yaml)?
-                }
-            };
-            res = Resources::new(swversion, state);
+            let loaded = State::load(yaml)?;
+            let state_file = &config.bifrost.state_file;
+
+            /* keep a copy of the original file, and save the upgraded state
+             * right away, so the old file is never loaded again. The backup
+             * is timestamped, so an earlier backup is never overwritten */
+            if loaded.is_changed() {
+                let backup_path = state_file.with_extension(format!(
+                    "v{}.{}.bak",
+                    loaded.version as u32,
+                    Utc::now().format("%Y%m%d-%H%M%S")
+                ));
+                fs::copy(state_file, &backup_path)?;
+                log::info!("Saved original state file as {backup_path}");
+
+                fs::write(state_file, serde_yml::to_string(&loaded.state)?)?;
+                log::info!(
+                    "Saved state file as version {} ({} resources dropped)",
+                    StateVersion::CURRENT as u32,
+                    loaded.dropped.len()
+                );
+            }
+
+            res = Resources::new(swversion, loaded.state, entm);
         } else {
             log::debug!("No state file found, initializing..");
-            res = Resources::new(swversion, State::new());
-            res.init(&hue::bridge_id(config.bridge.mac))?;
+            res = Resources::new(swversion, State::new(), entm);
+            res.init(&config.bridge.bridge_id())?;
+        }
+
+        /* the configured time zone applies, unless one has been set through
+         * the api (the host time zone is often wrong in containers) */
+        if res.time_zone() == TimeZone::best_guess() {
+            res.set_time_zone(&config.bridge.timezone)?;
         }
 
+        res.set_automations(AutomationStore::load(&config.bifrost.automation_file));
         res.reset_all_streaming()?;
 
+        let images = ImageStore::new(&config.bifrost.image_dir);
+        res.add_public_images(scene_icons::ALL.iter().map(|(id, _)| *id))?;
+        res.add_public_images(images.list().into_iter().map(|(id, _)| id))?;
+
+        // a resource that could not be loaded takes everything that belongs
+        // to it along, so only do this when asked to
+        let report = res.collect_garbage(!config.bifrost.gc_on_startup)?;
+        if report.dry_run && !report.is_empty() {
+            log::warn!(
+                "Consistency check: would delete {} and repair {} resources (enable bifrost.gc_on_startup, or use the gc api)",
+                report.deleted.len(),
+                report.repaired.len()
+            );
+        } else if !report.is_empty() {
+            log::warn!(
+                "Consistency check: deleted {} and repaired {} resources",
+                report.deleted.len(),
+                report.repaired.len()
+            );
+        }
+
+        let audit = AuditLog::open(&config.bifrost.audit_file, config.bifrost.audit_max_entries)?;
+
+        let max_age = Duration::hours(i64::from(config.history.hours));
+        let history = EventHistory::open(config.history.file.as_deref(), max_age)?;
+
+        let away = config.vacation.as_ref().is_some_and(|vac| vac.away);
+
         let conf = Arc::new(config);
         let res = Arc::new(Mutex::new(res));
+        let audit = Arc::new(Mutex::new(audit));
+        let history = Arc::new(Mutex::new(history));
+        let swupdate = Arc::new(Mutex::new(SoftwareUpdate2::new()));
 
         Ok(Self {
             conf,
             upd,
             svm,
+            audit,
+            history,
+            swupdate,
+            devicetypes: Arc::new(Mutex::new(HashMap::new())),
+            snapshots: Arc::new(Mutex::new(SnapshotStore::new())),
+            away: Arc::new(watch::Sender::new(away)),
             res,
         })
     }
@@ -91,20 +171,75 @@ impl AppState {
         self.svm.clone()
     }
 
+    #[must_use]
+    pub fn audit(&self) -> Arc<Mutex<AuditLog>> {
+        self.audit.clone()
+    }
+
+    #[must_use]
+    pub fn history(&self) -> Arc<Mutex<EventHistory>> {
+        self.history.clone()
+    }
+
+    #[must_use]
+    pub fn snapshots(&self) -> Arc<Mutex<SnapshotStore>> {
+        self.snapshots.clone()
+    }
+
+    /// Away mode (nobody home), which starts the presence simulation
+    #[must_use]
+    pub fn away(&self) -> Arc<watch::Sender<bool>> {
+        self.away.clone()
+    }
+
+    #[must_use]
+    pub fn swupdate(&self) -> Arc<Mutex<SoftwareUpdate2>> {
+        self.swupdate.clone()
+    }
+
+    /// Remember the devicetype an app paired with, for [`crate::config::CompatRule`]
+    pub async fn register_devicetype(&self, username: String, devicetype: String) {
+        self.devicetypes.lock().await.insert(username, devicetype);
+    }
+
+    /// Devicetype of the app using `username`, if it paired since startup
+    pub async fn devicetype(&self, username: &str) -> Option<String> {
+        self.devicetypes.lock().await.get(username).cloned()
+    }
+
     #[must_use]
     pub async fn api_short_config(&self) -> ApiShortConfig {
         let mac = self.conf.bridge.mac;
-        ApiShortConfig::from_mac_and_version(mac, self.upd.lock().await.get().await)
+        let mut config = ApiShortConfig {
+            name: self.conf.bridge.name.clone(),
+            ..ApiShortConfig::from_mac_and_version(mac, self.upd.lock().await.get().await)
+        };
+        config.bridgeid = self.conf.bridge.bridge_id().to_uppercase();
+        if let Some(apiversion) = &self.conf.bridge.apiversion {
+            config.apiversion.clone_from(apiversion);
+        }
+        config
+    }
+
+    /// Current state of the emulated software update, moved along to
+    /// account for the time passed since it was last looked at
+    pub async fn swupdate_status(&self) -> SoftwareUpdate2 {
+        let mut lock = self.swupdate.lock().await;
+        while lock.advance(Utc::now()) {
+            log::info!("Emulated bridge software update is now {:?}", lock.state());
+        }
+        lock.clone()
     }
 
     #[must_use]
-    pub async fn api_config(&self, username: String) -> ApiConfig {
+    pub async fn api_config(&self, username: String, res: &Resources) -> ApiConfig {
         ApiConfig {
             short_config: self.api_short_config().await,
             ipaddress: self.conf.bridge.ipaddress,
             netmask: self.conf.bridge.netmask,
             gateway: self.conf.bridge.gateway,
-            timezone: self.conf.bridge.timezone.clone(),
+            timezone: res.time_zone().time_zone,
+            swupdate2: self.swupdate_status().await,
             whitelist: HashMap::from([(
                 username,
                 Whitelist {
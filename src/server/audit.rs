@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::IpAddr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::ApiResult;
+
+/// A single mutating api request
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Application key (username) that made the request, if known
+    pub key: Option<String>,
+    /// Client address (as reported by trusted proxies, if any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<IpAddr>,
+    /// The request was made over https (as reported by trusted proxies, if
+    /// any)
+    #[serde(default)]
+    pub https: bool,
+    pub user_agent: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// Request body, if it was json
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub body: Value,
+}
+
+/// Bounded log of api changes, kept in memory and in a json-lines file
+#[derive(Debug)]
+pub struct AuditLog {
+    path: Utf8PathBuf,
+    max_entries: usize,
+    entries: VecDeque<AuditEntry>,
+    /// Entries appended to the file since it was last compacted
+    appended: usize,
+}
+
+impl AuditLog {
+    pub fn open(path: &Utf8Path, max_entries: usize) -> ApiResult<Self> {
+        let mut entries = VecDeque::with_capacity(max_entries);
+
+        if let Ok(fd) = File::open(path) {
+            for line in BufReader::new(fd).lines() {
+                match serde_json::from_str(&line?) {
+                    Ok(entry) => entries.push_back(entry),
+                    Err(err) => log::warn!("Skipping invalid audit log entry: {err}"),
+                }
+                if entries.len() > max_entries {
+                    entries.pop_front();
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            max_entries,
+            entries,
+            appended: 0,
+        })
+    }
+
+    /// Rewrite the file with only the entries kept in memory
+    fn compact(&mut self) -> ApiResult<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut fd = File::create(&tmp)?;
+        for entry in &self.entries {
+            writeln!(fd, "{}", serde_json::to_string(entry)?)?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        self.appended = 0;
+        Ok(())
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) -> ApiResult<()> {
+        if self.max_entries == 0 {
+            return Ok(());
+        }
+
+        let line = serde_json::to_string(&entry)?;
+
+        if self.entries.len() == self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+
+        /* append to the file, and compact it once it holds twice the
+         * number of entries we need to keep */
+        if self.appended >= self.max_entries {
+            self.compact()
+        } else {
+            let mut fd = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            writeln!(fd, "{line}")?;
+            self.appended += 1;
+            Ok(())
+        }
+    }
+
+    /// All entries, newest first
+    pub fn entries(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter().rev()
+    }
+}
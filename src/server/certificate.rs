@@ -8,7 +8,6 @@ use der::oid::db::rfc4519::COMMON_NAME;
 use der::oid::db::rfc5280::ID_KP_SERVER_AUTH;
 use der::pem::LineEnding;
 use der::{DateTime, EncodePem};
-use mac_address::MacAddress;
 use p256::ecdsa::DerSignature;
 use p256::pkcs8::EncodePrivateKey;
 use rsa::pkcs8::SubjectPublicKeyInfoRef;
@@ -49,17 +48,17 @@ use crate::error::{ApiError, ApiResult};
 ///    acting as a kind of CA certificate for the instance certificate.
 ///    This also seems to have no negative impact.
 ///
-pub fn generate(secret_key: &p256::SecretKey, mac: MacAddress) -> ApiResult<CertificateInner> {
+pub fn generate(secret_key: &p256::SecretKey, bridge_id: &str) -> ApiResult<CertificateInner> {
     let public_key = secret_key.public_key();
 
-    let bridge_id = hue::bridge_id(mac);
-
     let subject = Name::from_str(&format!("CN={bridge_id},O=Philips Hue,C=NL"))?;
 
     /* self-signed certificate, so subject == issuer */
     let issuer = subject.clone();
 
-    let serial_number = SerialNumber::new(&hue::bridge_id_raw(mac))?;
+    let serial_number = SerialNumber::new(
+        &hex::decode(bridge_id).map_err(|_| ApiError::InvalidBridgeId(bridge_id.to_string()))?,
+    )?;
 
     /* Philips Hue seems to start their certificates at the beginning of 2017.. */
     let not_before = GeneralizedTime::from_date_time(DateTime::new(2017, 1, 1, 0, 0, 0)?).into();
@@ -141,25 +140,24 @@ pub fn extract_common_name(rdr: impl Read) -> ApiResult<Option<String>> {
     Ok(None)
 }
 
-pub fn generate_and_save(certpath: &Utf8Path, mac: MacAddress) -> ApiResult<()> {
+pub fn generate_and_save(certpath: &Utf8Path, bridge_id: &str) -> ApiResult<()> {
     let secret_key = p256::SecretKey::random(&mut OsRng);
-    let cert = generate(&secret_key, mac)?;
+    let cert = generate(&secret_key, bridge_id)?;
     let mut fd = File::create(certpath)?;
     fd.write_all(secret_key.to_pkcs8_pem(LineEnding::LF)?.as_bytes())?;
     fd.write_all(cert.to_pem(LineEnding::LF)?.as_bytes())?;
     Ok(())
 }
 
-pub fn check_certificate(certpath: &Utf8Path, mac: MacAddress) -> ApiResult<()> {
+pub fn check_certificate(certpath: &Utf8Path, bridge_id: &str) -> ApiResult<()> {
     let cn = extract_common_name(File::open(certpath)?)?;
-    let id = hue::bridge_id(mac);
     match cn {
         Some(cn) => {
-            if cn == id {
-                log::debug!("Found existing certificate for bridge id [{id}]");
+            if cn == bridge_id {
+                log::debug!("Found existing certificate for bridge id [{bridge_id}]");
             } else {
                 log::warn!("Certificate found, but common name (CN) does not match!");
-                log::warn!("  [{id}] (expected)");
+                log::warn!("  [{bridge_id}] (expected)");
                 log::warn!("  [{cn}] {certpath}");
                 return Ok(());
             }
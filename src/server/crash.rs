@@ -0,0 +1,184 @@
+//! Crash reports: a diagnostic dump, written when bifrost panics
+//!
+//! A panic hook writes the panic message and backtrace to
+//! `bifrost.crash_file`, together with a snapshot of the bridge state:
+//! service states, backend connections, resource counts and the most recent
+//! hue events. Attaching this file to a bug report usually says more than
+//! the log output alone.
+//!
+//! The panic hook cannot wait for locks or talk to the service manager, so
+//! a snapshot is refreshed in the background, and the hook uses the fresh
+//! resource state only if it is not locked at the time of the panic.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use hue::api::RType;
+use hue::event::EventBlock;
+use svc::manager::SvmClient;
+use svc::traits::ServiceState;
+
+use crate::backend::BackendStatus;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// Number of hue events included in a crash report
+const RECENT_EVENTS: usize = 20;
+
+/// Interval between refreshes of the diagnostic snapshot
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Serialize)]
+struct RecentEvent {
+    id: String,
+    block: EventBlock,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct Diagnostics {
+    services: BTreeMap<String, ServiceState>,
+    services_updated: Option<DateTime<Utc>>,
+    backends: BTreeMap<String, BackendStatus>,
+    resources: BTreeMap<RType, usize>,
+    resources_updated: Option<DateTime<Utc>>,
+    recent_events: Vec<RecentEvent>,
+}
+
+impl Diagnostics {
+    fn update_resources(&mut self, res: &Resources) {
+        self.backends.clone_from(res.backend_status());
+        self.resources = res.resource_counts();
+        self.resources_updated = Some(Utc::now());
+        self.recent_events = res
+            .hue_event_stream()
+            .recent_events(RECENT_EVENTS)
+            .into_iter()
+            .map(|record| RecentEvent {
+                id: record.id(),
+                block: record.block,
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CrashReport {
+    timestamp: DateTime<Utc>,
+    version: &'static str,
+    thread: Option<String>,
+    message: String,
+    location: Option<String>,
+    backtrace: String,
+    diagnostics: Diagnostics,
+}
+
+impl CrashReport {
+    fn new(payload: &(dyn Any + Send), location: Option<String>, diagnostics: Diagnostics) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "<unknown panic payload>".to_string());
+
+        Self {
+            timestamp: Utc::now(),
+            version: env!("CARGO_PKG_VERSION"),
+            thread: std::thread::current().name().map(ToString::to_string),
+            message,
+            location,
+            backtrace: Backtrace::force_capture().to_string(),
+            diagnostics,
+        }
+    }
+}
+
+pub struct CrashReporter {
+    path: Utf8PathBuf,
+    res: Arc<Mutex<Resources>>,
+    svm: SvmClient,
+    snapshot: Arc<std::sync::Mutex<Diagnostics>>,
+}
+
+impl CrashReporter {
+    #[must_use]
+    pub fn new(path: Utf8PathBuf, res: Arc<Mutex<Resources>>, svm: SvmClient) -> Self {
+        Self {
+            path,
+            res,
+            svm,
+            snapshot: Arc::new(std::sync::Mutex::new(Diagnostics::default())),
+        }
+    }
+
+    /// Install the panic hook. The previously installed hook (which prints
+    /// the panic message) still runs afterwards.
+    pub fn install(&self) {
+        let path = self.path.clone();
+        let res = self.res.clone();
+        let snapshot = self.snapshot.clone();
+        let prev = std::panic::take_hook();
+
+        std::panic::set_hook(Box::new(move |info| {
+            let mut diag = snapshot
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+
+            if let Ok(lock) = res.try_lock() {
+                diag.update_resources(&lock);
+            }
+
+            let location = info.location().map(ToString::to_string);
+            let report = CrashReport::new(info.payload(), location, diag);
+            let written = serde_json::to_string_pretty(&report)
+                .map_err(std::io::Error::from)
+                .and_then(|json| std::fs::write(&path, json));
+
+            match written {
+                Ok(()) => log::error!("Bifrost panicked, crash report written to {path}"),
+                Err(err) => log::error!("Bifrost panicked, failed to write crash report: {err}"),
+            }
+
+            prev(info);
+        }));
+    }
+
+    async fn refresh(&mut self) -> ApiResult<()> {
+        let mut services = BTreeMap::new();
+        for (id, name) in self.svm.list().await? {
+            services.insert(name, self.svm.status(id).await?);
+        }
+
+        let lock = self.res.lock().await;
+        let mut diag = Diagnostics {
+            services,
+            services_updated: Some(Utc::now()),
+            ..Diagnostics::default()
+        };
+        diag.update_resources(&lock);
+        drop(lock);
+
+        *self.snapshot.lock().unwrap_or_else(PoisonError::into_inner) = diag;
+
+        Ok(())
+    }
+
+    pub async fn run_forever(mut self) -> ApiResult<()> {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.refresh().await {
+                log::warn!("Failed to refresh crash diagnostics: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,455 @@
+//! DMX input for entertainment areas
+//!
+//! Receives DMX universes over Art-Net or E1.31 (sACN), and maps them onto
+//! the channels of entertainment areas, as configured in the
+//! `entertainment.dmx` section. This lets lighting software drive hue lights
+//! without implementing the `HueStream` protocol.
+//!
+//! A session for an area starts when the first DMX frame for it arrives,
+//! and ends when no frames have been received for a while.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::select;
+use tokio::sync::Mutex;
+use tokio::time::{interval, Instant};
+use uuid::Uuid;
+
+use hue::api::{EntertainmentConfiguration, EntertainmentConfigurationStatus, RType};
+use hue::stream::{HueStreamLights, Rgb16};
+
+use crate::backend::BackendRequest;
+use crate::config::{AppConfig, DmxInputConfig, DmxProtocol};
+use crate::error::{ApiError, ApiResult};
+use crate::model::entertainment::EntertainmentStats;
+use crate::model::takeover::EntertainmentSnapshot;
+use crate::model::throttle::Throttle;
+use crate::resource::Resources;
+
+const ARTNET_PORT: u16 = 6454;
+const SACN_PORT: u16 = 5568;
+
+/// Sessions end when no frames have been received for this long
+const SESSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Delay before trying again, after a session failed to start
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Parse an Art-Net `ArtDmx` packet into (universe, dmx data)
+fn parse_artnet(pkt: &[u8]) -> Option<(u16, &[u8])> {
+    const ID: &[u8] = b"Art-Net\0";
+    const OP_DMX: u16 = 0x5000;
+
+    if pkt.len() < 18 || &pkt[..8] != ID {
+        return None;
+    }
+
+    if u16::from_le_bytes([pkt[8], pkt[9]]) != OP_DMX {
+        return None;
+    }
+
+    let universe = u16::from_le_bytes([pkt[14], pkt[15]]);
+    let len = usize::from(u16::from_be_bytes([pkt[16], pkt[17]]));
+
+    pkt.get(18..18 + len).map(|data| (universe, data))
+}
+
+/// Parse an E1.31 data packet into (universe, dmx data)
+fn parse_sacn(pkt: &[u8]) -> Option<(u16, &[u8])> {
+    const ACN_ID: &[u8] = b"ASC-E1.17\0\0\0";
+    const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+    const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+    const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+    if pkt.len() < 126 || &pkt[4..16] != ACN_ID {
+        return None;
+    }
+
+    let vector =
+        |ofs: usize| u32::from_be_bytes([pkt[ofs], pkt[ofs + 1], pkt[ofs + 2], pkt[ofs + 3]]);
+
+    if vector(18) != VECTOR_ROOT_E131_DATA
+        || vector(40) != VECTOR_E131_DATA_PACKET
+        || pkt[117] != VECTOR_DMP_SET_PROPERTY
+    {
+        return None;
+    }
+
+    let universe = u16::from_be_bytes([pkt[113], pkt[114]]);
+
+    /* property count includes the start code, which must be 0 for dmx data */
+    let count = usize::from(u16::from_be_bytes([pkt[123], pkt[124]]));
+    if count == 0 || pkt[125] != 0 {
+        return None;
+    }
+
+    pkt.get(126..125 + count).map(|data| (universe, data))
+}
+
+/// Multicast group for an sACN universe
+const fn sacn_group(universe: u16) -> Ipv4Addr {
+    let [hi, lo] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, hi, lo)
+}
+
+struct DmxSession {
+    area: Uuid,
+    channels: Vec<u8>,
+    snapshot: EntertainmentSnapshot,
+    stats: Arc<EntertainmentStats>,
+    throttle: Throttle,
+    last_frame: Instant,
+}
+
+pub struct DmxInput {
+    config: Arc<AppConfig>,
+    res: Arc<Mutex<Resources>>,
+    bind: Ipv4Addr,
+    sessions: HashMap<usize, DmxSession>,
+    retry: HashMap<usize, Instant>,
+}
+
+impl DmxInput {
+    #[must_use]
+    pub fn new(config: Arc<AppConfig>, res: Arc<Mutex<Resources>>, bind: Ipv4Addr) -> Self {
+        Self {
+            config,
+            res,
+            bind,
+            sessions: HashMap::new(),
+            retry: HashMap::new(),
+        }
+    }
+
+    fn inputs(&self, protocol: DmxProtocol) -> impl Iterator<Item = &DmxInputConfig> {
+        self.config
+            .entertainment
+            .dmx
+            .iter()
+            .filter(move |input| input.protocol == protocol)
+    }
+
+    async fn socket(&self, protocol: DmxProtocol) -> ApiResult<Option<UdpSocket>> {
+        let universes: Vec<u16> = self.inputs(protocol).map(|input| input.universe).collect();
+        if universes.is_empty() {
+            return Ok(None);
+        }
+
+        let port = match protocol {
+            DmxProtocol::Artnet => ARTNET_PORT,
+            DmxProtocol::Sacn => SACN_PORT,
+        };
+
+        let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port)).await?;
+
+        if protocol == DmxProtocol::Sacn {
+            for universe in universes {
+                socket.join_multicast_v4(sacn_group(universe), self.bind)?;
+            }
+        }
+
+        log::info!("Listening for {protocol:?} dmx input on port {port}");
+
+        Ok(Some(socket))
+    }
+
+    async fn start(&self, input: &DmxInputConfig) -> ApiResult<DmxSession> {
+        let mut lock = self.res.lock().await;
+
+        let area = lock
+            .get_resource_ids_by_type(RType::EntertainmentConfiguration)
+            .into_iter()
+            .find(|id| {
+                lock.get_id::<EntertainmentConfiguration>(*id)
+                    .is_ok_and(|ent| {
+                        ent.metadata.name == input.area || id.to_string() == input.area
+                    })
+            })
+            .ok_or_else(|| ApiError::EntAreaNotFound(input.area.clone()))?;
+
+        lock.check_entertainment_conflict(&area)?;
+
+        let ent: &EntertainmentConfiguration = lock.get_id(area)?;
+        let mut channels: Vec<u8> = ent
+            .channels
+            .iter()
+            .filter_map(|chan| u8::try_from(chan.channel_id).ok())
+            .collect();
+        channels.sort_unstable();
+
+        let settings =
+            lock.area_entertainment_settings(&self.config.entertainment, &area, &ent.metadata.name);
+        let stats = Arc::new(EntertainmentStats::new(
+            area,
+            &ent.metadata.name,
+            channels.len(),
+        ));
+
+        log::info!(
+            "Starting {:?} dmx session for entertainment area {:?}",
+            input.protocol,
+            ent.metadata.name
+        );
+
+        lock.set_entertainment_stats(area, stats.clone());
+        let snapshot = EntertainmentSnapshot::take(&lock, &area)?;
+        lock.backend_request(BackendRequest::EntertainmentStart(area))?;
+        lock.update(&area, |ec: &mut EntertainmentConfiguration| {
+            ec.status = EntertainmentConfigurationStatus::Active;
+        })?;
+        drop(lock);
+
+        Ok(DmxSession {
+            area,
+            channels,
+            snapshot,
+            stats,
+            throttle: Throttle::from_fps(settings.max_fps),
+            last_frame: Instant::now(),
+        })
+    }
+
+    async fn stop(&self, sess: &DmxSession) -> ApiResult<()> {
+        let mut lock = self.res.lock().await;
+        lock.update(&sess.area, |ec: &mut EntertainmentConfiguration| {
+            ec.status = EntertainmentConfigurationStatus::Inactive;
+        })?;
+        lock.backend_request(BackendRequest::EntertainmentStop(sess.area))?;
+        sess.snapshot
+            .restore(&lock, self.config.entertainment.restore)?;
+        drop(lock);
+
+        sess.stats.finish();
+        log::info!("Dmx session ended: {}", sess.stats.report());
+
+        Ok(())
+    }
+
+    async fn handle_packet(&mut self, protocol: DmxProtocol, pkt: &[u8]) -> ApiResult<()> {
+        let parsed = match protocol {
+            DmxProtocol::Artnet => parse_artnet(pkt),
+            DmxProtocol::Sacn => parse_sacn(pkt),
+        };
+
+        let Some((universe, data)) = parsed else {
+            return Ok(());
+        };
+
+        let inputs: Vec<(usize, DmxInputConfig)> = self
+            .config
+            .entertainment
+            .dmx
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| input.protocol == protocol && input.universe == universe)
+            .map(|(idx, input)| (idx, input.clone()))
+            .collect();
+
+        for (idx, input) in inputs {
+            if !self.sessions.contains_key(&idx) {
+                if self.retry.get(&idx).is_some_and(|at| *at > Instant::now()) {
+                    continue;
+                }
+
+                match self.start(&input).await {
+                    Ok(sess) => {
+                        self.retry.remove(&idx);
+                        self.sessions.insert(idx, sess);
+                    }
+                    Err(err) => {
+                        self.retry.insert(idx, Instant::now() + RETRY_DELAY);
+                        return Err(err);
+                    }
+                }
+            }
+
+            let Some(sess) = self.sessions.get_mut(&idx) else {
+                continue;
+            };
+
+            sess.last_frame = Instant::now();
+            sess.stats.frame_received();
+            if !sess.throttle.tick() {
+                continue;
+            }
+
+            let base = usize::from(input.address.saturating_sub(1));
+            let lights = sess
+                .channels
+                .iter()
+                .enumerate()
+                .filter_map(|(n, channel)| {
+                    let ofs = base + n * 3;
+                    let rgb = data.get(ofs..ofs + 3)?;
+                    Some(Rgb16 {
+                        channel: *channel,
+                        r: u16::from(rgb[0]) * 0x101,
+                        g: u16::from(rgb[1]) * 0x101,
+                        b: u16::from(rgb[2]) * 0x101,
+                    })
+                })
+                .collect();
+
+            sess.stats.frame_forwarded();
+            let req = BackendRequest::EntertainmentFrame(sess.area, HueStreamLights::Rgb(lights));
+            self.res.lock().await.backend_request(req)?;
+        }
+
+        Ok(())
+    }
+
+    async fn expire_sessions(&mut self) -> ApiResult<()> {
+        let expired: Vec<usize> = self
+            .sessions
+            .iter()
+            .filter(|(_, sess)| sess.last_frame.elapsed() > SESSION_TIMEOUT)
+            .map(|(idx, _)| *idx)
+            .collect();
+
+        for idx in expired {
+            if let Some(sess) = self.sessions.remove(&idx) {
+                self.stop(&sess).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn recv(socket: Option<&UdpSocket>, buf: &mut [u8]) -> std::io::Result<usize> {
+        match socket {
+            Some(socket) => socket.recv(buf).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    pub async fn run_forever(mut self) -> ApiResult<()> {
+        let artnet = self.socket(DmxProtocol::Artnet).await?;
+        let sacn = self.socket(DmxProtocol::Sacn).await?;
+
+        let mut artnet_buf = vec![0; 1024];
+        let mut sacn_buf = vec![0; 1024];
+        let mut timer = interval(SESSION_TIMEOUT / 2);
+
+        loop {
+            let res = select! {
+                len = Self::recv(artnet.as_ref(), &mut artnet_buf) => {
+                    let len = len?;
+                    self.handle_packet(DmxProtocol::Artnet, &artnet_buf[..len]).await
+                }
+                len = Self::recv(sacn.as_ref(), &mut sacn_buf) => {
+                    let len = len?;
+                    self.handle_packet(DmxProtocol::Sacn, &sacn_buf[..len]).await
+                }
+                _ = timer.tick() => self.expire_sessions().await,
+            };
+
+            if let Err(err) = res {
+                log::error!("Dmx input error: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::dmx::{parse_artnet, parse_sacn, sacn_group};
+
+    fn artnet(opcode: u16, universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut pkt = b"Art-Net\0".to_vec();
+        pkt.extend_from_slice(&opcode.to_le_bytes());
+        pkt.extend_from_slice(&[0, 14, 0, 0]);
+        pkt.extend_from_slice(&universe.to_le_bytes());
+        pkt.extend_from_slice(&u16::try_from(data.len()).unwrap().to_be_bytes());
+        pkt.extend_from_slice(data);
+        pkt
+    }
+
+    fn sacn(vector: u32, universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut pkt = vec![0; 126];
+        pkt[0..2].copy_from_slice(&0x0010u16.to_be_bytes());
+        pkt[4..16].copy_from_slice(b"ASC-E1.17\0\0\0");
+        pkt[18..22].copy_from_slice(&vector.to_be_bytes());
+        pkt[40..44].copy_from_slice(&2u32.to_be_bytes());
+        pkt[113..115].copy_from_slice(&universe.to_be_bytes());
+        pkt[117] = 0x02;
+        pkt[118] = 0xa1;
+        pkt[121..123].copy_from_slice(&1u16.to_be_bytes());
+        let count = u16::try_from(data.len() + 1).unwrap();
+        pkt[123..125].copy_from_slice(&count.to_be_bytes());
+        pkt.extend_from_slice(data);
+        pkt
+    }
+
+    #[test]
+    fn artnet_dmx() {
+        let pkt = artnet(0x5000, 3, &[1, 2, 3, 4]);
+        assert_eq!(parse_artnet(&pkt), Some((3, &[1, 2, 3, 4][..])));
+    }
+
+    #[test]
+    fn artnet_truncated() {
+        let pkt = artnet(0x5000, 3, &[1, 2, 3, 4]);
+        assert_eq!(parse_artnet(&pkt[..pkt.len() - 1]), None);
+        assert_eq!(parse_artnet(&pkt[..17]), None);
+        assert_eq!(parse_artnet(&[]), None);
+    }
+
+    #[test]
+    fn artnet_wrong_opcode() {
+        /* ArtPoll */
+        let pkt = artnet(0x2000, 3, &[1, 2, 3, 4]);
+        assert_eq!(parse_artnet(&pkt), None);
+    }
+
+    #[test]
+    fn artnet_wrong_id() {
+        let mut pkt = artnet(0x5000, 3, &[1, 2, 3, 4]);
+        pkt[0] = b'X';
+        assert_eq!(parse_artnet(&pkt), None);
+    }
+
+    #[test]
+    fn sacn_dmx() {
+        let pkt = sacn(0x0000_0004, 7, &[10, 20, 30]);
+        assert_eq!(parse_sacn(&pkt), Some((7, &[10, 20, 30][..])));
+    }
+
+    #[test]
+    fn sacn_truncated() {
+        let pkt = sacn(0x0000_0004, 7, &[10, 20, 30]);
+        assert_eq!(parse_sacn(&pkt[..pkt.len() - 1]), None);
+        assert_eq!(parse_sacn(&pkt[..125]), None);
+        assert_eq!(parse_sacn(&[]), None);
+    }
+
+    #[test]
+    fn sacn_wrong_vector() {
+        /* E1.31 extended (sync/discovery) packet */
+        let pkt = sacn(0x0000_0008, 7, &[10, 20, 30]);
+        assert_eq!(parse_sacn(&pkt), None);
+    }
+
+    #[test]
+    fn sacn_non_dmx_start_code() {
+        let mut pkt = sacn(0x0000_0004, 7, &[10, 20, 30]);
+        pkt[125] = 0xdd;
+        assert_eq!(parse_sacn(&pkt), None);
+    }
+
+    #[test]
+    fn sacn_zero_count() {
+        let mut pkt = sacn(0x0000_0004, 7, &[]);
+        pkt[123..125].copy_from_slice(&[0, 0]);
+        assert_eq!(parse_sacn(&pkt), None);
+    }
+
+    #[test]
+    fn sacn_multicast_group() {
+        assert_eq!(sacn_group(1).octets(), [239, 255, 0, 1]);
+        assert_eq!(sacn_group(0x0102).octets(), [239, 255, 1, 2]);
+    }
+}
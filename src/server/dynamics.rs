@@ -0,0 +1,166 @@
+//! Dynamic scenes: rotating the colors of a scene palette over its lights
+//!
+//! A single animation clock drives all dynamic scenes, so the lights of a
+//! scene stay in step with each other: each light is offset evenly through
+//! the palette, and all of them move on together, at the speed of the scene.
+//!
+//! A scene is animated for as long as its status is `dynamic_palette`, which
+//! ends when another scene is recalled in the same room.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::{Instant, MissedTickBehavior};
+use uuid::Uuid;
+
+use hue::api::{ColorUpdate, LightUpdate, RType, ResourceLink, Scene, SceneActive};
+use hue::gradient;
+use hue::xy::XY;
+
+use crate::backend::BackendRequest;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// Interval between color updates (also used as transition time)
+const TICK: Duration = Duration::from_secs(2);
+
+/// Time for a full rotation through the palette, at speed 0 and speed 1
+const SLOWEST_PERIOD: f64 = 300.0;
+const FASTEST_PERIOD: f64 = 10.0;
+
+#[derive(Deserialize)]
+struct PaletteColor {
+    color: ColorUpdate,
+}
+
+/// Seconds for a full rotation through the palette, for a scene speed
+/// (0..1)
+fn period(speed: f64) -> f64 {
+    SLOWEST_PERIOD * (FASTEST_PERIOD / SLOWEST_PERIOD).powf(speed.clamp(0.0, 1.0))
+}
+
+/// Colors to rotate through: the palette colors if there are any, otherwise
+/// the colors of the scene actions
+fn palette(scene: &Scene) -> Vec<XY> {
+    let colors: Vec<XY> = scene
+        .palette
+        .get("color")
+        .cloned()
+        .and_then(|colors| serde_json::from_value::<Vec<PaletteColor>>(colors).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|pc| pc.color.xy)
+        .collect();
+
+    if !colors.is_empty() {
+        return colors;
+    }
+
+    scene
+        .actions
+        .iter()
+        .filter_map(|act| act.action.color.map(|color| color.xy))
+        .collect()
+}
+
+/// Light updates for a dynamic scene, `elapsed` seconds after it started
+#[allow(clippy::cast_precision_loss)]
+fn scene_requests(scene: &Scene, elapsed: f64) -> Vec<BackendRequest> {
+    let colors = palette(scene);
+    if colors.len() < 2 {
+        return vec![];
+    }
+
+    let mut lights: Vec<ResourceLink> = scene
+        .actions
+        .iter()
+        .map(|act| act.target)
+        .filter(|target| target.rtype == RType::Light)
+        .collect();
+    lights.sort();
+
+    let count = lights.len() as f64;
+    let position = elapsed / period(scene.speed);
+    let duration = u32::try_from(TICK.as_millis()).unwrap_or(u32::MAX);
+
+    lights
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, light)| {
+            let xy = gradient::cycle(&colors, position + idx as f64 / count)?;
+            let upd = LightUpdate::new()
+                .with_color_xy(xy)
+                .with_duration(Some(duration));
+            Some(BackendRequest::LightUpdate(light, upd))
+        })
+        .collect()
+}
+
+pub struct SceneDynamics {
+    res: Arc<Mutex<Resources>>,
+    /// Active dynamic scenes, with the time their animation started
+    scenes: HashMap<Uuid, Instant>,
+}
+
+impl SceneDynamics {
+    #[must_use]
+    pub fn new(res: Arc<Mutex<Resources>>) -> Self {
+        Self {
+            res,
+            scenes: HashMap::new(),
+        }
+    }
+
+    fn tick(&mut self, res: &Resources) -> ApiResult<()> {
+        let now = Instant::now();
+
+        let active: Vec<Uuid> = res
+            .get_resource_ids_by_type(RType::Scene)
+            .into_iter()
+            .filter(|id| {
+                res.get_id::<Scene>(*id).is_ok_and(|scene| {
+                    scene.status.map(|st| st.active) == Some(SceneActive::DynamicPalette)
+                })
+            })
+            .collect();
+
+        self.scenes.retain(|id, _| active.contains(id));
+
+        for id in active {
+            let start = *self.scenes.entry(id).or_insert_with(|| {
+                log::info!(
+                    "Starting dynamic palette for {}",
+                    res.describe_link(&RType::Scene.link_to(id))
+                );
+                now
+            });
+
+            let scene: &Scene = res.get_id(id)?;
+            let elapsed = now.duration_since(start).as_secs_f64();
+            for req in scene_requests(scene, elapsed) {
+                res.backend_request(req)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn run_forever(mut self) -> ApiResult<()> {
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            let res = self.res.clone();
+            let lock = res.lock().await;
+            if let Err(err) = self.tick(&lock) {
+                log::error!("Failed to update dynamic scenes: {err}");
+            }
+            drop(lock);
+        }
+    }
+}
@@ -1,23 +1,31 @@
-use std::net::{Ipv4Addr, SocketAddr};
+use std::fs::File;
+use std::io::{BufReader as StdBufReader, BufWriter};
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use camino::Utf8Path;
 use chrono::Utc;
 use openssl::ssl::{Ssl, SslContext, SslMethod};
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
 use tokio::sync::Mutex;
-use tokio::time::timeout;
+use tokio::time::{sleep_until, timeout, Instant};
 use tokio_openssl::SslStream;
 use udp_stream::{UdpListener, UdpStream};
+use uuid::Uuid;
 
-use hue::api::EntertainmentConfiguration;
+use hue::api::{EntertainmentConfiguration, EntertainmentConfigurationStatus};
 use hue::stream::{HueStreamPacket, HueStreamPacketHeader};
 use svc::traits::Service;
 
 use crate::backend::BackendRequest;
+use crate::config::{AppConfig, EntertainmentRestore};
 use crate::error::{ApiError, ApiResult};
+use crate::model::entertainment::EntertainmentStats;
+use crate::model::recording::{self, RecordedFrame, RecordingTask, StreamPlayer};
+use crate::model::takeover::EntertainmentSnapshot;
 use crate::model::throttle::{Throttle, ThrottleQueue};
 use crate::resource::Resources;
 use crate::routes::auth::STANDARD_CLIENT_KEY;
@@ -26,6 +34,15 @@ pub struct EntertainmentService {
     addr: SocketAddr,
     udp: Option<Arc<UdpListener>>,
     ctx: Option<SslContext>,
+    config: Arc<AppConfig>,
+    res: Arc<Mutex<Resources>>,
+}
+
+/// A single streaming client. Each session runs in its own task, so several
+/// entertainment areas can stream at the same time.
+#[derive(Clone)]
+struct EntertainmentSession {
+    config: Arc<AppConfig>,
     res: Arc<Mutex<Resources>>,
 }
 
@@ -41,16 +58,42 @@ where
 }
 
 impl EntertainmentService {
-    pub fn new(addr: Ipv4Addr, port: u16, res: Arc<Mutex<Resources>>) -> ApiResult<Self> {
+    pub const fn new(
+        addr: IpAddr,
+        port: u16,
+        config: Arc<AppConfig>,
+        res: Arc<Mutex<Resources>>,
+    ) -> ApiResult<Self> {
         let res = Self {
-            addr: SocketAddr::new(addr.into(), port),
+            addr: SocketAddr::new(addr, port),
             udp: None,
             ctx: None,
+            config,
             res,
         };
 
         Ok(res)
     }
+}
+
+impl EntertainmentSession {
+    fn recorder(&self, area: &Uuid) -> ApiResult<Option<RecordingTask<BufWriter<File>>>> {
+        if self.config.entertainment.record_dir.is_none() {
+            return Ok(None);
+        }
+
+        let name = format!(
+            "{}-{area}.{}",
+            Utc::now().format("%Y%m%d-%H%M%S"),
+            recording::EXTENSION
+        );
+        let path = self.config.entertainment.recording_path(&name)?;
+        log::info!("Recording entertainment stream to {path}");
+
+        Ok(Some(RecordingTask::spawn(move || {
+            Ok(BufWriter::new(File::create(path)?))
+        })))
+    }
 
     pub async fn run_loop(&self, sess: SslStream<UdpStream>) -> ApiResult<()> {
         const TIMEOUT: Duration = Duration::from_millis(1000);
@@ -67,38 +110,70 @@ impl EntertainmentService {
         let header = HueStreamPacketHeader::parse(rdr.buffer())?;
 
         // look up entertainment area
-        let lock = self.res.lock().await;
+        let mut lock = self.res.lock().await;
+        lock.check_entertainment_conflict(&header.area)?;
         let ent: &EntertainmentConfiguration = lock.get_id(header.area)?;
         let nlights = ent.channels.len();
+        let settings = lock.area_entertainment_settings(
+            &self.config.entertainment,
+            &header.area,
+            &ent.metadata.name,
+        );
+        let stats = Arc::new(EntertainmentStats::new(
+            header.area,
+            &ent.metadata.name,
+            nlights,
+        ));
+        lock.set_entertainment_stats(header.area, stats.clone());
+        let snapshot = EntertainmentSnapshot::take(&lock, &header.area)?;
         lock.backend_request(BackendRequest::EntertainmentStart(header.area))?;
         drop(lock);
 
         let mut buf = vec![0u8; HueStreamPacket::size_with_lights(nlights)];
 
+        // the session has started, so a recording problem must not end it
+        // before the lights are restored
+        let mut recorder = self.recorder(&header.area).unwrap_or_else(|err| {
+            log::error!("Failed to start recording entertainment stream: {err}");
+            None
+        });
+
         let mut fps = 0;
         let mut period = Utc::now().timestamp();
-        let throttle = Throttle::from_fps(30);
+        log::debug!("Entertainment settings: {settings:?}");
+        let throttle = Throttle::from_fps(settings.max_fps);
         let mut queue = ThrottleQueue::new(throttle, 2);
 
-        loop {
+        let res = loop {
             match timeout(Duration::from_millis(1000), rdr.read_exact(&mut buf)).await {
-                Ok(Err(_)) | Err(_) => break,
+                Ok(Err(_)) | Err(_) => break Ok(()),
                 Ok(Ok(_)) => {}
             };
 
-            let pkt = HueStreamPacket::parse(&buf)?;
+            let pkt = match HueStreamPacket::parse(&buf) {
+                Ok(pkt) => pkt,
+                Err(err) => break Err(err.into()),
+            };
+            stats.frame_received();
+
+            if let Some(rec) = &mut recorder {
+                if let Err(err) = rec.record(&buf) {
+                    log::error!("Failed to record entertainment frame: {err}");
+                    recorder = None;
+                }
+            }
 
             if pkt.color_mode != header.color_mode {
                 log::error!("Entertainment Mode color_mode changes mid-stream.");
-                return Err(ApiError::EntStreamDesync);
+                break Err(ApiError::EntStreamDesync);
             }
 
             if pkt.area != header.area {
                 log::error!("Entertainment Mode area changed mid-stream.");
-                return Err(ApiError::EntStreamDesync);
+                break Err(ApiError::EntStreamDesync);
             }
 
-            if queue.push(BackendRequest::EntertainmentFrame(pkt.lights)) {
+            if queue.push(BackendRequest::EntertainmentFrame(header.area, pkt.lights)) {
                 let ts = Utc::now().timestamp();
                 if period != ts {
                     log::info!("Entertainment fps: {fps}");
@@ -109,17 +184,107 @@ impl EntertainmentService {
 
             if let Some(req) = queue.pop() {
                 fps += 1;
-                self.res.lock().await.backend_request(req)?;
+                stats.frame_forwarded();
+                let sent = self.res.lock().await.backend_request(req);
+                if let Err(err) = sent {
+                    break Err(err);
+                }
             }
-        }
+        };
+
+        let req = BackendRequest::EntertainmentStop(header.area);
+        let lock = self.res.lock().await;
+        lock.backend_request(req)?;
+        snapshot.restore(&lock, self.config.entertainment.restore)?;
+        drop(lock);
 
-        let req = BackendRequest::EntertainmentStop();
-        self.res.lock().await.backend_request(req)?;
+        stats.finish();
+        log::info!("Entertainment session ended: {}", stats.report());
 
-        Ok(())
+        if let Some(rec) = recorder {
+            if let Err(err) = rec.finish().await {
+                log::error!("Failed to record entertainment stream: {err}");
+            }
+        }
+
+        res
     }
 }
 
+/// Replay a recorded entertainment stream against the backends, using the
+/// original frame timing.
+pub async fn replay(
+    res: Arc<Mutex<Resources>>,
+    path: &Utf8Path,
+    restore: EntertainmentRestore,
+) -> ApiResult<()> {
+    let file = path.to_owned();
+    let mut frames = StreamPlayer::spawn(move || Ok(StdBufReader::new(File::open(file)?)));
+
+    let next = |frame: Option<ApiResult<RecordedFrame>>| -> ApiResult<_> {
+        frame
+            .map(|frame| {
+                let frame = frame?;
+                Ok((frame.offset, HueStreamPacket::parse(&frame.data)?))
+            })
+            .transpose()
+    };
+
+    let Some((offset, pkt)) = next(frames.recv().await)? else {
+        return Err(ApiError::EntRecordingInvalid);
+    };
+    let area = pkt.area;
+
+    log::info!("Replaying entertainment stream from {path}");
+
+    let mut lock = res.lock().await;
+    lock.check_entertainment_conflict(&area)?;
+    let snapshot = EntertainmentSnapshot::take(&lock, &area)?;
+    lock.backend_request(BackendRequest::EntertainmentStart(area))?;
+    lock.update(&area, |ec: &mut EntertainmentConfiguration| {
+        ec.status = EntertainmentConfigurationStatus::Active;
+    })?;
+    drop(lock);
+
+    // frames are read while replaying, so a broken frame ends the replay
+    // after the lights are restored
+    let start = Instant::now();
+    let mut frame = Some((offset, pkt));
+    let mut count = 0;
+    let result = loop {
+        let Some((offset, pkt)) = frame else {
+            break Ok(());
+        };
+        sleep_until(start + offset).await;
+        let req = BackendRequest::EntertainmentFrame(area, pkt.lights);
+        let sent = res.lock().await.backend_request(req);
+        if let Err(err) = sent {
+            break Err(err);
+        }
+        count += 1;
+
+        frame = match next(frames.recv().await) {
+            Ok(frame) => frame,
+            Err(err) => break Err(err),
+        };
+    };
+
+    let req = BackendRequest::EntertainmentStop(area);
+    let mut lock = res.lock().await;
+    lock.update(&area, |ec: &mut EntertainmentConfiguration| {
+        ec.status = EntertainmentConfigurationStatus::Inactive;
+    })?;
+    lock.backend_request(req)?;
+    snapshot.restore(&lock, restore)?;
+    drop(lock);
+
+    result?;
+
+    log::info!("Replay of {path} finished ({count} frames)");
+
+    Ok(())
+}
+
 #[async_trait]
 impl Service for EntertainmentService {
     type Error = ApiError;
@@ -128,8 +293,19 @@ impl Service for EntertainmentService {
     async fn configure(&mut self) -> Result<(), Self::Error> {
         let mut bldr = SslContext::builder(SslMethod::dtls_server())?;
 
-        bldr.set_psk_server_callback(|_sslref, cid, psk| {
+        let config = self.config.clone();
+        bldr.set_psk_server_callback(move |_sslref, cid, psk| {
             let client_id = String::from_utf8_lossy(cid.unwrap_or_default());
+
+            let allowed = config.api_keys.get(&client_id).map_or_else(
+                || !config.api_keys.deny_unknown(),
+                |key| !key.read_only && key.entertainment,
+            );
+            if !allowed {
+                log::warn!("Refusing entertainment stream for {client_id}: not allowed for key");
+                return Ok(0);
+            }
+
             log::debug!("Setting PSK for {client_id}",);
             STANDARD_CLIENT_KEY.write_to_slice(psk).unwrap();
 
@@ -155,16 +331,31 @@ impl Service for EntertainmentService {
             return Err(ApiError::SvcError("Ctx not initialized".to_string()));
         };
 
+        let session = EntertainmentSession {
+            config: self.config.clone(),
+            res: self.res.clone(),
+        };
+
         loop {
-            let (socket, _addr) = udp.accept().await?;
+            let (socket, addr) = udp.accept().await?;
             let ssl = Ssl::new(ctx)?;
+            let session = session.clone();
 
-            let mut stream = SslStream::new(ssl, socket)?;
-            Pin::new(&mut stream).accept().await?;
-            match self.run_loop(stream).await {
-                Ok(()) => log::info!("Entertainment stream finished"),
-                Err(err) => log::error!("Entertainment stream error: {err}"),
-            }
+            tokio::spawn(async move {
+                let mut stream = match SslStream::new(ssl, socket) {
+                    Ok(stream) => stream,
+                    Err(err) => return log::error!("Entertainment stream error: {err}"),
+                };
+
+                if let Err(err) = Pin::new(&mut stream).accept().await {
+                    return log::error!("Entertainment handshake with {addr} failed: {err}");
+                }
+
+                match session.run_loop(stream).await {
+                    Ok(()) => log::info!("Entertainment stream from {addr} finished"),
+                    Err(err) => log::error!("Entertainment stream from {addr} error: {err}"),
+                }
+            });
         }
     }
 
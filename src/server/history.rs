@@ -0,0 +1,137 @@
+//! History of hue events, for answering "what happened?" after the fact
+//!
+//! Every event sent on the hue event stream is kept for a configurable
+//! number of hours, and can be queried through `/bifrost/events`. If a
+//! history file is configured, events are also appended to it (as json
+//! lines), and loaded back on startup.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+
+use hue::event::EventBlock;
+
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+#[derive(Debug)]
+pub struct EventHistory {
+    path: Option<Utf8PathBuf>,
+    max_age: Duration,
+    events: VecDeque<EventBlock>,
+    /// Events appended to the file since it was last compacted
+    appended: usize,
+}
+
+impl EventHistory {
+    pub fn open(path: Option<&Utf8Path>, max_age: Duration) -> ApiResult<Self> {
+        let mut res = Self {
+            path: path.map(ToOwned::to_owned),
+            max_age,
+            events: VecDeque::new(),
+            appended: 0,
+        };
+
+        if let Some(fd) = path.and_then(|path| File::open(path).ok()) {
+            for line in BufReader::new(fd).lines() {
+                match serde_json::from_str(&line?) {
+                    Ok(block) => res.events.push_back(block),
+                    Err(err) => log::warn!("Skipping invalid event history entry: {err}"),
+                }
+            }
+            res.prune(Utc::now());
+            log::debug!("Loaded {} events from history file", res.events.len());
+        }
+
+        Ok(res)
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.max_age > Duration::zero()
+    }
+
+    /// Forget events older than the maximum age
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - self.max_age;
+        while self
+            .events
+            .front()
+            .is_some_and(|block| block.creationtime < cutoff)
+        {
+            self.events.pop_front();
+        }
+    }
+
+    /// Rewrite the file with only the events kept in memory
+    fn compact(&mut self, path: &Utf8Path) -> ApiResult<()> {
+        let tmp = path.with_extension("tmp");
+        let mut fd = File::create(&tmp)?;
+        for block in &self.events {
+            writeln!(fd, "{}", serde_json::to_string(block)?)?;
+        }
+        fs::rename(&tmp, path)?;
+        self.appended = 0;
+        Ok(())
+    }
+
+    pub fn record(&mut self, block: EventBlock) -> ApiResult<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        self.prune(block.creationtime);
+        let line = serde_json::to_string(&block)?;
+        self.events.push_back(block);
+
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+
+        /* append to the file, and compact it once it holds twice the
+         * number of events we need to keep */
+        if self.appended >= self.events.len() {
+            self.compact(&path)
+        } else {
+            let mut fd = OpenOptions::new().create(true).append(true).open(&path)?;
+            writeln!(fd, "{line}")?;
+            self.appended += 1;
+            Ok(())
+        }
+    }
+
+    /// All events, newest first
+    pub fn events(&self) -> impl Iterator<Item = &EventBlock> {
+        self.events.iter().rev()
+    }
+
+    /// Record every hue event, until the event stream closes
+    pub async fn run_forever(
+        history: Arc<Mutex<Self>>,
+        res: Arc<Mutex<Resources>>,
+    ) -> ApiResult<()> {
+        let mut chan = res.lock().await.hue_event_stream().subscribe();
+
+        loop {
+            let record = match chan.recv().await {
+                Ok(record) => record,
+                Err(RecvError::Lagged(num)) => {
+                    log::warn!("Event history lagging: dropped {num} events");
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let result = history.lock().await.record(record.block);
+            if let Err(err) = result {
+                log::error!("Failed to write event history: {err}");
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -74,7 +74,7 @@ impl<S, F> HttpServer<S, DefaultAcceptor, F>
 where
     Self: Service,
 {
-    pub fn http(listen_addr: Ipv4Addr, listen_port: u16, svc: S) -> Self
+    pub fn http(listen_addr: IpAddr, listen_port: u16, svc: S) -> Self
     where
         S: Send + Clone + MakeService<SocketAddr, Request<Incoming>>,
         S::MakeFuture: Send,
@@ -103,10 +103,11 @@ where
     S: Send + Unpin,
 {
     pub fn https_openssl(
-        listen_addr: Ipv4Addr,
+        listen_addr: IpAddr,
         listen_port: u16,
         svc: S,
         certfile: &Utf8Path,
+        keyfile: &Utf8Path,
     ) -> ApiResult<Self> {
         use std::sync::Arc;
 
@@ -128,7 +129,7 @@ where
 
         let mut tls_builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
         tls_builder.set_certificate_file(certfile, SslFiletype::PEM)?;
-        tls_builder.set_private_key_file(certfile, SslFiletype::PEM)?;
+        tls_builder.set_private_key_file(keyfile, SslFiletype::PEM)?;
         tls_builder.check_private_key()?;
         tls_builder.set_alpn_select_callback(alpn_select);
         let acceptor = tls_builder.build();
@@ -161,17 +162,18 @@ where
     S: Send + Unpin,
 {
     pub async fn https_rustls(
-        listen_addr: Ipv4Addr,
+        listen_addr: IpAddr,
         listen_port: u16,
         svc: S,
         certfile: &Utf8Path,
+        keyfile: &Utf8Path,
     ) -> ApiResult<Self> {
         use crate::error::ApiError;
         use axum_server::tls_rustls::RustlsConfig;
 
         log::debug!("Loading certificate from [{certfile}]");
 
-        let config = RustlsConfig::from_pem_file(certfile, certfile)
+        let config = RustlsConfig::from_pem_file(certfile, keyfile)
             .await
             .map_err(|e| ApiError::Certificate(certfile.to_owned(), e))?;
 
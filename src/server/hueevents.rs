@@ -1,9 +1,10 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, VecDeque};
 
 use chrono::{DateTime, Utc};
 use tokio::sync::broadcast::{Receiver, Sender};
 
-use hue::event::EventBlock;
+use hue::api::RType;
+use hue::event::{Event, EventBlock};
 
 #[derive(Clone, Debug)]
 pub struct HueEventRecord {
@@ -73,6 +74,13 @@ impl HueEventStream {
         }
     }
 
+    /// The last `count` events sent (or fewer, if the buffer holds less)
+    #[must_use]
+    pub fn recent_events(&self, count: usize) -> Vec<HueEventRecord> {
+        let skip = self.buffer.len().saturating_sub(count);
+        self.buffer.iter().skip(skip).cloned().collect()
+    }
+
     pub fn hue_event(&mut self, block: EventBlock) {
         let record = self.generate_record(block);
         self.add_to_buffer(record.clone());
@@ -86,3 +94,34 @@ impl HueEventStream {
         self.hue_updates.subscribe()
     }
 }
+
+/// Remove event data for resource types not in `types` (unless `types` is
+/// empty, which means all types are of interest).
+///
+/// Returns `None` if nothing is left.
+#[must_use]
+pub fn filter_types(mut block: EventBlock, types: &BTreeSet<RType>) -> Option<EventBlock> {
+    if types.is_empty() {
+        return Some(block);
+    }
+
+    let data = match &mut block.event {
+        Event::Add(add) => &mut add.data,
+        Event::Update(update) => &mut update.data,
+        Event::Delete(delete) => &mut delete.data,
+        Event::Error(_) => return None,
+    };
+
+    data.retain(|obj| {
+        obj.get("type")
+            .cloned()
+            .and_then(|rtype| serde_json::from_value::<RType>(rtype).ok())
+            .is_some_and(|rtype| types.contains(&rtype))
+    });
+
+    if data.is_empty() {
+        None
+    } else {
+        Some(block)
+    }
+}
@@ -0,0 +1,328 @@
+//! Export of light and sensor state changes in the influx line protocol
+//!
+//! State changes from the hue event stream are turned into points, e.g.
+//!
+//! ```text
+//! hue,type=light,id=<uuid>,name=Desk\ lamp on=true,brightness=54.3 1700000000000000000
+//! ```
+//!
+//! and written in batches to an `InfluxDB` (v1 or v2) write endpoint, or
+//! anything else that accepts line protocol over http.
+
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use hue::event::{Event, EventBlock};
+
+use crate::config::InfluxConfig;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// Escape a measurement name
+fn escape_measurement(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+}
+
+/// Escape a tag key or tag value. Line protocol has no escape for line
+/// breaks, so these are replaced by spaces.
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(['\r', '\n'], " ")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Fields of a resource update, in line protocol format
+fn fields(obj: &Value) -> Vec<String> {
+    let get = |a: &str, b: &str| obj.get(a).and_then(|v| v.get(b));
+    let mut res = vec![];
+
+    if let Some(on) = get("on", "on").and_then(Value::as_bool) {
+        res.push(format!("on={on}"));
+    }
+    if let Some(bri) = get("dimming", "brightness").and_then(Value::as_f64) {
+        res.push(format!("brightness={bri}"));
+    }
+    if let Some(mirek) = get("color_temperature", "mirek").and_then(Value::as_u64) {
+        res.push(format!("mirek={mirek}i"));
+    }
+
+    let temperature = get("temperature_report", "temperature")
+        .or_else(|| get("temperature", "temperature"))
+        .and_then(Value::as_f64);
+    if let Some(temp) = temperature {
+        res.push(format!("temperature={temp}"));
+    }
+
+    let motion = get("motion_report", "motion")
+        .or_else(|| get("motion", "motion"))
+        .and_then(Value::as_bool);
+    if let Some(motion) = motion {
+        res.push(format!("motion={motion}"));
+    }
+
+    let light_level = get("light_level_report", "light_level")
+        .or_else(|| get("light", "light_level"))
+        .and_then(Value::as_u64);
+    if let Some(level) = light_level {
+        res.push(format!("light_level={level}i"));
+    }
+
+    res
+}
+
+/// A line protocol point for a resource update, if it has any fields of
+/// interest
+fn point(
+    measurement: &str,
+    obj: &Value,
+    name: Option<&str>,
+    time: DateTime<Utc>,
+) -> Option<String> {
+    let fields = fields(obj);
+    if fields.is_empty() {
+        return None;
+    }
+
+    let rtype = obj.get("type")?.as_str()?;
+    let id = obj.get("id")?.as_str()?;
+
+    let mut line = format!(
+        "{},type={},id={}",
+        escape_measurement(measurement),
+        escape_tag(rtype),
+        escape_tag(id)
+    );
+    if let Some(name) = name {
+        let _ = write!(line, ",name={}", escape_tag(name));
+    }
+    let _ = write!(line, " {}", fields.join(","));
+    if let Some(nanos) = time.timestamp_nanos_opt() {
+        let _ = write!(line, " {nanos}");
+    }
+
+    Some(line)
+}
+
+pub struct InfluxExporter {
+    conf: InfluxConfig,
+    client: reqwest::Client,
+    res: Arc<Mutex<Resources>>,
+    pending: Vec<String>,
+}
+
+impl InfluxExporter {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Points kept for retrying while the endpoint is down, in batches
+    const MAX_PENDING_BATCHES: usize = 10;
+
+    pub fn new(conf: InfluxConfig, res: Arc<Mutex<Resources>>) -> ApiResult<Self> {
+        let client = reqwest::Client::builder().timeout(Self::TIMEOUT).build()?;
+        Ok(Self {
+            conf,
+            client,
+            res,
+            pending: vec![],
+        })
+    }
+
+    async fn add_block(&mut self, block: &EventBlock) {
+        let data = match &block.event {
+            Event::Add(add) => &add.data,
+            Event::Update(update) => &update.data,
+            Event::Delete(_) | Event::Error(_) => return,
+        };
+
+        let lock = self.res.lock().await;
+        let points: Vec<String> = data
+            .iter()
+            .filter_map(|obj| {
+                let name = obj
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .and_then(|id| id.parse::<Uuid>().ok())
+                    .and_then(|id| lock.resource_name(&id));
+                point(
+                    &self.conf.measurement,
+                    obj,
+                    name.as_deref(),
+                    block.creationtime,
+                )
+            })
+            .collect();
+        drop(lock);
+
+        self.pending.extend(points);
+
+        let max = self.conf.batch_size.max(1) * Self::MAX_PENDING_BATCHES;
+        if self.pending.len() > max {
+            let excess = self.pending.len() - max;
+            log::warn!("Influx export falling behind: dropped {excess} points");
+            self.pending.drain(..excess);
+        }
+    }
+
+    async fn flush(&mut self) -> ApiResult<()> {
+        while !self.pending.is_empty() {
+            let count = self.pending.len().min(self.conf.batch_size.max(1));
+            let body = self.pending[..count].join("\n");
+
+            let mut req = self.client.post(self.conf.url.clone()).body(body);
+            if let Some(token) = &self.conf.token {
+                req = req.header("authorization", format!("Token {token}"));
+            }
+            let res = req.send().await?;
+
+            // a rejected batch would be rejected again, so only server errors
+            // (and transport errors, above) are retried
+            if res.status().is_client_error() {
+                let status = res.status();
+                let msg = res.text().await.unwrap_or_default();
+                log::error!(
+                    "Influx export to {} rejected {count} points ({status}): {msg}",
+                    self.conf.url
+                );
+            } else {
+                res.error_for_status()?;
+            }
+
+            self.pending.drain(..count);
+        }
+
+        Ok(())
+    }
+
+    pub async fn run_forever(mut self) -> ApiResult<()> {
+        let mut chan = self.res.lock().await.hue_event_stream().subscribe();
+        let mut timer = tokio::time::interval(Duration::from_secs(self.conf.flush_interval.max(1)));
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        log::info!("Exporting state changes to {}", self.conf.url);
+
+        loop {
+            select! {
+                record = chan.recv() => {
+                    match record {
+                        Ok(record) => self.add_block(&record.block).await,
+                        Err(RecvError::Lagged(num)) => {
+                            log::warn!("Influx export lagging: dropped {num} events");
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+
+                    if self.pending.len() < self.conf.batch_size {
+                        continue;
+                    }
+                }
+                _ = timer.tick() => {}
+            }
+
+            if let Err(err) = self.flush().await {
+                log::error!("Influx export to {} failed: {err}", self.conf.url);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::server::influx::{escape_measurement, escape_tag, fields, point};
+
+    #[test]
+    fn escape_measurement_name() {
+        assert_eq!(escape_measurement("hue"), "hue");
+        assert_eq!(escape_measurement("hue lights,x"), "hue\\ lights\\,x");
+        assert_eq!(escape_measurement("a=b"), "a=b");
+    }
+
+    #[test]
+    fn escape_tag_value() {
+        assert_eq!(escape_tag("Desk lamp"), "Desk\\ lamp");
+        assert_eq!(escape_tag("a,b=c"), "a\\,b\\=c");
+        assert_eq!(escape_tag("back\\slash"), "back\\\\slash");
+        assert_eq!(escape_tag("two\nlines\r\n"), "two\\ lines\\ \\ ");
+    }
+
+    #[test]
+    fn light_fields() {
+        let obj = json!({
+            "on": {"on": true},
+            "dimming": {"brightness": 54.3},
+            "color_temperature": {"mirek": 366},
+        });
+        assert_eq!(fields(&obj), ["on=true", "brightness=54.3", "mirek=366i"]);
+    }
+
+    #[test]
+    fn sensor_fields() {
+        let obj = json!({
+            "temperature": {"temperature_report": {"temperature": 21.5}, "temperature": 21.5},
+            "motion": {"motion": false},
+            "light": {"light_level": 12000},
+        });
+        assert_eq!(
+            fields(&obj),
+            ["temperature=21.5", "motion=false", "light_level=12000i"]
+        );
+    }
+
+    #[test]
+    fn no_fields() {
+        assert!(fields(&json!({"metadata": {"name": "Hall"}})).is_empty());
+    }
+
+    #[test]
+    fn light_point() {
+        let obj = json!({
+            "id": "a8b7ab4c-e74e-5b45-a6c3-b23bfa1e4c8d",
+            "type": "light",
+            "on": {"on": false},
+        });
+        let time = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+
+        assert_eq!(
+            point("hue", &obj, Some("Desk lamp"), time).unwrap(),
+            "hue,type=light,id=a8b7ab4c-e74e-5b45-a6c3-b23bfa1e4c8d,name=Desk\\ lamp \
+             on=false 1700000000000000000"
+        );
+    }
+
+    #[test]
+    fn point_without_name() {
+        let obj = json!({"id": "1", "type": "motion", "motion": {"motion": true}});
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+
+        assert_eq!(
+            point("my data", &obj, None, time).unwrap(),
+            "my\\ data,type=motion,id=1 motion=true 0"
+        );
+    }
+
+    #[test]
+    fn point_needs_fields_and_id() {
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let no_fields = json!({"id": "1", "type": "light"});
+        let no_id = json!({"type": "light", "on": {"on": true}});
+
+        assert_eq!(point("hue", &no_fields, None, time), None);
+        assert_eq!(point("hue", &no_id, None, time), None);
+    }
+}
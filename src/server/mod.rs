@@ -1,25 +1,38 @@
 pub mod appstate;
+pub mod audit;
 pub mod banner;
 pub mod certificate;
+pub mod crash;
+pub mod dmx;
+pub mod dynamics;
 pub mod entertainment;
+pub mod history;
 pub mod http;
 pub mod hueevents;
+pub mod influx;
+pub mod mqtt;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod setup;
 pub mod updater;
+pub mod vacation;
+pub mod webhook;
 
 use std::fs::File;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use axum::body::Body;
+use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
 use axum::extract::Request;
 use axum::response::Response;
-use axum::routing::IntoMakeService;
-use axum::{Router, ServiceExt};
+use axum::{Extension, Router, ServiceExt};
 
 use camino::Utf8PathBuf;
 use tokio::select;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tokio::time::{sleep_until, MissedTickBehavior};
 use tower::Layer;
 use tower_http::normalize_path::{NormalizePath, NormalizePathLayer};
@@ -29,6 +42,7 @@ use tracing::{info_span, Span};
 use crate::error::ApiResult;
 use crate::resource::Resources;
 use crate::routes;
+use crate::routes::proxy::Listener;
 use crate::server::appstate::AppState;
 use crate::server::updater::VersionUpdater;
 
@@ -56,20 +70,32 @@ fn router(appstate: AppState) -> Router<()> {
     )
 }
 
+/// Build the service for a listener. Requests are tagged with a
+/// [`Listener`], so handlers can tell which of them came in over https.
 #[must_use]
-pub fn build_service(appstate: AppState) -> IntoMakeService<NormalizePath<Router>> {
-    let normalized = NormalizePathLayer::trim_trailing_slash().layer(router(appstate));
-
-    ServiceExt::<Request>::into_make_service(normalized)
+pub fn build_service(
+    appstate: AppState,
+    https: bool,
+) -> IntoMakeServiceWithConnectInfo<NormalizePath<Router>, SocketAddr> {
+    let router = router(appstate).layer(Extension(Listener { https }));
+    let normalized = NormalizePathLayer::trim_trailing_slash().layer(router);
+
+    ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(normalized)
 }
 
-pub async fn config_writer(res: Arc<Mutex<Resources>>, filename: Utf8PathBuf) -> ApiResult<()> {
+/// Write the output of `serialize` to `filename`, whenever `rx` is notified
+/// and the output has changed
+async fn write_on_change(
+    res: Arc<Mutex<Resources>>,
+    rx: Arc<Notify>,
+    filename: Utf8PathBuf,
+    serialize: fn(&Resources) -> ApiResult<String>,
+) -> ApiResult<()> {
     const STABILIZE_TIME: Duration = Duration::from_secs(1);
 
-    let rx = res.lock().await.state_channel();
     let tmp = filename.with_extension("tmp");
 
-    let mut old_state = res.lock().await.serialize()?;
+    let mut old_state = serialize(&*res.lock().await)?;
 
     loop {
         /* Wait for change notification */
@@ -87,14 +113,14 @@ pub async fn config_writer(res: Arc<Mutex<Resources>>, filename: Utf8PathBuf) ->
         }
 
         /* Now that the state is likely stabilized, serialize the new state */
-        let new_state = res.lock().await.serialize()?;
+        let new_state = serialize(&*res.lock().await)?;
 
         /* If state is not actually changed, try again */
         if old_state == new_state {
             continue;
         }
 
-        log::debug!("Config changed, saving..");
+        log::debug!("{filename} changed, saving..");
 
         let mut fd = File::create(&tmp)?;
         fd.write_all(new_state.as_bytes())?;
@@ -104,6 +130,16 @@ pub async fn config_writer(res: Arc<Mutex<Resources>>, filename: Utf8PathBuf) ->
     }
 }
 
+pub async fn config_writer(res: Arc<Mutex<Resources>>, filename: Utf8PathBuf) -> ApiResult<()> {
+    let rx = res.lock().await.state_channel();
+    write_on_change(res, rx, filename, Resources::serialize).await
+}
+
+pub async fn automation_writer(res: Arc<Mutex<Resources>>, filename: Utf8PathBuf) -> ApiResult<()> {
+    let rx = res.lock().await.automation_channel();
+    write_on_change(res, rx, filename, Resources::serialize_automations).await
+}
+
 #[allow(clippy::significant_drop_tightening)]
 pub async fn version_updater(
     res: Arc<Mutex<Resources>>,
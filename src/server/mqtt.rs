@@ -0,0 +1,295 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event as MqttEvent, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, Notify};
+use uuid::Uuid;
+
+use hue::api::{RType, ResourceRecord};
+use hue::event::{Event, EventBlock};
+
+use crate::config::MqttPublishConfig;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+
+/// Publishes resource state and hue events to an mqtt broker
+///
+/// Topics:
+///
+///   <prefix>/status                   "online" or "offline" (retained)
+///   <prefix>/events                   Hue event blocks, as json
+///   <prefix>/<rtype>/<uuid>/state     Full resource, as json (retained)
+pub struct MqttPublisher {
+    conf: MqttPublishConfig,
+    client: AsyncClient,
+    res: Arc<Mutex<Resources>>,
+}
+
+impl MqttPublisher {
+    const MAX_PACKET_SIZE: usize = 256 * 1024;
+
+    #[must_use]
+    pub fn new(conf: MqttPublishConfig, res: Arc<Mutex<Resources>>) -> (Self, rumqttc::EventLoop) {
+        let mut opts = MqttOptions::new("bifrost-publisher", &conf.host, conf.port);
+        opts.set_max_packet_size(Self::MAX_PACKET_SIZE, Self::MAX_PACKET_SIZE);
+        opts.set_last_will(LastWill::new(
+            format!("{}/status", conf.prefix),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let (Some(username), Some(password)) = (&conf.username, &conf.password) {
+            opts.set_credentials(username, password);
+        }
+
+        let (client, eventloop) = AsyncClient::new(opts, 64);
+
+        (Self { conf, client, res }, eventloop)
+    }
+
+    fn rtype_name(rtype: RType) -> String {
+        serde_json::to_value(rtype)
+            .ok()
+            .and_then(|val| val.as_str().map(ToString::to_string))
+            .unwrap_or_default()
+    }
+
+    fn state_topic(&self, rtype: RType, id: &Uuid) -> String {
+        format!(
+            "{}/{}/{id}/state",
+            self.conf.prefix,
+            Self::rtype_name(rtype)
+        )
+    }
+
+    /// Home Assistant discovery config for resource, if it has a meaningful
+    /// mapping
+    fn discovery(&self, rec: &ResourceRecord, name: &str) -> Option<(String, Value)> {
+        let (component, extra) = match rec.obj.rtype() {
+            RType::Light | RType::GroupedLight => (
+                "binary_sensor",
+                json!({
+                    "device_class": "light",
+                    "value_template": "{{ 'ON' if value_json.on.on else 'OFF' }}",
+                }),
+            ),
+            RType::Motion => (
+                "binary_sensor",
+                json!({
+                    "device_class": "motion",
+                    "value_template": "{{ 'ON' if value_json.motion.motion else 'OFF' }}",
+                }),
+            ),
+            RType::Temperature => (
+                "sensor",
+                json!({
+                    "device_class": "temperature",
+                    "unit_of_measurement": "°C",
+                    "value_template": "{{ value_json.temperature.temperature }}",
+                }),
+            ),
+            RType::PowerMeasurement => (
+                "sensor",
+                json!({
+                    "device_class": "power",
+                    "state_class": "measurement",
+                    "unit_of_measurement": "W",
+                    "value_template": "{{ value_json.power.power_report.power }}",
+                }),
+            ),
+            RType::LightLevel => (
+                "sensor",
+                json!({
+                    "device_class": "illuminance",
+                    "unit_of_measurement": "lx",
+                    "value_template":
+                        "{{ (10 ** ((value_json.light.light_level - 1) / 10000)) | round(1) }}",
+                }),
+            ),
+            _ => return None,
+        };
+
+        let unique_id = format!("bifrost_{}", rec.id.as_simple());
+        let topic = format!(
+            "{}/{component}/{unique_id}/config",
+            self.conf.discovery_prefix
+        );
+
+        let mut config = json!({
+            "name": name,
+            "unique_id": unique_id,
+            "state_topic": self.state_topic(rec.obj.rtype(), &rec.id),
+            "availability_topic": format!("{}/status", self.conf.prefix),
+        });
+        if let (Some(config), Value::Object(extra)) = (config.as_object_mut(), extra) {
+            config.extend(extra);
+        }
+
+        Some((topic, config))
+    }
+
+    async fn publish_retained(&self, topic: String, payload: Vec<u8>) -> ApiResult<()> {
+        self.client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .await?;
+        Ok(())
+    }
+
+    async fn publish_resource(&self, rec: &ResourceRecord, name: Option<&str>) -> ApiResult<()> {
+        if self.conf.discovery {
+            if let Some((topic, config)) = name.and_then(|name| self.discovery(rec, name)) {
+                self.publish_retained(topic, serde_json::to_vec(&config)?)
+                    .await?;
+            }
+        }
+
+        let topic = self.state_topic(rec.obj.rtype(), &rec.id);
+        self.publish_retained(topic, serde_json::to_vec(rec)?).await
+    }
+
+    /// Clear retained messages for deleted resource
+    async fn unpublish_resource(&self, rtype: RType, id: &Uuid) -> ApiResult<()> {
+        self.publish_retained(self.state_topic(rtype, id), vec![])
+            .await?;
+
+        if self.conf.discovery {
+            let unique_id = format!("bifrost_{}", id.as_simple());
+            for component in ["binary_sensor", "sensor"] {
+                let topic = format!(
+                    "{}/{component}/{unique_id}/config",
+                    self.conf.discovery_prefix
+                );
+                self.publish_retained(topic, vec![]).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn publish_all(&self) -> ApiResult<()> {
+        let records: Vec<_> = {
+            let lock = self.res.lock().await;
+            lock.get_resources()
+                .into_iter()
+                .map(|rec| {
+                    let name = lock.resource_name(&rec.id);
+                    (rec, name)
+                })
+                .collect()
+        };
+
+        log::debug!("Publishing {} resources to mqtt", records.len());
+
+        self.publish_retained(format!("{}/status", self.conf.prefix), b"online".to_vec())
+            .await?;
+
+        for (rec, name) in &records {
+            self.publish_resource(rec, name.as_deref()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn publish_event(&self, block: &EventBlock) -> ApiResult<()> {
+        self.client
+            .publish(
+                format!("{}/events", self.conf.prefix),
+                QoS::AtMostOnce,
+                false,
+                serde_json::to_vec(block)?,
+            )
+            .await?;
+
+        let (data, deleted) = match &block.event {
+            Event::Add(add) => (&add.data, false),
+            Event::Update(update) => (&update.data, false),
+            Event::Delete(delete) => (&delete.data, true),
+            Event::Error(_) => return Ok(()),
+        };
+
+        for obj in data {
+            let Some(id) = obj
+                .get("id")
+                .and_then(Value::as_str)
+                .and_then(|id| id.parse::<Uuid>().ok())
+            else {
+                continue;
+            };
+
+            if deleted {
+                let Some(rtype) = obj
+                    .get("type")
+                    .cloned()
+                    .and_then(|rtype| serde_json::from_value(rtype).ok())
+                else {
+                    continue;
+                };
+                self.unpublish_resource(rtype, &id).await?;
+            } else {
+                let (rec, name) = {
+                    let lock = self.res.lock().await;
+                    (lock.get_resource_by_id(&id), lock.resource_name(&id))
+                };
+                // resource might have been deleted since the event was sent
+                if let Ok(rec) = rec {
+                    self.publish_resource(&rec, name.as_deref()).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn run_forever(self, mut eventloop: rumqttc::EventLoop) -> ApiResult<()> {
+        let mut chan = self.res.lock().await.hue_event_stream().subscribe();
+
+        // (re)publish everything on every new connection, in case the broker
+        // lost its retained messages
+        let connected = Arc::new(Notify::new());
+        let notify = connected.clone();
+
+        let task = tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => notify.notify_one(),
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::warn!("Mqtt publisher: {err}");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+
+        log::info!(
+            "Publishing bridge state to mqtt://{}:{}/{}",
+            self.conf.host,
+            self.conf.port,
+            self.conf.prefix
+        );
+
+        let res = loop {
+            let res = select! {
+                () = connected.notified() => self.publish_all().await,
+                record = chan.recv() => match record {
+                    Ok(record) => self.publish_event(&record.block).await,
+                    Err(RecvError::Lagged(num)) => {
+                        log::warn!("Mqtt publisher lagging: dropped {num} events");
+                        Ok(())
+                    }
+                    Err(err) => break Err(err.into()),
+                },
+            };
+
+            if let Err(err) = res {
+                break Err(err);
+            }
+        };
+
+        task.abort();
+        res
+    }
+}
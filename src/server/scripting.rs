@@ -0,0 +1,300 @@
+//! User scripts, for automations bifrost does not have built in
+//!
+//! Scripts are written in [rhai](https://rhai.rs), and loaded from the
+//! configured directory. The top level of each script runs when it is
+//! loaded, and its `on_event(event)` function (if any) is called for every
+//! hue event, with the event data, and the kind of event (`"add"`,
+//! `"update"` or `"delete"`) in `event.event`.
+//!
+//! Scripts can look at resources, and make changes through the same backend
+//! requests as the hue api:
+//!
+//! - `get(id)`: resource with the given id (or `()` if there is none)
+//! - `light(id, update)`: update a light, e.g. `light(id, #{on: #{on: true}})`
+//! - `grouped_light(id, update)`: update a room or zone
+//! - `recall(id)`: recall a scene
+//!
+//! The directory is checked for new, changed and removed scripts
+//! periodically, so scripts can be edited without a restart.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use camino::Utf8PathBuf;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use hue::api::{GroupedLightUpdate, LightUpdate, RType, SceneActive, SceneStatus, SceneUpdate};
+use hue::event::{Event, EventBlock};
+
+use crate::backend::BackendRequest;
+use crate::config::ScriptingConfig;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+use crate::server::hueevents::HueEventRecord;
+
+/// File extension of scripts
+pub const EXTENSION: &str = "rhai";
+
+/// Name of the function called for each event
+const EVENT_HANDLER: &str = "on_event";
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+struct Script {
+    modified: SystemTime,
+    /// Compiled script, unless it failed to compile
+    ast: Option<AST>,
+    scope: Scope<'static>,
+}
+
+impl Script {
+    fn handles_events(&self) -> bool {
+        self.ast.as_ref().is_some_and(|ast| {
+            ast.iter_functions()
+                .any(|func| func.name == EVENT_HANDLER && func.params.len() == 1)
+        })
+    }
+}
+
+fn parse_id(id: &str) -> ScriptResult<Uuid> {
+    id.parse()
+        .map_err(|_| format!("invalid resource id {id:?}").into())
+}
+
+fn send(res: &Mutex<Resources>, req: BackendRequest) -> ScriptResult<()> {
+    res.blocking_lock()
+        .backend_request(req)
+        .map_err(|err| err.to_string().into())
+}
+
+/// Script engine, with the functions available to scripts. These lock the
+/// resources, so scripts must only run outside the async runtime.
+fn make_engine(conf: &ScriptingConfig, res: &Arc<Mutex<Resources>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(conf.max_operations);
+
+    engine.on_print(|text| log::info!("[script] {text}"));
+    engine.on_debug(|text, source, pos| {
+        log::debug!("[script {}:{pos}] {text}", source.unwrap_or_default());
+    });
+
+    let res_get = res.clone();
+    engine.register_fn("get", move |id: &str| -> ScriptResult<Dynamic> {
+        let id = parse_id(id)?;
+        let record = res_get.blocking_lock().get_resource_by_id(&id);
+        record.map_or_else(|_| Ok(Dynamic::UNIT), rhai::serde::to_dynamic)
+    });
+
+    let res_light = res.clone();
+    engine.register_fn("light", move |id: &str, upd: Dynamic| -> ScriptResult<()> {
+        let link = RType::Light.link_to(parse_id(id)?);
+        let upd: LightUpdate = rhai::serde::from_dynamic(&upd)?;
+        send(&res_light, BackendRequest::LightUpdate(link, upd))
+    });
+
+    let res_group = res.clone();
+    engine.register_fn(
+        "grouped_light",
+        move |id: &str, upd: Dynamic| -> ScriptResult<()> {
+            let link = RType::GroupedLight.link_to(parse_id(id)?);
+            let upd: GroupedLightUpdate = rhai::serde::from_dynamic(&upd)?;
+            send(&res_group, BackendRequest::GroupedLightUpdate(link, upd))
+        },
+    );
+
+    let res_recall = res.clone();
+    engine.register_fn("recall", move |id: &str| -> ScriptResult<()> {
+        let link = RType::Scene.link_to(parse_id(id)?);
+        let status = SceneStatus {
+            active: SceneActive::Static,
+            last_recall: None,
+        };
+        let upd = SceneUpdate::new().with_recall_action(Some(status));
+        send(&res_recall, BackendRequest::SceneUpdate(link, upd))
+    });
+
+    engine
+}
+
+/// Event data for scripts: one object per resource in the event, with the
+/// kind of event added
+fn script_events(block: &EventBlock) -> Vec<Dynamic> {
+    let (kind, data) = match &block.event {
+        Event::Add(add) => ("add", &add.data),
+        Event::Update(update) => ("update", &update.data),
+        Event::Delete(delete) => ("delete", &delete.data),
+        Event::Error(_) => return vec![],
+    };
+
+    data.iter()
+        .filter_map(|obj| {
+            let mut obj = obj.clone();
+            obj.as_object_mut()?
+                .insert("event".to_string(), Value::from(kind));
+            rhai::serde::to_dynamic(obj).ok()
+        })
+        .collect()
+}
+
+/// Load new and changed scripts, and forget removed ones
+fn reload(engine: &Engine, dir: &Utf8PathBuf, scripts: &mut BTreeMap<Utf8PathBuf, Script>) {
+    let mut found = BTreeMap::new();
+    if let Ok(entries) = dir.read_dir_utf8() {
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.into_path();
+            if path.extension() != Some(EXTENSION) {
+                continue;
+            }
+            if let Ok(modified) = path.metadata().and_then(|md| md.modified()) {
+                found.insert(path, modified);
+            }
+        }
+    }
+
+    scripts.retain(|path, _| {
+        let keep = found.contains_key(path);
+        if !keep {
+            log::info!("Script {path} removed");
+        }
+        keep
+    });
+
+    for (path, modified) in found {
+        if scripts
+            .get(&path)
+            .is_some_and(|script| script.modified == modified)
+        {
+            continue;
+        }
+
+        let mut script = Script {
+            modified,
+            ast: None,
+            scope: Scope::new(),
+        };
+
+        match engine.compile_file(path.clone().into()) {
+            Ok(mut ast) => {
+                ast.set_source(path.as_str());
+                match engine.run_ast_with_scope(&mut script.scope, &ast) {
+                    Ok(()) => {
+                        log::info!("Script {path} loaded");
+                        script.ast = Some(ast);
+                    }
+                    Err(err) => log::error!("Script {path} failed to start: {err}"),
+                }
+            }
+            Err(err) => log::error!("Script {path} failed to compile: {err}"),
+        }
+
+        scripts.insert(path, script);
+    }
+}
+
+/// Call the event handler of every script, for each event
+fn dispatch(engine: &Engine, scripts: &mut BTreeMap<Utf8PathBuf, Script>, events: &[Dynamic]) {
+    for (path, script) in scripts.iter_mut() {
+        if !script.handles_events() {
+            continue;
+        }
+        let Some(ast) = &script.ast else {
+            continue;
+        };
+
+        for event in events {
+            let result = engine.call_fn_with_options::<Dynamic>(
+                CallFnOptions::new().eval_ast(false),
+                &mut script.scope,
+                ast,
+                EVENT_HANDLER,
+                (event.clone(),),
+            );
+            if let Err(err) = result {
+                log::error!("Script {path} failed: {err}");
+            }
+        }
+    }
+}
+
+pub struct ScriptHost {
+    conf: ScriptingConfig,
+    res: Arc<Mutex<Resources>>,
+    engine: Arc<Engine>,
+    scripts: BTreeMap<Utf8PathBuf, Script>,
+}
+
+impl ScriptHost {
+    #[must_use]
+    pub fn new(conf: ScriptingConfig, res: Arc<Mutex<Resources>>) -> Self {
+        let engine = Arc::new(make_engine(&conf, &res));
+
+        Self {
+            conf,
+            res,
+            engine,
+            scripts: BTreeMap::new(),
+        }
+    }
+
+    /// Run `func` on the scripts, on a thread where scripts may block
+    async fn with_scripts(
+        &mut self,
+        func: impl FnOnce(&Engine, &mut BTreeMap<Utf8PathBuf, Script>) + Send + 'static,
+    ) -> ApiResult<()> {
+        let engine = self.engine.clone();
+        let mut scripts = std::mem::take(&mut self.scripts);
+
+        self.scripts = tokio::task::spawn_blocking(move || {
+            func(&engine, &mut scripts);
+            scripts
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Pass events on to the scripts, until the event stream closes
+    async fn handle_event(&mut self, record: Result<HueEventRecord, RecvError>) -> ApiResult<()> {
+        let record = match record {
+            Ok(record) => record,
+            Err(RecvError::Lagged(num)) => {
+                log::warn!("Scripts lagging: dropped {num} events");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let events = script_events(&record.block);
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        self.with_scripts(move |engine, scripts| dispatch(engine, scripts, &events))
+            .await
+    }
+
+    pub async fn run_forever(mut self) -> ApiResult<()> {
+        let mut chan = self.res.lock().await.hue_event_stream().subscribe();
+
+        let period = Duration::from_secs(self.conf.reload_interval.max(1));
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let dir = self.conf.dir.clone();
+                    self.with_scripts(move |engine, scripts| reload(engine, &dir, scripts))
+                        .await?;
+                }
+                record = chan.recv() => self.handle_event(record).await?,
+            }
+        }
+    }
+}
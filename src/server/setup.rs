@@ -0,0 +1,264 @@
+//! First-run setup wizard
+//!
+//! When bifrost is started without a configuration file, it serves a small
+//! setup page instead of the hue api. Submitting the page writes the
+//! configuration file (see [`crate::config::starter`]), after which the
+//! setup service is stopped, and bifrost starts normally.
+
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Form, Router};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use url::Url;
+use uuid::Uuid;
+
+use svc::manager::SvmClient;
+
+use crate::backend::z2m::snapshot::Z2mNetwork;
+use crate::config::starter::{self, NetworkInterface, StarterBridge};
+use crate::config::Z2mServer;
+use crate::error::{ApiError, ApiResult};
+use crate::server::http::HttpServer;
+
+/// Name of the setup service (it is stopped, but stays registered, once
+/// setup is complete)
+pub const SERVICE_NAME: &str = "setup";
+
+/// How long to wait for the zigbee2mqtt server
+const Z2M_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Devicetype shown for the api key created during setup
+pub const DEVICETYPE: &str = "bifrost#setup";
+
+/// Outcome of a completed setup
+#[derive(Clone, Debug)]
+pub struct SetupResult {
+    /// Admin api key, added to the `api_keys` section of the new config
+    pub app_key: String,
+}
+
+#[derive(Clone)]
+struct SetupWizard {
+    filename: Utf8PathBuf,
+    tx: mpsc::Sender<SetupResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetupForm {
+    interface: String,
+    z2m_url: String,
+    #[serde(default)]
+    z2m_token: String,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(body: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Bifrost setup</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; }}
+label {{ display: block; margin-top: 1em; font-weight: bold; }}
+input, select {{ width: 100%; padding: 0.3em; box-sizing: border-box; }}
+button {{ margin-top: 1.5em; padding: 0.5em 2em; }}
+.error {{ color: #b00; }}
+code {{ background: #eee; padding: 0.1em 0.3em; }}
+</style>
+</head>
+<body>
+<h1>Bifrost setup</h1>
+{body}
+</body>
+</html>
+"#
+    ))
+}
+
+fn setup_form(interfaces: &[NetworkInterface], form: Option<&SetupForm>, error: &str) -> String {
+    let mut body = String::new();
+
+    if !error.is_empty() {
+        let _ = writeln!(body, r#"<p class="error">{}</p>"#, escape(error));
+    }
+
+    body.push_str(
+        r#"<p>No configuration file was found. Fill in the details below, and
+bifrost will write a configuration file, and start.</p>
+<form method="post">
+<label for="interface">Network interface</label>
+<select id="interface" name="interface">
+"#,
+    );
+
+    for iface in interfaces {
+        let selected = if form.is_some_and(|form| form.interface == iface.name) {
+            " selected"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            body,
+            r#"<option value="{name}"{selected}>{name}: {ip} ({mac})</option>"#,
+            name = escape(&iface.name),
+            ip = iface.ipaddress,
+            mac = iface.mac,
+        );
+    }
+
+    let url = form.map_or("ws://localhost:8080", |form| &form.z2m_url);
+    let token = form.map_or("", |form| &form.z2m_token);
+
+    let _ = write!(
+        body,
+        r#"</select>
+<label for="z2m_url">Zigbee2mqtt websocket address</label>
+<input id="z2m_url" name="z2m_url" value="{url}" required>
+<label for="z2m_token">Zigbee2mqtt auth token (if enabled)</label>
+<input id="z2m_token" name="z2m_token" value="{token}">
+<button type="submit">Save and start</button>
+</form>
+"#,
+        url = escape(url),
+        token = escape(token),
+    );
+
+    body
+}
+
+fn setup_done(filename: &Utf8Path, result: &SetupResult) -> String {
+    format!(
+        "<p>The configuration has been written to <code>{filename}</code>, and
+bifrost is starting. This page will no longer be available.</p>
+<p>An admin api key has been created:</p>
+<p><code>{key}</code></p>
+<p>Keep it somewhere safe. It can be used for the bifrost apis, and is
+listed in the <code>api_keys</code> section of the configuration file.</p>
+<p>Now, open the hue app, and search for a bridge.</p>
+",
+        filename = escape(filename.as_str()),
+        key = escape(&result.app_key),
+    )
+}
+
+impl SetupWizard {
+    async fn apply(&self, interfaces: &[NetworkInterface], form: &SetupForm) -> ApiResult<String> {
+        let iface = interfaces
+            .iter()
+            .find(|iface| iface.name == form.interface)
+            .ok_or_else(|| ApiError::UnknownInterface(form.interface.clone()))?;
+
+        let url = Url::parse(form.z2m_url.trim())?;
+        let token = Some(form.z2m_token.trim()).filter(|token| !token.is_empty());
+
+        let mut server = Z2mServer::new(url.clone());
+        server.token = token.map(ToString::to_string);
+
+        let network = tokio::time::timeout(Z2M_TIMEOUT, Z2mNetwork::fetch(&server))
+            .await
+            .map_err(|_| ApiError::Z2mTimeout)??;
+
+        let app_key = Uuid::new_v4().as_simple().to_string();
+        let bridge = StarterBridge::for_interface(iface);
+        let mut text = starter::generate(&bridge, &url, token, &network.groups, &network.devices);
+
+        let _ = writeln!(text, "\n# Created by the setup wizard");
+        let _ = writeln!(text, "api_keys:");
+        let _ = writeln!(text, "  {app_key}:");
+        let _ = writeln!(text, "    admin: true");
+
+        if let Some(dir) = self
+            .filename
+            .parent()
+            .filter(|dir| !dir.as_str().is_empty())
+        {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        /* never overwrite a configuration that appeared in the meantime */
+        let mut fd = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.filename)?;
+        fd.write_all(text.as_bytes())?;
+
+        log::info!("Setup complete, wrote [{}]", self.filename);
+
+        Ok(app_key)
+    }
+}
+
+async fn get_setup() -> Html<String> {
+    page(&setup_form(&starter::network_interfaces(), None, ""))
+}
+
+async fn post_setup(
+    State(wizard): State<SetupWizard>,
+    Form(form): Form<SetupForm>,
+) -> Html<String> {
+    let interfaces = starter::network_interfaces();
+
+    match wizard.apply(&interfaces, &form).await {
+        Ok(app_key) => {
+            let result = SetupResult { app_key };
+            let _ = wizard.tx.send(result.clone()).await;
+            page(&setup_done(&wizard.filename, &result))
+        }
+        Err(err) => {
+            log::warn!("Setup failed: {err}");
+            page(&setup_form(&interfaces, Some(&form), &err.to_string()))
+        }
+    }
+}
+
+/// Serve the setup wizard on `port`, and wait until it has written the
+/// configuration to `filename`. The setup service is stopped before
+/// returning, so the port is free for the hue api.
+pub async fn run(filename: &Utf8Path, port: u16, mut mgr: SvmClient) -> ApiResult<SetupResult> {
+    let (tx, mut rx) = mpsc::channel(1);
+
+    let wizard = SetupWizard {
+        filename: filename.to_path_buf(),
+        tx,
+    };
+
+    let router = Router::new()
+        .route("/", get(get_setup).post(post_setup))
+        .with_state(wizard);
+
+    let svc = HttpServer::http(
+        Ipv4Addr::UNSPECIFIED.into(),
+        port,
+        router.into_make_service(),
+    );
+    let id = mgr.register_service(SERVICE_NAME, svc).await?;
+    mgr.start(id).await?;
+
+    log::warn!("No configuration file found at [{filename}]");
+    log::warn!("Open http://<address of this machine>:{port}/ to set up bifrost");
+
+    let result = rx.recv().await.ok_or(ApiError::SetupAborted)?;
+
+    mgr.stop(id).await?;
+    mgr.wait_for_stop(id).await?;
+
+    Ok(result)
+}
@@ -16,6 +16,7 @@ pub async fn fetch_updates(since_version: Option<u64>) -> ApiResult<Vec<UpdateEn
 pub struct VersionUpdater {
     version: Option<SwVersion>,
     last_fetch: Option<DateTime<Utc>>,
+    fixed: bool,
 }
 
 impl Default for VersionUpdater {
@@ -32,6 +33,18 @@ impl VersionUpdater {
         Self {
             version: None,
             last_fetch: None,
+            fixed: false,
+        }
+    }
+
+    /// Always report `version`, instead of looking up the newest version
+    /// from the hue update service
+    #[must_use]
+    pub const fn with_fixed_version(version: SwVersion) -> Self {
+        Self {
+            version: Some(version),
+            last_fetch: None,
+            fixed: true,
         }
     }
 
@@ -45,9 +58,10 @@ impl VersionUpdater {
     }
 
     pub async fn get(&mut self) -> &SwVersion {
-        let expired = self
-            .last_fetch
-            .map_or(true, |time| (Utc::now() - time) > Self::CACHE_TIME);
+        let expired = !self.fixed
+            && self
+                .last_fetch
+                .map_or(true, |time| (Utc::now() - time) > Self::CACHE_TIME);
 
         if expired {
             log::debug!("Firmware update information expired. Fetching..");
@@ -0,0 +1,236 @@
+//! Presence simulation: make the house look lived in while nobody is home
+//!
+//! While away mode is on, light changes from the event history are played
+//! back, shifted by a fixed time (a day, by default). Each change is moved
+//! earlier or later by a random amount, and some are left out, so the
+//! pattern is not an exact copy of the day before.
+//!
+//! The replayed changes end up in the event history themselves, so the
+//! simulation keeps going for longer than the history reaches back.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, Utc};
+use chrono_tz::Tz;
+use rand::Rng;
+use serde_json::Value;
+use tokio::sync::{watch, Mutex};
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use hue::api::{Light, LightUpdate, On, RType, ResourceLink};
+use hue::event::{Event, EventBlock};
+
+use crate::backend::BackendRequest;
+use crate::config::VacationConfig;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+use crate::server::history::EventHistory;
+
+/// Interval between scans of the event history
+const TICK: Duration = Duration::from_secs(30);
+
+/// Light changes (on/off and brightness) in an event, if any
+fn light_changes(block: &EventBlock) -> Vec<(ResourceLink, LightUpdate)> {
+    let Event::Update(update) = &block.event else {
+        return vec![];
+    };
+
+    update
+        .data
+        .iter()
+        .filter(|obj| obj.get("type").and_then(Value::as_str) == Some("light"))
+        .filter_map(|obj| {
+            let id: Uuid = obj.get("id")?.as_str()?.parse().ok()?;
+            let on = obj.pointer("/on/on").and_then(Value::as_bool);
+            let bri = obj.pointer("/dimming/brightness").and_then(Value::as_f64);
+            if on.is_none() && bri.is_none() {
+                return None;
+            }
+            let upd = LightUpdate::new()
+                .with_on(on.map(On::new))
+                .with_brightness(bri);
+            Some((RType::Light.link_to(id), upd))
+        })
+        .collect()
+}
+
+/// Time a change from the history is replayed at, `offset` later.
+///
+/// Whole days are added in the bridge time zone, so changes keep their local
+/// time of day, even across daylight saving time changes.
+fn shift(time: DateTime<Utc>, offset: chrono::Duration, tz: Tz) -> DateTime<Utc> {
+    let days = match u64::try_from(offset.num_days()) {
+        Ok(days) if days > 0 && offset.num_seconds() % 86400 == 0 => Days::new(days),
+        _ => return time + offset,
+    };
+
+    time.with_timezone(&tz)
+        .checked_add_days(days)
+        .map_or(time + offset, |local| local.with_timezone(&Utc))
+}
+
+pub struct VacationSim {
+    conf: VacationConfig,
+    res: Arc<Mutex<Resources>>,
+    history: Arc<Mutex<EventHistory>>,
+    away: watch::Receiver<bool>,
+    /// Time the history is shifted by
+    offset: chrono::Duration,
+    /// End of the part of the history that has been scanned (shifted to
+    /// the present)
+    cursor: DateTime<Utc>,
+    /// Changes waiting to be replayed, by the time they are due
+    pending: BTreeMap<DateTime<Utc>, Vec<(ResourceLink, LightUpdate)>>,
+}
+
+impl VacationSim {
+    #[must_use]
+    pub fn new(
+        conf: VacationConfig,
+        history_hours: u32,
+        res: Arc<Mutex<Resources>>,
+        history: Arc<Mutex<EventHistory>>,
+        away: watch::Receiver<bool>,
+    ) -> Self {
+        if conf.lookback_hours > history_hours {
+            log::warn!(
+                "Vacation lookback of {} hours is longer than the event history ({history_hours} hours)",
+                conf.lookback_hours
+            );
+        }
+        let hours = conf.lookback_hours.clamp(1, history_hours.max(1));
+
+        Self {
+            conf,
+            res,
+            history,
+            away,
+            offset: chrono::Duration::hours(i64::from(hours)),
+            cursor: Utc::now(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Schedule the changes found in the history, with some randomness
+    fn schedule(&mut self, found: Vec<(DateTime<Utc>, ResourceLink, LightUpdate)>, tz: Tz) {
+        let mut rng = rand::rng();
+        let jitter = i64::from(self.conf.jitter_minutes) * 60;
+        let skip = self.conf.skip.clamp(0.0, 1.0);
+
+        for (time, link, upd) in found {
+            if rng.random_bool(skip) {
+                continue;
+            }
+            let delay = chrono::Duration::seconds(rng.random_range(-jitter..=jitter));
+            self.pending
+                .entry(shift(time, self.offset, tz) + delay)
+                .or_default()
+                .push((link, upd));
+        }
+    }
+
+    async fn tick(&mut self) -> ApiResult<()> {
+        let now = Utc::now();
+        let from = self.cursor - self.offset;
+        let until = now - self.offset;
+        self.cursor = now;
+
+        let lock = self.history.lock().await;
+        let found: Vec<_> = lock
+            .events()
+            .take_while(|block| block.creationtime >= from)
+            .filter(|block| block.creationtime < until)
+            .flat_map(|block| {
+                light_changes(block)
+                    .into_iter()
+                    .map(|(link, upd)| (block.creationtime, link, upd))
+            })
+            .collect();
+        drop(lock);
+
+        let tz = self.res.lock().await.local_tz();
+        self.schedule(found, tz);
+
+        let later = self.pending.split_off(&now);
+        let due = std::mem::replace(&mut self.pending, later);
+
+        let lock = self.res.lock().await;
+        for (link, upd) in due.into_values().flatten() {
+            if lock.get::<Light>(&link).is_err() {
+                continue;
+            }
+            log::debug!(
+                "Vacation: replaying change of {}",
+                lock.describe_link(&link)
+            );
+            lock.backend_request(BackendRequest::LightUpdate(link, upd))?;
+        }
+        drop(lock);
+
+        Ok(())
+    }
+
+    /// Run the simulation whenever away mode is on
+    pub async fn run_forever(mut self) -> ApiResult<()> {
+        loop {
+            if self.away.wait_for(|away| *away).await.is_err() {
+                return Ok(());
+            }
+
+            log::info!("Away mode is on, starting presence simulation");
+            self.cursor = Utc::now();
+            self.pending.clear();
+
+            let mut interval = tokio::time::interval(TICK);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    changed = self.away.changed() => {
+                        if changed.is_err() || !*self.away.borrow() {
+                            break;
+                        }
+                    }
+                }
+
+                if let Err(err) = self.tick().await {
+                    log::error!("Presence simulation failed: {err}");
+                }
+            }
+
+            log::info!("Away mode is off, stopping presence simulation");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use crate::server::vacation::shift;
+
+    #[test]
+    fn shift_keeps_local_time_across_dst() {
+        // the night before daylight saving time starts in Copenhagen
+        let tz: Tz = "Europe/Copenhagen".parse().unwrap();
+        let time = Utc.with_ymd_and_hms(2025, 3, 29, 19, 0, 0).unwrap();
+
+        // 20:00 local time (CET), replayed at 20:00 local time (CEST)
+        let next = shift(time, Duration::hours(24), tz);
+        assert_eq!(next, Utc.with_ymd_and_hms(2025, 3, 30, 18, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn shift_by_hours() {
+        let tz: Tz = "Europe/Copenhagen".parse().unwrap();
+        let time = Utc.with_ymd_and_hms(2025, 3, 29, 19, 0, 0).unwrap();
+
+        let next = shift(time, Duration::hours(12), tz);
+        assert_eq!(next, time + Duration::hours(12));
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+
+use hue::event::EventBlock;
+
+use crate::config::WebhookConfig;
+use crate::error::ApiResult;
+use crate::resource::Resources;
+use crate::server::hueevents;
+
+pub struct Webhook {
+    conf: WebhookConfig,
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    const MIN_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+    pub fn new(conf: WebhookConfig) -> ApiResult<Self> {
+        let client = reqwest::Client::builder().timeout(Self::TIMEOUT).build()?;
+        Ok(Self { conf, client })
+    }
+
+    async fn post(&self, body: &Value) -> ApiResult<()> {
+        self.client
+            .post(self.conf.url.clone())
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Send event block, retrying with exponential backoff
+    async fn send(&self, block: &EventBlock) -> ApiResult<()> {
+        // same format as the event stream: a list of event blocks
+        let body = serde_json::to_value([block])?;
+
+        let mut backoff = Self::MIN_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            match self.post(&body).await {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt < self.conf.retries => {
+                    log::debug!(
+                        "Webhook {} failed: {err} (retrying in {}s)",
+                        self.conf.url,
+                        backoff.as_secs()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Self::MAX_BACKOFF);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub async fn run_forever(self, res: Arc<Mutex<Resources>>) -> ApiResult<()> {
+        let mut chan = res.lock().await.hue_event_stream().subscribe();
+
+        log::info!("Sending hue events to webhook {}", self.conf.url);
+
+        loop {
+            let record = match chan.recv().await {
+                Ok(record) => record,
+                Err(RecvError::Lagged(num)) => {
+                    log::warn!("Webhook {} lagging: dropped {num} events", self.conf.url);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let Some(block) = hueevents::filter_types(record.block, &self.conf.types) else {
+                continue;
+            };
+
+            if let Err(err) = self.send(&block).await {
+                log::error!("Webhook {} failed, dropping event: {err}", self.conf.url);
+            }
+        }
+    }
+}
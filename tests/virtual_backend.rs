@@ -0,0 +1,159 @@
+//! Runs the virtual backend against [`Resources`], the same way bifrost does
+//! without zigbee hardware, and checks that requests end up in the resources.
+//!
+//! Requests are sent on the coalesced backend channel, like the coalescer
+//! would.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use hue::api::{
+    Light, LightUpdate, On, RType, ResourceLink, Scene, SceneActive, SceneRecall,
+    SceneStatusUpdate, SceneUpdate,
+};
+use hue::version::SwVersion;
+
+use bifrost::backend::virt::VirtualBackend;
+use bifrost::backend::{Backend, BackendRequest};
+use bifrost::config::{AppConfig, EntertainmentConfig};
+use bifrost::model::entertainment::EntertainmentSettings;
+use bifrost::model::state::State;
+use bifrost::resource::Resources;
+
+const CONFIG: &str = "
+bridge:
+  name: Test bridge
+  mac: 00:11:22:33:44:55
+  ipaddress: 10.0.0.2
+  netmask: 255.255.255.0
+  gateway: 10.0.0.1
+  timezone: UTC
+z2m: {}
+virtual:
+  lights:
+    - name: Lamp
+      kind: color
+      room: living_room
+    - name: Spot
+      kind: white
+      room: living_room
+";
+
+fn light(name: &str) -> ResourceLink {
+    RType::Light.deterministic(("virtual", name))
+}
+
+/// Start the virtual backend, and wait for it to create its devices
+async fn start() -> Arc<Mutex<Resources>> {
+    let config: AppConfig = serde_yml::from_str(CONFIG).unwrap();
+    let settings = EntertainmentSettings::from_config(&EntertainmentConfig::default());
+    let res = Arc::new(Mutex::new(Resources::new(
+        SwVersion::default(),
+        State::new(),
+        settings,
+    )));
+
+    let rx = res.lock().await.backend_event_stream();
+    let backend = VirtualBackend::new(Arc::new(config), res.clone());
+    tokio::spawn(backend.run_forever(rx));
+
+    let room = RType::GroupedLight.deterministic(("virtual", "living_room"));
+    wait_for(&res, |res| res.get_resource_by_id(&room.rid).is_ok()).await;
+
+    res
+}
+
+async fn send(res: &Arc<Mutex<Resources>>, req: BackendRequest) {
+    res.lock()
+        .await
+        .backend_sender()
+        .send(Arc::new(req))
+        .unwrap();
+}
+
+/// Wait until `check` is true, since the backend runs in its own task
+async fn wait_for(res: &Arc<Mutex<Resources>>, check: impl Fn(&Resources) -> bool + Sync) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !check(&*res.lock().await) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("virtual backend did not update the resources in time");
+}
+
+fn brightness(res: &Resources, link: &ResourceLink) -> Option<f64> {
+    let light = res.get::<Light>(link).unwrap();
+    light.dimming.map(|dim| dim.brightness)
+}
+
+#[tokio::test]
+async fn light_update() {
+    let res = start().await;
+    let lamp = light("Lamp");
+
+    let upd = LightUpdate::new()
+        .with_on(Some(On::new(true)))
+        .with_brightness(Some(30.0));
+    send(&res, BackendRequest::LightUpdate(lamp, upd)).await;
+
+    wait_for(&res, |res| brightness(res, &lamp) == Some(30.0)).await;
+    let lock = res.lock().await;
+    assert!(lock.get::<Light>(&lamp).unwrap().on.on);
+    // other lights are left alone
+    assert_ne!(brightness(&lock, &light("Spot")), Some(30.0));
+    drop(lock);
+}
+
+#[tokio::test]
+async fn scene_recall() {
+    let res = start().await;
+    let (lamp, spot) = (light("Lamp"), light("Spot"));
+    let room = RType::Room.deterministic(("virtual", "living_room"));
+
+    let scene: Scene = serde_json::from_value(json!({
+        "actions": [
+            { "target": lamp, "action": { "on": { "on": true }, "dimming": { "brightness": 20.0 } } },
+            { "target": spot, "action": { "on": { "on": false } } },
+        ],
+        "group": room,
+        "metadata": { "name": "Evening" },
+        "speed": 0.5,
+        "status": null,
+    }))
+    .unwrap();
+
+    let link = RType::Scene.deterministic((room.rid, 1));
+    send(&res, BackendRequest::SceneCreate(link, 1, scene)).await;
+    wait_for(&res, |res| res.get::<Scene>(&link).is_ok()).await;
+
+    let recall = SceneUpdate {
+        recall: Some(SceneRecall {
+            action: Some(SceneStatusUpdate::Active),
+            duration: None,
+            dimming: None,
+        }),
+        ..SceneUpdate::default()
+    };
+    send(&res, BackendRequest::SceneUpdate(link, recall)).await;
+
+    wait_for(&res, |res| {
+        res.get::<Scene>(&link).is_ok_and(|scene| {
+            scene
+                .status
+                .is_some_and(|st| st.active == SceneActive::Static)
+        })
+    })
+    .await;
+
+    let lock = res.lock().await;
+    let status = lock.get::<Scene>(&link).unwrap().status.unwrap();
+    assert!(status.last_recall.is_some());
+    assert_eq!(brightness(&lock, &lamp), Some(20.0));
+    assert!(lock.get::<Light>(&lamp).unwrap().on.on);
+    assert!(!lock.get::<Light>(&spot).unwrap().on.on);
+    drop(lock);
+}